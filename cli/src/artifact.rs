@@ -0,0 +1,119 @@
+use std::path::Path;
+
+use anyhow::Context;
+use anyhow::Result;
+use nargo_parse::*;
+use onyx_api::prelude::*;
+
+use crate::failure::Failure;
+use crate::failure::FailureCode;
+use crate::index;
+use crate::output::summary;
+
+/// The id of `version_name` of `package_name` in the registry.
+async fn version_id(api: &OnyxApi, package_name: &str, version_name: &str) -> Result<HashId> {
+    let package = index::load(api, package_name)
+        .await
+        .context(format!("Unable to load package \"{package_name}\""))?;
+    package
+        .versions
+        .into_iter()
+        .find(|version| version.name == version_name)
+        .map(|version| version.id)
+        .ok_or(anyhow::anyhow!(
+            "\"{package_name}\" version \"{version_name}\" is not published"
+        ))
+}
+
+/// Attach the artifact `nargo compile` wrote for the package at `path` to its published
+/// version. The artifact is named after the package unless `name` is given.
+pub async fn upload(api: &OnyxApi, path: &Path, name: Option<&str>) -> Result<()> {
+    let config = NargoConfig::load(path)?;
+    let package_name = config.package.name;
+    let version_name = config
+        .package
+        .version
+        .ok_or(anyhow::anyhow!("Nargo.toml has no version"))?;
+    let artifact_path = path.join("target").join(format!("{package_name}.json"));
+    let content = std::fs::read_to_string(&artifact_path).context(
+        Failure::new(
+            FailureCode::Artifact,
+            "Unable to read the compiled artifact",
+        )
+        .with_advice("Run nargo compile first.")
+        .with_path(&artifact_path),
+    )?;
+    let version_id = version_id(api, &package_name, &version_name)
+        .await
+        .context(
+            Failure::new(
+                FailureCode::NotPublished,
+                format!("Unable to find \"{package_name}\" version \"{version_name}\""),
+            )
+            .with_advice("Publish it first with: nrpm publish"),
+        )?;
+
+    let login = super::attempt_auth().await?;
+    let artifact = api
+        .upload_artifact(
+            &login.token,
+            &version_id,
+            UploadArtifactRequest {
+                name: name.unwrap_or(&package_name).to_string(),
+                content,
+            },
+        )
+        .await
+        .context(format!(
+            "Unable to attach the artifact to \"{package_name}\" version \"{version_name}\""
+        ))?;
+    summary!(
+        "📦 Attached artifact \"{}\" to \"{package_name}\" version \"{version_name}\"",
+        artifact.name
+    );
+    summary!("    blake3: {}", artifact.hash);
+    Ok(())
+}
+
+/// Download an artifact attached to `version_name` of `package_name`. Without `name` the
+/// version must have exactly one artifact. Prints the artifact unless `output` is given.
+pub async fn download(
+    api: &OnyxApi,
+    package_name: &str,
+    version_name: &str,
+    name: Option<&str>,
+    output: Option<&Path>,
+) -> Result<()> {
+    let version_id = version_id(api, package_name, version_name).await?;
+    let artifacts = api.version_artifacts(&version_id).await?;
+    if name.is_none() && artifacts.len() > 1 {
+        let names = artifacts
+            .iter()
+            .map(|artifact| artifact.name.as_str())
+            .collect::<Vec<_>>()
+            .join(", ");
+        return Err(Failure::new(
+            FailureCode::Artifact,
+            format!("\"{package_name}\" version \"{version_name}\" has several artifacts: {names}"),
+        )
+        .with_advice("Choose one with --name.")
+        .into());
+    }
+    let artifact = artifacts
+        .iter()
+        .find(|artifact| name.is_none_or(|name| artifact.name == name))
+        .ok_or(anyhow::anyhow!(
+            "\"{package_name}\" version \"{version_name}\" has no artifact{}",
+            name.map(|name| format!(" named \"{name}\""))
+                .unwrap_or_default()
+        ))?;
+    let content = api.download_artifact(&version_id, artifact).await?;
+    match output {
+        Some(output) => {
+            std::fs::write(output, content)?;
+            summary!("📦 Wrote artifact \"{}\" to {output:?}", artifact.name);
+        }
+        None => println!("{content}"),
+    }
+    Ok(())
+}
@@ -0,0 +1,443 @@
+use std::collections::BTreeMap;
+use std::collections::BTreeSet;
+use std::fs::File;
+use std::fs::TryLockError;
+use std::io::Write;
+use std::path::Path;
+use std::path::PathBuf;
+
+use anyhow::Context;
+use anyhow::Result;
+use indicatif::ProgressBar;
+use onyx_api::prelude::*;
+use serde::Deserialize;
+use serde::Serialize;
+
+use crate::config;
+use crate::output::say;
+
+/// Bumped when nrpm changes how its cache is laid out, so versions of nrpm using different
+/// layouts keep separate caches instead of corrupting each other's.
+const LAYOUT_VERSION: u32 = 1;
+
+/// The nrpm package cache, e.g. `~/.cache/nrpm/packages/v1`. It's laid out like the ~/nargo
+/// cache, `{host}/{path}/{tag}`, and nrpm links the entries it installs into ~/nargo for
+/// nargo to find, see `link_into_nargo`. Entries an older nrpm downloaded into ~/nargo are
+/// moved here the first time it's used.
+pub fn cache_path() -> Result<PathBuf> {
+    let dep_cache_path = config::cache_dir()?
+        .join("packages")
+        .join(format!("v{LAYOUT_VERSION}"));
+    if dep_cache_path.exists() && !dep_cache_path.is_dir() {
+        anyhow::bail!(
+            "Global dependency cache is a non-directory! {:?}",
+            dep_cache_path
+        );
+    } else if !dep_cache_path.exists() {
+        std::fs::create_dir_all(&dep_cache_path)?;
+    }
+    let nargo_path = nargo_cache_path()?;
+    if usage_path(&nargo_path).exists() {
+        migrate(&nargo_path, &dep_cache_path)
+            .with_context(|| format!("Failed to move nrpm packages out of {nargo_path:?}"))?;
+    }
+    Ok(dep_cache_path)
+}
+
+/// The system cache nargo downloads git dependencies into. ~/nargo
+///
+/// https://github.com/noir-lang/noir/blob/12e90c0d51fc53998a2b75d6fb302d621227accd/tooling/nargo_toml/src/git.rs#L51
+pub fn nargo_cache_path() -> Result<PathBuf> {
+    // Match the nargo default path.
+    // TODO: make this more configurable
+    let nargo_path = dirs::home_dir()
+        .expect("unable to determine user home directory")
+        .join("nargo");
+    if nargo_path.exists() && !nargo_path.is_dir() {
+        anyhow::bail!(
+            "nargo dependency cache is a non-directory! {:?}",
+            nargo_path
+        );
+    } else if !nargo_path.exists() {
+        std::fs::create_dir(&nargo_path)?;
+    }
+    Ok(nargo_path)
+}
+
+/// Move the entries an older nrpm recorded using in ~/nargo into the nrpm cache and link them
+/// back, and carry over its usage file. Waits for older nrpm processes to stop using ~/nargo.
+fn migrate(nargo_path: &Path, dep_cache_path: &Path) -> Result<()> {
+    let _legacy_lock = lock_cache_exclusive(nargo_path, &ProgressBar::hidden())?;
+    // another process may have migrated while this one waited
+    if !usage_path(nargo_path).exists() {
+        return Ok(());
+    }
+    let legacy = read_usage(nargo_path)?;
+    let mut moved = vec![];
+    for key in legacy.last_used.keys() {
+        let from = nargo_path.join(key);
+        let to = dep_cache_path.join(key);
+        if !from.symlink_metadata().is_ok_and(|m| m.is_dir()) || to.exists() {
+            continue;
+        }
+        std::fs::create_dir_all(to.parent().unwrap_or(dep_cache_path))?;
+        if let Err(e) = std::fs::rename(&from, &to) {
+            // e.g. the caches are on different filesystems, nrpm downloads it again
+            log::debug!("failed to move {from:?} to {to:?}: {e}");
+            continue;
+        }
+        moved.push(to);
+    }
+    link_into_nargo(dep_cache_path, moved.iter().map(PathBuf::as_path))?;
+
+    let file = File::options()
+        .create(true)
+        .truncate(false)
+        .write(true)
+        .open(lock_dir(dep_cache_path)?.join("usage.lock"))?;
+    file.lock()?;
+    let mut usage = read_usage(dep_cache_path)?;
+    usage.lockfiles.extend(legacy.lockfiles);
+    for (key, last_used) in legacy.last_used {
+        usage.last_used.entry(key).or_insert(last_used);
+    }
+    write_usage(dep_cache_path, &usage)?;
+    std::fs::remove_file(usage_path(nargo_path))?;
+    say!(
+        "📦 moved {} cached package{} from {nargo_path:?} to {dep_cache_path:?}",
+        moved.len(),
+        if moved.len() == 1 { "" } else { "s" }
+    );
+    Ok(())
+}
+
+/// An advisory lock on part of the cache, released when dropped.
+///
+/// Processes using the cache hold a shared lock on the whole cache, and an exclusive lock on
+/// each dependency while checking for it and downloading it. Removing the cache takes the
+/// whole cache exclusively.
+pub struct CacheLock {
+    _file: File,
+}
+
+fn lock_dir(dep_cache_path: &Path) -> Result<PathBuf> {
+    let dir = dep_cache_path.join(".nrpm").join("locks");
+    std::fs::create_dir_all(&dir)?;
+    Ok(dir)
+}
+
+/// Lock `lock_path`, telling the user if another process has to release it first.
+fn acquire(
+    lock_path: &Path,
+    shared: bool,
+    waiting: String,
+    progress: &ProgressBar,
+) -> Result<CacheLock> {
+    let file = File::options()
+        .create(true)
+        .truncate(false)
+        .write(true)
+        .open(lock_path)?;
+    let attempt = if shared {
+        file.try_lock_shared()
+    } else {
+        file.try_lock()
+    };
+    match attempt {
+        Ok(()) => {}
+        Err(TryLockError::WouldBlock) => {
+            if progress.is_hidden() {
+                say!("⏳ {waiting}");
+            } else {
+                progress.set_message(waiting);
+            }
+            if shared {
+                file.lock_shared()?;
+            } else {
+                file.lock()?;
+            }
+        }
+        Err(TryLockError::Error(e)) => return Err(e.into()),
+    }
+    Ok(CacheLock { _file: file })
+}
+
+/// Take a shared lock on the cache for reading and adding dependencies.
+pub fn lock_cache(dep_cache_path: &Path, progress: &ProgressBar) -> Result<CacheLock> {
+    acquire(
+        &lock_dir(dep_cache_path)?.join("cache.lock"),
+        true,
+        "waiting for the package cache to be cleaned".to_string(),
+        progress,
+    )
+}
+
+/// Take an exclusive lock on the cache for removing it.
+pub fn lock_cache_exclusive(dep_cache_path: &Path, progress: &ProgressBar) -> Result<CacheLock> {
+    acquire(
+        &lock_dir(dep_cache_path)?.join("cache.lock"),
+        false,
+        "waiting for other nrpm processes to finish using the package cache".to_string(),
+        progress,
+    )
+}
+
+/// Take an exclusive lock on the dependency at `dep_root_path` in the cache. Hold it while
+/// checking whether the dependency exists and downloading it.
+pub fn lock_dependency(
+    dep_cache_path: &Path,
+    dep_root_path: &Path,
+    name: &str,
+    progress: &ProgressBar,
+) -> Result<CacheLock> {
+    let relative = dep_root_path
+        .strip_prefix(dep_cache_path)
+        .unwrap_or(dep_root_path);
+    let lock_name = blake3::hash(relative.as_os_str().as_encoded_bytes()).to_string();
+    acquire(
+        &lock_dir(dep_cache_path)?.join(format!("{lock_name}.lock")),
+        false,
+        format!("{name}: waiting for another nrpm process to download it"),
+        progress,
+    )
+}
+
+/// Move the cache entry at `dep_root_path`, whose content hashed to `found` instead of
+/// `expected`, into the quarantine directory and record it in the quarantine log. Entries are
+/// named by when they were quarantined, the package and the hash found, with a counter added
+/// if that's taken. Returns where the entry was moved.
+pub fn quarantine(
+    dep_cache_path: &Path,
+    dep_root_path: &Path,
+    identifier: &str,
+    expected: &str,
+    found: &str,
+) -> Result<PathBuf> {
+    let quarantine_dir = dep_cache_path.join(".nrpm").join("quarantine");
+    std::fs::create_dir_all(&quarantine_dir)?;
+    let now = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)?
+        .as_secs();
+    let package = identifier
+        .chars()
+        .map(|c| {
+            if c.is_ascii_alphanumeric() || matches!(c, '.' | '-' | '_' | '@') {
+                c
+            } else {
+                '_'
+            }
+        })
+        .collect::<String>();
+    let name = format!("{now}-{package}-{}", &found[..found.len().min(16)]);
+    let mut destination = quarantine_dir.join(&name);
+    let mut count = 1;
+    while destination.exists() {
+        destination = quarantine_dir.join(format!("{name}-{count}"));
+        count += 1;
+    }
+    std::fs::rename(dep_root_path, &destination)?;
+    let mut log = File::options()
+        .create(true)
+        .append(true)
+        .open(quarantine_dir.join("quarantine.log"))?;
+    writeln!(
+        log,
+        "{now} {identifier} expected {expected} found {found} moved {dep_root_path:?} to {destination:?}"
+    )?;
+    Ok(destination)
+}
+
+/// What nrpm knows about how the cache is used, kept in `.nrpm/usage.toml` for
+/// `nrpm cache gc`.
+#[derive(Default, Serialize, Deserialize)]
+pub struct Usage {
+    /// Every lockfile nrpm installed or fetched, the entries they lock are kept.
+    #[serde(default)]
+    pub lockfiles: BTreeSet<PathBuf>,
+    /// When each entry was last used, in seconds, by its path in the cache, see `entry_key`.
+    #[serde(default)]
+    pub last_used: BTreeMap<String, u64>,
+}
+
+fn usage_path(dep_cache_path: &Path) -> PathBuf {
+    dep_cache_path.join(".nrpm").join("usage.toml")
+}
+
+/// The path of the cache entry at `dep_root_path` relative to the cache, e.g.
+/// `github.com/noir-lang/ec/v0.1.2`.
+pub fn entry_key(dep_cache_path: &Path, dep_root_path: &Path) -> Option<String> {
+    let relative = dep_root_path.strip_prefix(dep_cache_path).ok()?;
+    let parts = relative
+        .components()
+        .map(|c| c.as_os_str().to_str())
+        .collect::<Option<Vec<_>>>()?;
+    Some(parts.join("/"))
+}
+
+pub fn read_usage(dep_cache_path: &Path) -> Result<Usage> {
+    let path = usage_path(dep_cache_path);
+    if !path.exists() {
+        return Ok(Usage::default());
+    }
+    let str = std::fs::read_to_string(&path)?;
+    toml::from_str(&str).with_context(|| format!("Failed to parse {path:?}"))
+}
+
+/// Replace the usage file, so a process that stops halfway doesn't leave it truncated.
+pub fn write_usage(dep_cache_path: &Path, usage: &Usage) -> Result<()> {
+    let path = usage_path(dep_cache_path);
+    let partial = path.with_extension(format!("toml.{}", std::process::id()));
+    std::fs::write(&partial, toml::to_string(usage)?)?;
+    std::fs::rename(&partial, &path)?;
+    Ok(())
+}
+
+/// Record that the lockfile at `lockfile_path` uses the cache entries at `dep_root_paths`,
+/// now. Called with a shared lock on the cache, so other processes may be recording too.
+pub fn record_use<'a>(
+    dep_cache_path: &Path,
+    dep_root_paths: impl IntoIterator<Item = &'a Path>,
+    lockfile_path: &Path,
+) -> Result<()> {
+    let file = File::options()
+        .create(true)
+        .truncate(false)
+        .write(true)
+        .open(lock_dir(dep_cache_path)?.join("usage.lock"))?;
+    file.lock()?;
+    let mut usage = read_usage(dep_cache_path)?;
+    usage.lockfiles.insert(std::path::absolute(lockfile_path)?);
+    let now = timestamp();
+    for dep_root_path in dep_root_paths {
+        if let Some(key) = entry_key(dep_cache_path, dep_root_path) {
+            usage.last_used.insert(key, now);
+        }
+    }
+    write_usage(dep_cache_path, &usage)
+}
+
+/// Where nargo looks for the cache entry at `dep_root_path`, the same path in ~/nargo.
+fn nargo_entry_path(dep_cache_path: &Path, dep_root_path: &Path) -> Result<Option<PathBuf>> {
+    let Ok(relative) = dep_root_path.strip_prefix(dep_cache_path) else {
+        return Ok(None);
+    };
+    Ok(Some(nargo_cache_path()?.join(relative)))
+}
+
+#[cfg(unix)]
+fn symlink_dir(original: &Path, link: &Path) -> std::io::Result<()> {
+    std::os::unix::fs::symlink(original, link)
+}
+
+#[cfg(windows)]
+fn symlink_dir(original: &Path, link: &Path) -> std::io::Result<()> {
+    std::os::windows::fs::symlink_dir(original, link)
+}
+
+fn copy_dir(from: &Path, to: &Path) -> Result<()> {
+    std::fs::create_dir_all(to)?;
+    for child in std::fs::read_dir(from)? {
+        let child = child?;
+        if child.file_type()?.is_dir() {
+            copy_dir(&child.path(), &to.join(child.file_name()))?;
+        } else {
+            std::fs::copy(child.path(), to.join(child.file_name()))?;
+        }
+    }
+    Ok(())
+}
+
+/// Put the cache entries at `dep_root_paths` where nargo looks for them in ~/nargo: a link to
+/// the entry, or a copy where links can't be made. A directory nargo downloaded there itself
+/// is kept if its content matches the entry, otherwise it's quarantined so nargo builds what
+/// nrpm checked. Called with a shared lock on the cache.
+pub fn link_into_nargo<'a>(
+    dep_cache_path: &Path,
+    dep_root_paths: impl IntoIterator<Item = &'a Path>,
+) -> Result<()> {
+    for dep_root_path in dep_root_paths {
+        let Some(nargo_entry) = nargo_entry_path(dep_cache_path, dep_root_path)? else {
+            continue;
+        };
+        if !dep_root_path.is_dir() {
+            continue;
+        }
+        let key = entry_key(dep_cache_path, dep_root_path).unwrap_or_default();
+        let _dep_lock =
+            lock_dependency(dep_cache_path, dep_root_path, &key, &ProgressBar::hidden())?;
+        match nargo_entry.symlink_metadata() {
+            Ok(metadata) if metadata.is_symlink() => {
+                if std::fs::read_link(&nargo_entry)? == dep_root_path {
+                    continue;
+                }
+                std::fs::remove_file(&nargo_entry)?;
+            }
+            Ok(metadata) if metadata.is_dir() => {
+                let found = nrpm_tarball::hash_dir(&nargo_entry)?.to_string();
+                let expected = nrpm_tarball::hash_dir(dep_root_path)?.to_string();
+                if found == expected {
+                    continue;
+                }
+                let quarantined =
+                    quarantine(dep_cache_path, &nargo_entry, &key, &expected, &found)?;
+                say!(
+                    "🩹 {key} in {:?} did not match the nrpm cache, moved it to {quarantined:?}",
+                    nargo_cache_path()?
+                );
+            }
+            Ok(_) => anyhow::bail!("{nargo_entry:?} is not a directory"),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => {}
+            Err(e) => return Err(e.into()),
+        }
+        if let Some(parent) = nargo_entry.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        if let Err(e) = symlink_dir(dep_root_path, &nargo_entry) {
+            log::debug!("failed to link {nargo_entry:?}, copying it instead: {e}");
+            copy_dir(dep_root_path, &nargo_entry)?;
+        }
+    }
+    Ok(())
+}
+
+/// Remove the link to the cache entry at `dep_root_path` from ~/nargo, and the directories
+/// above it that are left empty. A copy or a directory nargo downloaded is left alone.
+pub fn unlink_from_nargo(dep_cache_path: &Path, dep_root_path: &Path) -> Result<()> {
+    let Some(nargo_entry) = nargo_entry_path(dep_cache_path, dep_root_path)? else {
+        return Ok(());
+    };
+    if !nargo_entry.is_symlink() || std::fs::read_link(&nargo_entry)? != dep_root_path {
+        return Ok(());
+    }
+    std::fs::remove_file(&nargo_entry)?;
+    let nargo_path = nargo_cache_path()?;
+    let mut parent = nargo_entry.parent();
+    while let Some(dir) = parent
+        && dir != nargo_path
+        && std::fs::remove_dir(dir).is_ok()
+    {
+        parent = dir.parent();
+    }
+    Ok(())
+}
+
+/// Remove the links in `dir` into the nrpm cache whose entry no longer exists, e.g. after the
+/// cache is cleaned. Directories nargo downloaded aren't searched.
+pub fn prune_nargo_links(dep_cache_path: &Path, dir: &Path) -> Result<()> {
+    for child in std::fs::read_dir(dir)? {
+        let child = child?;
+        let path = child.path();
+        let file_type = child.file_type()?;
+        if file_type.is_symlink() {
+            if std::fs::read_link(&path)?.starts_with(dep_cache_path) && !path.exists() {
+                std::fs::remove_file(&path)?;
+            }
+        } else if file_type.is_dir()
+            && child.file_name() != ".nrpm"
+            && !path.join("Nargo.toml").exists()
+        {
+            prune_nargo_links(dep_cache_path, &path)?;
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,71 @@
+use std::path::Path;
+
+use anyhow::Context;
+use anyhow::Result;
+
+use crate::failure::Failure;
+use crate::failure::FailureCode;
+use crate::output::summary;
+use crate::version::git;
+
+/// Most commits listed in generated release notes.
+const MAX_COMMITS: usize = 100;
+
+/// The tag for `version`, `vX.Y.Z` or `X.Y.Z`, in the repository containing `dir`.
+fn find_tag(dir: &Path, version: &str) -> Option<String> {
+    [format!("v{version}"), version.to_string()]
+        .into_iter()
+        .find(|tag| {
+            git(
+                dir,
+                &["rev-parse", "-q", "--verify", &format!("refs/tags/{tag}")],
+            )
+            .is_ok()
+        })
+}
+
+/// The subjects of the commits since the tag of `previous_version` in the git repository
+/// containing `pkg_dir`, newest first. Merge commits are left out. Without a previous version
+/// or its tag the most recent commits are listed.
+pub fn commits_since(pkg_dir: &Path, previous_version: Option<&str>) -> Result<Vec<String>> {
+    git(pkg_dir, &["rev-parse", "--is-inside-work-tree"]).context(
+        Failure::new(
+            FailureCode::NotInGitRepository,
+            format!("{pkg_dir:?} is not in a git repository"),
+        )
+        .with_advice(
+            "--changelog lists the commits since the previous version, run it in a git repository.",
+        ),
+    )?;
+    git(pkg_dir, &["rev-parse", "--verify", "HEAD"])
+        .context("The git repository has no commits")?;
+    let mut range = "HEAD".to_string();
+    if let Some(version) = previous_version {
+        match find_tag(pkg_dir, version) {
+            Some(tag) => range = format!("refs/tags/{tag}..HEAD"),
+            None => summary!(
+                "⚠️  No git tag found for the previous version {version}, listing the last {MAX_COMMITS} commits"
+            ),
+        }
+    }
+    let log = git(
+        pkg_dir,
+        &[
+            "log",
+            "--no-merges",
+            "--format=%s",
+            &format!("--max-count={MAX_COMMITS}"),
+            &range,
+        ],
+    )?;
+    Ok(log.lines().map(str::to_string).collect())
+}
+
+/// Markdown release notes for `version` listing `commits`.
+pub fn release_notes(version: &str, commits: &[String]) -> String {
+    let mut notes = format!("## {version}\n\n");
+    for subject in commits {
+        notes.push_str(&format!("- {subject}\n"));
+    }
+    notes
+}
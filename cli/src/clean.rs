@@ -0,0 +1,205 @@
+use std::collections::BTreeSet;
+use std::path::Path;
+use std::path::PathBuf;
+
+use anyhow::Result;
+use indicatif::HumanBytes;
+use nargo_parse::*;
+
+use crate::cache;
+use crate::gc;
+use crate::lockfile::Lockfile;
+use crate::output::say;
+use crate::output::summary;
+
+/// Something `nrpm clean` removes from a package, with why it's no longer needed.
+struct Artifact {
+    path: PathBuf,
+    reason: &'static str,
+    size: u64,
+}
+
+/// Copies of nrpm.lock left next to it, e.g. `nrpm.lock.bak`, `nrpm.lock.orig` from a merge or
+/// `nrpm.lock~` from an editor. They're only stale once there's a nrpm.lock.
+fn lockfile_backups(path: &Path, artifacts: &mut Vec<Artifact>) -> Result<()> {
+    if !path.join("nrpm.lock").exists() {
+        return Ok(());
+    }
+    for child in std::fs::read_dir(path)? {
+        let child = child?;
+        let name = child.file_name();
+        let Some(name) = name.to_str() else {
+            continue;
+        };
+        if child.file_type()?.is_file() && (name.starts_with("nrpm.lock.") || name == "nrpm.lock~")
+        {
+            artifacts.push(Artifact {
+                size: child.metadata()?.len(),
+                path: child.path(),
+                reason: "lockfile backup",
+            });
+        }
+    }
+    Ok(())
+}
+
+/// The directories of the path dependencies of the package at `path`, or of each member of the
+/// workspace at `path`.
+fn path_dependencies(path: &Path) -> Result<BTreeSet<PathBuf>> {
+    let package_dirs = match Workspace::load(path)? {
+        Some(workspace) => workspace
+            .members
+            .iter()
+            .map(|member| path.join(member))
+            .collect::<Vec<_>>(),
+        None => vec![path.to_path_buf()],
+    };
+    let mut dirs = BTreeSet::new();
+    for package_dir in package_dirs {
+        let Ok(config) = NargoConfig::load(&package_dir) else {
+            continue;
+        };
+        for dep in config
+            .dependencies()?
+            .values()
+            .chain(config.dev_dependencies()?.values())
+            .chain(config.patches()?.values())
+        {
+            if let Some(dep_path) = &dep.path
+                && let Ok(dir) = package_dir.join(dep_path).canonicalize()
+            {
+                dirs.insert(dir);
+            }
+        }
+    }
+    Ok(dirs)
+}
+
+/// The packages in `dir` of `vendor/`, laid out like the nrpm cache, that aren't in nrpm.lock
+/// and that no path dependency points into.
+fn unlocked_vendored(
+    vendor_path: &Path,
+    dir: &Path,
+    locked: &BTreeSet<String>,
+    path_dependencies: &BTreeSet<PathBuf>,
+    artifacts: &mut Vec<Artifact>,
+) -> Result<()> {
+    for child in std::fs::read_dir(dir)? {
+        let child = child?;
+        let path = child.path();
+        if !child.file_type()?.is_dir() {
+            continue;
+        }
+        if !path.join("Nargo.toml").exists() {
+            unlocked_vendored(vendor_path, &path, locked, path_dependencies, artifacts)?;
+            continue;
+        }
+        let Some(key) = cache::entry_key(vendor_path, &path) else {
+            continue;
+        };
+        let canonical = path.canonicalize()?;
+        if locked.contains(&key)
+            || path_dependencies
+                .iter()
+                .any(|dep_dir| dep_dir.starts_with(&canonical))
+        {
+            continue;
+        }
+        artifacts.push(Artifact {
+            size: gc::dir_size(&path)?,
+            path,
+            reason: "vendored package not in nrpm.lock",
+        });
+    }
+    Ok(())
+}
+
+/// Packages in `vendor/` that nrpm.lock no longer locks. Without a nrpm.lock nothing is
+/// known to be unused, so nothing is removed.
+fn vendored(path: &Path, artifacts: &mut Vec<Artifact>) -> Result<()> {
+    let vendor_path = path.join("vendor");
+    let lockfile_path = path.join("nrpm.lock");
+    if !vendor_path.is_dir() || !lockfile_path.exists() {
+        return Ok(());
+    }
+    let lockfile = Lockfile::load_or_init(&lockfile_path)?;
+    let locked = lockfile
+        .entries()
+        .filter_map(|entry| entry.dependency().folder_path(&vendor_path).ok())
+        .filter_map(|dep_root_path| cache::entry_key(&vendor_path, &dep_root_path))
+        .collect::<BTreeSet<_>>();
+    unlocked_vendored(
+        &vendor_path,
+        &vendor_path,
+        &locked,
+        &path_dependencies(path)?,
+        artifacts,
+    )
+}
+
+/// Tarballs written to `target/package`, e.g. by `nrpm publish --archive`.
+fn package_archives(path: &Path, artifacts: &mut Vec<Artifact>) -> Result<()> {
+    let archive_dir = path.join("target").join("package");
+    if !archive_dir.is_dir() {
+        return Ok(());
+    }
+    for child in std::fs::read_dir(&archive_dir)? {
+        let child = child?;
+        let path = child.path();
+        if child.file_type()?.is_file() && path.extension().is_some_and(|ext| ext == "tar") {
+            artifacts.push(Artifact {
+                size: child.metadata()?.len(),
+                path,
+                reason: "package archive",
+            });
+        }
+    }
+    Ok(())
+}
+
+/// Remove the artifacts nrpm leaves in the package or workspace at `path`: lockfile backups,
+/// vendored packages nrpm.lock no longer locks, and archives in `target/package`.
+pub fn clean(path: &Path, dry_run: bool) -> Result<()> {
+    let mut artifacts = vec![];
+    lockfile_backups(path, &mut artifacts)?;
+    vendored(path, &mut artifacts)?;
+    package_archives(path, &mut artifacts)?;
+
+    for artifact in &artifacts {
+        say!(
+            "🗑️  {}: {}, {}",
+            artifact
+                .path
+                .strip_prefix(path)
+                .unwrap_or(&artifact.path)
+                .display(),
+            artifact.reason,
+            HumanBytes(artifact.size)
+        );
+        if dry_run {
+            continue;
+        }
+        if artifact.path.is_dir() {
+            std::fs::remove_dir_all(&artifact.path)?;
+            // leave no empty directories behind, like `vendor/github.com/noir-lang`
+            let mut parent = artifact.path.parent();
+            while let Some(dir) = parent
+                && dir != path.join("vendor")
+                && std::fs::remove_dir(dir).is_ok()
+            {
+                parent = dir.parent();
+            }
+        } else {
+            std::fs::remove_file(&artifact.path)?;
+        }
+    }
+
+    summary!(
+        "✅ {} {} artifact{}, freeing {}",
+        if dry_run { "would remove" } else { "removed" },
+        artifacts.len(),
+        if artifacts.len() == 1 { "" } else { "s" },
+        HumanBytes(artifacts.iter().map(|artifact| artifact.size).sum::<u64>())
+    );
+    Ok(())
+}
@@ -0,0 +1,599 @@
+use std::fmt::Display;
+use std::io::Write;
+use std::path::Path;
+use std::path::PathBuf;
+use std::sync::OnceLock;
+
+use anyhow::Context;
+use anyhow::Result;
+use onyx_api::REGISTRY_URL;
+
+use crate::failure::Failure;
+use crate::failure::FailureCode;
+use crate::output::say;
+use crate::output::summary;
+
+/// What values a setting accepts.
+enum Kind {
+    /// An http or https url.
+    Url,
+    /// A file that exists. Relative paths are resolved when the setting is set.
+    File,
+    /// A command on the `PATH`, or a path to one.
+    Command,
+    /// A hex encoded ed25519 public key.
+    PublicKey,
+    /// `true` or `false`.
+    Flag,
+    /// A secret without whitespace, not printed back.
+    Token,
+    /// A directory, created when it's used if it doesn't exist. Relative paths are resolved
+    /// when the setting is set.
+    Dir,
+}
+
+/// A setting that can be kept in config.toml in the nrpm config directory. The environment
+/// variable overrides the file.
+pub struct Setting {
+    pub key: &'static str,
+    pub env: Option<&'static str>,
+    pub about: &'static str,
+    kind: Kind,
+    default: fn() -> Option<String>,
+}
+
+pub const SETTINGS: &[Setting] = &[
+    Setting {
+        key: "registry_url",
+        env: Some("NRPM_REGISTRY_URL"),
+        about: "the registry packages are cloned from",
+        kind: Kind::Url,
+        default: || Some(super::DEFAULT_REGISTRY_URL.to_string()),
+    },
+    Setting {
+        key: "api_url",
+        env: Some("NRPM_API_URL"),
+        about: "the registry api",
+        kind: Kind::Url,
+        default: || Some(REGISTRY_URL.to_string()),
+    },
+    Setting {
+        key: "root_key",
+        env: Some("NRPM_ROOT_KEY"),
+        about: "public key of the registry's root of trust, trusted on first use if unset",
+        kind: Kind::PublicKey,
+        default: || None,
+    },
+    Setting {
+        key: "nargo",
+        env: Some("NRPM_NARGO"),
+        about: "the nargo binary nrpm runs",
+        kind: Kind::Command,
+        default: || Some("nargo".to_string()),
+    },
+    Setting {
+        key: "policy",
+        env: Some("NRPM_POLICY"),
+        about: "the policy file nrpm install enforces, instead of the closest nrpm-policy.toml",
+        kind: Kind::File,
+        default: || None,
+    },
+    Setting {
+        key: "cache_dir",
+        env: Some("NRPM_CACHE_DIR"),
+        about: "the directory nrpm keeps downloaded packages and index entries in",
+        kind: Kind::Dir,
+        default: || {
+            Some(
+                dirs::cache_dir()?
+                    .join("nrpm")
+                    .to_string_lossy()
+                    .to_string(),
+            )
+        },
+    },
+    Setting {
+        key: "stats",
+        env: None,
+        about: "report installs to the registry's download counts, see nrpm stats",
+        kind: Kind::Flag,
+        default: || Some("false".to_string()),
+    },
+    Setting {
+        key: "v_tags",
+        env: Some("NRPM_V_TAGS"),
+        about: "write registry dependencies with v prefixed tags, e.g. tag = \"v1.2.3\"",
+        kind: Kind::Flag,
+        default: || Some("false".to_string()),
+    },
+    Setting {
+        key: "no_emoji",
+        env: Some("NRPM_NO_EMOJI"),
+        about: "print output without emoji",
+        kind: Kind::Flag,
+        default: || Some("false".to_string()),
+    },
+    Setting {
+        key: "no_update_notice",
+        env: Some("NRPM_NO_UPDATE_NOTICE"),
+        about: "don't check for a newer nrpm",
+        kind: Kind::Flag,
+        default: || Some("false".to_string()),
+    },
+];
+
+/// Other registries, kept in the `registries` table of config.toml and set as
+/// `registries.<name>`. Dependencies are pinned to one with `registry = "<name>"`.
+const REGISTRIES: Setting = Setting {
+    key: "registries",
+    env: None,
+    about: "the api and git url of a registry dependencies can be pinned to by name",
+    kind: Kind::Url,
+    default: || None,
+};
+
+/// Tokens git dependencies are cloned with over http, kept in the `git_tokens` table of
+/// config.toml by host and set as `git_tokens.<host>`, e.g. `git_tokens.github.com`.
+const GIT_TOKENS: Setting = Setting {
+    key: "git_tokens",
+    env: None,
+    about: "the token, or user:token, git dependencies on the host are cloned with over https",
+    kind: Kind::Token,
+    default: || None,
+};
+
+/// Settings kept as tables of names to values.
+const TABLES: [&Setting; 2] = [&REGISTRIES, &GIT_TOKENS];
+
+/// The name `registry_url` goes by when dependencies are pinned to it.
+pub const DEFAULT_REGISTRY: &str = "default";
+
+/// Where the value of a setting came from.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Source {
+    Env(&'static str),
+    File,
+    Default,
+}
+
+impl Display for Source {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Source::Env(env) => write!(f, "env {env}"),
+            Source::File => write!(f, "config file"),
+            Source::Default => write!(f, "default"),
+        }
+    }
+}
+
+/// The nrpm config directory, e.g. `~/.config/nrpm`, created if it doesn't exist.
+pub fn config_dir() -> Result<PathBuf> {
+    let dir = dirs::config_dir()
+        .ok_or(anyhow::anyhow!("unable to determine user config directory"))?
+        .join("nrpm");
+    std::fs::create_dir_all(&dir)?;
+    Ok(dir)
+}
+
+pub fn config_path() -> Result<PathBuf> {
+    Ok(config_dir()?.join("config.toml"))
+}
+
+/// The `cache_dir` setting, `~/.cache/nrpm` by default.
+pub fn cache_dir() -> Result<PathBuf> {
+    value("cache_dir")
+        .map(PathBuf::from)
+        .ok_or(anyhow::anyhow!("unable to determine user cache directory"))
+}
+
+/// Write `contents` to `path` so only the user can read it. A new file is created with mode
+/// 0600, so it's never readable by others even briefly, and an existing one is restricted
+/// before it's written.
+pub fn write_private(path: &Path, contents: &str) -> Result<()> {
+    let mut options = std::fs::OpenOptions::new();
+    options.write(true).create(true).truncate(true);
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::OpenOptionsExt;
+        options.mode(0o600);
+    }
+    let mut file = options.open(path)?;
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        file.set_permissions(std::fs::Permissions::from_mode(0o600))?;
+    }
+    file.write_all(contents.as_bytes())?;
+    Ok(())
+}
+
+fn read_table(path: &Path) -> Result<toml::Table> {
+    if !path.exists() {
+        return Ok(toml::Table::new());
+    }
+    let str = std::fs::read_to_string(path)?;
+    toml::from_str::<toml::Table>(&str).with_context(|| format!("Failed to parse {path:?}"))
+}
+
+/// The settings in the config file at `path`, failing if any are unknown or invalid.
+fn read_file(path: &Path) -> Result<toml::Table> {
+    let table = read_table(path)?;
+    for (key, value) in &table {
+        if let Some(table_setting) = TABLES.into_iter().find(|s| s.key == key) {
+            let entries = value
+                .as_table()
+                .ok_or(anyhow::anyhow!("{key} in {path:?} must be a table"))?;
+            for (name, value) in entries {
+                check_table_name(table_setting, name)
+                    .with_context(|| format!("{key} in {path:?}"))?;
+                let value = value
+                    .as_str()
+                    .ok_or(anyhow::anyhow!("{key}.{name} in {path:?} must be a string"))?;
+                check(table_setting, value)
+                    .with_context(|| format!("{key}.{name} in {path:?} is invalid"))?;
+            }
+            continue;
+        }
+        let setting =
+            setting(key).with_context(|| format!("{path:?} has a setting nrpm doesn't know"))?;
+        let value = match value {
+            toml::Value::String(value) => value.clone(),
+            toml::Value::Boolean(value) => value.to_string(),
+            _ => anyhow::bail!("{key} in {path:?} must be a string or a boolean"),
+        };
+        check(setting, &value).with_context(|| format!("{key} in {path:?} is invalid"))?;
+    }
+    Ok(table)
+}
+
+/// The settings in config.toml, read once. A file that can't be read is reported by
+/// `check_file` and otherwise ignored.
+fn file() -> &'static toml::Table {
+    static FILE: OnceLock<toml::Table> = OnceLock::new();
+    FILE.get_or_init(|| {
+        config_path()
+            .and_then(|path| read_file(&path))
+            .unwrap_or_default()
+    })
+}
+
+/// Fail if config.toml can't be read or has settings that are invalid.
+pub fn check_file() -> Result<()> {
+    let path = config_path()?;
+    read_file(&path).map_err(|e| {
+        e.context(
+            Failure::new(
+                FailureCode::InvalidConfig,
+                "Failed to load the nrpm config file",
+            )
+            .with_advice(
+                "Fix the setting with nrpm config set, or remove it with nrpm config unset.",
+            )
+            .with_path(&path),
+        )
+    })?;
+    Ok(())
+}
+
+fn setting(key: &str) -> Result<&'static Setting> {
+    SETTINGS
+        .iter()
+        .find(|s| s.key == key)
+        .ok_or(anyhow::Error::new(
+            Failure::new(
+                FailureCode::InvalidConfig,
+                format!("Unknown setting \"{key}\""),
+            )
+            .with_advice(format!(
+                "Settings are: {}, {}.<name>, {}.<host>",
+                SETTINGS
+                    .iter()
+                    .map(|s| s.key)
+                    .collect::<Vec<_>>()
+                    .join(", "),
+                REGISTRIES.key,
+                GIT_TOKENS.key
+            )),
+        ))
+}
+
+/// The table setting and the name in it the setting `key` is, if it's e.g.
+/// `registries.<name>`.
+fn table_key(key: &str) -> Option<(&'static Setting, &str)> {
+    TABLES.into_iter().find_map(|table_setting| {
+        let name = key.strip_prefix(table_setting.key)?.strip_prefix('.')?;
+        Some((table_setting, name))
+    })
+}
+
+/// Check `name` can be a name in the table setting `table_setting`.
+fn check_table_name(table_setting: &Setting, name: &str) -> Result<()> {
+    if table_setting.key == REGISTRIES.key {
+        check_registry_name(name)
+    } else {
+        check_host(name)
+    }
+}
+
+/// Registry names are used in Nargo.toml files, so they're kept simple.
+fn check_registry_name(name: &str) -> Result<()> {
+    if name.is_empty()
+        || !name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+    {
+        anyhow::bail!("registry name {name:?} may only contain letters, numbers, - and _");
+    }
+    if name == DEFAULT_REGISTRY {
+        anyhow::bail!("registry name {name:?} is reserved for registry_url");
+    }
+    Ok(())
+}
+
+/// Hosts are written like they are in urls, e.g. `github.com` or `localhost:8080`.
+fn check_host(name: &str) -> Result<()> {
+    if name.is_empty()
+        || !name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '.' || c == ':')
+    {
+        anyhow::bail!("host {name:?} may only contain letters, numbers, -, . and :");
+    }
+    Ok(())
+}
+
+/// The entries of the table setting `table_setting` in config.toml, name to value.
+fn table_entries(table_setting: &Setting) -> Vec<(String, String)> {
+    file()
+        .get(table_setting.key)
+        .and_then(toml::Value::as_table)
+        .map(|entries| {
+            entries
+                .iter()
+                .filter_map(|(name, value)| Some((name.clone(), value.as_str()?.to_string())))
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+/// The registries in the `registries` table of config.toml, name to url.
+pub fn registries() -> Vec<(String, String)> {
+    table_entries(&REGISTRIES)
+}
+
+/// The token in the `git_tokens` table of config.toml for `host`, e.g. `github.com`.
+pub fn git_token(host: &str) -> Option<String> {
+    table_entries(&GIT_TOKENS)
+        .into_iter()
+        .find(|(name, _)| name.eq_ignore_ascii_case(host))
+        .map(|(_, token)| token)
+}
+
+/// The value of `setting` in `table`, and where it came from.
+fn resolve(setting: &Setting, table: &toml::Table) -> Option<(String, Source)> {
+    if let Some(env) = setting.env
+        && let Some(value) = std::env::var(env).ok().filter(|v| !v.is_empty())
+    {
+        // flags are set by the variable being set, whatever its value
+        return match setting.kind {
+            Kind::Flag => Some(("true".to_string(), Source::Env(env))),
+            _ => Some((value, Source::Env(env))),
+        };
+    }
+    match table.get(setting.key) {
+        Some(toml::Value::String(value)) => Some((value.clone(), Source::File)),
+        Some(toml::Value::Boolean(value)) => Some((value.to_string(), Source::File)),
+        _ => (setting.default)().map(|value| (value, Source::Default)),
+    }
+}
+
+/// The value of the setting `key` and where it came from, `None` if it isn't set and has no
+/// default.
+pub fn get(key: &str) -> Option<(String, Source)> {
+    if let Some((table_setting, name)) = table_key(key) {
+        return table_entries(table_setting)
+            .into_iter()
+            .find(|(n, _)| n == name)
+            .map(|(_, value)| (value, Source::File));
+    }
+    resolve(setting(key).ok()?, file())
+}
+
+/// The value of the setting `key`, see `get`.
+pub fn value(key: &str) -> Option<String> {
+    get(key).map(|(value, _)| value)
+}
+
+/// Whether the flag `key` is on.
+pub fn flag(key: &str) -> bool {
+    value(key).is_some_and(|v| v == "true")
+}
+
+/// Check `value` is something `setting` accepts, returning it as it's stored.
+fn check(setting: &Setting, value: &str) -> Result<String> {
+    match setting.kind {
+        Kind::Url => {
+            if !value.starts_with("https://") && !value.starts_with("http://") {
+                anyhow::bail!("{value:?} is not an http or https url");
+            }
+            Ok(value.trim_end_matches('/').to_string())
+        }
+        Kind::File => {
+            let path = std::path::absolute(value)?;
+            if !path.is_file() {
+                anyhow::bail!("{path:?} is not a file");
+            }
+            Ok(path.to_string_lossy().to_string())
+        }
+        Kind::Command => {
+            // a bare name is looked up on the PATH when it's run
+            if !value.contains(std::path::MAIN_SEPARATOR) {
+                return Ok(value.to_string());
+            }
+            let path = std::path::absolute(value)?;
+            if !path.is_file() {
+                anyhow::bail!("{path:?} is not a file");
+            }
+            Ok(path.to_string_lossy().to_string())
+        }
+        Kind::Dir => {
+            let path = std::path::absolute(value)?;
+            if path.exists() && !path.is_dir() {
+                anyhow::bail!("{path:?} is not a directory");
+            }
+            Ok(path.to_string_lossy().to_string())
+        }
+        Kind::PublicKey => {
+            if value.len() != 64 || !value.chars().all(|c| c.is_ascii_hexdigit()) {
+                anyhow::bail!("{value:?} is not a hex encoded ed25519 public key");
+            }
+            Ok(value.to_lowercase())
+        }
+        Kind::Flag => match value {
+            "true" | "false" => Ok(value.to_string()),
+            _ => anyhow::bail!("{value:?} is not true or false"),
+        },
+        Kind::Token => {
+            if value.is_empty() || value.chars().any(char::is_whitespace) {
+                anyhow::bail!("a token can't be empty or contain whitespace");
+            }
+            Ok(value.to_string())
+        }
+    }
+}
+
+/// `value` of `setting` as it's printed, tokens are hidden.
+fn shown(setting: &Setting, value: &str) -> String {
+    match setting.kind {
+        Kind::Token => "<hidden>".to_string(),
+        _ => format!("{value:?}"),
+    }
+}
+
+/// Write `value` to config.toml as the setting `key`, or remove the setting if `value` is
+/// `None`. Other settings are kept even if they're invalid, so each can be fixed in turn.
+/// Returns the new value of the setting and where it comes from.
+pub fn write(key: &str, value: Option<&str>) -> Result<Option<(String, Source)>> {
+    if let Some((table_setting, name)) = table_key(key) {
+        return write_table(table_setting, name, value);
+    }
+    let setting = setting(key)?;
+    let path = config_path()?;
+    let mut table = read_writable(&path)?;
+    match value {
+        Some(value) => {
+            let value = check(setting, value).map_err(|e| {
+                e.context(Failure::new(
+                    FailureCode::InvalidConfig,
+                    format!("Invalid value for {key}"),
+                ))
+            })?;
+            let value = match setting.kind {
+                Kind::Flag => toml::Value::Boolean(value == "true"),
+                _ => toml::Value::String(value),
+            };
+            table.insert(key.to_string(), value);
+        }
+        None => {
+            table.remove(key);
+        }
+    }
+    // registries.<name> tokens are kept in the same file
+    write_private(&path, &toml::to_string(&table)?)?;
+    Ok(resolve(setting, &table))
+}
+
+/// The config file at `path` to change, which only needs to be valid toml.
+fn read_writable(path: &Path) -> Result<toml::Table> {
+    read_table(path).map_err(|e| {
+        e.context(
+            Failure::new(
+                FailureCode::InvalidConfig,
+                "Failed to load the nrpm config file",
+            )
+            .with_advice("Fix the toml syntax of the file, or remove it.")
+            .with_path(path),
+        )
+    })
+}
+
+/// `write` for a name in a table setting, e.g. `registries.<name>`.
+fn write_table(
+    table_setting: &Setting,
+    name: &str,
+    value: Option<&str>,
+) -> Result<Option<(String, Source)>> {
+    let key = table_setting.key;
+    let path = config_path()?;
+    let mut table = read_writable(&path)?;
+    if !table.get(key).is_some_and(toml::Value::is_table) {
+        table.insert(key.to_string(), toml::Value::Table(toml::Table::new()));
+    }
+    let entries = table
+        .get_mut(key)
+        .and_then(toml::Value::as_table_mut)
+        .expect("table setting is a table");
+    let written = match value {
+        Some(value) => {
+            let value = check_table_name(table_setting, name)
+                .and_then(|()| check(table_setting, value))
+                .map_err(|e| {
+                    e.context(Failure::new(
+                        FailureCode::InvalidConfig,
+                        format!("Invalid value for {key}.{name}"),
+                    ))
+                })?;
+            entries.insert(name.to_string(), toml::Value::String(value.clone()));
+            Some((value, Source::File))
+        }
+        None => {
+            entries.remove(name);
+            if entries.is_empty() {
+                table.remove(key);
+            }
+            None
+        }
+    };
+    write_private(&path, &toml::to_string(&table)?)?;
+    Ok(written)
+}
+
+/// `write` the setting `key` and print its new value.
+pub fn set(key: &str, value: Option<&str>) -> Result<()> {
+    let written = write(key, value)?;
+    let setting = table_key(key).map_or_else(|| setting(key), |(s, _)| Ok(s))?;
+    match written {
+        Some((value, Source::Env(env))) => {
+            let value = shown(setting, &value);
+            summary!("⚠️  {key} = {value} from {env}, which takes precedence over the config file")
+        }
+        Some((value, source)) => summary!("✅ {key} = {} ({source})", shown(setting, &value)),
+        None => summary!("✅ {key} is unset"),
+    }
+    Ok(())
+}
+
+/// Print every setting, its value and where the value came from.
+pub fn list() {
+    for setting in SETTINGS {
+        match resolve(setting, file()) {
+            Some((value, source)) => summary!("{} = {value:?} ({source})", setting.key),
+            None => summary!("{} is unset", setting.key),
+        }
+        match setting.env {
+            Some(env) => say!("    {}, {env} overrides it", setting.about),
+            None => say!("    {}", setting.about),
+        }
+    }
+    for table_setting in TABLES {
+        for (name, value) in table_entries(table_setting) {
+            summary!(
+                "{}.{name} = {} ({})",
+                table_setting.key,
+                shown(table_setting, &value),
+                Source::File
+            );
+            say!("    {}", table_setting.about);
+        }
+    }
+}
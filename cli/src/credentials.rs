@@ -0,0 +1,58 @@
+use std::collections::BTreeMap;
+use std::path::PathBuf;
+
+use anyhow::Result;
+use onyx_api::prelude::*;
+
+use crate::config;
+
+// access tokens with less time than this remaining are refreshed before use so a slow
+// publish doesn't expire mid-flow
+const MIN_TOKEN_LIFETIME: u64 = 5 * 60;
+
+/// Logins saved between invocations, keyed by registry url. With a refresh token saved the
+/// browser is only needed when the session is revoked or goes unused for a long time.
+fn credentials_path() -> Result<PathBuf> {
+    Ok(config::config_dir()?.join("credentials.toml"))
+}
+
+fn load_all() -> BTreeMap<String, LoginResponse> {
+    credentials_path()
+        .and_then(|path| Ok(std::fs::read_to_string(path)?))
+        .ok()
+        .and_then(|str| toml::from_str(&str).ok())
+        .unwrap_or_default()
+}
+
+pub fn load(registry_url: &str) -> Option<LoginResponse> {
+    load_all().remove(registry_url)
+}
+
+pub fn save(registry_url: &str, login: &LoginResponse) -> Result<()> {
+    let mut all = load_all();
+    all.insert(registry_url.to_string(), login.clone());
+    config::write_private(&credentials_path()?, &toml::to_string(&all)?)
+}
+
+/// Use or refresh a saved login. Returns `None` if the user needs to authorize again.
+pub async fn resume(api: &OnyxApi, registry_url: &str) -> Option<LoginResponse> {
+    let saved = load(registry_url)?;
+    if saved.expires_at > timestamp() + MIN_TOKEN_LIFETIME
+        && api.auth(saved.token.clone()).await.is_ok()
+    {
+        return Some(saved);
+    }
+    let refresh_token = saved.refresh_token?;
+    match api.refresh(refresh_token).await {
+        Ok(login) => {
+            if let Err(e) = save(registry_url, &login) {
+                log::warn!("failed to save credentials: {e:?}");
+            }
+            Some(login)
+        }
+        Err(e) => {
+            log::debug!("failed to refresh session: {e:?}");
+            None
+        }
+    }
+}
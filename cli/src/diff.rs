@@ -0,0 +1,53 @@
+use anyhow::Result;
+use onyx_api::prelude::*;
+
+/// Print the files that differ between the versions `from` and `to` of `package_name`, as
+/// json if `json`. Unless `stat`, the unified diff of each changed text file follows the list.
+pub async fn diff(
+    api: &OnyxApi,
+    package_name: &str,
+    from: &str,
+    to: &str,
+    stat: bool,
+    json: bool,
+) -> Result<()> {
+    let diff = api.package_diff(package_name, from, to).await?;
+    if json {
+        println!("{}", serde_json::to_string_pretty(&diff)?);
+        return Ok(());
+    }
+    if diff.files.is_empty() {
+        println!("No changes between {from} and {to}");
+        return Ok(());
+    }
+    for file in &diff.files {
+        let change = match file.change {
+            FileChange::Added => "added",
+            FileChange::Removed => "removed",
+            FileChange::Modified => "modified",
+        };
+        let size = match (file.from_size, file.to_size) {
+            (Some(from_size), Some(to_size)) => format!("{from_size} -> {to_size} bytes"),
+            (None, Some(size)) | (Some(size), None) => format!("{size} bytes"),
+            (None, None) => String::new(),
+        };
+        println!("{change:>8}  {}  ({size})", file.path);
+    }
+    if stat {
+        return Ok(());
+    }
+    for file in &diff.files {
+        println!();
+        match &file.unified_diff {
+            Some(unified_diff) => print!("{unified_diff}"),
+            // large or binary files are only compared by hash
+            None => println!(
+                "{}: {} -> {}",
+                file.path,
+                file.from_blake3.as_deref().unwrap_or("(none)"),
+                file.to_blake3.as_deref().unwrap_or("(none)")
+            ),
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,227 @@
+use std::fmt::Display;
+use std::path::PathBuf;
+
+use onyx_api::prelude::*;
+use serde::Serialize;
+
+use crate::output;
+
+/// Kinds of failure nrpm can explain, so tooling can react to them. Serialized in snake case
+/// with `--json`.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum FailureCode {
+    /// A package doesn't match the hash nrpm.lock has for it.
+    LockfileMismatch,
+    /// A package doesn't match the hash the lockfile of a package depending on it has.
+    DependentLockfileMismatch,
+    /// A package downloaded again still doesn't match its lockfile.
+    PublishedContentChanged,
+    /// A lockfile can't be parsed.
+    MalformedLockfile,
+    /// A locked package isn't in the system cache.
+    NotInstalled,
+    /// A path dependency isn't published as it is.
+    UnpublishedPathDependency,
+    /// A version isn't published, or not as expected.
+    NotPublished,
+    /// The registry's signed snapshot doesn't vouch for what it serves.
+    UntrustedRegistry,
+    /// The command needs a git repository.
+    NotInGitRepository,
+    /// There's no signing key, or there already is one.
+    SigningKey,
+    /// An artifact to upload or download couldn't be found or chosen.
+    Artifact,
+    /// nargo couldn't be run.
+    NargoNotFound,
+    /// A package in the dependency tree breaks `nrpm-policy.toml`.
+    PolicyViolation,
+    /// A dependency is a `bin` or `contract` package, which nargo can't depend on.
+    NotALibrary,
+    /// The nrpm config file, or a value for it, is invalid.
+    InvalidConfig,
+    /// A dependency is pinned to a registry that isn't configured, or doesn't point into it.
+    UnknownRegistry,
+    /// A package name is published in more than one configured registry.
+    AmbiguousPackage,
+    /// The registry and this version of nrpm can't work together.
+    IncompatibleRegistry,
+    /// A new git dependency from outside the registries wasn't trusted on first use.
+    UntrustedDependency,
+    /// A git dependency from outside the registries downloaded again doesn't match the hash
+    /// it was trusted with, its tag was moved upstream.
+    TagMoved,
+    /// The host of a git dependency needs credentials nrpm doesn't have, or refused them.
+    GitCredentials,
+    /// An error returned by the registry, see `registry_code`.
+    Registry,
+    /// Anything else.
+    Other,
+}
+
+/// A failure nrpm can explain: what went wrong, what to do about it, and the files involved.
+/// Attach it to an error as context, the outermost one is rendered by `report`.
+#[derive(Clone, Debug, Serialize)]
+pub struct Failure {
+    pub code: FailureCode,
+    pub message: String,
+    pub advice: Option<String>,
+    pub paths: Vec<PathBuf>,
+}
+
+impl Failure {
+    pub fn new(code: FailureCode, message: impl Into<String>) -> Self {
+        Self {
+            code,
+            message: message.into(),
+            advice: None,
+            paths: vec![],
+        }
+    }
+
+    pub fn with_advice(mut self, advice: impl Into<String>) -> Self {
+        self.advice = Some(advice.into());
+        self
+    }
+
+    pub fn with_path(mut self, path: impl Into<PathBuf>) -> Self {
+        self.paths.push(path.into());
+        self
+    }
+}
+
+impl Display for Failure {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.message)
+    }
+}
+
+impl std::error::Error for Failure {}
+
+/// An error as it's rendered, see `report`.
+#[derive(Serialize)]
+struct Report {
+    code: FailureCode,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    registry_code: Option<OnyxErrorCode>,
+    message: String,
+    /// The errors that led to `message`, outermost first.
+    causes: Vec<String>,
+    advice: Vec<String>,
+    paths: Vec<PathBuf>,
+}
+
+impl Report {
+    fn new(err: &anyhow::Error) -> Self {
+        let failure = err.downcast_ref::<Failure>();
+        let api_error = err
+            .chain()
+            .find_map(|cause| cause.downcast_ref::<ApiError>());
+        let version_mismatch = err
+            .chain()
+            .find_map(|cause| cause.downcast_ref::<ApiVersionMismatch>());
+        let code = match (failure, api_error, version_mismatch) {
+            (Some(failure), _, _) => failure.code,
+            (None, Some(_), _) => FailureCode::Registry,
+            (None, None, Some(_)) => FailureCode::IncompatibleRegistry,
+            (None, None, None) => FailureCode::Other,
+        };
+        let mut advice = vec![];
+        if let Some(failure_advice) = failure.and_then(|f| f.advice.clone()) {
+            advice.push(failure_advice);
+        }
+        if let Some(api_advice) = api_error.and_then(|e| error_code_advice(e.code)) {
+            advice.push(api_advice.to_string());
+        }
+        match version_mismatch {
+            Some(ApiVersionMismatch::ClientTooOld { .. }) => {
+                advice.push("Update nrpm with: cargo install nrpm --locked".to_string());
+            }
+            Some(ApiVersionMismatch::ServerTooOld { .. }) => advice.push(
+                "Ask the registry operator to update onyx, or install an older nrpm with: cargo install nrpm --locked --version <version>"
+                    .to_string(),
+            ),
+            None => {}
+        }
+        Self {
+            code,
+            registry_code: api_error.map(|e| e.code),
+            message: err.to_string(),
+            causes: err.chain().skip(1).map(|cause| cause.to_string()).collect(),
+            advice,
+            paths: failure.map(|f| f.paths.clone()).unwrap_or_default(),
+        }
+    }
+}
+
+/// Print `err` to stderr, as a json object with `json`. Returns the process exit status for
+/// it.
+pub fn report(err: &anyhow::Error, json: bool) -> i32 {
+    let report = Report::new(err);
+    if json {
+        eprintln!("{}", serde_json::to_string(&report).unwrap_or_default());
+    } else {
+        eprintln!("{} {}", output::icon("❌", "error:"), report.message);
+        for cause in &report.causes {
+            eprintln!("   {cause}");
+        }
+        for path in &report.paths {
+            eprintln!("   --> {}", path.display());
+        }
+        for advice in &report.advice {
+            eprintln!("{} {advice}", output::icon("💡", "help:"));
+        }
+    }
+    report.registry_code.map(error_code_exit).unwrap_or(1)
+}
+
+/// Process exit status for an error returned by the registry.
+fn error_code_exit(code: OnyxErrorCode) -> i32 {
+    match code {
+        OnyxErrorCode::InvalidCredentials
+        | OnyxErrorCode::InvalidToken
+        | OnyxErrorCode::ExpiredToken => 2,
+        OnyxErrorCode::Forbidden | OnyxErrorCode::QuotaExceeded | OnyxErrorCode::NameTooSimilar => {
+            3
+        }
+        OnyxErrorCode::NotFound => 4,
+        OnyxErrorCode::Conflict => 5,
+        OnyxErrorCode::HashMismatch | OnyxErrorCode::InvalidPackage => 6,
+        OnyxErrorCode::BadRequest
+        | OnyxErrorCode::ValidationFailed
+        | OnyxErrorCode::PayloadTooLarge
+        | OnyxErrorCode::UnsupportedMediaType => 7,
+        OnyxErrorCode::RateLimited => 8,
+        OnyxErrorCode::Internal | OnyxErrorCode::Unknown => 1,
+    }
+}
+
+fn error_code_advice(code: OnyxErrorCode) -> Option<&'static str> {
+    match code {
+        OnyxErrorCode::InvalidToken | OnyxErrorCode::ExpiredToken => {
+            Some("Your session is no longer valid, run the command again to re-authorize.")
+        }
+        OnyxErrorCode::Forbidden => {
+            Some("Only the owner of a package may publish new versions of it or transfer it.")
+        }
+        OnyxErrorCode::QuotaExceeded => {
+            Some("Ask the registry admins to raise your storage quota.")
+        }
+        OnyxErrorCode::NameTooSimilar => Some(
+            "Choose a more distinctive package name, or wait for a registry admin to approve it.",
+        ),
+        OnyxErrorCode::NotFound => Some("Check the spelling of the package name."),
+        OnyxErrorCode::Conflict => {
+            Some("Versions are immutable, bump the version in Nargo.toml and publish again.")
+        }
+        OnyxErrorCode::HashMismatch => {
+            Some("The package changed during upload, make sure nothing is writing to it.")
+        }
+        OnyxErrorCode::RateLimited => {
+            Some("You've made too many requests, wait a while and try again.")
+        }
+        OnyxErrorCode::Internal => Some("The registry encountered an error, try again later."),
+        _ => None,
+    }
+}
@@ -0,0 +1,129 @@
+use std::path::Path;
+use std::path::PathBuf;
+
+use anyhow::Result;
+
+use crate::cache;
+use crate::failure::Failure;
+use crate::failure::FailureCode;
+use crate::install;
+use crate::lockfile::LockEntry;
+use crate::lockfile::Lockfile;
+use crate::output;
+use crate::registry;
+
+/// Download `entry` into the cache at `dep_root_path`, from the registry if it's a registry
+/// package and with git otherwise.
+async fn download(entry: &LockEntry, dep_root_path: &Path) -> Result<()> {
+    let dep = entry.dependency();
+    let bar = install::download_bar(&dep)?.with_prefix(entry.identifier());
+    output::plain(format!("    {}: downloading", entry.identifier()));
+    if !install::download_dependency(&dep, dep_root_path, &bar).await? {
+        install::clone_dependency(&dep, dep_root_path, &bar)?;
+    }
+    bar.finish_and_clear();
+    Ok(())
+}
+
+/// Download every package in the nrpm.lock in `path` into the system cache and check each
+/// against its locked hash. Nargo.toml isn't read, so a Dockerfile can copy just nrpm.lock
+/// and fetch dependencies in a layer that's only rebuilt when it changes. Cached packages
+/// that don't match are quarantined and downloaded again.
+pub async fn fetch(path: &Path) -> Result<()> {
+    let lockfile_path = path.join("nrpm.lock");
+    if !lockfile_path.exists() {
+        anyhow::bail!("No nrpm.lock found in {path:?}, run nrpm install to create one");
+    }
+    let lockfile = Lockfile::load_or_init(&lockfile_path)?;
+
+    let multiprogress = output::multiprogress();
+    let progress = output::spinner(&multiprogress);
+    let dep_cache_path = cache::cache_path()?;
+    let _cache_lock = cache::lock_cache(&dep_cache_path, &progress)?;
+    output::step(
+        &multiprogress,
+        &progress,
+        "🌨️  Fetching locked packages...".to_string(),
+    );
+
+    let mut downloaded = 0;
+    for entry in lockfile.entries() {
+        let identifier = entry.identifier();
+        let dep = entry.dependency();
+        let dep_root_path = dep.folder_path(&dep_cache_path)?;
+        let _dep_lock =
+            cache::lock_dependency(&dep_cache_path, &dep_root_path, &identifier, &progress)?;
+        if std::fs::exists(&dep_root_path)? {
+            progress.set_message(format!("{identifier}: hashing"));
+            let hash = nrpm_tarball::hash_dir(&dep_root_path)?.to_string();
+            if hash == entry.blake3 {
+                continue;
+            }
+            let quarantined = cache::quarantine(
+                &dep_cache_path,
+                &dep_root_path,
+                &identifier,
+                &entry.blake3,
+                &hash,
+            )?;
+            output::note(
+                &progress,
+                format!(
+                    "🩹 {identifier} did not match nrpm.lock, moved it to {quarantined:?} and downloading again"
+                ),
+            );
+        }
+        progress.set_message(format!("{identifier}: downloading"));
+        download(entry, &dep_root_path).await?;
+        downloaded += 1;
+        let hash = nrpm_tarball::hash_dir(&dep_root_path)?.to_string();
+        if hash != entry.blake3 && registry::of(&dep)?.is_none() {
+            return Err(install::tag_moved(
+                &dep,
+                &dep_root_path,
+                &entry.blake3,
+                &hash,
+            ));
+        }
+        if hash != entry.blake3 {
+            Err(anyhow::Error::new(
+                Failure::new(
+                    FailureCode::PublishedContentChanged,
+                    format!("downloaded hash: {hash}"),
+                )
+                .with_advice(format!(
+                    "Contact the author of {identifier}, the published content changed."
+                ))
+                .with_path(&dep_root_path),
+            )
+            .context(format!("expected hash: {}", entry.blake3))
+            .context(format!(
+                "downloaded {identifier} does not match the lockfile"
+            )))?;
+        }
+    }
+
+    let used = lockfile
+        .entries()
+        .map(|entry| entry.dependency().folder_path(&dep_cache_path))
+        .collect::<Result<Vec<_>>>()?;
+    if let Err(e) = cache::record_use(
+        &dep_cache_path,
+        used.iter().map(PathBuf::as_path),
+        &lockfile_path,
+    ) {
+        log::warn!("failed to record cache usage: {e:?}");
+    }
+    cache::link_into_nargo(&dep_cache_path, used.iter().map(PathBuf::as_path))?;
+    let total = lockfile.entries().count();
+    output::finish(
+        &multiprogress,
+        &progress,
+        format!(
+            "✅ {total} locked package{} in the cache, {downloaded} downloaded",
+            if total == 1 { "" } else { "s" }
+        ),
+    );
+    progress.finish_and_clear();
+    Ok(())
+}
@@ -0,0 +1,230 @@
+use std::collections::BTreeSet;
+use std::path::Path;
+use std::path::PathBuf;
+use std::time::Duration;
+use std::time::UNIX_EPOCH;
+
+use anyhow::Context;
+use anyhow::Result;
+use indicatif::HumanBytes;
+use indicatif::ProgressBar;
+use onyx_api::prelude::*;
+
+use crate::cache;
+use crate::cache::Usage;
+use crate::lockfile::Lockfile;
+use crate::output::say;
+use crate::output::summary;
+
+/// What `nrpm cache gc` removes.
+pub struct GcOptions {
+    /// Remove entries not used for longer than this.
+    pub max_age: Option<Duration>,
+    /// Remove the least recently used entries until the cache is no larger than this.
+    pub max_size: Option<u64>,
+    /// Print what would be removed without removing it.
+    pub dry_run: bool,
+}
+
+/// A package in the cache, e.g. `~/.cache/nrpm/packages/v1/github.com/noir-lang/ec/v0.1.2`.
+struct Entry {
+    key: String,
+    path: PathBuf,
+    /// Seconds since the epoch, from the usage file or the modification time of the directory
+    /// if nrpm didn't put it there.
+    last_used: u64,
+    size: u64,
+}
+
+/// Parse a size like `5G` or `500M`, in powers of 1024.
+pub fn parse_size(size: &str) -> Result<u64> {
+    let size = size.trim();
+    let size = size.strip_suffix(['B', 'b']).unwrap_or(size);
+    let (number, unit) = match size.char_indices().last() {
+        Some((i, c)) if c.is_ascii_alphabetic() => (&size[..i], c.to_ascii_uppercase()),
+        _ => (size, ' '),
+    };
+    let shift = match unit {
+        ' ' => 0,
+        'K' => 10,
+        'M' => 20,
+        'G' => 30,
+        'T' => 40,
+        _ => anyhow::bail!("unknown size unit {unit:?}, use K, M, G or T"),
+    };
+    let number = number
+        .parse::<u64>()
+        .with_context(|| format!("{size:?} is not a size like 5G"))?;
+    number
+        .checked_mul(1 << shift)
+        .ok_or(anyhow::anyhow!("{size:?} is too large"))
+}
+
+/// The size of the files in `path`. Links aren't followed.
+pub fn dir_size(path: &Path) -> Result<u64> {
+    let mut size = 0;
+    for child in std::fs::read_dir(path)? {
+        let child = child?;
+        let metadata = child.path().symlink_metadata()?;
+        if metadata.is_dir() {
+            size += dir_size(&child.path())?;
+        } else {
+            size += metadata.len();
+        }
+    }
+    Ok(size)
+}
+
+/// The packages in `dir`: directories nrpm recorded using, and the outermost directories with
+/// a Nargo.toml for packages it lost track of.
+fn find_entries(
+    dep_cache_path: &Path,
+    dir: &Path,
+    usage: &Usage,
+    entries: &mut Vec<Entry>,
+) -> Result<()> {
+    for child in std::fs::read_dir(dir)? {
+        let child = child?;
+        let path = child.path();
+        if !child.file_type()?.is_dir() || path == dep_cache_path.join(".nrpm") {
+            continue;
+        }
+        let Some(key) = cache::entry_key(dep_cache_path, &path) else {
+            continue;
+        };
+        let last_used = match usage.last_used.get(&key) {
+            Some(last_used) => *last_used,
+            None if path.join("Nargo.toml").exists() => child
+                .metadata()?
+                .modified()?
+                .duration_since(UNIX_EPOCH)?
+                .as_secs(),
+            None => {
+                find_entries(dep_cache_path, &path, usage, entries)?;
+                continue;
+            }
+        };
+        entries.push(Entry {
+            key,
+            size: dir_size(&path)?,
+            path,
+            last_used,
+        });
+    }
+    Ok(())
+}
+
+/// The entries locked by the lockfiles in `usage`. Lockfiles that no longer exist are
+/// forgotten.
+fn locked_entries(dep_cache_path: &Path, usage: &mut Usage) -> Result<BTreeSet<String>> {
+    usage.lockfiles.retain(|path| path.exists());
+    let mut locked = BTreeSet::new();
+    for path in &usage.lockfiles {
+        let lockfile = Lockfile::load_or_init(path)
+            .with_context(|| format!("Failed to load lockfile {path:?}"))?;
+        for entry in lockfile.entries() {
+            if let Ok(dep_root_path) = entry.dependency().folder_path(dep_cache_path)
+                && let Some(key) = cache::entry_key(dep_cache_path, &dep_root_path)
+            {
+                locked.insert(key);
+            }
+        }
+    }
+    Ok(locked)
+}
+
+/// Remove the entry at `path`, and the directories above it that are left empty.
+fn remove_entry(dep_cache_path: &Path, path: &Path) -> Result<()> {
+    std::fs::remove_dir_all(path)?;
+    let mut parent = path.parent();
+    while let Some(dir) = parent
+        && dir != dep_cache_path
+        && std::fs::remove_dir(dir).is_ok()
+    {
+        parent = dir.parent();
+    }
+    Ok(())
+}
+
+fn days_ago(now: u64, then: u64) -> String {
+    match now.saturating_sub(then) / (24 * 60 * 60) {
+        0 => "today".to_string(),
+        1 => "1 day ago".to_string(),
+        days => format!("{days} days ago"),
+    }
+}
+
+/// Remove the least recently used cache entries that no known lockfile locks: those unused for
+/// longer than `max_age`, then the oldest until the cache fits in `max_size`.
+pub fn gc(options: &GcOptions) -> Result<()> {
+    let dep_cache_path = cache::cache_path()?;
+    let _cache_lock = cache::lock_cache_exclusive(&dep_cache_path, &ProgressBar::hidden())?;
+    let mut usage = cache::read_usage(&dep_cache_path)?;
+    let locked = locked_entries(&dep_cache_path, &mut usage)?;
+    let mut entries = vec![];
+    find_entries(&dep_cache_path, &dep_cache_path, &usage, &mut entries)?;
+    entries.sort_by_key(|entry| entry.last_used);
+
+    let now = timestamp();
+    let total = entries.iter().map(|entry| entry.size).sum::<u64>();
+    let mut remaining = total;
+    let mut removed = 0;
+    let mut kept_locked = 0;
+    for entry in &entries {
+        let expired = options
+            .max_age
+            .is_some_and(|max_age| now.saturating_sub(entry.last_used) > max_age.as_secs());
+        let oversized = options
+            .max_size
+            .is_some_and(|max_size| remaining > max_size);
+        if !expired && !oversized {
+            continue;
+        }
+        if locked.contains(&entry.key) {
+            kept_locked += 1;
+            continue;
+        }
+        say!(
+            "🗑️  {}: {}, last used {}",
+            entry.key,
+            HumanBytes(entry.size),
+            days_ago(now, entry.last_used)
+        );
+        if !options.dry_run {
+            cache::unlink_from_nargo(&dep_cache_path, &entry.path)?;
+            remove_entry(&dep_cache_path, &entry.path)?;
+            usage.last_used.remove(&entry.key);
+        }
+        remaining -= entry.size;
+        removed += 1;
+    }
+    if !options.dry_run {
+        // forget entries removed some other way
+        usage
+            .last_used
+            .retain(|key, _| entries.iter().any(|entry| entry.key == *key));
+        cache::write_usage(&dep_cache_path, &usage)?;
+    }
+
+    summary!(
+        "✅ {} {removed} of {} cached package{}, freeing {}. The cache is {}",
+        if options.dry_run {
+            "would remove"
+        } else {
+            "removed"
+        },
+        entries.len(),
+        if entries.len() == 1 { "" } else { "s" },
+        HumanBytes(total - remaining),
+        HumanBytes(remaining)
+    );
+    if kept_locked > 0 {
+        summary!(
+            "🔒 kept {kept_locked} package{} locked by {} known lockfile{}",
+            if kept_locked == 1 { "" } else { "s" },
+            usage.lockfiles.len(),
+            if usage.lockfiles.len() == 1 { "" } else { "s" }
+        );
+    }
+    Ok(())
+}
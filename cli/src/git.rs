@@ -0,0 +1,128 @@
+use std::process::Command;
+
+use reqwest::Url;
+
+use crate::config;
+use crate::failure::Failure;
+use crate::failure::FailureCode;
+
+/// A git credential helper answering with the username and password nrpm passes it in the
+/// environment, so the token isn't in the arguments of any process.
+const CREDENTIAL_HELPER: &str = "!f() { test \"$1\" = get && echo \"username=$NRPM_GIT_USERNAME\" && echo \"password=$NRPM_GIT_PASSWORD\"; }; f";
+
+/// The username a token without one is sent with, accepted by GitHub and ignored by most
+/// other hosts.
+const TOKEN_USERNAME: &str = "x-access-token";
+
+/// The url git clones `git_url` from. `git+ssh://` urls are written `ssh://`.
+pub fn clone_url(git_url: &str) -> String {
+    match git_url.strip_prefix("git+ssh://") {
+        Some(rest) => format!("ssh://{rest}"),
+        None => git_url.to_string(),
+    }
+}
+
+fn is_ssh(git_url: &str) -> bool {
+    clone_url(git_url).starts_with("ssh://")
+}
+
+fn is_http(git_url: &str) -> bool {
+    git_url.starts_with("https://") || git_url.starts_with("http://")
+}
+
+/// The host of `git_url` as it's named in `git_tokens`, e.g. `github.com`, with the port if
+/// the url has one.
+pub fn host(git_url: &str) -> Option<String> {
+    let url = Url::parse(&clone_url(git_url)).ok()?;
+    let host = url.host_str()?;
+    Some(match url.port() {
+        Some(port) => format!("{host}:{port}"),
+        None => host.to_string(),
+    })
+}
+
+/// Whether the user chose how git runs ssh, in the environment or git config.
+fn ssh_command_configured() -> bool {
+    if std::env::var_os("GIT_SSH_COMMAND").is_some() || std::env::var_os("GIT_SSH").is_some() {
+        return true;
+    }
+    Command::new("git")
+        .args(["config", "--get", "core.sshCommand"])
+        .output()
+        .is_ok_and(|output| output.status.success())
+}
+
+/// A `git` command to clone `git_url` with. It never prompts: https dependencies are cloned
+/// with the token in `git_tokens` for their host if there is one, and ssh dependencies with
+/// the keys in the user's ssh agent and config, failing instead of asking for a passphrase
+/// or to trust a host key.
+pub fn command(git_url: &str) -> Command {
+    let mut command = Command::new("git");
+    command.env("GIT_TERMINAL_PROMPT", "0");
+    if is_ssh(git_url) && !ssh_command_configured() {
+        command.env("GIT_SSH_COMMAND", "ssh -o BatchMode=yes");
+    }
+    if is_http(git_url)
+        && let Some(token) = host(git_url).and_then(|host| config::git_token(&host))
+    {
+        let (username, password) = token
+            .split_once(':')
+            .unwrap_or((TOKEN_USERNAME, token.as_str()));
+        // added after any config already passed in the environment, an empty helper first
+        // so helpers configured elsewhere aren't asked
+        let count = std::env::var("GIT_CONFIG_COUNT")
+            .ok()
+            .and_then(|count| count.parse::<usize>().ok())
+            .unwrap_or(0);
+        command
+            .env("GIT_CONFIG_COUNT", (count + 2).to_string())
+            .env(format!("GIT_CONFIG_KEY_{count}"), "credential.helper")
+            .env(format!("GIT_CONFIG_VALUE_{count}"), "")
+            .env(format!("GIT_CONFIG_KEY_{}", count + 1), "credential.helper")
+            .env(format!("GIT_CONFIG_VALUE_{}", count + 1), CREDENTIAL_HELPER)
+            .env("NRPM_GIT_USERNAME", username)
+            .env("NRPM_GIT_PASSWORD", password);
+    }
+    command
+}
+
+/// Lines git prints when the host of a dependency wants credentials it doesn't have.
+const CREDENTIAL_ERRORS: &[&str] = &[
+    "could not read username",
+    "could not read password",
+    "terminal prompts disabled",
+    "authentication failed",
+    "access denied",
+    "permission denied (publickey",
+    "host key verification failed",
+];
+
+/// A failure naming the host that needs credentials, if `output` of a git clone of `git_url`
+/// shows it failed for lack of them.
+pub fn credentials_failure(git_url: &str, output: &str) -> Option<Failure> {
+    let output = output.to_lowercase();
+    if !CREDENTIAL_ERRORS.iter().any(|error| output.contains(error)) {
+        return None;
+    }
+    let host = host(git_url)?;
+    let advice = if is_ssh(git_url) {
+        format!(
+            "Add a key {host} accepts to your ssh agent with ssh-add, and make sure {host} is in ~/.ssh/known_hosts, e.g. by connecting with ssh once."
+        )
+    } else if config::git_token(&host).is_some() {
+        format!(
+            "{host} refused the token in git_tokens.{host}, set one that can read the repository with nrpm config set git_tokens.{host} <token>."
+        )
+    } else {
+        format!(
+            "Set a token that can read the repository with nrpm config set git_tokens.{host} <token>."
+        )
+    };
+    Some(
+        Failure::new(
+            FailureCode::GitCredentials,
+            format!("{host} needs credentials to clone {git_url}"),
+        )
+        .with_advice(advice),
+    )
+}
@@ -0,0 +1,160 @@
+use std::collections::BTreeSet;
+use std::collections::HashMap;
+use std::path::PathBuf;
+use std::sync::LazyLock;
+use std::sync::Mutex;
+
+use anyhow::Result;
+use onyx_api::prelude::*;
+use serde::Deserialize;
+use serde::Serialize;
+
+use crate::config;
+use crate::output::say;
+
+/// An index file saved with the etag it was served with, so later lookups only need a
+/// conditional request.
+#[derive(Serialize, Deserialize)]
+struct CachedIndex {
+    etag: String,
+    package: IndexPackage,
+}
+
+fn cache_file(registry_url: &str, package_name: &str) -> Result<PathBuf> {
+    let registry_dir = registry_url
+        .chars()
+        .map(|c| if c.is_ascii_alphanumeric() { c } else { '_' })
+        .collect::<String>();
+    Ok(config::cache_dir()?
+        .join("index")
+        .join(registry_dir)
+        .join(format!("{}.json", index_path(package_name))))
+}
+
+/// Most packages asked for in one batch request, the most a registry answers for at once.
+const BATCH_SIZE: usize = 256;
+
+/// Index entries loaded by `prefetch`, keyed by the api url of their registry and the
+/// package name. `None` if the registry doesn't have the package.
+type Prefetched = HashMap<(String, String), Option<IndexPackage>>;
+
+static PREFETCHED: LazyLock<Mutex<Prefetched>> = LazyLock::new(Default::default);
+
+fn prefetched(api: &OnyxApi, package_name: &str) -> Option<Option<IndexPackage>> {
+    PREFETCHED
+        .lock()
+        .unwrap_or_else(|e| e.into_inner())
+        .get(&(api.url.clone(), package_name.to_string()))
+        .cloned()
+}
+
+/// Load the index entries of `package_names` from one registry in as few requests as
+/// possible, so `load` doesn't need a request for each of them. Registries that can't answer
+/// batches are left for `load` to ask one package at a time.
+pub async fn prefetch(api: &OnyxApi, package_names: impl IntoIterator<Item = String>) {
+    let package_names = package_names
+        .into_iter()
+        .filter(|package_name| prefetched(api, package_name).is_none())
+        .collect::<BTreeSet<_>>()
+        .into_iter()
+        .collect::<Vec<_>>();
+    for chunk in package_names.chunks(BATCH_SIZE) {
+        let request = BatchPackagesRequest {
+            packages: chunk
+                .iter()
+                .map(|name| BatchPackageQuery {
+                    name: name.clone(),
+                    requirement: None,
+                })
+                .collect(),
+        };
+        let response = match api.batch_packages(&request).await {
+            Ok(response) => response,
+            Err(e) => {
+                log::debug!("unable to batch index lookups in {}: {e:?}", api.url);
+                return;
+            }
+        };
+        let mut prefetched = PREFETCHED.lock().unwrap_or_else(|e| e.into_inner());
+        for package in response.packages {
+            prefetched.insert((api.url.clone(), package.name), package.package);
+        }
+    }
+}
+
+/// Load the versions of a package from the registry index.
+pub async fn load(api: &OnyxApi, package_name: &str) -> Result<IndexPackage> {
+    if let Some(package) = prefetched(api, package_name) {
+        return package.ok_or(anyhow::anyhow!(
+            "package \"{package_name}\" isn't in the registry index"
+        ));
+    }
+    let path = cache_file(&api.url, package_name)?;
+    let cached = std::fs::read_to_string(&path)
+        .ok()
+        .and_then(|str| serde_json::from_str::<CachedIndex>(&str).ok());
+    match api
+        .index_package(package_name, cached.as_ref().map(|c| c.etag.as_str()))
+        .await?
+    {
+        IndexFetch::NotModified => cached.map(|c| c.package).ok_or(anyhow::anyhow!(
+            "registry reported an unchanged index for \"{package_name}\" that isn't cached"
+        )),
+        IndexFetch::Fetched { package, etag } => {
+            if let Some(etag) = etag {
+                let cached = CachedIndex {
+                    etag,
+                    package: package.clone(),
+                };
+                let saved = path
+                    .parent()
+                    .map(std::fs::create_dir_all)
+                    .transpose()
+                    .map_err(anyhow::Error::from)
+                    .and_then(|_| Ok(std::fs::write(&path, serde_json::to_vec(&cached)?)?));
+                if let Err(e) = saved {
+                    log::warn!("failed to cache index for {package_name}: {e:?}");
+                }
+            }
+            Ok(package)
+        }
+    }
+}
+
+/// Resolve the most recently published version of a package that isn't yanked. Registries
+/// without an index are asked through the package api instead.
+pub async fn latest_version(api: &OnyxApi, package_name: &str) -> Result<(String, IndexVersion)> {
+    match load(api, package_name).await {
+        Ok(package) => {
+            if let Some(deprecated) = &package.deprecated {
+                say!("⚠️  \"{}\" is deprecated: {deprecated}", package.name);
+            }
+            let version = package
+                .versions
+                .iter()
+                .rfind(|version| !version.yanked)
+                .cloned()
+                .ok_or(anyhow::anyhow!(
+                    "package \"{package_name}\" has no versions that aren't yanked"
+                ))?;
+            Ok((package.name, version))
+        }
+        Err(e) => {
+            log::debug!("index lookup failed, falling back to package api: {e:?}");
+            let (package, version) = api.load_package_latest_version(package_name).await?;
+            if let Some(deprecated) = &package.metadata.deprecated {
+                say!("⚠️  \"{}\" is deprecated: {deprecated}", package.name);
+            }
+            Ok((
+                package.name,
+                IndexVersion {
+                    name: version.name,
+                    id: version.id,
+                    published_at: version.created_at,
+                    yanked: false,
+                    static_content: version.static_content,
+                },
+            ))
+        }
+    }
+}
@@ -1,15 +1,252 @@
 use std::collections::HashMap;
+use std::io::Read;
 use std::path::Path;
 use std::path::PathBuf;
-use std::time::Duration;
+use std::process::Stdio;
 
 use anyhow::Context;
 use anyhow::Result;
+use indicatif::HumanBytes;
+use indicatif::MultiProgress;
 use indicatif::ProgressBar;
 use indicatif::ProgressStyle;
 use nargo_parse::*;
+use nrpm_resolver::Fetched;
+use nrpm_resolver::Resolved;
+use nrpm_resolver::Source;
+use onyx_api::prelude::*;
 
+use crate::cache;
+use crate::failure::Failure;
+use crate::failure::FailureCode;
+use crate::git;
+use crate::index;
+use crate::lockfile;
+use crate::lockfile::LockEntry;
 use crate::lockfile::Lockfile;
+use crate::output;
+use crate::output::summary;
+use crate::policy;
+use crate::policy::Policy;
+use crate::registry;
+use crate::report;
+use crate::report::Fetch;
+use crate::report::Resolution;
+use crate::snapshot;
+use crate::stats;
+
+/// Options for `install`.
+#[derive(Default)]
+pub struct InstallOptions {
+    /// Write a report of everything that was resolved here, see `report::write`.
+    pub report_path: Option<PathBuf>,
+    /// Ask what to do about lockfile mismatches and available updates instead of failing or
+    /// ignoring them.
+    pub interactive: bool,
+    /// Quarantine cache entries that don't match a lockfile and download them again, instead
+    /// of failing.
+    pub repair: bool,
+    /// Leave out the dev-dependencies of the package being installed.
+    pub no_dev: bool,
+    /// Print violations of `nrpm-policy.toml` instead of failing on them.
+    pub policy_report: bool,
+    /// Trust new git dependencies from outside the registries without asking, see
+    /// `trust_on_first_use`.
+    pub yes: bool,
+}
+
+/// What to do with a package that has a lockfile mismatch or an available update.
+enum Choice {
+    Keep,
+    Update,
+}
+
+/// Ask the user to keep a package as it is or, if `update` is offered, update it. Choosing to
+/// abort is returned as an error.
+fn choose(
+    progress: &ProgressBar,
+    prompt: String,
+    keep: String,
+    update: Option<String>,
+) -> Result<Choice> {
+    let offers_update = update.is_some();
+    let mut items = vec![keep];
+    items.extend(update);
+    items.push("Abort".to_string());
+    let selection = progress.suspend(|| {
+        dialoguer::Select::new()
+            .with_prompt(prompt)
+            .items(&items)
+            .default(0)
+            .interact()
+    })?;
+    match selection {
+        0 => Ok(Choice::Keep),
+        1 if offers_update => Ok(Choice::Update),
+        _ => anyhow::bail!("Install aborted"),
+    }
+}
+
+/// Trust the git dependencies in `untrusted`, which are locked for the first time and come
+/// from outside the registries, so nothing but the user vouches for their content. Their
+/// content hashes are printed, and the user confirms them unless `options.yes`. Without a
+/// terminal to ask on the install fails.
+fn trust_on_first_use(
+    untrusted: &[(Dependency, String)],
+    options: &InstallOptions,
+    progress: &ProgressBar,
+) -> Result<()> {
+    if untrusted.is_empty() {
+        return Ok(());
+    }
+    let listed = untrusted
+        .iter()
+        .map(|(dep, hash)| Ok(format!("{} blake3 {hash}", dep.identifier()?)))
+        .collect::<Result<Vec<_>>>()?;
+    if options.yes {
+        for line in &listed {
+            output::note(progress, format!("🔐 trusted on first use: {line}"));
+        }
+        return Ok(());
+    }
+    if !options.interactive {
+        return Err(Failure::new(
+            FailureCode::UntrustedDependency,
+            format!(
+                "new git dependencies from outside the registries need to be trusted:\n{}",
+                listed.join("\n")
+            ),
+        )
+        .with_advice("Check the content is what you expect, then install with --yes to trust it.")
+        .into());
+    }
+    let confirmed = progress.suspend(|| {
+        for line in &listed {
+            summary!("🔐 {line}");
+        }
+        dialoguer::Confirm::new()
+            .with_prompt(format!(
+                "No registry vouches for {}, trust {} content and lock it?",
+                if untrusted.len() == 1 {
+                    "this dependency"
+                } else {
+                    "these dependencies"
+                },
+                if untrusted.len() == 1 { "its" } else { "their" }
+            ))
+            .default(false)
+            .interact()
+    })?;
+    if !confirmed {
+        anyhow::bail!("Install aborted");
+    }
+    Ok(())
+}
+
+/// The error for a git dependency from outside the registries that hashes to `found` when
+/// downloaded again, instead of the `expected` hash it was locked with. The tag was moved to
+/// other content upstream, which nothing but the lockfile guards against.
+pub fn tag_moved(dep: &Dependency, dep_path: &Path, expected: &str, found: &str) -> anyhow::Error {
+    anyhow::Error::new(
+        Failure::new(FailureCode::TagMoved, format!("downloaded hash: {found}"))
+            .with_advice(format!(
+                "Someone with access to {} may have replaced the code at {}. Review the changes before trusting them, then remove its entry from nrpm.lock and install again.",
+                dep.git.as_deref().unwrap_or_default(),
+                dep.tag.as_deref().unwrap_or_default()
+            ))
+            .with_path(dep_path),
+    )
+    .context(format!("expected hash: {expected}"))
+    .context(format!(
+        "⚠️  the tag {} of {} now points at different content than nrpm.lock trusted, it was moved upstream",
+        dep.tag.as_deref().unwrap_or_default(),
+        dep.git.as_deref().unwrap_or_default()
+    ))
+}
+
+/// Load the index entries of the registry packages an install of the package at `path` looks
+/// up in one request per registry: its registry dependencies and the packages in nrpm.lock,
+/// which include the dependencies of its dependencies.
+/// Anything malformed is skipped here and fails once it's resolved.
+async fn prefetch_index(path: &Path, root_pkg: &NargoConfig, include_dev: bool) {
+    let mut packages = vec![];
+    let dev_deps = match include_dev {
+        true => root_pkg.dev_dependencies().ok(),
+        false => None,
+    };
+    let deps = root_pkg.dependencies().ok().into_iter().chain(dev_deps);
+    for dep in deps.flat_map(|deps| deps.values()) {
+        if let Ok(Some(package)) = registry::of(dep) {
+            packages.push(package);
+        }
+    }
+    if let Ok(lockfile) = Lockfile::load_or_init(&path.join("nrpm.lock")) {
+        for entry in lockfile.entries() {
+            if let Ok(Some(package)) = registry::of_git(&entry.git, entry.registry.as_deref()) {
+                packages.push(package);
+            }
+        }
+    }
+    registry::prefetch(packages).await;
+}
+
+/// Offer to update direct registry dependencies of the package at `path` that have newer
+/// published versions. Chosen updates are written to Nargo.toml.
+async fn offer_updates(path: &Path, root_pkg: &NargoConfig, progress: &ProgressBar) -> Result<()> {
+    progress.set_message("checking for updates");
+    let mut updates = vec![];
+    for (name, dep) in root_pkg.dependencies()? {
+        let Some(tag) = dep.tag.as_ref() else {
+            continue;
+        };
+        let Some((registry, package_name)) = registry::of(dep)? else {
+            continue;
+        };
+        let package = match index::load(&registry.api(), &package_name).await {
+            Ok(package) => package,
+            Err(e) => {
+                log::debug!("unable to check for updates to {package_name}: {e:?}");
+                continue;
+            }
+        };
+        // versions are listed in publish order
+        let Some(current) = package.position(tag) else {
+            continue;
+        };
+        // yanked versions are never suggested
+        let Some(latest) = package.versions.iter().rposition(|v| !v.yanked) else {
+            continue;
+        };
+        if latest <= current {
+            continue;
+        }
+        let latest = &package.versions[latest];
+        let choice = choose(
+            progress,
+            format!("\"{name}\" version \"{}\" is available", latest.name),
+            format!("Keep \"{tag}\""),
+            Some(format!("Update to \"{}\"", latest.name)),
+        )?;
+        if let Choice::Update = choice {
+            let mut update = dep.clone();
+            // keep writing the tag the way it's written
+            update.tag = match package.versions[current].name == *tag {
+                true => Some(latest.name.clone()),
+                false => Some(format!("v{}", latest.name)),
+            };
+            updates.push(update);
+        }
+    }
+    if !updates.is_empty() {
+        let nargo_path = path.join("Nargo.toml");
+        let source = std::fs::read_to_string(&nargo_path)?;
+        std::fs::write(
+            &nargo_path,
+            NargoConfig::replace_dependencies(&source, &updates)?,
+        )?;
+    }
+    Ok(())
+}
 
 /// A command to read a Nargo.toml file and retrieve all direct and indirect dependencies.
 ///
@@ -18,53 +255,107 @@ use crate::lockfile::Lockfile;
 /// 1. Git URL. This requires cloning the repository at a specific tag.
 /// 2. Package name. This will load the package from the nrpm registry.
 /// 3. Local path. Read the contents of a directory on the local machine.
-pub async fn install(path: PathBuf) -> Result<()> {
+///
+/// See `InstallOptions` for `options`.
+pub async fn install(path: PathBuf, options: &InstallOptions) -> Result<()> {
     // try to load the Nargo.toml in the target directory here
     // bail with a helpful error message if it's not there
-    let root_pkg = NargoConfig::load(&path)
+    let mut root_pkg = NargoConfig::load(&path)
         .with_context(|| "Unable to find a Nargo.toml in the target directory")?;
 
-    let progress = indicatif::ProgressBar::new_spinner();
-    let multiprogress = indicatif::MultiProgress::new();
-    let progress = multiprogress.add(progress);
-    progress.enable_steady_tick(Duration::from_millis(50));
+    let multiprogress = output::multiprogress();
+    let progress = output::spinner(&multiprogress);
     progress.set_message("Initializing...");
+    let dep_cache_path = cache::cache_path()?;
+    let _cache_lock = cache::lock_cache(&dep_cache_path, &progress)?;
 
-    multiprogress.insert_before(
+    progress.set_message("loading the registry index");
+    prefetch_index(&path, &root_pkg, !options.no_dev).await;
+
+    if options.interactive {
+        offer_updates(&path, &root_pkg, &progress).await?;
+        root_pkg = NargoConfig::load(&path)?;
+    }
+
+    output::step(
+        &multiprogress,
         &progress,
-        indicatif::ProgressBar::new(0)
-            .with_prefix("🎄 Building dep tree...")
-            .with_style(ProgressStyle::with_template("{prefix}")?)
-            .with_finish(indicatif::ProgressFinish::Abandon),
+        "🎄 Building dep tree...".to_string(),
     );
-
-    multiprogress.insert_before(
+    output::step(
+        &multiprogress,
         &progress,
-        indicatif::ProgressBar::new(0)
-            .with_prefix("🌨️  Downloading dependencies...")
-            .with_style(ProgressStyle::with_template("{prefix}")?)
-            .with_finish(indicatif::ProgressFinish::Abandon),
+        "🌨️  Downloading dependencies...".to_string(),
     );
 
-    let all_dependencies = download_dependencies(&root_pkg, &path, &progress)?;
+    let mut resolution = Resolution::default();
+    let resolved = download_dependencies(
+        &root_pkg,
+        &path,
+        !options.no_dev,
+        &multiprogress,
+        &progress,
+        &mut resolution,
+    )
+    .await?;
+    let conflicts = resolved.conflicts();
+    let Resolved {
+        packages: all_dependencies,
+        graph,
+    } = resolved;
+
+    check_libraries(&all_dependencies)?;
+
+    if let Some((policy_path, policy)) = Policy::find(&path)? {
+        progress.set_message("checking policy");
+        let violations = policy.check(&all_dependencies, &graph, &super::registry_url());
+        if options.policy_report {
+            policy::report(&multiprogress, &progress, &policy_path, &violations);
+        } else {
+            policy::enforce(&policy_path, &violations)?;
+        }
+    }
 
-    multiprogress.insert_before(
+    output::step(
+        &multiprogress,
         &progress,
-        indicatif::ProgressBar::new(0)
-            .with_prefix("✨ Checking integrity...")
-            .with_style(ProgressStyle::with_template("{prefix}")?)
-            .with_finish(indicatif::ProgressFinish::Abandon),
+        "✨ Checking integrity...".to_string(),
     );
 
     progress.set_message("computing hashes");
     let lockfile_path = path.join("nrpm.lock");
     let mut hashes = HashMap::<String, String>::default();
     for (dep_path, dep, _config) in all_dependencies.values() {
-        hashes.insert(
-            dep.identifier()?,
-            nrpm_tarball::hash_dir(dep_path)?.to_string(),
-        );
+        let mut hashed = 0;
+        let hash = nrpm_tarball::hash_dir_with_progress(dep_path, |bytes| {
+            hashed += bytes;
+            progress.set_message(format!("hashing {}: {}", dep.name, HumanBytes(hashed)));
+        })?;
+        hashes.insert(dep.identifier()?, hash.to_string());
     }
+    // lockfiles identify registry packages by name and version, see `lockfile::package_id`.
+    // This is the identifier of each in the tree, wherever it was installed from.
+    let mut sources = HashMap::<String, String>::default();
+    for (identifier, (_, dep, _)) in &all_dependencies {
+        let package_id = lockfile::package_id(dep)?;
+        if let Some(other) = sources.insert(package_id.clone(), identifier.clone()) {
+            return Err(Failure::new(
+                FailureCode::AmbiguousPackage,
+                format!("{package_id} is installed from both {other} and {identifier}"),
+            )
+            .with_advice(format!(
+                "Depend on \"{}\" from one registry, e.g. with a [patch] entry.",
+                dep.name
+            ))
+            .into());
+        }
+    }
+    let source_of = |entry: &LockEntry| {
+        sources
+            .get(&entry.package_id())
+            .cloned()
+            .unwrap_or(entry.identifier())
+    };
 
     progress.set_message("checking dependent lockfiles");
     let mut validated_lockfile_count = 0u64;
@@ -81,12 +372,23 @@ pub async fn install(path: PathBuf) -> Result<()> {
         }
 
         for entry in lockfile.entries() {
-            let entry_identifier = entry.identifier();
-            let hash = hashes.get(&entry_identifier).ok_or(anyhow::anyhow!(
-                "unknown lockfile identifier {}",
-                entry_identifier
-            ))?;
-            if hash != &entry.blake3 {
+            let entry_identifier = source_of(entry);
+            if !hashes.contains_key(&entry_identifier)
+                && (graph.is_patched(&entry_identifier)
+                    || config.dev_dependencies().is_ok_and(|d| !d.is_empty()))
+            {
+                // replaced by a patch, or locked for the dev-dependencies of the package which
+                // aren't installed, so there's nothing to check it against
+                continue;
+            }
+            let hash = hashes
+                .get(&entry_identifier)
+                .cloned()
+                .ok_or(anyhow::anyhow!(
+                    "unknown lockfile identifier {}",
+                    entry_identifier
+                ))?;
+            if hash != entry.blake3 {
                 // the dependency of the dependency we're checking
                 let (inner_dep_path, inner_dep, _config) = all_dependencies
                     .get(&entry_identifier)
@@ -94,8 +396,27 @@ pub async fn install(path: PathBuf) -> Result<()> {
                         "dependency was not enumerated {}",
                         entry.git
                     ))?;
-                Err(anyhow::anyhow!("ADVICE Consider deleting local copies and re-downloading. If this error persists contact the authors of \"{}\" and \"{}\".", dep.name, inner_dep.name)
-                    .context("integrity check failed, halting")
+                if options.repair && !inner_dep.is_local() {
+                    let hash = refetch(
+                        &dep_cache_path,
+                        inner_dep_path,
+                        inner_dep,
+                        &entry.blake3,
+                        &hash,
+                        &progress,
+                    )?;
+                    hashes.insert(entry_identifier, hash);
+                    continue;
+                }
+                Err(anyhow::Error::new(
+                    Failure::new(
+                        FailureCode::DependentLockfileMismatch,
+                        "integrity check failed, halting",
+                    )
+                    .with_advice(format!("Run install with --repair to download them again. If this error persists contact the authors of \"{}\" and \"{}\".", dep.name, inner_dep.name))
+                    .with_path(dep_path)
+                    .with_path(inner_dep_path),
+                )
                     .context(format!("\"{}\" exists at path: {dep_path:?}", dep.name))
                     .context(format!(
                         "\"{}\" exists at path: {inner_dep_path:?}",
@@ -121,32 +442,81 @@ pub async fn install(path: PathBuf) -> Result<()> {
     let mut lockfile = Lockfile::load_or_init(&lockfile_path)?;
     validated_lockfile_count += 1;
     // first remove any dependencies that no longer exist in the tree
-    // or that are local path references
+    // or that are local path references. Patched dependencies are kept so the lockfile still
+    // describes the tree without patches, and nothing is removed without dev-dependencies as
+    // the lockfile describes the tree with them.
     for entry in lockfile.entries().cloned().collect::<Vec<_>>() {
-        let entry_identifier = entry.identifier();
+        let entry_identifier = source_of(&entry);
         if let Some((_, dep, _)) = all_dependencies.get(&entry_identifier) {
             if dep.is_local() {
-                lockfile.remove(&entry_identifier);
+                lockfile.remove(&entry.package_id());
             }
-        } else {
-            lockfile.remove(&entry_identifier);
+        } else if !graph.is_patched(&entry_identifier) && !options.no_dev {
+            lockfile.remove(&entry.package_id());
         }
     }
-    // then add and verify all dependencies
+    // then add and verify all dependencies, except patches which are expected to change
+    let mut unattested = vec![];
+    let mut untrusted = vec![];
     for (dep_path, dep, _config) in all_dependencies.values() {
-        if dep.is_local() {
+        if dep.is_local() || graph.is_patch(&dep.identifier()?) {
             continue;
         }
-        if let Some(entry) = lockfile.entry(&dep.identifier()?) {
-            let entry_identifier = entry.identifier();
+        if let Some(entry) = lockfile.entry(&lockfile::package_id(dep)?) {
+            let entry_identifier = dep.identifier()?;
             // check that our existing hash matches
-            let hash = hashes.get(&entry_identifier).ok_or(anyhow::anyhow!(
-                "unknown lockfile identifier {}",
-                entry_identifier
-            ))?;
-            if hash != &entry.blake3 {
-                Err(anyhow::anyhow!("ADVICE Consider deleting local copy and re-downloading. If this error persists contact the author of \"{}\".", dep.name)
-                    .context("integrity check failed, halting")
+            let hash = hashes
+                .get(&entry_identifier)
+                .cloned()
+                .ok_or(anyhow::anyhow!(
+                    "unknown lockfile identifier {}",
+                    entry.package_id()
+                ))?;
+            if hash != entry.blake3 && options.repair {
+                let hash = refetch(
+                    &dep_cache_path,
+                    dep_path,
+                    dep,
+                    &entry.blake3,
+                    &hash,
+                    &progress,
+                )?;
+                hashes.insert(entry_identifier, hash);
+            } else if hash != entry.blake3 && options.interactive {
+                // a registry package that doesn't match is a modified or corrupted copy of
+                // the published version, it's never locked
+                let update = match registry::of(dep)? {
+                    Some(_) => None,
+                    None => Some("Update nrpm.lock to the local copy".to_string()),
+                };
+                let choice = choose(
+                    &progress,
+                    format!("Local copy of \"{}\" does not match nrpm.lock", dep.name),
+                    "Keep the locked version, download it again".to_string(),
+                    update,
+                )?;
+                match choice {
+                    Choice::Keep => {
+                        let hash = refetch(
+                            &dep_cache_path,
+                            dep_path,
+                            dep,
+                            &entry.blake3,
+                            &hash,
+                            &progress,
+                        )?;
+                        hashes.insert(entry_identifier, hash);
+                    }
+                    Choice::Update => {
+                        lockfile.upsert(dep.clone(), dep_path, attested(dep).await?)?
+                    }
+                }
+            } else if hash != entry.blake3 {
+                Err(anyhow::Error::new(
+                    Failure::new(FailureCode::LockfileMismatch, "integrity check failed, halting")
+                        .with_advice(format!("Run install with --repair to download it again. If this error persists contact the author of \"{}\".", dep.name))
+                        .with_path(dep_path),
+                )
                     .context(format!("computed hash: {}", hash))
                     .context(format!("expected hash: {}", entry.blake3))
                     .context(format!("dependent location: {:?}", dep_path))
@@ -155,137 +525,594 @@ pub async fn install(path: PathBuf) -> Result<()> {
                         dep.name
                     )))?;
             }
+            if entry.registry != dep.registry || Some(&entry.git) != dep.git.as_ref() {
+                // installed from another registry or mirror serving the same package, or
+                // pinned to another registry serving the same url, record where
+                lockfile.upsert(dep.clone(), dep_path, attested(dep).await?)?;
+            }
         } else {
             // add an entry
-            lockfile.upsert(dep.clone(), dep_path)?;
+            let attested = attested(dep).await?;
+            let in_registry = registry::of(dep)?.is_some();
+            if !attested && in_registry {
+                unattested.push(format!(
+                    "⚠️  \"{}\" {} has no static content attestation\n",
+                    dep.name,
+                    dep.tag.as_deref().unwrap_or_default()
+                ));
+            }
+            lockfile.upsert(dep.clone(), dep_path, attested)?;
+            if !in_registry {
+                let hash = hashes.get(&dep.identifier()?).cloned().unwrap_or_default();
+                untrusted.push((dep.clone(), hash));
+            }
         }
     }
+    trust_on_first_use(&untrusted, options, &progress)?;
+    for (dep, _) in &untrusted {
+        lockfile.trust(&lockfile::package_id(dep)?);
+    }
+    if let Some(snapshot) = snapshot::loaded() {
+        lockfile.snapshot = Some(snapshot.id);
+    }
     lockfile.save(&lockfile_path)?;
+    let used = all_dependencies
+        .values()
+        .filter_map(|(_, dep, _)| dep.folder_path(&dep_cache_path).ok())
+        .collect::<Vec<_>>();
+    if let Err(e) = cache::record_use(
+        &dep_cache_path,
+        used.iter().map(PathBuf::as_path),
+        &lockfile_path,
+    ) {
+        log::warn!("failed to record cache usage: {e:?}");
+    }
+    cache::link_into_nargo(&dep_cache_path, used.iter().map(PathBuf::as_path))?;
+    if let Some(report_path) = &options.report_path {
+        progress.set_message("writing report");
+        report::write(
+            report_path,
+            &path,
+            &root_pkg,
+            &all_dependencies,
+            &hashes,
+            &graph,
+            &resolution,
+        )?;
+    }
+    stats::report(&super::registry_api(), &all_dependencies, &resolution).await;
+    let mut patched = all_dependencies
+        .iter()
+        .filter(|(identifier, _)| graph.is_patch(identifier))
+        .map(|(identifier, (_, dep, _))| format!("🩹 \"{}\" patched to {identifier}\n", dep.name))
+        .collect::<Vec<_>>();
+    patched.sort();
+    // nargo builds each version separately, but types from one can't be used with another
+    let conflicted = conflicts
+        .iter()
+        .map(|c| {
+            format!(
+                "⚠️  {} is resolved at versions {}\n",
+                c.git,
+                c.tags.join(", ")
+            )
+        })
+        .collect::<Vec<_>>();
     // all our dependencies, plus the root package
     let total_packages = all_dependencies.len() + 1;
-    multiprogress.insert_before(
+    output::finish(
+        &multiprogress,
         &progress,
-        indicatif::ProgressBar::new(0)
-            .with_prefix(format!(
-                "👻 {} package{}, {} validated\n✅ wrote {}",
-                total_packages,
-                if total_packages == 1 { "" } else { "s" },
-                validated_lockfile_count,
-                pathdiff::diff_paths(&lockfile_path, std::env::current_dir()?)
-                    .unwrap_or(lockfile_path)
-                    .display()
-            ))
-            .with_style(ProgressStyle::with_template("{prefix}")?)
-            .with_finish(indicatif::ProgressFinish::Abandon),
+        format!(
+            "{}{}{}👻 {} package{}, {} validated\n✅ wrote {}",
+            unattested.concat(),
+            conflicted.concat(),
+            patched.concat(),
+            total_packages,
+            if total_packages == 1 { "" } else { "s" },
+            validated_lockfile_count,
+            pathdiff::diff_paths(&lockfile_path, std::env::current_dir()?)
+                .unwrap_or(lockfile_path)
+                .display()
+        ),
     );
     progress.finish_and_clear();
     Ok(())
 }
 
-// Given an entry Nargo.toml resolve all dependencies to locations on disk.
-fn download_dependencies(
-    root_pkg: &NargoConfig,
-    path: &Path,
-    progress: &ProgressBar,
-) -> Result<HashMap<String, (PathBuf, Dependency, NargoConfig)>> {
-    let dep_cache_path = super::cache_path()?;
-
-    // all direct and indirect dependencies for root_pkg
-    // identifier keyed to package path (not module path), dependency structure, and Nargo config
-    let mut all_dependencies = HashMap::<String, (PathBuf, Dependency, NargoConfig)>::default();
-
-    let mut pending_resolution = vec![(path.to_path_buf(), root_pkg.clone())];
-    while let Some((pkg_path, config)) = pending_resolution.pop() {
-        progress.set_message(format!("{}: resolving", config.package.name));
-        // check that our configuration is sane/valid
-        config.validate_dependencies()?;
-        // for each direct dependency let's load if needed.
-        for (_name, dep) in config.dependencies()? {
-            let identifier = dep.identifier()?;
-            if all_dependencies.contains_key(&identifier) {
-                // we've already loaded this dep and validated it, skip
-                continue;
-            }
+/// Fetches resolved packages into the system cache, or reads them from local paths.
+struct CacheSource<'a> {
+    dep_cache_path: PathBuf,
+    multiprogress: &'a MultiProgress,
+    progress: &'a ProgressBar,
+    resolution: &'a mut Resolution,
+}
 
-            // dependency is a local path, nothing to load
-            if let Some(dep_path_str) = &dep.path {
-                let dep_path = PathBuf::from(dep_path_str);
-                let dep_pkg_path = if dep_path.is_absolute() {
-                    dep_path
-                } else {
-                    pkg_path.join(&dep_path)
-                };
-                let dep_module_path = dep.module_path(&dep_pkg_path)?;
-                let dep_config = NargoConfig::load(&dep_module_path)
-                    .context(format!("located at path: {:?}", dep_module_path))
-                    .context(format!(
-                        "failed to load Nargo.toml for dependency \"{}\"",
-                        dep.name
-                    ))?;
-                all_dependencies.insert(
-                    identifier.clone(),
-                    (dep_pkg_path, dep.clone(), dep_config.clone()),
-                );
-                pending_resolution.push((dep_module_path, dep_config));
-                continue;
-            }
-            let dep_root_path = dep.folder_path(&dep_cache_path)?;
-            if std::fs::exists(&dep_root_path)? {
-                // dependency is already in the system cache
-                progress.set_message(format!("{}: exists in cache", dep.name));
-                let module_path = dep.module_path(&dep_root_path)?;
-                let config = NargoConfig::load(&module_path)
-                    .context(format!("located at: {:?}", module_path))
-                    .context(format!(
-                        "failed to load Nargo.toml for dependency \"{}\"",
-                        dep.name
-                    ))?;
-                all_dependencies.insert(
-                    identifier.clone(),
-                    (dep_root_path.clone(), dep.clone(), config.clone()),
-                );
-                pending_resolution.push((module_path, config));
-                continue;
-            }
-            progress.set_message(format!("{}: git clone", dep.name));
-            // otherwise we need to load the dependence
-            let tag = dep.tag.as_ref().expect("tag should be Some at this point");
-            let git_url = dep.git.as_ref().expect("git should be Some at this point");
-
-            // download atomically
-            // clone into a tmpdir then move it into place
-            let workdir = tempfile::tempdir()?.keep();
-            std::process::Command::new("git")
-                .arg("-c")
-                .arg("advice.detachedHead=false")
-                .arg("clone")
-                .arg("--depth")
-                .arg("1")
-                .arg("--branch")
-                .arg(tag)
-                .arg(git_url)
-                .arg(
-                    workdir
-                        .to_str()
-                        .expect("tempdir has non-unicode characters"),
-                )
-                .output()?;
-            std::fs::create_dir_all(&dep_root_path)?;
-            std::fs::rename(workdir, &dep_root_path)?;
+impl Source for CacheSource<'_> {
+    fn check(&mut self, dep: &Dependency, dir: &Path) -> Result<()> {
+        dep.valid_or_err(dir)?;
+        if dep.registry.is_some() {
+            registry::of(dep)?;
+        }
+        Ok(())
+    }
+
+    async fn fetch(&mut self, dep: &Dependency, dir: &Path) -> Result<Fetched> {
+        let identifier = dep.identifier()?;
+        let progress = self.progress;
+        // dependency is a local path, nothing to load
+        if let Some(dep_path_str) = &dep.path {
+            let dep_path = PathBuf::from(dep_path_str);
+            let dep_pkg_path = if dep_path.is_absolute() {
+                dep_path
+            } else {
+                dir.join(&dep_path)
+            };
+            let dep_module_path = dep.module_path(&dep_pkg_path)?;
+            let dep_config = NargoConfig::load(&dep_module_path)
+                .context(format!("located at path: {:?}", dep_module_path))
+                .context(format!(
+                    "failed to load Nargo.toml for dependency \"{}\"",
+                    dep.name
+                ))?;
+            self.resolution.fetched(&identifier, Fetch::Local);
+            return Ok(Fetched {
+                path: dep_pkg_path,
+                module_path: dep_module_path,
+                config: dep_config,
+            });
+        }
+        let dep_cache_path = &self.dep_cache_path;
+        let dep_root_path = dep.folder_path(dep_cache_path)?;
+        // held until the dependency is in the cache so concurrent installs don't both
+        // download it
+        let _dep_lock =
+            cache::lock_dependency(dep_cache_path, &dep_root_path, &dep.name, progress)?;
+        if std::fs::exists(&dep_root_path)? {
+            // dependency is already in the system cache
+            progress.set_message(format!("{}: exists in cache", dep.name));
             let module_path = dep.module_path(&dep_root_path)?;
             let config = NargoConfig::load(&module_path)
                 .context(format!("located at: {:?}", module_path))
                 .context(format!(
-                    "Downloaded dependency \"{}\" does not contain a Nargo.toml",
+                    "failed to load Nargo.toml for dependency \"{}\"",
                     dep.name
                 ))?;
-            all_dependencies.insert(
-                identifier.clone(),
-                (dep_root_path, dep.clone(), config.clone()),
-            );
-            pending_resolution.push((module_path, config));
+            self.resolution.fetched(&identifier, Fetch::Cache);
+            return Ok(Fetched {
+                path: dep_root_path,
+                module_path,
+                config,
+            });
+        }
+        // otherwise we need to load the dependency, from an older cached version if the
+        // registry has a delta for it
+        let fetch = match apply_delta(dep, &dep_root_path, progress).await {
+            Ok(true) => Fetch::Delta,
+            Ok(false) => Fetch::Download,
+            Err(e) => {
+                log::debug!("unable to apply a delta to {}: {e:?}", dep.name);
+                Fetch::Download
+            }
+        };
+        if let Fetch::Download = fetch {
+            let bar = self
+                .multiprogress
+                .insert_before(progress, download_bar(dep)?);
+            progress.set_message(format!("{}: downloading", dep.name));
+            output::plain(format!("    {}: downloading", dep.identifier()?));
+            if !download_dependency(dep, &dep_root_path, &bar).await? {
+                progress.set_message(format!("{}: git clone", dep.name));
+                clone_dependency(dep, &dep_root_path, &bar)?;
+                self.resolution.uncounted(&identifier);
+            }
+            bar.finish_and_clear();
+        } else {
+            self.resolution.uncounted(&identifier);
+        }
+        let module_path = dep.module_path(&dep_root_path)?;
+        let config = NargoConfig::load(&module_path)
+            .context(format!("located at: {:?}", module_path))
+            .context(format!(
+                "Downloaded dependency \"{}\" does not contain a Nargo.toml",
+                dep.name
+            ))?;
+        self.resolution.fetched(&identifier, fetch);
+        Ok(Fetched {
+            path: dep_root_path,
+            module_path,
+            config,
+        })
+    }
+}
+
+// Given an entry Nargo.toml resolve all dependencies to locations on disk. With `dev` the
+// dev-dependencies of the entry Nargo.toml are included.
+async fn download_dependencies(
+    root_pkg: &NargoConfig,
+    path: &Path,
+    dev: bool,
+    multiprogress: &MultiProgress,
+    progress: &ProgressBar,
+    resolution: &mut Resolution,
+) -> Result<Resolved> {
+    // patch paths are relative to the package being installed, not the dependent they apply to
+    let mut patches = HashMap::<String, Dependency>::default();
+    for (name, patch) in root_pkg.patches()? {
+        let mut patch = patch.clone();
+        if let Some(patch_path) = &patch.path
+            && PathBuf::from(patch_path).is_relative()
+        {
+            patch.path = Some(path.join(patch_path).to_string_lossy().to_string());
+        }
+        patch
+            .valid_or_err(path)
+            .with_context(|| format!("patch for \"{name}\" is misconfigured"))?;
+        if patch.registry.is_some() {
+            registry::of(&patch)
+                .with_context(|| format!("patch for \"{name}\" is misconfigured"))?;
+        }
+        patches.insert(name.clone(), patch);
+    }
+
+    progress.set_message(format!("{}: resolving", root_pkg.package.name));
+    let mut source = CacheSource {
+        dep_cache_path: cache::cache_path()?,
+        multiprogress,
+        progress,
+        resolution,
+    };
+    nrpm_resolver::resolve(root_pkg, path, dev, &patches, &mut source).await
+}
+
+/// Fail if any dependency isn't a library, nargo would fail to build the package depending on
+/// it with a less helpful error.
+fn check_libraries(
+    all_dependencies: &HashMap<String, (PathBuf, Dependency, NargoConfig)>,
+) -> Result<()> {
+    let mut not_libraries = all_dependencies
+        .values()
+        .filter(|(_, _, config)| !config.package.is_library())
+        .collect::<Vec<_>>();
+    not_libraries.sort_by(|a, b| a.1.name.cmp(&b.1.name));
+    let Some((dep_path, dep, config)) = not_libraries.first() else {
+        return Ok(());
+    };
+    let package_type = config
+        .package
+        .package_type
+        .map(|t| t.to_string())
+        .unwrap_or_default();
+    Err(anyhow::Error::new(
+        Failure::new(
+            FailureCode::NotALibrary,
+            format!(
+                "dependency \"{}\" is a {package_type} package, only lib packages can be dependencies",
+                dep.name
+            ),
+        )
+        .with_advice(format!(
+            "Remove \"{}\" from the dependencies of the packages depending on it, or depend on a library its authors publish instead.",
+            dep.name
+        ))
+        .with_path(dep.module_path(dep_path)?.join("Nargo.toml")),
+    ))
+}
+
+/// The published versions of the registry dependency `dep`, in publish order, and the
+/// position of the version it asks for. `None` if `dep` isn't in the registry.
+async fn registry_versions(
+    dep: &Dependency,
+) -> Result<Option<(OnyxApi, Vec<IndexVersion>, usize)>> {
+    let Some(tag) = dep.tag.as_ref() else {
+        return Ok(None);
+    };
+    let Some((registry, package_name)) = registry::of(dep)? else {
+        return Ok(None);
+    };
+    let package_name = package_name.as_str();
+    let api = registry.api();
+    // registries that sign snapshots are only trusted for what's in them, so don't fall back
+    // to cloning. Only the snapshots of the default registry are verified.
+    let snapshot = match registry.is_default() {
+        true => snapshot::current(&api).await?,
+        false => None,
+    };
+    let package = match index::load(&api, package_name).await {
+        Ok(package) => package,
+        Err(e) if snapshot.is_some() => {
+            return Err(e.context(format!("Failed to load the index of \"{package_name}\"")));
+        }
+        Err(e) => {
+            log::debug!("unable to load index for {package_name}: {e:?}");
+            return Ok(None);
+        }
+    };
+    let position = package.position(tag);
+    if let Some(snapshot) = snapshot {
+        let version = position.map(|position| &package.versions[position]);
+        snapshot::verify(
+            snapshot,
+            package_name,
+            version.map_or(tag, |version| version.name.as_str()),
+            version.map(|version| &version.id),
+        )?;
+    }
+    let Some(position) = position else {
+        return Ok(None);
+    };
+    Ok(Some((api, package.versions, position)))
+}
+
+/// Whether the registry attests the version of `dep` holds only static content. False for
+/// dependencies that aren't in a registry.
+async fn attested(dep: &Dependency) -> Result<bool> {
+    Ok(registry_versions(dep)
+        .await?
+        .is_some_and(|(_, versions, position)| versions[position].static_content))
+}
+
+/// Download the tarball of the registry dependency `dep` and unpack it at `dep_root_path`.
+/// The tarball is verified as it streams in, so it's never held in memory. Returns false if
+/// `dep` isn't in the registry, then it should be cloned instead.
+pub async fn download_dependency(
+    dep: &Dependency,
+    dep_root_path: &Path,
+    bar: &ProgressBar,
+) -> Result<bool> {
+    let Some((api, versions, position)) = registry_versions(dep).await? else {
+        return Ok(false);
+    };
+    let attested = versions[position].static_content;
+    let download = api.download_tarball_stream(&versions[position].id).await?;
+    if let Some(len) = download.content_length() {
+        bar.set_length(len);
+        bar.set_style(ProgressStyle::with_template(
+            "    {prefix} [{bar:20}] {bytes}/{total_bytes} {msg}",
+        )?);
+    }
+
+    // download atomically like `clone_dependency`
+    let workdir = tempfile::tempdir()?;
+    let unpack_path = workdir.path().to_path_buf();
+    let download = tokio_util::io::SyncIoBridge::new(download);
+    let bar = bar.clone();
+    tokio::task::spawn_blocking(move || -> Result<()> {
+        let mut reader = bar.wrap_read(download);
+        {
+            let mut archive = tar::Archive::new(&mut reader);
+            for entry in archive.entries()? {
+                let mut entry = entry?;
+                match entry.header().entry_type() {
+                    tar::EntryType::Regular | tar::EntryType::Directory => {
+                        let path = entry.path()?.to_path_buf();
+                        let mode = entry.header().mode()?;
+                        entry.unpack_in(&unpack_path)?;
+                        // hold the registry to its attestation
+                        if attested && entry.header().entry_type().is_file() {
+                            let mut head = vec![];
+                            std::fs::File::open(unpack_path.join(&path))?
+                                .take(2)
+                                .read_to_end(&mut head)?;
+                            nrpm_tarball::check_static_file(&path, mode, &head).context(
+                                "the registry attests the package holds only static content",
+                            )?;
+                        }
+                    }
+                    _ => anyhow::bail!("tarball contains an entry that isn't a file or directory"),
+                }
+            }
         }
+        // the download is only verified once it's read to the end
+        std::io::copy(&mut reader, &mut std::io::sink())?;
+        Ok(())
+    })
+    .await?
+    .context(format!("Failed to download \"{}\"", dep.name))?;
+    std::fs::create_dir_all(dep_root_path)?;
+    std::fs::rename(workdir.keep(), dep_root_path)?;
+    Ok(true)
+}
+
+/// Build the registry dependency `dep` at `dep_root_path` from the newest older version of it
+/// in the cache and the registry's delta between them, instead of downloading the whole
+/// version. Returns false if there is no such delta, or if the result doesn't match the
+/// published hash, then `dep` should be downloaded instead.
+async fn apply_delta(
+    dep: &Dependency,
+    dep_root_path: &Path,
+    progress: &ProgressBar,
+) -> Result<bool> {
+    let Some(versions_path) = dep_root_path.parent() else {
+        return Ok(false);
+    };
+    let Some((api, versions, position)) = registry_versions(dep).await? else {
+        return Ok(false);
+    };
+    let version = &versions[position];
+    let Some(cached) = versions[..position]
+        .iter()
+        .rev()
+        .find(|v| versions_path.join(&v.name).is_dir())
+    else {
+        return Ok(false);
+    };
+    let Some(delta) = api.version_delta(&version.id, &cached.id).await? else {
+        return Ok(false);
+    };
+
+    progress.set_message(format!("{}: applying delta from {}", dep.name, cached.name));
+    // build atomically like `clone_dependency`
+    let workdir = tempfile::tempdir()?;
+    nrpm_tarball::delta::apply(
+        &versions_path.join(&cached.name),
+        delta.as_slice(),
+        workdir.path(),
+    )?;
+    let hash = nrpm_tarball::hash_dir(workdir.path())?.to_string();
+    if hash != version.id.to_string() {
+        log::debug!(
+            "delta from {} to {} of {} hashed to {hash}",
+            cached.name,
+            version.name,
+            dep.name
+        );
+        return Ok(false);
+    }
+    std::fs::create_dir_all(dep_root_path)?;
+    std::fs::rename(workdir.keep(), dep_root_path)?;
+    Ok(true)
+}
+
+/// Quarantine the cache entry of `dep` at `dep_path`, whose content hashed to `found`, and
+/// download it again. Fails if the new copy doesn't hash to `expected`. Returns the new hash.
+fn refetch(
+    dep_cache_path: &Path,
+    dep_path: &Path,
+    dep: &Dependency,
+    expected: &str,
+    found: &str,
+    progress: &ProgressBar,
+) -> Result<String> {
+    let _dep_lock = cache::lock_dependency(dep_cache_path, dep_path, &dep.name, progress)?;
+    let quarantined = cache::quarantine(
+        dep_cache_path,
+        dep_path,
+        &dep.identifier()?,
+        expected,
+        found,
+    )?;
+    output::note(
+        progress,
+        format!(
+            "🩹 \"{}\" did not match its lockfile, moved it to {quarantined:?} and downloading again",
+            dep.name
+        ),
+    );
+    clone_dependency(dep, dep_path, progress)?;
+    let hash = nrpm_tarball::hash_dir(dep_path)?.to_string();
+    if hash != expected && registry::of(dep)?.is_none() {
+        return Err(tag_moved(dep, dep_path, expected, &hash));
+    }
+    if hash != expected {
+        Err(anyhow::Error::new(
+            Failure::new(
+                FailureCode::PublishedContentChanged,
+                format!("downloaded hash: {}", hash),
+            )
+            .with_advice(format!(
+                "Contact the author of \"{}\", the published content changed.",
+                dep.name
+            ))
+            .with_path(dep_path),
+        )
+        .context(format!("expected hash: {}", expected))
+        .context(format!(
+            "re-downloaded \"{}\" does not match the lockfile",
+            dep.name
+        )))?;
     }
+    Ok(hash)
+}
+
+/// A bar for the download of `dep`, see `download_dependency` and `clone_dependency`.
+pub fn download_bar(dep: &Dependency) -> Result<ProgressBar> {
+    Ok(ProgressBar::new(100)
+        .with_prefix(format!(
+            "{}@{}",
+            dep.name,
+            dep.tag.as_deref().unwrap_or_default()
+        ))
+        .with_style(ProgressStyle::with_template(
+            "    {prefix} [{bar:20}] {pos:>3}% {msg}",
+        )?))
+}
+
+/// Show a line of git clone progress, e.g.
+/// `Receiving objects:  42% (100/238), 1.23 MiB | 1.10 MiB/s`, on `bar`.
+fn show_git_progress(bar: &ProgressBar, line: &str) {
+    let Some((stage, rest)) = line.split_once(':') else {
+        return;
+    };
+    let Some((percent, detail)) = rest.split_once('%') else {
+        return;
+    };
+    let Ok(percent) = percent.trim().parse::<u64>() else {
+        return;
+    };
+    bar.set_position(percent);
+    // bytes received and the transfer rate follow the object counts
+    let transferred = detail
+        .split_once("),")
+        .map(|(_, transferred)| transferred.trim())
+        .unwrap_or_default();
+    bar.set_message(format!("{} {transferred}", stage.trim().to_lowercase()));
+}
 
-    Ok(all_dependencies)
+/// Clone a git dependency into `dep_root_path` in the system cache, showing the progress
+/// reported by git on `bar`.
+pub fn clone_dependency(dep: &Dependency, dep_root_path: &Path, bar: &ProgressBar) -> Result<()> {
+    let tag = dep.tag.as_ref().expect("tag should be Some at this point");
+    let git_url = dep.git.as_ref().expect("git should be Some at this point");
+
+    // download atomically
+    // clone into a tmpdir then move it into place
+    let workdir = tempfile::tempdir()?.keep();
+    let mut child = git::command(git_url)
+        .arg("-c")
+        .arg("advice.detachedHead=false")
+        .arg("clone")
+        .arg("--progress")
+        .arg("--depth")
+        .arg("1")
+        .arg("--branch")
+        .arg(tag)
+        .arg(git::clone_url(git_url))
+        .arg(
+            workdir
+                .to_str()
+                .expect("tempdir has non-unicode characters"),
+        )
+        .stdout(Stdio::null())
+        .stderr(Stdio::piped())
+        .spawn()?;
+    // git separates progress updates with carriage returns
+    let mut stderr = child.stderr.take().expect("stderr is piped");
+    let mut buf = [0u8; 1024];
+    let mut line = vec![];
+    let mut last_line = String::new();
+    let mut messages = vec![];
+    loop {
+        let len = stderr.read(&mut buf)?;
+        if len == 0 {
+            break;
+        }
+        for byte in &buf[..len] {
+            if *byte != b'\r' && *byte != b'\n' {
+                line.push(*byte);
+                continue;
+            }
+            let text = String::from_utf8_lossy(&line).trim().to_string();
+            if !text.is_empty() {
+                show_git_progress(bar, &text);
+                if !text.contains('%') {
+                    messages.push(text.clone());
+                }
+                last_line = text;
+            }
+            line.clear();
+        }
+    }
+    if !child.wait()?.success() {
+        let error = anyhow::anyhow!("Failed to clone {git_url} at {tag}: {last_line}");
+        return Err(
+            match git::credentials_failure(git_url, &messages.join("\n")) {
+                Some(failure) => error.context(failure),
+                None => error,
+            },
+        );
+    }
+    std::fs::create_dir_all(dep_root_path)?;
+    std::fs::rename(workdir, dep_root_path)?;
+    Ok(())
 }
@@ -0,0 +1,290 @@
+use std::collections::BTreeMap;
+use std::path::PathBuf;
+
+use anyhow::Context;
+use anyhow::Result;
+use argon2::Argon2;
+use onyx_api::prelude::*;
+use ring::aead::Aad;
+use ring::aead::CHACHA20_POLY1305;
+use ring::aead::LessSafeKey;
+use ring::aead::NONCE_LEN;
+use ring::aead::Nonce;
+use ring::aead::UnboundKey;
+use ring::rand::SecureRandom;
+use ring::rand::SystemRandom;
+use ring::signature::Ed25519KeyPair;
+use ring::signature::KeyPair;
+use serde::Deserialize;
+use serde::Serialize;
+
+use super::config;
+use super::failure::Failure;
+use super::failure::FailureCode;
+use super::output::summary;
+use super::registry_url;
+
+const SALT_LEN: usize = 16;
+
+/// A signing key kept on disk. The pkcs8 document is encrypted with a key derived from the
+/// passphrase, the public key is stored in the clear to find keys without the passphrase.
+#[derive(Clone, Serialize, Deserialize)]
+struct StoredKey {
+    public_key: String,
+    salt: String,
+    nonce: String,
+    ciphertext: String,
+    created_at: u64,
+    /// When the key was rotated out or revoked. Retired keys are kept so their history can
+    /// be listed.
+    retired_at: Option<u64>,
+}
+
+/// Signing keys by registry url.
+fn keys_path() -> Result<PathBuf> {
+    Ok(config::config_dir()?.join("keys.toml"))
+}
+
+fn load_all() -> Result<BTreeMap<String, Vec<StoredKey>>> {
+    let path = keys_path()?;
+    match std::fs::read_to_string(&path) {
+        Ok(str) => toml::from_str(&str).with_context(|| format!("Failed to parse {path:?}")),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(BTreeMap::new()),
+        Err(e) => Err(e.into()),
+    }
+}
+
+fn save_all(all: &BTreeMap<String, Vec<StoredKey>>) -> Result<()> {
+    config::write_private(&keys_path()?, &toml::to_string(all)?)
+}
+
+/// The passphrase keys are encrypted with, from `NRPM_KEY_PASSPHRASE` or a prompt.
+fn passphrase(confirm: bool) -> Result<String> {
+    if let Ok(passphrase) = std::env::var("NRPM_KEY_PASSPHRASE")
+        && !passphrase.is_empty()
+    {
+        return Ok(passphrase);
+    }
+    let mut prompt = dialoguer::Password::new().with_prompt("Key passphrase");
+    if confirm {
+        prompt = prompt.with_confirmation("Repeat the passphrase", "Passphrases don't match");
+    }
+    Ok(prompt.interact()?)
+}
+
+fn cipher(passphrase: &str, salt: &[u8]) -> Result<LessSafeKey> {
+    let mut key = [0u8; 32];
+    Argon2::default()
+        .hash_password_into(passphrase.as_bytes(), salt, &mut key)
+        .map_err(|e| anyhow::anyhow!("Failed to derive the key encryption key: {e}"))?;
+    let key = UnboundKey::new(&CHACHA20_POLY1305, &key)
+        .map_err(|_| anyhow::anyhow!("Failed to create the key encryption key"))?;
+    Ok(LessSafeKey::new(key))
+}
+
+/// Generate a keypair and encrypt it with `passphrase`.
+fn generate(passphrase: &str) -> Result<(Ed25519KeyPair, StoredKey)> {
+    let rng = SystemRandom::new();
+    let pkcs8 = Ed25519KeyPair::generate_pkcs8(&rng)
+        .map_err(|_| anyhow::anyhow!("Failed to generate a key"))?;
+    let keypair = Ed25519KeyPair::from_pkcs8(pkcs8.as_ref())
+        .map_err(|_| anyhow::anyhow!("Failed to parse the generated key"))?;
+    let public_key = hex::encode(keypair.public_key().as_ref());
+
+    let mut salt = [0u8; SALT_LEN];
+    let mut nonce = [0u8; NONCE_LEN];
+    rng.fill(&mut salt)
+        .and_then(|_| rng.fill(&mut nonce))
+        .map_err(|_| anyhow::anyhow!("Failed to generate randomness"))?;
+    let mut ciphertext = pkcs8.as_ref().to_vec();
+    cipher(passphrase, &salt)?
+        .seal_in_place_append_tag(
+            Nonce::assume_unique_for_key(nonce),
+            Aad::from(public_key.as_bytes()),
+            &mut ciphertext,
+        )
+        .map_err(|_| anyhow::anyhow!("Failed to encrypt the key"))?;
+    let stored = StoredKey {
+        public_key,
+        salt: hex::encode(salt),
+        nonce: hex::encode(nonce),
+        ciphertext: hex::encode(ciphertext),
+        created_at: timestamp(),
+        retired_at: None,
+    };
+    Ok((keypair, stored))
+}
+
+/// Decrypt a stored key with `passphrase`.
+fn decrypt(stored: &StoredKey, passphrase: &str) -> Result<Ed25519KeyPair> {
+    let nonce: [u8; NONCE_LEN] = hex::decode(&stored.nonce)?
+        .try_into()
+        .map_err(|_| anyhow::anyhow!("Invalid nonce for key {}", stored.public_key))?;
+    let mut ciphertext = hex::decode(&stored.ciphertext)?;
+    let pkcs8 = cipher(passphrase, &hex::decode(&stored.salt)?)?
+        .open_in_place(
+            Nonce::assume_unique_for_key(nonce),
+            Aad::from(stored.public_key.as_bytes()),
+            &mut ciphertext,
+        )
+        .map_err(|_| anyhow::anyhow!("Wrong passphrase for key {}", stored.public_key))?;
+    Ed25519KeyPair::from_pkcs8(pkcs8)
+        .map_err(|_| anyhow::anyhow!("Failed to parse key {}", stored.public_key))
+}
+
+fn sign(keypair: &Ed25519KeyPair, username: &str, public_key: &str) -> String {
+    hex::encode(
+        keypair
+            .sign(key_message(username, public_key).as_bytes())
+            .as_ref(),
+    )
+}
+
+/// The key that isn't retired, if there is one.
+fn active(keys: &[StoredKey]) -> Option<&StoredKey> {
+    keys.iter().find(|key| key.retired_at.is_none())
+}
+
+/// Generate a signing key for the registry and add its public key to the logged in user.
+pub async fn keygen(api: &OnyxApi) -> Result<()> {
+    let registry_url = registry_url();
+    let mut all = load_all()?;
+    if let Some(key) = all.get(&registry_url).and_then(|keys| active(keys)) {
+        return Err(Failure::new(
+            FailureCode::SigningKey,
+            format!(
+                "You already have the signing key {} for {registry_url}",
+                key.public_key
+            ),
+        )
+        .with_advice("Replace it with: nrpm key rotate, or revoke it with: nrpm key revoke")
+        .into());
+    }
+    let login = super::attempt_auth().await?;
+    let (keypair, stored) = generate(&passphrase(true)?)?;
+    let username = &login.user.username;
+    api.add_key(
+        &login.token,
+        AddKeyRequest {
+            public_key: stored.public_key.clone(),
+            signature: sign(&keypair, username, &stored.public_key),
+            replaces: None,
+            replaces_signature: None,
+        },
+    )
+    .await
+    .context("Unable to add the key to your account")?;
+    summary!("🔑 Added signing key {} to {username}", stored.public_key);
+    all.entry(registry_url).or_default().push(stored);
+    save_all(&all)
+}
+
+/// Replace the active signing key with a new one. The old key signs the new one so anyone
+/// can follow the lineage, and signatures it made before the rotation stay valid.
+pub async fn rotate(api: &OnyxApi) -> Result<()> {
+    let registry_url = registry_url();
+    let mut all = load_all()?;
+    let keys = all.entry(registry_url.clone()).or_default();
+    let Some(old) = active(keys).cloned() else {
+        return Err(Failure::new(
+            FailureCode::SigningKey,
+            format!("You have no signing key for {registry_url}"),
+        )
+        .with_advice("Create one with: nrpm keygen")
+        .into());
+    };
+    let login = super::attempt_auth().await?;
+    // the new key is encrypted with the same passphrase
+    let passphrase = passphrase(false)?;
+    let old_keypair = decrypt(&old, &passphrase)?;
+    let (keypair, stored) = generate(&passphrase)?;
+    let username = &login.user.username;
+    api.add_key(
+        &login.token,
+        AddKeyRequest {
+            public_key: stored.public_key.clone(),
+            signature: sign(&keypair, username, &stored.public_key),
+            replaces: Some(old.public_key.clone()),
+            replaces_signature: Some(sign(&old_keypair, username, &stored.public_key)),
+        },
+    )
+    .await
+    .context(format!("Unable to rotate the key {}", old.public_key))?;
+    summary!(
+        "🔁 Rotated signing key {} to {}",
+        old.public_key,
+        stored.public_key
+    );
+    for key in keys.iter_mut() {
+        if key.public_key == old.public_key {
+            key.retired_at = Some(timestamp());
+        }
+    }
+    keys.push(stored);
+    save_all(&all)
+}
+
+/// Revoke `public_key`, or the active signing key. Nothing signed by a revoked key is
+/// trusted, so rotate instead unless the key was compromised.
+pub async fn revoke(api: &OnyxApi, public_key: Option<&str>) -> Result<()> {
+    let registry_url = registry_url();
+    let mut all = load_all()?;
+    let keys = all.entry(registry_url.clone()).or_default();
+    let public_key = match public_key {
+        Some(public_key) => public_key.to_lowercase(),
+        None => active(keys)
+            .map(|key| key.public_key.clone())
+            .ok_or(anyhow::anyhow!(
+                "You have no signing key for {registry_url}, pass the public key to revoke"
+            ))?,
+    };
+    let login = super::attempt_auth().await?;
+    api.revoke_key(&login.token, &public_key)
+        .await
+        .context(format!("Unable to revoke the key {public_key}"))?;
+    summary!("🚫 Revoked signing key {public_key}");
+    for key in keys.iter_mut() {
+        if key.public_key == public_key && key.retired_at.is_none() {
+            key.retired_at = Some(timestamp());
+        }
+    }
+    save_all(&all)
+}
+
+/// List the signing keys of `username`, or the logged in user, and mark the ones stored
+/// locally.
+pub async fn list(api: &OnyxApi, username: Option<&str>) -> Result<()> {
+    let username = match username {
+        Some(username) => username.to_string(),
+        None => super::attempt_auth().await?.user.username,
+    };
+    let local = load_all()?.remove(&registry_url()).unwrap_or_default();
+    let keys = api
+        .user_keys(&username)
+        .await
+        .context(format!("Unable to load the keys of \"{username}\""))?;
+    if keys.is_empty() {
+        summary!("🔑 {username} has no signing keys");
+        return Ok(());
+    }
+    summary!("🔑 Signing keys of {username}");
+    for key in keys {
+        let status = if let Some(revoked_at) = key.revoked_at {
+            format!("revoked at {revoked_at}")
+        } else if let Some(replaced_by) = &key.replaced_by {
+            format!(
+                "rotated to {replaced_by} at {}",
+                key.replaced_at.unwrap_or_default()
+            )
+        } else {
+            "active".to_string()
+        };
+        let stored = if local.iter().any(|k| k.public_key == key.public_key) {
+            " (stored locally)"
+        } else {
+            ""
+        };
+        summary!("    {} {status}{stored}", key.public_key);
+    }
+    Ok(())
+}
@@ -0,0 +1,105 @@
+use std::collections::BTreeMap;
+use std::path::Path;
+
+use anyhow::Context;
+use anyhow::Result;
+use indicatif::ProgressBar;
+use nargo_parse::*;
+use serde::Serialize;
+
+use crate::cache;
+use crate::failure::Failure;
+use crate::failure::FailureCode;
+use crate::lockfile::LockEntry;
+use crate::lockfile::Lockfile;
+
+/// The license of a package in a resolved dependency tree.
+#[derive(Serialize)]
+pub struct PackageLicense {
+    pub name: String,
+    pub version: Option<String>,
+    /// Where the package was installed from, see `LockEntry::identifier`.
+    pub source: String,
+    /// `None` if the package doesn't declare a license.
+    pub license: Option<String>,
+}
+
+/// Every package in the nrpm.lock of the package at `path` with its Nargo.toml. Locked
+/// packages must already be installed.
+pub fn locked_packages(path: &Path) -> Result<Vec<(LockEntry, NargoConfig)>> {
+    let lockfile_path = path.join("nrpm.lock");
+    if !lockfile_path.exists() {
+        anyhow::bail!("No nrpm.lock found in {path:?}, run nrpm install to create one");
+    }
+    let lockfile = Lockfile::load_or_init(&lockfile_path)?;
+    let dep_cache_path = cache::cache_path()?;
+    let _cache_lock = cache::lock_cache(&dep_cache_path, &ProgressBar::hidden())?;
+
+    let mut packages = vec![];
+    for entry in lockfile.entries() {
+        let dep = Dependency::new_git(String::new(), entry.git.clone(), entry.tag.clone());
+        let dep_root_path = dep.folder_path(&dep_cache_path)?;
+        let config = NargoConfig::load(&dep_root_path)
+            .context(
+                Failure::new(FailureCode::NotInstalled, "locked package is not installed")
+                    .with_advice("Run nrpm install to download locked packages.")
+                    .with_path(&dep_root_path),
+            )
+            .context(format!(
+                "failed to load Nargo.toml for locked package {}",
+                entry.identifier()
+            ))?;
+        packages.push((entry.clone(), config));
+    }
+    Ok(packages)
+}
+
+/// The licenses of every package in the nrpm.lock of the package at `path`, sorted by name.
+/// Locked packages must already be installed.
+pub fn licenses(path: &Path) -> Result<Vec<PackageLicense>> {
+    let mut licenses = locked_packages(path)?
+        .into_iter()
+        .map(|(entry, config)| PackageLicense {
+            name: config.package.name,
+            version: config.package.version,
+            source: entry.identifier(),
+            license: config.package.license,
+        })
+        .collect::<Vec<_>>();
+    licenses.sort_by(|a, b| (&a.name, &a.source).cmp(&(&b.name, &b.source)));
+    Ok(licenses)
+}
+
+/// Print `licenses` for people, or as a json array if `json`.
+pub fn print(licenses: &[PackageLicense], json: bool) -> Result<()> {
+    if json {
+        println!("{}", serde_json::to_string_pretty(licenses)?);
+        return Ok(());
+    }
+    if licenses.is_empty() {
+        println!("No dependencies");
+        return Ok(());
+    }
+    let name_width = licenses.iter().map(|l| l.name.len()).max().unwrap_or(0);
+    let version_width = licenses
+        .iter()
+        .map(|l| l.version.as_deref().unwrap_or("-").len())
+        .max()
+        .unwrap_or(0);
+    // license keyed to the number of packages using it
+    let mut totals = BTreeMap::<&str, usize>::new();
+    for l in licenses {
+        let license = l.license.as_deref().unwrap_or("(none)");
+        *totals.entry(license).or_default() += 1;
+        println!(
+            "{:name_width$}  {:version_width$}  {license}",
+            l.name,
+            l.version.as_deref().unwrap_or("-"),
+        );
+    }
+    println!();
+    for (license, count) in totals {
+        println!("{count:>4}  {license}");
+    }
+    Ok(())
+}
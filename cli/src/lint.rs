@@ -0,0 +1,271 @@
+use std::io::Read;
+use std::path::Path;
+use std::path::PathBuf;
+
+use anyhow::Context;
+use anyhow::Result;
+use nargo_parse::*;
+use serde::Serialize;
+
+use crate::output;
+
+/// Files larger than this are reported, they're downloaded by every user of the package.
+const LARGE_FILE_SIZE: u64 = 1024 * 1024;
+/// The most the registry accepts, see `nrpm_tarball::validate_tarball`.
+const MAX_PACKAGE_SIZE: u64 = 20 * 1024 * 1024;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Severity {
+    Info,
+    Warning,
+    /// The registry would reject the package.
+    Error,
+}
+
+/// A problem with a package found by `lint`.
+#[derive(Clone, Debug, Serialize)]
+pub struct Diagnostic {
+    pub severity: Severity,
+    /// Stable identifier of the check, e.g. `missing-license`.
+    pub code: &'static str,
+    pub message: String,
+    /// The file the problem is in, relative to the package root.
+    pub path: PathBuf,
+}
+
+impl Diagnostic {
+    fn new(severity: Severity, code: &'static str, message: String) -> Self {
+        Self {
+            severity,
+            code,
+            message,
+            path: PathBuf::from("Nargo.toml"),
+        }
+    }
+}
+
+/// Check the Nargo.toml of the package in `pkg_dir`, and the files that would be published
+/// with it. Diagnostics are sorted most severe first.
+pub fn lint(pkg_dir: &Path) -> Result<Vec<Diagnostic>> {
+    let config =
+        NargoConfig::load(pkg_dir).with_context(|| "Nargo.toml not found in directory!")?;
+    let package = &config.package;
+    let mut diagnostics = vec![];
+
+    match package.version.as_ref() {
+        None => diagnostics.push(Diagnostic::new(
+            Severity::Error,
+            "missing-version",
+            "package has no version".to_string(),
+        )),
+        Some(version) => {
+            if let Err(e) = semver::Version::parse(version) {
+                diagnostics.push(Diagnostic::new(
+                    Severity::Error,
+                    "invalid-version",
+                    format!("version \"{version}\" is not semver: {e}"),
+                ));
+            }
+        }
+    }
+    if let Some(package_type) = package.package_type
+        && !package.is_library()
+    {
+        diagnostics.push(Diagnostic::new(
+            Severity::Warning,
+            "not-a-library",
+            format!(
+                "package is a {package_type}, only lib packages can be installed as dependencies"
+            ),
+        ));
+    }
+    let is_missing = |value: Option<&String>| value.is_none_or(|v| v.trim().is_empty());
+    if is_missing(package.description.as_ref()) {
+        diagnostics.push(Diagnostic::new(
+            Severity::Warning,
+            "missing-description",
+            "package has no description, it's shown in search results".to_string(),
+        ));
+    }
+    if is_missing(package.license.as_ref()) {
+        diagnostics.push(Diagnostic::new(
+            Severity::Warning,
+            "missing-license",
+            "package has no license, users can't tell if they may use it".to_string(),
+        ));
+    } else if let Some(license) = package.license.as_ref()
+        && let Err(e) = spdx::Expression::parse(license)
+    {
+        diagnostics.push(Diagnostic::new(
+            Severity::Error,
+            "invalid-license",
+            format!(
+                "license \"{license}\" is not a valid SPDX expression: {}",
+                e.reason
+            ),
+        ));
+    }
+    match package.repository.as_ref() {
+        None => diagnostics.push(Diagnostic::new(
+            Severity::Warning,
+            "missing-repository",
+            "package has no repository".to_string(),
+        )),
+        Some(repository) => {
+            if reqwest::Url::parse(repository).is_err() {
+                diagnostics.push(Diagnostic::new(
+                    Severity::Warning,
+                    "invalid-repository",
+                    format!("repository \"{repository}\" is not a url"),
+                ));
+            }
+        }
+    }
+    if let Err(e) = package.nrpm_metadata() {
+        diagnostics.push(Diagnostic::new(
+            Severity::Error,
+            "invalid-metadata",
+            e.to_string(),
+        ));
+    }
+    if package.keywords.as_ref().is_none_or(|k| k.is_empty()) {
+        diagnostics.push(Diagnostic::new(
+            Severity::Info,
+            "missing-keywords",
+            "package has no keywords to be found by".to_string(),
+        ));
+    }
+
+    for dep in config.dependencies()?.values() {
+        if let Some(path) = dep.path.as_ref() {
+            diagnostics.push(Diagnostic::new(
+                Severity::Warning,
+                "path-dependency",
+                format!(
+                    "dependency \"{}\" is at path {path:?}, it must be published and is replaced by its registry version when publishing",
+                    dep.name
+                ),
+            ));
+        }
+    }
+
+    lint_files(pkg_dir, &mut diagnostics)?;
+    diagnostics.sort_by_key(|d| std::cmp::Reverse(d.severity));
+    Ok(diagnostics)
+}
+
+/// Check the files `nrpm_tarball::create` would package.
+fn lint_files(pkg_dir: &Path, diagnostics: &mut Vec<Diagnostic>) -> Result<()> {
+    let tarball = nrpm_tarball::create(pkg_dir, tempfile::tempfile()?)?;
+    let mut archive = tar::Archive::new(tarball);
+    let mut total_size = 0u64;
+    for entry in archive.entries()? {
+        let mut entry = entry?;
+        let path = entry.path()?.to_path_buf();
+        let size = entry.size();
+        total_size += size;
+        if entry.header().entry_type().is_file() {
+            let mode = entry.header().mode()?;
+            let mut head = vec![];
+            (&mut entry).take(2).read_to_end(&mut head)?;
+            if let Err(e) = nrpm_tarball::check_static_file(&path, mode, &head) {
+                diagnostics.push(Diagnostic {
+                    severity: Severity::Error,
+                    code: "not-static",
+                    message: format!("{e}, the registry rejects it"),
+                    path: path.clone(),
+                });
+            }
+        }
+        if path.starts_with("target") {
+            diagnostics.push(Diagnostic {
+                severity: Severity::Warning,
+                code: "build-output",
+                message: "nargo build output is packaged, add target to .gitignore".to_string(),
+                path,
+            });
+        } else if path.file_name().is_some_and(|name| name == ".env")
+            || path
+                .extension()
+                .is_some_and(|ext| ext == "pem" || ext == "key")
+        {
+            diagnostics.push(Diagnostic {
+                severity: Severity::Warning,
+                code: "secret-file",
+                message: "file may contain secrets and is packaged, add it to .gitignore"
+                    .to_string(),
+                path,
+            });
+        } else if size > LARGE_FILE_SIZE {
+            diagnostics.push(Diagnostic {
+                severity: Severity::Info,
+                code: "large-file",
+                message: format!("file is {size} bytes, every user of the package downloads it"),
+                path,
+            });
+        }
+    }
+    if total_size > MAX_PACKAGE_SIZE {
+        diagnostics.push(Diagnostic {
+            severity: Severity::Error,
+            code: "package-too-large",
+            message: format!(
+                "package is {total_size} bytes, the registry accepts at most {MAX_PACKAGE_SIZE}"
+            ),
+            path: PathBuf::from("."),
+        });
+    }
+    Ok(())
+}
+
+/// Print `diagnostics` for people, or as a json array if `json`.
+pub fn print(diagnostics: &[Diagnostic], json: bool) -> Result<()> {
+    if json {
+        println!("{}", serde_json::to_string_pretty(diagnostics)?);
+        return Ok(());
+    }
+    for diagnostic in diagnostics {
+        let (icon, severity) = match diagnostic.severity {
+            Severity::Error => (output::icon("❌ ", ""), "error"),
+            Severity::Warning => (output::icon("⚠️  ", ""), "warning"),
+            Severity::Info => (output::icon("💡 ", ""), "info"),
+        };
+        println!(
+            "{icon}{severity}[{}]: {}",
+            diagnostic.code, diagnostic.message
+        );
+        println!("    --> {}", diagnostic.path.display());
+    }
+    println!("{}", summary(diagnostics));
+    Ok(())
+}
+
+/// e.g. `1 error, 2 warnings, 0 infos`
+pub fn summary(diagnostics: &[Diagnostic]) -> String {
+    let count = |severity| {
+        diagnostics
+            .iter()
+            .filter(|d| d.severity == severity)
+            .count()
+    };
+    let plural = |n: usize, word: &str| format!("{n} {word}{}", if n == 1 { "" } else { "s" });
+    format!(
+        "{}, {}, {}",
+        plural(count(Severity::Error), "error"),
+        plural(count(Severity::Warning), "warning"),
+        plural(count(Severity::Info), "info"),
+    )
+}
+
+/// Fail if any of `diagnostics` is an error.
+pub fn check(diagnostics: &[Diagnostic]) -> Result<()> {
+    let errors = diagnostics
+        .iter()
+        .filter(|d| d.severity == Severity::Error)
+        .count();
+    if errors > 0 {
+        anyhow::bail!("Lint found {errors} error(s), run nrpm lint for details");
+    }
+    Ok(())
+}
@@ -1,6 +1,7 @@
 use std::collections::BTreeMap;
 use std::path::Path;
 
+use anyhow::Context;
 use anyhow::Result;
 use serde::Deserialize;
 use serde::Serialize;
@@ -8,10 +9,17 @@ use toml::Table;
 
 use nargo_parse::*;
 
+use crate::failure::Failure;
+use crate::failure::FailureCode;
+use crate::output::summary;
+use crate::registry;
+
 #[derive(Clone, Debug)]
 pub struct Lockfile {
     #[allow(dead_code)]
     pub version: i64,
+    /// Id of the registry snapshot the last downloaded packages were verified against.
+    pub snapshot: Option<u64>,
     packages_cache: BTreeMap<String, LockEntry>,
 }
 
@@ -19,6 +27,7 @@ impl Lockfile {
     pub fn new() -> Self {
         Self {
             version: 0,
+            snapshot: None,
             packages_cache: BTreeMap::default(),
         }
     }
@@ -28,6 +37,14 @@ impl Lockfile {
         if !path.exists() {
             return Ok(Self::new());
         }
+        Self::parse(path).context(
+            Failure::new(FailureCode::MalformedLockfile, "Unable to parse lockfile")
+                .with_advice("Fix or delete the lockfile, nrpm install writes a new one.")
+                .with_path(path),
+        )
+    }
+
+    fn parse(path: &Path) -> Result<Self> {
         let mut s: BTreeMap<String, toml::Value> = toml::from_str(&std::fs::read_to_string(path)?)?;
         let packages = match s.remove("packages").unwrap_or(toml::Value::Array(vec![])) {
             toml::Value::Array(packages) => packages
@@ -41,12 +58,21 @@ impl Lockfile {
             _ => anyhow::bail!("malformed lockfile, packages must be an array: {path:?}"),
         };
         let mut packages_cache = BTreeMap::default();
-        for entry in packages {
-            let entry_identifier = entry.identifier();
+        for mut entry in packages {
+            if entry.name.is_none() {
+                // locked before registry packages were identified by name, the next install
+                // writes it
+                entry.name = registry::of_git(&entry.git, entry.registry.as_deref())
+                    .ok()
+                    .flatten()
+                    .map(|(_, package_name)| package_name);
+            }
+            let entry_identifier = entry.package_id();
             if packages_cache.contains_key(&entry_identifier) {
-                println!(
+                summary!(
                     "WARNING: lockfile contains a duplicate entry for {}:{}",
-                    entry.git, entry.tag
+                    entry.git,
+                    entry.tag
                 );
             }
             packages_cache.insert(entry_identifier, entry);
@@ -62,8 +88,14 @@ impl Lockfile {
                 "bad version number, only version 0 is supported by this version of nrpm: {path:?}"
             );
         }
+        let snapshot = match s.get("snapshot") {
+            None => None,
+            Some(toml::Value::Integer(snapshot)) => Some(u64::try_from(*snapshot)?),
+            _ => anyhow::bail!("malformed lockfile, snapshot must be an integer: {path:?}"),
+        };
         Ok(Self {
             version,
+            snapshot,
             packages_cache,
         })
     }
@@ -76,7 +108,7 @@ impl Lockfile {
         self.packages_cache.is_empty()
     }
 
-    /// Retrieve a lockfile entry, if it exists.
+    /// Retrieve a lockfile entry by `package_id`, if it exists.
     pub fn entry(&self, identifier: &str) -> Option<LockEntry> {
         self.packages_cache
             .get(identifier)
@@ -87,6 +119,12 @@ impl Lockfile {
     pub fn save(&self, path: &Path) -> Result<()> {
         let mut out = BTreeMap::<String, toml::Value>::default();
         out.insert("version".into(), toml::Value::Integer(0));
+        if let Some(snapshot) = self.snapshot {
+            out.insert(
+                "snapshot".into(),
+                toml::Value::Integer(i64::try_from(snapshot)?),
+            );
+        }
         out.insert(
             "packages".into(),
             toml::Value::Array(
@@ -106,8 +144,9 @@ impl Lockfile {
 
     /// Insert a dependence that exists at `path` into the lockfile
     ///
-    /// The contents at `path` will be hashed.
-    pub fn upsert(&mut self, dep: Dependency, path: &Path) -> Result<()> {
+    /// The contents at `path` will be hashed. `static_content` is whether the registry attests
+    /// the dependency holds only static content.
+    pub fn upsert(&mut self, dep: Dependency, path: &Path, static_content: bool) -> Result<()> {
         if !path.is_absolute() {
             anyhow::bail!("lockfile paths must be absolute");
         }
@@ -115,12 +154,22 @@ impl Lockfile {
         if let Some(git) = &dep.git
             && let Some(tag) = &dep.tag
         {
+            let package_id = package_id(&dep)?;
+            // trust is kept when the entry is updated, e.g. to a new hash the user accepted
+            let trusted_on_first_use = self
+                .packages_cache
+                .get(&package_id)
+                .is_some_and(|entry| entry.trusted_on_first_use);
             self.packages_cache.insert(
-                dep.identifier()?,
+                package_id,
                 LockEntry {
+                    name: registry::of(&dep)?.map(|(_, package_name)| package_name),
                     git: git.clone(),
                     tag: tag.clone(),
+                    registry: dep.registry.clone(),
                     blake3: hash.to_string(),
+                    static_content,
+                    trusted_on_first_use,
                 },
             );
         }
@@ -128,20 +177,71 @@ impl Lockfile {
         Ok(())
     }
 
+    /// Record that the user trusted the entry `identifier` on first use.
+    pub fn trust(&mut self, identifier: &str) {
+        if let Some(entry) = self.packages_cache.get_mut(identifier) {
+            entry.trusted_on_first_use = true;
+        }
+    }
+
     pub fn remove(&mut self, identifier: &str) {
         self.packages_cache.remove(identifier);
     }
 }
 
+/// How nrpm.lock identifies `dep`. A registry package is identified by its name and version,
+/// not the url it's cloned from, so an entry is kept when the package moves to another
+/// registry or a mirror of it. Its content hash pins that it's the same package. Other git
+/// dependencies are identified by url and tag, see `Dependency::identifier`.
+pub fn package_id(dep: &Dependency) -> Result<String> {
+    match (registry::of(dep)?, dep.tag.as_ref()) {
+        (Some((_, package_name)), Some(tag)) => Ok(format!("{package_name}@{tag}")),
+        _ => dep.identifier(),
+    }
+}
+
 #[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct LockEntry {
+    /// Name of the package in the registry `git` points into, if it's a registry package.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub name: Option<String>,
+    /// Where the package was cloned or downloaded from when it was locked. For registry
+    /// packages this is only where to find it, see `package_id`.
     pub git: String,
     pub tag: String,
+    /// The registry the dependency is pinned to, if it is. `git` is the identity of the
+    /// package, this is how the package depending on it found it.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub registry: Option<String>,
     pub blake3: String, // Content hash of the package
+    /// The registry attested the package holds only static content when it was locked, no
+    /// executables, scripts or symlinks. See `IndexVersion::static_content`.
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub static_content: bool,
+    /// The user trusted the content of this git dependency from outside the registries when
+    /// it was first locked, since no registry vouches for it. See `install::trust_on_first_use`.
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub trusted_on_first_use: bool,
 }
 
 impl LockEntry {
+    /// The dependency this entry locks, named by its identifier.
+    pub fn dependency(&self) -> Dependency {
+        let mut dep = Dependency::new_git(self.identifier(), self.git.clone(), self.tag.clone());
+        dep.registry = self.registry.clone();
+        dep
+    }
+
+    /// Where the package was installed from, `git@tag`, matching `Dependency::identifier`.
     pub fn identifier(&self) -> String {
         format!("{}@{}", self.git, self.tag)
     }
+
+    /// How the lockfile identifies the package, see `package_id`.
+    pub fn package_id(&self) -> String {
+        match &self.name {
+            Some(package_name) => format!("{package_name}@{}", self.tag),
+            None => self.identifier(),
+        }
+    }
 }
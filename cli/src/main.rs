@@ -1,3 +1,4 @@
+use std::io::IsTerminal;
 use std::path::PathBuf;
 use std::time::Duration;
 
@@ -6,70 +7,180 @@ use anyhow::Result;
 use clap::Arg;
 use clap::ArgAction;
 use clap::Command;
+use indicatif::ProgressBar;
 use nanoid::nanoid;
 use nargo_parse::Dependency;
 use nargo_parse::NargoConfig;
+use nargo_parse::Workspace;
 use onyx_api::prelude::*;
 use tokio;
 use tokio::task::JoinSet;
 
+use install::InstallOptions;
+use output::say;
+use output::summary;
+
+mod artifact;
+mod cache;
+mod changelog;
+mod clean;
+mod config;
+mod credentials;
+mod diff;
+mod failure;
+mod fetch;
+mod gc;
+mod git;
+mod index;
 mod install;
+mod key;
+mod licenses;
+mod lint;
 mod lockfile;
+mod nargo;
+mod outdated;
+mod output;
+mod owner;
+mod policy;
 mod publish;
+mod registry;
+mod report;
+mod sbom;
+mod snapshot;
+mod stats;
+mod sync;
+mod update_notice;
+mod verify;
+mod version;
+mod workspace;
 
 #[cfg(debug_assertions)]
-const REGISTRY_URL: &str = "http://localhost:8080";
+const DEFAULT_REGISTRY_URL: &str = "http://localhost:8080";
 #[cfg(not(debug_assertions))]
-const REGISTRY_URL: &str = "https://nrpm.io";
+const DEFAULT_REGISTRY_URL: &str = "https://nrpm.io";
+
+/// The url registry packages are cloned from, the `registry_url` setting.
+fn registry_url() -> String {
+    config::value("registry_url").unwrap_or(DEFAULT_REGISTRY_URL.to_string())
+}
+
+/// A client for the registry api, at the `api_url` setting.
+fn registry_api() -> OnyxApi {
+    match config::value("api_url") {
+        Some(url) => OnyxApi { url },
+        None => OnyxApi::default(),
+    }
+}
 
 #[tokio::main]
 async fn main() -> Result<()> {
     env_logger::init();
-    log::debug!("registry url: {REGISTRY_URL}");
+    log::debug!("registry url: {}", registry_url());
 
     if let Err(err) = run().await {
-        eprintln!("❌ {}", err);
-
-        // Print all errors in the chain
-        for (_i, cause) in err.chain().enumerate().skip(1) {
-            if cause.to_string().starts_with("ADVICE") {
-                eprintln!(
-                    "💡 {}",
-                    cause.to_string().trim_start_matches("ADVICE").trim()
-                );
-            } else {
-                eprintln!("   {}", cause);
-            }
-        }
-
-        std::process::exit(1);
+        std::process::exit(failure::report(&err, output::is_json()));
     } else {
+        if !output::is_quiet() {
+            update_notice::notify(&registry_api()).await;
+        }
         Ok(())
     }
 }
 
 async fn run() -> Result<()> {
     let matches = cli().get_matches();
-    let api = OnyxApi::default();
+    output::init(matches.get_flag("quiet"), matches.get_flag("json"));
+    // an invalid setting can still be fixed or removed
+    if !matches!(matches.subcommand_name(), Some("config")) {
+        config::check_file()?;
+    }
+    let api = registry_api();
     let cwd = std::env::current_dir()?;
+    if matches!(
+        matches.subcommand_name(),
+        Some(
+            "publish"
+                | "install"
+                | "fetch"
+                | "nargo"
+                | "test"
+                | "check"
+                | "owner"
+                | "keygen"
+                | "key"
+                | "artifact"
+                | "verify"
+                | "outdated"
+        )
+    ) {
+        check_registry(&api).await?;
+    }
     if let Some(matches) = matches.subcommand_matches("publish") {
+        // when publishing from git the path is relative to the root of the checkout
+        let git_source = match matches.get_one::<String>("git") {
+            Some(url) => {
+                let tag = matches
+                    .get_one::<String>("tag")
+                    .expect("clap requires tag with git");
+                Some(publish::clone_git_source(url, tag)?)
+            }
+            None => None,
+        };
+        let base = git_source
+            .as_ref()
+            .map(|source| source.path().to_path_buf())
+            .unwrap_or(cwd);
         let path = matches
             .get_one::<String>("path")
             .map(|p| {
                 let in_path = PathBuf::from(p);
                 if in_path.is_relative() {
-                    cwd.join(in_path)
+                    base.join(in_path)
                 } else {
                     in_path
                 }
             })
-            .unwrap_or(cwd);
+            .unwrap_or(base);
         let archive_path = matches
             .get_one::<String>("archive")
             .and_then(|s| Some(PathBuf::from(s)));
-        install::install(path.to_path_buf()).await?;
-        publish::upload_tarball(&api, &path, archive_path).await?;
+        let assume_yes = matches.get_flag("yes");
+        let changelog = matches.get_flag("changelog");
+        // a missing or malformed Nargo.toml is reported by install below
+        if let Some(workspace) = Workspace::load(&path).ok().flatten() {
+            if archive_path.is_some() {
+                anyhow::bail!("--archive is not supported for workspaces");
+            }
+            publish::publish_workspace(
+                &api,
+                &path,
+                &workspace,
+                git_source.as_ref(),
+                changelog,
+                assume_yes,
+            )
+            .await?;
+        } else {
+            install::install(path.to_path_buf(), &InstallOptions::default()).await?;
+            publish::upload_tarball(
+                &api,
+                &path,
+                archive_path,
+                git_source.as_ref(),
+                changelog,
+                assume_yes,
+            )
+            .await?;
+        }
     } else if let Some(matches) = matches.subcommand_matches("install") {
+        let options = InstallOptions {
+            report_path: matches.get_one::<String>("report").map(|p| cwd.join(p)),
+            interactive: !matches.get_flag("no_interactive") && std::io::stdin().is_terminal(),
+            repair: matches.get_flag("repair"),
+            no_dev: matches.get_flag("no_dev"),
+            policy_report: matches.get_flag("policy_report"),
+            yes: matches.get_flag("yes"),
+        };
         let path = matches
             .get_one::<String>("path")
             .map(|p| {
@@ -87,18 +198,37 @@ async fn run() -> Result<()> {
         let packages_to_install = matches
             .get_many::<String>("package_name")
             .unwrap_or_default();
+        let pinned = matches.get_one::<String>("registry").cloned();
+        // look every package up in each registry it may be in with one request
+        let registries = match pinned.as_deref() {
+            Some(pinned) => registry::named(pinned).into_iter().collect(),
+            None => registry::all(),
+        };
+        registry::prefetch(registries.iter().flat_map(|registry| {
+            packages_to_install
+                .clone()
+                .map(|package_name| (registry.clone(), package_name.clone()))
+        }))
+        .await;
         for new_dep_name in packages_to_install {
             let new_dep_name = new_dep_name.clone();
-            let api = api.clone();
+            let pinned = pinned.clone();
             join_set.spawn(async move {
-                let (package, version) = api
-                    .load_package_latest_version(&new_dep_name)
-                    .await
-                    .context(format!("Unable to install package \"{new_dep_name}\""))?;
-                println!("Adding package: {}@{}", package.name, version.name);
-                let git_url = format!("{REGISTRY_URL}/{new_dep_name}");
-                let tag = version.name;
-                Ok(Dependency::new_git(new_dep_name.to_string(), git_url, tag))
+                let (registry, package_name, version) =
+                    registry::latest_version(&new_dep_name, pinned.as_deref())
+                        .await
+                        .context(format!("Unable to install package \"{new_dep_name}\""))?;
+                say!("Adding package: {}@{}", package_name, version.name);
+                let mut dep = Dependency::registry(
+                    new_dep_name.to_string(),
+                    &registry.url,
+                    registry::tag(&version.name),
+                );
+                // packages from the default registry are only pinned if asked to be
+                if pinned.is_some() || !registry.is_default() {
+                    dep.registry = Some(registry.name);
+                }
+                Ok(dep)
             });
         }
         let mut new_packages: Vec<Dependency> = Vec::default();
@@ -110,57 +240,379 @@ async fn run() -> Result<()> {
             NargoConfig::add_dependencies_in_place(&path, new_packages)
                 .context("Failed to write new dependencies to Nargo.toml")?;
         }
-        install::install(path).await?;
-    } else if let Some(_matches) = matches.subcommand_matches("clean") {
-        let path = cache_path()?;
+        install::install(path, &options).await?;
+    } else if let Some(matches) = matches.subcommand_matches("fetch") {
+        let path = matches
+            .get_one::<String>("path")
+            .map(|p| {
+                let in_path = PathBuf::from(p);
+                if in_path.is_relative() {
+                    cwd.join(in_path)
+                } else {
+                    in_path
+                }
+            })
+            .unwrap_or(cwd);
+        fetch::fetch(&path).await?;
+    } else if let Some(matches) = matches.subcommand_matches("nargo") {
+        let args = matches
+            .get_many::<String>("args")
+            .unwrap_or_default()
+            .cloned()
+            .collect::<Vec<_>>();
+        let code = nargo::run(&cwd, &args).await?;
+        if code != 0 {
+            std::process::exit(code);
+        }
+    } else if let Some((command, matches)) = matches
+        .subcommand()
+        .filter(|(name, _)| matches!(*name, "test" | "check"))
+    {
+        let path = matches
+            .get_one::<String>("path")
+            .map(|p| {
+                let in_path = PathBuf::from(p);
+                if in_path.is_relative() {
+                    cwd.join(in_path)
+                } else {
+                    in_path
+                }
+            })
+            .unwrap_or(cwd);
+        let jobs = matches
+            .get_one::<u64>("jobs")
+            .map(|jobs| *jobs as usize)
+            .unwrap_or_else(|| {
+                std::thread::available_parallelism()
+                    .map(|n| n.get())
+                    .unwrap_or(1)
+            });
+        let args = matches
+            .get_many::<String>("args")
+            .unwrap_or_default()
+            .cloned()
+            .collect::<Vec<_>>();
+        nargo::run_members(&path, command, &args, jobs).await?;
+    } else if let Some(matches) = matches.subcommand_matches("sync") {
+        let path = matches
+            .get_one::<String>("path")
+            .map(|p| {
+                let in_path = PathBuf::from(p);
+                if in_path.is_relative() {
+                    cwd.join(in_path)
+                } else {
+                    in_path
+                }
+            })
+            .unwrap_or(cwd);
+        sync::sync(path)?;
+    } else if let Some(matches) = matches.subcommand_matches("lint") {
+        let path = matches
+            .get_one::<String>("path")
+            .map(|p| {
+                let in_path = PathBuf::from(p);
+                if in_path.is_relative() {
+                    cwd.join(in_path)
+                } else {
+                    in_path
+                }
+            })
+            .unwrap_or(cwd);
+        let json = matches.get_one::<String>("format").map(String::as_str) == Some("json");
+        let diagnostics = lint::lint(&path)?;
+        lint::print(&diagnostics, json)?;
+        lint::check(&diagnostics)?;
+    } else if let Some(matches) = matches.subcommand_matches("licenses") {
+        let path = matches
+            .get_one::<String>("path")
+            .map(|p| {
+                let in_path = PathBuf::from(p);
+                if in_path.is_relative() {
+                    cwd.join(in_path)
+                } else {
+                    in_path
+                }
+            })
+            .unwrap_or(cwd);
+        let json = matches.get_one::<String>("format").map(String::as_str) == Some("json");
+        licenses::print(&licenses::licenses(&path)?, json)?;
+    } else if let Some(matches) = matches.subcommand_matches("outdated") {
+        let path = matches
+            .get_one::<String>("path")
+            .map(|p| {
+                let in_path = PathBuf::from(p);
+                if in_path.is_relative() {
+                    cwd.join(in_path)
+                } else {
+                    in_path
+                }
+            })
+            .unwrap_or(cwd);
+        let json = matches.get_one::<String>("format").map(String::as_str) == Some("json");
+        let outdated = outdated::outdated(&path).await?;
+        outdated::print(&outdated, json)?;
+        // like git diff --exit-code, so CI can fail on outdated dependencies
+        if matches.get_flag("exit_code") && !outdated.is_empty() {
+            std::process::exit(1);
+        }
+    } else if let Some(matches) = matches.subcommand_matches("diff") {
+        diff::diff(
+            &api,
+            matches.get_one::<String>("package").unwrap(),
+            matches.get_one::<String>("from").unwrap(),
+            matches.get_one::<String>("to").unwrap(),
+            matches.get_flag("stat"),
+            matches.get_one::<String>("format").map(String::as_str) == Some("json"),
+        )
+        .await?;
+    } else if let Some(matches) = matches.subcommand_matches("sbom") {
+        let path = matches
+            .get_one::<String>("path")
+            .map(|p| {
+                let in_path = PathBuf::from(p);
+                if in_path.is_relative() {
+                    cwd.join(in_path)
+                } else {
+                    in_path
+                }
+            })
+            .unwrap_or(cwd.clone());
+        let format = matches
+            .get_one::<String>("format")
+            .expect("clap provides a default format");
+        let sbom = sbom::sbom(&api, &path, format).await?;
+        match matches.get_one::<String>("output") {
+            Some(output) => {
+                std::fs::write(cwd.join(output), sbom)?;
+                summary!("📄 Wrote a {format} sbom to {output}");
+            }
+            None => println!("{sbom}"),
+        }
+    } else if let Some(matches) = matches.subcommand_matches("verify") {
+        let path = matches
+            .get_one::<String>("path")
+            .map(|p| {
+                let in_path = PathBuf::from(p);
+                if in_path.is_relative() {
+                    cwd.join(in_path)
+                } else {
+                    in_path
+                }
+            })
+            .unwrap_or(cwd);
+        verify::verify(&api, &path, matches.get_flag("log")).await?;
+    } else if let Some(matches) = matches.subcommand_matches("version") {
+        let path_arg = matches.get_one::<String>("path");
+        let path = path_arg
+            .map(|p| {
+                let in_path = PathBuf::from(p);
+                if in_path.is_relative() {
+                    cwd.join(in_path)
+                } else {
+                    in_path
+                }
+            })
+            .unwrap_or(cwd);
+        let target = matches
+            .get_one::<String>("version")
+            .expect("clap requires a version");
+        version::bump(&path, target, matches.get_flag("commit"))?;
+        match path_arg {
+            Some(p) => say!("📦 Publish it with: nrpm publish --path {p}"),
+            None => say!("📦 Publish it with: nrpm publish"),
+        }
+    } else if let Some(matches) = matches.subcommand_matches("owner") {
+        match matches.subcommand() {
+            Some(("list", matches)) => {
+                owner::list(&api, matches.get_one::<String>("package").unwrap()).await?
+            }
+            Some(("add", matches)) => {
+                owner::add(
+                    &api,
+                    matches.get_one::<String>("user").unwrap(),
+                    matches.get_one::<String>("package").unwrap(),
+                )
+                .await?
+            }
+            Some(("remove", matches)) => {
+                owner::remove(&api, matches.get_one::<String>("package").unwrap()).await?
+            }
+            Some(("accept", matches)) => {
+                owner::accept(&api, matches.get_one::<String>("package").unwrap()).await?
+            }
+            _ => unreachable!("clap requires an owner subcommand"),
+        }
+    } else if let Some(matches) = matches.subcommand_matches("artifact") {
+        match matches.subcommand() {
+            Some(("upload", matches)) => {
+                let path = matches
+                    .get_one::<String>("path")
+                    .map(|p| cwd.join(p))
+                    .unwrap_or(cwd);
+                artifact::upload(
+                    &api,
+                    &path,
+                    matches.get_one::<String>("name").map(String::as_str),
+                )
+                .await?
+            }
+            Some(("download", matches)) => {
+                artifact::download(
+                    &api,
+                    matches.get_one::<String>("package").unwrap(),
+                    matches.get_one::<String>("version").unwrap(),
+                    matches.get_one::<String>("name").map(String::as_str),
+                    matches
+                        .get_one::<String>("output")
+                        .map(|p| cwd.join(p))
+                        .as_deref(),
+                )
+                .await?
+            }
+            _ => unreachable!("clap requires an artifact subcommand"),
+        }
+    } else if let Some(_matches) = matches.subcommand_matches("keygen") {
+        key::keygen(&api).await?;
+    } else if let Some(matches) = matches.subcommand_matches("key") {
+        match matches.subcommand() {
+            Some(("list", matches)) => {
+                key::list(&api, matches.get_one::<String>("user").map(String::as_str)).await?
+            }
+            Some(("rotate", _matches)) => key::rotate(&api).await?,
+            Some(("revoke", matches)) => {
+                key::revoke(
+                    &api,
+                    matches.get_one::<String>("public_key").map(String::as_str),
+                )
+                .await?
+            }
+            _ => unreachable!("clap requires a key subcommand"),
+        }
+    } else if let Some(matches) = matches.subcommand_matches("config") {
+        match matches.subcommand() {
+            Some(("get", matches)) => {
+                let key = matches.get_one::<String>("key").expect("clap requires key");
+                match config::get(key) {
+                    Some((value, _source)) => summary!("{value}"),
+                    None => anyhow::bail!("{key} is unset"),
+                }
+            }
+            Some(("set", matches)) => config::set(
+                matches.get_one::<String>("key").expect("clap requires key"),
+                matches.get_one::<String>("value").map(String::as_str),
+            )?,
+            Some(("unset", matches)) => config::set(
+                matches.get_one::<String>("key").expect("clap requires key"),
+                None,
+            )?,
+            _ => config::list(),
+        }
+    } else if let Some(matches) = matches.subcommand_matches("stats") {
+        match matches.subcommand() {
+            Some(("enable", _matches)) => stats::set_enabled(true)?,
+            Some(("disable", _matches)) => stats::set_enabled(false)?,
+            _ => stats::print_status(),
+        }
+    } else if let Some(matches) = matches.subcommand_matches("cache") {
+        match matches.subcommand() {
+            Some(("gc", matches)) => {
+                let max_age = matches
+                    .get_one::<String>("max_age")
+                    .map(|age| humantime::parse_duration(age))
+                    .transpose()
+                    .context("--max-age must be a duration like 90d")?;
+                let max_size = matches
+                    .get_one::<String>("max_size")
+                    .map(|size| gc::parse_size(size))
+                    .transpose()
+                    .context("--max-size must be a size like 5G")?;
+                gc::gc(&gc::GcOptions {
+                    max_age,
+                    max_size,
+                    dry_run: matches.get_flag("dry_run"),
+                })?
+            }
+            Some(("clean", _matches)) => {
+                let path = cache::cache_path()?;
+                if !dialoguer::Confirm::new()
+                    .with_prompt(format!("Remove contents of {:?}?", path))
+                    .interact()?
+                {
+                    println!("User cancelled the action");
+                    return Ok(());
+                }
 
-        // remove the contents of the system cache
-        std::fs::remove_dir_all(cache_path()?)?;
-        if !dialoguer::Confirm::new()
-            .with_prompt(format!("Remove contents of {:?}?", path))
-            .interact()?
-        {
-            println!("User cancelled the action");
-            return Ok(());
+                // remove the contents of the system cache
+                let _cache_lock = cache::lock_cache_exclusive(&path, &ProgressBar::hidden())?;
+                std::fs::remove_dir_all(&path)?;
+                cache::prune_nargo_links(&path, &cache::nargo_cache_path()?)?;
+            }
+            _ => unreachable!("clap requires a cache subcommand"),
         }
+    } else if let Some(matches) = matches.subcommand_matches("clean") {
+        let path = matches
+            .get_one::<String>("path")
+            .map(|p| {
+                let in_path = PathBuf::from(p);
+                if in_path.is_relative() {
+                    cwd.join(in_path)
+                } else {
+                    in_path
+                }
+            })
+            .unwrap_or(cwd);
+        clean::clean(&path, matches.get_flag("dry_run"))?;
     }
     Ok(())
 }
 
-/// The shared system cache for noir packages. ~/nargo
-///
-/// https://github.com/noir-lang/noir/blob/12e90c0d51fc53998a2b75d6fb302d621227accd/tooling/nargo_toml/src/git.rs#L51
-fn cache_path() -> Result<PathBuf> {
-    // Match the nargo default path.
-    // TODO: make this more configurable
-    let dep_cache_path = dirs::home_dir()
-        .expect("unable to determine user home directory")
-        .join("nargo");
-    if dep_cache_path.exists() && !dep_cache_path.is_dir() {
-        anyhow::bail!(
-            "Global dependency cache is a non-directory! {:?}",
-            dep_cache_path
-        );
-    } else if !dep_cache_path.exists() {
-        std::fs::create_dir(&dep_cache_path)?;
+/// Fail if the registry and this version of nrpm can't work together. A registry that can't
+/// be reached is left for the command itself to report.
+async fn check_registry(api: &OnyxApi) -> Result<()> {
+    match api.check_compatibility().await {
+        Ok(meta) => {
+            log::debug!("registry api version: {}", meta.api_version);
+            Ok(())
+        }
+        Err(e) if e.downcast_ref::<ApiVersionMismatch>().is_some() => Err(e),
+        Err(e) => {
+            log::debug!("failed to check registry api version: {e:?}");
+            Ok(())
+        }
     }
-    Ok(dep_cache_path)
 }
 
 async fn attempt_auth() -> Result<LoginResponse> {
+    let api = registry_api();
+    let registry_url = registry_url();
+    if let Some(login) = credentials::resume(&api, &registry_url).await {
+        return Ok(login);
+    }
+
+    summary!("🔃 Redirecting to authorize");
+    tokio::time::sleep(Duration::from_millis(500)).await;
     let proposed_token = nanoid!();
-    // we'll create a token and open the web browser
-    let url = format!("{REGISTRY_URL}/_/propose_token?token={proposed_token}");
+    let proposed_refresh_token = nanoid!();
+    // we'll create a token and open the web browser. nrpm only publishes and manages
+    // packages, so it asks for a publish scoped token, which lasts longer
+    let url = format!(
+        "{registry_url}/_/propose_token?token={proposed_token}&refresh_token={proposed_refresh_token}&scope=publish"
+    );
     println!("    {url}");
     open::that(url)?;
 
-    let api = OnyxApi::default();
     const MAX_ATTEMPTS: usize = 60;
     let mut attempts = 0;
     loop {
         tokio::time::sleep(Duration::from_millis(1000)).await;
         match api.auth(proposed_token.clone()).await {
-            Ok(login) => return Ok(login),
+            Ok(mut login) => {
+                login.refresh_token = Some(proposed_refresh_token);
+                if let Err(e) = credentials::save(&registry_url, &login) {
+                    log::warn!("failed to save credentials: {e:?}");
+                }
+                return Ok(login);
+            }
             Err(_) => {
                 attempts += 1;
                 if attempts >= MAX_ATTEMPTS {
@@ -171,11 +623,59 @@ async fn attempt_auth() -> Result<LoginResponse> {
     }
 }
 
+/// `nrpm test` or `nrpm check`, see `nargo::run_members`.
+fn workspace_nargo_command(name: &'static str, about: &'static str) -> Command {
+    Command::new(name)
+        .about(about)
+        .arg(
+            Arg::new("path")
+                .short('p')
+                .long("path")
+                .value_name("path")
+                .action(ArgAction::Set)
+                .help("Run in a workspace or package at a path"),
+        )
+        .arg(
+            Arg::new("jobs")
+                .short('j')
+                .long("jobs")
+                .value_name("jobs")
+                .value_parser(clap::value_parser!(u64).range(1..))
+                .action(ArgAction::Set)
+                .help("Most members to run at once, the number of cpus by default"),
+        )
+        .arg(
+            Arg::new("args")
+                .value_name("args")
+                .num_args(0..)
+                .last(true)
+                .allow_hyphen_values(true)
+                .help("Arguments passed to nargo after --"),
+        )
+}
+
 fn cli() -> Command {
     Command::new("nrpm")
         .version(clap::crate_version!())
         .about("Noir package manager")
-        .subcommand(Command::new("clean").about("clear the system package cache directory"))
+        .arg(Arg::new("json").long("json").global(true).action(ArgAction::SetTrue).help("Print errors as json objects of { code, registry_code, message, causes, advice, paths }"))
+        .arg(Arg::new("quiet").short('q').long("quiet").global(true).action(ArgAction::SetTrue).help("Only print errors and the result of the command"))
+        .subcommand(
+            Command::new("clean")
+                .about("remove lockfile backups, vendored packages no longer in nrpm.lock, and archives in target/package")
+                .arg(Arg::new("path").short('p').long("path").value_name("path").action(ArgAction::Set).help("Clean a package or workspace at a path"))
+                .arg(Arg::new("dry_run").long("dry-run").action(ArgAction::SetTrue).help("Print what would be removed without removing it"))
+        )
+        .subcommand(
+            Command::new("cache")
+                .about("manage the system package cache")
+                .subcommand_required(true)
+                .subcommand(Command::new("gc").about("remove the least recently used cached packages that no known nrpm.lock locks")
+                    .arg(Arg::new("max_age").long("max-age").value_name("duration").default_value("90d").action(ArgAction::Set).help("Remove packages not used for longer than this, e.g. 90d"))
+                    .arg(Arg::new("max_size").long("max-size").value_name("size").action(ArgAction::Set).help("Then remove the least recently used packages until the cache is no larger than this, e.g. 5G"))
+                    .arg(Arg::new("dry_run").long("dry-run").action(ArgAction::SetTrue).help("Print what would be removed without removing it")))
+                .subcommand(Command::new("clean").about("clear the system package cache directory"))
+        )
         .subcommand(
             Command::new("publish")
                 .about("publish a package to the registry")
@@ -186,12 +686,134 @@ fn cli() -> Command {
                         .value_name("path")
                         .action(ArgAction::Set).help("Generate a package tarball and save it to local file instead of uploading to registry"),
                 ).arg(Arg::new("path").short('p').long("path").value_name("path").action(ArgAction::Set).help("Publish a package from a custom path"))
+                .arg(Arg::new("git").long("git").value_name("url").action(ArgAction::Set).requires("tag").help("Clone a git repository and publish the package in it, recording the repository and commit as the version's source"))
+                .arg(Arg::new("tag").long("tag").value_name("tag").action(ArgAction::Set).requires("git").help("The tag of the git repository to publish"))
+                .arg(Arg::new("changelog").long("changelog").action(ArgAction::SetTrue).conflicts_with("git").help("Publish release notes listing the git commits since the tag of the previous version"))
+                .arg(Arg::new("yes").short('y').long("yes").action(ArgAction::SetTrue).help("Publish without asking for confirmation"))
         )
         .subcommand(
             Command::new("install")
             .alias("i")
                 .about("install dependencies for a local project")
                 .arg(Arg::new("path").short('p').long("path").value_name("path").action(ArgAction::Set).help("Install dependencies for a package at a path"))
+                .arg(Arg::new("no_interactive").long("no-interactive").action(ArgAction::SetTrue).help("Fail on lockfile mismatches and don't offer updates instead of asking what to do"))
+                .arg(Arg::new("repair").long("repair").action(ArgAction::SetTrue).help("Quarantine cached packages that don't match a lockfile and download them again"))
+                .arg(Arg::new("no_dev").long("no-dev").action(ArgAction::SetTrue).help("Don't install dev-dependencies"))
+                .arg(Arg::new("policy_report").long("policy-report").action(ArgAction::SetTrue).help("Print the dependencies that violate nrpm-policy.toml instead of failing on them"))
+                .arg(Arg::new("yes").short('y').long("yes").action(ArgAction::SetTrue).help("Trust new git dependencies from outside the registries without asking"))
+                .arg(Arg::new("report").long("report").value_name("path").action(ArgAction::Set).help("Write a hash chained json report of every resolved package, where it came from, and its hash"))
+                .arg(Arg::new("registry").long("registry").value_name("name").action(ArgAction::Set).help("Add packages from the registry with this name, and pin them to it"))
                 .arg(Arg::new("package_name").value_name("package_name").action(ArgAction::Append))
         )
+        .subcommand(
+            Command::new("fetch")
+                .about("download and verify the packages in nrpm.lock into the cache, without reading Nargo.toml")
+                .arg(Arg::new("path").short('p').long("path").value_name("path").action(ArgAction::Set).help("Fetch the packages locked by a package at a path"))
+        )
+        .subcommand(
+            Command::new("nargo")
+                .about("install dependencies from nrpm.lock, then run nargo with the given arguments")
+                .disable_help_flag(true)
+                .arg(Arg::new("args").value_name("args").num_args(0..).trailing_var_arg(true).allow_hyphen_values(true).help("Arguments passed to nargo"))
+        )
+        .subcommand(workspace_nargo_command("test", "run nargo test in each workspace member, in dependency order, and report which passed"))
+        .subcommand(workspace_nargo_command("check", "run nargo check in each workspace member, in dependency order, and report which passed"))
+        .subcommand(
+            Command::new("owner")
+                .about("show and change who owns a package")
+                .subcommand_required(true)
+                .subcommand(Command::new("list").about("show the owner of a package and its ownership history").arg(Arg::new("package").value_name("package").required(true)))
+                .subcommand(Command::new("add").about("offer a package you own to another user, who becomes its only owner after accepting").arg(Arg::new("user").value_name("user").required(true)).arg(Arg::new("package").value_name("package").required(true)))
+                .subcommand(Command::new("remove").about("cancel a pending transfer you offered, or decline one offered to you").arg(Arg::new("package").value_name("package").required(true)))
+                .subcommand(Command::new("accept").about("accept a package offered to you").arg(Arg::new("package").value_name("package").required(true)))
+        )
+        .subcommand(
+            Command::new("artifact")
+                .about("attach compiled artifacts to published versions and download them")
+                .subcommand_required(true)
+                .subcommand(Command::new("upload").about("attach the json nargo compile wrote to target/ to the published version of a package")
+                    .arg(Arg::new("path").short('p').long("path").value_name("path").action(ArgAction::Set).help("Upload the artifact of a package at a path"))
+                    .arg(Arg::new("name").long("name").value_name("name").action(ArgAction::Set).help("Name the artifact, the package name by default")))
+                .subcommand(Command::new("download").about("download an artifact attached to a version")
+                    .arg(Arg::new("package").value_name("package").required(true))
+                    .arg(Arg::new("version").value_name("version").required(true))
+                    .arg(Arg::new("name").long("name").value_name("name").action(ArgAction::Set).help("The artifact to download, required if the version has several"))
+                    .arg(Arg::new("output").short('o').long("output").value_name("path").action(ArgAction::Set).help("Write the artifact to a file instead of printing it")))
+        )
+        .subcommand(Command::new("keygen").about("generate a signing key, encrypted with a passphrase, and add it to your account"))
+        .subcommand(
+            Command::new("key")
+                .about("show and manage signing keys")
+                .subcommand_required(true)
+                .subcommand(Command::new("list").about("show the signing keys of a user, including rotated and revoked keys").arg(Arg::new("user").value_name("user")))
+                .subcommand(Command::new("rotate").about("replace your signing key with a new one signed by the old one"))
+                .subcommand(Command::new("revoke").about("revoke a compromised signing key, nothing it signed is trusted afterwards").arg(Arg::new("public_key").value_name("public_key")))
+        )
+        .subcommand(
+            Command::new("config")
+                .about("show and change the settings in the nrpm config file, and where each value comes from")
+                .subcommand(Command::new("list").about("show every setting, its value, and whether it's from the environment, the config file, or the default"))
+                .subcommand(Command::new("get").about("print the value of a setting").arg(Arg::new("key").value_name("key").required(true)))
+                .subcommand(Command::new("set").about("check a value and write it to the config file").arg(Arg::new("key").value_name("key").required(true)).arg(Arg::new("value").value_name("value").required(true)))
+                .subcommand(Command::new("unset").about("remove a setting from the config file").arg(Arg::new("key").value_name("key").required(true)))
+        )
+        .subcommand(
+            Command::new("stats")
+                .about("show or change whether installs are reported to the registry's download counts, off by default")
+                .subcommand(Command::new("enable").about("report the name and version of registry packages installed without downloading them from the registry"))
+                .subcommand(Command::new("disable").about("stop reporting installs"))
+        )
+        .subcommand(
+            Command::new("lint")
+                .about("check Nargo.toml and the packaged files for problems before publishing")
+                .arg(Arg::new("path").short('p').long("path").value_name("path").action(ArgAction::Set).help("Lint a package at a path"))
+                .arg(Arg::new("format").long("format").value_name("format").value_parser(["human", "json"]).default_value("human").action(ArgAction::Set).help("Print diagnostics for people, or as a json array"))
+        )
+        .subcommand(
+            Command::new("licenses")
+                .about("show the license of every package in nrpm.lock, for compliance review")
+                .arg(Arg::new("path").short('p').long("path").value_name("path").action(ArgAction::Set).help("Show licenses for a package at a path"))
+                .arg(Arg::new("format").long("format").value_name("format").value_parser(["human", "json"]).default_value("human").action(ArgAction::Set).help("Print licenses for people, or as a json array"))
+        )
+        .subcommand(
+            Command::new("outdated")
+                .about("show the registry packages in nrpm.lock that have newer versions, and the newest compatible and latest versions")
+                .arg(Arg::new("path").short('p').long("path").value_name("path").action(ArgAction::Set).help("Check dependencies of a package at a path"))
+                .arg(Arg::new("format").long("format").value_name("format").value_parser(["human", "json"]).default_value("human").action(ArgAction::Set).help("Print outdated packages for people, or as a json array"))
+                .arg(Arg::new("exit_code").long("exit-code").action(ArgAction::SetTrue).help("Exit with status 1 if any package is outdated or yanked"))
+        )
+        .subcommand(
+            Command::new("diff")
+                .about("show what changed between two published versions of a package")
+                .arg(Arg::new("package").value_name("package").required(true))
+                .arg(Arg::new("from").value_name("from").required(true).help("The version to compare from"))
+                .arg(Arg::new("to").value_name("to").required(true).help("The version to compare to"))
+                .arg(Arg::new("stat").long("stat").action(ArgAction::SetTrue).help("Only list the changed files"))
+                .arg(Arg::new("format").long("format").value_name("format").value_parser(["human", "json"]).default_value("human").action(ArgAction::Set).help("Print the changes for people, or as a json object"))
+        )
+        .subcommand(
+            Command::new("sbom")
+                .about("export a software bill of materials for the dependencies in nrpm.lock")
+                .arg(Arg::new("path").short('p').long("path").value_name("path").action(ArgAction::Set).help("Export an sbom for a package at a path"))
+                .arg(Arg::new("format").long("format").value_name("format").value_parser(["cyclonedx", "spdx"]).default_value("cyclonedx").action(ArgAction::Set).help("Export CycloneDX 1.5 or SPDX 2.3 json"))
+                .arg(Arg::new("output").short('o').long("output").value_name("path").action(ArgAction::Set).help("Write the sbom to a file instead of printing it"))
+        )
+        .subcommand(
+            Command::new("verify")
+                .about("check the registry packages in nrpm.lock are still published as locked")
+                .arg(Arg::new("path").short('p').long("path").value_name("path").action(ArgAction::Set).help("Verify dependencies of a package at a path"))
+                .arg(Arg::new("log").long("log").action(ArgAction::SetTrue).help("Also check each package is in the registry's transparency log"))
+        )
+        .subcommand(
+            Command::new("version")
+                .about("bump the version in Nargo.toml")
+                .arg(Arg::new("version").value_name("major|minor|patch|x.y.z").required(true).help("The part of the version to increment, or the new version"))
+                .arg(Arg::new("path").short('p').long("path").value_name("path").action(ArgAction::Set).help("Bump the version of a package at a path"))
+                .arg(Arg::new("commit").long("commit").action(ArgAction::SetTrue).help("Commit the change to Nargo.toml and tag the commit vX.Y.Z"))
+        )
+        .subcommand(
+            Command::new("sync")
+                .about("add and retag Nargo.toml dependencies to match nrpm.lock, downloading locked packages")
+                .arg(Arg::new("path").short('p').long("path").value_name("path").action(ArgAction::Set).help("Sync dependencies for a package at a path"))
+        )
 }
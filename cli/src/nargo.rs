@@ -0,0 +1,190 @@
+use std::path::Path;
+use std::path::PathBuf;
+use std::time::Instant;
+
+use anyhow::Context;
+use anyhow::Result;
+use indicatif::ProgressBar;
+use nargo_parse::Workspace;
+use tokio::task::JoinSet;
+
+use crate::cache;
+use crate::config;
+use crate::failure::Failure;
+use crate::failure::FailureCode;
+use crate::install;
+use crate::install::InstallOptions;
+use crate::output::summary;
+use crate::workspace;
+
+/// The nargo binary to run, the `nargo` setting.
+fn nargo_bin() -> String {
+    config::value("nargo").unwrap_or("nargo".to_string())
+}
+
+/// The package nargo runs on: the `--program-dir` in `args`, or `cwd`.
+fn program_dir(cwd: &Path, args: &[String]) -> PathBuf {
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        let dir = match arg.strip_prefix("--program-dir") {
+            Some("") => args.next().map(String::as_str),
+            Some(dir) => dir.strip_prefix('='),
+            None => None,
+        };
+        if let Some(dir) = dir {
+            return cwd.join(dir);
+        }
+    }
+    cwd.to_path_buf()
+}
+
+/// Install the dependencies of the package nargo runs on, failing on lockfile mismatches,
+/// then run nargo with `args`. nargo reads git dependencies through the links in ~/nargo to
+/// the nrpm cache they were verified in, and a shared lock on the cache is held until nargo
+/// exits so it isn't cleaned or repaired underneath it. Returns the exit code of nargo.
+pub async fn run(cwd: &Path, args: &[String]) -> Result<i32> {
+    let path = program_dir(cwd, args);
+    // commands like `nargo new` don't run on an existing package
+    if path.join("Nargo.toml").exists() {
+        install::install(path, &InstallOptions::default())
+            .await
+            .context("Failed to install dependencies before running nargo")?;
+    }
+
+    let dep_cache_path = cache::cache_path()?;
+    let _cache_lock = cache::lock_cache(&dep_cache_path, &ProgressBar::hidden())?;
+    let nargo = nargo_bin();
+    let status = std::process::Command::new(&nargo)
+        .args(args)
+        .current_dir(cwd)
+        .status()
+        .context(
+            Failure::new(FailureCode::NargoNotFound, format!("Failed to run {nargo}")).with_advice(
+                "Install nargo with noirup, or set its path with nrpm config set nargo <path>.",
+            ),
+        )?;
+    Ok(status.code().unwrap_or(1))
+}
+
+/// How a workspace member fared in `run_members`.
+enum Outcome {
+    Passed,
+    /// The output of nargo.
+    Failed(String),
+    /// A member it depends on didn't pass.
+    Skipped,
+}
+
+/// Run `nargo <command> <args>` in each member of the workspace at `root`, or in the package
+/// at `root` if it isn't a workspace. Every member is installed first, then up to `jobs`
+/// members run at once, each starting when the members it depends on have passed. Members
+/// depending on one that failed are skipped. Fails if any member didn't pass.
+pub async fn run_members(root: &Path, command: &str, args: &[String], jobs: usize) -> Result<()> {
+    let Some(workspace) = Workspace::load(root)? else {
+        let args = [&[command.to_string()], args].concat();
+        let code = run(root, &args).await?;
+        if code != 0 {
+            std::process::exit(code);
+        }
+        return Ok(());
+    };
+    let members = workspace::publish_order(root, &workspace)?;
+    if members.is_empty() {
+        anyhow::bail!("Workspace has no members");
+    }
+    for member in &members {
+        install::install(member.dir.clone(), &InstallOptions::default())
+            .await
+            .with_context(|| format!("Failed to install dependencies of {}", member.name()))?;
+    }
+
+    let dep_cache_path = cache::cache_path()?;
+    let _cache_lock = cache::lock_cache(&dep_cache_path, &ProgressBar::hidden())?;
+    let nargo = nargo_bin();
+    let mut outcomes: Vec<Option<Outcome>> = members.iter().map(|_| None).collect();
+    let mut started = vec![false; members.len()];
+    let mut running = JoinSet::new();
+    loop {
+        // members are in dependency order, so a skip is seen by the members after it
+        for i in 0..members.len() {
+            if started[i] {
+                continue;
+            }
+            let mut ready = true;
+            let mut failed_dependency = None;
+            for j in members[i].member_dependencies() {
+                match &outcomes[j] {
+                    Some(Outcome::Passed) => {}
+                    Some(_) => failed_dependency = Some(members[j].name().to_string()),
+                    None => ready = false,
+                }
+            }
+            if let Some(dependency) = failed_dependency {
+                started[i] = true;
+                summary!(
+                    "⏭️  {} skipped, {dependency} did not pass",
+                    members[i].name()
+                );
+                outcomes[i] = Some(Outcome::Skipped);
+            } else if ready && running.len() < jobs {
+                started[i] = true;
+                let mut cmd = tokio::process::Command::new(&nargo);
+                cmd.arg(command).args(args).current_dir(&members[i].dir);
+                running.spawn(async move {
+                    let start = Instant::now();
+                    (i, cmd.output().await, start.elapsed())
+                });
+            }
+        }
+        let Some(result) = running.join_next().await else {
+            break;
+        };
+        let (i, output, elapsed) = result?;
+        let output = output.context(
+            Failure::new(FailureCode::NargoNotFound, format!("Failed to run {nargo}")).with_advice(
+                "Install nargo with noirup, or set its path with nrpm config set nargo <path>.",
+            ),
+        )?;
+        let name = members[i].name();
+        if output.status.success() {
+            summary!("✅ {name} passed ({:.1}s)", elapsed.as_secs_f64());
+            outcomes[i] = Some(Outcome::Passed);
+        } else {
+            let log = format!(
+                "{}{}",
+                String::from_utf8_lossy(&output.stdout),
+                String::from_utf8_lossy(&output.stderr)
+            );
+            summary!("❌ {name} failed ({:.1}s)", elapsed.as_secs_f64());
+            outcomes[i] = Some(Outcome::Failed(log));
+        }
+    }
+
+    let mut passed = 0;
+    let mut failed = 0;
+    let mut skipped = 0;
+    for (member, outcome) in members.iter().zip(&outcomes) {
+        match outcome {
+            Some(Outcome::Passed) => passed += 1,
+            Some(Outcome::Failed(log)) => {
+                failed += 1;
+                summary!(
+                    "\n--- nargo {command} in {} ---\n{}",
+                    member.name(),
+                    log.trim_end()
+                );
+            }
+            Some(Outcome::Skipped) => skipped += 1,
+            None => unreachable!("every member is run or skipped"),
+        }
+    }
+    summary!("{passed} passed, {failed} failed, {skipped} skipped");
+    if failed + skipped > 0 {
+        anyhow::bail!(
+            "{} of {} workspace members did not pass nargo {command}",
+            failed + skipped,
+            members.len()
+        );
+    }
+    Ok(())
+}
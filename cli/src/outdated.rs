@@ -0,0 +1,176 @@
+use std::collections::BTreeMap;
+use std::collections::BTreeSet;
+use std::path::Path;
+
+use anyhow::Context;
+use anyhow::Result;
+use nargo_parse::*;
+use nrpm_resolver::newest;
+use semver::Version;
+use semver::VersionReq;
+use serde::Serialize;
+
+use crate::credentials;
+use crate::index;
+use crate::lockfile::Lockfile;
+use crate::registry;
+use crate::registry::Registry;
+
+/// A registry package in nrpm.lock that isn't at its latest version.
+#[derive(Serialize)]
+pub struct OutdatedPackage {
+    pub name: String,
+    pub registry: String,
+    /// Whether the Nargo.toml depends on the package itself, instead of through another
+    /// package.
+    pub direct: bool,
+    /// The locked version.
+    pub current: String,
+    /// Whether the locked version is yanked.
+    pub yanked: bool,
+    /// The newest version semver compatible with `current`, e.g. `1.2.3` for `^1.2.0`.
+    pub compatible: Option<String>,
+    /// The newest version.
+    pub latest: Option<String>,
+    /// Whether the user watches the package on its registry, so they're notified of its new
+    /// versions.
+    pub watched: bool,
+}
+
+/// The registry packages in the nrpm.lock of the package at `path` that are yanked or have
+/// newer versions that aren't, sorted by name. Packages that aren't in a configured registry
+/// are skipped.
+pub async fn outdated(path: &Path) -> Result<Vec<OutdatedPackage>> {
+    let lockfile_path = path.join("nrpm.lock");
+    if !lockfile_path.exists() {
+        anyhow::bail!("No nrpm.lock found in {path:?}, run nrpm install to create one");
+    }
+    let lockfile = Lockfile::load_or_init(&lockfile_path)?;
+    let config = NargoConfig::load(path)?;
+    let direct = config
+        .dependencies()?
+        .values()
+        .chain(config.dev_dependencies()?.values())
+        .filter_map(|dep| dep.identifier().ok())
+        .collect::<BTreeSet<_>>();
+
+    let mut packages = vec![];
+    for entry in lockfile.entries() {
+        if let Some(package) = registry::of_git(&entry.git, entry.registry.as_deref())? {
+            packages.push((entry, package));
+        }
+    }
+    registry::prefetch(packages.iter().map(|(_, package)| package.clone())).await;
+
+    let mut watches = BTreeMap::new();
+    let mut outdated = vec![];
+    for (entry, (registry, package_name)) in packages {
+        if !watches.contains_key(&registry.name) {
+            watches.insert(registry.name.clone(), watched_packages(&registry).await);
+        }
+        let package = index::load(&registry.api(), &package_name)
+            .await
+            .context(format!("Unable to load package \"{package_name}\""))?;
+        let locked = package.position(&entry.tag).map(|i| &package.versions[i]);
+        let yanked = locked.is_some_and(|v| v.yanked);
+        // yanked versions are never suggested
+        let versions = package
+            .versions
+            .iter()
+            .filter(|v| !v.yanked)
+            .map(|v| v.name.as_str())
+            .collect::<Vec<_>>();
+        let current = Version::parse(locked.map_or(&entry.tag, |v| &v.name)).ok();
+        let latest = newest(versions.iter().copied(), &VersionReq::STAR);
+        let compatible = current.as_ref().and_then(|current| {
+            newest(
+                versions.iter().copied(),
+                &nrpm_resolver::compatible(current),
+            )
+        });
+        let has_newer = match (&latest, &current) {
+            (Some(latest), Some(current)) => latest > current,
+            (latest, None) => latest.is_some(),
+            (None, _) => false,
+        };
+        if !yanked && !has_newer {
+            continue;
+        }
+        let watched = watches[&registry.name].contains(&package.name);
+        outdated.push(OutdatedPackage {
+            name: package.name,
+            registry: registry.name,
+            direct: direct.contains(&entry.identifier()),
+            current: entry.tag.clone(),
+            yanked,
+            compatible: compatible.map(|v| v.to_string()),
+            latest: latest.map(|v| v.to_string()),
+            watched,
+        });
+    }
+    outdated.sort_by(|a, b| (&a.name, &a.current).cmp(&(&b.name, &b.current)));
+    Ok(outdated)
+}
+
+/// Names of the packages the user watches on `registry`, none if they aren't logged in to it.
+async fn watched_packages(registry: &Registry) -> BTreeSet<String> {
+    let Some(login) = credentials::load(&registry.url) else {
+        return BTreeSet::new();
+    };
+    registry
+        .api()
+        .watches(&login.token)
+        .await
+        .inspect_err(|e| log::debug!("failed to load watched packages: {e:?}"))
+        .map(|watches| watches.into_iter().collect())
+        .unwrap_or_default()
+}
+
+/// Print `outdated` for people as a table, or as a json array if `json`.
+pub fn print(outdated: &[OutdatedPackage], json: bool) -> Result<()> {
+    if json {
+        println!("{}", serde_json::to_string_pretty(outdated)?);
+        return Ok(());
+    }
+    if outdated.is_empty() {
+        println!("All registry dependencies are up to date");
+        return Ok(());
+    }
+    let rows = outdated
+        .iter()
+        .map(|p| {
+            [
+                if p.watched {
+                    format!("{} (watched)", p.name)
+                } else {
+                    p.name.clone()
+                },
+                if p.yanked {
+                    format!("{} (yanked)", p.current)
+                } else {
+                    p.current.clone()
+                },
+                p.compatible.clone().unwrap_or("-".to_string()),
+                p.latest.clone().unwrap_or("-".to_string()),
+                if p.direct { "direct" } else { "transitive" }.to_string(),
+            ]
+        })
+        .collect::<Vec<_>>();
+    let header = ["name", "current", "compatible", "latest", "kind"].map(str::to_string);
+    let mut widths = [0; 5];
+    for row in std::iter::once(&header).chain(&rows) {
+        for (width, cell) in widths.iter_mut().zip(row) {
+            *width = (*width).max(cell.len());
+        }
+    }
+    for row in std::iter::once(&header).chain(&rows) {
+        let line = row
+            .iter()
+            .zip(widths)
+            .map(|(cell, width)| format!("{cell:width$}"))
+            .collect::<Vec<_>>()
+            .join("  ");
+        println!("{}", line.trim_end());
+    }
+    Ok(())
+}
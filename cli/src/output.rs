@@ -0,0 +1,153 @@
+use std::io::IsTerminal;
+use std::sync::atomic::AtomicBool;
+use std::sync::atomic::Ordering;
+use std::time::Duration;
+
+use indicatif::MultiProgress;
+use indicatif::ProgressBar;
+use indicatif::ProgressDrawTarget;
+use indicatif::ProgressStyle;
+
+use crate::config;
+
+static QUIET: AtomicBool = AtomicBool::new(false);
+static PLAIN: AtomicBool = AtomicBool::new(false);
+static JSON: AtomicBool = AtomicBool::new(false);
+
+/// Configure output for this run. With `quiet` only errors and the final summary of a command
+/// are printed, with `json` errors are printed as json. Progress is only drawn when stdout is a
+/// terminal, otherwise each step is printed on its own line so logs stay readable.
+pub fn init(quiet: bool, json: bool) {
+    QUIET.store(quiet, Ordering::Relaxed);
+    JSON.store(json, Ordering::Relaxed);
+    PLAIN.store(!std::io::stdout().is_terminal(), Ordering::Relaxed);
+}
+
+pub fn is_quiet() -> bool {
+    QUIET.load(Ordering::Relaxed)
+}
+
+/// Whether errors are printed as json, see `failure::report`.
+pub fn is_json() -> bool {
+    JSON.load(Ordering::Relaxed)
+}
+
+/// Whether progress bars are drawn.
+fn is_drawn() -> bool {
+    !is_quiet() && !PLAIN.load(Ordering::Relaxed)
+}
+
+/// Whether lines start with an emoji, turn on the `no_emoji` setting to leave them out.
+fn emoji() -> bool {
+    !config::flag("no_emoji")
+}
+
+/// `emoji`, or `plain` if emoji are left out.
+pub fn icon(emoji: &'static str, plain: &'static str) -> &'static str {
+    if self::emoji() { emoji } else { plain }
+}
+
+/// `text` without the emoji each line starts with if emoji are left out.
+pub fn decorate(text: &str) -> String {
+    if emoji() {
+        return text.to_string();
+    }
+    text.split('\n')
+        .map(|line| {
+            line.trim_start_matches(|c: char| !c.is_ascii() && !c.is_alphanumeric())
+                .trim_start()
+        })
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+/// Print a line of progress or detail. Nothing is printed with `--quiet`.
+pub fn print_detail(line: String) {
+    if !is_quiet() {
+        println!("{}", decorate(&line));
+    }
+}
+
+/// Print the result of a command, or a warning about it. Printed even with `--quiet`.
+pub fn print_summary(line: String) {
+    println!("{}", decorate(&line));
+}
+
+/// `println!` for progress and details, see `print_detail`.
+macro_rules! say {
+    ($($arg:tt)*) => {
+        $crate::output::print_detail(format!($($arg)*))
+    };
+}
+
+/// `println!` for the result of a command, see `print_summary`.
+macro_rules! summary {
+    ($($arg:tt)*) => {
+        $crate::output::print_summary(format!($($arg)*))
+    };
+}
+
+pub(crate) use say;
+pub(crate) use summary;
+
+/// A `MultiProgress` that's only drawn when progress is, see `init`.
+pub fn multiprogress() -> MultiProgress {
+    if is_drawn() {
+        MultiProgress::new()
+    } else {
+        MultiProgress::with_draw_target(ProgressDrawTarget::hidden())
+    }
+}
+
+/// A spinner for the current step of a command in `multiprogress`.
+pub fn spinner(multiprogress: &MultiProgress) -> ProgressBar {
+    let progress = multiprogress.add(ProgressBar::new_spinner());
+    if is_drawn() {
+        progress.enable_steady_tick(Duration::from_millis(50));
+    }
+    progress
+}
+
+/// Print a finished step above `progress`, or on its own line if progress isn't drawn. Nothing
+/// is printed with `--quiet`.
+pub fn step(multiprogress: &MultiProgress, progress: &ProgressBar, line: String) {
+    if is_drawn() {
+        multiprogress.insert_before(progress, finished_line(decorate(&line)));
+    } else {
+        print_detail(line);
+    }
+}
+
+/// Print a line above `progress` while it's drawn, or on its own line if it isn't. Nothing is
+/// printed with `--quiet`.
+pub fn note(progress: &ProgressBar, line: String) {
+    if is_drawn() {
+        progress.println(decorate(&line));
+    } else {
+        print_detail(line);
+    }
+}
+
+/// Print a line in place of progress that isn't drawn, e.g. a download starting. Nothing is
+/// printed while progress is drawn or with `--quiet`.
+pub fn plain(line: String) {
+    if !is_drawn() {
+        print_detail(line);
+    }
+}
+
+/// Print the final summary of a command above `progress`, see `print_summary`.
+pub fn finish(multiprogress: &MultiProgress, progress: &ProgressBar, line: String) {
+    if is_drawn() {
+        multiprogress.insert_before(progress, finished_line(decorate(&line)));
+    } else {
+        print_summary(line);
+    }
+}
+
+fn finished_line(line: String) -> ProgressBar {
+    ProgressBar::new(0)
+        .with_prefix(line)
+        .with_style(ProgressStyle::with_template("{prefix}").expect("template is valid"))
+        .with_finish(indicatif::ProgressFinish::Abandon)
+}
@@ -0,0 +1,94 @@
+use anyhow::Context;
+use anyhow::Result;
+use onyx_api::prelude::*;
+
+use super::credentials;
+use super::output::summary;
+use super::registry_url;
+
+/// Show the owner of `package_name`, how ownership changed over time, and any pending
+/// transfer the logged in user is part of.
+pub async fn list(api: &OnyxApi, package_name: &str) -> Result<()> {
+    let (package, _) = api
+        .load_package_versions(package_name)
+        .await
+        .context(format!("Unable to load package \"{package_name}\""))?;
+    let owner = package
+        .ownership_history
+        .last()
+        .map(|transfer| transfer.to_username.clone())
+        .unwrap_or(format!("user id {}", package.author_id));
+    summary!("👤 {package_name} is owned by {owner}");
+    for transfer in &package.ownership_history {
+        let by = transfer
+            .admin_username
+            .as_ref()
+            .map(|admin| format!(" by admin {admin}"))
+            .unwrap_or_default();
+        summary!(
+            "    {} -> {}{by} at {}",
+            transfer.from_username,
+            transfer.to_username,
+            transfer.transferred_at
+        );
+        if let Some(reason) = &transfer.reason {
+            summary!("        {reason}");
+        }
+    }
+
+    // pending transfers are only visible to the users involved
+    let Some(login) = credentials::resume(api, &registry_url()).await else {
+        return Ok(());
+    };
+    let transfers = api.transfers(&login.token).await?;
+    let pending = transfers
+        .incoming
+        .iter()
+        .chain(transfers.outgoing.iter())
+        .find(|transfer| transfer.package_name == package_name);
+    if let Some(transfer) = pending {
+        summary!(
+            "⏳ pending transfer from {} to {}",
+            transfer.from_username,
+            transfer.to_username
+        );
+    }
+    Ok(())
+}
+
+/// Offer `package_name` to `username`. Packages have a single owner, so the recipient
+/// replaces the current owner once they accept.
+pub async fn add(api: &OnyxApi, username: &str, package_name: &str) -> Result<()> {
+    let login = super::attempt_auth().await?;
+    api.request_transfer(&login.token, package_name, username)
+        .await
+        .context(format!(
+            "Unable to offer \"{package_name}\" to \"{username}\""
+        ))?;
+    summary!("📨 Offered \"{package_name}\" to {username}");
+    summary!("    They become the owner after running: nrpm owner accept {package_name}");
+    Ok(())
+}
+
+/// Cancel a pending transfer of `package_name` offered by the logged in user, or decline
+/// one offered to them.
+pub async fn remove(api: &OnyxApi, package_name: &str) -> Result<()> {
+    let login = super::attempt_auth().await?;
+    api.cancel_transfer(&login.token, package_name)
+        .await
+        .context(format!(
+            "Unable to cancel the transfer of \"{package_name}\""
+        ))?;
+    summary!("🚫 Cancelled the pending transfer of \"{package_name}\"");
+    Ok(())
+}
+
+/// Accept `package_name` offered to the logged in user.
+pub async fn accept(api: &OnyxApi, package_name: &str) -> Result<()> {
+    let login = super::attempt_auth().await?;
+    api.accept_transfer(&login.token, package_name)
+        .await
+        .context(format!("Unable to accept \"{package_name}\""))?;
+    summary!("✅ You now own \"{package_name}\"");
+    Ok(())
+}
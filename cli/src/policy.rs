@@ -0,0 +1,221 @@
+use std::collections::BTreeMap;
+use std::collections::HashMap;
+use std::path::Path;
+use std::path::PathBuf;
+
+use anyhow::Context;
+use anyhow::Result;
+use indicatif::MultiProgress;
+use indicatif::ProgressBar;
+use nargo_parse::*;
+use nrpm_resolver::Graph;
+use semver::Version;
+use serde::Deserialize;
+use serde::Serialize;
+
+use crate::config;
+use crate::failure::Failure;
+use crate::failure::FailureCode;
+use crate::output;
+
+/// Name of the policy file, looked for in the package being installed and the directories
+/// above it.
+pub const POLICY_FILENAME: &str = "nrpm-policy.toml";
+
+/// Rules every package in a dependency tree must follow, from `nrpm-policy.toml`.
+#[derive(Clone, Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Policy {
+    /// Packages that may not be installed, `name` for every version or `name@version`.
+    #[serde(default)]
+    pub deny: Vec<String>,
+    /// The oldest version of a package that may be installed, keyed by package name.
+    #[serde(default)]
+    pub minimum_versions: BTreeMap<String, String>,
+    /// Only allow git dependencies from the registry. Path dependencies are allowed.
+    #[serde(default)]
+    pub registry_only: bool,
+    /// The deepest a package may be below the package being installed, direct dependencies
+    /// are at depth 1.
+    pub max_depth: Option<usize>,
+}
+
+/// The rule a package broke, see `Violation`.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Rule {
+    Deny,
+    MinimumVersion,
+    RegistryOnly,
+    MaxDepth,
+}
+
+/// A package in the dependency tree that breaks the policy.
+#[derive(Clone, Debug, Serialize)]
+pub struct Violation {
+    pub rule: Rule,
+    pub package_name: String,
+    /// See `Dependency::identifier`.
+    pub identifier: String,
+    pub message: String,
+}
+
+impl Policy {
+    /// The policy at the `policy` setting if set, otherwise the closest `nrpm-policy.toml` in `path`
+    /// or a directory above it. `None` if there isn't one.
+    pub fn find(path: &Path) -> Result<Option<(PathBuf, Self)>> {
+        let policy_path = match config::value("policy") {
+            Some(policy_path) => Some(PathBuf::from(policy_path)),
+            None => path
+                .ancestors()
+                .map(|dir| dir.join(POLICY_FILENAME))
+                .find(|policy_path| policy_path.is_file()),
+        };
+        let Some(policy_path) = policy_path else {
+            return Ok(None);
+        };
+        let policy = Self::load(&policy_path)
+            .with_context(|| format!("Failed to load policy at {policy_path:?}"))?;
+        Ok(Some((policy_path, policy)))
+    }
+
+    fn load(policy_path: &Path) -> Result<Self> {
+        let policy: Self = toml::from_str(&std::fs::read_to_string(policy_path)?)?;
+        for (name, version) in &policy.minimum_versions {
+            Version::parse(version).with_context(|| {
+                format!("minimum version of \"{name}\" is not a semver version: {version}")
+            })?;
+        }
+        Ok(policy)
+    }
+
+    /// Every way the packages in `all_dependencies`, keyed by identifier, break the policy.
+    /// Packages are checked in identifier order.
+    pub fn check(
+        &self,
+        all_dependencies: &HashMap<String, (PathBuf, Dependency, NargoConfig)>,
+        graph: &Graph,
+        registry_url: &str,
+    ) -> Vec<Violation> {
+        let registry_prefix = format!("{registry_url}/");
+        let depths = graph.depths();
+        let mut identifiers = all_dependencies.keys().collect::<Vec<_>>();
+        identifiers.sort();
+
+        let mut violations = vec![];
+        for identifier in identifiers {
+            let (_, dep, config) = &all_dependencies[identifier];
+            let name = &config.package.name;
+            let version = config.package.version.as_deref();
+            let mut violation = |rule, message: String| {
+                violations.push(Violation {
+                    rule,
+                    package_name: name.clone(),
+                    identifier: identifier.clone(),
+                    message,
+                })
+            };
+
+            let denied = self.deny.iter().any(|entry| match entry.split_once('@') {
+                Some((denied_name, denied_version)) => {
+                    denied_name == name && Some(denied_version) == version
+                }
+                None => entry == name,
+            });
+            if denied {
+                let version = version.map(|v| format!(" {v}")).unwrap_or_default();
+                violation(Rule::Deny, format!("\"{name}\"{version} is denied"));
+            }
+            if let Some(minimum) = self.minimum_versions.get(name) {
+                let minimum =
+                    Version::parse(minimum).expect("minimum versions are checked on load");
+                match version.map(|v| Version::parse(v.trim_start_matches('v'))) {
+                    Some(Ok(version)) if version >= minimum => {}
+                    Some(Ok(version)) => violation(
+                        Rule::MinimumVersion,
+                        format!("\"{name}\" {version} is older than the minimum version {minimum}"),
+                    ),
+                    _ => violation(
+                        Rule::MinimumVersion,
+                        format!(
+                            "\"{name}\" has no semver version to compare with the minimum version {minimum}"
+                        ),
+                    ),
+                }
+            }
+            if self.registry_only
+                && let Some(git) = &dep.git
+                && !git.starts_with(&registry_prefix)
+            {
+                violation(
+                    Rule::RegistryOnly,
+                    format!("\"{name}\" is a git dependency outside the registry: {git}"),
+                );
+            }
+            if let Some(max_depth) = self.max_depth
+                && let Some(depth) = depths.get(identifier)
+                && *depth > max_depth
+            {
+                violation(
+                    Rule::MaxDepth,
+                    format!(
+                        "\"{name}\" is at depth {depth}, deeper than the maximum of {max_depth}"
+                    ),
+                );
+            }
+        }
+        violations
+    }
+}
+
+/// Fail with every violation of the policy at `policy_path`, each as a cause of the failure.
+pub fn enforce(policy_path: &Path, violations: &[Violation]) -> Result<()> {
+    let Some((last, rest)) = violations.split_last() else {
+        return Ok(());
+    };
+    let mut err = anyhow::anyhow!(last.message.clone());
+    for violation in rest.iter().rev() {
+        err = err.context(violation.message.clone());
+    }
+    Err(err.context(
+        Failure::new(
+            FailureCode::PolicyViolation,
+            format!(
+                "{} policy violation{}, halting",
+                violations.len(),
+                if violations.len() == 1 { "" } else { "s" }
+            ),
+        )
+        .with_advice("Change the dependencies that break the policy. Run install with --policy-report to list violations without failing.")
+        .with_path(policy_path),
+    ))
+}
+
+/// Print every violation of the policy at `policy_path` above `progress` instead of failing.
+pub fn report(
+    multiprogress: &MultiProgress,
+    progress: &ProgressBar,
+    policy_path: &Path,
+    violations: &[Violation],
+) {
+    let policy_path =
+        pathdiff::diff_paths(policy_path, std::env::current_dir().unwrap_or_default())
+            .unwrap_or(policy_path.to_path_buf());
+    for violation in violations {
+        output::finish(
+            multiprogress,
+            progress,
+            format!("⚠️  policy: {}", violation.message),
+        );
+    }
+    output::finish(
+        multiprogress,
+        progress,
+        format!(
+            "📜 {} violation{} of {}",
+            violations.len(),
+            if violations.len() == 1 { "" } else { "s" },
+            policy_path.display()
+        ),
+    );
+}
@@ -1,3 +1,4 @@
+use std::collections::HashMap;
 use std::fs::File;
 use std::io::Read;
 use std::io::Seek;
@@ -7,16 +8,96 @@ use std::time::Duration;
 
 use anyhow::Context;
 use anyhow::Result;
+use nanoid::nanoid;
 use onyx_api::prelude::*;
 use tempfile::tempfile;
 
 use nargo_parse::*;
 
-pub async fn upload_tarball(
-    api: &OnyxApi,
-    pkg_dir: &Path,
-    archive_path: Option<PathBuf>,
-) -> Result<()> {
+use super::changelog;
+use super::failure::Failure;
+use super::failure::FailureCode;
+use super::index;
+use super::install;
+use super::lint;
+use super::output::say;
+use super::output::summary;
+use super::registry;
+use super::registry_url;
+use super::workspace;
+
+// number of times to attempt an upload when the connection fails
+const MAX_PUBLISH_ATTEMPTS: usize = 3;
+// how often, and for how long, to check on an upload the registry is scanning
+const SCAN_POLL_INTERVAL: Duration = Duration::from_secs(5);
+const SCAN_POLL_TIMEOUT: Duration = Duration::from_secs(10 * 60);
+
+/// A checkout of a git repository at a tag. The checkout is removed when this is dropped.
+pub struct GitSource {
+    workdir: tempfile::TempDir,
+    pub repository: String,
+    pub tag: String,
+    pub commit: String,
+}
+
+impl GitSource {
+    pub fn path(&self) -> &Path {
+        self.workdir.path()
+    }
+}
+
+/// Shallow clone `repository` at `tag` into a temporary directory.
+pub fn clone_git_source(repository: &str, tag: &str) -> Result<GitSource> {
+    say!("🔃 Cloning {repository} at {tag}");
+    let workdir = tempfile::tempdir()?;
+    let output = std::process::Command::new("git")
+        .arg("-c")
+        .arg("advice.detachedHead=false")
+        .arg("clone")
+        .arg("--depth")
+        .arg("1")
+        .arg("--branch")
+        .arg(tag)
+        .arg(repository)
+        .arg(workdir.path())
+        .output()
+        .context("Failed to run git, is it installed?")?;
+    if !output.status.success() {
+        anyhow::bail!(
+            "Failed to clone {repository} at {tag}: {}",
+            String::from_utf8_lossy(&output.stderr).trim()
+        );
+    }
+    let output = std::process::Command::new("git")
+        .arg("-C")
+        .arg(workdir.path())
+        .arg("rev-parse")
+        .arg("HEAD")
+        .output()?;
+    if !output.status.success() {
+        anyhow::bail!("Failed to resolve the commit of {repository} at {tag}");
+    }
+    let commit = String::from_utf8(output.stdout)?.trim().to_string();
+    Ok(GitSource {
+        workdir,
+        repository: repository.to_string(),
+        tag: tag.to_string(),
+        commit,
+    })
+}
+
+/// A package tarball ready to be uploaded.
+struct Packaged {
+    package_name: String,
+    version_name: String,
+    tarball: File,
+    hash: blake3::Hash,
+    release_notes: Option<String>,
+}
+
+/// Create the tarball for the package in `pkg_dir`. See `nrpm_tarball::create_with_overrides`
+/// for `overrides`.
+fn package(pkg_dir: &Path, overrides: &HashMap<PathBuf, Vec<u8>>) -> Result<Packaged> {
     log::info!("📦 Packaging {:?}", pkg_dir);
     if let Ok(metadata) = std::fs::metadata(pkg_dir) {
         if !metadata.is_dir() {
@@ -33,54 +114,396 @@ pub async fn upload_tarball(
     ))?;
     let package_name = config.package.name;
 
-    let mut tarball = nrpm_tarball::create(pkg_dir, tempfile()?)?;
-    if let Some(path) = archive_path {
-        std::io::copy(&mut tarball, &mut File::create(path)?)?;
-        return Ok(());
-    }
+    let mut tarball = nrpm_tarball::create_with_overrides(pkg_dir, tempfile()?, overrides)?;
     let hash = nrpm_tarball::hash_tarball(&mut tarball)?;
+    Ok(Packaged {
+        package_name,
+        version_name,
+        tarball,
+        hash,
+        release_notes: None,
+    })
+}
 
-    println!("🔃 Redirecting to authorize");
-    tokio::time::sleep(Duration::from_millis(500)).await;
-    let login = super::attempt_auth().await?;
+/// Release notes for `packaged` listing the commits since the tag of the previous published
+/// version. `None` if there are no commits.
+async fn release_notes(
+    api: &OnyxApi,
+    pkg_dir: &Path,
+    packaged: &Packaged,
+) -> Result<Option<String>> {
+    let version = semver::Version::parse(&packaged.version_name)?;
+    let previous = index::load(api, &packaged.package_name)
+        .await
+        .ok()
+        .and_then(|package| {
+            package
+                .versions
+                .into_iter()
+                .filter_map(|v| semver::Version::parse(&v.name).ok())
+                .filter(|v| v < &version)
+                .max()
+        })
+        .map(|v| v.to_string());
+    let commits = changelog::commits_since(pkg_dir, previous.as_deref())?;
+    if commits.is_empty() {
+        summary!(
+            "⚠️  No commits since the previous version, publishing \"{}\" without release notes",
+            packaged.package_name
+        );
+        return Ok(None);
+    }
+    Ok(Some(changelog::release_notes(
+        &packaged.version_name,
+        &commits,
+    )))
+}
 
-    println!(""); // line break
-    if !dialoguer::Confirm::new()
-        .with_prompt(format!(
-            "Publish \"{package_name}\" version \"{version_name}\"?"
+/// Resolve the path dependency `name` of the package in `pkg_dir` to the registry version it
+/// was published as. The dependency directory must package to exactly the published tarball.
+async fn resolve_path_dependency(
+    api: &OnyxApi,
+    pkg_dir: &Path,
+    name: &str,
+    path: &str,
+) -> Result<Dependency> {
+    let dep_dir = pkg_dir.join(path);
+    let config = NargoConfig::load(&dep_dir).with_context(|| {
+        format!("Failed to load Nargo.toml of path dependency \"{name}\" at {path:?}")
+    })?;
+    let package_name = config.package.name;
+    let version_name = config.package.version.ok_or(
+        Failure::new(
+            FailureCode::UnpublishedPathDependency,
+            format!("Path dependency \"{name}\" at {path:?} has no version"),
+        )
+        .with_advice(format!(
+            "Packages with path dependencies can't be built by other users, publish \"{package_name}\" first."
         ))
-        .interact()?
-    {
+        .with_path(&dep_dir),
+    )?;
+    let mut tarball = nrpm_tarball::create(&dep_dir, tempfile()?)?;
+    let hash = HashId::from(nrpm_tarball::hash_tarball(&mut tarball)?);
+    let published = index::load(api, &package_name)
+        .await
+        .ok()
+        .and_then(|package| {
+            package
+                .versions
+                .into_iter()
+                .find(|v| v.name == version_name)
+        });
+    match published {
+        Some(version) if version.id == hash => {
+            say!(
+                "Path dependency \"{name}\" resolved to \"{package_name}\" version \"{version_name}\""
+            );
+            Ok(Dependency::new_git(
+                name.to_string(),
+                format!("{}/{package_name}", registry_url()),
+                registry::tag(&version_name),
+            ))
+        }
+        Some(_) => Err(Failure::new(
+            FailureCode::UnpublishedPathDependency,
+            format!(
+                "Path dependency \"{name}\" at {path:?} differs from the published \"{package_name}\" version \"{version_name}\""
+            ),
+        )
+        .with_advice("Publish the changes as a new version first.")
+        .with_path(&dep_dir)
+        .into()),
+        None => Err(Failure::new(
+            FailureCode::UnpublishedPathDependency,
+            format!("Path dependency \"{name}\" at {path:?} is not published"),
+        )
+        .with_advice(format!(
+            "Packages with path dependencies can't be built by other users, publish \"{package_name}\" version \"{version_name}\" first."
+        ))
+        .with_path(&dep_dir)
+        .into()),
+    }
+}
+
+/// Tarball overrides that replace every path dependency in the Nargo.toml of `pkg_dir` with a
+/// registry dependency. Dependencies named in `replacements` use them, the rest must resolve to
+/// a published version.
+async fn registry_overrides(
+    api: &OnyxApi,
+    pkg_dir: &Path,
+    mut replacements: Vec<Dependency>,
+) -> Result<HashMap<PathBuf, Vec<u8>>> {
+    let config =
+        NargoConfig::load(pkg_dir).with_context(|| "Nargo.toml not found in directory!")?;
+    for (name, dep) in config.dependencies()? {
+        let Some(path) = dep.path.as_ref() else {
+            continue;
+        };
+        if replacements.iter().any(|r| &r.name == name) {
+            continue;
+        }
+        replacements.push(resolve_path_dependency(api, pkg_dir, name, path).await?);
+    }
+    if replacements.is_empty() {
+        return Ok(HashMap::new());
+    }
+    let source = std::fs::read_to_string(pkg_dir.join("Nargo.toml"))?;
+    let nargo_toml = NargoConfig::replace_dependencies(&source, &replacements)?;
+    Ok(HashMap::from([(
+        PathBuf::from("Nargo.toml"),
+        nargo_toml.into_bytes(),
+    )]))
+}
+
+fn confirm(prompt: String, assume_yes: bool) -> Result<bool> {
+    println!(); // line break
+    if assume_yes {
+        println!("{prompt} yes");
+        return Ok(true);
+    }
+    if !dialoguer::Confirm::new().with_prompt(prompt).interact()? {
         println!("User cancelled the action");
-        return Ok(());
+        return Ok(false);
     }
+    Ok(true)
+}
 
+async fn upload(
+    api: &OnyxApi,
+    login: &LoginResponse,
+    packaged: Packaged,
+    git_source: Option<&GitSource>,
+) -> Result<PublishResponse> {
+    let Packaged {
+        package_name,
+        version_name,
+        mut tarball,
+        hash,
+        release_notes,
+    } = packaged;
+    say!("Publishing \"{package_name}\" version \"{version_name}\"");
     // reset the file handle for copying to final destination
     tarball.seek(std::io::SeekFrom::Start(0))?;
     let mut tarball_bytes = vec![];
     tarball.read_to_end(&mut tarball_bytes)?;
-    println!("Uploading: {} bytes", tarball_bytes.len());
-    println!("Hash: {}", hash.to_string());
-    match api
-        .publish(
-            PublishData {
-                hash: hash.to_string(),
-                token: login.token,
-            },
-            tarball_bytes,
+    say!("Uploading: {} bytes", tarball_bytes.len());
+    say!("Hash: {hash}");
+    let mut publish_data = PublishData::new(hash.to_string(), login.token.clone());
+    if let Some(source) = git_source {
+        say!(
+            "Source: {} at {} ({})",
+            source.repository,
+            source.tag,
+            source.commit
+        );
+        publish_data.source_repository = Some(source.repository.clone());
+        publish_data.source_commit = Some(source.commit.clone());
+    }
+    publish_data.release_notes = release_notes;
+    // the same key is sent with every attempt so a publish that succeeded but whose response
+    // was lost is not rejected as a duplicate
+    let idempotency_key = nanoid!();
+    let mut attempts = 0;
+    let response = loop {
+        attempts += 1;
+        match api
+            .publish_with_idempotency_key(
+                publish_data.clone(),
+                tarball_bytes.clone(),
+                &idempotency_key,
+            )
+            .await
+        {
+            Err(e)
+                if attempts < MAX_PUBLISH_ATTEMPTS
+                    && e.downcast_ref::<reqwest::Error>().is_some() =>
+            {
+                say!("Upload failed, retrying: {e}");
+                tokio::time::sleep(Duration::from_millis(1000)).await;
+            }
+            result => break result?,
+        }
+    };
+    wait_for_scan(api, login, &hash, &package_name, response).await
+}
+
+/// Poll an upload the registry is scanning until it's published, or fail if a scanner flagged
+/// it.
+async fn wait_for_scan(
+    api: &OnyxApi,
+    login: &LoginResponse,
+    hash: &blake3::Hash,
+    package_name: &str,
+    mut response: PublishResponse,
+) -> Result<PublishResponse> {
+    let version_id = HashId::from(*hash);
+    let started = std::time::Instant::now();
+    let mut status = response.status;
+    let mut findings = vec![];
+    if status == PublishStatus::Pending {
+        say!("Waiting for the registry to scan the upload");
+    }
+    while status == PublishStatus::Pending {
+        if started.elapsed() >= SCAN_POLL_TIMEOUT {
+            return Err(Failure::new(
+                FailureCode::NotPublished,
+                "The registry is still scanning the upload, it will be published once the scan finds nothing",
+            )
+            .into());
+        }
+        tokio::time::sleep(SCAN_POLL_INTERVAL).await;
+        let polled = api.publish_status(&login.token, &version_id).await?;
+        status = polled.status;
+        findings = polled.findings;
+    }
+    let reason = match status {
+        PublishStatus::Published => None,
+        PublishStatus::Flagged => {
+            Some("was flagged by the registry's scanners and waits for an admin to review it")
+        }
+        _ => Some("was rejected by the registry"),
+    };
+    if let Some(reason) = reason {
+        let findings = findings
+            .iter()
+            .map(|finding| format!("\n{}: {}", finding.scanner, finding.output.trim_end()))
+            .collect::<String>();
+        return Err(Failure::new(
+            FailureCode::NotPublished,
+            format!("The upload {reason}{findings}"),
         )
+        .into());
+    }
+    // a new package has no id until it's published
+    if response.package_id.is_empty() {
+        response.package_id = api.load_package_versions(package_name).await?.0.id;
+    }
+    response.status = status;
+    Ok(response)
+}
+
+pub async fn upload_tarball(
+    api: &OnyxApi,
+    pkg_dir: &Path,
+    archive_path: Option<PathBuf>,
+    git_source: Option<&GitSource>,
+    changelog: bool,
+    assume_yes: bool,
+) -> Result<()> {
+    let diagnostics = lint::lint(pkg_dir)?;
+    let overrides = registry_overrides(api, pkg_dir, vec![]).await?;
+    let mut packaged = package(pkg_dir, &overrides)?;
+    if let Some(path) = archive_path {
+        packaged.tarball.seek(std::io::SeekFrom::Start(0))?;
+        std::io::copy(&mut packaged.tarball, &mut File::create(path)?)?;
+        return Ok(());
+    }
+
+    let login = super::attempt_auth().await?;
+
+    if changelog {
+        packaged.release_notes = release_notes(api, pkg_dir, &packaged).await?;
+    }
+    let package_name = packaged.package_name.clone();
+    let version_name = packaged.version_name.clone();
+    if let Some(release_notes) = &packaged.release_notes {
+        println!();
+        print!("{release_notes}");
+    }
+    println!();
+    lint::print(&diagnostics, false)?;
+    lint::check(&diagnostics)?;
+    if !confirm(
+        format!("Publish \"{package_name}\" version \"{version_name}\"?"),
+        assume_yes,
+    )? {
+        return Ok(());
+    }
+
+    let PublishResponse {
+        package_id,
+        warnings,
+        ..
+    } = upload(api, &login, packaged, git_source)
         .await
-    {
-        Ok(PublishResponse { package_id }) => {
-            println!(
-                "Success: published version \"{version_name}\" for package \"{package_name}\""
-            );
-            println!("Package id: {package_id}");
+        .context("Failed to publish package")?;
+    for warning in warnings {
+        summary!("⚠️  {warning}");
+    }
+    summary!("Success: published version \"{version_name}\" for package \"{package_name}\"");
+    summary!("Package id: {package_id}");
+    Ok(())
+}
+
+/// Publish every member of the workspace at `root`, dependencies first. Path dependencies
+/// between members are packaged as dependencies on the registry versions being published,
+/// other path dependencies must already be published.
+pub async fn publish_workspace(
+    api: &OnyxApi,
+    root: &Path,
+    workspace: &Workspace,
+    git_source: Option<&GitSource>,
+    changelog: bool,
+    assume_yes: bool,
+) -> Result<()> {
+    let members = workspace::publish_order(root, workspace)?;
+    if members.is_empty() {
+        anyhow::bail!("Workspace has no members");
+    }
+    let mut packaged = vec![];
+    let mut diagnostics = vec![];
+    for member in &members {
+        diagnostics.push(lint::lint(&member.dir)?);
+        install::install(member.dir.clone(), &install::InstallOptions::default()).await?;
+        let replacements = workspace::member_replacements(member, &members)?;
+        let overrides = registry_overrides(api, &member.dir, replacements).await?;
+        let mut p = package(&member.dir, &overrides)?;
+        if changelog {
+            p.release_notes = release_notes(api, &member.dir, &p).await?;
+        }
+        packaged.push(p);
+    }
+
+    let login = super::attempt_auth().await?;
+
+    println!();
+    println!("Workspace packages, in publish order:");
+    for (p, diagnostics) in packaged.iter().zip(&diagnostics) {
+        println!(
+            "    {} {} ({})",
+            p.package_name,
+            p.version_name,
+            lint::summary(diagnostics)
+        );
+    }
+    for (p, diagnostics) in packaged.iter().zip(&diagnostics) {
+        if !diagnostics.is_empty() {
+            println!();
+            println!("{}:", p.package_name);
+            lint::print(diagnostics, false)?;
         }
-        Err(e) => {
-            eprintln!("failed to publish package");
-            eprintln!("{e:?}");
+    }
+    for p in &packaged {
+        if let Some(release_notes) = &p.release_notes {
+            println!();
+            print!("{release_notes}");
         }
     }
+    lint::check(&diagnostics.concat())?;
+    if !confirm(format!("Publish {} packages?", packaged.len()), assume_yes)? {
+        return Ok(());
+    }
+
+    let total = packaged.len();
+    for (i, p) in packaged.into_iter().enumerate() {
+        let package_name = p.package_name.clone();
+        let version_name = p.version_name.clone();
+        upload(api, &login, p, git_source).await.with_context(|| {
+            format!(
+                "Failed to publish \"{package_name}\" version \"{version_name}\", {i} of {total} packages were published"
+            )
+        })?;
+        summary!("Success: published version \"{version_name}\" for package \"{package_name}\"");
+    }
     Ok(())
 }
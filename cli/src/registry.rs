@@ -0,0 +1,190 @@
+use std::collections::BTreeMap;
+
+use anyhow::Result;
+use nargo_parse::Dependency;
+use onyx_api::prelude::*;
+
+use crate::config;
+use crate::config::DEFAULT_REGISTRY;
+use crate::failure::Failure;
+use crate::failure::FailureCode;
+use crate::index;
+
+/// A registry dependencies can come from: the one at `registry_url`, or one in the
+/// `registries` table of the config file.
+#[derive(Clone, Debug)]
+pub struct Registry {
+    pub name: String,
+    /// The url packages are cloned from, `{url}/{package_name}`.
+    pub url: String,
+}
+
+impl Registry {
+    pub fn is_default(&self) -> bool {
+        self.name == DEFAULT_REGISTRY
+    }
+
+    /// A client for the api of the registry. Other registries serve their api and packages
+    /// from the same url.
+    pub fn api(&self) -> OnyxApi {
+        if self.is_default() {
+            super::registry_api()
+        } else {
+            OnyxApi {
+                url: self.url.clone(),
+            }
+        }
+    }
+
+    /// The name of the package `git` clones from this registry, if it's one of its packages.
+    fn package_name<'a>(&self, git: &'a str) -> Option<&'a str> {
+        git.strip_prefix(&self.url)?
+            .strip_prefix('/')
+            .filter(|name| !name.is_empty())
+    }
+}
+
+/// The tag nrpm writes in dependencies on `version_name` of a registry package. Registries
+/// serve each version as a tag both as it's named and with a `v` prefix, the `v_tags`
+/// setting picks the prefixed one.
+pub fn tag(version_name: &str) -> String {
+    match config::flag("v_tags") && version_name.starts_with(|c: char| c.is_ascii_digit()) {
+        true => format!("v{version_name}"),
+        false => version_name.to_string(),
+    }
+}
+
+/// Every configured registry, the default first.
+pub fn all() -> Vec<Registry> {
+    let mut registries = vec![Registry {
+        name: DEFAULT_REGISTRY.to_string(),
+        url: super::registry_url(),
+    }];
+    for (name, url) in config::registries() {
+        registries.push(Registry { name, url });
+    }
+    registries
+}
+
+/// The registry called `name`.
+pub fn named(name: &str) -> Result<Registry> {
+    let registries = all();
+    registries
+        .iter()
+        .find(|r| r.name == name)
+        .cloned()
+        .ok_or(anyhow::Error::new(
+            Failure::new(
+                FailureCode::UnknownRegistry,
+                format!("No registry is configured as \"{name}\""),
+            )
+            .with_advice(format!(
+                "Add it with nrpm config set registries.{name} <url>. Registries are: {}",
+                registries
+                    .iter()
+                    .map(|r| r.name.as_str())
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            )),
+        ))
+}
+
+/// The registry `git` clones from and the name of the package in it, `None` if it isn't a
+/// registry package. With `pinned`, `git` must point into that registry.
+pub fn of_git(git: &str, pinned: Option<&str>) -> Result<Option<(Registry, String)>> {
+    let Some(pinned) = pinned else {
+        return Ok(all().into_iter().find_map(|registry| {
+            let package_name = registry.package_name(git)?.to_string();
+            Some((registry, package_name))
+        }));
+    };
+    let registry = named(pinned)?;
+    let Some(package_name) = registry.package_name(git).map(str::to_string) else {
+        return Err(Failure::new(
+            FailureCode::UnknownRegistry,
+            format!(
+                "{git} is pinned to registry \"{pinned}\", which is at {}",
+                registry.url
+            ),
+        )
+        .with_advice(format!(
+            "Point git at {}/<package>, or change the url of the registry with nrpm config set.",
+            registry.url
+        ))
+        .into());
+    };
+    Ok(Some((registry, package_name)))
+}
+
+/// The registry `dep` is a package of and its name there, see `of_git`.
+pub fn of(dep: &Dependency) -> Result<Option<(Registry, String)>> {
+    match (dep.git.as_ref(), dep.tag.as_ref()) {
+        (Some(git), Some(_)) => of_git(git, dep.registry.as_deref()),
+        _ => Ok(None),
+    }
+}
+
+/// Load the index entries of registry packages ahead of time with a batch request to each
+/// of their registries, see `index::prefetch`.
+pub async fn prefetch(packages: impl IntoIterator<Item = (Registry, String)>) {
+    let mut by_registry = BTreeMap::<String, (Registry, Vec<String>)>::new();
+    for (registry, package_name) in packages {
+        by_registry
+            .entry(registry.name.clone())
+            .or_insert_with(|| (registry, vec![]))
+            .1
+            .push(package_name);
+    }
+    for (registry, package_names) in by_registry.into_values() {
+        index::prefetch(&registry.api(), package_names).await;
+    }
+}
+
+/// Find the latest version of `package_name` for `nrpm install <package_name>`, in the
+/// registry named `pinned` or otherwise in whichever configured registry publishes it. Fails
+/// if more than one does.
+pub async fn latest_version(
+    package_name: &str,
+    pinned: Option<&str>,
+) -> Result<(Registry, String, IndexVersion)> {
+    if let Some(pinned) = pinned {
+        let registry = named(pinned)?;
+        let (name, version) = index::latest_version(&registry.api(), package_name).await?;
+        return Ok((registry, name, version));
+    }
+    let mut registries = all().into_iter();
+    let default = registries
+        .next()
+        .expect("the default registry is configured");
+    let default_result = index::latest_version(&default.api(), package_name).await;
+    let mut found = vec![];
+    for registry in registries {
+        match index::latest_version(&registry.api(), package_name).await {
+            Ok((name, version)) => found.push((registry, name, version)),
+            Err(e) => log::debug!("\"{package_name}\" not found in {}: {e:?}", registry.name),
+        }
+    }
+    match default_result {
+        Ok((name, version)) => found.insert(0, (default, name, version)),
+        Err(e) if found.is_empty() => return Err(e),
+        Err(e) => log::debug!("\"{package_name}\" not found in {}: {e:?}", default.name),
+    }
+    if found.len() > 1 {
+        return Err(Failure::new(
+            FailureCode::AmbiguousPackage,
+            format!(
+                "\"{package_name}\" is published in more than one registry: {}",
+                found
+                    .iter()
+                    .map(|(registry, _, _)| format!("{} ({})", registry.name, registry.url))
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            ),
+        )
+        .with_advice(format!(
+            "Choose one with nrpm install {package_name} --registry <name>."
+        ))
+        .into());
+    }
+    Ok(found.remove(0))
+}
@@ -0,0 +1,203 @@
+use std::collections::HashMap;
+use std::path::Path;
+use std::path::PathBuf;
+
+use anyhow::Result;
+use serde::Serialize;
+
+use nargo_parse::*;
+use nrpm_resolver::Graph;
+use nrpm_resolver::ROOT_IDENTIFIER;
+
+/// Version of the install report format.
+pub const REPORT_SCHEMA_VERSION: u32 = 2;
+
+/// How a package was made available on disk.
+#[derive(Clone, Copy, Debug, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Fetch {
+    /// Read from a local path.
+    Local,
+    /// Already present in the system cache.
+    Cache,
+    /// Cloned from its git url.
+    Download,
+    /// Built from an older version in the system cache and a delta from the registry.
+    Delta,
+}
+
+/// How packages were fetched during an install, for the report.
+#[derive(Default)]
+pub struct Resolution {
+    fetched: HashMap<String, Fetch>,
+    // identifiers of packages fetched without the registry's download endpoint, see `stats`
+    uncounted: Vec<String>,
+}
+
+impl Resolution {
+    pub fn fetched(&mut self, identifier: &str, fetch: Fetch) {
+        self.fetched.insert(identifier.to_string(), fetch);
+    }
+
+    /// Record that `identifier` was fetched without the registry counting a download, e.g.
+    /// cloned with git or built from a delta.
+    pub fn uncounted(&mut self, identifier: &str) {
+        self.uncounted.push(identifier.to_string());
+    }
+
+    pub fn uncounted_fetches(&self) -> &[String] {
+        &self.uncounted
+    }
+}
+
+#[derive(Serialize)]
+struct EntryBody {
+    identifier: String,
+    package_name: String,
+    package_version: Option<String>,
+    git: Option<String>,
+    tag: Option<String>,
+    path: Option<String>,
+    location: PathBuf,
+    fetch: Fetch,
+    blake3: String,
+    dependencies: Vec<String>,
+    /// Identifiers of the dependencies this package replaced because of a patch.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    patched_from: Vec<String>,
+}
+
+#[derive(Serialize)]
+struct Entry {
+    #[serde(flatten)]
+    body: EntryBody,
+    /// `hash` of the previous entry, zeros for the first.
+    previous_hash: String,
+    /// blake3 of `previous_hash` followed by the other fields of the entry, see `canonical_json`.
+    hash: String,
+}
+
+/// Everything an install resolved. Entries are hash chained starting from the root package, so
+/// `report_hash` commits to the whole resolution.
+#[derive(Serialize)]
+struct InstallReport {
+    schema_version: u32,
+    nrpm_version: &'static str,
+    registry_url: String,
+    generated_at: u64,
+    entries: Vec<Entry>,
+    report_hash: String,
+}
+
+/// The bytes an entry is hashed as: compact json with the keys of every object sorted, so
+/// anyone parsing the report can encode an entry the same way to check its hash.
+fn canonical_json(value: &serde_json::Value) -> Result<Vec<u8>> {
+    let mut out = vec![];
+    match value {
+        serde_json::Value::Object(map) => {
+            let mut keys = map.keys().collect::<Vec<_>>();
+            keys.sort();
+            out.push(b'{');
+            for (i, key) in keys.into_iter().enumerate() {
+                if i > 0 {
+                    out.push(b',');
+                }
+                out.extend(serde_json::to_vec(key)?);
+                out.push(b':');
+                out.extend(canonical_json(&map[key])?);
+            }
+            out.push(b'}');
+        }
+        serde_json::Value::Array(values) => {
+            out.push(b'[');
+            for (i, value) in values.iter().enumerate() {
+                if i > 0 {
+                    out.push(b',');
+                }
+                out.extend(canonical_json(value)?);
+            }
+            out.push(b']');
+        }
+        value => out.extend(serde_json::to_vec(value)?),
+    }
+    Ok(out)
+}
+
+/// Write the report for an install of the package at `root_path` to `report_path`.
+/// `all_dependencies` and `hashes` are keyed by dependency identifier.
+pub fn write(
+    report_path: &Path,
+    root_path: &Path,
+    root_pkg: &NargoConfig,
+    all_dependencies: &HashMap<String, (PathBuf, Dependency, NargoConfig)>,
+    hashes: &HashMap<String, String>,
+    graph: &Graph,
+    resolution: &Resolution,
+) -> Result<()> {
+    let mut bodies = vec![EntryBody {
+        identifier: ROOT_IDENTIFIER.to_string(),
+        package_name: root_pkg.package.name.clone(),
+        package_version: root_pkg.package.version.clone(),
+        git: None,
+        tag: None,
+        path: None,
+        location: root_path.to_path_buf(),
+        fetch: Fetch::Local,
+        blake3: nrpm_tarball::hash_dir(root_path)?.to_string(),
+        dependencies: graph.dependencies(ROOT_IDENTIFIER),
+        patched_from: vec![],
+    }];
+    let mut identifiers = all_dependencies.keys().collect::<Vec<_>>();
+    identifiers.sort();
+    for identifier in identifiers {
+        let (dep_path, dep, config) = &all_dependencies[identifier];
+        bodies.push(EntryBody {
+            identifier: identifier.clone(),
+            package_name: config.package.name.clone(),
+            package_version: config.package.version.clone(),
+            git: dep.git.clone(),
+            tag: dep.tag.clone(),
+            path: dep.path.clone(),
+            location: dep_path.clone(),
+            fetch: resolution
+                .fetched
+                .get(identifier)
+                .copied()
+                .unwrap_or(Fetch::Local),
+            blake3: hashes
+                .get(identifier)
+                .cloned()
+                .ok_or(anyhow::anyhow!("no hash for dependency {identifier}"))?,
+            dependencies: graph.dependencies(identifier),
+            patched_from: graph.patched_from(identifier),
+        });
+    }
+
+    let mut previous_hash = "0".repeat(64);
+    let mut entries = vec![];
+    for body in bodies {
+        let mut hasher = blake3::Hasher::new();
+        hasher.update(previous_hash.as_bytes());
+        hasher.update(&canonical_json(&serde_json::to_value(&body)?)?);
+        let hash = hasher.finalize().to_string();
+        entries.push(Entry {
+            body,
+            previous_hash,
+            hash: hash.clone(),
+        });
+        previous_hash = hash;
+    }
+
+    let report = InstallReport {
+        schema_version: REPORT_SCHEMA_VERSION,
+        nrpm_version: clap::crate_version!(),
+        registry_url: super::registry_url(),
+        generated_at: std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)?
+            .as_secs(),
+        entries,
+        report_hash: previous_hash,
+    };
+    std::fs::write(report_path, serde_json::to_vec_pretty(&report)?)?;
+    Ok(())
+}
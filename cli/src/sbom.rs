@@ -0,0 +1,459 @@
+use std::path::Path;
+use std::time::SystemTime;
+
+use anyhow::Result;
+use nargo_parse::*;
+use onyx_api::prelude::*;
+use percent_encoding::AsciiSet;
+use percent_encoding::NON_ALPHANUMERIC;
+use percent_encoding::utf8_percent_encode;
+use serde::Serialize;
+
+use crate::index;
+use crate::licenses;
+
+// characters left as is in purl components, everything else is percent encoded
+const PURL_ENCODE: &AsciiSet = &NON_ALPHANUMERIC.remove(b'-').remove(b'.').remove(b'_');
+
+/// A package in the resolved dependency tree, from the lockfile, the package's Nargo.toml,
+/// and the registry if it was published there.
+struct Component {
+    /// `git@tag`, see `LockEntry::identifier`. `None` for the root package.
+    identifier: Option<String>,
+    name: String,
+    version: Option<String>,
+    git: Option<String>,
+    tag: Option<String>,
+    /// Hex encoded blake3 hash of the package contents, from the lockfile.
+    blake3: Option<String>,
+    license: Option<String>,
+    description: Option<String>,
+    registry: Option<RegistryVersion>,
+    /// Identifiers of the locked packages this one depends on.
+    depends_on: Vec<String>,
+}
+
+struct RegistryVersion {
+    /// Hex encoded blake3 hash of the published tarball.
+    tarball_blake3: String,
+    download_url: String,
+}
+
+impl Component {
+    fn from_config(
+        identifier: Option<String>,
+        config: &NargoConfig,
+        locked: &[String],
+    ) -> Result<Self> {
+        let mut depends_on = vec![];
+        for dep in config.dependencies()?.values() {
+            // path dependencies aren't locked, their contents are part of the package
+            if let Ok(identifier) = dep.identifier()
+                && locked.contains(&identifier)
+            {
+                depends_on.push(identifier);
+            }
+        }
+        Ok(Self {
+            identifier,
+            name: config.package.name.clone(),
+            version: config.package.version.clone(),
+            git: None,
+            tag: None,
+            blake3: None,
+            license: config.package.license.clone(),
+            description: config.package.description.clone(),
+            registry: None,
+            depends_on,
+        })
+    }
+
+    fn bom_ref(&self) -> String {
+        self.identifier
+            .clone()
+            .unwrap_or_else(|| format!("root:{}", self.name))
+    }
+
+    /// A package url for the component, git packages aren't an ecosystem of their own so
+    /// they're `generic` with the repository as a qualifier.
+    fn purl(&self) -> String {
+        let mut purl = format!(
+            "pkg:generic/{}",
+            utf8_percent_encode(&self.name, PURL_ENCODE)
+        );
+        if let Some(version) = self.tag.as_ref().or(self.version.as_ref()) {
+            purl.push_str(&format!("@{}", utf8_percent_encode(version, PURL_ENCODE)));
+        }
+        if let (Some(git), Some(tag)) = (&self.git, &self.tag) {
+            purl.push_str(&format!(
+                "?vcs_url={}",
+                utf8_percent_encode(&format!("git+{git}@{tag}"), PURL_ENCODE)
+            ));
+        }
+        purl
+    }
+}
+
+/// The root package at `path` and every package in its nrpm.lock, with the registry's hash
+/// and download url for packages published there. Locked packages must already be
+/// installed.
+async fn components(api: &OnyxApi, path: &Path) -> Result<(Component, Vec<Component>)> {
+    let packages = licenses::locked_packages(path)?;
+    let locked = packages
+        .iter()
+        .map(|(entry, _)| entry.identifier())
+        .collect::<Vec<_>>();
+    let root = Component::from_config(None, &NargoConfig::load(path)?, &locked)?;
+
+    let registry_prefix = format!("{}/", super::registry_url());
+    let mut components = vec![];
+    for (entry, config) in packages {
+        let mut component = Component::from_config(Some(entry.identifier()), &config, &locked)?;
+        if let Some(package_name) = entry.git.strip_prefix(&registry_prefix) {
+            match index::load(api, package_name).await {
+                Ok(package) => {
+                    component.registry = package
+                        .position(&entry.tag)
+                        .map(|i| &package.versions[i])
+                        .map(|version| {
+                            let id = version.id.to_string();
+                            RegistryVersion {
+                                download_url: format!("{}/v0/version/{id}", api.url),
+                                tarball_blake3: id,
+                            }
+                        });
+                }
+                Err(e) => log::debug!("unable to load index for {package_name}: {e:?}"),
+            }
+        }
+        component.git = Some(entry.git);
+        component.tag = Some(entry.tag);
+        component.blake3 = Some(entry.blake3);
+        components.push(component);
+    }
+    components.sort_by_key(|component| (component.name.clone(), component.bom_ref()));
+    Ok((root, components))
+}
+
+/// A software bill of materials for the resolved dependency tree of the package at `path`,
+/// as CycloneDX 1.5 or SPDX 2.3 json.
+pub async fn sbom(api: &OnyxApi, path: &Path, format: &str) -> Result<String> {
+    let (root, components) = components(api, path).await?;
+    let serial = uuid::Uuid::new_v4();
+    let created = humantime::format_rfc3339_seconds(SystemTime::now()).to_string();
+    let json = match format {
+        "cyclonedx" => {
+            serde_json::to_string_pretty(&cyclonedx(&root, &components, serial, created))?
+        }
+        "spdx" => serde_json::to_string_pretty(&spdx(&root, &components, serial, created))?,
+        _ => anyhow::bail!("Unknown sbom format \"{format}\", expected cyclonedx or spdx"),
+    };
+    Ok(json)
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct CycloneDx {
+    bom_format: &'static str,
+    spec_version: &'static str,
+    serial_number: String,
+    version: u32,
+    metadata: CycloneDxMetadata,
+    components: Vec<CycloneDxComponent>,
+    dependencies: Vec<CycloneDxDependency>,
+}
+
+#[derive(Serialize)]
+struct CycloneDxMetadata {
+    timestamp: String,
+    tools: CycloneDxTools,
+    component: CycloneDxComponent,
+}
+
+#[derive(Serialize)]
+struct CycloneDxTools {
+    components: Vec<CycloneDxTool>,
+}
+
+#[derive(Serialize)]
+struct CycloneDxTool {
+    #[serde(rename = "type")]
+    kind: &'static str,
+    name: &'static str,
+    version: &'static str,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct CycloneDxComponent {
+    #[serde(rename = "type")]
+    kind: &'static str,
+    #[serde(rename = "bom-ref")]
+    bom_ref: String,
+    name: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    version: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    description: Option<String>,
+    purl: String,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    hashes: Vec<CycloneDxHash>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    licenses: Vec<CycloneDxLicense>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    external_references: Vec<CycloneDxReference>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    properties: Vec<CycloneDxProperty>,
+}
+
+#[derive(Serialize)]
+struct CycloneDxHash {
+    alg: &'static str,
+    content: String,
+}
+
+#[derive(Serialize)]
+struct CycloneDxLicense {
+    expression: String,
+}
+
+#[derive(Serialize)]
+struct CycloneDxReference {
+    #[serde(rename = "type")]
+    kind: &'static str,
+    url: String,
+}
+
+#[derive(Serialize)]
+struct CycloneDxProperty {
+    name: &'static str,
+    value: String,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct CycloneDxDependency {
+    #[serde(rename = "ref")]
+    reference: String,
+    depends_on: Vec<String>,
+}
+
+fn cyclonedx_component(component: &Component, kind: &'static str) -> CycloneDxComponent {
+    let mut external_references = vec![];
+    let mut properties = vec![];
+    if let Some(git) = &component.git {
+        external_references.push(CycloneDxReference {
+            kind: "vcs",
+            url: git.clone(),
+        });
+    }
+    if let Some(registry) = &component.registry {
+        external_references.push(CycloneDxReference {
+            kind: "distribution",
+            url: registry.download_url.clone(),
+        });
+        properties.push(CycloneDxProperty {
+            name: "nrpm:tarball_blake3",
+            value: registry.tarball_blake3.clone(),
+        });
+    }
+    CycloneDxComponent {
+        kind,
+        bom_ref: component.bom_ref(),
+        name: component.name.clone(),
+        version: component.version.clone(),
+        description: component.description.clone(),
+        purl: component.purl(),
+        hashes: component
+            .blake3
+            .iter()
+            .map(|content| CycloneDxHash {
+                alg: "BLAKE3",
+                content: content.clone(),
+            })
+            .collect(),
+        licenses: component
+            .license
+            .iter()
+            .map(|expression| CycloneDxLicense {
+                expression: expression.clone(),
+            })
+            .collect(),
+        external_references,
+        properties,
+    }
+}
+
+fn cyclonedx(
+    root: &Component,
+    components: &[Component],
+    serial: uuid::Uuid,
+    timestamp: String,
+) -> CycloneDx {
+    CycloneDx {
+        bom_format: "CycloneDX",
+        spec_version: "1.5",
+        serial_number: format!("urn:uuid:{serial}"),
+        version: 1,
+        metadata: CycloneDxMetadata {
+            timestamp,
+            tools: CycloneDxTools {
+                components: vec![CycloneDxTool {
+                    kind: "application",
+                    name: "nrpm",
+                    version: clap::crate_version!(),
+                }],
+            },
+            component: cyclonedx_component(root, "application"),
+        },
+        components: components
+            .iter()
+            .map(|component| cyclonedx_component(component, "library"))
+            .collect(),
+        dependencies: std::iter::once(root)
+            .chain(components)
+            .map(|component| CycloneDxDependency {
+                reference: component.bom_ref(),
+                depends_on: component.depends_on.clone(),
+            })
+            .collect(),
+    }
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct Spdx {
+    spdx_version: &'static str,
+    data_license: &'static str,
+    #[serde(rename = "SPDXID")]
+    spdx_id: &'static str,
+    name: String,
+    document_namespace: String,
+    creation_info: SpdxCreationInfo,
+    packages: Vec<SpdxPackage>,
+    relationships: Vec<SpdxRelationship>,
+}
+
+#[derive(Serialize)]
+struct SpdxCreationInfo {
+    created: String,
+    creators: Vec<String>,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct SpdxPackage {
+    #[serde(rename = "SPDXID")]
+    spdx_id: String,
+    name: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    version_info: Option<String>,
+    download_location: String,
+    files_analyzed: bool,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    checksums: Vec<SpdxChecksum>,
+    license_concluded: &'static str,
+    license_declared: String,
+    copyright_text: &'static str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    description: Option<String>,
+    external_refs: Vec<SpdxExternalRef>,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct SpdxChecksum {
+    algorithm: &'static str,
+    checksum_value: String,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct SpdxExternalRef {
+    reference_category: &'static str,
+    reference_type: &'static str,
+    reference_locator: String,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct SpdxRelationship {
+    spdx_element_id: String,
+    relationship_type: &'static str,
+    related_spdx_element: String,
+}
+
+fn spdx(root: &Component, components: &[Component], serial: uuid::Uuid, created: String) -> Spdx {
+    // SPDX ids are limited to letters, numbers, '.' and '-', so packages are numbered
+    let spdx_id = |i: usize| format!("SPDXRef-Package-{i}");
+    let all = std::iter::once(root).chain(components).collect::<Vec<_>>();
+    let packages = all
+        .iter()
+        .enumerate()
+        .map(|(i, component)| SpdxPackage {
+            spdx_id: spdx_id(i),
+            name: component.name.clone(),
+            version_info: component.version.clone(),
+            download_location: match (&component.registry, &component.git, &component.tag) {
+                (Some(registry), _, _) => registry.download_url.clone(),
+                (None, Some(git), Some(tag)) => format!("git+{git}@{tag}"),
+                _ => "NOASSERTION".to_string(),
+            },
+            files_analyzed: false,
+            checksums: component
+                .blake3
+                .iter()
+                .map(|value| SpdxChecksum {
+                    algorithm: "BLAKE3",
+                    checksum_value: value.clone(),
+                })
+                .collect(),
+            license_concluded: "NOASSERTION",
+            license_declared: component
+                .license
+                .clone()
+                .unwrap_or("NOASSERTION".to_string()),
+            copyright_text: "NOASSERTION",
+            description: component.description.clone(),
+            external_refs: vec![SpdxExternalRef {
+                reference_category: "PACKAGE-MANAGER",
+                reference_type: "purl",
+                reference_locator: component.purl(),
+            }],
+        })
+        .collect();
+
+    let mut relationships = vec![SpdxRelationship {
+        spdx_element_id: "SPDXRef-DOCUMENT".to_string(),
+        relationship_type: "DESCRIBES",
+        related_spdx_element: spdx_id(0),
+    }];
+    for (i, component) in all.iter().enumerate() {
+        for dependency in &component.depends_on {
+            if let Some(j) = all
+                .iter()
+                .position(|c| c.identifier.as_ref() == Some(dependency))
+            {
+                relationships.push(SpdxRelationship {
+                    spdx_element_id: spdx_id(i),
+                    relationship_type: "DEPENDS_ON",
+                    related_spdx_element: spdx_id(j),
+                });
+            }
+        }
+    }
+
+    Spdx {
+        spdx_version: "SPDX-2.3",
+        data_license: "CC0-1.0",
+        spdx_id: "SPDXRef-DOCUMENT",
+        name: root.name.clone(),
+        document_namespace: format!("https://nrpm.io/spdx/{}-{serial}", root.name),
+        creation_info: SpdxCreationInfo {
+            created,
+            creators: vec![format!("Tool: nrpm-{}", clap::crate_version!())],
+        },
+        packages,
+        relationships,
+    }
+}
@@ -0,0 +1,214 @@
+use std::path::PathBuf;
+
+use anyhow::Context;
+use anyhow::Result;
+use onyx_api::prelude::*;
+use ring::signature::ED25519;
+use ring::signature::UnparsedPublicKey;
+use serde::Deserialize;
+use serde::Serialize;
+use tokio::sync::OnceCell;
+
+use crate::config;
+use crate::failure::Failure;
+use crate::failure::FailureCode;
+use crate::output::say;
+
+// the snapshot is fetched at most once per run, and only if a package is downloaded
+static SNAPSHOT: OnceCell<Option<Snapshot>> = OnceCell::const_new();
+
+/// What's trusted about a registry, saved between invocations.
+#[derive(Serialize, Deserialize)]
+struct TrustedRegistry {
+    root: SignedDocument,
+    /// Id of the newest snapshot seen, older snapshots are rejected.
+    snapshot_id: u64,
+}
+
+fn trust_file(api_url: &str) -> Result<PathBuf> {
+    let registry_dir = api_url
+        .chars()
+        .map(|c| if c.is_ascii_alphanumeric() { c } else { '_' })
+        .collect::<String>();
+    Ok(config::config_dir()?
+        .join("trust")
+        .join(format!("{registry_dir}.json")))
+}
+
+/// Whether `document` carries a valid signature by one of `keys`.
+fn is_signed_by(document: &SignedDocument, keys: &[String]) -> bool {
+    document.signatures.iter().any(|signature| {
+        keys.contains(&signature.key)
+            && match (
+                hex::decode(&signature.key),
+                hex::decode(&signature.signature),
+            ) {
+                (Ok(key), Ok(bytes)) => UnparsedPublicKey::new(&ED25519, key)
+                    .verify(document.signed.as_bytes(), &bytes)
+                    .is_ok(),
+                _ => false,
+            }
+    })
+}
+
+/// The root to trust for a registry seen for the first time. It must be signed by the key in
+/// the `root_key` setting if set, otherwise it's trusted on first use.
+fn first_root(api: &OnyxApi, signed: SignedDocument) -> Result<SignedDocument> {
+    let root = signed.document::<RegistryRoot>()?;
+    if !is_signed_by(&signed, &root.root_keys) {
+        anyhow::bail!("The registry root is not signed by its own root keys");
+    }
+    match config::value("root_key") {
+        Some(key) if is_signed_by(&signed, &[key.to_lowercase()]) => {}
+        Some(key) => anyhow::bail!("The registry root is not signed by the root key {key}"),
+        None => say!(
+            "🔐 Trusting the signing keys of {} on first use, set root_key with nrpm config set to pin them",
+            api.url
+        ),
+    }
+    Ok(signed)
+}
+
+/// Load and verify the latest snapshot of the registry. Newer roots are only trusted if
+/// they're signed by the keys of the root before them.
+async fn load(api: &OnyxApi) -> Result<Option<Snapshot>> {
+    let path = trust_file(&api.url)?;
+    let trusted = match std::fs::read_to_string(&path) {
+        Ok(str) => Some(
+            serde_json::from_str::<TrustedRegistry>(&str)
+                .with_context(|| format!("Failed to parse {path:?}"))?,
+        ),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => None,
+        Err(e) => return Err(e.into()),
+    };
+    let Some(latest) = api.registry_root(None).await? else {
+        if trusted.is_some() {
+            return Err(Failure::new(
+                FailureCode::UntrustedRegistry,
+                format!("{} signed snapshots before and no longer does", api.url),
+            )
+            .with_advice("If the registry stopped signing snapshots on purpose, delete it.")
+            .with_path(&path)
+            .into());
+        }
+        return Ok(None);
+    };
+    let latest_version = latest.document::<RegistryRoot>()?.version;
+
+    let mut signed_root = match &trusted {
+        Some(trusted) => trusted.root.clone(),
+        None => first_root(api, latest)?,
+    };
+    let mut root = signed_root.document::<RegistryRoot>()?;
+    if root.version > latest_version {
+        anyhow::bail!(
+            "The registry served root version {latest_version}, older than the trusted version {}",
+            root.version
+        );
+    }
+    while root.version < latest_version {
+        let next_signed =
+            api.registry_root(Some(root.version + 1))
+                .await?
+                .ok_or(anyhow::anyhow!(
+                    "The registry is missing root version {}",
+                    root.version + 1
+                ))?;
+        let next = next_signed.document::<RegistryRoot>()?;
+        if next.version != root.version + 1
+            || !is_signed_by(&next_signed, &root.root_keys)
+            || !is_signed_by(&next_signed, &next.root_keys)
+        {
+            anyhow::bail!(
+                "Root version {} is not signed by the keys of the trusted root version {}",
+                root.version + 1,
+                root.version
+            );
+        }
+        signed_root = next_signed;
+        root = next;
+    }
+    let now = timestamp();
+    if root.expires_at < now {
+        anyhow::bail!("The registry root version {} has expired", root.version);
+    }
+
+    let signed = api.snapshot().await?.ok_or(anyhow::anyhow!(
+        "The registry has not signed a snapshot yet"
+    ))?;
+    if !is_signed_by(&signed, &root.snapshot_keys) {
+        anyhow::bail!("The registry snapshot is not signed by a trusted snapshot key");
+    }
+    let snapshot = signed.document::<Snapshot>()?;
+    if snapshot.expires_at < now {
+        anyhow::bail!("The registry snapshot {} has expired", snapshot.id);
+    }
+    let seen = trusted.map(|t| t.snapshot_id).unwrap_or_default();
+    if snapshot.id < seen {
+        anyhow::bail!(
+            "The registry served snapshot {}, older than snapshot {seen} seen before",
+            snapshot.id
+        );
+    }
+
+    if let Some(parent) = path.parent() {
+        std::fs::create_dir_all(parent)?;
+    }
+    std::fs::write(
+        &path,
+        serde_json::to_vec(&TrustedRegistry {
+            root: signed_root,
+            snapshot_id: snapshot.id,
+        })?,
+    )?;
+    Ok(Some(snapshot))
+}
+
+/// The verified snapshot of the registry. `None` if the registry doesn't sign snapshots and
+/// hasn't before.
+pub async fn current(api: &OnyxApi) -> Result<Option<&'static Snapshot>> {
+    SNAPSHOT
+        .get_or_try_init(|| load(api))
+        .await
+        .map(Option::as_ref)
+        .context("Failed to verify the registry snapshot")
+}
+
+/// The snapshot `current` loaded, if it was called.
+pub fn loaded() -> Option<&'static Snapshot> {
+    SNAPSHOT.get().and_then(Option::as_ref)
+}
+
+/// Check the version id the registry served for `package_name` at `version_name` against
+/// `snapshot`. `version_id` is `None` if the registry didn't list the version.
+pub fn verify(
+    snapshot: &Snapshot,
+    package_name: &str,
+    version_name: &str,
+    version_id: Option<&HashId>,
+) -> Result<()> {
+    let expected = snapshot
+        .packages
+        .get(package_name)
+        .and_then(|versions| versions.get(version_name));
+    match (expected, version_id) {
+        (Some(expected), Some(id)) if *expected == id.to_string() => Ok(()),
+        (Some(expected), id) => anyhow::bail!(
+            "The registry served {} for \"{package_name}\" version \"{version_name}\", its signed snapshot {} lists {expected}",
+            id.map(|id| id.to_string())
+                .unwrap_or("no version".to_string()),
+            snapshot.id
+        ),
+        (None, _) => Err(Failure::new(
+            FailureCode::UntrustedRegistry,
+            format!(
+                "\"{package_name}\" version \"{version_name}\" is not in the registry's signed snapshot {}",
+                snapshot.id
+            ),
+        )
+        .with_advice(
+            "Versions are added to the snapshot shortly after they're published, try again in a minute.",
+        )
+        .into()),
+    }
+}
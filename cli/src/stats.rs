@@ -0,0 +1,100 @@
+use std::collections::HashMap;
+use std::path::PathBuf;
+use std::time::Duration;
+
+use anyhow::Result;
+use nargo_parse::*;
+use onyx_api::prelude::*;
+use serde::Deserialize;
+use serde::Serialize;
+
+use crate::config;
+use crate::config::Source;
+use crate::output::summary;
+use crate::report::Resolution;
+
+/// Don't hold up a finished install waiting on the registry.
+const REPORT_TIMEOUT: Duration = Duration::from_secs(2);
+
+/// Whether the user opted in to reporting installs, before it was the `stats` setting.
+#[derive(Serialize, Deserialize, Default)]
+struct StatsConfig {
+    enabled: bool,
+}
+
+fn legacy_config_path() -> Result<PathBuf> {
+    Ok(config::config_dir()?.join("stats.toml"))
+}
+
+fn load_legacy() -> StatsConfig {
+    legacy_config_path()
+        .and_then(|path| Ok(std::fs::read_to_string(path)?))
+        .ok()
+        .and_then(|str| toml::from_str(&str).ok())
+        .unwrap_or_default()
+}
+
+/// Whether the user opted in to reporting installs. Off unless `nrpm stats enable` was run.
+pub fn is_enabled() -> bool {
+    match config::get("stats") {
+        Some((value, Source::File)) => value == "true",
+        _ => load_legacy().enabled,
+    }
+}
+
+pub fn set_enabled(enabled: bool) -> Result<()> {
+    config::write("stats", Some(&enabled.to_string()))?;
+    print_enabled(enabled);
+    Ok(())
+}
+
+pub fn print_status() {
+    print_enabled(is_enabled());
+}
+
+fn print_enabled(enabled: bool) {
+    if enabled {
+        summary!("📊 Reporting installs is on, turn it off with: nrpm stats disable");
+    } else {
+        summary!("📊 Reporting installs is off, turn it on with: nrpm stats enable");
+    }
+    summary!(
+        "    Only the name and version of registry packages installed without downloading them from the registry, e.g. cloned with git, are sent so they're counted as downloads. Nothing identifies you or your machine."
+    );
+}
+
+/// Report the registry packages an install fetched without the registry's download endpoint,
+/// if the user opted in. Failures are ignored, they only affect statistics.
+pub async fn report(
+    api: &OnyxApi,
+    all_dependencies: &HashMap<String, (PathBuf, Dependency, NargoConfig)>,
+    resolution: &Resolution,
+) {
+    if !is_enabled() {
+        return;
+    }
+    let registry_prefix = format!("{}/", super::registry_url());
+    let mut installs = vec![];
+    for identifier in resolution.uncounted_fetches() {
+        let Some((_, dep, _)) = all_dependencies.get(identifier) else {
+            continue;
+        };
+        if let (Some(git), Some(tag)) = (&dep.git, &dep.tag)
+            && let Some(package_name) = git.strip_prefix(&registry_prefix)
+        {
+            installs.push(PackageInstall {
+                package_name: package_name.to_string(),
+                version_name: tag.clone(),
+            });
+        }
+    }
+    if installs.is_empty() {
+        return;
+    }
+    let request = InstallStatsRequest { installs };
+    match tokio::time::timeout(REPORT_TIMEOUT, api.report_installs(&request)).await {
+        Ok(Ok(())) => {}
+        Ok(Err(e)) => log::debug!("failed to report installs: {e:?}"),
+        Err(_) => log::debug!("timed out reporting installs"),
+    }
+}
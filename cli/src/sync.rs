@@ -0,0 +1,165 @@
+use std::collections::BTreeMap;
+use std::collections::HashSet;
+use std::path::PathBuf;
+
+use anyhow::Context;
+use anyhow::Result;
+use indicatif::ProgressBar;
+use nargo_parse::*;
+
+use crate::cache;
+use crate::failure::Failure;
+use crate::failure::FailureCode;
+use crate::install;
+use crate::lockfile;
+use crate::lockfile::LockEntry;
+use crate::lockfile::Lockfile;
+use crate::output::say;
+use crate::output::summary;
+use crate::registry;
+
+/// Make the dependencies of the package at `path` match its nrpm.lock.
+///
+/// Every locked package is downloaded into the system cache and checked against its locked
+/// hash. Nargo.toml git dependencies are moved to the tag they're locked at, locked packages
+/// that nothing else depends on are added, and dependencies that aren't locked are reported.
+pub fn sync(path: PathBuf) -> Result<()> {
+    let lockfile_path = path.join("nrpm.lock");
+    if !lockfile_path.exists() {
+        anyhow::bail!("No nrpm.lock found in {path:?}, run nrpm install to create one");
+    }
+    let lockfile = Lockfile::load_or_init(&lockfile_path)?;
+    let root_pkg = NargoConfig::load(&path)
+        .with_context(|| "Unable to find a Nargo.toml in the target directory")?;
+    let dep_cache_path = cache::cache_path()?;
+    let _cache_lock = cache::lock_cache(&dep_cache_path, &ProgressBar::hidden())?;
+
+    // package id keyed to the lock entry and the locked package's Nargo config, see
+    // `lockfile::package_id`
+    let mut locked = BTreeMap::<String, (LockEntry, NargoConfig)>::default();
+    for entry in lockfile.entries() {
+        let dep = entry.dependency();
+        let dep_root_path = dep.folder_path(&dep_cache_path)?;
+        let dep_lock = cache::lock_dependency(
+            &dep_cache_path,
+            &dep_root_path,
+            &entry.identifier(),
+            &ProgressBar::hidden(),
+        )?;
+        if !std::fs::exists(&dep_root_path)? {
+            let bar = install::download_bar(&dep)?.with_prefix(entry.identifier());
+            install::clone_dependency(&dep, &dep_root_path, &bar)?;
+            bar.finish_and_clear();
+            say!("🌨️  Downloaded {}", entry.identifier());
+        }
+        drop(dep_lock);
+        let hash = nrpm_tarball::hash_dir(&dep_root_path)?.to_string();
+        if hash != entry.blake3 {
+            Err(anyhow::Error::new(
+                Failure::new(
+                    FailureCode::LockfileMismatch,
+                    "integrity check failed, halting",
+                )
+                .with_advice("Consider deleting the local copy and running sync again.")
+                .with_path(&dep_root_path),
+            )
+            .context(format!("computed hash: {hash}"))
+            .context(format!("expected hash: {}", entry.blake3))
+            .context(format!("location: {dep_root_path:?}"))
+            .context(format!(
+                "hash mismatch for locked package: {}",
+                entry.identifier()
+            )))?;
+        }
+        let config = NargoConfig::load(&dep_root_path)
+            .context(format!("located at: {dep_root_path:?}"))
+            .context(format!(
+                "failed to load Nargo.toml for locked package {}",
+                entry.identifier()
+            ))?;
+        cache::link_into_nargo(&dep_cache_path, [dep_root_path.as_path()])?;
+        locked.insert(entry.package_id(), (entry.clone(), config));
+    }
+
+    // locked packages that another locked package depends on
+    let mut indirect = HashSet::<String>::default();
+    for (_, config) in locked.values() {
+        for dep in config.dependencies()?.values() {
+            if !dep.is_local() {
+                indirect.insert(lockfile::package_id(dep)?);
+            }
+        }
+    }
+
+    let mut satisfied = HashSet::<String>::default();
+    // locked dev-dependencies are left as they are
+    for dep in root_pkg.dev_dependencies()?.values() {
+        if !dep.is_local() {
+            satisfied.insert(lockfile::package_id(dep)?);
+        }
+    }
+    let mut replacements = vec![];
+    let mut extraneous = vec![];
+    for (name, dep) in root_pkg.dependencies()? {
+        // path dependencies are never locked
+        let Some(git) = dep.git.as_ref() else {
+            continue;
+        };
+        let package_id = lockfile::package_id(dep)?;
+        if locked.contains_key(&package_id) {
+            satisfied.insert(package_id);
+            continue;
+        }
+        // registry packages are the same package by name, wherever they're cloned from
+        let package_name = registry::of(dep)?.map(|(_, package_name)| package_name);
+        let relocked = locked.iter().find(|(id, (entry, _))| {
+            let same_package = match (&entry.name, &package_name) {
+                (Some(locked_name), Some(package_name)) => locked_name == package_name,
+                _ => &entry.git == git,
+            };
+            same_package && !indirect.contains(*id)
+        });
+        match relocked {
+            Some((id, (entry, _))) => {
+                let mut replacement = dep.clone();
+                say!(
+                    "🔁 {name}: {} -> {}",
+                    replacement.tag.as_deref().unwrap_or_default(),
+                    entry.tag
+                );
+                satisfied.insert(id.clone());
+                replacement.tag = Some(entry.tag.clone());
+                replacements.push(replacement);
+            }
+            None => extraneous.push(name),
+        }
+    }
+    let missing = locked
+        .iter()
+        .filter(|(id, _)| !satisfied.contains(*id) && !indirect.contains(*id))
+        .map(|(_, (entry, config))| {
+            say!("➕ {}: {}", config.package.name, entry.tag);
+            let mut dep = entry.dependency();
+            dep.name = config.package.name.clone();
+            dep
+        })
+        .collect::<Vec<_>>();
+
+    let nargo_path = path.join("Nargo.toml");
+    if !replacements.is_empty() {
+        let source = std::fs::read_to_string(&nargo_path)?;
+        std::fs::write(
+            &nargo_path,
+            NargoConfig::replace_dependencies(&source, &replacements)?,
+        )?;
+    }
+    if !missing.is_empty() {
+        NargoConfig::add_dependencies_in_place(&nargo_path, missing)
+            .context("Failed to write locked dependencies to Nargo.toml")?;
+    }
+    for name in &extraneous {
+        summary!("⚠️  {name}: in Nargo.toml but not in nrpm.lock, run nrpm install to lock it");
+    }
+    summary!("✅ {} locked packages in sync", locked.len());
+    Ok(())
+}
@@ -0,0 +1,86 @@
+use std::path::PathBuf;
+use std::time::Duration;
+
+use anyhow::Result;
+use onyx_api::prelude::*;
+use serde::Deserialize;
+use serde::Serialize;
+
+use crate::config;
+use crate::output;
+
+/// Ask the registry for the latest release at most this often, in seconds.
+const CHECK_INTERVAL: u64 = 24 * 60 * 60;
+/// Don't hold up a finished command waiting on the registry.
+const CHECK_TIMEOUT: Duration = Duration::from_secs(2);
+
+/// The latest release as of the last time the registry was asked.
+#[derive(Serialize, Deserialize)]
+struct LastCheck {
+    checked_at: u64,
+    /// `None` if the registry didn't announce a release.
+    latest: Option<String>,
+}
+
+fn last_check_path() -> Result<PathBuf> {
+    Ok(config::config_dir()?.join("update_check.toml"))
+}
+
+/// The latest nrpm release, from the last check if it was less than a day ago.
+async fn latest_release(api: &OnyxApi) -> Result<Option<String>> {
+    let path = last_check_path()?;
+    let last_check = std::fs::read_to_string(&path)
+        .ok()
+        .and_then(|str| toml::from_str::<LastCheck>(&str).ok());
+    if let Some(last_check) = last_check
+        && last_check.checked_at + CHECK_INTERVAL > timestamp()
+    {
+        return Ok(last_check.latest);
+    }
+    // a registry that is slow or doesn't announce releases is asked again tomorrow
+    let latest = match tokio::time::timeout(CHECK_TIMEOUT, api.cli_version()).await {
+        Ok(Ok(latest)) => Some(latest),
+        Ok(Err(e)) => {
+            log::debug!("registry did not return the latest nrpm release: {e:?}");
+            None
+        }
+        Err(_) => None,
+    };
+    let last_check = LastCheck {
+        checked_at: timestamp(),
+        latest,
+    };
+    std::fs::write(&path, toml::to_string(&last_check)?)?;
+    Ok(last_check.latest)
+}
+
+/// Tell the user if a newer nrpm has been released. Turn on the `no_update_notice` setting to
+/// never check.
+pub async fn notify(api: &OnyxApi) {
+    if config::flag("no_update_notice") {
+        return;
+    }
+    let latest = match latest_release(api).await {
+        Ok(Some(latest)) => latest,
+        Ok(None) => return,
+        Err(e) => {
+            log::debug!("failed to check for a newer nrpm: {e:?}");
+            return;
+        }
+    };
+    let current = clap::crate_version!();
+    let is_newer = match (
+        semver::Version::parse(&latest),
+        semver::Version::parse(current),
+    ) {
+        (Ok(latest), Ok(current)) => latest > current,
+        _ => false,
+    };
+    if is_newer {
+        eprintln!(
+            "{} nrpm {latest} is available, you have {current}",
+            output::icon("💡", "help:")
+        );
+        eprintln!("    Update with: cargo install nrpm --locked");
+    }
+}
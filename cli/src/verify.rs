@@ -0,0 +1,141 @@
+use std::path::Path;
+use std::path::PathBuf;
+use std::str::FromStr;
+
+use anyhow::Context;
+use anyhow::Result;
+use onyx_api::prelude::*;
+
+use crate::config;
+use crate::index;
+use crate::lockfile::Lockfile;
+use crate::output::summary;
+use crate::snapshot;
+
+fn log_file(api_url: &str) -> Result<PathBuf> {
+    let registry_dir = api_url
+        .chars()
+        .map(|c| if c.is_ascii_alphanumeric() { c } else { '_' })
+        .collect::<String>();
+    Ok(config::config_dir()?
+        .join("log")
+        .join(format!("{registry_dir}.json")))
+}
+
+/// Check `head` extends the head of the transparency log seen last time, then remember it.
+async fn check_log_head(api: &OnyxApi, head: &LogHead) -> Result<()> {
+    let path = log_file(&api.url)?;
+    let seen = match std::fs::read_to_string(&path) {
+        Ok(str) => Some(
+            serde_json::from_str::<LogHead>(&str)
+                .with_context(|| format!("Failed to parse {path:?}"))?,
+        ),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => None,
+        Err(e) => return Err(e.into()),
+    };
+    if let Some(seen) = seen {
+        if seen.tree_size > head.tree_size {
+            anyhow::bail!(
+                "The registry's transparency log has {} entries, fewer than the {} seen before",
+                head.tree_size,
+                seen.tree_size
+            );
+        }
+        let proof = api
+            .log_consistency_proof(seen.tree_size, head.tree_size)
+            .await?;
+        if !proof.verify(&seen, head) {
+            anyhow::bail!(
+                "The registry's transparency log at {} entries doesn't extend the log seen before at {} entries",
+                head.tree_size,
+                seen.tree_size
+            );
+        }
+    }
+    if let Some(parent) = path.parent() {
+        std::fs::create_dir_all(parent)?;
+    }
+    std::fs::write(&path, serde_json::to_vec(head)?)?;
+    Ok(())
+}
+
+/// Check the registry packages in the nrpm.lock of the package at `path` against the
+/// registry: each locked version must still be published with the locked hash, and be in the
+/// registry's signed snapshot if it signs them. With `check_log` each must also be included
+/// in the registry's transparency log, and the log must extend the one seen last time.
+pub async fn verify(api: &OnyxApi, path: &Path, check_log: bool) -> Result<()> {
+    let lockfile_path = path.join("nrpm.lock");
+    if !lockfile_path.exists() {
+        anyhow::bail!("No nrpm.lock found in {path:?}, run nrpm install to create one");
+    }
+    let lockfile = Lockfile::load_or_init(&lockfile_path)?;
+    let registry_prefix = format!("{}/", super::registry_url());
+    let snapshot = snapshot::current(api).await?;
+    let head = match (check_log, snapshot.and_then(|s| s.log.clone())) {
+        (false, _) => None,
+        (true, Some(head)) => Some(head),
+        (true, None) => Some(api.log_head().await?),
+    };
+    if let Some(head) = &head {
+        check_log_head(api, head)
+            .await
+            .context("Failed to verify the registry's transparency log")?;
+    }
+
+    let mut verified = 0;
+    for entry in lockfile.entries() {
+        let Some(package_name) = entry.git.strip_prefix(&registry_prefix) else {
+            continue;
+        };
+        let locked_id = HashId::from_str(&entry.blake3)
+            .with_context(|| format!("Invalid hash in nrpm.lock for {}", entry.identifier()))?;
+        let package = index::load(api, package_name)
+            .await
+            .context(format!("Unable to load package \"{package_name}\""))?;
+        let version = package.position(&entry.tag).map(|i| &package.versions[i]);
+        let version_name = version.map_or(&entry.tag, |version| &version.name);
+        if let Some(snapshot) = snapshot {
+            snapshot::verify(snapshot, package_name, version_name, version.map(|v| &v.id))?;
+        }
+        match version {
+            Some(version) if version.id == locked_id => {}
+            Some(version) => anyhow::bail!(
+                "The registry serves {} for \"{package_name}\" version \"{version_name}\", nrpm.lock has {}",
+                version.id.to_string(),
+                entry.blake3
+            ),
+            None => anyhow::bail!(
+                "\"{package_name}\" version \"{version_name}\" is no longer published"
+            ),
+        }
+        if let Some(head) = &head {
+            let proof = api
+                .log_inclusion_proof(&locked_id, Some(head.tree_size))
+                .await
+                .context(format!(
+                    "Unable to prove \"{package_name}\" version \"{version_name}\" is in the transparency log"
+                ))?;
+            if proof.entry.package_name != package_name
+                || &proof.entry.version_name != version_name
+                || proof.entry.version_id != locked_id
+                || !proof.verify(head)
+            {
+                anyhow::bail!(
+                    "The transparency log doesn't include \"{package_name}\" version \"{version_name}\" with hash {}",
+                    entry.blake3
+                );
+            }
+        }
+        verified += 1;
+    }
+
+    summary!("✅ Verified {verified} registry packages in nrpm.lock");
+    if let Some(head) = head {
+        summary!(
+            "🪵 Each is included in the transparency log of {} entries, root {}",
+            head.tree_size,
+            head.root_hash.to_string()
+        );
+    }
+    Ok(())
+}
@@ -0,0 +1,98 @@
+use std::path::Path;
+use std::process::Command;
+
+use anyhow::Context;
+use anyhow::Result;
+use nargo_parse::NargoDocument;
+use semver::Version;
+
+use crate::failure::Failure;
+use crate::failure::FailureCode;
+use crate::output::summary;
+
+/// The version after `current` for a bump of `major`, `minor` or `patch`, or the explicit
+/// version `target`. A pre-release bumps to its release first, like npm.
+pub fn next_version(current: &Version, target: &str) -> Result<Version> {
+    let pre = !current.pre.is_empty();
+    let next = match target {
+        "major" if pre && current.minor == 0 && current.patch == 0 => {
+            Version::new(current.major, 0, 0)
+        }
+        "major" => Version::new(current.major + 1, 0, 0),
+        "minor" if pre && current.patch == 0 => Version::new(current.major, current.minor, 0),
+        "minor" => Version::new(current.major, current.minor + 1, 0),
+        "patch" if pre => Version::new(current.major, current.minor, current.patch),
+        "patch" => Version::new(current.major, current.minor, current.patch + 1),
+        version => Version::parse(version.trim_start_matches('v')).with_context(|| {
+            format!("\"{version}\" is not major, minor, patch or a semver version")
+        })?,
+    };
+    if next <= *current {
+        anyhow::bail!("The new version {next} must be greater than the current version {current}");
+    }
+    Ok(next)
+}
+
+pub fn git(dir: &Path, args: &[&str]) -> Result<String> {
+    let output = Command::new("git")
+        .arg("-C")
+        .arg(dir)
+        .args(args)
+        .output()
+        .context("Failed to run git, is it installed?")?;
+    if !output.status.success() {
+        anyhow::bail!(
+            "git {} failed: {}",
+            args.join(" "),
+            String::from_utf8_lossy(&output.stderr).trim()
+        );
+    }
+    Ok(String::from_utf8(output.stdout)?.trim().to_string())
+}
+
+/// Set the version of the package at `path` to the version after its current one, see
+/// `next_version`. If `commit`, commit the Nargo.toml change and tag it `vX.Y.Z`.
+pub fn bump(path: &Path, target: &str, commit: bool) -> Result<Version> {
+    let nargo_path = path.join("Nargo.toml");
+    let mut doc = NargoDocument::load(path)?;
+    let config = doc.config()?;
+    let current = config.package.version.as_deref().ok_or(anyhow::anyhow!(
+        "version field is not present in package section"
+    ))?;
+    let current = Version::parse(current)
+        .with_context(|| format!("Failed to parse version as semver: {current}"))?;
+    let next = next_version(&current, target)?;
+    let tag = format!("v{next}");
+    if commit {
+        // fail before touching Nargo.toml
+        git(path, &["rev-parse", "--is-inside-work-tree"]).context(
+            Failure::new(
+                FailureCode::NotInGitRepository,
+                format!("{path:?} is not in a git repository"),
+            )
+            .with_advice("Run without --commit to only update Nargo.toml."),
+        )?;
+        if git(
+            path,
+            &["rev-parse", "-q", "--verify", &format!("refs/tags/{tag}")],
+        )
+        .is_ok()
+        {
+            anyhow::bail!("git tag {tag} already exists");
+        }
+    }
+
+    doc.set_version(&next.to_string())?;
+    doc.save(path)?;
+    summary!("🔖 {} {current} -> {next}", config.package.name);
+
+    if commit {
+        let nargo_path = nargo_path.to_string_lossy();
+        git(path, &["add", "--", &nargo_path])?;
+        // only Nargo.toml is committed, whatever else is staged stays staged
+        git(path, &["commit", "-m", &tag, "--", &nargo_path])?;
+        git(path, &["tag", &tag])?;
+        summary!("🏷️  Committed and tagged {tag}");
+    }
+    Ok(next)
+}
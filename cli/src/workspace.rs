@@ -0,0 +1,130 @@
+use std::path::Path;
+use std::path::PathBuf;
+
+use anyhow::Context;
+use anyhow::Result;
+use nargo_parse::*;
+
+use super::registry;
+use super::registry_url;
+
+/// A package in a workspace.
+pub struct Member {
+    pub dir: PathBuf,
+    pub config: NargoConfig,
+    /// `(dependency name, index of the member)` for each path dependency on another member.
+    member_dependencies: Vec<(String, usize)>,
+}
+
+impl Member {
+    pub fn name(&self) -> &str {
+        &self.config.package.name
+    }
+
+    pub fn version(&self) -> Result<&str> {
+        self.config
+            .package
+            .version
+            .as_deref()
+            .ok_or(anyhow::anyhow!(
+                "no version field in Nargo.toml package section of \"{}\"",
+                self.name()
+            ))
+    }
+
+    /// Indices of the members this member depends on.
+    pub fn member_dependencies(&self) -> impl Iterator<Item = usize> {
+        self.member_dependencies.iter().map(|(_, j)| *j)
+    }
+}
+
+/// Load the members of the workspace at `root`, ordered so that every member comes after the
+/// members it depends on.
+pub fn publish_order(root: &Path, workspace: &Workspace) -> Result<Vec<Member>> {
+    let mut members = vec![];
+    for member in &workspace.members {
+        let dir = root
+            .join(member)
+            .canonicalize()
+            .with_context(|| format!("Workspace member {member:?} does not exist"))?;
+        let config = NargoConfig::load(&dir)
+            .with_context(|| format!("Failed to load Nargo.toml of workspace member {member:?}"))?;
+        members.push(Member {
+            dir,
+            config,
+            member_dependencies: vec![],
+        });
+    }
+
+    for i in 0..members.len() {
+        let mut member_dependencies = vec![];
+        for (name, dep) in members[i].config.dependencies()? {
+            let Some(path) = dep.path.as_ref() else {
+                continue;
+            };
+            let Ok(dep_dir) = members[i].dir.join(path).canonicalize() else {
+                continue;
+            };
+            if let Some(j) = members.iter().position(|m| m.dir == dep_dir) {
+                member_dependencies.push((name.clone(), j));
+            }
+        }
+        members[i].member_dependencies = member_dependencies;
+    }
+
+    // repeatedly take the first member, in declared order, whose dependencies are placed
+    let mut order = vec![];
+    while order.len() < members.len() {
+        let next = (0..members.len()).find(|i| {
+            !order.contains(i)
+                && members[*i]
+                    .member_dependencies
+                    .iter()
+                    .all(|(_, j)| order.contains(j))
+        });
+        match next {
+            Some(i) => order.push(i),
+            None => {
+                let cycle = (0..members.len())
+                    .filter(|i| !order.contains(i))
+                    .map(|i| members[i].name().to_string())
+                    .collect::<Vec<_>>();
+                anyhow::bail!(
+                    "Workspace members depend on each other in a cycle: {}",
+                    cycle.join(", ")
+                );
+            }
+        }
+    }
+
+    let mut members = members.into_iter().map(Some).collect::<Vec<_>>();
+    let mut ordered = order
+        .iter()
+        .map(|i| members[*i].take().expect("each member is ordered once"))
+        .collect::<Vec<_>>();
+    // indices now refer to positions in the ordered list
+    for member in &mut ordered {
+        for (_, j) in &mut member.member_dependencies {
+            *j = order
+                .iter()
+                .position(|i| *i == *j)
+                .expect("dependency is ordered");
+        }
+    }
+    Ok(ordered)
+}
+
+/// Registry dependencies replacing the path dependencies of `member` on other members, at
+/// the versions they'll be published as.
+pub fn member_replacements(member: &Member, members: &[Member]) -> Result<Vec<Dependency>> {
+    let mut replacements = vec![];
+    for (name, j) in &member.member_dependencies {
+        let dependency = &members[*j];
+        replacements.push(Dependency::new_git(
+            name.clone(),
+            format!("{}/{}", registry_url(), dependency.name()),
+            registry::tag(dependency.version()?),
+        ));
+    }
+    Ok(replacements)
+}
@@ -0,0 +1,97 @@
+//! Compare reading the dependencies of a large tree of packages from the map parsed at load
+//! against re-parsing the toml table on every call, as `NargoConfig::dependencies` used to.
+//!
+//! cargo bench -p nargo_parse --bench dependencies
+
+use std::collections::HashMap;
+use std::hint::black_box;
+use std::time::Duration;
+use std::time::Instant;
+
+use anyhow::Result;
+use nargo_parse::Dependency;
+use nargo_parse::NargoConfig;
+
+const PACKAGES: usize = 500;
+const DEPENDENCIES: usize = 20;
+/// `nrpm install` reads the dependencies of each package about this many times.
+const READS_PER_PACKAGE: usize = 3;
+
+fn nargo_toml(i: usize) -> String {
+    let mut str = format!("[package]\nname = \"pkg_{i}\"\nversion = \"0.1.0\"\n\n[dependencies]\n");
+    for j in 0..DEPENDENCIES {
+        str.push_str(&format!(
+            "dep_{j} = {{ git = \"https://nrpm.io/dep_{j}\", tag = \"0.{i}.{j}\" }}\n"
+        ));
+    }
+    str
+}
+
+/// The previous implementation, converting each entry of the table on every call.
+fn reparse(table: &toml::Table) -> Result<HashMap<String, Dependency>> {
+    let mut dependencies = HashMap::new();
+    for (name, val) in table {
+        let mut dep = val.clone().try_into::<Dependency>()?;
+        dep.name = name.clone();
+        dependencies.insert(name.clone(), dep);
+    }
+    Ok(dependencies)
+}
+
+fn time(name: &str, iterations: u32, mut f: impl FnMut() -> Result<usize>) -> Result<Duration> {
+    let start = Instant::now();
+    for _ in 0..iterations {
+        black_box(f()?);
+    }
+    let per_iter = start.elapsed() / iterations;
+    println!("{name:<10} {per_iter:>12.2?}/iter");
+    Ok(per_iter)
+}
+
+fn main() -> Result<()> {
+    // `cargo test --all-targets` runs this once as a smoke test
+    let iterations = if std::env::args().any(|arg| arg == "--bench") {
+        20
+    } else {
+        1
+    };
+    let sources = (0..PACKAGES).map(nargo_toml).collect::<Vec<_>>();
+    let configs = sources
+        .iter()
+        .map(|s| NargoConfig::from_str(s))
+        .collect::<Result<Vec<_>>>()?;
+    let tables = sources
+        .iter()
+        .map(|s| {
+            Ok(s.parse::<toml::Table>()?["dependencies"]
+                .as_table()
+                .cloned()
+                .unwrap())
+        })
+        .collect::<Result<Vec<_>>>()?;
+
+    println!("{PACKAGES} packages, {DEPENDENCIES} dependencies each, {READS_PER_PACKAGE} reads");
+    let reparsed = time("reparse", iterations, || {
+        let mut count = 0;
+        for table in &tables {
+            for _ in 0..READS_PER_PACKAGE {
+                count += reparse(table)?.len();
+            }
+        }
+        Ok(count)
+    })?;
+    let cached = time("cached", iterations, || {
+        let mut count = 0;
+        for config in &configs {
+            for _ in 0..READS_PER_PACKAGE {
+                count += config.dependencies()?.len();
+            }
+        }
+        Ok(count)
+    })?;
+    println!(
+        "cached is {:.0}x faster",
+        reparsed.as_secs_f64() / cached.as_secs_f64().max(f64::EPSILON)
+    );
+    Ok(())
+}
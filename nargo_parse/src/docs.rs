@@ -0,0 +1,421 @@
+use std::ffi::OsStr;
+use std::path::Component;
+use std::path::Path;
+
+use serde::Deserialize;
+use serde::Serialize;
+
+/// Documentation for the public api of a package, extracted from the doc comments in its
+/// `.nr` sources.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[cfg_attr(feature = "schemars", derive(schemars::JsonSchema))]
+pub struct PackageDocs {
+    /// Sorted by module path, the crate root first.
+    pub modules: Vec<ModuleDocs>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[cfg_attr(feature = "schemars", derive(schemars::JsonSchema))]
+pub struct ModuleDocs {
+    /// Module path relative to the crate root, e.g. `hash::poseidon`. Empty for the root.
+    pub path: String,
+    /// Inner doc comments (`//!`) at the top of the module.
+    pub doc: String,
+    pub items: Vec<DocItem>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+#[cfg_attr(feature = "schemars", derive(schemars::JsonSchema))]
+pub enum DocItemKind {
+    Function,
+    Struct,
+    Trait,
+    Global,
+    Type,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[cfg_attr(feature = "schemars", derive(schemars::JsonSchema))]
+pub struct DocItem {
+    pub kind: DocItemKind,
+    pub name: String,
+    /// The type a method is implemented on, e.g. `Poseidon<N>`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub parent: Option<String>,
+    /// Declaration without the body. Structs include their fields.
+    pub signature: String,
+    /// Outer doc comments (`///`) preceding the item, markdown.
+    pub doc: String,
+}
+
+/// Extract docs from the files of a package. Files outside of `src` or not ending in `.nr`
+/// are ignored. Parsing is best effort: anything that isn't recognized is skipped rather
+/// than reported.
+pub fn extract_docs<'a>(files: impl IntoIterator<Item = (&'a Path, &'a str)>) -> PackageDocs {
+    let mut modules = files
+        .into_iter()
+        .filter_map(|(path, source)| {
+            let module_path = module_path(path)?;
+            let (doc, items) = parse_module(source);
+            if doc.is_empty() && items.is_empty() {
+                return None;
+            }
+            Some(ModuleDocs {
+                path: module_path,
+                doc,
+                items,
+            })
+        })
+        .collect::<Vec<_>>();
+    modules.sort_by(|a, b| a.path.cmp(&b.path));
+    PackageDocs { modules }
+}
+
+/// `src/lib.nr` and `src/main.nr` are the root, `src/a/b.nr` and `src/a/b/mod.nr` are `a::b`.
+fn module_path(path: &Path) -> Option<String> {
+    if path.extension()? != "nr" {
+        return None;
+    }
+    let mut components = path.components();
+    if components.next()? != Component::Normal(OsStr::new("src")) {
+        return None;
+    }
+    let mut segments = components
+        .map(|c| match c {
+            Component::Normal(segment) => segment.to_str(),
+            _ => None,
+        })
+        .collect::<Option<Vec<_>>>()?;
+    let file_stem = segments.pop()?.trim_end_matches(".nr");
+    if !(segments.is_empty() && (file_stem == "lib" || file_stem == "main")) && file_stem != "mod" {
+        segments.push(file_stem);
+    }
+    Some(segments.join("::"))
+}
+
+#[derive(Debug, PartialEq)]
+enum Token<'a> {
+    OuterDoc(&'a str),
+    InnerDoc(&'a str),
+    Ident(&'a str),
+    Punct(char),
+}
+
+/// Split source into tokens with their byte ranges. Ordinary comments, whitespace and the
+/// contents of string literals are dropped.
+fn tokenize(source: &str) -> Vec<(Token<'_>, usize, usize)> {
+    let bytes = source.as_bytes();
+    let mut tokens = vec![];
+    let mut i = 0;
+    while i < bytes.len() {
+        let start = i;
+        let c = bytes[i];
+        if c.is_ascii_whitespace() {
+            i += 1;
+        } else if source[i..].starts_with("//") {
+            let end = source[i..].find('\n').map(|n| i + n).unwrap_or(bytes.len());
+            let line = &source[i..end];
+            if line.starts_with("///") && !line.starts_with("////") {
+                tokens.push((Token::OuterDoc(&line[3..]), start, end));
+            } else if let Some(doc) = line.strip_prefix("//!") {
+                tokens.push((Token::InnerDoc(doc), start, end));
+            }
+            i = end;
+        } else if source[i..].starts_with("/*") {
+            i = source[i + 2..]
+                .find("*/")
+                .map(|n| i + 2 + n + 2)
+                .unwrap_or(bytes.len());
+        } else if c == b'"' {
+            i += 1;
+            while i < bytes.len() && bytes[i] != b'"' {
+                if bytes[i] == b'\\' {
+                    i += 1;
+                }
+                i += 1;
+            }
+            i = (i + 1).min(bytes.len());
+            tokens.push((Token::Punct('"'), start, i));
+        } else if c.is_ascii_alphanumeric() || c == b'_' {
+            while i < bytes.len() && (bytes[i].is_ascii_alphanumeric() || bytes[i] == b'_') {
+                i += 1;
+            }
+            tokens.push((Token::Ident(&source[start..i]), start, i));
+        } else {
+            let ch = source[i..].chars().next().unwrap_or_default();
+            i += ch.len_utf8();
+            tokens.push((Token::Punct(ch), start, i));
+        }
+    }
+    tokens
+}
+
+/// Join doc comment lines, removing the single space conventionally written after the
+/// comment marker.
+fn join_doc(lines: &[&str]) -> String {
+    lines
+        .iter()
+        .map(|line| line.strip_prefix(' ').unwrap_or(line))
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+fn collapse_whitespace(str: &str) -> String {
+    str.split_whitespace().collect::<Vec<_>>().join(" ")
+}
+
+fn parse_module(source: &str) -> (String, Vec<DocItem>) {
+    let tokens = tokenize(source);
+    let mut module_doc = vec![];
+    let mut pending_doc = vec![];
+    let mut items = vec![];
+    let mut depth = 0usize;
+    // the type of the impl block we're in, and the depth of its body
+    let mut impl_block: Option<(String, usize)> = None;
+    let mut i = 0;
+    while i < tokens.len() {
+        match &tokens[i].0 {
+            Token::InnerDoc(line) => {
+                if depth == 0 {
+                    module_doc.push(*line);
+                }
+            }
+            Token::OuterDoc(line) => pending_doc.push(*line),
+            Token::Punct('#') => {
+                // attributes sit between a doc comment and its item, skip over them
+                if matches!(tokens.get(i + 1), Some((Token::Punct('['), _, _))) {
+                    let mut brackets = 0;
+                    for (j, (token, _, _)) in tokens.iter().enumerate().skip(i + 1) {
+                        match token {
+                            Token::Punct('[') => brackets += 1,
+                            Token::Punct(']') => brackets -= 1,
+                            _ => {}
+                        }
+                        if brackets == 0 {
+                            i = j;
+                            break;
+                        }
+                    }
+                }
+            }
+            Token::Punct('{') => {
+                depth += 1;
+                pending_doc.clear();
+            }
+            Token::Punct('}') => {
+                depth = depth.saturating_sub(1);
+                if impl_block.as_ref().is_some_and(|(_, d)| depth < *d) {
+                    impl_block = None;
+                }
+                pending_doc.clear();
+            }
+            Token::Ident("impl") if depth == 0 => {
+                let end = find_punct(&tokens, i, &['{', ';']);
+                impl_block = end.map(|end| (impl_target(source, &tokens[i..end]), 1));
+                pending_doc.clear();
+            }
+            Token::Ident("pub") if depth == 0 || (depth == 1 && impl_block.is_some()) => {
+                if let Some(mut item) = parse_item(source, &tokens, i) {
+                    item.doc = join_doc(&pending_doc);
+                    item.parent = impl_block.as_ref().map(|(target, _)| target.clone());
+                    items.push(item);
+                }
+                pending_doc.clear();
+            }
+            _ => pending_doc.clear(),
+        }
+        i += 1;
+    }
+    (join_doc(&module_doc), items)
+}
+
+/// Index of the first of `puncts` at or after `start`, outside of parentheses.
+fn find_punct(tokens: &[(Token, usize, usize)], start: usize, puncts: &[char]) -> Option<usize> {
+    let mut parens = 0i32;
+    for (j, (token, _, _)) in tokens.iter().enumerate().skip(start) {
+        match token {
+            Token::Punct('(') => parens += 1,
+            Token::Punct(')') => parens -= 1,
+            Token::Punct(c) if parens == 0 && puncts.contains(c) => return Some(j),
+            _ => {}
+        }
+    }
+    None
+}
+
+/// The implementing type from an impl header, e.g. `Foo<T>` from `impl<T> Foo<T> where ...`.
+fn impl_target(source: &str, header: &[(Token, usize, usize)]) -> String {
+    let mut start = 1;
+    // skip the generic parameters of the impl
+    if matches!(header.get(1), Some((Token::Punct('<'), _, _))) {
+        let mut angles = 0;
+        for (j, (token, _, _)) in header.iter().enumerate().skip(1) {
+            match token {
+                Token::Punct('<') => angles += 1,
+                Token::Punct('>') => angles -= 1,
+                _ => {}
+            }
+            if angles == 0 {
+                start = j + 1;
+                break;
+            }
+        }
+    }
+    let end = header
+        .iter()
+        .position(|(token, _, _)| *token == Token::Ident("where"))
+        .unwrap_or(header.len());
+    if start >= end {
+        return String::new();
+    }
+    let (start, end) = (header[start].1, header[end - 1].2);
+    collapse_whitespace(&source[start..end])
+}
+
+fn parse_item(source: &str, tokens: &[(Token, usize, usize)], pub_index: usize) -> Option<DocItem> {
+    let mut i = pub_index + 1;
+    // `pub(crate)` items aren't part of the public api
+    if matches!(tokens.get(i), Some((Token::Punct('('), _, _))) {
+        return None;
+    }
+    while let Some((Token::Ident("unconstrained" | "comptime" | "unsafe"), _, _)) = tokens.get(i) {
+        i += 1;
+    }
+    let kind = match tokens.get(i)?.0 {
+        Token::Ident("fn") => DocItemKind::Function,
+        Token::Ident("struct") => DocItemKind::Struct,
+        Token::Ident("trait") => DocItemKind::Trait,
+        Token::Ident("global") => DocItemKind::Global,
+        Token::Ident("type") => DocItemKind::Type,
+        _ => return None,
+    };
+    let Token::Ident(name) = tokens.get(i + 1)?.0 else {
+        return None;
+    };
+    let start = tokens[pub_index].1;
+    let end = find_punct(tokens, i, &['{', ';'])?;
+    let signature = match (kind, &tokens[end].0) {
+        (DocItemKind::Struct, Token::Punct('{')) => {
+            // include the fields, keeping the original formatting
+            let mut braces = 0;
+            let close = tokens.iter().skip(end).position(|(token, _, _)| {
+                match token {
+                    Token::Punct('{') => braces += 1,
+                    Token::Punct('}') => braces -= 1,
+                    _ => {}
+                }
+                braces == 0
+            })?;
+            source[start..tokens[end + close].2].trim().to_string()
+        }
+        (DocItemKind::Global | DocItemKind::Type, _) => {
+            let end = find_punct(tokens, i, &[';'])?;
+            collapse_whitespace(&source[start..tokens[end].1])
+        }
+        _ => collapse_whitespace(&source[start..tokens[end].1]),
+    };
+    Some(DocItem {
+        kind,
+        name: name.to_string(),
+        parent: None,
+        signature,
+        doc: String::new(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SOURCE: &str = r#"
+//! Hashing utilities.
+//!
+//! Nothing here is audited.
+
+use dep::std;
+
+/// Hash two field elements.
+///
+/// ```
+/// let h = hash_2([1, 2]);
+/// ```
+#[inline]
+pub fn hash_2(input: [Field; 2]) -> Field {
+    let s = "pub fn not_an_item() {}";
+    input[0] + input[1]
+}
+
+// not a doc comment
+fn private_helper() {}
+
+/// A sponge.
+pub struct Sponge<let N: u32> {
+    /// Absorbed state.
+    pub state: [Field; N],
+}
+
+impl<let N: u32> Sponge<N> {
+    /// Create an empty sponge.
+    pub fn new() -> Self {
+        Sponge { state: [0; N] }
+    }
+
+    fn private_method(self) {}
+}
+
+pub(crate) fn internal() {}
+
+/// The field modulus bit size.
+pub global BITS: u32 = 254;
+"#;
+
+    #[test]
+    fn should_extract_docs() {
+        let (doc, items) = parse_module(SOURCE);
+        assert_eq!(doc, "Hashing utilities.\n\nNothing here is audited.");
+        assert_eq!(
+            items
+                .iter()
+                .map(|item| (item.kind, item.name.as_str(), item.parent.as_deref()))
+                .collect::<Vec<_>>(),
+            vec![
+                (DocItemKind::Function, "hash_2", None),
+                (DocItemKind::Struct, "Sponge", None),
+                (DocItemKind::Function, "new", Some("Sponge<N>")),
+                (DocItemKind::Global, "BITS", None),
+            ]
+        );
+        assert_eq!(
+            items[0].signature,
+            "pub fn hash_2(input: [Field; 2]) -> Field"
+        );
+        assert_eq!(
+            items[0].doc,
+            "Hash two field elements.\n\n```\nlet h = hash_2([1, 2]);\n```"
+        );
+        assert!(items[1].signature.ends_with("pub state: [Field; N],\n}"));
+        assert_eq!(items[2].doc, "Create an empty sponge.");
+        assert_eq!(items[3].signature, "pub global BITS: u32 = 254");
+    }
+
+    #[test]
+    fn should_map_module_paths() {
+        assert_eq!(module_path(Path::new("src/lib.nr")).as_deref(), Some(""));
+        assert_eq!(module_path(Path::new("src/main.nr")).as_deref(), Some(""));
+        assert_eq!(
+            module_path(Path::new("src/hash.nr")).as_deref(),
+            Some("hash")
+        );
+        assert_eq!(
+            module_path(Path::new("src/hash/poseidon.nr")).as_deref(),
+            Some("hash::poseidon")
+        );
+        assert_eq!(
+            module_path(Path::new("src/hash/mod.nr")).as_deref(),
+            Some("hash")
+        );
+        assert_eq!(module_path(Path::new("Nargo.toml")), None);
+        assert_eq!(module_path(Path::new("test/lib.nr")), None);
+    }
+}
@@ -0,0 +1,397 @@
+use std::fmt;
+use std::path::Path;
+use std::path::PathBuf;
+use std::str::FromStr;
+
+use anyhow::Context;
+use anyhow::Result;
+use toml_edit::DocumentMut;
+use toml_edit::Item;
+use toml_edit::TableLike;
+use toml_edit::Value;
+
+use super::Dependency;
+use super::NargoConfig;
+
+/// A `Nargo.toml` opened for editing. Mutations only touch the entries they change, comments,
+/// ordering and formatting of everything else are kept as written. Line endings are written as
+/// `\n`.
+#[derive(Debug, Clone)]
+pub struct NargoDocument {
+    doc: DocumentMut,
+}
+
+impl FromStr for NargoDocument {
+    type Err = anyhow::Error;
+
+    fn from_str(str: &str) -> Result<Self> {
+        Ok(Self {
+            doc: str.parse::<DocumentMut>()?,
+        })
+    }
+}
+
+impl fmt::Display for NargoDocument {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.doc.fmt(f)
+    }
+}
+
+/// `path` may be either a `Nargo.toml` file, or a directory containing a `Nargo.toml` file.
+fn nargo_path(path: &Path) -> PathBuf {
+    if path.is_dir() {
+        path.join("Nargo.toml")
+    } else {
+        path.to_path_buf()
+    }
+}
+
+/// Set `key` in `table` to `value`, keeping the whitespace and comments around an existing value.
+fn set_value(table: &mut dyn TableLike, key: &str, mut value: Value) {
+    match table.get_mut(key) {
+        Some(Item::Value(existing)) => {
+            *value.decor_mut() = existing.decor().clone();
+            *existing = value;
+        }
+        _ => {
+            table.insert(key, Item::Value(value));
+        }
+    }
+}
+
+impl NargoDocument {
+    /// Read the `Nargo.toml` at `path`, either the file or a directory containing it.
+    pub fn load(path: &Path) -> Result<Self> {
+        let nargo_path = nargo_path(path);
+        std::fs::read_to_string(&nargo_path)
+            .with_context(|| format!("Unable to read {:?}", nargo_path))?
+            .parse()
+            .with_context(|| format!("Unable to parse {:?}", nargo_path))
+    }
+
+    /// Write the document to `path`, either the file or a directory containing it.
+    pub fn save(&self, path: &Path) -> Result<()> {
+        std::fs::write(nargo_path(path), self.to_string())?;
+        Ok(())
+    }
+
+    /// Parse the document as it currently reads.
+    pub fn config(&self) -> Result<NargoConfig> {
+        NargoConfig::from_str(&self.to_string())
+    }
+
+    fn dependencies_mut(&mut self) -> Result<Option<&mut dyn TableLike>> {
+        match self.doc.get_mut("dependencies") {
+            None => Ok(None),
+            Some(item) => Ok(Some(
+                item.as_table_like_mut()
+                    .ok_or(anyhow::anyhow!("dependencies is not a table in Nargo.toml"))?,
+            )),
+        }
+    }
+
+    fn package_mut(&mut self) -> Result<&mut dyn TableLike> {
+        self.doc
+            .get_mut("package")
+            .and_then(|p| p.as_table_like_mut())
+            .ok_or(anyhow::anyhow!(
+                "package section is missing from Nargo.toml"
+            ))
+    }
+
+    /// Add a dependency as an inline table, creating the dependencies section if needed. Fails if
+    /// a dependency with the same name exists.
+    pub fn add_dependency(&mut self, dep: &Dependency) -> Result<()> {
+        if self.doc.get("dependencies").is_none() {
+            self.doc
+                .insert("dependencies", Item::Table(toml_edit::Table::new()));
+        }
+        let table = dep.to_inline_table()?;
+        let dependencies = self.dependencies_mut()?.expect("dependencies should exist");
+        if dependencies.contains_key(&dep.name) {
+            anyhow::bail!(
+                "package \"{}\" already exists in Nargo.toml dependencies\nRemove the existing entry to install",
+                dep.name
+            );
+        }
+        dependencies.insert(&dep.name, toml_edit::value(table));
+        Ok(())
+    }
+
+    /// Remove the dependency named `name`. Fails if there's no such dependency.
+    pub fn remove_dependency(&mut self, name: &str) -> Result<()> {
+        let removed = self
+            .dependencies_mut()?
+            .and_then(|dependencies| dependencies.remove(name));
+        if removed.is_none() {
+            anyhow::bail!("package \"{name}\" is not a dependency");
+        }
+        Ok(())
+    }
+
+    /// Update the dependency with the name of `dep` to point where `dep` does. Fields `dep`
+    /// leaves unset are removed, fields nrpm doesn't read are left as they are.
+    pub fn update_dependency(&mut self, dep: &Dependency) -> Result<()> {
+        let entry = self
+            .dependencies_mut()?
+            .and_then(|dependencies| dependencies.get_mut(&dep.name))
+            .ok_or(anyhow::anyhow!(
+                "package \"{}\" is not a dependency",
+                dep.name
+            ))?;
+        let table = entry.as_table_like_mut().ok_or(anyhow::anyhow!(
+            "dependency \"{}\" is not a table in Nargo.toml",
+            dep.name
+        ))?;
+        for (key, val) in [
+            ("git", &dep.git),
+            ("tag", &dep.tag),
+            ("path", &dep.path),
+            ("directory", &dep.directory),
+            ("registry", &dep.registry),
+        ] {
+            match val {
+                Some(val) => set_value(table, key, Value::from(val.as_str())),
+                None => {
+                    table.remove(key);
+                }
+            }
+        }
+        if let Some(table) = entry.as_inline_table_mut() {
+            // keys may have been added or removed, keep the spacing consistent
+            table.fmt();
+        }
+        Ok(())
+    }
+
+    /// Set the version of the package. `version` must be semver.
+    pub fn set_version(&mut self, version: &str) -> Result<()> {
+        semver::Version::parse(version)
+            .with_context(|| format!("Failed to parse version as semver: {version}"))?;
+        self.set_package_value("version", Some(Value::from(version)))
+    }
+
+    /// Set or remove the description of the package.
+    pub fn set_description(&mut self, description: Option<&str>) -> Result<()> {
+        self.set_package_value("description", description.map(Value::from))
+    }
+
+    /// Set or remove the SPDX license expression of the package.
+    pub fn set_license(&mut self, license: Option<&str>) -> Result<()> {
+        self.set_package_value("license", license.map(Value::from))
+    }
+
+    /// Set or remove the repository url of the package.
+    pub fn set_repository(&mut self, repository: Option<&str>) -> Result<()> {
+        self.set_package_value("repository", repository.map(Value::from))
+    }
+
+    /// Set or remove the keywords of the package.
+    pub fn set_keywords(&mut self, keywords: Option<&[String]>) -> Result<()> {
+        self.set_package_value("keywords", keywords.map(|k| Value::from_iter(k.iter())))
+    }
+
+    /// Set or remove the authors of the package.
+    pub fn set_authors(&mut self, authors: Option<&[String]>) -> Result<()> {
+        self.set_package_value("authors", authors.map(|a| Value::from_iter(a.iter())))
+    }
+
+    fn set_package_value(&mut self, key: &str, value: Option<Value>) -> Result<()> {
+        let package = self.package_mut()?;
+        match value {
+            Some(value) => set_value(package, key, value),
+            None => {
+                package.remove(key);
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const NARGO_TOML: &str = r#"# An application
+[package]
+name = "app"   # the name
+type = "bin"
+version = "0.1.0" # bumped by release tooling
+authors = [
+    "alice",
+]
+
+# libraries we use
+[dependencies]
+a = { git = "https://nrpm.io/a", tag = "0.1.0", features = ["x"] } # pinned
+b = {path="../b"}
+
+# keep last
+[dependencies.c]
+git = "https://nrpm.io/c" # upstream
+tag = "1.0.0"
+
+[metadata]
+audited = true
+"#;
+
+    fn edit(f: impl FnOnce(&mut NargoDocument) -> Result<()>) -> Result<String> {
+        let mut doc = NARGO_TOML.parse::<NargoDocument>()?;
+        f(&mut doc)?;
+        let edited = doc.to_string();
+        // whatever we write must parse back to the same document
+        assert_eq!(edited.parse::<NargoDocument>()?.to_string(), edited);
+        doc.config()?;
+        Ok(edited)
+    }
+
+    #[test]
+    fn should_round_trip_unchanged() -> Result<()> {
+        for source in [
+            NARGO_TOML,
+            "",
+            "[package]\nname = \"a\"\n",
+            "[package]\nname = \"a\"\n\n[dependencies]\n\n\n# nothing yet\n",
+            "[package]\nname='a'\n[dependencies]\nb.git = \"https://nrpm.io/b\"\nb.tag = \"1\"\n",
+        ] {
+            assert_eq!(source.parse::<NargoDocument>()?.to_string(), source);
+        }
+        assert_eq!(edit(|_| Ok(()))?, NARGO_TOML);
+        Ok(())
+    }
+
+    #[test]
+    fn should_add_dependency() -> Result<()> {
+        let dep = Dependency::new_git(
+            "d".to_string(),
+            "https://nrpm.io/d".to_string(),
+            "0.3.0".to_string(),
+        );
+        let edited = edit(|doc| doc.add_dependency(&dep))?;
+        assert_eq!(
+            edited,
+            NARGO_TOML.replace(
+                "b = {path=\"../b\"}\n",
+                "b = {path=\"../b\"}\nd = { git = \"https://nrpm.io/d\", tag = \"0.3.0\" }\n"
+            )
+        );
+
+        let mut duplicate = dep.clone();
+        duplicate.name = "a".to_string();
+        assert!(edit(|doc| doc.add_dependency(&duplicate)).is_err());
+        duplicate.name = "c".to_string();
+        assert!(edit(|doc| doc.add_dependency(&duplicate)).is_err());
+
+        let mut doc = "[package]\nname = \"a\" # hi\n".parse::<NargoDocument>()?;
+        doc.add_dependency(&dep)?;
+        assert_eq!(
+            doc.to_string(),
+            "[package]\nname = \"a\" # hi\n\n[dependencies]\nd = { git = \"https://nrpm.io/d\", tag = \"0.3.0\" }\n"
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn should_remove_dependency() -> Result<()> {
+        let edited = edit(|doc| doc.remove_dependency("b"))?;
+        assert_eq!(edited, NARGO_TOML.replace("b = {path=\"../b\"}\n", ""));
+
+        let edited = edit(|doc| doc.remove_dependency("c"))?;
+        assert_eq!(
+            edited,
+            NARGO_TOML.replace(
+                "# keep last\n[dependencies.c]\ngit = \"https://nrpm.io/c\" # upstream\ntag = \"1.0.0\"\n\n",
+                ""
+            )
+        );
+
+        assert!(edit(|doc| doc.remove_dependency("missing")).is_err());
+        let mut doc = "[package]\nname = \"a\"\n".parse::<NargoDocument>()?;
+        assert!(doc.remove_dependency("a").is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn should_update_dependency() -> Result<()> {
+        let config = NargoConfig::from_str(NARGO_TOML)?;
+        let dependencies = config.dependencies()?;
+
+        let mut a = dependencies["a"].clone();
+        a.tag = Some("0.2.0".to_string());
+        let edited = edit(|doc| doc.update_dependency(&a))?;
+        assert_eq!(
+            edited,
+            NARGO_TOML.replace("0.1.0\", features", "0.2.0\", features")
+        );
+
+        let mut c = dependencies["c"].clone();
+        c.tag = Some("1.1.0".to_string());
+        let edited = edit(|doc| doc.update_dependency(&c))?;
+        assert_eq!(edited, NARGO_TOML.replace("1.0.0", "1.1.0"));
+
+        // a path dependency replaced by its registry version
+        let b = Dependency::new_git(
+            "b".to_string(),
+            "https://nrpm.io/b".to_string(),
+            "0.1.0".to_string(),
+        );
+        let edited = edit(|doc| doc.update_dependency(&b))?;
+        assert_eq!(
+            edited,
+            NARGO_TOML.replace(
+                "b = {path=\"../b\"}",
+                "b = { git = \"https://nrpm.io/b\", tag = \"0.1.0\" }"
+            )
+        );
+
+        let mut missing = b.clone();
+        missing.name = "missing".to_string();
+        assert!(edit(|doc| doc.update_dependency(&missing)).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn should_set_package_fields() -> Result<()> {
+        let edited = edit(|doc| doc.set_version("0.2.0"))?;
+        assert_eq!(
+            edited,
+            NARGO_TOML.replace("version = \"0.1.0\"", "version = \"0.2.0\"")
+        );
+        assert!(edit(|doc| doc.set_version("two")).is_err());
+
+        let edited = edit(|doc| {
+            doc.set_license(Some("MIT"))?;
+            doc.set_description(Some("An app"))
+        })?;
+        assert_eq!(
+            edited,
+            NARGO_TOML.replace(
+                "]\n\n# libraries",
+                "]\nlicense = \"MIT\"\ndescription = \"An app\"\n\n# libraries"
+            )
+        );
+
+        let edited = edit(|doc| doc.set_authors(None))?;
+        assert_eq!(
+            edited,
+            NARGO_TOML.replace("authors = [\n    \"alice\",\n]\n", "")
+        );
+
+        let keywords = ["zk".to_string(), "hash".to_string()];
+        let edited = edit(|doc| {
+            doc.set_keywords(Some(&keywords))?;
+            doc.set_repository(Some("https://github.com/noir-lang/app"))
+        })?;
+        let config = NargoConfig::from_str(&edited)?;
+        assert_eq!(config.package.keywords, Some(keywords.to_vec()));
+        assert_eq!(
+            config.package.repository.as_deref(),
+            Some("https://github.com/noir-lang/app")
+        );
+        assert!(edited.starts_with("# An application\n[package]\nname = \"app\"   # the name\n"));
+
+        let mut doc = "[dependencies]\n".parse::<NargoDocument>()?;
+        assert!(doc.set_version("0.1.0").is_err());
+        Ok(())
+    }
+}
@@ -1,3 +1,4 @@
+use std::collections::BTreeMap;
 use std::collections::HashMap;
 use std::fs::File;
 use std::io::Read;
@@ -11,12 +12,79 @@ use reqwest::Url;
 use serde::Deserialize;
 use serde::Serialize;
 
+mod docs;
+mod document;
+
+pub use docs::*;
+pub use document::NargoDocument;
+
 /// Represents the contents of a `Nargo.toml` file.
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct NargoConfig {
     pub package: Package,
     #[serde(default)]
-    dependencies: toml::Table,
+    dependencies: Dependencies,
+    /// Dependencies only needed to test the package. They're installed for the package being
+    /// installed, never for its dependencies.
+    #[serde(
+        rename = "dev-dependencies",
+        default,
+        skip_serializing_if = "Dependencies::is_empty"
+    )]
+    dev_dependencies: Dependencies,
+    /// Replacements for dependencies anywhere in the dependency tree, keyed by the name they're
+    /// depended on by. Only read from the package being installed.
+    #[serde(default, skip_serializing_if = "Dependencies::is_empty")]
+    patch: Dependencies,
+    /// Sections we don't read, kept so the config round-trips.
+    #[serde(flatten)]
+    other: toml::Table,
+}
+
+/// The `dependencies` section, parsed once when the config is loaded.
+#[derive(Debug, Clone, Default)]
+struct Dependencies {
+    parsed: BTreeMap<String, Dependency>,
+    /// Entries that aren't a valid dependency. Reported by `NargoConfig::dependencies`.
+    invalid: toml::Table,
+}
+
+impl Dependencies {
+    fn is_empty(&self) -> bool {
+        self.parsed.is_empty() && self.invalid.is_empty()
+    }
+}
+
+impl<'de> Deserialize<'de> for Dependencies {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let mut dependencies = Self::default();
+        for (name, val) in toml::Table::deserialize(deserializer)? {
+            match val.clone().try_into::<Dependency>() {
+                Ok(mut dep) => {
+                    dep.name = name.clone();
+                    dependencies.parsed.insert(name, dep);
+                }
+                Err(_) => {
+                    dependencies.invalid.insert(name, val);
+                }
+            }
+        }
+        Ok(dependencies)
+    }
+}
+
+impl Serialize for Dependencies {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        use serde::ser::SerializeMap;
+        let mut map = serializer.serialize_map(Some(self.parsed.len() + self.invalid.len()))?;
+        for (name, dep) in &self.parsed {
+            map.serialize_entry(name, dep)?;
+        }
+        for (name, val) in &self.invalid {
+            map.serialize_entry(name, val)?;
+        }
+        map.end()
+    }
 }
 
 impl NargoConfig {
@@ -42,58 +110,41 @@ impl NargoConfig {
         Self::from_str(&str)
     }
 
+    /// Add dependencies to the Nargo.toml at `path`, keeping the rest of the file as it is.
     pub fn add_dependencies_in_place(path: &Path, new_dependencies: Vec<Dependency>) -> Result<()> {
-        let nargo_path = if path.is_dir() {
-            path.join("Nargo.toml")
-        } else {
-            path.to_path_buf()
-        };
-        let mut str = String::default();
-        File::open(&nargo_path)?.read_to_string(&mut str)?;
-        let mut doc = str.parse::<toml_edit::DocumentMut>()?;
-        if doc.get("dependencies").is_none() {
-            doc.insert(
-                "dependencies",
-                toml_edit::Item::Table(toml_edit::Table::new()),
-            );
-        }
-        let dependencies = doc
-            .get_mut("dependencies")
-            .expect("dependencies should exist");
+        let mut doc = NargoDocument::load(path)?;
         for dep in new_dependencies {
-            if dependencies.get(&dep.name).is_some() {
-                anyhow::bail!(
-                    "package \"{}\" already exists in Nargo.toml dependencies\nRemove the existing entry to install",
-                    dep.name
-                );
-            }
-            let mut table = toml_edit::InlineTable::new();
-            for (key, val) in dep.to_value() {
-                table.insert(&key, val.into());
-            }
-            dependencies
-                .as_table_mut()
-                .ok_or(anyhow::anyhow!("dependencies is not a table in Nargo.toml"))?
-                .insert(&dep.name, table.into());
+            doc.add_dependency(&dep)?;
         }
-        std::fs::write(&nargo_path, doc.to_string())?;
+        doc.save(path)
+    }
 
-        Ok(())
+    /// Replace entries in the dependencies section of the Nargo.toml `source`, keeping the
+    /// rest of the file as it is. Each replacement is matched to an entry by name.
+    pub fn replace_dependencies(source: &str, replacements: &[Dependency]) -> Result<String> {
+        let mut doc = source.parse::<NargoDocument>()?;
+        for dep in replacements {
+            doc.update_dependency(dep)?;
+        }
+        Ok(doc.to_string())
     }
 
-    /// Validates package metadata. Currently does semver validation for version field.
+    /// Validates package metadata. Currently does semver validation for version field. A
+    /// leading `v`, like in a git tag, is allowed; registries drop it.
     pub fn validate_metadata(&self) -> Result<()> {
-        semver::Version::parse(self.package.version.as_ref().ok_or(anyhow::anyhow!(
+        let version = self.package.version.as_ref().ok_or(anyhow::anyhow!(
             "version field is not present in package section"
-        ))?)
-        .with_context(|| "Failed to parse version as semver")?;
+        ))?;
+        semver::Version::parse(version.strip_prefix('v').unwrap_or(version))
+            .with_context(|| "Failed to parse version as semver")?;
         Ok(())
     }
 
-    /// Check that all the dependencies in this `Nargo.toml` are configured correctly.
-    pub fn validate_dependencies(&self) -> Result<()> {
+    /// Check that all the dependencies in this `Nargo.toml`, which is in `dir`, are configured
+    /// correctly.
+    pub fn validate_dependencies(&self, dir: &Path) -> Result<()> {
         for (name, dep) in self.dependencies()? {
-            dep.valid_or_err().map_err(|e| {
+            dep.valid_or_err(dir).map_err(|e| {
                 anyhow::anyhow!(
                     "in package {} dependency {} is misconfigured: {:?}",
                     self.package.name,
@@ -105,22 +156,71 @@ impl NargoConfig {
         Ok(())
     }
 
-    /// TODO: cache this. Potentially lots of extra parsing here.
-    pub fn dependencies(&self) -> Result<HashMap<String, Dependency>> {
-        let mut dependencies = HashMap::new();
-        for (name, val) in &self.dependencies {
-            if let Ok(mut dep) = val.clone().try_into::<Dependency>() {
-                dep.name = name.clone();
-                dependencies.insert(name.clone(), dep);
-            } else {
-                anyhow::bail!(
-                    "failed to parse dependency {} in package {}",
-                    name,
-                    self.package.name
-                );
-            }
+    /// The dependencies of this package keyed by name. Fails if any entry is misconfigured.
+    pub fn dependencies(&self) -> Result<&BTreeMap<String, Dependency>> {
+        if let Some(name) = self.dependencies.invalid.keys().next() {
+            anyhow::bail!(
+                "failed to parse dependency {} in package {}",
+                name,
+                self.package.name
+            );
         }
-        Ok(dependencies)
+        Ok(&self.dependencies.parsed)
+    }
+
+    /// The dev-dependencies of this package keyed by name. Fails if any entry is misconfigured.
+    pub fn dev_dependencies(&self) -> Result<&BTreeMap<String, Dependency>> {
+        if let Some(name) = self.dev_dependencies.invalid.keys().next() {
+            anyhow::bail!(
+                "failed to parse dev-dependency {} in package {}",
+                name,
+                self.package.name
+            );
+        }
+        Ok(&self.dev_dependencies.parsed)
+    }
+
+    /// The entries of the `patch` section keyed by the name of the dependency they replace.
+    /// Fails if any entry is misconfigured.
+    pub fn patches(&self) -> Result<&BTreeMap<String, Dependency>> {
+        if let Some(name) = self.patch.invalid.keys().next() {
+            anyhow::bail!(
+                "failed to parse patch {} in package {}",
+                name,
+                self.package.name
+            );
+        }
+        Ok(&self.patch.parsed)
+    }
+}
+
+/// Represents the `workspace` section of a `Nargo.toml` at the root of a workspace.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Workspace {
+    /// Paths of the member packages relative to the workspace root.
+    pub members: Vec<String>,
+    #[serde(rename = "default-member")]
+    pub default_member: Option<String>,
+}
+
+impl Workspace {
+    /// Load the workspace section of a Nargo.toml. Returns `None` if the Nargo.toml doesn't
+    /// describe a workspace.
+    ///
+    /// `path` may be either a `Nargo.toml` file, or a directory containing a `Nargo.toml` file.
+    pub fn load(path: &Path) -> Result<Option<Self>> {
+        let nargo_path = if path.is_dir() {
+            path.join("Nargo.toml")
+        } else {
+            path.to_path_buf()
+        };
+        let str = std::fs::read_to_string(&nargo_path)
+            .with_context(|| format!("Unable to read {:?}", nargo_path))?;
+        #[derive(Deserialize)]
+        struct Root {
+            workspace: Option<Workspace>,
+        }
+        Ok(toml::from_str::<Root>(&str)?.workspace)
     }
 }
 
@@ -133,6 +233,74 @@ pub struct Package {
     pub authors: Option<Vec<String>>,
     pub repository: Option<String>,
     pub keywords: Option<Vec<String>>,
+    /// SPDX license expression, e.g. `MIT OR Apache-2.0`.
+    pub license: Option<String>,
+    /// What nargo builds the package as. Only libraries can be dependencies.
+    #[serde(rename = "type", default, skip_serializing_if = "Option::is_none")]
+    pub package_type: Option<PackageType>,
+    /// Tables of metadata for tools other than nargo, keyed by tool, e.g.
+    /// `[package.metadata.nrpm]`.
+    #[serde(default, skip_serializing_if = "toml::Table::is_empty")]
+    pub metadata: toml::Table,
+    /// Fields we don't read, e.g. `compiler_version`, kept so the package round-trips.
+    #[serde(flatten)]
+    pub other: toml::Table,
+}
+
+/// The `type` of a package.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum PackageType {
+    Lib,
+    Bin,
+    Contract,
+}
+
+impl Package {
+    /// Whether the package can be depended on. Packages without a `type` are assumed to be
+    /// libraries, nargo reports the missing field itself.
+    pub fn is_library(&self) -> bool {
+        self.package_type.is_none_or(|t| t == PackageType::Lib)
+    }
+
+    /// The `[package.metadata.nrpm]` table the registry filters searches by. Each value is a
+    /// string or an array of strings, returned as an array.
+    pub fn nrpm_metadata(&self) -> Result<BTreeMap<String, Vec<String>>> {
+        let Some(table) = self.metadata.get("nrpm") else {
+            return Ok(BTreeMap::default());
+        };
+        let table = table
+            .as_table()
+            .ok_or(anyhow::anyhow!("package.metadata.nrpm must be a table"))?;
+        let mut out = BTreeMap::default();
+        for (key, value) in table {
+            let values = match value {
+                toml::Value::String(value) => vec![value.clone()],
+                toml::Value::Array(values) => values
+                    .iter()
+                    .map(|v| v.as_str().map(str::to_string))
+                    .collect::<Option<Vec<_>>>()
+                    .ok_or(anyhow::anyhow!(
+                        "package.metadata.nrpm.{key} must be a string or an array of strings"
+                    ))?,
+                _ => anyhow::bail!(
+                    "package.metadata.nrpm.{key} must be a string or an array of strings"
+                ),
+            };
+            out.insert(key.clone(), values);
+        }
+        Ok(out)
+    }
+}
+
+impl std::fmt::Display for PackageType {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Lib => write!(f, "lib"),
+            Self::Bin => write!(f, "bin"),
+            Self::Contract => write!(f, "contract"),
+        }
+    }
 }
 
 /// Represents each entry in the `dependencies` section of a `Nargo.toml` file.
@@ -144,6 +312,13 @@ pub struct Dependency {
     pub tag: Option<String>, // Nargo resolves this as a git clone --branch argument: https://github.com/noir-lang/noir/blob/12e90c0d51fc53998a2b75d6fb302d621227accd/tooling/nargo_toml/src/git.rs#L51
     pub directory: Option<String>, // Allows a module to reside inside a subdirectory of a package.
     pub path: Option<String>,
+    /// Name of the registry `git` must point into, one nrpm is configured with. nargo ignores
+    /// it, it pins the dependency to that registry when nrpm installs it.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub registry: Option<String>,
+    /// Fields we don't read, kept so the dependency round-trips.
+    #[serde(flatten)]
+    pub other: toml::Table,
 }
 
 impl Dependency {
@@ -154,9 +329,25 @@ impl Dependency {
             tag: Some(tag),
             directory: None,
             path: None,
+            registry: None,
+            other: toml::Table::new(),
         }
     }
 
+    /// A dependency on `version` of the package `name` in the registry at `registry_url`, as
+    /// `nrpm install <name>` adds it.
+    pub fn registry(name: String, registry_url: &str, version: String) -> Self {
+        let url = format!("{registry_url}/{name}");
+        Self::new_git(name, url, version)
+    }
+
+    /// The dependency as a `[dependencies]` section, formatted as it's added to a Nargo.toml.
+    pub fn to_dependencies_section(&self) -> Result<String> {
+        let mut doc = NargoDocument::from_str("")?;
+        doc.add_dependency(self)?;
+        Ok(doc.to_string())
+    }
+
     pub fn to_value(&self) -> HashMap<String, String> {
         let mut content = HashMap::new();
         if let Some(git) = self.git.as_ref() {
@@ -171,9 +362,30 @@ impl Dependency {
         if let Some(directory) = self.directory.as_ref() {
             content.insert("directory".to_string(), directory.clone());
         }
+        if let Some(registry) = self.registry.as_ref() {
+            content.insert("registry".to_string(), registry.clone());
+        }
         content
     }
 
+    /// The dependency as an inline table for a Nargo.toml, with keys sorted.
+    pub fn to_inline_table(&self) -> Result<toml_edit::InlineTable> {
+        let mut values = self
+            .to_value()
+            .into_iter()
+            .map(|(key, val)| (key, toml_edit::Value::from(val)))
+            .collect::<Vec<_>>();
+        for (key, val) in &self.other {
+            values.push((key.clone(), val.to_string().parse::<toml_edit::Value>()?));
+        }
+        values.sort_by(|(a, _), (b, _)| a.cmp(b));
+        let mut table = toml_edit::InlineTable::new();
+        for (key, val) in values {
+            table.insert(&key, val);
+        }
+        Ok(table)
+    }
+
     pub fn is_local(&self) -> bool {
         self.path.is_some()
     }
@@ -193,14 +405,19 @@ impl Dependency {
     }
 
     /// Validate the dependence configuration. Ensure a proper combination of fields are set, and
-    /// that local dependencies exist.
-    pub fn valid_or_err(&self) -> Result<()> {
+    /// that local dependencies exist. Relative paths are relative to `dir`, the package
+    /// depending on this.
+    pub fn valid_or_err(&self, dir: &Path) -> Result<()> {
         if self.path.is_some() && self.git.is_some() {
             anyhow::bail!("path and git may not both be specified for dependence");
         } else if self.path.is_some() && self.tag.is_some() {
             anyhow::bail!("path and tag may not both be specified for dependence");
         } else if self.git.is_some() && self.tag.is_none() {
             anyhow::bail!("git dependencies must specify a tag");
+        } else if self.registry.is_some() && self.git.is_none() {
+            anyhow::bail!("registry may only be specified for git dependencies");
+        } else if self.registry.as_ref().is_some_and(|r| r.is_empty()) {
+            anyhow::bail!("registry may not be empty");
         }
         if let Some(dir_str) = self.directory.as_ref()
             && PathBuf::from(dir_str).is_absolute()
@@ -210,7 +427,7 @@ impl Dependency {
         if let Some(path_str) = self.path.as_ref() {
             let path = PathBuf::from_str(path_str)
                 .map_err(|_| anyhow::anyhow!("failed to parse path: {}", path_str))?;
-            let canonical = std::fs::canonicalize(&path).map_err(|e| {
+            let canonical = std::fs::canonicalize(dir.join(path)).map_err(|e| {
                 anyhow::anyhow!("failed to canonicalize path: {} {:?}", path_str, e)
             })?;
             match std::fs::metadata(&canonical) {
@@ -257,3 +474,144 @@ impl Dependency {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const NARGO_TOML: &str = r#"
+[package]
+name = "app"
+type = "bin"
+
+[dependencies]
+a = { git = "https://nrpm.io/a", tag = "0.1.0", features = ["x"] }
+b = { path = "../b" }
+c = "not a table"
+
+[dev-dependencies]
+d = { git = "https://nrpm.io/d", tag = "0.1.0" }
+
+[patch]
+a = { path = "../a" }
+
+[metadata]
+audited = true
+"#;
+
+    #[test]
+    fn should_parse_dependencies_once() -> Result<()> {
+        let config = NargoConfig::from_str(NARGO_TOML)?;
+        assert!(config.dependencies().is_err());
+        assert!(config.validate_dependencies(Path::new(".")).is_err());
+
+        let valid = NARGO_TOML.replace("c = \"not a table\"\n", "");
+        let config = NargoConfig::from_str(&valid)?;
+        let dependencies = config.dependencies()?;
+        assert_eq!(dependencies.keys().collect::<Vec<_>>(), ["a", "b"]);
+        assert_eq!(dependencies["a"].name, "a");
+        assert_eq!(dependencies["a"].tag.as_deref(), Some("0.1.0"));
+        assert!(dependencies["b"].is_local());
+        assert_eq!(config.dev_dependencies()?.keys().collect::<Vec<_>>(), ["d"]);
+        assert_eq!(config.patches()?["a"].path.as_deref(), Some("../a"));
+        assert!(!config.other.contains_key("patch"));
+        assert!(!config.other.contains_key("dev-dependencies"));
+        Ok(())
+    }
+
+    #[test]
+    fn should_read_nrpm_metadata() -> Result<()> {
+        let config = NargoConfig::from_str(
+            r#"[package]
+name = "lib"
+type = "lib"
+
+[package.metadata.nrpm]
+backend = ["barretenberg", "plonky2"]
+noir = "1.0"
+
+[package.metadata.other]
+anything = 1
+"#,
+        )?;
+        let metadata = config.package.nrpm_metadata()?;
+        assert_eq!(metadata["backend"], ["barretenberg", "plonky2"]);
+        assert_eq!(metadata["noir"], ["1.0"]);
+        assert_eq!(
+            config.package.metadata["other"]["anything"].as_integer(),
+            Some(1)
+        );
+        let reparsed = NargoConfig::from_str(&toml::to_string(&config)?)?;
+        assert_eq!(reparsed.package.nrpm_metadata()?, metadata);
+
+        let config = NargoConfig::from_str(
+            "[package]\nname = \"lib\"\n\n[package.metadata.nrpm]\nbackend = 1\n",
+        )?;
+        assert!(config.package.nrpm_metadata().is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn should_round_trip_unknown_fields() -> Result<()> {
+        let config = NargoConfig::from_str(NARGO_TOML)?;
+        let reparsed = NargoConfig::from_str(&toml::to_string(&config)?)?;
+        assert_eq!(reparsed.package.package_type, Some(PackageType::Bin));
+        assert!(!reparsed.package.is_library());
+        assert!(!reparsed.package.other.contains_key("type"));
+        assert!(NargoConfig::from_str("[package]\nname = \"app\"\ntype = \"widget\"\n").is_err());
+        assert_eq!(
+            reparsed.other["metadata"]["audited"],
+            toml::Value::Boolean(true)
+        );
+        assert!(reparsed.dependencies.invalid.contains_key("c"));
+        assert_eq!(reparsed.dev_dependencies()?["d"].name, "d");
+        assert_eq!(reparsed.patches()?["a"].name, "a");
+        assert_eq!(
+            reparsed.dependencies.parsed["a"].other["features"],
+            toml::Value::Array(vec!["x".into()])
+        );
+
+        let mut a = config.dependencies.parsed["a"].clone();
+        a.tag = Some("0.2.0".to_string());
+        let source = NargoConfig::replace_dependencies(NARGO_TOML, &[a])?;
+        assert!(
+            source
+                .contains(r#"a = { git = "https://nrpm.io/a", tag = "0.2.0", features = ["x"] }"#)
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn should_format_registry_dependency() -> Result<()> {
+        let dep = Dependency::registry("a".to_string(), "https://nrpm.io", "0.1.0".to_string());
+        assert_eq!(
+            dep.to_dependencies_section()?,
+            "[dependencies]\na = { git = \"https://nrpm.io/a\", tag = \"0.1.0\" }\n"
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn should_parse_registry_dependency() -> Result<()> {
+        let config = NargoConfig::from_str(
+            r#"[package]
+name = "pinned"
+type = "lib"
+
+[dependencies]
+a = { git = "https://example.com/a", tag = "0.1.0", registry = "internal" }
+b = { path = "../b", registry = "internal" }
+"#,
+        )?;
+        let a = &config.dependencies()?["a"];
+        assert_eq!(a.registry.as_deref(), Some("internal"));
+        assert!(a.other.is_empty());
+        assert_eq!(
+            a.to_dependencies_section()?,
+            "[dependencies]\na = { git = \"https://example.com/a\", registry = \"internal\", tag = \"0.1.0\" }\n"
+        );
+        let b = &config.dependencies()?["b"];
+        assert!(b.valid_or_err(Path::new(".")).is_err());
+        Ok(())
+    }
+}
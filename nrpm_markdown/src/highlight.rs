@@ -0,0 +1,212 @@
+/// Classes emitted around highlighted tokens. Colors are defined in `HIGHLIGHT_CSS`.
+pub const HIGHLIGHT_CLASSES: &[&str] = &[
+    "hl-comment",
+    "hl-string",
+    "hl-keyword",
+    "hl-number",
+    "hl-type",
+    "hl-function",
+    "hl-section",
+    "hl-key",
+];
+
+pub const HIGHLIGHT_CSS: &str = "
+.hl-comment { color: #6a737d; font-style: italic; }
+.hl-string { color: #032f62; }
+.hl-keyword { color: #d73a49; }
+.hl-number { color: #005cc5; }
+.hl-type { color: #6f42c1; }
+.hl-function { color: #6f42c1; }
+.hl-section { color: #22863a; font-weight: bold; }
+.hl-key { color: #005cc5; }
+";
+
+const RUST_KEYWORDS: &[&str] = &[
+    "as",
+    "assert",
+    "assert_eq",
+    "break",
+    "comptime",
+    "const",
+    "continue",
+    "crate",
+    "dep",
+    "else",
+    "enum",
+    "false",
+    "fn",
+    "for",
+    "global",
+    "if",
+    "impl",
+    "in",
+    "let",
+    "loop",
+    "match",
+    "mod",
+    "mut",
+    "pub",
+    "return",
+    "self",
+    "Self",
+    "static",
+    "struct",
+    "super",
+    "trait",
+    "true",
+    "type",
+    "unconstrained",
+    "unsafe",
+    "use",
+    "where",
+    "while",
+];
+
+/// Escape text for use in html content or a quoted attribute.
+pub fn escape_html(str: &str) -> String {
+    str.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+        .replace('\'', "&#39;")
+}
+
+fn push_token(out: &mut String, class: Option<&str>, text: &str) {
+    match class {
+        Some(class) => out.push_str(&format!(
+            "<span class=\"{class}\">{}</span>",
+            escape_html(text)
+        )),
+        None => out.push_str(&escape_html(text)),
+    }
+}
+
+/// Highlight a code block as html. Returns `None` for languages we don't have a grammar for.
+pub fn highlight(language: &str, code: &str) -> Option<String> {
+    match language {
+        "rust" | "rs" | "noir" | "nr" => Some(highlight_rust(code)),
+        "toml" => Some(highlight_toml(code)),
+        _ => None,
+    }
+}
+
+/// Noir shares enough syntax with rust that one grammar covers both.
+fn highlight_rust(code: &str) -> String {
+    let chars = code.char_indices().collect::<Vec<_>>();
+    let slice = |from: usize, to: usize| {
+        let start = chars.get(from).map(|(i, _)| *i).unwrap_or(code.len());
+        let end = chars.get(to).map(|(i, _)| *i).unwrap_or(code.len());
+        &code[start..end]
+    };
+    let mut out = String::new();
+    let mut i = 0;
+    while i < chars.len() {
+        let c = chars[i].1;
+        let next = chars.get(i + 1).map(|(_, c)| *c);
+        let start = i;
+        if c == '/' && next == Some('/') {
+            while i < chars.len() && chars[i].1 != '\n' {
+                i += 1;
+            }
+            push_token(&mut out, Some("hl-comment"), slice(start, i));
+        } else if c == '/' && next == Some('*') {
+            i += 2;
+            while i < chars.len()
+                && !(chars[i].1 == '*' && chars.get(i + 1).map(|(_, c)| *c) == Some('/'))
+            {
+                i += 1;
+            }
+            i = (i + 2).min(chars.len());
+            push_token(&mut out, Some("hl-comment"), slice(start, i));
+        } else if c == '"' {
+            i += 1;
+            while i < chars.len() && chars[i].1 != '"' {
+                if chars[i].1 == '\\' {
+                    i += 1;
+                }
+                i += 1;
+            }
+            i = (i + 1).min(chars.len());
+            push_token(&mut out, Some("hl-string"), slice(start, i));
+        } else if c.is_ascii_digit() {
+            while i < chars.len() && (chars[i].1.is_ascii_alphanumeric() || chars[i].1 == '_') {
+                i += 1;
+            }
+            push_token(&mut out, Some("hl-number"), slice(start, i));
+        } else if c.is_alphabetic() || c == '_' {
+            while i < chars.len() && (chars[i].1.is_alphanumeric() || chars[i].1 == '_') {
+                i += 1;
+            }
+            let word = slice(start, i);
+            let class = if RUST_KEYWORDS.contains(&word) {
+                Some("hl-keyword")
+            } else if word.starts_with(char::is_uppercase) {
+                Some("hl-type")
+            } else if chars.get(i).map(|(_, c)| *c) == Some('(') {
+                Some("hl-function")
+            } else {
+                None
+            };
+            push_token(&mut out, class, word);
+        } else {
+            i += 1;
+            push_token(&mut out, None, slice(start, i));
+        }
+    }
+    out
+}
+
+fn highlight_toml(code: &str) -> String {
+    let mut out = String::new();
+    for line in code.split_inclusive('\n') {
+        let trimmed = line.trim_start();
+        let indent = &line[..line.len() - trimmed.len()];
+        out.push_str(indent);
+        if trimmed.starts_with('#') {
+            push_token(&mut out, Some("hl-comment"), trimmed);
+        } else if trimmed.starts_with('[') {
+            push_token(&mut out, Some("hl-section"), trimmed);
+        } else if let Some((key, value)) = trimmed.split_once('=') {
+            push_token(&mut out, Some("hl-key"), key);
+            out.push('=');
+            highlight_toml_value(&mut out, value);
+        } else {
+            highlight_toml_value(&mut out, trimmed);
+        }
+    }
+    out
+}
+
+fn highlight_toml_value(out: &mut String, value: &str) {
+    let mut rest = value;
+    while !rest.is_empty() {
+        let c = rest.chars().next().unwrap_or_default();
+        let len = if c == '"' || c == '\'' {
+            let end = rest[1..].find(c).map(|n| n + 2).unwrap_or(rest.len());
+            push_token(out, Some("hl-string"), &rest[..end]);
+            end
+        } else if c == '#' {
+            let end = rest.find('\n').unwrap_or(rest.len());
+            push_token(out, Some("hl-comment"), &rest[..end]);
+            end
+        } else if c.is_ascii_alphanumeric() || c == '-' || c == '.' {
+            let end = rest
+                .find(|c: char| !(c.is_ascii_alphanumeric() || c == '-' || c == '.' || c == '_'))
+                .unwrap_or(rest.len());
+            let word = &rest[..end];
+            let class = if word == "true" || word == "false" {
+                Some("hl-keyword")
+            } else if word.starts_with(|c: char| c.is_ascii_digit() || c == '-') {
+                Some("hl-number")
+            } else {
+                None
+            };
+            push_token(out, class, word);
+            end
+        } else {
+            push_token(out, None, &rest[..c.len_utf8()]);
+            c.len_utf8()
+        };
+        rest = &rest[len..];
+    }
+}
@@ -0,0 +1,325 @@
+use std::borrow::Cow;
+use std::collections::BTreeMap;
+use std::path::Component;
+use std::path::Path;
+use std::path::PathBuf;
+
+use base64::Engine;
+use percent_encoding::AsciiSet;
+use percent_encoding::NON_ALPHANUMERIC;
+use percent_encoding::utf8_percent_encode;
+
+mod highlight;
+
+pub use highlight::HIGHLIGHT_CLASSES;
+pub use highlight::HIGHLIGHT_CSS;
+pub use highlight::escape_html;
+pub use highlight::highlight;
+
+/// Characters escaped in a query parameter, the same set as `encodeURIComponent`.
+const QUERY_VALUE: &AsciiSet = &NON_ALPHANUMERIC
+    .remove(b'-')
+    .remove(b'_')
+    .remove(b'.')
+    .remove(b'!')
+    .remove(b'~')
+    .remove(b'*')
+    .remove(b'\'')
+    .remove(b'(')
+    .remove(b')');
+
+/// How to resolve relative links and images in a markdown file from a package.
+pub struct LinkBase<'a> {
+    /// Directory of the markdown file within the package.
+    pub dir: &'a Path,
+    pub target: LinkTarget<'a>,
+}
+
+/// Where resolved links should point.
+pub enum LinkTarget<'a> {
+    /// The repository listed in Nargo.toml, assumed to use the GitHub url layout.
+    Repository(&'a str),
+    /// The package's own file browser. Images are inlined from the tarball.
+    FileBrowser {
+        package_name: &'a str,
+        files: &'a BTreeMap<PathBuf, Vec<u8>>,
+    },
+}
+
+/// Resolve a link to a path from the package root, e.g. `./docs/../img.png` in `src` to
+/// `src/img.png`. Links starting with `/` are relative to the package root. Returns `None`
+/// for links that leave the package.
+fn resolve_path(dir: &Path, link: &str) -> Option<PathBuf> {
+    let joined = match link.strip_prefix('/') {
+        Some(link) => PathBuf::from(link),
+        None => dir.join(link),
+    };
+    let mut out = PathBuf::new();
+    for component in joined.components() {
+        match component {
+            Component::Normal(c) => out.push(c),
+            Component::CurDir | Component::RootDir => {}
+            Component::ParentDir => {
+                if !out.pop() {
+                    return None;
+                }
+            }
+            Component::Prefix(_) => return None,
+        }
+    }
+    Some(out)
+}
+
+fn image_mime(path: &Path) -> Option<&'static str> {
+    match path.extension()?.to_str()? {
+        "png" => Some("image/png"),
+        "jpg" | "jpeg" => Some("image/jpeg"),
+        "gif" => Some("image/gif"),
+        "svg" => Some("image/svg+xml"),
+        "webp" => Some("image/webp"),
+        _ => None,
+    }
+}
+
+impl<'a> ammonia::UrlRelativeEvaluate<'a> for LinkBase<'a> {
+    fn evaluate<'url>(&self, url: &'url str) -> Option<Cow<'url, str>> {
+        self.rewrite(url).map(Cow::Owned)
+    }
+}
+
+impl LinkBase<'_> {
+    fn rewrite(&self, url: &str) -> Option<String> {
+        // in-page anchors stay as they are
+        if url.starts_with('#') {
+            return Some(url.to_string());
+        }
+        let (link, fragment) = match url.split_once('#') {
+            Some((link, fragment)) => (link, format!("#{fragment}")),
+            None => (url, String::new()),
+        };
+        let path = resolve_path(self.dir, link)?;
+        let path_str = path.to_string_lossy();
+        match &self.target {
+            LinkTarget::Repository(repository) => {
+                let repository = repository.trim_end_matches('/').trim_end_matches(".git");
+                // images need the raw file, links go to the rendered page
+                let kind = if image_mime(&path).is_some() {
+                    "raw"
+                } else {
+                    "blob"
+                };
+                Some(format!("{repository}/{kind}/HEAD/{path_str}{fragment}"))
+            }
+            LinkTarget::FileBrowser {
+                package_name,
+                files,
+            } => {
+                if let Some(mime) = image_mime(&path) {
+                    let bytes = files.get(&path)?;
+                    let data = base64::engine::general_purpose::STANDARD.encode(bytes);
+                    Some(format!("data:{mime};base64,{data}"))
+                } else if files.contains_key(&path) {
+                    let file = utf8_percent_encode(&path_str, QUERY_VALUE);
+                    Some(format!("/{package_name}?file={file}"))
+                } else {
+                    None
+                }
+            }
+        }
+    }
+}
+
+fn unescape_html(str: &str) -> String {
+    str.replace("&quot;", "\"")
+        .replace("&lt;", "<")
+        .replace("&gt;", ">")
+        .replace("&amp;", "&")
+}
+
+/// Replace the contents of fenced code blocks in a known language with highlighted html.
+fn highlight_code_blocks(html: &str) -> String {
+    const OPEN: &str = "<pre><code class=\"language-";
+    const CLOSE: &str = "</code></pre>";
+    let mut out = String::with_capacity(html.len());
+    let mut rest = html;
+    while let Some(start) = rest.find(OPEN) {
+        let after_open = &rest[start + OPEN.len()..];
+        let (Some(lang_end), Some(code_end)) = (after_open.find("\">"), after_open.find(CLOSE))
+        else {
+            break;
+        };
+        if lang_end > code_end {
+            break;
+        }
+        let language = &after_open[..lang_end];
+        let code = &after_open[lang_end + 2..code_end];
+        out.push_str(&rest[..start]);
+        match highlight(language, &unescape_html(code)) {
+            Some(highlighted) => out.push_str(&format!("{OPEN}{language}\">{highlighted}{CLOSE}")),
+            None => out.push_str(&rest[start..start + OPEN.len() + code_end + CLOSE.len()]),
+        }
+        rest = &after_open[code_end + CLOSE.len()..];
+    }
+    out.push_str(rest);
+    out
+}
+
+/// Render untrusted markdown to sanitized html. Code blocks are highlighted and, if
+/// `link_base` is given, relative links are rewritten against it. Relative links that can't
+/// be resolved are removed.
+pub fn render_markdown(source: &str, link_base: Option<LinkBase>) -> String {
+    let mut options = markdown::Options::gfm();
+    // raw html is common in READMEs, ammonia removes anything unsafe below
+    options.compile.allow_dangerous_html = true;
+    let html = markdown::to_html_with_options(source, &options)
+        .unwrap_or_else(|_| markdown::to_html(source));
+    let html = highlight_code_blocks(&html);
+
+    let Some(link_base) = link_base else {
+        return sanitizer().clean(&html).to_string();
+    };
+    let html = sanitizer()
+        .url_relative(ammonia::UrlRelative::Custom(Box::new(link_base)))
+        .clean(&html)
+        .to_string();
+    // relative links to images were inlined as data urls above, which ammonia doesn't check
+    // the scheme of. Keep them only where they're shown as an image.
+    sanitizer()
+        .add_url_schemes(&["data"])
+        .attribute_filter(|element, attribute, value| {
+            if value.starts_with("data:")
+                && !(element == "img" && attribute == "src" && value.starts_with("data:image/"))
+            {
+                None
+            } else {
+                Some(value.into())
+            }
+        })
+        .clean(&html)
+        .to_string()
+}
+
+/// The ammonia settings shared by both passes of `render_markdown`.
+fn sanitizer<'a>() -> ammonia::Builder<'a> {
+    let mut builder = ammonia::Builder::default();
+    builder
+        .add_allowed_classes("span", HIGHLIGHT_CLASSES)
+        .add_allowed_classes(
+            "code",
+            &[
+                "language-rust",
+                "language-rs",
+                "language-noir",
+                "language-nr",
+                "language-toml",
+            ],
+        );
+    builder
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn should_resolve_paths_within_the_package() {
+        let dir = Path::new("src");
+        assert_eq!(
+            resolve_path(dir, "./docs/../img.png"),
+            Some(PathBuf::from("src/img.png"))
+        );
+        assert_eq!(
+            resolve_path(dir, "/README.md"),
+            Some(PathBuf::from("README.md"))
+        );
+        assert_eq!(
+            resolve_path(dir, "../lib.nr"),
+            Some(PathBuf::from("lib.nr"))
+        );
+        assert_eq!(resolve_path(dir, "../../secret"), None);
+        assert_eq!(resolve_path(Path::new(""), "/../secret"), None);
+    }
+
+    #[test]
+    fn should_rewrite_links_with_fragments() {
+        let link_base = LinkBase {
+            dir: Path::new("docs"),
+            target: LinkTarget::Repository("https://github.com/noir-lang/example.git"),
+        };
+        assert_eq!(link_base.rewrite("#usage"), Some("#usage".to_string()));
+        assert_eq!(
+            link_base.rewrite("guide.md#install"),
+            Some(
+                "https://github.com/noir-lang/example/blob/HEAD/docs/guide.md#install".to_string()
+            )
+        );
+        assert_eq!(
+            link_base.rewrite("../logo.png"),
+            Some("https://github.com/noir-lang/example/raw/HEAD/logo.png".to_string())
+        );
+        assert_eq!(link_base.rewrite("../../outside.md#top"), None);
+    }
+
+    #[test]
+    fn should_link_files_in_the_file_browser() {
+        let files = BTreeMap::from([(PathBuf::from("docs/usage notes.md"), vec![])]);
+        let link_base = LinkBase {
+            dir: Path::new("docs"),
+            target: LinkTarget::FileBrowser {
+                package_name: "example",
+                files: &files,
+            },
+        };
+        assert_eq!(
+            link_base.rewrite("usage notes.md"),
+            Some("/example?file=docs%2Fusage%20notes.md".to_string())
+        );
+        assert_eq!(link_base.rewrite("missing.md"), None);
+    }
+
+    #[test]
+    fn should_highlight_code_blocks() {
+        let html = "<p>a</p><pre><code class=\"language-rust\">fn main() {}</code></pre>";
+        let highlighted = highlight_code_blocks(html);
+        assert!(highlighted.starts_with("<p>a</p><pre><code class=\"language-rust\">"));
+        assert!(highlighted.ends_with("</code></pre>"));
+        assert!(highlighted.contains("<span"));
+
+        // unknown languages are left as they are
+        let html = "<pre><code class=\"language-python\">x = 1 &lt; 2</code></pre>";
+        assert_eq!(highlight_code_blocks(html), html);
+    }
+
+    #[test]
+    fn should_remove_scripts() {
+        let html = render_markdown(
+            "<script>alert(1)</script>\n\n[a](javascript:alert(1)) <a href=\"javascript:alert(1)\">b</a>",
+            None,
+        );
+        assert!(!html.contains("<script"), "{html}");
+        assert!(!html.contains("javascript:"), "{html}");
+    }
+
+    #[test]
+    fn should_only_allow_inlined_images_as_data_urls() {
+        let files = BTreeMap::from([(PathBuf::from("logo.png"), b"png".to_vec())]);
+        let link_base = || LinkBase {
+            dir: Path::new(""),
+            target: LinkTarget::FileBrowser {
+                package_name: "example",
+                files: &files,
+            },
+        };
+        let html = render_markdown("![logo](logo.png)", Some(link_base()));
+        assert!(html.contains("<img src=\"data:image/png;base64,"), "{html}");
+
+        let html = render_markdown(
+            "[logo](logo.png) <a href=\"data:text/html,<script>alert(1)</script>\">a</a> \
+             <img src=\"data:text/html,x\"> <img src=\"data:image/svg+xml,x\">",
+            Some(link_base()),
+        );
+        assert!(!html.contains("data:"), "{html}");
+        let html = render_markdown("<img src=\"data:image/png;base64,x\">", None);
+        assert!(!html.contains("data:"), "{html}");
+    }
+}
@@ -0,0 +1,74 @@
+use std::collections::BTreeMap;
+use std::collections::HashMap;
+use std::collections::VecDeque;
+
+use super::ROOT_IDENTIFIER;
+
+/// How resolved packages depend on each other, by identifier.
+#[derive(Clone, Debug, Default)]
+pub struct Graph {
+    // identifier of a package keyed to the identifiers of its direct dependencies
+    edges: BTreeMap<String, Vec<String>>,
+    // identifier of a dependency keyed to the identifier a `[patch]` entry replaced it with
+    patched: HashMap<String, String>,
+}
+
+impl Graph {
+    pub fn depends_on(&mut self, identifier: &str, dependency: &str) {
+        let dependencies = self.edges.entry(identifier.to_string()).or_default();
+        if !dependencies.iter().any(|d| d == dependency) {
+            dependencies.push(dependency.to_string());
+        }
+    }
+
+    pub fn patched(&mut self, original: &str, identifier: &str) {
+        self.patched
+            .insert(original.to_string(), identifier.to_string());
+    }
+
+    /// Identifiers of the direct dependencies of `identifier`, sorted.
+    pub fn dependencies(&self, identifier: &str) -> Vec<String> {
+        let mut dependencies = self.edges.get(identifier).cloned().unwrap_or_default();
+        dependencies.sort();
+        dependencies
+    }
+
+    /// Whether a dependency declared as `identifier` was replaced by a patch.
+    pub fn is_patched(&self, identifier: &str) -> bool {
+        self.patched.contains_key(identifier)
+    }
+
+    /// Whether `identifier` replaced a dependency because of a patch.
+    pub fn is_patch(&self, identifier: &str) -> bool {
+        self.patched.values().any(|patch| patch == identifier)
+    }
+
+    /// Identifiers of the dependencies `identifier` replaced, sorted.
+    pub fn patched_from(&self, identifier: &str) -> Vec<String> {
+        let mut originals = self
+            .patched
+            .iter()
+            .filter(|(_, patch)| *patch == identifier)
+            .map(|(original, _)| original.clone())
+            .collect::<Vec<_>>();
+        originals.sort();
+        originals
+    }
+
+    /// The fewest dependency edges from the root package to each resolved package. Direct
+    /// dependencies have depth 1.
+    pub fn depths(&self) -> HashMap<String, usize> {
+        let mut depths = HashMap::from([(ROOT_IDENTIFIER.to_string(), 0)]);
+        let mut queue = VecDeque::from([ROOT_IDENTIFIER.to_string()]);
+        while let Some(identifier) = queue.pop_front() {
+            let depth = depths[&identifier];
+            for dependency in self.edges.get(&identifier).into_iter().flatten() {
+                if !depths.contains_key(dependency) {
+                    depths.insert(dependency.clone(), depth + 1);
+                    queue.push_back(dependency.clone());
+                }
+            }
+        }
+        depths
+    }
+}
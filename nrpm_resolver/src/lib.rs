@@ -0,0 +1,148 @@
+use std::collections::BTreeMap;
+use std::collections::BTreeSet;
+use std::collections::HashMap;
+use std::path::Path;
+use std::path::PathBuf;
+
+use anyhow::Context;
+use anyhow::Result;
+use nargo_parse::*;
+
+mod graph;
+mod version;
+
+#[cfg(test)]
+mod proptests;
+
+pub use graph::Graph;
+pub use version::compatible;
+pub use version::newest;
+
+/// Identifier of the package being resolved, the root of the graph.
+pub const ROOT_IDENTIFIER: &str = ".";
+
+/// A package a `Source` made available.
+pub struct Fetched {
+    /// Where the package is, the root of its git repository for git dependencies.
+    pub path: PathBuf,
+    /// Where its Nargo.toml is, `path` joined with the `directory` of the dependency.
+    pub module_path: PathBuf,
+    pub config: NargoConfig,
+}
+
+/// Where resolved packages come from. The resolver only decides which packages are needed,
+/// reading and downloading them is left to the source, so the resolver can run against a
+/// cache on disk, a registry, or packages made up in a test.
+pub trait Source {
+    /// Check `dep`, declared by the package with its Nargo.toml in `dir`, could be fetched, e.g.
+    /// that a path exists or a pinned registry is configured.
+    fn check(&mut self, dep: &Dependency, dir: &Path) -> Result<()>;
+
+    /// Make `dep`, declared by the package with its Nargo.toml in `dir`, available. Each
+    /// identifier is fetched at most once per resolution.
+    fn fetch(&mut self, dep: &Dependency, dir: &Path) -> impl Future<Output = Result<Fetched>>;
+}
+
+/// Every package a resolution reached and how they depend on each other.
+#[derive(Default)]
+pub struct Resolved {
+    /// Identifier keyed to package path (not module path), dependency and Nargo.toml.
+    pub packages: HashMap<String, (PathBuf, Dependency, NargoConfig)>,
+    pub graph: Graph,
+}
+
+/// A package resolved at more than one version, because its dependents ask for different
+/// tags. nargo builds each separately, so it's allowed, but types from one can't be used with
+/// the other.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Conflict {
+    pub git: String,
+    /// Sorted.
+    pub tags: Vec<String>,
+}
+
+impl Resolved {
+    /// Packages resolved at more than one version, sorted by git url.
+    pub fn conflicts(&self) -> Vec<Conflict> {
+        let mut tags = BTreeMap::<&str, BTreeSet<&str>>::new();
+        for (_, dep, _) in self.packages.values() {
+            if let (Some(git), Some(tag)) = (&dep.git, &dep.tag) {
+                tags.entry(git).or_default().insert(tag);
+            }
+        }
+        tags.into_iter()
+            .filter(|(_, tags)| tags.len() > 1)
+            .map(|(git, tags)| Conflict {
+                git: git.to_string(),
+                tags: tags.into_iter().map(str::to_string).collect(),
+            })
+            .collect()
+    }
+}
+
+/// Resolve every direct and indirect dependency of `root`, the Nargo.toml in `root_path`. With
+/// `dev` the dev-dependencies of `root` are included. Dependencies named in `patches` are
+/// replaced by the patch wherever they appear in the graph.
+pub async fn resolve<S: Source>(
+    root: &NargoConfig,
+    root_path: &Path,
+    dev: bool,
+    patches: &HashMap<String, Dependency>,
+    source: &mut S,
+) -> Result<Resolved> {
+    let mut resolved = Resolved::default();
+    let mut pending = vec![(
+        ROOT_IDENTIFIER.to_string(),
+        root_path.to_path_buf(),
+        root.clone(),
+    )];
+    while let Some((pkg_identifier, pkg_path, config)) = pending.pop() {
+        // check every dependency is sane before fetching any
+        let mut dependencies = vec![];
+        for dep in config.dependencies()?.values() {
+            source.check(dep, &pkg_path).with_context(|| {
+                format!(
+                    "in package {} dependency {} is misconfigured",
+                    config.package.name, dep.name
+                )
+            })?;
+            dependencies.push(dep);
+        }
+        if dev && pkg_identifier == ROOT_IDENTIFIER {
+            for dep in config.dev_dependencies()?.values() {
+                source
+                    .check(dep, &pkg_path)
+                    .with_context(|| format!("dev-dependency {} is misconfigured", dep.name))?;
+                dependencies.push(dep);
+            }
+        }
+        for dep in dependencies {
+            let patched;
+            let dep = match patches.get(&dep.name) {
+                Some(patch) => {
+                    patched = Dependency {
+                        name: dep.name.clone(),
+                        ..patch.clone()
+                    };
+                    resolved
+                        .graph
+                        .patched(&dep.identifier()?, &patched.identifier()?);
+                    &patched
+                }
+                None => dep,
+            };
+            let identifier = dep.identifier()?;
+            resolved.graph.depends_on(&pkg_identifier, &identifier);
+            if resolved.packages.contains_key(&identifier) {
+                continue;
+            }
+            let fetched = source.fetch(dep, &pkg_path).await?;
+            resolved.packages.insert(
+                identifier.clone(),
+                (fetched.path, dep.clone(), fetched.config.clone()),
+            );
+            pending.push((identifier, fetched.module_path, fetched.config));
+        }
+    }
+    Ok(resolved)
+}
@@ -0,0 +1,221 @@
+use std::collections::BTreeSet;
+use std::collections::HashMap;
+use std::collections::VecDeque;
+use std::path::Path;
+use std::path::PathBuf;
+
+use anyhow::Result;
+use proptest::prelude::*;
+
+use super::*;
+
+const REGISTRY_URL: &str = "https://registry.test";
+
+/// A registry made up for a test: Nargo.toml keyed by identifier. Records what it fetches.
+#[derive(Debug, Default)]
+struct TestRegistry {
+    packages: HashMap<String, String>,
+    fetched: Vec<String>,
+}
+
+impl TestRegistry {
+    /// Publish `version` of `name`, depending on each `(name, version)` of `dependencies`.
+    fn publish(&mut self, name: &str, version: &str, dependencies: &[(String, String)]) {
+        self.packages.insert(
+            identifier(name, version),
+            nargo_toml(name, version, dependencies),
+        );
+    }
+}
+
+impl Source for TestRegistry {
+    fn check(&mut self, dep: &Dependency, _dir: &Path) -> Result<()> {
+        if !self.packages.contains_key(&dep.identifier()?) {
+            anyhow::bail!("{} is not published", dep.identifier()?);
+        }
+        Ok(())
+    }
+
+    async fn fetch(&mut self, dep: &Dependency, _dir: &Path) -> Result<Fetched> {
+        let identifier = dep.identifier()?;
+        let path = PathBuf::from("/cache").join(&identifier);
+        self.fetched.push(identifier.clone());
+        Ok(Fetched {
+            module_path: path.clone(),
+            path,
+            config: NargoConfig::from_str(&self.packages[&identifier])?,
+        })
+    }
+}
+
+fn identifier(name: &str, version: &str) -> String {
+    format!("{REGISTRY_URL}/{name}@{version}")
+}
+
+fn nargo_toml(name: &str, version: &str, dependencies: &[(String, String)]) -> String {
+    let mut toml = format!(
+        "[package]\nname = \"{name}\"\nversion = \"{version}\"\ntype = \"lib\"\n\n[dependencies]\n"
+    );
+    for (dep_name, dep_version) in dependencies {
+        toml.push_str(&format!(
+            "{dep_name} = {{ git = \"{REGISTRY_URL}/{dep_name}\", tag = \"{dep_version}\" }}\n"
+        ));
+    }
+    toml
+}
+
+fn block_on<T>(future: impl Future<Output = T>) -> T {
+    tokio::runtime::Builder::new_current_thread()
+        .build()
+        .unwrap()
+        .block_on(future)
+}
+
+/// A registry of up to 8 packages with up to 3 versions each, and the dependencies of a root
+/// package on it. Packages only depend on packages after them, so there are no cycles.
+fn registry() -> impl Strategy<Value = (TestRegistry, Vec<(String, String)>)> {
+    let dependencies = || proptest::collection::vec((any::<usize>(), 0..3usize), 0..4);
+    (
+        proptest::collection::vec(proptest::collection::vec(dependencies(), 1..=3), 1..=8),
+        dependencies(),
+    )
+        .prop_map(|(packages, root)| {
+            let count = packages.len();
+            let versions = packages.iter().map(Vec::len).collect::<Vec<_>>();
+            // a dependency of a package before `after` on a version of a package after it
+            let dependency = |after: usize, (package, version): &(usize, usize)| {
+                let j = after + package % (count - after);
+                (format!("p{j}"), format!("0.{}.0", version % versions[j]))
+            };
+            // a package can only depend on one version of each package
+            let dependencies_after = |after: usize, dependencies: &[(usize, usize)]| {
+                let mut dependencies = dependencies
+                    .iter()
+                    .map(|d| dependency(after, d))
+                    .collect::<Vec<_>>();
+                dependencies.sort();
+                dependencies.dedup_by(|a, b| a.0 == b.0);
+                dependencies
+            };
+            let mut registry = TestRegistry::default();
+            for (i, package_versions) in packages.iter().enumerate() {
+                for (v, dependencies) in package_versions.iter().enumerate() {
+                    let dependencies = match i + 1 < count {
+                        true => dependencies_after(i + 1, dependencies),
+                        false => vec![],
+                    };
+                    registry.publish(&format!("p{i}"), &format!("0.{v}.0"), &dependencies);
+                }
+            }
+            let root_dependencies = dependencies_after(0, &root);
+            (registry, root_dependencies)
+        })
+}
+
+/// The identifiers reachable from `root`, found without the resolver.
+fn reachable(registry: &TestRegistry, root: &NargoConfig) -> Result<BTreeSet<String>> {
+    let mut reachable = BTreeSet::new();
+    let mut queue = VecDeque::from([root.clone()]);
+    while let Some(config) = queue.pop_front() {
+        for dep in config.dependencies()?.values() {
+            let identifier = dep.identifier()?;
+            if reachable.insert(identifier.clone()) {
+                queue.push_back(NargoConfig::from_str(&registry.packages[&identifier])?);
+            }
+        }
+    }
+    Ok(reachable)
+}
+
+proptest! {
+    #[test]
+    fn should_resolve_every_reachable_package_once((mut registry, dependencies) in registry()) {
+        let root = NargoConfig::from_str(&nargo_toml("root", "1.0.0", &dependencies)).unwrap();
+        let resolved = block_on(resolve(&root, Path::new("/root"), false, &HashMap::new(), &mut registry)).unwrap();
+        let expected = reachable(&registry, &root).unwrap();
+
+        let fetched = registry.fetched.iter().cloned().collect::<BTreeSet<_>>();
+        prop_assert_eq!(fetched.len(), registry.fetched.len());
+        prop_assert_eq!(&fetched, &expected);
+        prop_assert_eq!(resolved.packages.keys().cloned().collect::<BTreeSet<_>>(), expected);
+
+        let depths = resolved.graph.depths();
+        prop_assert_eq!(depths.len(), resolved.packages.len() + 1);
+        for (identifier, (_, _, config)) in &resolved.packages {
+            let mut declared = config
+                .dependencies()
+                .unwrap()
+                .values()
+                .map(|dep| dep.identifier())
+                .collect::<Result<Vec<_>>>()
+                .unwrap();
+            declared.sort();
+            prop_assert_eq!(resolved.graph.dependencies(identifier), declared);
+            for dependency in resolved.graph.dependencies(identifier) {
+                prop_assert!(depths[&dependency] <= depths[identifier] + 1);
+            }
+        }
+
+        let mut tags = HashMap::<String, BTreeSet<String>>::new();
+        for (_, dep, _) in resolved.packages.values() {
+            tags.entry(dep.git.clone().unwrap()).or_default().insert(dep.tag.clone().unwrap());
+        }
+        let conflicts = resolved.conflicts();
+        prop_assert_eq!(conflicts.len(), tags.values().filter(|tags| tags.len() > 1).count());
+        for conflict in conflicts {
+            prop_assert_eq!(conflict.tags.into_iter().collect::<BTreeSet<_>>(), tags[&conflict.git].clone());
+        }
+    }
+}
+
+#[test]
+fn should_patch_dependencies_throughout_the_graph() -> Result<()> {
+    let mut registry = TestRegistry::default();
+    registry.publish("b", "0.1.0", &[]);
+    registry.publish("b", "0.2.0", &[]);
+    registry.publish("a", "0.1.0", &[("b".to_string(), "0.1.0".to_string())]);
+    let root = NargoConfig::from_str(&format!(
+        "{}\n[dev-dependencies]\nb = {{ git = \"{REGISTRY_URL}/b\", tag = \"0.1.0\" }}\n",
+        nargo_toml("root", "1.0.0", &[("a".to_string(), "0.1.0".to_string())])
+    ))?;
+    let patch = Dependency::new_git(
+        "b".to_string(),
+        format!("{REGISTRY_URL}/b"),
+        "0.2.0".to_string(),
+    );
+    let patches = HashMap::from([("b".to_string(), patch)]);
+
+    let resolved = block_on(resolve(
+        &root,
+        Path::new("/root"),
+        false,
+        &patches,
+        &mut registry,
+    ))?;
+    let (a, b) = (identifier("a", "0.1.0"), identifier("b", "0.2.0"));
+    assert_eq!(
+        resolved.packages.keys().cloned().collect::<BTreeSet<_>>(),
+        BTreeSet::from([a.clone(), b.clone()])
+    );
+    assert!(resolved.graph.is_patched(&identifier("b", "0.1.0")));
+    assert!(resolved.graph.is_patch(&b));
+    assert_eq!(resolved.graph.dependencies(&a), vec![b.clone()]);
+    assert_eq!(resolved.graph.depths()[&b], 2);
+
+    // dependencies are checked before they're patched
+    registry.packages.remove(&identifier("b", "0.1.0"));
+    let e = block_on(resolve(
+        &root,
+        Path::new("/root"),
+        true,
+        &patches,
+        &mut registry,
+    ))
+    .err()
+    .unwrap();
+    assert!(
+        e.to_string().contains("dependency b is misconfigured"),
+        "{e}"
+    );
+    Ok(())
+}
@@ -0,0 +1,62 @@
+use semver::Comparator;
+use semver::Op;
+use semver::Version;
+use semver::VersionReq;
+
+/// The versions semver compatible with `version`, `^version`: the same major version, or the
+/// same minor version below 1.0.0.
+pub fn compatible(version: &Version) -> VersionReq {
+    VersionReq {
+        comparators: vec![Comparator {
+            op: Op::Caret,
+            major: version.major,
+            minor: Some(version.minor),
+            patch: Some(version.patch),
+            pre: version.pre.clone(),
+        }],
+    }
+}
+
+/// The newest of `versions` that `req` allows. Versions that aren't semver are skipped.
+pub fn newest<'a>(
+    versions: impl IntoIterator<Item = &'a str>,
+    req: &VersionReq,
+) -> Option<Version> {
+    versions
+        .into_iter()
+        .filter_map(|v| Version::parse(v).ok())
+        .filter(|v| req.matches(v))
+        .max()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn should_select_newest_compatible_version() {
+        let versions = [
+            "0.1.0",
+            "0.1.3",
+            "0.2.0",
+            "1.0.0",
+            "1.4.2",
+            "2.0.0-rc.1",
+            "main",
+        ];
+        let newest_compatible = |v: &str| {
+            newest(versions, &compatible(&Version::parse(v).unwrap())).map(|v| v.to_string())
+        };
+        assert_eq!(newest_compatible("0.1.0").as_deref(), Some("0.1.3"));
+        assert_eq!(newest_compatible("0.2.0").as_deref(), Some("0.2.0"));
+        assert_eq!(newest_compatible("1.0.0").as_deref(), Some("1.4.2"));
+        assert_eq!(newest_compatible("1.5.0"), None);
+        // prereleases are only allowed by a requirement on the same version
+        assert_eq!(
+            newest(versions, &VersionReq::STAR)
+                .map(|v| v.to_string())
+                .as_deref(),
+            Some("1.4.2")
+        );
+    }
+}
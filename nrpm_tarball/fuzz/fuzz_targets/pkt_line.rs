@@ -0,0 +1,9 @@
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use nrpm_tarball::pkt_line;
+
+fuzz_target!(|data: &[u8]| {
+    let _ = pkt_line::parse(data);
+    let _ = pkt_line::parse_upload_pack_request(data);
+});
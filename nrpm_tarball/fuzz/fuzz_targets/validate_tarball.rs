@@ -0,0 +1,8 @@
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+
+fuzz_target!(|data: &[u8]| {
+    let _ = nrpm_tarball::validate_tarball(data);
+    let _ = nrpm_tarball::extract_metadata(data.to_vec());
+});
@@ -0,0 +1,217 @@
+//! Deltas between two versions of a package, so a large package can be updated by downloading
+//! only the files that changed.
+//!
+//! A delta is a tarball. Its first entry lists the paths removed from the old version,
+//! separated by NUL bytes, and every other entry is a file that was added or changed. The
+//! first entry is recognized by position, so its name can't collide with a package file.
+
+use std::collections::BTreeMap;
+use std::collections::HashMap;
+use std::io::Read;
+use std::path::Component;
+use std::path::Path;
+use std::path::PathBuf;
+
+use anyhow::Context;
+use anyhow::Result;
+use tar::Archive;
+use tar::EntryType;
+
+/// Name of the first entry of a delta.
+const REMOVED_ENTRY: &str = "removed";
+
+/// Create a delta from a version with the file hashes `from` to a version with the files
+/// `to`. `from` is keyed by path relative to the package root, see `hash_file`.
+pub fn create(
+    from: &BTreeMap<PathBuf, blake3::Hash>,
+    to: &HashMap<PathBuf, Vec<u8>>,
+) -> Result<Vec<u8>> {
+    let removed = from
+        .keys()
+        .filter(|path| !to.contains_key(*path))
+        .map(|path| {
+            path.to_str()
+                .map(|path| path.as_bytes())
+                .with_context(|| format!("path contains non-unicode characters: {path:?}"))
+        })
+        .collect::<Result<Vec<_>>>()?
+        .join(&0);
+
+    let mut archive = tar::Builder::new(vec![]);
+    let mut header = tar::Header::new_gnu();
+    header.set_size(removed.len() as u64);
+    header.set_mode(0o644);
+    archive.append_data(&mut header, REMOVED_ENTRY, removed.as_slice())?;
+
+    let mut changed = to.iter().collect::<Vec<_>>();
+    changed.sort_by_key(|(path, _)| *path);
+    for (path, bytes) in changed {
+        if from.get(path) == Some(&super::hash_file(path, bytes)?) {
+            continue;
+        }
+        let mut header = tar::Header::new_gnu();
+        header.set_size(bytes.len() as u64);
+        header.set_mode(0o644);
+        archive.append_data(&mut header, path, bytes.as_slice())?;
+    }
+    Ok(archive.into_inner()?)
+}
+
+/// Only relative paths without `.` or `..` components may be written by a delta.
+fn checked_path(path: &Path) -> Result<&Path> {
+    if path.as_os_str().is_empty()
+        || !path
+            .components()
+            .all(|component| matches!(component, Component::Normal(_)))
+    {
+        anyhow::bail!("only normal path components are allowed in deltas: {path:?}");
+    }
+    if path.components().next() == Some(Component::Normal(".git".as_ref())) {
+        anyhow::bail!("deltas may not write to .git: {path:?}");
+    }
+    Ok(path)
+}
+
+/// Copy the regular files in `from` to `to`, except `.git` and the paths in `skip`.
+fn copy_dir(from: &Path, to: &Path, relative: &Path, skip: &[PathBuf]) -> Result<()> {
+    for entry in std::fs::read_dir(from.join(relative))? {
+        let entry = entry?;
+        let path = relative.join(entry.file_name());
+        if path == Path::new(".git") || skip.contains(&path) {
+            continue;
+        }
+        let file_type = entry.file_type()?;
+        if file_type.is_dir() {
+            copy_dir(from, to, &path, skip)?;
+        } else if file_type.is_file() {
+            std::fs::create_dir_all(to.join(relative))?;
+            std::fs::copy(entry.path(), to.join(&path))?;
+        }
+    }
+    Ok(())
+}
+
+/// Build the new version of a package in the empty directory `to`, from the old version in
+/// `from` and the delta between them.
+///
+/// The delta is untrusted, it can only write files beneath `to`. Check the content hash of
+/// `to` afterwards, nothing here verifies the result is the version that was asked for.
+pub fn apply<R: Read>(from: &Path, delta: R, to: &Path) -> Result<()> {
+    let mut archive = Archive::new(delta);
+    let mut entries = archive.entries()?;
+    let mut removed_entry = entries.next().ok_or(anyhow::anyhow!("delta is empty"))??;
+    if removed_entry.path()?.as_ref() != Path::new(REMOVED_ENTRY) {
+        anyhow::bail!("delta does not start with the removed paths");
+    }
+    let mut removed = vec![];
+    removed_entry.read_to_end(&mut removed)?;
+    let removed = removed
+        .split(|byte| *byte == 0)
+        .filter(|path| !path.is_empty())
+        .map(|path| Ok(checked_path(Path::new(std::str::from_utf8(path)?))?.to_path_buf()))
+        .collect::<Result<Vec<_>>>()?;
+
+    copy_dir(from, to, Path::new(""), &removed)?;
+
+    for entry in entries {
+        let mut entry = entry?;
+        if entry.header().entry_type() != EntryType::Regular {
+            anyhow::bail!("only regular files are allowed in deltas");
+        }
+        let path = checked_path(&entry.path()?)?.to_path_buf();
+        let out_path = to.join(&path);
+        if let Some(parent) = out_path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        let mut bytes = vec![];
+        entry.read_to_end(&mut bytes)?;
+        std::fs::write(out_path, bytes)?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeMap;
+    use std::collections::HashMap;
+    use std::path::PathBuf;
+
+    use anyhow::Result;
+
+    use super::*;
+    use crate::hash_dir;
+    use crate::hash_file;
+
+    fn write_files(dir: &Path, files: &HashMap<PathBuf, Vec<u8>>) -> Result<()> {
+        for (path, bytes) in files {
+            let path = dir.join(path);
+            std::fs::create_dir_all(path.parent().unwrap())?;
+            std::fs::write(path, bytes)?;
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn should_apply_delta() -> Result<()> {
+        let old = HashMap::from([
+            (PathBuf::from("Nargo.toml"), b"[package]\n".to_vec()),
+            (PathBuf::from("src/lib.nr"), b"fn one() {}\n".to_vec()),
+            (PathBuf::from("params/big.bin"), vec![7; 4096]),
+            (PathBuf::from("src/old.nr"), b"fn old() {}\n".to_vec()),
+        ]);
+        let new = HashMap::from([
+            (PathBuf::from("Nargo.toml"), b"[package]\n".to_vec()),
+            (PathBuf::from("src/lib.nr"), b"fn two() {}\n".to_vec()),
+            (PathBuf::from("params/big.bin"), vec![7; 4096]),
+            (PathBuf::from("src/new/mod.nr"), b"fn new() {}\n".to_vec()),
+        ]);
+        let old_hashes = old
+            .iter()
+            .map(|(path, bytes)| Ok((path.clone(), hash_file(path, bytes)?)))
+            .collect::<Result<BTreeMap<_, _>>>()?;
+
+        let delta = create(&old_hashes, &new)?;
+        let mut archive = Archive::new(delta.as_slice());
+        let paths = archive
+            .entries()?
+            .map(|entry| Ok(entry?.path()?.to_path_buf()))
+            .collect::<Result<Vec<_>>>()?;
+        assert_eq!(
+            paths,
+            vec![
+                PathBuf::from(REMOVED_ENTRY),
+                PathBuf::from("src/lib.nr"),
+                PathBuf::from("src/new/mod.nr"),
+            ]
+        );
+
+        let from = tempfile::tempdir()?;
+        write_files(from.path(), &old)?;
+        std::fs::create_dir(from.path().join(".git"))?;
+        std::fs::write(from.path().join(".git/HEAD"), "ref: refs/heads/main\n")?;
+        let to = tempfile::tempdir()?;
+        apply(from.path(), delta.as_slice(), to.path())?;
+
+        let expected = tempfile::tempdir()?;
+        write_files(expected.path(), &new)?;
+        assert_eq!(hash_dir(to.path())?, hash_dir(expected.path())?);
+        assert!(!to.path().join("src/old.nr").exists());
+        assert!(!to.path().join(".git").exists());
+        Ok(())
+    }
+
+    #[test]
+    fn should_reject_unsafe_paths() -> Result<()> {
+        for removed in ["../outside", "/etc/passwd", ".git/HEAD"] {
+            let mut archive = tar::Builder::new(vec![]);
+            let mut header = tar::Header::new_gnu();
+            header.set_size(removed.len() as u64);
+            archive.append_data(&mut header, REMOVED_ENTRY, removed.as_bytes())?;
+            let delta = archive.into_inner()?;
+            let from = tempfile::tempdir()?;
+            let to = tempfile::tempdir()?;
+            assert!(apply(from.path(), delta.as_slice(), to.path()).is_err());
+        }
+        Ok(())
+    }
+}
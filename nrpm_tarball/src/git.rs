@@ -47,8 +47,16 @@ pub fn extract_git_mock(tarball: &mut File, version_name: &str) -> Result<(Strin
     let mut archive = Archive::new(tarball);
     let git_dir = tempdir()?;
 
-    // TODO: make sure user git configurations aren't being read here or doing nasty things
-    let repo = gix::init(&git_dir)?;
+    // isolated so the git configuration of whoever runs this isn't read. Creating the version
+    // branch writes a reflog entry, which needs a committer.
+    let repo = gix::ThreadSafeRepository::init_opts(
+        &git_dir,
+        gix::create::Kind::WithWorktree,
+        gix::create::Options::default(),
+        gix::open::Options::isolated()
+            .config_overrides(["committer.name=nrpm", "committer.email=nrpm@localhost"]),
+    )?
+    .to_thread_local();
     let mut editor = repo.edit_tree(ObjectId::empty_tree(gix::hash::Kind::Sha1))?;
     for entry in archive.entries()? {
         let mut entry = entry?;
@@ -21,6 +21,13 @@ mod git;
 #[cfg(feature = "git")]
 pub use git::*;
 
+pub mod delta;
+pub mod pkt_line;
+pub mod vectors;
+
+#[cfg(test)]
+mod proptests;
+
 use nargo_parse::*;
 
 pub fn extract_metadata(
@@ -48,9 +55,155 @@ pub fn extract_metadata(
     ))
 }
 
+/// Take a tarball and look through it to make sure it's safe-ish, and contains a valid
+/// Nargo.toml with a semver version, see `NargoConfig::validate_metadata`.
+///
+/// Extract the package name and version from the Nargo.toml and return them.
+pub fn validate_tarball<R: Read>(tarball: R) -> Result<(String, String)> {
+    let config = validate_contents(tarball)?;
+    config.validate_metadata()?;
+    Ok((
+        config.package.name,
+        config.package.version.unwrap_or_default(),
+    ))
+}
+
+/// Like `validate_tarball`, but accepts any version name, for registries that still accept the
+/// non-semver version names some packages were published with.
+pub fn validate_tarball_any_version<R: Read>(tarball: R) -> Result<(String, String)> {
+    let config = validate_contents(tarball)?;
+    let version = config.package.version.ok_or(anyhow::anyhow!(
+        "version field is not present in package section"
+    ))?;
+    Ok((config.package.name, version))
+}
+
+/// Check the contents of a tarball and parse its Nargo.toml.
+///
+/// Here we check that the contents of a tarball are of bounded size, and bounded number of
+/// entries. We check all path entries and disallow absolute paths, and paths referencing parent
+/// directories. We disallow all non-regular files. We disallow file paths that are non-utf8.
+/// We disallow file paths that are empty. We disallow `.git` directories. Every file must be
+/// static content, see `check_static_file`.
+///
+/// The tarball is untrusted input, this only reads from it and never touches the filesystem.
+fn validate_contents<R: Read>(tarball: R) -> Result<NargoConfig> {
+    let mut archive = Archive::new(tarball);
+
+    // maximum allowable size for the contents of the tarball
+    const MAX_ARCHIVE_SIZE: u64 = 20 * 1024 * 1024;
+    const MAX_ARCHIVE_ENTRIES: u64 = 10_000;
+    // total number of bytes in the tarball
+    let mut total_size = 0u64;
+    let mut total_entries = 0u64;
+
+    let mut nargo_toml_bytes = None;
+    for entry in archive.entries()? {
+        let mut entry = entry?;
+        total_entries += 1;
+        if total_entries > MAX_ARCHIVE_ENTRIES {
+            anyhow::bail!("archive contains too many entries: {} files", total_entries);
+        }
+        total_size = total_size.saturating_add(entry.size());
+        if total_size > MAX_ARCHIVE_SIZE {
+            anyhow::bail!("archive too large: {} bytes", total_size);
+        }
+        let path = entry.path()?.to_path_buf();
+        if path.is_absolute() {
+            anyhow::bail!("absolute paths are disallowed in tarballs!");
+        }
+        if path.as_os_str().is_empty() {
+            anyhow::bail!("tarball contains entry with empty name");
+        }
+        path.to_str()
+            .with_context(|| "tarball entry path contains non-unicode characters")?;
+        if path == PathBuf::from(".git") {
+            anyhow::bail!("tarball may not contain a .git entry");
+        }
+        for component in path.components() {
+            match component {
+                Component::Normal(_) => {}
+                _ => {
+                    anyhow::bail!("only normal path components are allowed in tarball entries!")
+                }
+            }
+        }
+        match entry.header().entry_type() {
+            EntryType::Regular => {
+                let mode = entry.header().mode()?;
+                let mut head = Vec::default();
+                (&mut entry).take(2).read_to_end(&mut head)?;
+                check_static_file(&path, mode, &head)?;
+                if path == PathBuf::from("Nargo.toml") {
+                    let mut bytes = head;
+                    entry.read_to_end(&mut bytes)?;
+                    nargo_toml_bytes = Some(bytes);
+                }
+            }
+            EntryType::Directory => {
+                continue;
+            }
+            EntryType::Link | EntryType::Symlink => anyhow::bail!(
+                "Tar contains link or symlink. Only directories and files are allowed in package tarballs!"
+            ),
+            _ => anyhow::bail!(
+                "Irregular entry detected in tar archive. Only directories and files are allowed in package tarballs!"
+            ),
+        }
+    }
+    if nargo_toml_bytes.is_none() {
+        anyhow::bail!("Nargo.toml does not exist in package root!");
+    }
+    let nargo_toml_bytes = nargo_toml_bytes.unwrap();
+    NargoConfig::from_str(&String::try_from(nargo_toml_bytes)?)
+}
+
+/// Extensions of the files a package may contain: Noir source, configuration, documentation,
+/// and data like circuit parameters. Files without an extension, e.g. `LICENSE`, are allowed
+/// too. Nothing nrpm or nargo install runs code from any of these.
+pub const STATIC_EXTENSIONS: &[&str] = &[
+    "nr", "toml", "lock", "json", "md", "markdown", "txt", "csv", "bin", "png", "jpg", "jpeg",
+    "gif",
+];
+
+/// Check a packaged file is static content that installing can't execute: it has an extension
+/// in `STATIC_EXTENSIONS` or none, isn't executable, and isn't a script. `mode` is the unix
+/// permissions of the file and `head` its first bytes.
+pub fn check_static_file(path: &Path, mode: u32, head: &[u8]) -> Result<()> {
+    if mode & 0o111 != 0 {
+        anyhow::bail!(
+            "{} is executable, packages may only contain static content",
+            path.display()
+        );
+    }
+    if let Some(extension) = path.extension()
+        && !STATIC_EXTENSIONS
+            .iter()
+            .any(|allowed| extension.eq_ignore_ascii_case(allowed))
+    {
+        anyhow::bail!(
+            "{} is not static content, packages may only contain files without an extension or with one of: {}",
+            path.display(),
+            STATIC_EXTENSIONS.join(", ")
+        );
+    }
+    if head.starts_with(b"#!") {
+        anyhow::bail!(
+            "{} is a script, packages may only contain static content",
+            path.display()
+        );
+    }
+    Ok(())
+}
+
 /// Do a content hash of a directory. This may differ from a tarball content hash based on
 /// gitignores in parent directories on different systems.
 pub fn hash_dir(path: &Path) -> Result<blake3::Hash> {
+    hash_dir_with_progress(path, |_| {})
+}
+
+/// `hash_dir`, calling `progress` with the size in bytes of each file as it's read.
+pub fn hash_dir_with_progress(path: &Path, mut progress: impl FnMut(u64)) -> Result<blake3::Hash> {
     let walker = WalkBuilder::new(&path)
         .git_ignore(true)
         .git_global(false)
@@ -71,6 +224,7 @@ pub fn hash_dir(path: &Path) -> Result<blake3::Hash> {
             anyhow::bail!("symlinks are not allowed in nrpm hashes");
         }
         let bytes = std::fs::read(entry.path())?;
+        progress(bytes.len() as u64);
         Ok(Some((
             entry.path().strip_prefix(path)?.to_path_buf(),
             bytes,
@@ -95,24 +249,39 @@ pub fn hash_content(
             continue;
         }
         let (path, bytes) = entry.unwrap();
-        log::trace!("beginning hash for {:?}", path);
-        let mut inner_hasher = blake3::Hasher::new();
-        for component in path.components() {
-            match component {
-                Component::Normal(component) => {
-                    log::trace!("adding bytes: {component:?}");
-                    inner_hasher.update(component.as_encoded_bytes());
-                }
-                _ => anyhow::bail!("Non-normal path component detected hash function"),
+        let inner_hash = hash_file(&path, &bytes)?;
+        ordered_files.insert(path, inner_hash);
+    }
+    Ok(combine_file_hashes(ordered_files))
+}
+
+/// Hash a single file of a package by hashing each component of its `path`, relative to the
+/// package root, followed by its contents.
+pub fn hash_file(path: &Path, bytes: &[u8]) -> Result<blake3::Hash> {
+    hash_file_reader(path, bytes)
+}
+
+/// `hash_file` with the contents read from `reader`, so large files aren't buffered.
+pub fn hash_file_reader<R: Read>(path: &Path, reader: R) -> Result<blake3::Hash> {
+    log::trace!("beginning hash for {:?}", path);
+    let mut inner_hasher = blake3::Hasher::new();
+    for component in path.components() {
+        match component {
+            Component::Normal(component) => {
+                log::trace!("adding bytes: {component:?}");
+                inner_hasher.update(component.as_encoded_bytes());
             }
+            _ => anyhow::bail!("Non-normal path component detected hash function"),
         }
-        log::trace!("hashing file contents ({} bytes)", bytes.len());
-        inner_hasher.update_reader(bytes.as_slice())?;
-        let inner_hash = inner_hasher.finalize();
-        log::trace!("entry: {:?} hash: {}", path, inner_hash.to_string());
-        ordered_files.insert(path, inner_hash);
     }
-    // now combine our ordered hashes into a final hash
+    inner_hasher.update_reader(reader)?;
+    let inner_hash = inner_hasher.finalize();
+    log::trace!("entry: {:?} hash: {}", path, inner_hash);
+    Ok(inner_hash)
+}
+
+/// Combine the `hash_file` hashes of every file in a package into the package hash.
+pub fn combine_file_hashes(ordered_files: BTreeMap<PathBuf, blake3::Hash>) -> blake3::Hash {
     let mut hasher = blake3::Hasher::new();
     log::trace!("{} entries, computing outer hash", ordered_files.len());
     for (file, hash) in ordered_files {
@@ -121,7 +290,7 @@ pub fn hash_content(
     }
     let hash = hasher.finalize();
     log::trace!("final hash: {}", hash.to_string());
-    Ok(hash)
+    hash
 }
 
 /// Take a tar archive and calculate a content based hash. Each file is separately hashed
@@ -129,7 +298,7 @@ pub fn hash_content(
 /// all file hashes in lexicographic order of file paths.
 ///
 /// This function assumes the tarball is untrusted.
-pub fn hash_tarball(tarball: &mut File) -> Result<blake3::Hash> {
+pub fn hash_tarball<R: Read + Seek>(tarball: &mut R) -> Result<blake3::Hash> {
     tarball.seek(SeekFrom::Start(0))?;
     let mut archive = Archive::new(tarball);
 
@@ -151,6 +320,28 @@ pub fn hash_tarball(tarball: &mut File) -> Result<blake3::Hash> {
     }))
 }
 
+/// `hash_tarball` for a tarball that can only be read once, e.g. while it's downloaded. Files
+/// are hashed as they're read, nothing is buffered. Reading stops at the end of the archive.
+pub fn hash_tarball_stream<R: Read>(tarball: R) -> Result<blake3::Hash> {
+    let mut archive = Archive::new(tarball);
+    let mut ordered_files = BTreeMap::new();
+    for entry in archive.entries()? {
+        let entry = entry?;
+        match entry.header().entry_type() {
+            EntryType::Regular => {
+                let path = entry.path()?.to_path_buf();
+                let inner_hash = hash_file_reader(&path, entry)?;
+                ordered_files.insert(path, inner_hash);
+            }
+            EntryType::Directory => {}
+            _ => anyhow::bail!(
+                "Irregular entry detected in tar archive. Only directories and files are allowed in package tarballs!"
+            ),
+        }
+    }
+    Ok(combine_file_hashes(ordered_files))
+}
+
 /// Create a tarball from `path`, which must exist and be a directory. Returned value with be
 /// a temporary File handle that is removed on Drop. Make sure to copy the file if persistence is needed!
 ///
@@ -158,6 +349,16 @@ pub fn hash_tarball(tarball: &mut File) -> Result<blake3::Hash> {
 /// Empty directories are not included. Irregular files (symlinks, block devices, etc) are not included.
 /// File permission errors will cause a failure. File paths are stored relative to `path`.
 pub fn create(path: &Path, tar_file: File) -> Result<File> {
+    create_with_overrides(path, tar_file, &HashMap::new())
+}
+
+/// Create a tarball like `create`, but with the contents of some files replaced. `overrides`
+/// is keyed by path relative to `path`, e.g. to package a rewritten `Nargo.toml`.
+pub fn create_with_overrides(
+    path: &Path,
+    tar_file: File,
+    overrides: &HashMap<PathBuf, Vec<u8>>,
+) -> Result<File> {
     // will detect non-existent paths
     let path = match path.canonicalize() {
         Ok(p) => p,
@@ -191,6 +392,13 @@ pub fn create(path: &Path, tar_file: File) -> Result<File> {
             continue;
         }
         let relative_path = entry_path.strip_prefix(&path)?;
+        if let Some(bytes) = overrides.get(relative_path) {
+            let mut header = tar::Header::new_gnu();
+            header.set_metadata(&entry_path.metadata()?);
+            header.set_size(bytes.len() as u64);
+            archive.append_data(&mut header, relative_path, bytes.as_slice())?;
+            continue;
+        }
         let mut file = match File::open(entry_path) {
             Ok(f) => f,
             Err(e) => anyhow::bail!(
@@ -243,6 +451,44 @@ mod tests {
         Ok(())
     }
 
+    #[test]
+    fn should_report_hash_progress() -> Result<()> {
+        let tempdir = tempfile::tempdir()?;
+        fs::write(tempdir.path().join("test.txt"), "test")?;
+        fs::create_dir(tempdir.path().join("src"))?;
+        fs::write(tempdir.path().join("src").join("test2.txt"), "test2")?;
+
+        let mut hashed = 0;
+        let hash = hash_dir_with_progress(tempdir.path(), |bytes| hashed += bytes)?;
+        assert_eq!(hashed, 9);
+        assert_eq!(hash, hash_dir(tempdir.path())?);
+
+        Ok(())
+    }
+
+    #[test]
+    fn should_package_overridden_files() -> Result<()> {
+        let tempdir = tempfile::tempdir()?;
+        fs::write(
+            tempdir.path().join("Nargo.toml"),
+            "[package]\nname = \"a\"\n\n[dependencies]\nb = { path = \"../b\" }\n",
+        )?;
+        let rewritten = "[package]\nname = \"a\"\n\n[dependencies]\nb = { git = \"https://nrpm.io/b\", tag = \"0.1.0\" }\n";
+        let overrides =
+            HashMap::from([(PathBuf::from("Nargo.toml"), rewritten.as_bytes().to_vec())]);
+
+        let mut tarball = create_with_overrides(tempdir.path(), tempfile::tempfile()?, &overrides)?;
+        let mut bytes = vec![];
+        tarball.read_to_end(&mut bytes)?;
+        let (config, files) = extract_metadata(bytes)?;
+        assert_eq!(files[&PathBuf::from("Nargo.toml")], rewritten.as_bytes());
+        assert_eq!(
+            config.dependencies()?["b"].git.as_deref(),
+            Some("https://nrpm.io/b")
+        );
+        Ok(())
+    }
+
     #[test]
     fn should_return_start_of_tarball() -> Result<()> {
         let tar_file = tempfile::tempfile()?;
@@ -409,6 +655,71 @@ mod tests {
         Ok(())
     }
 
+    #[test]
+    fn should_reject_non_static_files() -> Result<()> {
+        let tarball = |path: &str, mode: u32, contents: &[u8]| -> Result<Vec<u8>> {
+            let mut archive = tar::Builder::new(vec![]);
+            let nargo_toml = b"[package]\nname = \"a\"\nversion = \"0.1.0\"\ntype = \"lib\"\n";
+            for (path, mode, contents) in [
+                ("Nargo.toml", 0o644, nargo_toml.as_slice()),
+                (path, mode, contents),
+            ] {
+                let mut header = tar::Header::new_gnu();
+                header.set_size(contents.len() as u64);
+                header.set_mode(mode);
+                archive.append_data(&mut header, path, contents)?;
+            }
+            Ok(archive.into_inner()?)
+        };
+        for (path, mode, contents) in [
+            ("src/lib.nr", 0o644, b"fn main() {}".as_slice()),
+            ("LICENSE", 0o600, b"MIT".as_slice()),
+            ("README.MD", 0o644, b"#".as_slice()),
+        ] {
+            validate_tarball(tarball(path, mode, contents)?.as_slice())?;
+        }
+        for (path, mode, contents, reason) in [
+            ("install", 0o755, b"".as_slice(), "is executable"),
+            (
+                "build.rs",
+                0o644,
+                b"fn main() {}".as_slice(),
+                "is not static content",
+            ),
+            ("hook", 0o644, b"#!/bin/sh\n".as_slice(), "is a script"),
+        ] {
+            let e = validate_tarball(tarball(path, mode, contents)?.as_slice())
+                .err()
+                .unwrap();
+            assert!(e.to_string().contains(reason), "{e}");
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn should_require_semver_versions() -> Result<()> {
+        let tarball = |version: &str| -> Result<Vec<u8>> {
+            let mut archive = tar::Builder::new(vec![]);
+            let nargo_toml =
+                format!("[package]\nname = \"a\"\nversion = \"{version}\"\ntype = \"lib\"\n");
+            let mut header = tar::Header::new_gnu();
+            header.set_size(nargo_toml.len() as u64);
+            header.set_mode(0o644);
+            archive.append_data(&mut header, "Nargo.toml", nargo_toml.as_bytes())?;
+            Ok(archive.into_inner()?)
+        };
+        for version in ["0.1.0", "v0.1.0", "1.0.0-rc.1+build"] {
+            let (_, name) = validate_tarball(tarball(version)?.as_slice())?;
+            assert_eq!(name, version);
+        }
+        for version in ["next", "vnext", "1.0"] {
+            assert!(validate_tarball(tarball(version)?.as_slice()).is_err());
+            let (_, name) = validate_tarball_any_version(tarball(version)?.as_slice())?;
+            assert_eq!(name, version);
+        }
+        Ok(())
+    }
+
     #[test]
     fn should_fail_nonexistent_root() -> Result<()> {
         let tar_file = tempfile::tempfile()?;
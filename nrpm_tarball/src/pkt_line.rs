@@ -0,0 +1,235 @@
+//! Parsing for the pkt-line framing used by git protocol v2 requests.
+//!
+//! https://git-scm.com/docs/protocol-common#_pkt_line_format
+
+use anyhow::Result;
+
+/// Longest pkt-line allowed by git, including the length prefix.
+pub const MAX_PKT_LEN: usize = 65520;
+
+/// A single pkt-line.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum PktLine<'a> {
+    /// `0000`, ends a message.
+    Flush,
+    /// `0001`, separates sections of a message.
+    Delimiter,
+    /// `0002`, ends a response in stateless connections.
+    ResponseEnd,
+    /// A line of data without its length prefix.
+    Data(&'a [u8]),
+}
+
+/// Split `bytes` into pkt-lines. Fails if the lines don't exactly cover `bytes`.
+pub fn parse(bytes: &[u8]) -> Result<Vec<PktLine<'_>>> {
+    let mut lines = vec![];
+    let mut rest = bytes;
+    while !rest.is_empty() {
+        let Some(prefix) = rest.get(..4) else {
+            anyhow::bail!("truncated pkt-line length");
+        };
+        let len = std::str::from_utf8(prefix)
+            .ok()
+            .and_then(|prefix| usize::from_str_radix(prefix, 16).ok())
+            .ok_or(anyhow::anyhow!("invalid pkt-line length {prefix:?}"))?;
+        let line = match len {
+            0 => PktLine::Flush,
+            1 => PktLine::Delimiter,
+            2 => PktLine::ResponseEnd,
+            3 => anyhow::bail!("invalid pkt-line length 3"),
+            len if len > MAX_PKT_LEN => anyhow::bail!("pkt-line too long: {len} bytes"),
+            len => PktLine::Data(
+                rest.get(4..len)
+                    .ok_or(anyhow::anyhow!("truncated pkt-line of {len} bytes"))?,
+            ),
+        };
+        rest = &rest[len.max(4)..];
+        lines.push(line);
+    }
+    Ok(lines)
+}
+
+/// Encode `data` as a pkt-line.
+pub fn encode(data: &[u8]) -> Result<Vec<u8>> {
+    let len = data.len() + 4;
+    if len > MAX_PKT_LEN {
+        anyhow::bail!("pkt-line too long: {len} bytes");
+    }
+    Ok([format!("{len:04x}").into_bytes(), data.to_vec()].concat())
+}
+
+/// A git protocol v2 request to `git-upload-pack`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum UploadPackRequest {
+    /// List the refs starting with any of `ref_prefixes`, or all refs if there are none.
+    LsRefs {
+        ref_prefixes: Vec<String>,
+        /// Whether symbolic refs should name their target.
+        symrefs: bool,
+    },
+    /// Fetch the objects of the wanted commits, given as hex object ids.
+    Fetch { wants: Vec<String> },
+}
+
+fn line_str(data: &[u8]) -> Result<&str> {
+    let line = std::str::from_utf8(data)?;
+    Ok(line.strip_suffix('\n').unwrap_or(line))
+}
+
+/// Parse the body of a `git-upload-pack` request. Only the commands the registry serves are
+/// recognized.
+pub fn parse_upload_pack_request(bytes: &[u8]) -> Result<UploadPackRequest> {
+    let lines = parse(bytes)?;
+    let mut lines = lines.into_iter();
+    let command = match lines.next() {
+        Some(PktLine::Data(data)) => line_str(data)?
+            .strip_prefix("command=")
+            .ok_or(anyhow::anyhow!("request does not start with a command"))?
+            .to_string(),
+        _ => anyhow::bail!("request does not start with a command"),
+    };
+    // capabilities come before the delimiter and arguments after it
+    let mut arguments = vec![];
+    let mut in_arguments = false;
+    let mut flushed = false;
+    for line in lines {
+        match line {
+            PktLine::Data(data) if in_arguments => arguments.push(line_str(data)?),
+            PktLine::Data(_) => {}
+            PktLine::Delimiter if !in_arguments => in_arguments = true,
+            PktLine::Flush => {
+                flushed = true;
+                break;
+            }
+            _ => anyhow::bail!("unexpected pkt-line in request"),
+        }
+    }
+    if !flushed {
+        anyhow::bail!("request is not terminated by a flush");
+    }
+    match command.as_str() {
+        "ls-refs" => Ok(UploadPackRequest::LsRefs {
+            ref_prefixes: arguments
+                .iter()
+                .filter_map(|argument| argument.strip_prefix("ref-prefix "))
+                .map(str::to_string)
+                .collect(),
+            symrefs: arguments.contains(&"symrefs"),
+        }),
+        "fetch" => {
+            let mut wants = vec![];
+            for argument in arguments {
+                let Some(oid) = argument.strip_prefix("want ") else {
+                    continue;
+                };
+                if !(oid.len() == 40 || oid.len() == 64)
+                    || !oid.bytes().all(|b| b.is_ascii_hexdigit())
+                {
+                    anyhow::bail!("invalid object id in want: {oid:?}");
+                }
+                wants.push(oid.to_ascii_lowercase());
+            }
+            if wants.is_empty() {
+                anyhow::bail!("fetch request does not want any objects");
+            }
+            Ok(UploadPackRequest::Fetch { wants })
+        }
+        command => anyhow::bail!("unknown git command: {command:?}"),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use anyhow::Result;
+    use proptest::prelude::*;
+
+    use super::*;
+
+    #[test]
+    fn should_parse_fetch_request() -> Result<()> {
+        let oid = "0123456789abcdef0123456789abcdef01234567";
+        let body = [
+            encode(b"command=fetch")?,
+            encode(b"agent=git/2.43.0\n")?,
+            b"0001".to_vec(),
+            encode(b"thin-pack\n")?,
+            encode(format!("want {oid}\n").as_bytes())?,
+            encode(b"done\n")?,
+            b"0000".to_vec(),
+        ]
+        .concat();
+        assert_eq!(
+            parse_upload_pack_request(&body)?,
+            UploadPackRequest::Fetch {
+                wants: vec![oid.to_string()]
+            }
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn should_parse_ls_refs_request() -> Result<()> {
+        let body = [
+            encode(b"command=ls-refs\n")?,
+            b"0001".to_vec(),
+            encode(b"peel\n")?,
+            encode(b"symrefs\n")?,
+            encode(b"ref-prefix HEAD\n")?,
+            encode(b"ref-prefix refs/tags/\n")?,
+            b"0000".to_vec(),
+        ]
+        .concat();
+        assert_eq!(
+            parse_upload_pack_request(&body)?,
+            UploadPackRequest::LsRefs {
+                ref_prefixes: vec!["HEAD".to_string(), "refs/tags/".to_string()],
+                symrefs: true,
+            }
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn should_reject_malformed_lines() {
+        assert!(parse(b"00").is_err());
+        assert!(parse(b"0003").is_err());
+        assert!(parse(b"zzzz").is_err());
+        assert!(parse(b"0010short").is_err());
+        assert!(parse_upload_pack_request(b"0000").is_err());
+        // unterminated
+        assert!(parse_upload_pack_request(&encode(b"command=ls-refs\n").unwrap()).is_err());
+    }
+
+    fn pkt_line() -> impl Strategy<Value = Option<Vec<u8>>> {
+        prop_oneof![
+            Just(None),
+            proptest::collection::vec(any::<u8>(), 0..1024).prop_map(Some),
+        ]
+    }
+
+    proptest! {
+        #[test]
+        fn should_round_trip_pkt_lines(lines in proptest::collection::vec(pkt_line(), 0..32)) {
+            let mut bytes = vec![];
+            for line in &lines {
+                match line {
+                    Some(data) => bytes.extend(encode(data).unwrap()),
+                    None => bytes.extend(b"0000"),
+                }
+            }
+            let parsed = parse(&bytes).unwrap();
+            prop_assert_eq!(parsed.len(), lines.len());
+            for (parsed, line) in parsed.iter().zip(&lines) {
+                match line {
+                    Some(data) => prop_assert_eq!(parsed, &PktLine::Data(data)),
+                    None => prop_assert_eq!(parsed, &PktLine::Flush),
+                }
+            }
+        }
+
+        #[test]
+        fn should_not_panic_on_arbitrary_requests(bytes in proptest::collection::vec(any::<u8>(), 0..512)) {
+            let _ = parse_upload_pack_request(&bytes);
+        }
+    }
+}
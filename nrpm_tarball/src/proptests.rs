@@ -0,0 +1,92 @@
+use std::collections::BTreeMap;
+use std::io::Cursor;
+use std::io::Read;
+use std::path::PathBuf;
+
+use proptest::prelude::*;
+
+use super::*;
+
+const NARGO_TOML: &str = "[package]\nname = \"fuzz\"\nversion = \"0.1.0\"\ntype = \"lib\"\n";
+
+/// A path component with unicode. Never starts with `.`, so no hidden or ignore files.
+fn component() -> impl Strategy<Value = String> {
+    "[a-zA-Z0-9_é漢字🦀 -]{1,12}"
+}
+
+/// File contents around the 512 byte tar block size, plus a few larger ones.
+fn contents() -> impl Strategy<Value = Vec<u8>> {
+    prop_oneof![
+        Just(vec![]),
+        proptest::collection::vec(any::<u8>(), 511..=513),
+        proptest::collection::vec(any::<u8>(), 0..4096),
+    ]
+    // scripts aren't static content
+    .prop_filter("starts with #!", |contents| !contents.starts_with(b"#!"))
+}
+
+/// Files keyed by path relative to the package root. Directories and files are prefixed
+/// differently so a path is never both.
+fn file_tree() -> impl Strategy<Value = BTreeMap<PathBuf, Vec<u8>>> {
+    proptest::collection::vec(
+        (
+            proptest::collection::vec(component(), 0..8),
+            component(),
+            contents(),
+        ),
+        0..16,
+    )
+    .prop_map(|files| {
+        files
+            .into_iter()
+            .map(|(dirs, name, contents)| {
+                let mut path = dirs.iter().map(|d| format!("d{d}")).collect::<PathBuf>();
+                path.push(format!("f{name}"));
+                (path, contents)
+            })
+            .collect()
+    })
+}
+
+proptest! {
+    #![proptest_config(ProptestConfig::with_cases(64))]
+
+    #[test]
+    fn should_round_trip_file_trees(files in file_tree()) {
+        let tempdir = tempfile::tempdir().unwrap();
+        std::fs::write(tempdir.path().join("Nargo.toml"), NARGO_TOML).unwrap();
+        for (path, contents) in &files {
+            let path = tempdir.path().join(path);
+            std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+            std::fs::write(path, contents).unwrap();
+        }
+
+        let mut tarball = create(tempdir.path(), tempfile::tempfile().unwrap()).unwrap();
+        let hash = hash_tarball(&mut tarball).unwrap();
+        prop_assert_eq!(hash, hash_dir(tempdir.path()).unwrap());
+
+        let mut bytes = vec![];
+        tarball.seek(SeekFrom::Start(0)).unwrap();
+        tarball.read_to_end(&mut bytes).unwrap();
+        prop_assert_eq!(hash, hash_tarball_stream(bytes.as_slice()).unwrap());
+        let (name, version) = validate_tarball(Cursor::new(&bytes)).unwrap();
+        prop_assert_eq!(name, "fuzz");
+        prop_assert_eq!(version, "0.1.0");
+
+        let (config, mut extracted) = extract_metadata(bytes).unwrap();
+        prop_assert_eq!(config.package.name, "fuzz");
+        prop_assert_eq!(
+            extracted.remove(&PathBuf::from("Nargo.toml")),
+            Some(NARGO_TOML.as_bytes().to_vec())
+        );
+        prop_assert_eq!(extracted.into_iter().collect::<BTreeMap<_, _>>(), files);
+    }
+
+    #[test]
+    fn should_not_panic_on_arbitrary_tarballs(bytes in proptest::collection::vec(any::<u8>(), 0..4096)) {
+        let _ = validate_tarball(Cursor::new(&bytes));
+        let _ = hash_tarball(&mut Cursor::new(&bytes));
+        let _ = hash_tarball_stream(bytes.as_slice());
+        let _ = extract_metadata(bytes);
+    }
+}
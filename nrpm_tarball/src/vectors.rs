@@ -0,0 +1,129 @@
+//! Known file sets and their content hashes. Anything computing nrpm hashes, on any platform,
+//! should reproduce these exactly. Changing one of the hashes is a breaking change to every
+//! published package and lockfile.
+
+/// A set of files and the hash `hash_content` must produce for them.
+pub struct HashVector {
+    pub name: &'static str,
+    /// Paths relative to the package root with `/` separators, and file contents.
+    pub files: &'static [(&'static str, &'static [u8])],
+    /// Lowercase hex blake3 hash.
+    pub blake3: &'static str,
+}
+
+const fn is_hex_hash(hash: &str) -> bool {
+    let bytes = hash.as_bytes();
+    if bytes.len() != 64 {
+        return false;
+    }
+    let mut i = 0;
+    while i < bytes.len() {
+        if !matches!(bytes[i], b'0'..=b'9' | b'a'..=b'f') {
+            return false;
+        }
+        i += 1;
+    }
+    true
+}
+
+pub const HASH_VECTORS: &[HashVector] = &[
+    HashVector {
+        name: "empty",
+        files: &[],
+        blake3: "af1349b9f5f9a1a6a0404dea36dcc9499bcb25c9adc112b7cc9a93cae41f3262",
+    },
+    HashVector {
+        name: "single file",
+        files: &[(
+            "Nargo.toml",
+            b"[package]\nname = \"vector\"\nversion = \"0.1.0\"\ntype = \"lib\"\n",
+        )],
+        blake3: "de41ccb8bd3c80260a34b0cc7d81c4a9ca1147e8aadbbabe5c9f9cb9c600b571",
+    },
+    HashVector {
+        name: "nested files",
+        files: &[
+            (
+                "Nargo.toml",
+                b"[package]\nname = \"vector\"\nversion = \"0.1.0\"\ntype = \"lib\"\n",
+            ),
+            ("src/lib.nr", b"pub fn one() -> Field { 1 }\n"),
+            ("src/util/mod.nr", b""),
+            (".hidden", b"hidden files are hashed"),
+        ],
+        blake3: "05a1936754ea1db445114cf39b328b583e77edb15188d88dfb30385c91d54ade",
+    },
+    HashVector {
+        name: "unicode paths",
+        files: &[("src/漢字.nr", b"\xff\x00\xfe"), ("é/🦀", b"crab")],
+        blake3: "1dda85099907d9e0f5fd6e91dd5f5b4b164ff60a87f69a02c0c60bf21c942f2e",
+    },
+    HashVector {
+        // path components are hashed without separators, so both files get the same file hash
+        name: "joined components",
+        files: &[("a/b", b"x"), ("ab", b"x")],
+        blake3: "6390367147b810dfb27cb5358d6d69e99e06bac82c63ae87618e91671b4b6acb",
+    },
+];
+
+const _: () = {
+    let mut i = 0;
+    while i < HASH_VECTORS.len() {
+        assert!(
+            is_hex_hash(HASH_VECTORS[i].blake3),
+            "hash vectors must be 64 lowercase hex characters"
+        );
+        i += 1;
+    }
+};
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+
+    use anyhow::Result;
+
+    use super::*;
+    use crate::*;
+
+    #[test]
+    fn should_match_hash_vectors() -> Result<()> {
+        for vector in HASH_VECTORS {
+            let content = hash_content(
+                vector
+                    .files
+                    .iter()
+                    .map(|(path, bytes)| Ok(Some((PathBuf::from(path), bytes.to_vec())))),
+            )?;
+            assert_eq!(content.to_string(), vector.blake3, "{}", vector.name);
+
+            let tempdir = tempfile::tempdir()?;
+            for (path, bytes) in vector.files {
+                let path = tempdir.path().join(path);
+                std::fs::create_dir_all(path.parent().unwrap())?;
+                std::fs::write(path, bytes)?;
+            }
+            assert_eq!(
+                hash_dir(tempdir.path())?.to_string(),
+                vector.blake3,
+                "{}",
+                vector.name
+            );
+            let mut tarball = create(tempdir.path(), tempfile::tempfile()?)?;
+            assert_eq!(
+                hash_tarball(&mut tarball)?.to_string(),
+                vector.blake3,
+                "{}",
+                vector.name
+            );
+            tarball.seek(SeekFrom::Start(0))?;
+            assert_eq!(
+                hash_tarball_stream(&mut tarball)?.to_string(),
+                vector.blake3,
+                "{}",
+                vector.name
+            );
+        }
+        Ok(())
+    }
+}
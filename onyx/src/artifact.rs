@@ -0,0 +1,237 @@
+use std::str::FromStr;
+
+use axum::body::Body;
+use axum::extract::Path;
+use axum::extract::State;
+use axum::http::HeaderMap;
+use axum::http::header;
+use axum::response::IntoResponse;
+use axum::response::Json as ResponseJson;
+use axum::response::Response;
+use redb::ReadableTable;
+use tokio_util::io::ReaderStream;
+
+use onyx_api::prelude::*;
+
+use super::OnyxError;
+use super::OnyxState;
+use super::build;
+use super::quota;
+use super::session::AuthSession;
+use super::validate::ValidJson;
+
+/// Attach a compiled artifact to a version. Only the owner of the package may attach
+/// artifacts, and an artifact can't be replaced once attached.
+pub async fn upload_artifact(
+    State(state): State<OnyxState>,
+    session: AuthSession,
+    Path(id): Path<String>,
+    ValidJson(payload): ValidJson<UploadArtifactRequest>,
+) -> Result<ResponseJson<VersionArtifactModel>, OnyxError> {
+    let version_id = HashId::from_str(&id)?;
+    let content = serde_json::from_str::<serde_json::Value>(&payload.content)
+        .map_err(|e| OnyxError::bad_request(&format!("Artifact is not valid json: {e}")))?;
+    if content.get("abi").is_none() {
+        return Err(OnyxError::bad_request(
+            "Artifact has no abi, upload the json written by nargo compile",
+        ));
+    }
+
+    let write = state.db.begin_write()?;
+    let package = {
+        let version_table = write.open_table(VERSION_TABLE)?;
+        let package_table = write.open_table(PACKAGE_TABLE)?;
+        let Some(version) = version_table.get(&version_id)? else {
+            return Err(OnyxError::not_found("Unable to find version"));
+        };
+        let Some(package) = package_table.get(version.value().package_id.as_str())? else {
+            return Err(OnyxError::not_found("Unable to find package"));
+        };
+        let package = package.value();
+        if package.author_id != session.user_id {
+            return Err(OnyxError::new(
+                OnyxErrorCode::Forbidden,
+                "Only the owner of a package may attach artifacts",
+            ));
+        }
+        package
+    };
+    let artifact = VersionArtifactModel {
+        name: payload.name,
+        hash: blake3::hash(payload.content.as_bytes())
+            .to_hex()
+            .to_string(),
+        size: payload.content.len() as u64,
+        noir_version: content
+            .get("noir_version")
+            .and_then(|v| v.as_str())
+            .map(str::to_string),
+        uploaded_at: timestamp(),
+    };
+    {
+        let mut version_artifact_table = write.open_table(VERSION_ARTIFACT_TABLE)?;
+        if version_artifact_table
+            .get((version_id.clone(), artifact.name.as_str()))?
+            .is_some()
+        {
+            return Err(OnyxError::conflict(&format!(
+                "Version already has an artifact named \"{}\"",
+                artifact.name
+            )));
+        }
+        quota::check(&write, &state.config, &package.author_id, artifact.size)?;
+        quota::charge(&write, &package.author_id, &package.id, artifact.size)?;
+        // stored before the transaction commits so a listed artifact can always be read
+        state
+            .storage
+            .store_artifact(&artifact.hash, payload.content.as_bytes())?;
+        version_artifact_table.insert(
+            (version_id.clone(), artifact.name.as_str()),
+            artifact.clone(),
+        )?;
+    }
+    if state.config.nargo_path.is_some() {
+        build::enqueue(&write, &version_id)?;
+    }
+    write.commit()?;
+    Ok(ResponseJson(artifact))
+}
+
+/// The artifacts attached to a version, sorted by name.
+pub async fn version_artifacts(
+    State(state): State<OnyxState>,
+    Path(id): Path<String>,
+) -> Result<ResponseJson<Vec<VersionArtifactModel>>, OnyxError> {
+    let version_id = HashId::from_str(&id)?;
+    let read = state.db.begin_read()?;
+    let version_table = read.open_table(VERSION_TABLE)?;
+    if version_table.get(&version_id)?.is_none() {
+        return Err(OnyxError::not_found("Unable to find version"));
+    }
+    let version_artifact_table = read.open_table(VERSION_ARTIFACT_TABLE)?;
+    let mut artifacts = vec![];
+    for entry in version_artifact_table
+        .range((version_id.clone(), "")..=(version_id.clone(), "\u{10ffff}"))?
+    {
+        artifacts.push(entry?.1.value());
+    }
+    Ok(ResponseJson(artifacts))
+}
+
+/// The contents of an artifact attached to a version.
+pub async fn download_artifact(
+    State(state): State<OnyxState>,
+    Path((id, name)): Path<(String, String)>,
+) -> Result<Response, OnyxError> {
+    let version_id = HashId::from_str(&id)?;
+    let artifact = {
+        let read = state.db.begin_read()?;
+        let version_artifact_table = read.open_table(VERSION_ARTIFACT_TABLE)?;
+        match version_artifact_table.get((version_id.clone(), name.as_str()))? {
+            Some(artifact) => artifact.value(),
+            None => return Err(OnyxError::not_found("Unable to find artifact")),
+        }
+    };
+    let reader = tokio::fs::File::open(state.storage.name_to_artifact_path(&artifact.hash)).await?;
+    let mut headers = HeaderMap::new();
+    headers.insert(
+        header::CONTENT_TYPE,
+        "application/json"
+            .parse()
+            .map_err(|_| OnyxError::default())?,
+    );
+    headers.insert(
+        header::CONTENT_DISPOSITION,
+        format!("attachment; filename=\"{}.json\"", artifact.name)
+            .parse()
+            .map_err(|_| OnyxError::default())?,
+    );
+    Ok((headers, Body::from_stream(ReaderStream::new(reader))).into_response())
+}
+
+#[cfg(test)]
+mod tests {
+    use anyhow::Result;
+    use nanoid::nanoid;
+    use onyx_api::prelude::*;
+
+    use crate::testing::OnyxTest;
+
+    const ARTIFACT: &str =
+        r#"{"noir_version":"1.0.0-beta.9","hash":"1","abi":{"parameters":[]},"bytecode":"H4sI"}"#;
+
+    fn upload(name: &str, content: &str) -> UploadArtifactRequest {
+        UploadArtifactRequest {
+            name: name.to_string(),
+            content: content.to_string(),
+        }
+    }
+
+    #[tokio::test]
+    async fn should_attach_artifacts() -> Result<()> {
+        let test = OnyxTest::new().await?;
+        let (login, _password) = test.signup(None).await?;
+        let tarball = OnyxTest::create_test_tarball_named(None, Some(&nanoid!()), None)?;
+        let version_id = HashId::from(tarball.1);
+        test.publish(
+            Some(PublishData::new(tarball.1.to_string(), login.token.clone())),
+            tarball,
+        )
+        .await?;
+        assert!(test.api.version_artifacts(&version_id).await?.is_empty());
+
+        let artifact = test
+            .api
+            .upload_artifact(&login.token, &version_id, upload("main", ARTIFACT))
+            .await?;
+        assert_eq!(artifact.noir_version.as_deref(), Some("1.0.0-beta.9"));
+        assert_eq!(artifact.size, ARTIFACT.len() as u64);
+        assert_eq!(
+            test.api.version_artifacts(&version_id).await?,
+            vec![artifact.clone()]
+        );
+        assert_eq!(
+            test.api.download_artifact(&version_id, &artifact).await?,
+            ARTIFACT
+        );
+
+        // artifacts can't be replaced
+        let err = test
+            .api
+            .upload_artifact(&login.token, &version_id, upload("main", ARTIFACT))
+            .await
+            .unwrap_err();
+        let err = err.downcast_ref::<ApiError>().unwrap();
+        assert_eq!(err.code, OnyxErrorCode::Conflict);
+
+        let err = test
+            .api
+            .upload_artifact(
+                &login.token,
+                &version_id,
+                upload("other", "{\"bytecode\":\"\"}"),
+            )
+            .await
+            .unwrap_err();
+        let err = err.downcast_ref::<ApiError>().unwrap();
+        assert_eq!(err.code, OnyxErrorCode::BadRequest);
+
+        let err = test
+            .api
+            .upload_artifact(&login.token, &version_id, upload("../main", ARTIFACT))
+            .await
+            .unwrap_err();
+        let err = err.downcast_ref::<ApiError>().unwrap();
+        assert_eq!(err.code, OnyxErrorCode::ValidationFailed);
+
+        let (other, _password) = test.signup(None).await?;
+        let err = test
+            .api
+            .upload_artifact(&other.token, &version_id, upload("other", ARTIFACT))
+            .await
+            .unwrap_err();
+        let err = err.downcast_ref::<ApiError>().unwrap();
+        assert_eq!(err.code, OnyxErrorCode::Forbidden);
+        Ok(())
+    }
+}
@@ -0,0 +1,75 @@
+use axum::extract::Query;
+use axum::extract::State;
+use axum::response::Json as ResponseJson;
+use redb::ReadableTable;
+use serde::Deserialize;
+
+use onyx_api::prelude::*;
+
+use super::OnyxError;
+use super::OnyxState;
+use super::db::WriteTxn;
+use super::session::AdminSession;
+
+const DEFAULT_PAGE_SIZE: usize = 100;
+const MAX_PAGE_SIZE: usize = 1000;
+
+/// Append a decision to the audit log. `admin_username` is `None` for decisions the registry
+/// made itself.
+pub fn record(
+    write: &WriteTxn,
+    action: AuditAction,
+    admin_username: Option<&str>,
+    package_name: &str,
+    details: String,
+) -> Result<u64, OnyxError> {
+    let mut audit_log_table = write.open_table(AUDIT_LOG_TABLE)?;
+    let seq = audit_log_table
+        .last()?
+        .map(|(seq, _)| seq.value() + 1)
+        .unwrap_or(1);
+    audit_log_table.insert(
+        seq,
+        AuditEntry {
+            seq,
+            created_at: timestamp(),
+            action,
+            admin_username: admin_username.map(|v| v.to_string()),
+            package_name: package_name.to_string(),
+            details,
+        },
+    )?;
+    Ok(seq)
+}
+
+#[derive(Deserialize)]
+pub struct AuditLogQuery {
+    #[serde(default)]
+    since: u64,
+    limit: Option<usize>,
+}
+
+pub async fn audit_log(
+    State(state): State<OnyxState>,
+    _admin: AdminSession,
+    Query(query): Query<AuditLogQuery>,
+) -> Result<ResponseJson<AuditLogResponse>, OnyxError> {
+    let limit = query.limit.unwrap_or(DEFAULT_PAGE_SIZE).min(MAX_PAGE_SIZE);
+    let read = state.db.begin_read()?;
+    let audit_log_table = read.open_table(AUDIT_LOG_TABLE)?;
+    let latest_seq = audit_log_table
+        .last()?
+        .map(|(seq, _)| seq.value())
+        .unwrap_or(0);
+    let mut entries = vec![];
+    for entry in audit_log_table
+        .range(query.since.saturating_add(1)..)?
+        .take(limit)
+    {
+        entries.push(entry?.1.value());
+    }
+    Ok(ResponseJson(AuditLogResponse {
+        entries,
+        latest_seq,
+    }))
+}
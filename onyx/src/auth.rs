@@ -1,87 +1,122 @@
 use anyhow::Result;
-use axum::extract::Json;
 use axum::extract::State;
 use axum::response::Json as ResponseJson;
-use bcrypt::DEFAULT_COST;
-use bcrypt::hash;
 use nanoid::nanoid;
 use redb::ReadableTable;
+use redb::ReadableTableMetadata;
 
 use onyx_api::prelude::*;
 
+use super::Config;
 use super::OnyxError;
 use super::OnyxState;
-
-const MIN_PASSWORD_LEN: usize = 10;
+use super::db::Db;
+use super::password::PasswordCheck;
+use super::password::hash_password;
+use super::password::verify_password;
+use super::session::NewSession;
+use super::session::create_session;
+use super::session::login_response;
+use super::validate::SignupRequest;
+use super::validate::ValidJson;
+use super::validate::validate_password;
+use super::validate::validate_username;
 
 pub async fn login(
     State(state): State<OnyxState>,
-    Json(payload): Json<LoginRequest>,
+    ValidJson(payload): ValidJson<LoginRequest>,
 ) -> Result<ResponseJson<LoginResponse>, OnyxError> {
-    let user = {
+    let mut user = {
         let read = state.db.begin_read()?;
         let username_table = read.open_table(USERNAME_USER_ID_TABLE)?;
         let user_table = read.open_table(USER_TABLE)?;
 
         let user_id = match username_table.get(payload.username.as_str())? {
             Some(id) => id.value().to_string(),
-            None => return Err(OnyxError::bad_request("username not registered")),
+            None => {
+                return Err(OnyxError::new(
+                    OnyxErrorCode::InvalidCredentials,
+                    "username not registered",
+                ));
+            }
         };
 
         match user_table.get(user_id.as_str())? {
             Some(user) => user.value(),
             None => {
-                return Err(OnyxError::bad_request(
+                return Err(OnyxError::new(
+                    OnyxErrorCode::Internal,
                     "username registered without user document. This is an internal error",
                 ));
             }
         }
     };
 
-    match bcrypt::verify(payload.password, &user.password_hash) {
-        Ok(success) => {
-            if !success {
-                return Err(OnyxError::bad_request("bad password"));
-            }
+    let needs_rehash = match verify_password(&state.config, &payload.password, &user.password_hash)
+    {
+        Ok(PasswordCheck::Valid) => false,
+        Ok(PasswordCheck::ValidNeedsRehash) => true,
+        Ok(PasswordCheck::Invalid) => {
+            return Err(OnyxError::new(
+                OnyxErrorCode::InvalidCredentials,
+                "bad password",
+            ));
         }
         Err(e) => {
-            println!("bcrypt error: {}", e);
-            return Err(OnyxError::bad_request("bad password"));
+            log::error!("password verification error for user {}: {e:?}", user.id);
+            return Err(OnyxError::new(
+                OnyxErrorCode::InvalidCredentials,
+                "bad password",
+            ));
+        }
+    };
+
+    {
+        let read = state.db.begin_read()?;
+        let banned_user_table = read.open_table(BANNED_USER_TABLE)?;
+        if banned_user_table.get(user.id.as_str())?.is_some() {
+            return Err(OnyxError::new(
+                OnyxErrorCode::Forbidden,
+                "This account is banned",
+            ));
         }
     }
 
     let token = nanoid!();
-    let expires_at = timestamp() + 3600;
-
     let write = state.db.begin_write()?;
-    {
-        let mut auth_token_table = write.open_table(AUTH_TOKEN_TABLE)?;
-        auth_token_table.insert(token.as_str(), (user.id.as_str(), expires_at))?;
+    // migrate bcrypt and outdated argon2 hashes while we have the plaintext password
+    if needs_rehash {
+        user.password_hash = hash_password(&state.config, &payload.password)?;
+        let mut user_table = write.open_table(USER_TABLE)?;
+        user_table.insert(user.id.as_str(), user.clone())?;
     }
+    let session = create_session(
+        &write,
+        &state.config,
+        &user.id,
+        &token,
+        NewSession {
+            source: SessionSource::Login,
+            refresh_token: Some(&nanoid!()),
+            scope: TokenScope::Full,
+            remember: payload.remember_me,
+        },
+    )?;
     write.commit()?;
 
-    Ok(ResponseJson(LoginResponse {
-        user: UserModelSafe::from(user),
-        token,
-        expires_at,
-    }))
+    Ok(ResponseJson(login_response(user, token, session)))
 }
 
 pub async fn signup(
     State(state): State<OnyxState>,
-    Json(payload): Json<LoginRequest>,
+    ValidJson(SignupRequest(payload)): ValidJson<SignupRequest>,
 ) -> Result<ResponseJson<LoginResponse>, OnyxError> {
-    if payload.password.len() < MIN_PASSWORD_LEN {
-        return Err(OnyxError::bad_request(&format!(
-            "password must be more than {MIN_PASSWORD_LEN} characters"
-        )));
-    }
-    let password_hash = hash(payload.password, DEFAULT_COST)?;
+    let password_hash = hash_password(&state.config, &payload.password)?;
     let write = state.db.begin_write()?;
     let mut username_table = write.open_table(USERNAME_USER_ID_TABLE)?;
 
     if let Some(_) = username_table.get(payload.username.as_str())? {
-        return Err(OnyxError::bad_request("username is already in use"));
+        return Err(OnyxError::conflict("username is already in use"));
     }
 
     let user = UserModel {
@@ -91,30 +126,69 @@ pub async fn signup(
         password_hash,
     };
     let token = nanoid!();
-    let expires_at = timestamp() + 3600;
 
     {
         let mut user_table = write.open_table(USER_TABLE)?;
-        let mut auth_token_table = write.open_table(AUTH_TOKEN_TABLE)?;
         username_table.insert(user.username.as_str(), user.id.as_str())?;
         user_table.insert(user.id.as_str(), user.clone())?;
-        auth_token_table.insert(token.as_str(), (user.id.as_str(), expires_at))?;
         drop(username_table);
     }
+    let session = create_session(
+        &write,
+        &state.config,
+        &user.id,
+        &token,
+        NewSession {
+            source: SessionSource::Signup,
+            refresh_token: Some(&nanoid!()),
+            scope: TokenScope::Full,
+            remember: payload.remember_me,
+        },
+    )?;
     write.commit()?;
 
-    Ok(ResponseJson(LoginResponse {
-        user: UserModelSafe::from(user),
-        token,
-        expires_at,
-    }))
+    Ok(ResponseJson(login_response(user, token, session)))
+}
+
+/// Create an account for each of `config.admins` if the registry has no users yet, so a new
+/// registry can be administered without anyone signing up first. Each gets `admin_password`, or
+/// a random password. Returns the usernames and passwords created.
+pub fn bootstrap_admins(db: &Db, config: &Config) -> Result<Vec<(String, String)>> {
+    let write = db.begin_write()?;
+    let mut user_table = write.open_table(USER_TABLE)?;
+    if !user_table.is_empty()? {
+        return Ok(vec![]);
+    }
+    let mut username_table = write.open_table(USERNAME_USER_ID_TABLE)?;
+    let mut created = vec![];
+    for username in &config.admins {
+        validate_username(username).map_err(|e| anyhow::anyhow!("admin {username:?}: {e}"))?;
+        let password = config.admin_password.clone().unwrap_or_else(|| nanoid!());
+        validate_password(&password, username)
+            .map_err(|e| anyhow::anyhow!("admin_password for {username:?}: {e}"))?;
+        let user = UserModel {
+            username: username.clone(),
+            id: nanoid!(),
+            created_at: timestamp(),
+            password_hash: hash_password(config, &password)
+                .map_err(|e| anyhow::anyhow!("failed to hash password: {e:?}"))?,
+        };
+        username_table.insert(user.username.as_str(), user.id.as_str())?;
+        user_table.insert(user.id.as_str(), user.clone())?;
+        created.push((username.clone(), password));
+    }
+    drop(username_table);
+    drop(user_table);
+    write.commit()?;
+    Ok(created)
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
 
-    use crate::tests::OnyxTest;
+    use crate::testing::OnyxTest;
+    use crate::validate::MIN_PASSWORD_LEN;
     use anyhow::Result;
 
     #[tokio::test]
@@ -132,6 +206,7 @@ mod tests {
             .login(Some(LoginRequest {
                 username: login.user.username.clone(),
                 password,
+                ..Default::default()
             }))
             .await?;
 
@@ -151,10 +226,60 @@ mod tests {
             .signup(Some(LoginRequest {
                 username: nanoid!(),
                 password: nanoid!(TEST_PASSWORD_LEN),
+                ..Default::default()
             }))
             .await
             .unwrap_err();
         assert_eq!(e.to_string(), "password must be more than 10 characters");
+        let e = e.downcast_ref::<ApiError>().unwrap();
+        assert_eq!(e.code, OnyxErrorCode::ValidationFailed);
+        assert_eq!(
+            e.field_errors(),
+            vec![FieldError {
+                field: "password".to_string(),
+                message: "password must be more than 10 characters".to_string(),
+            }]
+        );
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn should_rehash_bcrypt_password() -> Result<()> {
+        let test = OnyxTest::new().await?;
+        let (login, password) = test.signup(None).await?;
+
+        // replace the hash with one from before the argon2 migration
+        {
+            let write = test.state.db.begin_write()?;
+            let mut user_table = write.open_table(USER_TABLE)?;
+            let mut user = user_table.get(login.user.id.as_str())?.unwrap().value();
+            user.password_hash = bcrypt::hash(&password, 4)?;
+            user_table.insert(login.user.id.as_str(), user)?;
+            drop(user_table);
+            write.commit()?;
+        }
+
+        test.login(Some(LoginRequest {
+            username: login.user.username.clone(),
+            password: password.clone(),
+            ..Default::default()
+        }))
+        .await?;
+
+        let read = test.state.db.begin_read()?;
+        let user_table = read.open_table(USER_TABLE)?;
+        let user = user_table.get(login.user.id.as_str())?.unwrap().value();
+        assert!(user.password_hash.starts_with("$argon2id$"));
+        drop(user_table);
+        drop(read);
+
+        // the new hash should work too
+        test.login(Some(LoginRequest {
+            username: login.user.username,
+            password,
+            ..Default::default()
+        }))
+        .await?;
         Ok(())
     }
 
@@ -177,6 +302,7 @@ mod tests {
             .login(Some(LoginRequest {
                 username: login.user.username,
                 password: nanoid!(),
+                ..Default::default()
             }))
             .await
             .unwrap_err();
@@ -193,6 +319,7 @@ mod tests {
             .signup(Some(LoginRequest {
                 username: username.clone(),
                 password: nanoid!(),
+                ..Default::default()
             }))
             .await?;
 
@@ -202,10 +329,40 @@ mod tests {
             .signup(Some(LoginRequest {
                 username: login.user.username,
                 password,
+                ..Default::default()
             }))
             .await
             .unwrap_err();
         assert_eq!(e.to_string(), "username is already in use");
         Ok(())
     }
+
+    #[tokio::test]
+    async fn should_bootstrap_admins_on_first_run() -> Result<()> {
+        let test = OnyxTest::new().await?;
+        let mut config = Config {
+            admins: ["root".to_string()].into_iter().collect(),
+            ..Config::default()
+        };
+
+        let created = bootstrap_admins(test.db(), &config)?;
+        assert_eq!(created.len(), 1);
+        let (username, password) = created[0].clone();
+        let login = test
+            .login(Some(LoginRequest {
+                username: username.clone(),
+                password,
+                ..Default::default()
+            }))
+            .await?;
+        assert_eq!(login.user.username, "root");
+
+        // only a registry without users is bootstrapped
+        assert!(bootstrap_admins(test.db(), &config)?.is_empty());
+
+        let test = OnyxTest::new().await?;
+        config.admin_password = Some("root".to_string());
+        assert!(bootstrap_admins(test.db(), &config).is_err());
+        Ok(())
+    }
 }
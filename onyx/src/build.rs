@@ -0,0 +1,387 @@
+//! Rebuilds published versions with `nargo` to check the artifacts attached to them.
+//!
+//! Packages are untrusted. nargo runs without the registry's environment and under time and
+//! memory limits, but it is not sandboxed otherwise: it can read what the registry's user can
+//! read and it has the host's network, which it needs to fetch git dependencies. Only set
+//! `nargo_path` on a registry whose host, or container, can't reach internal services and
+//! holds nothing but the registry.
+
+use std::fs::File;
+use std::fs::OpenOptions;
+use std::io::Write;
+use std::path::Path;
+use std::process::Command;
+use std::process::Stdio;
+use std::str::FromStr;
+use std::time::Duration;
+use std::time::Instant;
+
+use anyhow::Result;
+use axum::extract::Path as UrlPath;
+use axum::extract::State;
+use axum::response::Json as ResponseJson;
+use nargo_parse::NargoConfig;
+use redb::ReadableTable;
+use tar::Archive;
+
+use onyx_api::prelude::*;
+
+use super::OnyxError;
+use super::OnyxState;
+use super::db::WriteTxn;
+
+const DEFAULT_BUILD_TIMEOUT: u64 = 5 * 60;
+const DEFAULT_BUILD_MEMORY_LIMIT: u64 = 2048;
+// versions rebuilt per run of the job, the rest wait for the next run
+const BUILDS_PER_RUN: usize = 4;
+// bytes of compiler output kept from a failed build
+const MAX_LOG_LEN: usize = 4096;
+
+/// Queue a version to be rebuilt, e.g. after it's published or an artifact is attached to
+/// it. A version that's already queued is rebuilt once.
+pub fn enqueue(write: &WriteTxn, version_id: &HashId) -> Result<(), OnyxError> {
+    let mut build_queue_table = write.open_table(BUILD_QUEUE_TABLE)?;
+    let queued_at = build_queue_table
+        .get(version_id)?
+        .map(|queued_at| queued_at.value());
+    // always changes so a build running while this is queued doesn't dequeue it
+    let queued_at = queued_at.map_or(timestamp(), |v| timestamp().max(v + 1));
+    build_queue_table.insert(version_id, queued_at)?;
+    Ok(())
+}
+
+/// Rebuild queued versions with the configured `nargo` and record the results. Does
+/// nothing unless `nargo_path` is configured. Returns the number of versions rebuilt.
+pub fn run_queue(state: &OnyxState) -> Result<usize> {
+    let Some(nargo_path) = &state.config.nargo_path else {
+        return Ok(0);
+    };
+    let queued = {
+        let read = state.db.begin_read()?;
+        let build_queue_table = read.open_table(BUILD_QUEUE_TABLE)?;
+        let mut queued = vec![];
+        for entry in build_queue_table.iter()?.take(BUILDS_PER_RUN) {
+            let (version_id, queued_at) = entry?;
+            queued.push((version_id.value(), queued_at.value()));
+        }
+        queued
+    };
+    for (version_id, queued_at) in &queued {
+        // a version that can't be built is recorded as failed so it doesn't block the queue
+        let build = build(state, nargo_path, version_id).unwrap_or_else(|e| {
+            log::warn!("Failed to build version {}: {e:?}", version_id.to_string());
+            VersionBuildModel {
+                status: BuildStatus::Failed,
+                nargo_version: None,
+                built_at: timestamp(),
+                artifacts: vec![],
+                log: Some(format!("{e:#}")),
+            }
+        });
+        let write = state.db.begin_write()?;
+        {
+            let mut version_build_table = write.open_table(VERSION_BUILD_TABLE)?;
+            let mut build_queue_table = write.open_table(BUILD_QUEUE_TABLE)?;
+            version_build_table.insert(version_id, build)?;
+            let requeued = build_queue_table
+                .get(version_id)?
+                .is_some_and(|v| v.value() != *queued_at);
+            if !requeued {
+                build_queue_table.remove(version_id)?;
+            }
+        }
+        write.commit()?;
+    }
+    Ok(queued.len())
+}
+
+/// Extract the tarball of a version and compile it, then compare the artifacts attached to
+/// the version with the ones the compiler wrote.
+fn build(state: &OnyxState, nargo_path: &Path, version_id: &HashId) -> Result<VersionBuildModel> {
+    let workdir = tempfile::tempdir()?;
+    let package_dir = workdir.path().join("package");
+    let home_dir = workdir.path().join("home");
+    std::fs::create_dir(&package_dir)?;
+    std::fs::create_dir(&home_dir)?;
+    // tarballs are validated on publish, unpack_in also refuses paths outside package_dir
+    let mut archive = Archive::new(state.storage.reader(&version_id.to_string())?);
+    for entry in archive.entries()? {
+        entry?.unpack_in(&package_dir)?;
+    }
+    // only what nargo writes is compared, not artifacts the publisher shipped in the tarball
+    let target_dir = package_dir.join("target");
+    if target_dir.exists() {
+        std::fs::remove_dir_all(&target_dir)?;
+    }
+
+    let timeout = state.config.build_timeout.unwrap_or(DEFAULT_BUILD_TIMEOUT);
+    let memory_limit = state
+        .config
+        .build_memory_limit
+        .unwrap_or(DEFAULT_BUILD_MEMORY_LIMIT);
+    let nargo_version = Command::new(nargo_path)
+        .arg("--version")
+        .output()
+        .ok()
+        .filter(|output| output.status.success())
+        .and_then(|output| {
+            String::from_utf8_lossy(&output.stdout)
+                .lines()
+                .next()
+                .map(|line| line.trim().to_string())
+        });
+
+    let log_path = workdir.path().join("build.log");
+    let log = File::create(&log_path)?;
+    // the package is untrusted, so nargo runs without our environment and under limits, but
+    // with the host's network, see the module docs
+    let mut child = Command::new("sh")
+        .arg("-c")
+        .arg(format!(
+            "ulimit -v {}; ulimit -t {timeout}; exec \"$0\" compile",
+            memory_limit * 1024
+        ))
+        .arg(nargo_path)
+        .current_dir(&package_dir)
+        .env_clear()
+        .env("PATH", std::env::var_os("PATH").unwrap_or_default())
+        .env("HOME", &home_dir)
+        .stdin(Stdio::null())
+        .stdout(log.try_clone()?)
+        .stderr(log)
+        .spawn()?;
+    let deadline = Instant::now() + Duration::from_secs(timeout);
+    let succeeded = loop {
+        if let Some(status) = child.try_wait()? {
+            break status.success();
+        }
+        if Instant::now() >= deadline {
+            child.kill()?;
+            child.wait()?;
+            let mut log = OpenOptions::new().append(true).open(&log_path)?;
+            write!(log, "\ntimed out after {timeout}s")?;
+            break false;
+        }
+        std::thread::sleep(Duration::from_millis(100));
+    };
+    if !succeeded {
+        let log = std::fs::read(&log_path)?;
+        let tail = &log[log.len().saturating_sub(MAX_LOG_LEN)..];
+        return Ok(VersionBuildModel {
+            status: BuildStatus::Failed,
+            nargo_version,
+            built_at: timestamp(),
+            artifacts: vec![],
+            log: Some(String::from_utf8_lossy(tail).into_owned()),
+        });
+    }
+
+    let package_name = NargoConfig::load(&package_dir)?.package.name;
+    let attached = {
+        let read = state.db.begin_read()?;
+        let version_artifact_table = read.open_table(VERSION_ARTIFACT_TABLE)?;
+        let mut attached = vec![];
+        for entry in version_artifact_table
+            .range((version_id.clone(), "")..=(version_id.clone(), "\u{10ffff}"))?
+        {
+            attached.push(entry?.1.value());
+        }
+        attached
+    };
+    let mut artifacts = vec![];
+    for artifact in attached {
+        let content = std::fs::read(state.storage.name_to_artifact_path(&artifact.hash))?;
+        // artifacts may be renamed on upload, so fall back to the package artifact
+        let rebuilt_path = [&artifact.name, &package_name]
+            .iter()
+            .map(|name| target_dir.join(format!("{name}.json")))
+            .find(|path| path.exists());
+        let matches = match rebuilt_path {
+            Some(path) => same_program(&content, &std::fs::read(path)?),
+            None => false,
+        };
+        artifacts.push(ArtifactCheck {
+            name: artifact.name,
+            matches,
+        });
+    }
+    let status = if artifacts.is_empty() {
+        BuildStatus::Built
+    } else if artifacts.iter().all(|artifact| artifact.matches) {
+        BuildStatus::Verified
+    } else {
+        BuildStatus::Mismatch
+    };
+    Ok(VersionBuildModel {
+        status,
+        nargo_version,
+        built_at: timestamp(),
+        artifacts,
+        log: None,
+    })
+}
+
+/// Whether two program artifacts have the same abi and bytecode. Other fields, e.g. debug
+/// symbols with absolute paths, differ between machines.
+fn same_program(attached: &[u8], rebuilt: &[u8]) -> bool {
+    let (Ok(attached), Ok(rebuilt)) = (
+        serde_json::from_slice::<serde_json::Value>(attached),
+        serde_json::from_slice::<serde_json::Value>(rebuilt),
+    ) else {
+        return false;
+    };
+    ["abi", "bytecode"]
+        .iter()
+        .all(|field| attached.get(field).is_some() && attached.get(field) == rebuilt.get(field))
+}
+
+/// The latest rebuild of a version by the verification worker.
+pub async fn version_build(
+    State(state): State<OnyxState>,
+    UrlPath(id): UrlPath<String>,
+) -> Result<ResponseJson<VersionBuildModel>, OnyxError> {
+    let version_id = HashId::from_str(&id)?;
+    let read = state.db.begin_read()?;
+    let version_build_table = read.open_table(VERSION_BUILD_TABLE)?;
+    match version_build_table.get(&version_id)? {
+        Some(build) => Ok(ResponseJson(build.value())),
+        None => Err(OnyxError::not_found("Version has not been built")),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::os::unix::fs::PermissionsExt;
+
+    use anyhow::Result;
+    use nanoid::nanoid;
+    use onyx_api::prelude::*;
+
+    use crate::testing::OnyxTest;
+
+    // compiles a package by copying its `aaaaa` file into the bytecode of the artifact, or
+    // writes nothing if it says so
+    const FAKE_NARGO: &str = r#"#!/bin/sh
+if [ "$1" = "--version" ]; then
+    echo "nargo version = 1.0.0-test"
+    exit 0
+fi
+if grep -q nothing aaaaa; then
+    exit 0
+fi
+if grep -q fail aaaaa; then
+    echo "error: failed to compile" >&2
+    exit 1
+fi
+name=$(sed -n 's/^name = "\(.*\)"/\1/p' Nargo.toml)
+mkdir -p target
+printf '{"abi":{"parameters":[]},"bytecode":"%s","debug_symbols":"%s"}' "$(cat aaaaa)" "$PWD" > "target/$name.json"
+"#;
+
+    /// A program artifact of `bytecode`.
+    fn program(bytecode: &str) -> String {
+        format!("{{\"abi\":{{\"parameters\":[]}},\"bytecode\":\"{bytecode}\"}}")
+    }
+
+    #[tokio::test]
+    async fn should_verify_builds() -> Result<()> {
+        let nargo_dir = tempfile::tempdir()?;
+        let nargo_path = nargo_dir.path().join("nargo");
+        std::fs::write(&nargo_path, FAKE_NARGO)?;
+        std::fs::set_permissions(&nargo_path, std::fs::Permissions::from_mode(0o755))?;
+        let test = OnyxTest::with_nargo(&nargo_path).await?;
+        let (login, _password) = test.signup(None).await?;
+        assert!(test.api.meta().await?.has_feature("builds"));
+
+        // the `aaaaa` file of each holds what the fake nargo compiles to bytecode
+        let mut version_ids = vec![];
+        for content in ["abc", "abc", "def", "fail"] {
+            let (hash, _) = test
+                .seed_version(&login, &nanoid!(), "0.1.0", Some(content))
+                .await?;
+            version_ids.push(HashId::from(hash));
+        }
+        let [verified, mismatch, built, failed] = version_ids.try_into().unwrap();
+        test.seed_artifact(&login, &verified, "program", &program("abc"))
+            .await?;
+        test.seed_artifact(&login, &mismatch, "program", &program("xyz"))
+            .await?;
+        assert!(test.api.version_build(&verified).await?.is_none());
+
+        assert_eq!(test.verify_builds().await?, 4);
+        assert_eq!(test.verify_builds().await?, 0);
+
+        let build = test.api.version_build(&verified).await?.unwrap();
+        assert_eq!(build.status, BuildStatus::Verified);
+        assert_eq!(
+            build.nargo_version.as_deref(),
+            Some("nargo version = 1.0.0-test")
+        );
+        assert_eq!(
+            build.artifacts,
+            vec![ArtifactCheck {
+                name: "program".to_string(),
+                matches: true,
+            }]
+        );
+        let build = test.api.version_build(&mismatch).await?.unwrap();
+        assert_eq!(build.status, BuildStatus::Mismatch);
+        assert!(!build.artifacts[0].matches);
+        let build = test.api.version_build(&built).await?.unwrap();
+        assert_eq!(build.status, BuildStatus::Built);
+        let build = test.api.version_build(&failed).await?.unwrap();
+        assert_eq!(build.status, BuildStatus::Failed);
+        assert!(build.log.unwrap().contains("failed to compile"));
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn should_not_verify_shipped_artifacts() -> Result<()> {
+        let nargo_dir = tempfile::tempdir()?;
+        let nargo_path = nargo_dir.path().join("nargo");
+        std::fs::write(&nargo_path, FAKE_NARGO)?;
+        std::fs::set_permissions(&nargo_path, std::fs::Permissions::from_mode(0o755))?;
+        let test = OnyxTest::with_nargo(&nargo_path).await?;
+        let (login, _password) = test.signup(None).await?;
+
+        // the tarball ships the artifact nargo would write, but nargo writes nothing
+        let artifact = program("spoofed");
+        let tarball = OnyxTest::create_tarball_from_files(&[
+            (
+                "Nargo.toml",
+                "[package]\nname = \"spoofed\"\nversion = \"0.1.0\"\n",
+            ),
+            ("aaaaa", "nothing"),
+            ("target/program.json", &artifact),
+            ("target/spoofed.json", &artifact),
+        ])?;
+        let version_id = HashId::from(tarball.1);
+        test.publish(
+            Some(PublishData::new(tarball.1.to_string(), login.token.clone())),
+            tarball,
+        )
+        .await?;
+        test.seed_artifact(&login, &version_id, "program", &artifact)
+            .await?;
+
+        assert_eq!(test.verify_builds().await?, 1);
+        let build = test.api.version_build(&version_id).await?.unwrap();
+        assert_eq!(build.status, BuildStatus::Mismatch);
+        assert!(!build.artifacts[0].matches);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn should_not_build_without_nargo() -> Result<()> {
+        let test = OnyxTest::new().await?;
+        let (login, _password) = test.signup(None).await?;
+        let (hash, _) = test
+            .seed_version(&login, &nanoid!(), "0.1.0", Some("abc"))
+            .await?;
+        let version_id = HashId::from(hash);
+        assert_eq!(test.verify_builds().await?, 0);
+        assert!(test.api.version_build(&version_id).await?.is_none());
+        assert!(!test.api.meta().await?.has_feature("builds"));
+        Ok(())
+    }
+}
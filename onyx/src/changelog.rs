@@ -0,0 +1,152 @@
+use anyhow::Result;
+use axum::extract::Query;
+use axum::extract::State;
+use axum::response::Json as ResponseJson;
+use redb::ReadableTable;
+use redb::ReadableTableMetadata;
+use serde::Deserialize;
+
+use onyx_api::prelude::*;
+
+use super::OnyxError;
+use super::OnyxState;
+use super::db::Db;
+use super::db::WriteTxn;
+
+const DEFAULT_PAGE_SIZE: usize = 100;
+const MAX_PAGE_SIZE: usize = 1000;
+
+/// Record a newly published version.
+pub fn append(
+    write: &WriteTxn,
+    package: &PackageModel,
+    version: &PackageVersionModel,
+) -> Result<u64, OnyxError> {
+    let mut changelog_table = write.open_table(CHANGELOG_TABLE)?;
+    let seq = changelog_table
+        .last()?
+        .map(|(seq, _)| seq.value() + 1)
+        .unwrap_or(1);
+    changelog_table.insert(
+        seq,
+        ChangelogEntry {
+            seq,
+            package_name: package.name.clone(),
+            version_name: version.name.clone(),
+            version_id: version.id.clone(),
+            author_id: version.author_id.clone(),
+            published_at: version.created_at,
+        },
+    )?;
+    Ok(seq)
+}
+
+/// Populate an empty changelog with versions published before it existed, ordered by
+/// publish time.
+pub fn backfill(db: &Db) -> Result<()> {
+    let write = db.begin_write()?;
+    {
+        let mut changelog_table = write.open_table(CHANGELOG_TABLE)?;
+        if !changelog_table.is_empty()? {
+            return Ok(());
+        }
+        let version_table = write.open_table(VERSION_TABLE)?;
+        let package_table = write.open_table(PACKAGE_TABLE)?;
+        let mut versions = vec![];
+        for entry in version_table.iter()? {
+            versions.push(entry?.1.value());
+        }
+        versions.sort_by_key(|v| v.created_at);
+        for (i, version) in versions.into_iter().enumerate() {
+            let Some(package) = package_table.get(version.package_id.as_str())? else {
+                log::warn!("version {} has no package", version.id.to_string());
+                continue;
+            };
+            let seq = i as u64 + 1;
+            changelog_table.insert(
+                seq,
+                ChangelogEntry {
+                    seq,
+                    package_name: package.value().name,
+                    version_name: version.name,
+                    version_id: version.id,
+                    author_id: version.author_id,
+                    published_at: version.created_at,
+                },
+            )?;
+        }
+    }
+    write.commit()?;
+    Ok(())
+}
+
+#[derive(Deserialize)]
+pub struct ChangelogQuery {
+    #[serde(default)]
+    since: u64,
+    limit: Option<usize>,
+}
+
+pub async fn changelog(
+    State(state): State<OnyxState>,
+    Query(query): Query<ChangelogQuery>,
+) -> Result<ResponseJson<ChangelogResponse>, OnyxError> {
+    let limit = query.limit.unwrap_or(DEFAULT_PAGE_SIZE).min(MAX_PAGE_SIZE);
+    let read = state.db.begin_read()?;
+    let changelog_table = read.open_table(CHANGELOG_TABLE)?;
+    let latest_seq = changelog_table
+        .last()?
+        .map(|(seq, _)| seq.value())
+        .unwrap_or(0);
+    let mut entries = vec![];
+    for entry in changelog_table
+        .range(query.since.saturating_add(1)..)?
+        .take(limit)
+    {
+        entries.push(entry?.1.value());
+    }
+    Ok(ResponseJson(ChangelogResponse {
+        entries,
+        latest_seq,
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::testing::OnyxTest;
+    use anyhow::Result;
+    use nanoid::nanoid;
+    use onyx_api::prelude::*;
+
+    #[tokio::test]
+    async fn should_list_changelog() -> Result<()> {
+        let test = OnyxTest::new().await?;
+        let (login, _password) = test.signup(None).await?;
+        let name = nanoid!();
+        for version in ["0.1.0", "0.2.0"] {
+            let tarball = OnyxTest::create_test_tarball_named(None, Some(&name), Some(version))?;
+            test.publish(
+                Some(PublishData::new(tarball.1.to_string(), login.token.clone())),
+                tarball,
+            )
+            .await?;
+        }
+
+        let changelog = test.api.changelog(0, 100).await?;
+        assert_eq!(changelog.latest_seq, 2);
+        assert_eq!(
+            changelog
+                .entries
+                .iter()
+                .map(|e| (e.seq, e.version_name.as_str()))
+                .collect::<Vec<_>>(),
+            vec![(1, "0.1.0"), (2, "0.2.0")]
+        );
+        assert!(changelog.entries.iter().all(|e| e.package_name == name));
+
+        let changelog = test.api.changelog(1, 100).await?;
+        assert_eq!(changelog.entries.len(), 1);
+        assert_eq!(changelog.entries[0].seq, 2);
+        Ok(())
+    }
+}
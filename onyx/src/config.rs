@@ -0,0 +1,451 @@
+use std::collections::BTreeMap;
+use std::collections::BTreeSet;
+use std::path::Path;
+use std::path::PathBuf;
+use std::str::FromStr;
+
+use anyhow::Context;
+use anyhow::Result;
+use argon2::Params;
+use serde::Deserialize;
+use serde::Serialize;
+
+use super::cors::DEFAULT_CORS_ORIGINS;
+use super::session::PUBLISH_TOKEN_TTL;
+use super::session::REFRESH_TTL;
+use super::session::REMEMBER_TTL;
+use super::session::SESSION_TTL;
+use super::typosquat::TyposquatPolicy;
+
+/// Config file read from the working directory when no other is given.
+pub const DEFAULT_CONFIG_PATH: &str = "onyx.toml";
+
+/// Settings of a registry. Each is read from `onyx.toml`, then overridden by the environment
+/// variable named in its doc comment, and otherwise left at its default.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    /// Port to listen on. `PORT`
+    pub port: u16,
+    /// Directory relative `db_path` and `storage_path` are in, created if it doesn't exist.
+    /// The working directory by default. `ONYX_DATA_DIR`
+    pub data_dir: Option<PathBuf>,
+    /// The redb database. `ONYX_DB_PATH`
+    pub db_path: PathBuf,
+    /// Directory tarballs are stored in. `ONYX_STORAGE_PATH`
+    pub storage_path: PathBuf,
+    /// Largest publish request accepted, in bytes. `ONYX_MAX_UPLOAD_SIZE`
+    pub max_upload_size: usize,
+    /// Largest body accepted by any other request, in bytes. `ONYX_MAX_REQUEST_SIZE`
+    pub max_request_size: usize,
+    /// Bytes of tarballs and artifacts each user may store for the packages they own, unless
+    /// an admin sets another quota for them. Unlimited by default. `ONYX_STORAGE_QUOTA`
+    pub storage_quota: Option<u64>,
+    /// Seconds an auth token is valid for. `ONYX_SESSION_TTL`
+    pub session_ttl: u64,
+    /// Seconds a refresh token is valid for. `ONYX_REFRESH_TTL`
+    pub refresh_ttl: u64,
+    /// Seconds a refresh token is valid for when the user logs in with "remember me".
+    /// `ONYX_REMEMBER_TTL`
+    pub remember_ttl: u64,
+    /// Seconds a publish scoped auth token, as proposed by nrpm, is valid for.
+    /// `ONYX_PUBLISH_TOKEN_TTL`
+    pub publish_token_ttl: u64,
+    /// Origins browsers may call the api from with credentials, the nrpm web app by default. `"*"`
+    /// allows any origin without credentials. Comma separated in `ONYX_CORS_ORIGINS`
+    pub cors_origins: Vec<String>,
+    /// Usernames allowed to use the admin endpoints. Comma separated in `ONYX_ADMINS`
+    pub admins: BTreeSet<String>,
+    /// Password of the admin accounts created when the registry starts without any users, a
+    /// random one is logged for each if unset. `ONYX_ADMIN_PASSWORD`
+    pub admin_password: Option<String>,
+    /// Registry to mirror. A mirror rejects publishes and periodically pulls new versions from
+    /// upstream. `ONYX_UPSTREAM_URL`
+    pub upstream_url: Option<String>,
+    /// Directory to export the static index to. The export job keeps it up to date with new
+    /// publishes. `ONYX_INDEX_PATH`
+    pub index_path: Option<PathBuf>,
+    /// The latest nrpm release. Clients older than this suggest updating. `ONYX_CLI_VERSION`
+    pub cli_version: Option<String>,
+    /// Redirect downloads to signed urls on this CDN instead of streaming them, see
+    /// `CdnConfig`. `ONYX_CDN_URL`
+    pub cdn_url: Option<String>,
+    /// Secret shared with the CDN, required with `cdn_url`. `ONYX_CDN_SIGNING_KEY`
+    pub cdn_signing_key: Option<String>,
+    /// Seconds a signed CDN url remains valid. `ONYX_CDN_URL_TTL`
+    pub cdn_url_ttl: Option<u64>,
+    /// Sign snapshots of every published version with this pkcs8 ed25519 key, made with
+    /// `onyx keygen`. `ONYX_SNAPSHOT_KEY_PATH`
+    pub snapshot_key_path: Option<PathBuf>,
+    /// Directory of signed roots, `{version}.root.json` made with `onyx sign-root`, required
+    /// with `snapshot_key_path`. `ONYX_ROOT_PATH`
+    pub root_path: Option<PathBuf>,
+    /// Seconds a signed snapshot remains valid. `ONYX_SNAPSHOT_TTL`
+    pub snapshot_ttl: Option<u64>,
+    /// Rebuild each published version with this `nargo` and check the artifacts attached to
+    /// it against the rebuilt ones. Untrusted packages are compiled with the host's network, so
+    /// only set this on an isolated host. `ONYX_NARGO_PATH`
+    pub nargo_path: Option<PathBuf>,
+    /// Seconds a rebuild may run for. `ONYX_BUILD_TIMEOUT`
+    pub build_timeout: Option<u64>,
+    /// Megabytes of memory a rebuild may use. `ONYX_BUILD_MEMORY_LIMIT`
+    pub build_memory_limit: Option<u64>,
+    /// Scan each upload with these commands before publishing it, see `scan::CommandScanner`.
+    /// Comma separated in `ONYX_SCAN_COMMANDS`
+    pub scan_commands: Vec<PathBuf>,
+    /// Seconds a scan command may run for. `ONYX_SCAN_TIMEOUT`
+    pub scan_timeout: Option<u64>,
+    /// What to do when a new package name resembles a popular package: `off`, `warn`,
+    /// `review`, or `reject`. `ONYX_TYPOSQUAT_POLICY`
+    pub typosquat_policy: TyposquatPolicy,
+    /// Take the address a request came from from the `X-Forwarded-For` header set by a reverse
+    /// proxy in front of the registry. Only set this behind a proxy that overwrites the
+    /// header, anyone can send it. `ONYX_TRUST_FORWARDED_FOR`
+    pub trust_forwarded_for: bool,
+    /// Send webhooks to loopback, private and link-local addresses. Only set this when every
+    /// user is trusted with the registry's network. `ONYX_ALLOW_PRIVATE_WEBHOOKS`
+    pub allow_private_webhooks: bool,
+    /// Hosts git dependencies can be fetched through the registry from, keyed to the url
+    /// their repositories are cloned from, e.g. `"github.com" = "https://github.com"`. The
+    /// registry serves `/{host}/{org}/{repo}` at any tag of the upstream repository, fetched
+    /// once and kept. Comma separated `host=url` pairs in `ONYX_PROXY_UPSTREAMS`
+    pub proxy_upstreams: BTreeMap<String, String>,
+    /// Database transactions that take longer than this many milliseconds are logged with the
+    /// route and tables involved. `ONYX_SLOW_TRANSACTION_MS`
+    pub slow_transaction_ms: u64,
+    /// Packages, latest versions and git refs each kept in memory, the least recently used
+    /// dropped first. 0 reads them from the database every time. `ONYX_READ_CACHE_ENTRIES`
+    pub read_cache_entries: usize,
+    /// Accept versions published with names that aren't semver, as registries did before
+    /// they were required. Set it while publishers move to semver names, versions already
+    /// published keep their names either way. `ONYX_LEGACY_VERSION_NAMES`
+    pub legacy_version_names: bool,
+    /// Memory of each new Argon2id password hash, in KiB. `ONYX_ARGON2_M_COST`
+    pub argon2_m_cost: u32,
+    /// Iterations of each new Argon2id password hash. `ONYX_ARGON2_T_COST`
+    pub argon2_t_cost: u32,
+    /// Parallelism of each new Argon2id password hash. `ONYX_ARGON2_P_COST`
+    pub argon2_p_cost: u32,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            port: 3000,
+            data_dir: None,
+            db_path: PathBuf::from("./db.redb"),
+            storage_path: PathBuf::from("./package_data"),
+            // Max 20 MB upload size
+            max_upload_size: 20 * 1024 * 1024,
+            max_request_size: 1024 * 1024,
+            storage_quota: None,
+            session_ttl: SESSION_TTL,
+            refresh_ttl: REFRESH_TTL,
+            remember_ttl: REMEMBER_TTL,
+            publish_token_ttl: PUBLISH_TOKEN_TTL,
+            cors_origins: DEFAULT_CORS_ORIGINS.iter().map(|v| v.to_string()).collect(),
+            admins: BTreeSet::new(),
+            admin_password: None,
+            upstream_url: None,
+            index_path: None,
+            cli_version: None,
+            cdn_url: None,
+            cdn_signing_key: None,
+            cdn_url_ttl: None,
+            snapshot_key_path: None,
+            root_path: None,
+            snapshot_ttl: None,
+            nargo_path: None,
+            build_timeout: None,
+            build_memory_limit: None,
+            scan_commands: vec![],
+            scan_timeout: None,
+            typosquat_policy: TyposquatPolicy::default(),
+            trust_forwarded_for: false,
+            allow_private_webhooks: false,
+            proxy_upstreams: BTreeMap::new(),
+            slow_transaction_ms: 500,
+            read_cache_entries: 10_000,
+            legacy_version_names: false,
+            argon2_m_cost: Params::DEFAULT_M_COST,
+            argon2_t_cost: Params::DEFAULT_T_COST,
+            argon2_p_cost: Params::DEFAULT_P_COST,
+        }
+    }
+}
+
+/// A non-empty environment variable.
+fn env(key: &str) -> Option<String> {
+    std::env::var(key).ok().filter(|v| !v.is_empty())
+}
+
+fn parse_env<T: FromStr>(key: &str) -> Result<Option<T>>
+where
+    T::Err: std::error::Error + Send + Sync + 'static,
+{
+    env(key)
+        .map(|v| {
+            v.parse()
+                .with_context(|| format!("{key} is invalid: {v:?}"))
+        })
+        .transpose()
+}
+
+fn split_list(value: &str) -> impl Iterator<Item = String> {
+    value
+        .split(',')
+        .map(|v| v.trim().to_string())
+        .filter(|v| !v.is_empty())
+}
+
+impl Config {
+    /// Read the config file at `path`, or `onyx.toml` if it exists, and apply environment
+    /// overrides.
+    pub fn load(path: Option<&Path>) -> Result<Self> {
+        let mut config = match path {
+            Some(path) => Self::from_file(path)?,
+            None if Path::new(DEFAULT_CONFIG_PATH).exists() => {
+                Self::from_file(Path::new(DEFAULT_CONFIG_PATH))?
+            }
+            None => Self::default(),
+        };
+        config.apply_env()?;
+        config.argon2_params()?;
+        Ok(config)
+    }
+
+    fn from_file(path: &Path) -> Result<Self> {
+        let str = std::fs::read_to_string(path)
+            .with_context(|| format!("Failed to read config file {path:?}"))?;
+        toml::from_str(&str).with_context(|| format!("Failed to parse config file {path:?}"))
+    }
+
+    fn apply_env(&mut self) -> Result<()> {
+        if let Some(port) = parse_env("PORT")? {
+            self.port = port;
+        }
+        if let Some(data_dir) = env("ONYX_DATA_DIR") {
+            self.data_dir = Some(PathBuf::from(data_dir));
+        }
+        if let Some(db_path) = env("ONYX_DB_PATH") {
+            self.db_path = PathBuf::from(db_path);
+        }
+        if let Some(storage_path) = env("ONYX_STORAGE_PATH") {
+            self.storage_path = PathBuf::from(storage_path);
+        }
+        if let Some(max_upload_size) = parse_env("ONYX_MAX_UPLOAD_SIZE")? {
+            self.max_upload_size = max_upload_size;
+        }
+        if let Some(max_request_size) = parse_env("ONYX_MAX_REQUEST_SIZE")? {
+            self.max_request_size = max_request_size;
+        }
+        if let Some(storage_quota) = parse_env("ONYX_STORAGE_QUOTA")? {
+            self.storage_quota = Some(storage_quota);
+        }
+        if let Some(session_ttl) = parse_env("ONYX_SESSION_TTL")? {
+            self.session_ttl = session_ttl;
+        }
+        if let Some(refresh_ttl) = parse_env("ONYX_REFRESH_TTL")? {
+            self.refresh_ttl = refresh_ttl;
+        }
+        if let Some(remember_ttl) = parse_env("ONYX_REMEMBER_TTL")? {
+            self.remember_ttl = remember_ttl;
+        }
+        if let Some(publish_token_ttl) = parse_env("ONYX_PUBLISH_TOKEN_TTL")? {
+            self.publish_token_ttl = publish_token_ttl;
+        }
+        if let Some(cors_origins) = env("ONYX_CORS_ORIGINS") {
+            self.cors_origins = split_list(&cors_origins).collect();
+        }
+        if let Some(admins) = env("ONYX_ADMINS") {
+            self.admins = split_list(&admins).collect();
+        }
+        if let Some(admin_password) = env("ONYX_ADMIN_PASSWORD") {
+            self.admin_password = Some(admin_password);
+        }
+        if let Some(upstream_url) = env("ONYX_UPSTREAM_URL") {
+            self.upstream_url = Some(upstream_url);
+        }
+        if let Some(index_path) = env("ONYX_INDEX_PATH") {
+            self.index_path = Some(PathBuf::from(index_path));
+        }
+        if let Some(cli_version) = env("ONYX_CLI_VERSION") {
+            self.cli_version = Some(cli_version);
+        }
+        if let Some(cdn_url) = env("ONYX_CDN_URL") {
+            self.cdn_url = Some(cdn_url);
+        }
+        if let Some(cdn_signing_key) = env("ONYX_CDN_SIGNING_KEY") {
+            self.cdn_signing_key = Some(cdn_signing_key);
+        }
+        if let Some(cdn_url_ttl) = parse_env("ONYX_CDN_URL_TTL")? {
+            self.cdn_url_ttl = Some(cdn_url_ttl);
+        }
+        if let Some(snapshot_key_path) = env("ONYX_SNAPSHOT_KEY_PATH") {
+            self.snapshot_key_path = Some(PathBuf::from(snapshot_key_path));
+        }
+        if let Some(root_path) = env("ONYX_ROOT_PATH") {
+            self.root_path = Some(PathBuf::from(root_path));
+        }
+        if let Some(snapshot_ttl) = parse_env("ONYX_SNAPSHOT_TTL")? {
+            self.snapshot_ttl = Some(snapshot_ttl);
+        }
+        if let Some(nargo_path) = env("ONYX_NARGO_PATH") {
+            self.nargo_path = Some(PathBuf::from(nargo_path));
+        }
+        if let Some(build_timeout) = parse_env("ONYX_BUILD_TIMEOUT")? {
+            self.build_timeout = Some(build_timeout);
+        }
+        if let Some(build_memory_limit) = parse_env("ONYX_BUILD_MEMORY_LIMIT")? {
+            self.build_memory_limit = Some(build_memory_limit);
+        }
+        if let Some(scan_commands) = env("ONYX_SCAN_COMMANDS") {
+            self.scan_commands = split_list(&scan_commands).map(PathBuf::from).collect();
+        }
+        if let Some(scan_timeout) = parse_env("ONYX_SCAN_TIMEOUT")? {
+            self.scan_timeout = Some(scan_timeout);
+        }
+        if let Some(typosquat_policy) = parse_env("ONYX_TYPOSQUAT_POLICY")? {
+            self.typosquat_policy = typosquat_policy;
+        }
+        if let Some(trust_forwarded_for) = parse_env("ONYX_TRUST_FORWARDED_FOR")? {
+            self.trust_forwarded_for = trust_forwarded_for;
+        }
+        if let Some(allow_private_webhooks) = parse_env("ONYX_ALLOW_PRIVATE_WEBHOOKS")? {
+            self.allow_private_webhooks = allow_private_webhooks;
+        }
+        if let Some(proxy_upstreams) = env("ONYX_PROXY_UPSTREAMS") {
+            self.proxy_upstreams = split_list(&proxy_upstreams)
+                .map(|pair| {
+                    pair.split_once('=')
+                        .map(|(host, url)| (host.trim().to_string(), url.trim().to_string()))
+                        .ok_or(anyhow::anyhow!(
+                            "ONYX_PROXY_UPSTREAMS entries must be host=url: {pair:?}"
+                        ))
+                })
+                .collect::<Result<_>>()?;
+        }
+        if let Some(slow_transaction_ms) = parse_env("ONYX_SLOW_TRANSACTION_MS")? {
+            self.slow_transaction_ms = slow_transaction_ms;
+        }
+        if let Some(read_cache_entries) = parse_env("ONYX_READ_CACHE_ENTRIES")? {
+            self.read_cache_entries = read_cache_entries;
+        }
+        if let Some(legacy_version_names) = parse_env("ONYX_LEGACY_VERSION_NAMES")? {
+            self.legacy_version_names = legacy_version_names;
+        }
+        if let Some(argon2_m_cost) = parse_env("ONYX_ARGON2_M_COST")? {
+            self.argon2_m_cost = argon2_m_cost;
+        }
+        if let Some(argon2_t_cost) = parse_env("ONYX_ARGON2_T_COST")? {
+            self.argon2_t_cost = argon2_t_cost;
+        }
+        if let Some(argon2_p_cost) = parse_env("ONYX_ARGON2_P_COST")? {
+            self.argon2_p_cost = argon2_p_cost;
+        }
+        Ok(())
+    }
+
+    /// The Argon2id parameters new password hashes use. Checked when the config is loaded, so
+    /// it only fails for a config that wasn't.
+    pub fn argon2_params(&self) -> Result<Params> {
+        Params::new(
+            self.argon2_m_cost,
+            self.argon2_t_cost,
+            self.argon2_p_cost,
+            None,
+        )
+        .map_err(|e| anyhow::anyhow!("invalid argon2 parameters: {e}"))
+    }
+
+    /// `path` in `data_dir`, unless it's absolute.
+    pub fn data_path(&self, path: &Path) -> PathBuf {
+        match &self.data_dir {
+            Some(data_dir) => data_dir.join(path),
+            None => path.to_path_buf(),
+        }
+    }
+
+    /// Create `data_dir` and the storage directory in it, if it's set. Without it both are
+    /// expected to exist.
+    pub fn create_data_dir(&self) -> Result<()> {
+        if self.data_dir.is_some() {
+            std::fs::create_dir_all(self.data_path(&self.storage_path))?;
+        }
+        Ok(())
+    }
+
+    /// The config as toml, with secrets hidden.
+    pub fn to_toml_redacted(&self) -> Result<String> {
+        let mut config = self.clone();
+        if config.cdn_signing_key.is_some() {
+            config.cdn_signing_key = Some("<redacted>".to_string());
+        }
+        if config.admin_password.is_some() {
+            config.admin_password = Some("<redacted>".to_string());
+        }
+        Ok(toml::to_string_pretty(&config)?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn should_read_config_file() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let path = dir.path().join("onyx.toml");
+        std::fs::write(
+            &path,
+            "port = 8080\nadmins = [\"alice\"]\ncdn_url = \"https://cdn.example.com\"\n\
+             cdn_signing_key = \"secret\"\nadmin_password = \"secret\"\n",
+        )?;
+        let config = Config::from_file(&path)?;
+        assert_eq!(config.port, 8080);
+        assert!(config.admins.contains("alice"));
+        // unset fields keep their defaults
+        assert_eq!(config.session_ttl, SESSION_TTL);
+
+        let printed = config.to_toml_redacted()?;
+        assert!(!printed.contains("secret"));
+        let printed = toml::from_str::<Config>(&printed)?;
+        assert_eq!(printed.port, 8080);
+
+        std::fs::write(&path, "prot = 8080\n")?;
+        assert!(Config::from_file(&path).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn should_reject_invalid_argon2_params() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let path = dir.path().join("onyx.toml");
+        std::fs::write(&path, "argon2_m_cost = 1\n")?;
+        assert!(Config::load(Some(&path)).is_err());
+        std::fs::write(&path, "argon2_t_cost = 1\n")?;
+        assert!(Config::load(Some(&path)).is_ok());
+        std::fs::write(&path, "argon2_p_cost = -1\n")?;
+        assert!(Config::load(Some(&path)).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn should_resolve_paths_in_data_dir() {
+        let mut config = Config::default();
+        assert_eq!(
+            config.data_path(&config.db_path),
+            PathBuf::from("./db.redb")
+        );
+
+        config.data_dir = Some(PathBuf::from("/var/lib/onyx"));
+        assert_eq!(
+            config.data_path(&config.db_path),
+            PathBuf::from("/var/lib/onyx/db.redb")
+        );
+        assert_eq!(
+            config.data_path(Path::new("/mnt/package_data")),
+            PathBuf::from("/mnt/package_data")
+        );
+    }
+}
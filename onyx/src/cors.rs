@@ -0,0 +1,184 @@
+use axum::http::HeaderName;
+use axum::http::HeaderValue;
+use axum::http::Method;
+use axum::http::header;
+use tower_http::cors::AllowOrigin;
+use tower_http::cors::CorsLayer;
+
+use onyx_api::prelude::*;
+
+use super::Config;
+
+/// Origins of the web app allowed to call the api unless `cors_origins` is configured.
+#[cfg(debug_assertions)]
+pub const DEFAULT_CORS_ORIGINS: &[&str] = &[
+    "https://nrpm.io",
+    "http://127.0.0.1:8080",
+    "http://localhost:8080",
+];
+#[cfg(not(debug_assertions))]
+pub const DEFAULT_CORS_ORIGINS: &[&str] = &["https://nrpm.io"];
+
+/// The `cors_origins` entry allowing any origin. Browsers won't send credentials to such a
+/// registry.
+pub const ANY_ORIGIN: &str = "*";
+
+/// Build the CORS policy for `config.cors_origins`. Listed origins may send credentials and the
+/// headers the api reads, nothing else is allowed.
+pub fn cors_layer(config: &Config) -> CorsLayer {
+    let cors = CorsLayer::new()
+        .allow_methods([
+            Method::GET,
+            Method::POST,
+            Method::PUT,
+            Method::PATCH,
+            Method::DELETE,
+        ])
+        .allow_headers([
+            header::AUTHORIZATION,
+            header::CONTENT_TYPE,
+            header::IF_NONE_MATCH,
+            HeaderName::try_from(IDEMPOTENCY_KEY_HEADER).expect("valid header name"),
+        ])
+        .expose_headers([header::ETAG, HeaderName::from_static(API_VERSION_HEADER)])
+        .max_age(std::time::Duration::from_secs(60 * 60));
+    if config
+        .cors_origins
+        .iter()
+        .any(|origin| origin == ANY_ORIGIN)
+    {
+        // the spec forbids credentials with a wildcard origin
+        return cors.allow_origin(AllowOrigin::any());
+    }
+    let origins = config
+        .cors_origins
+        .iter()
+        .filter_map(|origin| {
+            HeaderValue::from_str(origin.trim_end_matches('/'))
+                .inspect_err(|_| log::warn!("ignoring invalid cors origin: {origin:?}"))
+                .ok()
+        })
+        .collect::<Vec<_>>();
+    cors.allow_origin(AllowOrigin::list(origins))
+        .allow_credentials(true)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use anyhow::Result;
+    use reqwest::Method;
+    use reqwest::header;
+
+    use super::*;
+    use crate::testing::OnyxTest;
+
+    async fn preflight(
+        test: &OnyxTest,
+        origin: &str,
+        method: &str,
+        headers: &str,
+    ) -> Result<reqwest::Response> {
+        Ok(reqwest::Client::new()
+            .request(Method::OPTIONS, format!("{}/v0/auth", test.url))
+            .header(header::ORIGIN, origin)
+            .header(header::ACCESS_CONTROL_REQUEST_METHOD, method)
+            .header(header::ACCESS_CONTROL_REQUEST_HEADERS, headers)
+            .send()
+            .await?)
+    }
+
+    #[tokio::test]
+    async fn should_allow_known_origins() -> Result<()> {
+        let test = OnyxTest::new().await?;
+        let response = preflight(
+            &test,
+            "https://nrpm.io",
+            "POST",
+            "authorization,content-type",
+        )
+        .await?;
+        assert!(response.status().is_success());
+        let headers = response.headers();
+        assert_eq!(
+            headers[header::ACCESS_CONTROL_ALLOW_ORIGIN],
+            "https://nrpm.io"
+        );
+        assert_eq!(headers[header::ACCESS_CONTROL_ALLOW_CREDENTIALS], "true");
+        let allowed_headers = headers[header::ACCESS_CONTROL_ALLOW_HEADERS].to_str()?;
+        assert!(allowed_headers.contains("authorization"));
+        assert!(allowed_headers.contains("content-type"));
+        let allowed_methods = headers[header::ACCESS_CONTROL_ALLOW_METHODS].to_str()?;
+        assert!(allowed_methods.contains("POST"));
+
+        // the settings page changes the username and password with PATCH /v0/user
+        let response = preflight(&test, "https://nrpm.io", "PATCH", "authorization").await?;
+        assert!(response.status().is_success());
+        let allowed_methods = response.headers()[header::ACCESS_CONTROL_ALLOW_METHODS].to_str()?;
+        assert!(allowed_methods.contains("PATCH"));
+
+        // watching packages and the notification digest use PUT
+        let response = preflight(&test, "https://nrpm.io", "PUT", "authorization").await?;
+        assert!(response.status().is_success());
+        let allowed_methods = response.headers()[header::ACCESS_CONTROL_ALLOW_METHODS].to_str()?;
+        assert!(allowed_methods.contains("PUT"));
+
+        // simple requests carry the same headers
+        let response = reqwest::Client::new()
+            .get(format!("{}/v0/packages", test.url))
+            .header(header::ORIGIN, "https://nrpm.io")
+            .send()
+            .await?;
+        assert_eq!(
+            response.headers()[header::ACCESS_CONTROL_ALLOW_ORIGIN],
+            "https://nrpm.io"
+        );
+        let exposed = response.headers()[header::ACCESS_CONTROL_EXPOSE_HEADERS].to_str()?;
+        assert!(exposed.contains(API_VERSION_HEADER));
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn should_reject_unknown_origins() -> Result<()> {
+        let test = OnyxTest::new().await?;
+        let response = preflight(&test, "https://evil.example", "POST", "authorization").await?;
+        assert!(
+            !response
+                .headers()
+                .contains_key(header::ACCESS_CONTROL_ALLOW_ORIGIN)
+        );
+
+        let test = OnyxTest::with_config(|state| {
+            Arc::make_mut(&mut state.config).cors_origins =
+                vec!["https://registry.example/".to_string()]
+        })
+        .await?;
+        let response = preflight(&test, "https://nrpm.io", "GET", "authorization").await?;
+        assert!(
+            !response
+                .headers()
+                .contains_key(header::ACCESS_CONTROL_ALLOW_ORIGIN)
+        );
+        let response =
+            preflight(&test, "https://registry.example", "DELETE", "authorization").await?;
+        assert_eq!(
+            response.headers()[header::ACCESS_CONTROL_ALLOW_ORIGIN],
+            "https://registry.example"
+        );
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn should_allow_any_origin_without_credentials() -> Result<()> {
+        let test = OnyxTest::with_config(|state| {
+            Arc::make_mut(&mut state.config).cors_origins = vec![ANY_ORIGIN.to_string()]
+        })
+        .await?;
+        let response = preflight(&test, "https://evil.example", "GET", "if-none-match").await?;
+        let headers = response.headers();
+        assert_eq!(headers[header::ACCESS_CONTROL_ALLOW_ORIGIN], "*");
+        assert!(!headers.contains_key(header::ACCESS_CONTROL_ALLOW_CREDENTIALS));
+        Ok(())
+    }
+}
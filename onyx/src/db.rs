@@ -0,0 +1,375 @@
+use std::collections::BTreeMap;
+use std::collections::BTreeSet;
+use std::sync::Arc;
+use std::sync::Mutex;
+use std::time::Duration;
+use std::time::Instant;
+
+use axum::extract::MatchedPath;
+use axum::extract::Request;
+use axum::extract::State;
+use axum::middleware::Next;
+use axum::response::Json as ResponseJson;
+use axum::response::Response;
+use redb::CommitError;
+use redb::Database;
+use redb::Key;
+use redb::MultimapTable;
+use redb::MultimapTableDefinition;
+use redb::MultimapTableHandle;
+use redb::ReadOnlyMultimapTable;
+use redb::ReadOnlyTable;
+use redb::ReadTransaction;
+use redb::Table;
+use redb::TableDefinition;
+use redb::TableError;
+use redb::TableHandle;
+use redb::TransactionError;
+use redb::Value;
+use redb::WriteTransaction;
+
+use onyx_api::prelude::*;
+
+use super::Config;
+use super::OnyxError;
+use super::OnyxState;
+use super::git::GitRef;
+use super::read_cache::ReadCache;
+use super::session::AdminSession;
+
+tokio::task_local! {
+    /// What is using the database, see `scope_route` and `with_route`.
+    static ROUTE: String;
+}
+
+/// The route of the request being handled, or `other` outside of a request or job.
+fn current_route() -> String {
+    ROUTE
+        .try_with(String::clone)
+        .unwrap_or_else(|_| "other".to_string())
+}
+
+/// Attribute the transactions of a request to its route, e.g. `GET /v0/packages`.
+pub async fn scope_route(path: MatchedPath, request: Request, next: Next) -> Response {
+    let route = format!("{} {}", request.method(), path.as_str());
+    ROUTE.scope(route, next.run(request)).await
+}
+
+/// Attribute the transactions of `f` to `route`, for work outside of a request like jobs.
+pub fn with_route<T>(route: String, f: impl FnOnce() -> T) -> T {
+    ROUTE.sync_scope(route, f)
+}
+
+/// Transactions of one kind made by one route.
+#[derive(Default)]
+struct Aggregate {
+    count: u64,
+    slow: u64,
+    total: Duration,
+    max: Duration,
+    tables: BTreeSet<String>,
+}
+
+struct Metrics {
+    /// Transactions that take longer are logged.
+    slow_threshold: Duration,
+    aggregates: Mutex<BTreeMap<(String, TransactionKind), Aggregate>>,
+}
+
+/// The registry database. Transactions are timed from when they begin until they're committed
+/// or dropped, logged if they take longer than `slow_transaction_ms`, and counted per route
+/// for `GET /v0/admin/db/metrics`. The hottest reads are cached, see `ReadCache`.
+pub struct Db {
+    database: Database,
+    metrics: Arc<Metrics>,
+    cache: Arc<ReadCache>,
+}
+
+impl Db {
+    pub fn new(database: Database, slow_threshold: Duration, cache_entries: usize) -> Self {
+        Self {
+            database,
+            metrics: Arc::new(Metrics {
+                slow_threshold,
+                aggregates: Mutex::new(BTreeMap::new()),
+            }),
+            cache: Arc::new(ReadCache::new(cache_entries)),
+        }
+    }
+
+    /// Open or create the database of `config`.
+    pub fn open(config: &Config) -> anyhow::Result<Self> {
+        Ok(Self::new(
+            Database::create(config.data_path(&config.db_path))?,
+            Duration::from_millis(config.slow_transaction_ms),
+            config.read_cache_entries,
+        ))
+    }
+
+    /// `PackageModel::package_by_name`, cached.
+    pub fn package_by_name(&self, name: &str) -> anyhow::Result<Option<PackageModel>> {
+        self.cache.get_or_load(&self.cache.packages, name, || {
+            PackageModel::package_by_name(&self.begin_read()?, name)
+        })
+    }
+
+    /// `PackageModel::latest_version`, cached.
+    pub fn latest_version(
+        &self,
+        name: &str,
+    ) -> anyhow::Result<Option<(PackageModel, PackageVersionModel)>> {
+        self.cache
+            .get_or_load(&self.cache.latest_versions, name, || {
+                PackageModel::latest_version(&self.begin_read()?, name)
+            })
+    }
+
+    /// The refs of the package with `package_id`, read with `load` if they aren't cached.
+    pub fn package_refs<E>(
+        &self,
+        package_id: &str,
+        load: impl FnOnce() -> Result<Vec<GitRef>, E>,
+    ) -> Result<Vec<GitRef>, E> {
+        self.cache.get_or_load(&self.cache.refs, package_id, load)
+    }
+
+    /// The database itself, for maintenance that needs the only handle to it.
+    pub fn get_mut(&mut self) -> &mut Database {
+        &mut self.database
+    }
+
+    #[allow(clippy::result_large_err)]
+    pub fn begin_read(&self) -> Result<ReadTxn, TransactionError> {
+        Ok(ReadTxn {
+            inner: self.database.begin_read()?,
+            trace: Trace::new(&self.metrics, TransactionKind::Read),
+        })
+    }
+
+    #[allow(clippy::result_large_err)]
+    pub fn begin_write(&self) -> Result<WriteTxn, TransactionError> {
+        Ok(WriteTxn {
+            inner: self.database.begin_write()?,
+            trace: Trace::new(&self.metrics, TransactionKind::Write),
+            cache: self.cache.clone(),
+        })
+    }
+
+    /// The transactions of each route so far, those that took longest in total first.
+    pub fn metrics(&self) -> DbMetricsResponse {
+        let aggregates = self.metrics.aggregates.lock().unwrap();
+        let mut metrics = aggregates
+            .iter()
+            .map(|((route, kind), aggregate)| TransactionMetrics {
+                route: route.clone(),
+                kind: *kind,
+                count: aggregate.count,
+                slow: aggregate.slow,
+                total_micros: aggregate.total.as_micros() as u64,
+                max_micros: aggregate.max.as_micros() as u64,
+                tables: aggregate.tables.iter().cloned().collect(),
+            })
+            .collect::<Vec<_>>();
+        metrics.sort_by_key(|m| std::cmp::Reverse(m.total_micros));
+        DbMetricsResponse {
+            slow_transaction_ms: self.metrics.slow_threshold.as_millis() as u64,
+            transactions: metrics,
+            caches: self.cache.metrics(),
+        }
+    }
+}
+
+/// The route, tables and duration of a transaction, recorded when it's dropped.
+struct Trace {
+    metrics: Arc<Metrics>,
+    kind: TransactionKind,
+    route: String,
+    tables: Mutex<BTreeSet<String>>,
+    started: Instant,
+}
+
+impl Trace {
+    fn new(metrics: &Arc<Metrics>, kind: TransactionKind) -> Self {
+        Self {
+            metrics: metrics.clone(),
+            kind,
+            route: current_route(),
+            tables: Mutex::new(BTreeSet::new()),
+            started: Instant::now(),
+        }
+    }
+
+    fn open(&self, table: &str) {
+        self.tables.lock().unwrap().insert(table.to_string());
+    }
+}
+
+impl Drop for Trace {
+    fn drop(&mut self) {
+        let elapsed = self.started.elapsed();
+        let tables = std::mem::take(self.tables.get_mut().unwrap());
+        let slow = elapsed > self.metrics.slow_threshold;
+        if slow {
+            log::warn!(
+                "Slow {:?} transaction in {}: {}ms using {}",
+                self.kind,
+                self.route,
+                elapsed.as_millis(),
+                tables.iter().cloned().collect::<Vec<_>>().join(", ")
+            );
+        }
+        let mut aggregates = self.metrics.aggregates.lock().unwrap();
+        let aggregate = aggregates
+            .entry((std::mem::take(&mut self.route), self.kind))
+            .or_default();
+        aggregate.count += 1;
+        aggregate.slow += u64::from(slow);
+        aggregate.total += elapsed;
+        aggregate.max = aggregate.max.max(elapsed);
+        aggregate.tables.extend(tables);
+    }
+}
+
+/// A read transaction that records the tables it opens, see `ReadTables`.
+pub struct ReadTxn {
+    inner: ReadTransaction,
+    trace: Trace,
+}
+
+impl ReadTxn {
+    pub fn open_table<K: Key + 'static, V: Value + 'static>(
+        &self,
+        definition: TableDefinition<K, V>,
+    ) -> Result<ReadOnlyTable<K, V>, TableError> {
+        self.trace.open(definition.name());
+        self.inner.open_table(definition)
+    }
+
+    pub fn open_multimap_table<K: Key + 'static, V: Key + 'static>(
+        &self,
+        definition: MultimapTableDefinition<K, V>,
+    ) -> Result<ReadOnlyMultimapTable<K, V>, TableError> {
+        self.trace.open(definition.name());
+        self.inner.open_multimap_table(definition)
+    }
+}
+
+impl ReadTables for ReadTxn {
+    fn open_table<K: Key + 'static, V: Value + 'static>(
+        &self,
+        definition: TableDefinition<K, V>,
+    ) -> Result<ReadOnlyTable<K, V>, TableError> {
+        ReadTxn::open_table(self, definition)
+    }
+
+    fn open_multimap_table<K: Key + 'static, V: Key + 'static>(
+        &self,
+        definition: MultimapTableDefinition<K, V>,
+    ) -> Result<ReadOnlyMultimapTable<K, V>, TableError> {
+        ReadTxn::open_multimap_table(self, definition)
+    }
+}
+
+/// A write transaction that records the tables it opens. Dropping it without committing
+/// aborts it.
+pub struct WriteTxn {
+    inner: WriteTransaction,
+    trace: Trace,
+    cache: Arc<ReadCache>,
+}
+
+impl WriteTxn {
+    pub fn open_table<K: Key + 'static, V: Value + 'static>(
+        &self,
+        definition: TableDefinition<K, V>,
+    ) -> Result<Table<'_, K, V>, TableError> {
+        self.trace.open(definition.name());
+        self.inner.open_table(definition)
+    }
+
+    pub fn open_multimap_table<K: Key + 'static, V: Key + 'static>(
+        &self,
+        definition: MultimapTableDefinition<K, V>,
+    ) -> Result<MultimapTable<'_, K, V>, TableError> {
+        self.trace.open(definition.name());
+        self.inner.open_multimap_table(definition)
+    }
+
+    /// Commit the transaction, which is timed until the commit finishes. Cached reads of the
+    /// tables it opened are dropped.
+    pub fn commit(self) -> Result<(), CommitError> {
+        let Self {
+            inner,
+            trace,
+            cache,
+        } = self;
+        let result = inner.commit();
+        if result.is_ok() {
+            cache.invalidate(trace.tables.lock().unwrap().iter());
+        }
+        drop(trace);
+        result
+    }
+}
+
+/// How many transactions each route made and how long they took since the registry started,
+/// to find routes that scan tables or hold the write lock for long.
+pub async fn db_metrics(
+    State(state): State<OnyxState>,
+    _admin: AdminSession,
+) -> Result<ResponseJson<DbMetricsResponse>, OnyxError> {
+    Ok(ResponseJson(state.db.metrics()))
+}
+
+#[cfg(test)]
+mod tests {
+    use anyhow::Result;
+    use nanoid::nanoid;
+    use onyx_api::prelude::*;
+
+    use crate::testing::OnyxTest;
+
+    #[tokio::test]
+    async fn should_record_transactions_by_route() -> Result<()> {
+        let admin_username = nanoid!();
+        let test = OnyxTest::with_admins(&[&admin_username]).await?;
+        let (admin, _password) = test
+            .signup(Some(LoginRequest {
+                username: admin_username,
+                password: nanoid!(),
+                ..Default::default()
+            }))
+            .await?;
+        let (user, _password) = test.signup(None).await?;
+        let tarball = OnyxTest::create_test_tarball_named(None, Some("metered"), None)?;
+        test.publish(
+            Some(PublishData::new(tarball.1.to_string(), user.token.clone())),
+            tarball,
+        )
+        .await?;
+        test.api.load_package_versions("metered").await?;
+        test.api.load_package_versions("metered").await?;
+
+        let metrics = test.api.admin_db_metrics(&admin.token).await?;
+        let versions = metrics
+            .transactions
+            .iter()
+            .find(|t| t.route == "GET /v0/packages/{package_name}/versions")
+            .unwrap();
+        assert_eq!(versions.kind, TransactionKind::Read);
+        assert_eq!(versions.count, 2);
+        assert_eq!(
+            versions.tables,
+            vec!["package_names", "package_versions", "packages", "versions"]
+        );
+        assert!(versions.max_micros <= versions.total_micros);
+        assert!(
+            metrics
+                .transactions
+                .iter()
+                .any(|t| t.route == "POST /v0/publish" && t.kind == TransactionKind::Write)
+        );
+        assert!(test.api.admin_db_metrics(&user.token).await.is_err());
+        Ok(())
+    }
+}
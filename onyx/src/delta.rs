@@ -0,0 +1,147 @@
+use std::collections::HashMap;
+use std::path::PathBuf;
+use std::str::FromStr;
+
+use anyhow::Result;
+use axum::extract::Path;
+use axum::extract::Query;
+use axum::extract::State;
+use axum::http::HeaderMap;
+use axum::http::header;
+use axum::response::IntoResponse;
+use axum::response::Response;
+use redb::ReadableTable;
+use serde::Deserialize;
+
+use onyx_api::prelude::*;
+
+use super::OnyxError;
+use super::OnyxState;
+use super::db::WriteTxn;
+
+/// Store the delta from the version `from_id` to the version `to_id` made of `files`. Skipped
+/// if `from_id` has no stored manifest, or if the delta isn't smaller than the `tarball_len`
+/// bytes of the whole version.
+pub fn store(
+    write: &WriteTxn,
+    from_id: &HashId,
+    to_id: &HashId,
+    files: &HashMap<PathBuf, Vec<u8>>,
+    tarball_len: u64,
+) -> Result<(), OnyxError> {
+    let from_manifest = {
+        let version_manifest_table = write.open_table(VERSION_MANIFEST_TABLE)?;
+        let Some(json) = version_manifest_table.get(from_id)? else {
+            return Ok(());
+        };
+        serde_json::from_str::<VersionManifest>(json.value()).map_err(anyhow::Error::from)?
+    };
+    let delta = nrpm_tarball::delta::create(&from_manifest.file_hashes()?, files)?;
+    if delta.len() as u64 >= tarball_len {
+        return Ok(());
+    }
+    let mut version_delta_table = write.open_table(VERSION_DELTA_TABLE)?;
+    version_delta_table.insert((from_id.clone(), to_id.clone()), delta.as_slice())?;
+    Ok(())
+}
+
+#[derive(Deserialize)]
+pub struct DeltaQuery {
+    from: String,
+}
+
+pub async fn version_delta(
+    State(state): State<OnyxState>,
+    Path(id): Path<String>,
+    Query(query): Query<DeltaQuery>,
+) -> Result<Response, OnyxError> {
+    let to_id = HashId::from_str(&id)?;
+    let from_id = HashId::from_str(&query.from)?;
+    let read = state.db.begin_read()?;
+    let version_delta_table = read.open_table(VERSION_DELTA_TABLE)?;
+    let delta = version_delta_table
+        .get((from_id, to_id))?
+        .ok_or(OnyxError::not_found("No delta between these versions"))?
+        .value()
+        .to_vec();
+    let mut headers = HeaderMap::new();
+    headers.insert(
+        header::CONTENT_TYPE,
+        "application/octet-stream"
+            .parse()
+            .map_err(|_| OnyxError::default())?,
+    );
+    Ok((headers, delta).into_response())
+}
+
+#[cfg(test)]
+mod tests {
+    use anyhow::Result;
+    use nanoid::nanoid;
+    use onyx_api::prelude::*;
+
+    use crate::testing::OnyxTest;
+
+    #[tokio::test]
+    async fn should_serve_delta_between_versions() -> Result<()> {
+        let test = OnyxTest::new().await?;
+        let (login, _password) = test.signup(None).await?;
+        let name = nanoid!();
+        let params = "0".repeat(64 * 1024);
+        let mut version_ids = vec![];
+        for (version, lib) in [("0.1.0", "1"), ("0.2.0", "2")] {
+            let nargo_toml = format!("[package]\nname = \"{name}\"\nversion = \"{version}\"\n");
+            let lib_nr = format!("pub fn value() -> Field {{\n    {lib}\n}}\n");
+            let tarball = OnyxTest::create_tarball_from_files(&[
+                ("Nargo.toml", &nargo_toml),
+                ("src/lib.nr", &lib_nr),
+                ("params/big.bin", &params),
+            ])?;
+            version_ids.push(HashId::from(tarball.1));
+            test.publish(
+                Some(PublishData::new(tarball.1.to_string(), login.token.clone())),
+                tarball,
+            )
+            .await?;
+        }
+
+        let delta = test
+            .api
+            .version_delta(&version_ids[1], &version_ids[0])
+            .await?
+            .expect("delta between consecutive versions");
+        assert!(delta.len() < params.len());
+
+        let from = tempfile::tempdir()?;
+        std::fs::create_dir_all(from.path().join("src"))?;
+        std::fs::create_dir_all(from.path().join("params"))?;
+        std::fs::write(
+            from.path().join("Nargo.toml"),
+            format!("[package]\nname = \"{name}\"\nversion = \"0.1.0\"\n"),
+        )?;
+        std::fs::write(
+            from.path().join("src/lib.nr"),
+            "pub fn value() -> Field {\n    1\n}\n",
+        )?;
+        std::fs::write(from.path().join("params/big.bin"), &params)?;
+        assert_eq!(
+            HashId::from(nrpm_tarball::hash_dir(from.path())?),
+            version_ids[0]
+        );
+        let to = tempfile::tempdir()?;
+        nrpm_tarball::delta::apply(from.path(), delta.as_slice(), to.path())?;
+        assert_eq!(
+            HashId::from(nrpm_tarball::hash_dir(to.path())?),
+            version_ids[1]
+        );
+
+        // only deltas between consecutive versions are stored
+        assert!(
+            test.api
+                .version_delta(&version_ids[0], &version_ids[1])
+                .await?
+                .is_none()
+        );
+        Ok(())
+    }
+}
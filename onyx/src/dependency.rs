@@ -0,0 +1,471 @@
+use std::collections::HashMap;
+use std::collections::VecDeque;
+
+use anyhow::Result;
+use axum::extract::Path;
+use axum::extract::Query;
+use axum::extract::State;
+use axum::response::Json as ResponseJson;
+use nargo_parse::NargoConfig;
+use redb::ReadableTable;
+use redb::Table;
+use serde::Deserialize;
+use tokio::io::AsyncReadExt;
+
+use onyx_api::prelude::*;
+
+use super::OnyxError;
+use super::OnyxState;
+use super::db::ReadTxn;
+use super::db::WriteTxn;
+
+const MAX_LISTED_DEPENDENTS: usize = 100;
+/// Most nodes in a dependency tree, the rest are left out.
+const MAX_TREE_NODES: usize = 500;
+
+/// Dependencies on packages in this registry. These are git dependencies pointing at the
+/// registry's git endpoint for a package, a url with a single path segment naming a package
+/// that exists here.
+fn registry_dependencies(
+    package_name_table: &Table<&str, &str>,
+    package: &PackageModel,
+    config: &NargoConfig,
+) -> Result<Vec<PackageDependency>, OnyxError> {
+    let dependencies = match config.dependencies() {
+        Ok(dependencies) => dependencies,
+        Err(e) => {
+            log::warn!("failed to parse dependencies of {}: {e:?}", package.name);
+            return Ok(vec![]);
+        }
+    };
+    let mut out = vec![];
+    for dependency in dependencies.values() {
+        let (Some(git), Some(tag)) = (&dependency.git, &dependency.tag) else {
+            continue;
+        };
+        let Ok(url) = reqwest::Url::parse(git) else {
+            continue;
+        };
+        let segments = url
+            .path_segments()
+            .map(|s| s.filter(|s| !s.is_empty()).collect::<Vec<_>>())
+            .unwrap_or_default();
+        let [package_name] = segments.as_slice() else {
+            continue;
+        };
+        if *package_name == package.name || package_name_table.get(*package_name)?.is_none() {
+            continue;
+        }
+        out.push(PackageDependency {
+            package_name: package_name.to_string(),
+            version_name: tag.clone(),
+        });
+    }
+    out.sort_by(|a, b| a.package_name.cmp(&b.package_name));
+    out.dedup_by(|a, b| a.package_name == b.package_name);
+    Ok(out)
+}
+
+/// Store the dependencies of a newly published version and move the package's edges in the
+/// reverse index from the previous latest version to this one.
+pub fn record(
+    write: &WriteTxn,
+    package: &PackageModel,
+    version_id: &HashId,
+    previous_version_id: Option<&HashId>,
+    config: &NargoConfig,
+) -> Result<(), OnyxError> {
+    let package_name_table = write.open_table(PACKAGE_NAME_TABLE)?;
+    let mut version_dependency_table = write.open_table(VERSION_DEPENDENCY_TABLE)?;
+    let mut package_dependent_table = write.open_multimap_table(PACKAGE_DEPENDENT_TABLE)?;
+
+    let dependencies = registry_dependencies(&package_name_table, package, config)?;
+    if let Some(previous_version_id) = previous_version_id
+        && let Some(previous) = version_dependency_table.get(previous_version_id)?
+    {
+        for dependency in previous.value().dependencies {
+            if let Some(dependency_id) = package_name_table.get(dependency.package_name.as_str())? {
+                package_dependent_table.remove(dependency_id.value(), package.id.as_str())?;
+            }
+        }
+    }
+    for dependency in &dependencies {
+        if let Some(dependency_id) = package_name_table.get(dependency.package_name.as_str())? {
+            package_dependent_table.insert(dependency_id.value(), package.id.as_str())?;
+        }
+    }
+    version_dependency_table.insert(version_id, VersionDependencies { dependencies })?;
+    Ok(())
+}
+
+/// Record the dependencies of latest versions published before the dependency graph
+/// existed. Returns the number of packages added.
+pub async fn backfill(state: &OnyxState) -> Result<usize> {
+    let missing = {
+        let read = state.db.begin_read()?;
+        let package_table = read.open_table(PACKAGE_TABLE)?;
+        let version_dependency_table = read.open_table(VERSION_DEPENDENCY_TABLE)?;
+        let mut missing = vec![];
+        for entry in package_table.iter()? {
+            let package = entry?.1.value();
+            if version_dependency_table
+                .get(&package.latest_version_id)?
+                .is_none()
+            {
+                missing.push(package);
+            }
+        }
+        missing
+    };
+    let mut added = 0;
+    for package in missing {
+        let version_id = package.latest_version_id.to_string();
+        let mut bytes = vec![];
+        let read = async {
+            state
+                .storage
+                .reader_async(&version_id)
+                .await?
+                .read_to_end(&mut bytes)
+                .await?;
+            nrpm_tarball::extract_metadata(std::mem::take(&mut bytes))
+        };
+        let (config, _files) = match read.await {
+            Ok(metadata) => metadata,
+            Err(e) => {
+                log::warn!(
+                    "failed to read metadata for {} {version_id}: {e:?}",
+                    package.name
+                );
+                continue;
+            }
+        };
+        let write = state.db.begin_write()?;
+        record(&write, &package, &package.latest_version_id, None, &config)
+            .map_err(|e| anyhow::anyhow!("failed to record dependencies: {e:?}"))?;
+        write.commit()?;
+        added += 1;
+    }
+    Ok(added)
+}
+
+pub async fn package_graph(
+    State(state): State<OnyxState>,
+    Path(package_name): Path<String>,
+) -> Result<ResponseJson<PackageGraphResponse>, OnyxError> {
+    let read = state.db.begin_read()?;
+    let package_name_table = read.open_table(PACKAGE_NAME_TABLE)?;
+    let package_table = read.open_table(PACKAGE_TABLE)?;
+    let version_dependency_table = read.open_table(VERSION_DEPENDENCY_TABLE)?;
+    let package_dependent_table = read.open_multimap_table(PACKAGE_DEPENDENT_TABLE)?;
+
+    let package_id = package_name_table
+        .get(package_name.as_str())?
+        .ok_or(OnyxError::not_found(&format!(
+            "Unable to find package \"{package_name}\""
+        )))?
+        .value()
+        .to_string();
+    let package = package_table
+        .get(package_id.as_str())?
+        .ok_or(OnyxError::not_found("Unable to find package"))?
+        .value();
+    let dependencies = version_dependency_table
+        .get(&package.latest_version_id)?
+        .map(|v| v.value().dependencies)
+        .unwrap_or_default();
+
+    let dependent_ids = package_dependent_table.get(package_id.as_str())?;
+    let dependent_count = dependent_ids.len() as usize;
+    let mut dependents = vec![];
+    for dependent_id in dependent_ids.take(MAX_LISTED_DEPENDENTS) {
+        if let Some(dependent) = package_table.get(dependent_id?.value())? {
+            dependents.push(dependent.value().name);
+        }
+    }
+    dependents.sort();
+    Ok(ResponseJson(PackageGraphResponse {
+        dependencies,
+        dependents,
+        dependent_count,
+    }))
+}
+
+#[derive(Deserialize)]
+pub struct DependencyTreeQuery {
+    version: Option<String>,
+}
+
+/// A node for `version_name` of `package_name`, and the id of the version if it's published
+/// here. A dependency tagged `v1.2.3` is found, and named, as version `1.2.3`.
+fn tree_node(
+    read: &ReadTxn,
+    package_name: &str,
+    version_name: &str,
+    depth: usize,
+) -> Result<(DependencyTreeNode, Option<HashId>), OnyxError> {
+    let yanked_version_table = read.open_table(YANKED_VERSION_TABLE)?;
+    let version = match PackageModel::version(read, package_name, version_name)? {
+        Some(version) => Some(version),
+        None => match version_name.strip_prefix('v') {
+            Some(stripped) => PackageModel::version(read, package_name, stripped)?,
+            None => None,
+        },
+    };
+    let yanked = match &version {
+        Some(version) => yanked_version_table.get(&version.id)?.is_some(),
+        None => false,
+    };
+    let deprecated = PackageModel::package_by_name(read, package_name)?
+        .and_then(|package| package.metadata.deprecated);
+    let node = DependencyTreeNode {
+        package_name: package_name.to_string(),
+        version_name: version
+            .as_ref()
+            .map_or(version_name, |version| &version.name)
+            .to_string(),
+        depth,
+        yanked,
+        deprecated,
+        dependencies: vec![],
+    };
+    Ok((node, version.map(|version| version.id)))
+}
+
+/// The packages a version depends on through the registry, breadth first from the root.
+pub async fn dependency_tree(
+    State(state): State<OnyxState>,
+    Path(package_name): Path<String>,
+    Query(query): Query<DependencyTreeQuery>,
+) -> Result<ResponseJson<DependencyTreeResponse>, OnyxError> {
+    let read = state.db.begin_read()?;
+    let root = match &query.version {
+        Some(version_name) => PackageModel::version(&read, &package_name, version_name)?,
+        None => PackageModel::latest_version(&read, &package_name)?.map(|(_, version)| version),
+    }
+    .ok_or(OnyxError::not_found(&format!(
+        "Unable to find package \"{package_name}\""
+    )))?;
+    let version_dependency_table = read.open_table(VERSION_DEPENDENCY_TABLE)?;
+
+    let mut tree = DependencyTreeResponse::default();
+    let (root_node, root_id) = tree_node(&read, &package_name, &root.name, 0)?;
+    tree.nodes.push(root_node);
+    let mut indices = HashMap::from([((package_name, root.name), 0)]);
+    let mut queue = VecDeque::from([(0, root_id)]);
+    while let Some((index, version_id)) = queue.pop_front() {
+        let Some(version_id) = version_id else {
+            continue;
+        };
+        let dependencies = version_dependency_table
+            .get(&version_id)?
+            .map(|v| v.value().dependencies)
+            .unwrap_or_default();
+        let depth = tree.nodes[index].depth + 1;
+        for dependency in dependencies {
+            let key = (dependency.package_name, dependency.version_name);
+            let dependency_index = match indices.get(&key) {
+                Some(dependency_index) => *dependency_index,
+                None => {
+                    let (node, id) = tree_node(&read, &key.0, &key.1, depth)?;
+                    // tags with and without a v prefix are the same node
+                    let resolved = (node.package_name.clone(), node.version_name.clone());
+                    let dependency_index = match indices.get(&resolved) {
+                        Some(dependency_index) => *dependency_index,
+                        None if tree.nodes.len() >= MAX_TREE_NODES => {
+                            tree.truncated = true;
+                            continue;
+                        }
+                        None => {
+                            tree.nodes.push(node);
+                            queue.push_back((tree.nodes.len() - 1, id));
+                            tree.nodes.len() - 1
+                        }
+                    };
+                    indices.insert(key, dependency_index);
+                    indices.insert(resolved, dependency_index);
+                    dependency_index
+                }
+            };
+            tree.nodes[index].dependencies.push(dependency_index);
+        }
+        tree.nodes[index].dependencies.sort();
+    }
+    Ok(ResponseJson(tree))
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::testing::OnyxTest;
+    use anyhow::Result;
+    use nanoid::nanoid;
+    use onyx_api::prelude::*;
+
+    #[tokio::test]
+    async fn should_track_dependents() -> Result<()> {
+        let test = OnyxTest::new().await?;
+        let (login, _password) = test.signup(None).await?;
+        let publish = async |files: &[(&str, &str)]| -> Result<()> {
+            let tarball = OnyxTest::create_tarball_from_files(files)?;
+            test.publish(
+                Some(PublishData::new(tarball.1.to_string(), login.token.clone())),
+                tarball,
+            )
+            .await?;
+            Ok(())
+        };
+        let dependency = nanoid!();
+        let dependent = nanoid!();
+        let dev_dependency = nanoid!();
+        for name in [&dependency, &dev_dependency] {
+            publish(&[(
+                "Nargo.toml",
+                &format!("[package]\nname = \"{name}\"\nversion = \"0.1.0\"\n"),
+            )])
+            .await?;
+        }
+        publish(&[(
+            "Nargo.toml",
+            &format!(
+                "[package]\nname = \"{dependent}\"\nversion = \"0.1.0\"\n\n[dependencies]\n\
+                 dep = {{ git = \"{}/{dependency}\", tag = \"0.1.0\" }}\n\
+                 other = {{ git = \"https://github.com/noir-lang/noir\", tag = \"v1\" }}\n\n\
+                 [dev-dependencies]\n\
+                 dev = {{ git = \"{}/{dev_dependency}\", tag = \"0.1.0\" }}\n",
+                test.url, test.url
+            ),
+        )])
+        .await?;
+
+        let graph = test.api.package_graph(&dependent).await?;
+        assert_eq!(
+            graph.dependencies,
+            vec![PackageDependency {
+                package_name: dependency.clone(),
+                version_name: "0.1.0".to_string(),
+            }]
+        );
+        let graph = test.api.package_graph(&dependency).await?;
+        assert_eq!(graph.dependent_count, 1);
+        assert_eq!(graph.dependents, vec![dependent.clone()]);
+        // dev-dependencies aren't needed to use a package
+        let graph = test.api.package_graph(&dev_dependency).await?;
+        assert_eq!(graph.dependent_count, 0);
+
+        // the latest version dropped the dependency
+        publish(&[(
+            "Nargo.toml",
+            &format!("[package]\nname = \"{dependent}\"\nversion = \"0.2.0\"\n"),
+        )])
+        .await?;
+        let graph = test.api.package_graph(&dependency).await?;
+        assert_eq!(graph.dependent_count, 0);
+        assert!(
+            test.api
+                .package_graph(&dependent)
+                .await?
+                .dependencies
+                .is_empty()
+        );
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn should_build_dependency_tree() -> Result<()> {
+        let test = OnyxTest::new().await?;
+        let (login, _password) = test.signup(None).await?;
+        let publish = async |name: &str, version: &str, dependencies: &[(&str, &str)]| {
+            let mut toml = format!(
+                "[package]\nname = \"{name}\"\nversion = \"{version}\"\n\n[dependencies]\n"
+            );
+            for (dependency, tag) in dependencies {
+                toml.push_str(&format!(
+                    "{dependency} = {{ git = \"{}/{dependency}\", tag = \"{tag}\" }}\n",
+                    test.url
+                ));
+            }
+            let tarball = OnyxTest::create_tarball_from_files(&[("Nargo.toml", &toml)])?;
+            test.publish(
+                Some(PublishData::new(tarball.1.to_string(), login.token.clone())),
+                tarball,
+            )
+            .await
+        };
+        // named so dependencies sort a, b, c
+        let (a, b, c) = (
+            format!("a{}", nanoid!(8)),
+            format!("b{}", nanoid!(8)),
+            format!("c{}", nanoid!(8)),
+        );
+        publish(&a, "0.1.0", &[]).await?;
+        publish(&b, "0.1.0", &[(&a, "v0.1.0")]).await?;
+        publish(&c, "0.1.0", &[(&a, "0.1.0"), (&b, "0.1.0")]).await?;
+        publish(&c, "0.2.0", &[]).await?;
+
+        let (_package, version) = test.api.load_package_latest_version(&b).await?;
+        {
+            let write = test.db().begin_write()?;
+            write.open_table(YANKED_VERSION_TABLE)?.insert(
+                &version.id,
+                ModerationRecord {
+                    admin_username: "admin".to_string(),
+                    report_id: nanoid!(),
+                    note: "malware".to_string(),
+                    created_at: timestamp(),
+                },
+            )?;
+            write.commit()?;
+        }
+        test.api
+            .update_package(
+                &login.token,
+                &a,
+                &UpdatePackageRequest {
+                    deprecated: Some("use something else".to_string()),
+                    ..Default::default()
+                },
+            )
+            .await?;
+
+        // the latest version has no dependencies
+        let tree = test.api.dependency_tree(&c, None).await?;
+        assert_eq!(tree.nodes.len(), 1);
+        assert_eq!(tree.nodes[0].version_name, "0.2.0");
+
+        let tree = test.api.dependency_tree(&c, Some("0.1.0")).await?;
+        assert!(!tree.truncated);
+        let nodes = tree
+            .nodes
+            .iter()
+            .map(|n| (n.package_name.as_str(), n.version_name.as_str(), n.depth))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            nodes,
+            vec![
+                (c.as_str(), "0.1.0", 0),
+                (a.as_str(), "0.1.0", 1),
+                (b.as_str(), "0.1.0", 1),
+            ]
+        );
+        assert_eq!(tree.nodes[0].dependencies, vec![1, 2]);
+        // b depends on a through a v prefixed tag, a is still shared
+        assert_eq!(tree.nodes[2].dependencies, vec![1]);
+        assert!(tree.nodes[2].yanked);
+        assert!(!tree.nodes[1].yanked);
+        assert_eq!(
+            tree.nodes[1].deprecated.as_deref(),
+            Some("use something else")
+        );
+
+        let e = test
+            .api
+            .dependency_tree(&c, Some("9.9.9"))
+            .await
+            .unwrap_err();
+        assert_eq!(
+            e.downcast_ref::<ApiError>().map(|e| e.code),
+            Some(OnyxErrorCode::NotFound)
+        );
+        Ok(())
+    }
+}
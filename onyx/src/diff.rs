@@ -0,0 +1,242 @@
+use std::collections::BTreeMap;
+use std::collections::HashMap;
+use std::path::PathBuf;
+
+use axum::extract::Path;
+use axum::extract::Query;
+use axum::extract::State;
+use axum::response::Json as ResponseJson;
+use serde::Deserialize;
+use tokio::io::AsyncReadExt;
+
+use onyx_api::prelude::*;
+
+use super::OnyxError;
+use super::OnyxState;
+use super::manifest;
+
+/// Files larger than this in either version aren't given a unified diff.
+const MAX_TEXT_DIFF_SIZE: u64 = 64 * 1024;
+/// Lines of context around each change in a unified diff.
+const CONTEXT_LINES: usize = 3;
+
+#[derive(Deserialize)]
+pub struct DiffQuery {
+    from: String,
+    to: String,
+}
+
+pub async fn package_diff(
+    State(state): State<OnyxState>,
+    Path(package_name): Path<String>,
+    Query(query): Query<DiffQuery>,
+) -> Result<ResponseJson<VersionDiff>, OnyxError> {
+    let mut versions = vec![];
+    for version_name in [&query.from, &query.to] {
+        let version = PackageModel::version(&state.db.begin_read()?, &package_name, version_name)?
+            .ok_or(OnyxError::not_found(&format!(
+                "Unable to find version {version_name} of package {package_name}"
+            )))?;
+        versions.push(version);
+    }
+    let (from, to) = (&versions[0], &versions[1]);
+    let mut files = changed_files(
+        &manifest::load(&state, &from.id).await?,
+        &manifest::load(&state, &to.id).await?,
+    );
+
+    if files.iter().any(is_diffable) {
+        let from_files = version_files(&state, &from.id).await?;
+        let to_files = version_files(&state, &to.id).await?;
+        for file in files.iter_mut().filter(|file| is_diffable(file)) {
+            let path = PathBuf::from(&file.path);
+            file.unified_diff = unified_diff(
+                &file.path,
+                from_files.get(&path).map(Vec::as_slice),
+                to_files.get(&path).map(Vec::as_slice),
+            );
+        }
+    }
+
+    Ok(ResponseJson(VersionDiff {
+        package_name,
+        from: from.name.clone(),
+        to: to.name.clone(),
+        files,
+    }))
+}
+
+/// The files added, removed or modified going from `from` to `to`, sorted by path.
+fn changed_files(from: &VersionManifest, to: &VersionManifest) -> Vec<FileDiff> {
+    let from_files = from
+        .files
+        .iter()
+        .map(|file| (file.path.as_str(), file))
+        .collect::<BTreeMap<_, _>>();
+    let to_files = to
+        .files
+        .iter()
+        .map(|file| (file.path.as_str(), file))
+        .collect::<BTreeMap<_, _>>();
+    let mut paths = from_files
+        .keys()
+        .chain(to_files.keys())
+        .copied()
+        .collect::<Vec<_>>();
+    paths.sort();
+    paths.dedup();
+
+    let mut files = vec![];
+    for path in paths {
+        let from_file = from_files.get(path);
+        let to_file = to_files.get(path);
+        let change = match (from_file, to_file) {
+            (None, Some(_)) => FileChange::Added,
+            (Some(_), None) => FileChange::Removed,
+            (Some(a), Some(b)) if a.blake3 != b.blake3 => FileChange::Modified,
+            _ => continue,
+        };
+        files.push(FileDiff {
+            path: path.to_string(),
+            change,
+            from_blake3: from_file.map(|file| file.blake3.clone()),
+            to_blake3: to_file.map(|file| file.blake3.clone()),
+            from_size: from_file.map(|file| file.size),
+            to_size: to_file.map(|file| file.size),
+            unified_diff: None,
+        });
+    }
+    files
+}
+
+/// Whether `file` is small enough to be given a unified diff.
+fn is_diffable(file: &FileDiff) -> bool {
+    [file.from_size, file.to_size]
+        .iter()
+        .flatten()
+        .all(|size| *size <= MAX_TEXT_DIFF_SIZE)
+}
+
+async fn version_files(
+    state: &OnyxState,
+    version_id: &HashId,
+) -> Result<HashMap<PathBuf, Vec<u8>>, OnyxError> {
+    let mut bytes = vec![];
+    PackageVersionModel::reader_by_id(state.storage.clone(), version_id.clone())
+        .await?
+        .read_to_end(&mut bytes)
+        .await?;
+    let (_config, files) = nrpm_tarball::extract_metadata(bytes)?;
+    Ok(files)
+}
+
+/// A unified diff from `from` to `to`, either missing if the file was added or removed. `None`
+/// if either isn't utf-8 text.
+fn unified_diff(path: &str, from: Option<&[u8]>, to: Option<&[u8]>) -> Option<String> {
+    let text_lines = |bytes: Option<&[u8]>| -> Option<Vec<String>> {
+        let text = std::str::from_utf8(bytes.unwrap_or_default()).ok()?;
+        if text.contains('\0') {
+            return None;
+        }
+        Some(text.lines().map(|line| format!("{line}\n")).collect())
+    };
+    let from_lines = text_lines(from)?;
+    let to_lines = text_lines(to)?;
+    let from_name = if from.is_some() {
+        format!("a/{path}")
+    } else {
+        "/dev/null".to_string()
+    };
+    let to_name = if to.is_some() {
+        format!("b/{path}")
+    } else {
+        "/dev/null".to_string()
+    };
+    let hunks = difflib::unified_diff(&from_lines, &to_lines, "", "", "", "", CONTEXT_LINES);
+    let mut diff = format!("--- {from_name}\n+++ {to_name}\n");
+    // the first two lines are the file names, with an empty date difflib can't leave out
+    for line in hunks.iter().skip(2) {
+        diff.push_str(line);
+    }
+    Some(diff)
+}
+
+#[cfg(test)]
+mod tests {
+    use anyhow::Result;
+    use nanoid::nanoid;
+    use onyx_api::prelude::*;
+
+    use crate::testing::OnyxTest;
+
+    #[tokio::test]
+    async fn should_diff_versions() -> Result<()> {
+        let test = OnyxTest::new().await?;
+        let (login, _password) = test.signup(None).await?;
+        let name = nanoid!();
+        for (version, files) in [
+            (
+                "0.1.0",
+                vec![
+                    ("src/lib.nr", "pub fn value() -> Field {\n    1\n}\n"),
+                    ("src/old.nr", "fn old() {}\n"),
+                    ("README.md", "# Readme\n"),
+                ],
+            ),
+            (
+                "0.2.0",
+                vec![
+                    ("src/lib.nr", "pub fn value() -> Field {\n    2\n}\n"),
+                    ("src/new.nr", "fn new() {}\n"),
+                    ("README.md", "# Readme\n"),
+                ],
+            ),
+        ] {
+            let nargo_toml = format!("[package]\nname = \"{name}\"\nversion = \"{version}\"\n");
+            let mut files = files;
+            files.push(("Nargo.toml", nargo_toml.as_str()));
+            let tarball = OnyxTest::create_tarball_from_files(&files)?;
+            test.publish(
+                Some(PublishData::new(tarball.1.to_string(), login.token.clone())),
+                tarball,
+            )
+            .await?;
+        }
+
+        let diff = test.api.package_diff(&name, "0.1.0", "0.2.0").await?;
+        assert_eq!((diff.from.as_str(), diff.to.as_str()), ("0.1.0", "0.2.0"));
+        let changes = diff
+            .files
+            .iter()
+            .map(|file| (file.path.as_str(), file.change))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            changes,
+            vec![
+                ("Nargo.toml", FileChange::Modified),
+                ("src/lib.nr", FileChange::Modified),
+                ("src/new.nr", FileChange::Added),
+                ("src/old.nr", FileChange::Removed),
+            ]
+        );
+        assert_eq!(
+            diff.files[1].unified_diff.as_deref(),
+            Some(
+                "--- a/src/lib.nr\n+++ b/src/lib.nr\n@@ -1,3 +1,3 @@\n pub fn value() -> Field {\n-    1\n+    2\n }\n"
+            )
+        );
+        assert_eq!(
+            diff.files[2].unified_diff.as_deref(),
+            Some("--- /dev/null\n+++ b/src/new.nr\n@@ -0,0 +1 @@\n+fn new() {}\n")
+        );
+        assert!(diff.files[3].to_blake3.is_none());
+
+        assert!(
+            test.api
+                .package_diff(&name, "0.1.0", "0.3.0")
+                .await
+                .is_err()
+        );
+        Ok(())
+    }
+}
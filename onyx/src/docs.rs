@@ -0,0 +1,105 @@
+use std::collections::HashMap;
+use std::path::PathBuf;
+use std::str::FromStr;
+
+use anyhow::Result;
+use axum::extract::Path;
+use axum::extract::State;
+use axum::response::Json as ResponseJson;
+use nargo_parse::PackageDocs;
+use tokio::io::AsyncReadExt;
+
+use onyx_api::prelude::*;
+
+use super::OnyxError;
+use super::OnyxState;
+use super::db::WriteTxn;
+
+/// Extract api docs from the `.nr` sources of a package.
+pub fn from_files(files: &HashMap<PathBuf, Vec<u8>>) -> PackageDocs {
+    nargo_parse::extract_docs(
+        files
+            .iter()
+            .filter_map(|(path, bytes)| Some((path.as_path(), std::str::from_utf8(bytes).ok()?))),
+    )
+}
+
+pub fn store(write: &WriteTxn, version_id: &HashId, docs: &PackageDocs) -> Result<(), OnyxError> {
+    let mut version_docs_table = write.open_table(VERSION_DOCS_TABLE)?;
+    let json = serde_json::to_string(docs).map_err(anyhow::Error::from)?;
+    version_docs_table.insert(version_id, json.as_str())?;
+    Ok(())
+}
+
+pub async fn version_docs(
+    State(state): State<OnyxState>,
+    Path(id): Path<String>,
+) -> Result<ResponseJson<PackageDocs>, OnyxError> {
+    let version_id = HashId::from_str(&id)?;
+    {
+        let read = state.db.begin_read()?;
+        let version_table = read.open_table(VERSION_TABLE)?;
+        if version_table.get(&version_id)?.is_none() {
+            return Err(OnyxError::not_found("Unable to find version"));
+        }
+        let version_docs_table = read.open_table(VERSION_DOCS_TABLE)?;
+        if let Some(json) = version_docs_table.get(&version_id)? {
+            let docs = serde_json::from_str(json.value()).map_err(anyhow::Error::from)?;
+            return Ok(ResponseJson(docs));
+        }
+    }
+    // versions published before docs were extracted
+    let mut bytes = vec![];
+    state
+        .storage
+        .reader_async(&id)
+        .await?
+        .read_to_end(&mut bytes)
+        .await?;
+    let (_config, files) = nrpm_tarball::extract_metadata(bytes)?;
+    Ok(ResponseJson(from_files(&files)))
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::testing::OnyxTest;
+    use anyhow::Result;
+    use nanoid::nanoid;
+    use nargo_parse::DocItemKind;
+    use onyx_api::prelude::*;
+
+    #[tokio::test]
+    async fn should_extract_docs_on_publish() -> Result<()> {
+        let test = OnyxTest::new().await?;
+        let (login, _password) = test.signup(None).await?;
+        let (bytes, hash) = OnyxTest::create_tarball_from_files(&[
+            (
+                "Nargo.toml",
+                &format!("[package]\nname = \"{}\"\nversion = \"0.1.0\"\n", nanoid!()),
+            ),
+            (
+                "src/lib.nr",
+                "/// Adds one.\npub fn add_one(x: Field) -> Field {\n    x + 1\n}\n",
+            ),
+        ])?;
+        let version_id = HashId::from(hash);
+        test.publish(
+            Some(PublishData::new(hash.to_string(), login.token.clone())),
+            (bytes, hash),
+        )
+        .await?;
+
+        let docs = test.api.version_docs(&version_id).await?;
+        let items = docs
+            .modules
+            .iter()
+            .flat_map(|m| m.items.iter())
+            .collect::<Vec<_>>();
+        assert_eq!(items.len(), 1);
+        assert_eq!(items[0].kind, DocItemKind::Function);
+        assert_eq!(items[0].name, "add_one");
+        assert_eq!(items[0].doc, "Adds one.");
+        assert_eq!(items[0].signature, "pub fn add_one(x: Field) -> Field");
+        Ok(())
+    }
+}
@@ -5,33 +5,78 @@ use axum::body::Body;
 use axum::extract::Path;
 use axum::extract::State;
 use axum::http::HeaderMap;
+use axum::http::StatusCode;
 use axum::http::header;
 use axum::response::IntoResponse;
 use axum::response::Response;
 use onyx_api::db::HashId;
+use onyx_api::db::PACKAGE_NAME_TABLE;
 use onyx_api::db::PACKAGE_TABLE;
+use onyx_api::db::PACKAGE_VERSION_NAME_TABLE;
 use onyx_api::db::VERSION_TABLE;
 use tokio_util::io::ReaderStream;
 
 use super::OnyxError;
 use super::OnyxState;
+use super::stats;
+use super::timestamp;
 
 pub async fn download_package(
     State(state): State<OnyxState>,
     Path(id): Path<String>,
 ) -> Result<Response, OnyxError> {
-    let reader = state.storage.reader_async(&id).await?;
+    serve_version(&state, HashId::from_str(&id)?).await
+}
 
-    let stream = ReaderStream::new(reader);
-    let body = Body::from_stream(stream);
+/// Download a version by package and version name instead of hash.
+pub async fn download_package_version(
+    State(state): State<OnyxState>,
+    Path((package_name, version_name)): Path<(String, String)>,
+) -> Result<Response, OnyxError> {
+    let version_id = {
+        let read = state.db.begin_read()?;
+        let package_name_table = read.open_table(PACKAGE_NAME_TABLE)?;
+        let package_version_name_table = read.open_table(PACKAGE_VERSION_NAME_TABLE)?;
+        let package_id = package_name_table
+            .get(package_name.as_str())?
+            .ok_or(OnyxError::not_found(&format!(
+                "Unable to find package \"{package_name}\""
+            )))?
+            .value()
+            .to_string();
+        package_version_name_table
+            .get((package_id.as_str(), version_name.as_str()))?
+            .ok_or(OnyxError::not_found(&format!(
+                "Unable to find version \"{version_name}\" of package \"{package_name}\""
+            )))?
+            .value()
+    };
+    serve_version(&state, version_id).await
+}
 
+/// Respond with the tarball of a version, or a redirect to it on the CDN.
+async fn serve_version(state: &OnyxState, version_id: HashId) -> Result<Response, OnyxError> {
+    let id = version_id.to_string();
     let read = state.db.begin_read()?;
     let package_tree = read.open_table(PACKAGE_TABLE)?;
     let version_tree = read.open_table(VERSION_TABLE)?;
-    if let Some(version) = version_tree.get(HashId::from_str(&id)?)? {
+    if let Some(version) = version_tree.get(version_id)? {
         let version = version.value();
         if let Some(package) = package_tree.get(version.package_id.as_str())? {
             let package = package.value();
+            stats::record_download(state, &version.id)?;
+            if let Some(cdn) = &state.cdn {
+                let mut headers = HeaderMap::new();
+                headers.insert(
+                    header::LOCATION,
+                    cdn.signed_url(&id, timestamp())
+                        .parse()
+                        .map_err(|_| OnyxError::default())?,
+                );
+                return Ok((StatusCode::FOUND, headers).into_response());
+            }
+            let reader = state.storage.reader_async(&id).await?;
+            let body = Body::from_stream(ReaderStream::new(reader));
             let mut headers = HeaderMap::new();
             headers.insert(
                 header::CONTENT_TYPE,
@@ -51,9 +96,112 @@ pub async fn download_package(
 
             Ok((headers, body).into_response())
         } else {
-            Err(OnyxError::bad_request("Unable to find package"))
+            Err(OnyxError::not_found("Unable to find package"))
         }
     } else {
-        Err(OnyxError::bad_request("Unable to find version"))
+        Err(OnyxError::not_found("Unable to find version"))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+    use std::sync::Mutex;
+
+    use anyhow::Result;
+    use axum::Router;
+    use axum::routing::get;
+    use onyx_api::prelude::*;
+    use tokio::io::AsyncReadExt;
+
+    use crate::cdn::CdnConfig;
+    use crate::testing::OnyxTest;
+
+    #[tokio::test]
+    async fn should_download_by_name() -> Result<()> {
+        let test = OnyxTest::new().await?;
+        let (login, _) = test.signup(None).await?;
+        let tarball = OnyxTest::create_test_tarball_named(None, Some("named"), Some("0.1.0"))?;
+        test.publish(
+            Some(PublishData::new(tarball.1.to_string(), login.token.clone())),
+            tarball.clone(),
+        )
+        .await?;
+
+        let response = reqwest::get(test.api.package_download_url("named", "0.1.0")).await?;
+        assert_eq!(response.status(), reqwest::StatusCode::OK);
+        assert_eq!(
+            response.headers()[reqwest::header::CONTENT_DISPOSITION],
+            "attachment; filename=\"named_0.1.0.tar\""
+        );
+        assert_eq!(response.bytes().await?.to_vec(), tarball.0);
+
+        for url in [
+            test.api.package_download_url("named", "0.2.0"),
+            test.api.package_download_url("unnamed", "0.1.0"),
+        ] {
+            let response = reqwest::get(url).await?;
+            assert_eq!(response.status(), reqwest::StatusCode::NOT_FOUND);
+        }
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn should_redirect_to_cdn() -> Result<()> {
+        // stand in for a CDN, serving whatever bytes the test puts here
+        let served = Arc::new(Mutex::new(Vec::<u8>::new()));
+        let cdn_app = Router::new().route(
+            "/{id}",
+            get({
+                let served = served.clone();
+                async move || served.lock().unwrap().clone()
+            }),
+        );
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await?;
+        let cdn_url = format!("http://{}", listener.local_addr()?);
+        tokio::spawn(async move {
+            axum::serve(listener, cdn_app).await.unwrap();
+        });
+
+        let test = OnyxTest::with_config(|state| {
+            state.cdn = Some(CdnConfig::new(&cdn_url, "secret", 60));
+        })
+        .await?;
+        let (login, _password) = test.signup(None).await?;
+        let tarball = OnyxTest::create_test_tarball(None)?;
+        let version_id = HashId::from(tarball.1);
+        *served.lock().unwrap() = tarball.0.clone();
+        test.publish(
+            Some(PublishData::new(tarball.1.to_string(), login.token.clone())),
+            tarball.clone(),
+        )
+        .await?;
+
+        let response = reqwest::Client::builder()
+            .redirect(reqwest::redirect::Policy::none())
+            .build()?
+            .get(test.api.version_download_url(&version_id))
+            .send()
+            .await?;
+        assert_eq!(response.status(), reqwest::StatusCode::FOUND);
+        let location = response.headers()[reqwest::header::LOCATION].to_str()?;
+        assert!(location.starts_with(&format!("{cdn_url}/{}?", version_id.to_string())));
+
+        // the client follows the redirect
+        assert_eq!(test.api.download_tarball(&version_id).await?, tarball.0);
+        let mut download = test.api.download_tarball_stream(&version_id).await?;
+        let mut bytes = vec![];
+        download.read_to_end(&mut bytes).await?;
+        assert_eq!(bytes, tarball.0);
+        assert_eq!(download.hash().map(HashId::from), Some(version_id.clone()));
+
+        // and rejects content that doesn't match the version
+        *served.lock().unwrap() = OnyxTest::create_test_tarball(Some("tampered"))?.0;
+        assert!(test.api.download_tarball(&version_id).await.is_err());
+        let mut download = test.api.download_tarball_stream(&version_id).await?;
+        let error = download.read_to_end(&mut vec![]).await.unwrap_err();
+        assert_eq!(error.kind(), std::io::ErrorKind::InvalidData);
+        assert!(download.hash().is_none());
+        Ok(())
     }
 }
@@ -1,18 +1,48 @@
+use axum::Json;
 use axum::extract::multipart::MultipartError;
 use axum::http::StatusCode;
 use axum::response::IntoResponse;
+use onyx_api::prelude::*;
 
-#[derive(Clone, Default)]
+#[derive(Clone, Debug, Default)]
 pub struct OnyxError {
+    code: OnyxErrorCode,
     message: Option<String>,
-    status_code: StatusCode,
+    details: Option<serde_json::Value>,
 }
 
 impl OnyxError {
-    pub fn bad_request(message: &str) -> Self {
+    pub fn new(code: OnyxErrorCode, message: &str) -> Self {
         Self {
+            code,
             message: Some(message.to_string()),
-            status_code: StatusCode::BAD_REQUEST,
+            details: None,
+        }
+    }
+
+    pub fn bad_request(message: &str) -> Self {
+        Self::new(OnyxErrorCode::BadRequest, message)
+    }
+
+    pub fn not_found(message: &str) -> Self {
+        Self::new(OnyxErrorCode::NotFound, message)
+    }
+
+    pub fn conflict(message: &str) -> Self {
+        Self::new(OnyxErrorCode::Conflict, message)
+    }
+
+    pub fn with_details(mut self, details: serde_json::Value) -> Self {
+        self.details = Some(details);
+        self
+    }
+}
+
+impl std::fmt::Display for OnyxError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match &self.message {
+            Some(message) => write!(f, "{message}"),
+            None => write!(f, "{:?}", self.code),
         }
     }
 }
@@ -21,10 +51,7 @@ macro_rules! impl_error_from {
     ($error_type:ty) => {
         impl From<$error_type> for OnyxError {
             fn from(value: $error_type) -> Self {
-                Self {
-                    message: Some(value.to_string()),
-                    status_code: StatusCode::INTERNAL_SERVER_ERROR,
-                }
+                Self::new(OnyxErrorCode::Internal, &value.to_string())
             }
         }
     };
@@ -35,43 +62,39 @@ impl_error_from!(redb::TransactionError);
 impl_error_from!(redb::TableError);
 impl_error_from!(redb::CommitError);
 impl_error_from!(bcrypt::BcryptError);
+impl_error_from!(argon2::password_hash::Error);
 impl_error_from!(blake3::HexError);
 impl_error_from!(Box<bincode::ErrorKind>);
 
 impl From<std::io::Error> for OnyxError {
     fn from(value: std::io::Error) -> Self {
-        Self {
-            message: Some(format!("Uncaught io error: {:?}", value.to_string())),
-            status_code: StatusCode::INTERNAL_SERVER_ERROR,
-        }
+        Self::new(
+            OnyxErrorCode::Internal,
+            &format!("Uncaught io error: {:?}", value.to_string()),
+        )
     }
 }
 impl From<MultipartError> for OnyxError {
     fn from(value: MultipartError) -> Self {
-        Self {
-            message: Some(format!(
-                "Error in multipart request: {:?}",
-                value.to_string()
-            )),
-            status_code: StatusCode::BAD_REQUEST,
-        }
+        Self::bad_request(&format!(
+            "Error in multipart request: {:?}",
+            value.to_string()
+        ))
     }
 }
 
 impl From<anyhow::Error> for OnyxError {
     fn from(value: anyhow::Error) -> Self {
-        Self {
-            message: Some(value.to_string()),
-            status_code: StatusCode::INTERNAL_SERVER_ERROR,
-        }
+        Self::new(OnyxErrorCode::Internal, &value.to_string())
     }
 }
 
 impl From<StatusCode> for OnyxError {
     fn from(value: StatusCode) -> Self {
         Self {
+            code: OnyxErrorCode::from_status_code(value.as_u16()),
             message: None,
-            status_code: value,
+            details: None,
         }
     }
 }
@@ -79,9 +102,15 @@ impl From<StatusCode> for OnyxError {
 impl IntoResponse for OnyxError {
     fn into_response(self) -> axum::response::Response {
         (
-            self.status_code,
-            self.message
-                .unwrap_or("Unknown error ocurred in Onyx system".to_string()),
+            StatusCode::from_u16(self.code.status_code())
+                .unwrap_or(StatusCode::INTERNAL_SERVER_ERROR),
+            Json(ApiError {
+                code: self.code,
+                message: self
+                    .message
+                    .unwrap_or("Unknown error ocurred in Onyx system".to_string()),
+                details: self.details,
+            }),
         )
             .into_response()
     }
@@ -1,19 +1,26 @@
-use std::sync::LazyLock;
-
 use anyhow::Result;
 use axum::body::Body;
+use axum::body::Bytes;
 use axum::extract::Path;
 use axum::extract::State;
+use axum::http::HeaderMap;
 use axum::response::Response;
+use nrpm_tarball::pkt_line;
+use nrpm_tarball::pkt_line::PktLine;
+use nrpm_tarball::pkt_line::UploadPackRequest;
+use nrpm_tarball::pkt_line::parse_upload_pack_request;
 use nrpm_tarball::ptk_bytes;
 use onyx_api::db::GIT_PACK_TABLE;
 use onyx_api::db::GIT_REFS_TABLE;
 use onyx_api::db::PackageModel;
-use regex::Regex;
+use onyx_api::db::VERSION_TABLE;
 use reqwest::StatusCode;
 
 use super::OnyxError;
 use super::OnyxState;
+use super::db::Db;
+
+const AGENT: &str = "agent=onyx/0.0.0-pre-release";
 
 pub async fn empty() -> Result<Response, OnyxError> {
     let mut res = Response::new("not found".into());
@@ -21,115 +28,234 @@ pub async fn empty() -> Result<Response, OnyxError> {
     Ok(res)
 }
 
+/// A ref advertised for a package. Every version is both a branch and a tag pointing at its
+/// commit, and `HEAD` points at the latest version. Versions starting with a digit are also
+/// tagged with a `v` prefix, see `v_tag_refs`.
+#[derive(Clone)]
+pub struct GitRef {
+    name: String,
+    oid: String,
+    /// The ref a symbolic ref points to.
+    target: Option<String>,
+}
+
+impl GitRef {
+    /// The ref as a line of an ls-refs response.
+    fn ls_refs_line(&self, symrefs: bool) -> String {
+        match &self.target {
+            Some(target) if symrefs => {
+                format!("{} {} symref-target:{target}\n", self.oid, self.name)
+            }
+            _ => format!("{} {}\n", self.oid, self.name),
+        }
+    }
+}
+
+/// The refs of a repository with a commit for each of `versions`, as `(oid, version_name)`.
+/// `HEAD` points at the version named `head`, if there is one.
+pub fn version_refs(versions: &[(String, String)], head: Option<&str>) -> Vec<GitRef> {
+    let mut refs = vec![];
+    if let Some((oid, name)) = versions
+        .iter()
+        .find(|(_, name)| Some(name.as_str()) == head)
+    {
+        refs.push(GitRef {
+            name: "HEAD".to_string(),
+            oid: oid.clone(),
+            target: Some(format!("refs/heads/{name}")),
+        });
+    }
+    for prefix in ["refs/heads", "refs/tags"] {
+        for (oid, name) in versions {
+            refs.push(GitRef {
+                name: format!("{prefix}/{name}"),
+                oid: oid.clone(),
+                target: None,
+            });
+        }
+    }
+    refs
+}
+
+/// Tags aliasing each of `versions` with a `v` prefix, e.g. `refs/tags/v1.2.3` for `1.2.3`, so
+/// Nargo.toml files written with `tag = "v1.2.3"` work. Versions already named with a `v`
+/// aren't aliased, nor are versions a `v` prefixed version exists for.
+pub fn v_tag_refs(versions: &[(String, String)]) -> Vec<GitRef> {
+    versions
+        .iter()
+        .filter(|(_, name)| name.starts_with(|c: char| c.is_ascii_digit()))
+        .filter(|(_, name)| {
+            !versions
+                .iter()
+                .any(|(_, other)| *other == format!("v{name}"))
+        })
+        .map(|(oid, name)| GitRef {
+            name: format!("refs/tags/v{name}"),
+            oid: oid.clone(),
+            target: None,
+        })
+        .collect()
+}
+
+fn package_refs(db: &Db, package: &PackageModel) -> Result<Vec<GitRef>, OnyxError> {
+    db.package_refs(&package.id, || read_package_refs(db, package))
+}
+
+fn read_package_refs(db: &Db, package: &PackageModel) -> Result<Vec<GitRef>, OnyxError> {
+    let read = db.begin_read()?;
+    let git_refs_table = read.open_table(GIT_REFS_TABLE)?;
+    let version_table = read.open_table(VERSION_TABLE)?;
+    // the branch of each version is stored as it's sent in an ls-refs response
+    let stored = git_refs_table
+        .get(package.id.as_str())?
+        .map(|v| v.value().to_string())
+        .unwrap_or_default();
+    let mut versions = vec![];
+    for line in pkt_line::parse(stored.as_bytes())? {
+        let PktLine::Data(data) = line else {
+            continue;
+        };
+        let line = String::from_utf8_lossy(data);
+        if let Some((oid, name)) = line.trim_end().split_once(' ')
+            && let Some(version_name) = name.strip_prefix("refs/heads/")
+        {
+            versions.push((oid.to_string(), version_name.to_string()));
+        }
+    }
+
+    let latest_version_name = version_table
+        .get(&package.latest_version_id)?
+        .map(|v| v.value().name);
+    let mut refs = version_refs(&versions, latest_version_name.as_deref());
+    refs.extend(v_tag_refs(&versions));
+    Ok(refs)
+}
+
+/// The response to `info/refs` for a repository with the refs `refs` returns. The refs are only
+/// needed by clients that don't speak protocol v2.
+pub fn advertise(
+    headers: &HeaderMap,
+    refs: impl FnOnce() -> Result<Vec<GitRef>, OnyxError>,
+) -> Result<Response, OnyxError> {
+    let protocol_v2 = headers
+        .get("Git-Protocol")
+        .and_then(|v| v.to_str().ok())
+        .is_some_and(|v| v.split(':').any(|param| param == "version=2"));
+    let body = if protocol_v2 {
+        [
+            ptk_bytes("version 2\n"),
+            ptk_bytes(&format!("{AGENT}\n")),
+            ptk_bytes("ls-refs=unborn\n"),
+            ptk_bytes("ls-refs=symrefs\n"),
+            ptk_bytes("fetch=shallow\n"),
+            "0000".into(),
+        ]
+        .concat()
+    } else {
+        // older clients expect the refs in the advertisement. They're enough for
+        // `git ls-remote`, fetching needs protocol v2
+        let refs = refs()?;
+        let symref = refs
+            .iter()
+            .find_map(|r| r.target.as_ref().map(|t| format!(" symref={}:{t}", r.name)))
+            .unwrap_or_default();
+        let mut lines = vec![ptk_bytes("# service=git-upload-pack\n"), "0000".into()];
+        for (i, r) in refs.iter().enumerate() {
+            if i == 0 {
+                lines.push(ptk_bytes(&format!(
+                    "{} {}\0{AGENT}{symref}\n",
+                    r.oid, r.name
+                )));
+            } else {
+                lines.push(ptk_bytes(&format!("{} {}\n", r.oid, r.name)));
+            }
+        }
+        lines.push("0000".into());
+        lines.concat()
+    };
+    let mut res = Response::new(body.into());
+    res.headers_mut().insert(
+        "Content-Type",
+        "application/x-git-upload-pack-advertisement"
+            .parse()
+            .unwrap(),
+    );
+    res.headers_mut()
+        .insert("Cache-Control", "no-cache".parse().unwrap());
+    Ok(res)
+}
+
 pub async fn mocked_refs(
     State(state): State<OnyxState>,
     Path(package_name): Path<String>,
+    headers: HeaderMap,
 ) -> Result<Response, OnyxError> {
-    if let Some(_version) = PackageModel::latest_version(state.db, &package_name)? {
-        let mut res = Response::new(
-            [
-                ptk_bytes("version 2\n"),
-                ptk_bytes("agent=onyx/0.0.0-pre-release\n"),
-                ptk_bytes("ls-refs=unborn\n"),
-                ptk_bytes("ls-refs=symrefs\n"),
-                ptk_bytes("fetch=shallow\n"),
-                "0000".into(),
-            ]
-            .concat()
-            .into(),
-        );
-        res.headers_mut().insert(
-            "Content-Type",
-            "application/x-git-upload-pack-advertisement"
-                .parse()
-                .unwrap(),
-        );
-        res.headers_mut()
-            .insert("Cache-Control", "no-cache".parse().unwrap());
-        Ok(res)
-    } else {
+    let Some(package) = state.db.package_by_name(&package_name)? else {
         let mut res = Response::new("not found".into());
         *res.status_mut() = StatusCode::NOT_FOUND;
-        Ok(res)
-    }
+        return Ok(res);
+    };
+    advertise(&headers, || package_refs(&state.db, &package))
 }
 
-/// Handles loading references and sending packs
-pub async fn mocked_upload_pack(
-    State(state): State<OnyxState>,
-    Path(package_name): Path<String>,
-    body: String,
+/// Respond to the upload-pack `request` for the repository called `name`, which has `refs`.
+/// Each commit is a single commit with a ready to send pack.
+pub fn upload_pack(
+    db: &Db,
+    name: &str,
+    request: UploadPackRequest,
+    refs: Vec<GitRef>,
 ) -> Result<Response, OnyxError> {
-    if let Some(package) = PackageModel::package_by_name(state.db.clone(), &package_name)? {
-        let mut res = Response::new(Body::empty());
-        res.headers_mut().insert(
-            "Content-Type",
-            "application/x-git-upload-pack-result".parse().unwrap(),
-        );
-        res.headers_mut()
-            .insert("Cache-Control", "no-cache".parse().unwrap());
-
-        log::debug!("upload-pack: {}", body);
-
-        if body.contains("0014command=ls-refs") {
-            let read = state.db.begin_read()?;
-            let git_refs_table = read.open_table(GIT_REFS_TABLE)?;
-            // a list of refs, we'll manually add a terminating sequence
-            let refs = git_refs_table
-                .get(package.id.as_str())?
-                .and_then(|v| Some(v.value().to_string()))
-                .unwrap_or_default();
-
-            *res.body_mut() = format!("{}0000", refs).into_bytes().into();
-        } else if body.contains("0011command=fetch") {
-            // parse what commit is being requested, then send the pack data for that commit
-            static COMMIT_REGEX: LazyLock<Regex> = LazyLock::new(|| {
-                Regex::new(r"0032want ([a-f0-9]{40})").expect("failed to create commit regex")
-            });
-            let commit_hex = if let Some(caps) = COMMIT_REGEX.captures(&body)
-                // first entry is full match, we want the subgroup
-                && caps.len() >= 2
-            {
-                caps[1].to_string()
-            } else {
-                return Err(OnyxError::bad_request("unable to find want commits"));
-            };
+    let mut res = Response::new(Body::empty());
+    res.headers_mut().insert(
+        "Content-Type",
+        "application/x-git-upload-pack-result".parse().unwrap(),
+    );
+    res.headers_mut()
+        .insert("Cache-Control", "no-cache".parse().unwrap());
+    match request {
+        UploadPackRequest::LsRefs {
+            ref_prefixes,
+            symrefs,
+        } => {
+            let mut lines = vec![];
+            for r in refs {
+                if ref_prefixes.is_empty()
+                    || ref_prefixes.iter().any(|prefix| r.name.starts_with(prefix))
+                {
+                    lines.push(ptk_bytes(&r.ls_refs_line(symrefs)));
+                }
+            }
+            lines.push("0000".into());
+            *res.body_mut() = lines.concat().into();
+        }
+        UploadPackRequest::Fetch { wants } => {
+            // each version is a single commit, send the pack data for the first one wanted
+            let commit_hex = wants[0].clone();
 
-            let read = state.db.begin_read()?;
+            let read = db.begin_read()?;
             let git_packs_table = read.open_table(GIT_PACK_TABLE)?;
             let pack_bytes = if let Some(pack) = git_packs_table.get(commit_hex.as_str())? {
                 pack.value()
             } else {
-                return Err(OnyxError::bad_request(&format!(
+                return Err(OnyxError::not_found(&format!(
                     "unable to find pack for commit {}",
                     commit_hex
                 )));
             };
 
-            // determine the name of the ref for the download message
-            // TODO: consider storing this in the db
-            let git_refs_table = read.open_table(GIT_REFS_TABLE)?;
-            // a list of refs, we'll manually add a terminating sequence
-            let refs = git_refs_table
-                .get(package.id.as_str())?
-                .and_then(|v| Some(v.value().to_string()))
-                .unwrap_or_default();
-
-            let ref_regex = Regex::new(&format!("{} refs/heads/(.*)", commit_hex))
-                .expect("failed to build ref_regex");
-            let version_name = if let Some(caps) = ref_regex.captures(&refs)
-                && caps.len() >= 2
-            {
-                caps[1].to_string()
-            } else {
-                "unknown_version".to_string()
-            };
+            // the name of the ref for the download message
+            let version_name = refs
+                .iter()
+                .filter(|r| r.oid == commit_hex)
+                .find_map(|r| r.name.strip_prefix("refs/heads/"))
+                .unwrap_or("unknown_version");
 
             let mut res_bytes = vec![
                 ptk_bytes("packfile\n"),
                 ptk_bytes(&format!(
                     "\x02🚒 nrpm downloading {}@{}\n",
-                    package_name, version_name
+                    name, version_name
                 )),
             ];
             for chunk in pack_bytes.chunks((pack_bytes.len() / (10 * 1024)).max(1)) {
@@ -141,14 +267,141 @@ pub async fn mocked_upload_pack(
 
             res_bytes.push("0000".into());
             *res.body_mut() = res_bytes.concat().into();
-        } else {
-            return Err(OnyxError::bad_request("unknown git command"));
         }
+    }
+    Ok(res)
+}
 
-        Ok(res)
-    } else {
+/// Handles loading references and sending packs
+pub async fn mocked_upload_pack(
+    State(state): State<OnyxState>,
+    Path(package_name): Path<String>,
+    body: Bytes,
+) -> Result<Response, OnyxError> {
+    let Some(package) = state.db.package_by_name(&package_name)? else {
         let mut res = Response::new("not found".into());
         *res.status_mut() = StatusCode::NOT_FOUND;
-        Ok(res)
+        return Ok(res);
+    };
+    log::debug!("upload-pack: {}", String::from_utf8_lossy(&body));
+    let request = parse_upload_pack_request(&body)
+        .map_err(|e| OnyxError::bad_request(&format!("invalid git request: {e}")))?;
+    let refs = package_refs(&state.db, &package)?;
+    upload_pack(&state.db, &package_name, request, refs)
+}
+
+#[cfg(test)]
+mod tests {
+    use anyhow::Result;
+    use onyx_api::prelude::*;
+
+    use crate::testing::OnyxTest;
+
+    #[tokio::test]
+    async fn should_clone_published_version() -> Result<()> {
+        let test = OnyxTest::new().await?;
+        let (login, _) = test.signup(None).await?;
+        let name = "cloneable";
+        let tarball =
+            OnyxTest::create_test_tarball_named(Some("cloned\n"), Some(name), Some("0.1.0"))?;
+        test.publish(
+            Some(PublishData::new(tarball.1.to_string(), login.token)),
+            tarball,
+        )
+        .await?;
+
+        // the version is a branch, and a tag with and without a v prefix
+        for branch in ["0.1.0", "v0.1.0"] {
+            let workdir = tempfile::tempdir()?;
+            let checkout = workdir.path().join(name);
+            let output = tokio::process::Command::new("git")
+                .arg("-c")
+                .arg("protocol.version=2")
+                .arg("clone")
+                .arg("--depth")
+                .arg("1")
+                .arg("--branch")
+                .arg(branch)
+                .arg(format!("{}/{name}", test.url))
+                .arg(&checkout)
+                .output()
+                .await?;
+            assert!(
+                output.status.success(),
+                "{}",
+                String::from_utf8_lossy(&output.stderr)
+            );
+            assert_eq!(std::fs::read_to_string(checkout.join("aaaaa"))?, "cloned\n");
+        }
+
+        Ok(())
+    }
+    #[tokio::test]
+    async fn should_list_version_refs() -> Result<()> {
+        let test = OnyxTest::new().await?;
+        let (login, _) = test.signup(None).await?;
+        let name = "listable";
+        for version in ["0.1.0", "0.2.0"] {
+            let tarball =
+                OnyxTest::create_test_tarball_named(Some(version), Some(name), Some(version))?;
+            test.publish(
+                Some(PublishData::new(tarball.1.to_string(), login.token.clone())),
+                tarball,
+            )
+            .await?;
+        }
+
+        for protocol in ["protocol.version=2", "protocol.version=0"] {
+            let output = tokio::process::Command::new("git")
+                .arg("-c")
+                .arg(protocol)
+                .arg("ls-remote")
+                .arg("--symref")
+                .arg(format!("{}/{name}", test.url))
+                .output()
+                .await?;
+            assert!(
+                output.status.success(),
+                "{}",
+                String::from_utf8_lossy(&output.stderr)
+            );
+            let refs = String::from_utf8(output.stdout)?;
+            let ref_names = refs
+                .lines()
+                .filter_map(|line| line.split_once('\t').map(|(_, name)| name))
+                .collect::<Vec<_>>();
+            assert_eq!(
+                ref_names,
+                vec![
+                    "HEAD",
+                    "HEAD",
+                    "refs/heads/0.1.0",
+                    "refs/heads/0.2.0",
+                    "refs/tags/0.1.0",
+                    "refs/tags/0.2.0",
+                    "refs/tags/v0.1.0",
+                    "refs/tags/v0.2.0"
+                ],
+                "{protocol}"
+            );
+            assert!(
+                refs.starts_with("ref: refs/heads/0.2.0\tHEAD\n"),
+                "{protocol}"
+            );
+        }
+
+        let output = tokio::process::Command::new("git")
+            .arg("-c")
+            .arg("protocol.version=2")
+            .arg("ls-remote")
+            .arg("--tags")
+            .arg(format!("{}/{name}", test.url))
+            .output()
+            .await?;
+        let refs = String::from_utf8(output.stdout)?;
+        assert_eq!(refs.lines().count(), 4);
+        assert!(refs.lines().all(|line| line.contains("\trefs/tags/")));
+
+        Ok(())
     }
 }
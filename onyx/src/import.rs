@@ -0,0 +1,421 @@
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::Read;
+use std::path::Path;
+use std::path::PathBuf;
+use std::process::Command;
+use std::process::Stdio;
+use std::time::Duration;
+use std::time::Instant;
+
+use anyhow::Context;
+use anyhow::Result;
+use axum::extract::State;
+use axum::response::Json as ResponseJson;
+use nargo_parse::NargoConfig;
+use nargo_parse::NargoDocument;
+use tempfile::TempDir;
+use tempfile::tempfile;
+
+use onyx_api::prelude::*;
+
+use super::OnyxError;
+use super::OnyxState;
+use super::audit;
+use super::build;
+use super::publish::NewVersion;
+use super::publish::store_version;
+use super::quota;
+use super::session::AdminSession;
+use super::validate::ValidJson;
+use super::validate::ValidationErrors;
+use super::validate::normalize_version_name;
+use super::validate::validate_license;
+use super::validate::validate_nrpm_metadata;
+use super::validate::validate_package_name;
+
+/// A repository checked out at a tag. The checkout is removed when this is dropped.
+pub struct Checkout {
+    pub dir: TempDir,
+    pub commit: String,
+}
+
+/// How long cloning a repository may take before it's given up on.
+const CHECKOUT_TIMEOUT: Duration = Duration::from_secs(120);
+
+/// Shallow clone `url` at `tag`. The clone is stopped if it takes longer than
+/// `CHECKOUT_TIMEOUT` and git is never allowed to prompt for credentials.
+pub fn checkout(url: &str, tag: &str) -> Result<Checkout> {
+    let dir = tempfile::tempdir()?;
+    let mut child = Command::new("git")
+        .arg("-c")
+        .arg("advice.detachedHead=false")
+        .arg("clone")
+        .arg("--quiet")
+        .arg("--depth")
+        .arg("1")
+        .arg("--branch")
+        .arg(tag)
+        .arg("--")
+        .arg(url)
+        .arg(dir.path())
+        .env("GIT_TERMINAL_PROMPT", "0")
+        .stdin(Stdio::null())
+        .stdout(Stdio::null())
+        .stderr(Stdio::piped())
+        .spawn()
+        .context("Failed to run git, is it installed?")?;
+    // drain stderr as git writes it so a full pipe can't stall the clone
+    let mut pipe = child.stderr.take().context("git stderr was not piped")?;
+    let stderr = std::thread::spawn(move || {
+        let mut stderr = String::new();
+        let _ = pipe.read_to_string(&mut stderr);
+        stderr
+    });
+    let started_at = Instant::now();
+    let status = loop {
+        if let Some(status) = child.try_wait()? {
+            break status;
+        }
+        if started_at.elapsed() > CHECKOUT_TIMEOUT {
+            child.kill()?;
+            child.wait()?;
+            anyhow::bail!(
+                "Failed to clone {url} at {tag}: timed out after {}s",
+                CHECKOUT_TIMEOUT.as_secs()
+            );
+        }
+        std::thread::sleep(Duration::from_millis(50));
+    };
+    if !status.success() {
+        let stderr = stderr.join().unwrap_or_default();
+        anyhow::bail!("Failed to clone {url} at {tag}: {}", stderr.trim());
+    }
+    let output = Command::new("git")
+        .arg("-C")
+        .arg(dir.path())
+        .arg("rev-parse")
+        .arg("HEAD")
+        .output()?;
+    if !output.status.success() {
+        anyhow::bail!("Failed to resolve the commit of {url} at {tag}");
+    }
+    let commit = String::from_utf8(output.stdout)?.trim().to_string();
+    Ok(Checkout { dir, commit })
+}
+
+/// Package the checkout in `dir`. Most packages published with git don't have a version in
+/// their Nargo.toml, so the tag without a leading `v` is used, and the repository is recorded
+/// if Nargo.toml doesn't name one.
+fn package(dir: &Path, url: &str, tag: &str) -> Result<(File, blake3::Hash)> {
+    let source = std::fs::read_to_string(dir.join("Nargo.toml"))
+        .context("Nargo.toml not found in the repository")?;
+    let config = NargoConfig::from_str(&source)?;
+    let mut doc = source.parse::<NargoDocument>()?;
+    let mut changed = false;
+    if config.package.version.is_none() {
+        doc.set_version(tag.strip_prefix('v').unwrap_or(tag))?;
+        changed = true;
+    }
+    if config.package.repository.is_none() {
+        doc.set_repository(Some(url))?;
+        changed = true;
+    }
+    let overrides = if changed {
+        HashMap::from([(PathBuf::from("Nargo.toml"), doc.to_string().into_bytes())])
+    } else {
+        HashMap::new()
+    };
+    let mut tarball = nrpm_tarball::create_with_overrides(dir, tempfile()?, &overrides)?;
+    let hash = nrpm_tarball::hash_tarball(&mut tarball)?;
+    Ok((tarball, hash))
+}
+
+/// Import `tag` of `url` as a version owned by `user_id`. Returns the package and version
+/// names, and whether the version was already published.
+async fn import_tag(
+    state: &OnyxState,
+    user_id: &str,
+    admin_username: Option<&str>,
+    url: &str,
+    tag: &str,
+) -> Result<(String, String, ImportStatus), OnyxError> {
+    let (checkout, mut tarball, hash) = {
+        let (url, tag) = (url.to_string(), tag.to_string());
+        tokio::task::spawn_blocking(move || -> Result<_> {
+            let checkout = checkout(&url, &tag)?;
+            let (tarball, hash) = package(checkout.dir.path(), &url, &tag)?;
+            Ok((checkout, tarball, hash))
+        })
+        .await
+        .map_err(|e| anyhow::anyhow!(e))??
+    };
+    let (package_name, version_name) = state
+        .storage
+        .validate_tarball(&mut tarball, state.config.legacy_version_names)
+        .map_err(|e| OnyxError::new(OnyxErrorCode::InvalidPackage, &e.to_string()))?;
+    let mut errors = ValidationErrors::default();
+    errors.check("package.name", validate_package_name(&package_name));
+    let version_name =
+        match normalize_version_name(&version_name, state.config.legacy_version_names) {
+            Ok(normalized) => normalized,
+            Err(message) => {
+                errors.check("package.version", Err(message));
+                version_name
+            }
+        };
+    let config = NargoConfig::load(checkout.dir.path())?;
+    if let Some(license) = &config.package.license {
+        errors.check("package.license", validate_license(license));
+    }
+    errors.check(
+        "package.metadata.nrpm",
+        validate_nrpm_metadata(&config.package),
+    );
+    errors.into_result()?;
+
+    let version_id = HashId::from(hash);
+    {
+        let read = state.db.begin_read()?;
+        let version_table = read.open_table(VERSION_TABLE)?;
+        if version_table.get(&version_id)?.is_some() {
+            return Ok((package_name, version_name, ImportStatus::Skipped));
+        }
+    }
+
+    let write = state.db.begin_write()?;
+    let size = tarball.metadata()?.len();
+    let package = store_version(
+        &state.storage,
+        &write,
+        NewVersion {
+            author_id: user_id,
+            package_name,
+            version_name: version_name.clone(),
+            hash,
+            created_at: timestamp(),
+            follow_owner: false,
+            source_repository: Some(url.to_string()),
+            source_commit: Some(checkout.commit.clone()),
+            release_notes: None,
+        },
+        &mut tarball,
+    )?;
+    quota::charge(&write, &package.author_id, &package.id, size)?;
+    if state.config.nargo_path.is_some() {
+        build::enqueue(&write, &version_id)?;
+    }
+    audit::record(
+        &write,
+        AuditAction::VersionImported,
+        admin_username,
+        &package.name,
+        format!("{version_name} from {url} at {tag} ({})", checkout.commit),
+    )?;
+    write.commit()?;
+    Ok((package.name, version_name, ImportStatus::Published))
+}
+
+/// Publish each tag of each repository in `request` as a version owned by its user. Tags are
+/// imported in order and one failing doesn't stop the rest, list older tags first so the
+/// latest version of each package is its newest tag.
+pub async fn import(
+    state: &OnyxState,
+    request: &ImportRequest,
+    admin_username: Option<&str>,
+) -> Result<ImportResponse, OnyxError> {
+    let user_id = {
+        let read = state.db.begin_read()?;
+        let username_table = read.open_table(USERNAME_USER_ID_TABLE)?;
+        username_table
+            .get(request.username.as_str())?
+            .map(|id| id.value().to_string())
+            .ok_or(OnyxError::not_found(&format!(
+                "Unable to find user \"{}\"",
+                request.username
+            )))?
+    };
+    let mut results = vec![];
+    for repository in &request.repositories {
+        for tag in &repository.tags {
+            let result = import_tag(state, &user_id, admin_username, &repository.url, tag).await;
+            results.push(match result {
+                Ok((package_name, version_name, status)) => ImportResult {
+                    url: repository.url.clone(),
+                    tag: tag.clone(),
+                    status,
+                    package_name: Some(package_name),
+                    version_name: Some(version_name),
+                    error: None,
+                },
+                Err(e) => ImportResult {
+                    url: repository.url.clone(),
+                    tag: tag.clone(),
+                    status: ImportStatus::Failed,
+                    package_name: None,
+                    version_name: None,
+                    error: Some(e.to_string()),
+                },
+            });
+        }
+    }
+    Ok(ImportResponse { results })
+}
+
+pub async fn admin_import(
+    State(state): State<OnyxState>,
+    admin: AdminSession,
+    ValidJson(payload): ValidJson<ImportRequest>,
+) -> Result<ResponseJson<ImportResponse>, OnyxError> {
+    if let Some(upstream) = &state.config.upstream_url {
+        return Err(OnyxError::new(
+            OnyxErrorCode::Forbidden,
+            &format!("This registry is a read-only mirror, import to {upstream} instead"),
+        ));
+    }
+    Ok(ResponseJson(
+        import(&state, &payload, Some(&admin.user.username)).await?,
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+    use std::process::Command;
+    use std::sync::Arc;
+
+    use anyhow::Result;
+    use nanoid::nanoid;
+    use onyx_api::prelude::*;
+
+    use super::checkout;
+    use super::import;
+    use crate::testing::OnyxTest;
+
+    fn git(dir: &Path, args: &[&str]) -> Result<()> {
+        let output = Command::new("git")
+            .arg("-C")
+            .arg(dir)
+            .args(["-c", "user.name=test", "-c", "user.email=test@example.com"])
+            .args(args)
+            .output()?;
+        assert!(
+            output.status.success(),
+            "{}",
+            String::from_utf8_lossy(&output.stderr)
+        );
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn should_import_tagged_repository() -> Result<()> {
+        let admin_username = nanoid!();
+        let test = OnyxTest::with_admins(&[&admin_username]).await?;
+        let (admin, _password) = test
+            .signup(Some(LoginRequest {
+                username: admin_username,
+                password: nanoid!(),
+                ..Default::default()
+            }))
+            .await?;
+        let (user, _password) = test.signup(None).await?;
+
+        let repo = tempfile::tempdir()?;
+        std::fs::write(
+            repo.path().join("Nargo.toml"),
+            "[package]\nname = \"imported\"\ntype = \"lib\"\n",
+        )?;
+        std::fs::write(repo.path().join("lib.nr"), "fn main() {}\n")?;
+        git(repo.path(), &["init", "-q"])?;
+        git(repo.path(), &["add", "."])?;
+        git(repo.path(), &["commit", "-q", "-m", "init"])?;
+        git(repo.path(), &["tag", "v0.1.0"])?;
+
+        let url = format!("file://{}", repo.path().display());
+        let request = ImportRequest {
+            username: user.user.username.clone(),
+            repositories: vec![ImportRepository {
+                url: url.clone(),
+                tags: vec!["v0.1.0".to_string(), "v9.9.9".to_string()],
+            }],
+        };
+        let response = import(&test.state, &request, None)
+            .await
+            .map_err(|e| anyhow::anyhow!("{e}"))?;
+        assert_eq!(response.results[0].status, ImportStatus::Published);
+        assert_eq!(response.results[0].version_name.as_deref(), Some("0.1.0"));
+        assert_eq!(response.results[1].status, ImportStatus::Failed);
+
+        let (package, versions) = test.api.load_package_versions("imported").await?;
+        assert_eq!(package.author_id, user.user.id);
+        assert_eq!(versions[0].source_repository.as_deref(), Some(url.as_str()));
+        assert!(versions[0].source_commit.is_some());
+
+        let response = import(&test.state, &request, None)
+            .await
+            .map_err(|e| anyhow::anyhow!("{e}"))?;
+        assert_eq!(response.results[0].status, ImportStatus::Skipped);
+
+        // the api only imports from https urls, and only for admins
+        let err = test
+            .api
+            .admin_import(&admin.token, &request)
+            .await
+            .unwrap_err();
+        let err = err.downcast_ref::<ApiError>().unwrap();
+        assert_eq!(err.code, OnyxErrorCode::ValidationFailed);
+        assert!(test.api.admin_import(&user.token, &request).await.is_err());
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn should_import_legacy_version_names_only_when_allowed() -> Result<()> {
+        let repo = tempfile::tempdir()?;
+        std::fs::write(
+            repo.path().join("Nargo.toml"),
+            "[package]\nname = \"nightly\"\nversion = \"nightly\"\ntype = \"lib\"\n",
+        )?;
+        std::fs::write(repo.path().join("lib.nr"), "fn main() {}\n")?;
+        git(repo.path(), &["init", "-q"])?;
+        git(repo.path(), &["add", "."])?;
+        git(repo.path(), &["commit", "-q", "-m", "init"])?;
+        git(repo.path(), &["tag", "nightly"])?;
+        let request = |username: String| ImportRequest {
+            username,
+            repositories: vec![ImportRepository {
+                url: format!("file://{}", repo.path().display()),
+                tags: vec!["nightly".to_string()],
+            }],
+        };
+
+        let test = OnyxTest::new().await?;
+        let (user, _password) = test.signup(None).await?;
+        let response = import(&test.state, &request(user.user.username), None)
+            .await
+            .map_err(|e| anyhow::anyhow!("{e}"))?;
+        assert_eq!(response.results[0].status, ImportStatus::Failed);
+        assert!(test.api.load_package_versions("nightly").await.is_err());
+
+        let test = OnyxTest::with_config(|state| {
+            Arc::make_mut(&mut state.config).legacy_version_names = true
+        })
+        .await?;
+        let (user, _password) = test.signup(None).await?;
+        let response = import(&test.state, &request(user.user.username), None)
+            .await
+            .map_err(|e| anyhow::anyhow!("{e}"))?;
+        assert_eq!(response.results[0].status, ImportStatus::Published);
+        assert_eq!(response.results[0].version_name.as_deref(), Some("nightly"));
+        Ok(())
+    }
+
+    #[test]
+    fn should_not_read_urls_as_options() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let marker = dir.path().join("marker");
+        let url = format!("--upload-pack=touch {}", marker.display());
+        assert!(checkout(&url, "v0.1.0").is_err());
+        assert!(!marker.exists());
+        Ok(())
+    }
+}
@@ -0,0 +1,318 @@
+use std::collections::BTreeSet;
+use std::path::Path;
+use std::sync::Arc;
+use std::sync::Mutex;
+
+use anyhow::Result;
+use axum::extract::Path as UrlPath;
+use axum::extract::State;
+use axum::http::HeaderMap;
+use axum::http::StatusCode;
+use axum::http::header;
+use axum::response::IntoResponse;
+use axum::response::Json as ResponseJson;
+use axum::response::Response;
+use redb::ReadableTable;
+
+use onyx_api::prelude::*;
+
+use super::OnyxError;
+use super::OnyxState;
+use super::db::Db;
+use super::session::AdminSession;
+use super::timestamp;
+
+const CONFIG_FILE: &str = "config.json";
+// how long caches may serve an index file without revalidating
+const INDEX_MAX_AGE: u64 = 60;
+
+// the export job and the admin endpoint write the same files
+static EXPORT_LOCK: Mutex<()> = Mutex::new(());
+
+/// Write the static index to `dir`. Only packages published to since the last export are
+/// rewritten unless `full` is set or the directory has no `config.json`. Returns the
+/// resulting config and the number of package files written.
+pub fn export(db: &Arc<Db>, dir: &Path, full: bool) -> Result<(IndexConfig, usize)> {
+    let _guard = EXPORT_LOCK.lock().unwrap_or_else(|e| e.into_inner());
+    let previous = if full {
+        None
+    } else {
+        std::fs::read_to_string(dir.join(CONFIG_FILE))
+            .ok()
+            .and_then(|str| serde_json::from_str::<IndexConfig>(&str).ok())
+    };
+
+    let (package_names, latest_seq) = {
+        let read = db.begin_read()?;
+        let changelog_table = read.open_table(CHANGELOG_TABLE)?;
+        let latest_seq = changelog_table
+            .last()?
+            .map(|(seq, _)| seq.value())
+            .unwrap_or(0);
+        let mut package_names = BTreeSet::new();
+        if let Some(previous) = &previous {
+            if previous.latest_seq >= latest_seq {
+                return Ok((previous.clone(), 0));
+            }
+            for entry in changelog_table.range(previous.latest_seq + 1..)? {
+                package_names.insert(entry?.1.value().package_name);
+            }
+        } else {
+            let package_name_table = read.open_table(PACKAGE_NAME_TABLE)?;
+            for entry in package_name_table.iter()? {
+                package_names.insert(entry?.0.value().to_string());
+            }
+        }
+        (package_names, latest_seq)
+    };
+
+    for package_name in &package_names {
+        let Some(index_package) = render_package(db, package_name)? else {
+            log::warn!("package {package_name} is in the changelog but does not exist");
+            continue;
+        };
+        write_atomic(
+            &dir.join(index_path(package_name)),
+            &serde_json::to_vec(&index_package)?,
+        )?;
+    }
+
+    let config = IndexConfig {
+        latest_seq,
+        generated_at: timestamp(),
+    };
+    write_atomic(&dir.join(CONFIG_FILE), &serde_json::to_vec(&config)?)?;
+    Ok((config, package_names.len()))
+}
+
+/// Build the index entry for a package.
+pub fn render_package(db: &Arc<Db>, package_name: &str) -> Result<Option<IndexPackage>> {
+    let Some((package, mut versions)) = PackageModel::versions(&db.begin_read()?, package_name)?
+    else {
+        return Ok(None);
+    };
+    // timestamps have second resolution, fall back to the name for versions published together
+    versions.sort_by(|a, b| (a.created_at, &a.name).cmp(&(b.created_at, &b.name)));
+    let read = db.begin_read()?;
+    let yanked_version_table = read.open_table(YANKED_VERSION_TABLE)?;
+    let mut index_versions = vec![];
+    for v in versions {
+        index_versions.push(IndexVersion {
+            yanked: yanked_version_table.get(&v.id)?.is_some(),
+            name: v.name,
+            id: v.id,
+            published_at: v.created_at,
+            static_content: v.static_content,
+        });
+    }
+    Ok(Some(IndexPackage {
+        name: package.name,
+        deprecated: package.metadata.deprecated,
+        versions: index_versions,
+    }))
+}
+
+/// Rewrite the index file of a package in `dir` outside of an export, e.g. after a version
+/// is yanked. The file is removed if the package no longer exists.
+pub fn refresh_package(db: &Arc<Db>, dir: &Path, package_name: &str) -> Result<()> {
+    let _guard = EXPORT_LOCK.lock().unwrap_or_else(|e| e.into_inner());
+    let path = dir.join(index_path(package_name));
+    match render_package(db, package_name)? {
+        Some(index_package) => write_atomic(&path, &serde_json::to_vec(&index_package)?),
+        None => match std::fs::remove_file(&path) {
+            Err(e) if e.kind() != std::io::ErrorKind::NotFound => Err(e.into()),
+            _ => Ok(()),
+        },
+    }
+}
+
+/// Write through a temporary file so readers never see a partial file.
+fn write_atomic(path: &Path, contents: &[u8]) -> Result<()> {
+    if let Some(parent) = path.parent() {
+        std::fs::create_dir_all(parent)?;
+    }
+    let tmp_path = path.with_extension("tmp");
+    std::fs::write(&tmp_path, contents)?;
+    std::fs::rename(tmp_path, path)?;
+    Ok(())
+}
+
+/// Serve a package's index file at the same path it has in the static index, so clients
+/// can resolve versions with a small cacheable request. Responses carry an `ETag` and
+/// `If-None-Match` is answered with 304 Not Modified.
+pub async fn index_file(
+    State(state): State<OnyxState>,
+    UrlPath(path): UrlPath<String>,
+    request_headers: HeaderMap,
+) -> Result<Response, OnyxError> {
+    let package_name = path.rsplit('/').next().unwrap_or_default();
+    if package_name.is_empty() || index_path(package_name) != path {
+        return Err(OnyxError::not_found(&format!(
+            "No index file at \"{path}\""
+        )));
+    }
+    let package = render_package(&state.db, package_name)?.ok_or(OnyxError::not_found(
+        &format!("Unable to find package \"{package_name}\""),
+    ))?;
+    let body = serde_json::to_vec(&package).map_err(anyhow::Error::from)?;
+    let etag = format!("\"{}\"", blake3::hash(&body));
+
+    let mut headers = HeaderMap::new();
+    headers.insert(
+        header::ETAG,
+        etag.parse().map_err(|_| OnyxError::default())?,
+    );
+    headers.insert(
+        header::CACHE_CONTROL,
+        format!("public, max-age={INDEX_MAX_AGE}")
+            .parse()
+            .map_err(|_| OnyxError::default())?,
+    );
+    let not_modified = request_headers
+        .get(header::IF_NONE_MATCH)
+        .and_then(|v| v.to_str().ok())
+        .is_some_and(|v| {
+            v.split(',')
+                .any(|tag| tag.trim() == etag || tag.trim() == "*")
+        });
+    if not_modified {
+        return Ok((StatusCode::NOT_MODIFIED, headers).into_response());
+    }
+    headers.insert(
+        header::CONTENT_TYPE,
+        "application/json"
+            .parse()
+            .map_err(|_| OnyxError::default())?,
+    );
+    Ok((headers, body).into_response())
+}
+
+pub async fn admin_export_index(
+    State(state): State<OnyxState>,
+    _admin: AdminSession,
+) -> Result<ResponseJson<IndexExportResponse>, OnyxError> {
+    let Some(dir) = state.config.index_path.clone() else {
+        return Err(OnyxError::bad_request(
+            "Static index export is not configured, set ONYX_INDEX_PATH",
+        ));
+    };
+    let (config, packages_written) =
+        tokio::task::spawn_blocking(move || export(&state.db, &dir, true))
+            .await
+            .map_err(|e| anyhow::anyhow!(e))??;
+    Ok(ResponseJson(IndexExportResponse {
+        latest_seq: config.latest_seq,
+        packages_written,
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::testing::OnyxTest;
+    use anyhow::Result;
+    use nanoid::nanoid;
+    use onyx_api::prelude::*;
+    use tempfile::TempDir;
+
+    use super::*;
+
+    #[test]
+    fn should_bucket_index_paths() {
+        assert_eq!(index_path("a"), "1/a");
+        assert_eq!(index_path("ab"), "2/ab");
+        assert_eq!(index_path("Abc"), "3/a/Abc");
+        assert_eq!(index_path("Poseidon"), "po/se/Poseidon");
+    }
+
+    #[tokio::test]
+    async fn should_export_index() -> Result<()> {
+        let index_dir = TempDir::new()?;
+        let dir = index_dir.path().to_path_buf();
+        let test = OnyxTest::with_config(|state| {
+            let config = Arc::make_mut(&mut state.config);
+            config.index_path = Some(dir);
+            config.admins = ["admin".to_string()].into_iter().collect();
+        })
+        .await?;
+        let (login, _password) = test.signup(None).await?;
+        let name = nanoid!();
+        let publish = async |version: &str| -> Result<HashId> {
+            let tarball = OnyxTest::create_test_tarball_named(None, Some(&name), Some(version))?;
+            let id = HashId::from(tarball.1);
+            test.publish(
+                Some(PublishData::new(tarball.1.to_string(), login.token.clone())),
+                tarball,
+            )
+            .await?;
+            Ok(id)
+        };
+        let first_id = publish("0.1.0").await?;
+
+        let (config, written) = export(&test.state.db, index_dir.path(), false)?;
+        assert_eq!(config.latest_seq, 1);
+        assert_eq!(written, 1);
+        // nothing published since the last export
+        assert_eq!(export(&test.state.db, index_dir.path(), false)?.1, 0);
+
+        let second_id = publish("0.2.0").await?;
+        assert_eq!(export(&test.state.db, index_dir.path(), false)?.1, 1);
+        let package: IndexPackage = serde_json::from_str(&std::fs::read_to_string(
+            index_dir.path().join(index_path(&name)),
+        )?)?;
+        assert_eq!(package.name, name);
+        assert_eq!(
+            package
+                .versions
+                .iter()
+                .map(|v| (v.name.as_str(), v.id.clone()))
+                .collect::<Vec<_>>(),
+            vec![("0.1.0", first_id), ("0.2.0", second_id)]
+        );
+
+        // full rebuilds are admin only
+        assert!(test.api.admin_export_index(&login.token).await.is_err());
+        let (admin, _password) = test
+            .signup(Some(LoginRequest {
+                username: "admin".to_string(),
+                password: nanoid!(),
+                ..Default::default()
+            }))
+            .await?;
+        let response = test.api.admin_export_index(&admin.token).await?;
+        assert_eq!(response.latest_seq, 2);
+        assert_eq!(response.packages_written, 1);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn should_serve_index_file() -> Result<()> {
+        let test = OnyxTest::new().await?;
+        let (login, _password) = test.signup(None).await?;
+        let name = nanoid!();
+        let tarball = OnyxTest::create_test_tarball_named(None, Some(&name), Some("0.1.0"))?;
+        let version_id = HashId::from(tarball.1);
+        test.publish(
+            Some(PublishData::new(tarball.1.to_string(), login.token.clone())),
+            tarball,
+        )
+        .await?;
+
+        let IndexFetch::Fetched { package, etag } = test.api.index_package(&name, None).await?
+        else {
+            panic!("expected index file");
+        };
+        assert_eq!(package.versions.len(), 1);
+        assert_eq!(package.versions[0].id, version_id);
+        let etag = etag.expect("index file should have an etag");
+        assert!(matches!(
+            test.api.index_package(&name, Some(&etag)).await?,
+            IndexFetch::NotModified
+        ));
+
+        // a package is only served at its own path
+        let response = reqwest::get(format!("{}/v0/index/zz/zz/{name}", test.url)).await?;
+        assert_eq!(response.status(), reqwest::StatusCode::NOT_FOUND);
+        assert!(test.api.index_package(&nanoid!(), None).await.is_err());
+        Ok(())
+    }
+}
@@ -0,0 +1,153 @@
+use std::time::Duration;
+
+use anyhow::Result;
+
+use super::OnyxState;
+use super::build;
+use super::db;
+use super::index;
+use super::mirror;
+use super::publish;
+use super::scan;
+use super::session;
+use super::snapshot;
+use super::watch;
+use super::webhook;
+
+/// A maintenance task run periodically alongside the http server.
+pub struct Job {
+    pub name: &'static str,
+    pub interval: Duration,
+    pub run: fn(&OnyxState) -> Result<()>,
+}
+
+pub const JOBS: &[Job] = &[
+    Job {
+        name: "purge_expired_sessions",
+        interval: Duration::from_secs(10 * 60),
+        run: |state| {
+            let purged = session::purge_expired_sessions(state)?;
+            if purged > 0 {
+                log::info!("Purged {purged} expired auth tokens");
+            }
+            Ok(())
+        },
+    },
+    Job {
+        name: "purge_expired_idempotency_keys",
+        interval: Duration::from_secs(60 * 60),
+        run: |state| {
+            let purged = publish::purge_expired_idempotency_keys(state)?;
+            if purged > 0 {
+                log::info!("Purged {purged} expired idempotency keys");
+            }
+            Ok(())
+        },
+    },
+    Job {
+        name: "mirror_upstream",
+        interval: Duration::from_secs(60),
+        run: |state| {
+            let mirrored = tokio::runtime::Handle::current().block_on(mirror::sync(state))?;
+            if mirrored > 0 {
+                log::info!("Mirrored {mirrored} versions from upstream");
+            }
+            Ok(())
+        },
+    },
+    Job {
+        name: "export_index",
+        interval: Duration::from_secs(15),
+        run: |state| {
+            let Some(dir) = &state.config.index_path else {
+                return Ok(());
+            };
+            let (config, written) = index::export(&state.db, dir, false)?;
+            if written > 0 {
+                log::info!(
+                    "Exported {written} packages to the static index at seq {}",
+                    config.latest_seq
+                );
+            }
+            Ok(())
+        },
+    },
+    Job {
+        name: "sign_snapshot",
+        interval: Duration::from_secs(30),
+        run: |state| {
+            if let Some(snapshot) = snapshot::refresh(state)? {
+                log::info!("Signed snapshot {}", snapshot.id);
+            }
+            Ok(())
+        },
+    },
+    Job {
+        name: "scan_uploads",
+        interval: Duration::from_secs(15),
+        run: |state| {
+            let scanned = tokio::runtime::Handle::current().block_on(scan::run_queue(state))?;
+            if scanned > 0 {
+                log::info!("Scanned {scanned} uploads");
+            }
+            Ok(())
+        },
+    },
+    Job {
+        name: "verify_builds",
+        interval: Duration::from_secs(30),
+        run: |state| {
+            let built = build::run_queue(state)?;
+            if built > 0 {
+                log::info!("Rebuilt {built} versions");
+            }
+            Ok(())
+        },
+    },
+    Job {
+        name: "deliver_notifications",
+        interval: Duration::from_secs(15),
+        run: |state| {
+            let delivered = tokio::runtime::Handle::current().block_on(webhook::deliver(state))?;
+            if delivered > 0 {
+                log::info!("Delivered {delivered} notifications");
+            }
+            Ok(())
+        },
+    },
+    Job {
+        name: "send_digests",
+        interval: Duration::from_secs(60 * 60),
+        run: |state| {
+            let sent = tokio::runtime::Handle::current().block_on(watch::send_digests(state))?;
+            if sent > 0 {
+                log::info!("Sent {sent} digests");
+            }
+            Ok(())
+        },
+    },
+];
+
+/// Start running each job on its interval. Jobs run on the blocking thread pool because
+/// database access is synchronous.
+pub fn spawn(state: OnyxState) {
+    for job in JOBS {
+        let state = state.clone();
+        tokio::spawn(async move {
+            let mut interval = tokio::time::interval(job.interval);
+            loop {
+                interval.tick().await;
+                let state = state.clone();
+                let run = job.run;
+                let route = format!("job {}", job.name);
+                match tokio::task::spawn_blocking(move || db::with_route(route, || run(&state)))
+                    .await
+                {
+                    Ok(Ok(())) => {}
+                    Ok(Err(e)) => log::error!("Job {} failed: {e:?}", job.name),
+                    Err(e) => log::error!("Job {} panicked: {e:?}", job.name),
+                }
+            }
+        });
+    }
+}
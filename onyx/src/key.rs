@@ -0,0 +1,283 @@
+use std::collections::HashMap;
+
+use axum::extract::Path;
+use axum::extract::State;
+use axum::response::Json as ResponseJson;
+use redb::ReadableTable;
+
+use onyx_api::prelude::*;
+
+use super::OnyxError;
+use super::OnyxState;
+use super::db::WriteTxn;
+use super::session::AuthSession;
+use super::snapshot::verify_signature;
+use super::validate::ValidJson;
+
+fn user_key(write: &WriteTxn, public_key: &str) -> Result<UserKeyModel, OnyxError> {
+    let user_key_table = write.open_table(USER_KEY_TABLE)?;
+    match user_key_table.get(public_key)? {
+        Some(key) => Ok(key.value()),
+        None => Err(OnyxError::not_found("Key not found")),
+    }
+}
+
+/// Add a signing key to the authenticated user. The key signs `key_message` to prove the user
+/// holds it. When rotating, the replaced key signs the same message so anyone walking the
+/// lineage can check each key was handed off by the one before it.
+pub async fn add_key(
+    State(state): State<OnyxState>,
+    session: AuthSession,
+    ValidJson(payload): ValidJson<AddKeyRequest>,
+) -> Result<ResponseJson<UserKeyModel>, OnyxError> {
+    let write = state.db.begin_write()?;
+    let user = {
+        let user_table = write.open_table(USER_TABLE)?;
+        match user_table.get(session.user_id.as_str())? {
+            Some(user) => user.value(),
+            None => return Err(OnyxError::not_found("User not found")),
+        }
+    };
+    let message = key_message(&user.username, &payload.public_key);
+    if !verify_signature(&payload.public_key, message.as_bytes(), &payload.signature) {
+        return Err(OnyxError::bad_request(
+            "signature is not a signature of the key message by public_key",
+        ));
+    }
+    {
+        let user_key_table = write.open_table(USER_KEY_TABLE)?;
+        if user_key_table.get(payload.public_key.as_str())?.is_some() {
+            return Err(OnyxError::conflict("Key is already registered"));
+        }
+    }
+
+    let now = timestamp();
+    if let (Some(replaces), Some(replaces_signature)) =
+        (&payload.replaces, &payload.replaces_signature)
+    {
+        let mut replaced = user_key(&write, replaces)?;
+        if replaced.user_id != user.id {
+            return Err(OnyxError::new(
+                OnyxErrorCode::Forbidden,
+                "Only the owner of a key may rotate it",
+            ));
+        }
+        if !replaced.is_active() {
+            return Err(OnyxError::bad_request("Only an active key may be rotated"));
+        }
+        if !verify_signature(replaces, message.as_bytes(), replaces_signature) {
+            return Err(OnyxError::bad_request(
+                "replaces_signature is not a signature of the key message by the replaced key",
+            ));
+        }
+        replaced.replaced_by = Some(payload.public_key.clone());
+        replaced.replaced_at = Some(now);
+        let mut user_key_table = write.open_table(USER_KEY_TABLE)?;
+        user_key_table.insert(replaced.public_key.as_str(), replaced.clone())?;
+    }
+
+    let key = UserKeyModel {
+        public_key: payload.public_key,
+        user_id: user.id,
+        username: user.username,
+        created_at: now,
+        replaces: payload.replaces,
+        replaced_by: None,
+        replaced_at: None,
+        revoked_at: None,
+    };
+    {
+        let mut user_key_table = write.open_table(USER_KEY_TABLE)?;
+        let mut user_keys_table = write.open_multimap_table(USER_KEYS_TABLE)?;
+        user_key_table.insert(key.public_key.as_str(), key.clone())?;
+        user_keys_table.insert(key.user_id.as_str(), key.public_key.as_str())?;
+    }
+    write.commit()?;
+    Ok(ResponseJson(key))
+}
+
+/// Revoke a key of the authenticated user, e.g. because it was compromised. Unlike a rotated
+/// key, nothing signed by a revoked key should be trusted.
+pub async fn revoke_key(
+    State(state): State<OnyxState>,
+    session: AuthSession,
+    Path(public_key): Path<String>,
+) -> Result<ResponseJson<UserKeyModel>, OnyxError> {
+    let write = state.db.begin_write()?;
+    let mut key = user_key(&write, &public_key)?;
+    if key.user_id != session.user_id {
+        return Err(OnyxError::new(
+            OnyxErrorCode::Forbidden,
+            "Only the owner of a key may revoke it",
+        ));
+    }
+    if key.revoked_at.is_some() {
+        return Err(OnyxError::conflict("Key is already revoked"));
+    }
+    key.revoked_at = Some(timestamp());
+    {
+        let mut user_key_table = write.open_table(USER_KEY_TABLE)?;
+        user_key_table.insert(key.public_key.as_str(), key.clone())?;
+    }
+    write.commit()?;
+    Ok(ResponseJson(key))
+}
+
+/// Every key a user has added, oldest first. Rotated and revoked keys are included so
+/// signatures made with them can still be checked.
+pub async fn user_keys(
+    State(state): State<OnyxState>,
+    Path(username): Path<String>,
+) -> Result<ResponseJson<Vec<UserKeyModel>>, OnyxError> {
+    let read = state.db.begin_read()?;
+    let username_table = read.open_table(USERNAME_USER_ID_TABLE)?;
+    let Some(user_id) = username_table.get(username.as_str())? else {
+        return Err(OnyxError::not_found("User not found"));
+    };
+    let user_key_table = read.open_table(USER_KEY_TABLE)?;
+    let user_keys_table = read.open_multimap_table(USER_KEYS_TABLE)?;
+    let mut keys = vec![];
+    for public_key in user_keys_table.get(user_id.value())? {
+        if let Some(key) = user_key_table.get(public_key?.value())? {
+            keys.push(key.value());
+        }
+    }
+    // timestamps are in seconds, so rotations in the same second are ordered by lineage
+    let replaces = keys
+        .iter()
+        .filter_map(|key| Some((key.public_key.clone(), key.replaces.clone()?)))
+        .collect::<HashMap<_, _>>();
+    keys.sort_by_cached_key(|key| {
+        let mut depth = 0;
+        let mut public_key = &key.public_key;
+        while let Some(replaced) = replaces.get(public_key) {
+            depth += 1;
+            public_key = replaced;
+        }
+        (key.created_at, depth)
+    });
+    Ok(ResponseJson(keys))
+}
+
+#[cfg(test)]
+mod tests {
+    use anyhow::Result;
+    use onyx_api::prelude::*;
+    use ring::rand::SystemRandom;
+    use ring::signature::Ed25519KeyPair;
+    use ring::signature::KeyPair;
+
+    use crate::testing::OnyxTest;
+
+    fn keypair() -> Result<Ed25519KeyPair> {
+        let pkcs8 = Ed25519KeyPair::generate_pkcs8(&SystemRandom::new())
+            .map_err(|_| anyhow::anyhow!("Failed to generate key"))?;
+        Ed25519KeyPair::from_pkcs8(pkcs8.as_ref())
+            .map_err(|_| anyhow::anyhow!("Failed to parse key"))
+    }
+
+    fn public_key(key: &Ed25519KeyPair) -> String {
+        hex::encode(key.public_key().as_ref())
+    }
+
+    fn sign(key: &Ed25519KeyPair, username: &str, public_key: &str) -> String {
+        hex::encode(
+            key.sign(key_message(username, public_key).as_bytes())
+                .as_ref(),
+        )
+    }
+
+    fn add_request(key: &Ed25519KeyPair, username: &str) -> AddKeyRequest {
+        AddKeyRequest {
+            public_key: public_key(key),
+            signature: sign(key, username, &public_key(key)),
+            replaces: None,
+            replaces_signature: None,
+        }
+    }
+
+    #[tokio::test]
+    async fn should_rotate_and_revoke_keys() -> Result<()> {
+        let test = OnyxTest::new().await?;
+        let (login, _password) = test.signup(None).await?;
+        let username = login.user.username.clone();
+
+        let first = keypair()?;
+        let added = test
+            .api
+            .add_key(&login.token, add_request(&first, &username))
+            .await?;
+        assert!(added.is_active());
+        assert_eq!(added.username, username);
+
+        let second = keypair()?;
+        let mut request = add_request(&second, &username);
+        request.replaces = Some(public_key(&first));
+        request.replaces_signature = Some(sign(&first, &username, &public_key(&second)));
+        let rotated = test.api.add_key(&login.token, request).await?;
+        assert_eq!(rotated.replaces, Some(public_key(&first)));
+
+        // a rotated key can't be rotated again
+        let third = keypair()?;
+        let mut request = add_request(&third, &username);
+        request.replaces = Some(public_key(&first));
+        request.replaces_signature = Some(sign(&first, &username, &public_key(&third)));
+        let err = test.api.add_key(&login.token, request).await.unwrap_err();
+        let err = err.downcast_ref::<ApiError>().unwrap();
+        assert_eq!(err.code, OnyxErrorCode::BadRequest);
+
+        let revoked = test
+            .api
+            .revoke_key(&login.token, &public_key(&second))
+            .await?;
+        assert!(revoked.revoked_at.is_some());
+
+        let keys = test.api.user_keys(&username).await?;
+        assert_eq!(keys.len(), 2);
+        assert_eq!(keys[0].public_key, public_key(&first));
+        assert_eq!(keys[0].replaced_by, Some(public_key(&second)));
+        assert!(keys[0].revoked_at.is_none());
+        assert_eq!(keys[1], revoked);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn should_require_proof_of_possession() -> Result<()> {
+        let test = OnyxTest::new().await?;
+        let (login, _password) = test.signup(None).await?;
+        let (other, _password) = test.signup(None).await?;
+        let key = keypair()?;
+
+        // signed for another user
+        let request = add_request(&key, &other.user.username);
+        let err = test.api.add_key(&login.token, request).await.unwrap_err();
+        let err = err.downcast_ref::<ApiError>().unwrap();
+        assert_eq!(err.code, OnyxErrorCode::BadRequest);
+
+        let mut request = add_request(&key, &login.user.username);
+        request.replaces = Some(public_key(&keypair()?));
+        let err = test.api.add_key(&login.token, request).await.unwrap_err();
+        let err = err.downcast_ref::<ApiError>().unwrap();
+        assert_eq!(err.code, OnyxErrorCode::ValidationFailed);
+
+        test.api
+            .add_key(&login.token, add_request(&key, &login.user.username))
+            .await?;
+        let err = test
+            .api
+            .add_key(&other.token, add_request(&key, &other.user.username))
+            .await
+            .unwrap_err();
+        let err = err.downcast_ref::<ApiError>().unwrap();
+        assert_eq!(err.code, OnyxErrorCode::Conflict);
+
+        let err = test
+            .api
+            .revoke_key(&other.token, &public_key(&key))
+            .await
+            .unwrap_err();
+        let err = err.downcast_ref::<ApiError>().unwrap();
+        assert_eq!(err.code, OnyxErrorCode::Forbidden);
+        Ok(())
+    }
+}
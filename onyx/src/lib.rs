@@ -0,0 +1,372 @@
+use std::net::SocketAddr;
+use std::sync::Arc;
+
+use anyhow::Result;
+use axum::Router;
+use axum::extract::DefaultBodyLimit;
+use axum::routing::delete;
+use axum::routing::get;
+use axum::routing::patch;
+use axum::routing::post;
+use axum::routing::put;
+
+use onyx_api::prelude::*;
+
+use cdn::CdnConfig;
+use db::Db;
+use maintenance::for_each_table;
+use snapshot::SnapshotSigner;
+use webhook::WebhookClient;
+
+mod artifact;
+mod audit;
+mod auth;
+mod build;
+mod cdn;
+mod changelog;
+mod config;
+mod cors;
+mod db;
+mod delta;
+mod dependency;
+mod diff;
+mod docs;
+mod download;
+mod error;
+mod git;
+mod import;
+mod index;
+mod jobs;
+mod key;
+mod limits;
+mod list_packages;
+mod maintenance;
+mod manifest;
+mod meta;
+mod metadata;
+mod mirror;
+mod moderation;
+mod openapi;
+mod page;
+mod password;
+mod proxy;
+mod publish;
+mod quota;
+mod read_cache;
+mod release;
+mod release_notes;
+mod scan;
+mod search;
+mod session;
+mod snapshot;
+mod stats;
+#[cfg(any(test, feature = "testing"))]
+pub mod testing;
+mod transfer;
+mod transparency;
+mod typosquat;
+mod user;
+mod validate;
+mod watch;
+mod web;
+mod webhook;
+
+pub use config::Config;
+pub use error::OnyxError;
+pub use maintenance::MaintenanceOptions;
+pub use maintenance::run as maintain;
+pub use snapshot::keygen;
+pub use snapshot::sign_root;
+
+#[derive(Clone)]
+struct OnyxState {
+    pub db: Arc<Db>,
+    pub storage: OnyxStorage,
+    pub config: Arc<Config>,
+    /// Redirect downloads to signed CDN urls instead of streaming them.
+    pub cdn: Option<CdnConfig>,
+    /// Sign snapshots of the registry, see `snapshot::refresh`.
+    pub snapshots: Option<SnapshotSigner>,
+    /// Scan uploads before publishing them, see `scan::run_queue`.
+    pub scanners: Vec<Arc<dyn scan::Scanner>>,
+    /// Sends webhook requests, see `WebhookClient`.
+    pub webhooks: WebhookClient,
+}
+
+impl OnyxState {
+    /// Open the database and storage of `config`, creating what's missing.
+    fn open(config: Config) -> Result<Self> {
+        config.create_data_dir()?;
+        let db = Arc::new(Db::open(&config)?);
+        create_tables(&db)?;
+        changelog::backfill(&db)?;
+        transparency::backfill(&db)?;
+        Ok(Self {
+            db,
+            storage: OnyxStorage::new(config.data_path(&config.storage_path))?,
+            cdn: CdnConfig::from_config(&config)?,
+            snapshots: SnapshotSigner::from_config(&config)?,
+            scanners: scan::scanners(&config),
+            webhooks: WebhookClient::from_config(&config)?,
+            config: Arc::new(config),
+        })
+    }
+}
+
+/// Run the registry with `config`.
+pub async fn serve(config: Config) -> Result<()> {
+    let state = OnyxState::open(config)?;
+    for (username, password) in auth::bootstrap_admins(&state.db, &state.config)? {
+        if state.config.admin_password.is_some() {
+            log::info!("Created admin account \"{username}\"");
+        } else {
+            log::warn!(
+                "Created admin account \"{username}\" with password \"{password}\", change it after logging in"
+            );
+        }
+    }
+    let backfilled = dependency::backfill(&state).await?;
+    if backfilled > 0 {
+        log::info!("Recorded dependencies of {backfilled} packages");
+    }
+    jobs::spawn(state.clone());
+    let port = state.config.port;
+    let app = build_server(state);
+    let listener = tokio::net::TcpListener::bind(format!("0.0.0.0:{port}")).await?;
+    log::info!("Listening on port {port}");
+    axum::serve(
+        listener,
+        app.into_make_service_with_connect_info::<SocketAddr>(),
+    )
+    .await?;
+    Ok(())
+}
+
+/// Import the repositories in `request` into the registry of `config` while it isn't running,
+/// see `import::import`.
+pub async fn import(config: Config, request: ImportRequest) -> Result<ImportResponse> {
+    let state = OnyxState::open(config)?;
+    import::import(&state, &request, None)
+        .await
+        .map_err(|e| anyhow::anyhow!("{e}"))
+}
+
+fn create_tables(db: &Db) -> Result<()> {
+    let write = db.begin_write()?;
+    macro_rules! table {
+        ($table:ident) => {
+            write.open_table($table)?;
+        };
+    }
+    macro_rules! multimap_table {
+        ($table:ident) => {
+            write.open_multimap_table($table)?;
+        };
+    }
+    for_each_table!(table, multimap_table);
+    write.commit()?;
+    Ok(())
+}
+
+fn build_server(state: OnyxState) -> axum::Router {
+    let max_upload_size = state.config.max_upload_size;
+    let max_request_size = state.config.max_request_size;
+    let cors = cors::cors_layer(&state.config);
+    Router::new()
+        .route("/", get(root))
+        // package names can't contain '.' so these don't shadow the git routes below
+        .route("/openapi.json", get(openapi::openapi_json))
+        .route("/openapi.html", get(openapi::swagger_ui))
+        .route("/sw.js", get(web::service_worker))
+        .route("/v0/packages", get(list_packages::list_packages))
+        .route("/v0/packages/batch", post(list_packages::batch_packages))
+        .route("/v0/search", get(search::search))
+        .route(
+            "/v0/publish",
+            post(publish::publish).layer(DefaultBodyLimit::max(max_upload_size)),
+        )
+        .route("/v0/publish/{id}", get(scan::publish_status))
+        .route("/v0/signup", post(auth::signup))
+        .route("/v0/login", post(auth::login))
+        .route("/v0/auth", post(user::current_auth))
+        .route("/v0/propose_token", post(user::propose_token))
+        .route(
+            "/v0/user",
+            patch(user::update_user).delete(user::delete_user),
+        )
+        .route("/v0/sessions", get(session::list_sessions))
+        .route(
+            "/v0/sessions/{token_prefix}",
+            delete(session::revoke_session),
+        )
+        .route(
+            "/v0/webhook",
+            get(webhook::webhook)
+                .put(webhook::set_webhook)
+                .delete(webhook::delete_webhook),
+        )
+        .route("/v0/keys", post(key::add_key))
+        .route("/v0/keys/{public_key}", delete(key::revoke_key))
+        .route("/v0/users/{username}/keys", get(key::user_keys))
+        .route("/v0/version/{id}", get(download::download_package))
+        .route("/v0/version/{id}/docs", get(docs::version_docs))
+        .route("/v0/version/{id}/manifest", get(manifest::version_manifest))
+        .route(
+            "/v0/version/{id}/release_notes",
+            get(release_notes::version_release_notes),
+        )
+        .route(
+            "/v0/version/{id}/artifacts",
+            get(artifact::version_artifacts)
+                .post(artifact::upload_artifact)
+                .layer(DefaultBodyLimit::max(max_upload_size)),
+        )
+        .route(
+            "/v0/version/{id}/artifacts/{name}",
+            get(artifact::download_artifact),
+        )
+        .route("/v0/version/{id}/build", get(build::version_build))
+        .route("/v0/version/{id}/delta", get(delta::version_delta))
+        .route(
+            "/v0/packages/{package_name}",
+            patch(metadata::update_package),
+        )
+        .route(
+            "/v0/packages/{package_name}/latest",
+            get(list_packages::load_package_version),
+        )
+        .route(
+            "/v0/packages/{package_name}/versions",
+            get(list_packages::load_package_versions),
+        )
+        .route(
+            "/v0/packages/{package_name}/{version_name}/download",
+            get(download::download_package_version),
+        )
+        .route(
+            "/v0/packages/{package_name}/graph",
+            get(dependency::package_graph),
+        )
+        .route(
+            "/v0/packages/{package_name}/graph/tree",
+            get(dependency::dependency_tree),
+        )
+        .route("/v0/packages/{package_name}/diff", get(diff::package_diff))
+        .route(
+            "/v0/packages/{package_name}/transfer",
+            post(transfer::request_transfer).delete(transfer::cancel_transfer),
+        )
+        .route(
+            "/v0/packages/{package_name}/transfer/accept",
+            post(transfer::accept_transfer),
+        )
+        .route(
+            "/v0/packages/{package_name}/claim",
+            post(transfer::claim_package),
+        )
+        .route(
+            "/v0/packages/{package_name}/watch",
+            put(watch::watch_package).delete(watch::unwatch_package),
+        )
+        .route(
+            "/v0/packages/{package_name}/report",
+            post(moderation::report_package),
+        )
+        .route(
+            "/v0/packages/{package_name}/stats",
+            get(stats::package_stats),
+        )
+        .route("/v0/stats/installs", post(stats::report_installs))
+        .route("/v0/changelog", get(changelog::changelog))
+        .route("/v0/meta", get(meta::meta))
+        .route("/v0/cli/version", get(release::cli_version))
+        .route("/v0/root", get(snapshot::registry_root))
+        .route("/v0/root/{version}", get(snapshot::registry_root_version))
+        .route("/v0/snapshot", get(snapshot::snapshot))
+        .route("/v0/log", get(transparency::log_head))
+        .route("/v0/log/entries", get(transparency::log_entries))
+        .route("/v0/log/proof/{id}", get(transparency::inclusion_proof))
+        .route("/v0/log/consistency", get(transparency::consistency_proof))
+        .route("/v0/index/{*path}", get(index::index_file))
+        .route("/v0/notifications", get(watch::notifications))
+        .route(
+            "/v0/notifications/read",
+            post(watch::mark_notifications_read),
+        )
+        .route(
+            "/v0/notifications/digest",
+            put(watch::enable_digest).delete(watch::disable_digest),
+        )
+        .route("/v0/watches", get(watch::list_watches))
+        .route("/v0/transfers", get(transfer::list_transfers))
+        .route("/v0/usage", get(quota::usage))
+        .route("/v0/admin/claims", get(transfer::list_claims))
+        .route("/v0/admin/index", post(index::admin_export_index))
+        .route("/v0/admin/import", post(import::admin_import))
+        .route(
+            "/v0/admin/packages/{package_name}/transfer",
+            post(transfer::admin_transfer),
+        )
+        .route(
+            "/v0/admin/users/{username}/quota",
+            get(quota::admin_quota).put(quota::admin_set_quota),
+        )
+        .route("/v0/admin/reports", get(moderation::list_reports))
+        .route(
+            "/v0/admin/reports/{id}/resolve",
+            post(moderation::resolve_report),
+        )
+        .route("/v0/admin/names", get(typosquat::name_reviews))
+        .route(
+            "/v0/admin/names/{package_name}",
+            delete(typosquat::decline_name),
+        )
+        .route(
+            "/v0/admin/names/{package_name}/approve",
+            post(typosquat::approve_name),
+        )
+        .route("/v0/admin/publishes", get(scan::pending_publishes))
+        .route("/v0/admin/publishes/{id}", delete(scan::reject_publish))
+        .route(
+            "/v0/admin/publishes/{id}/release",
+            post(scan::release_publish),
+        )
+        .route("/v0/admin/audit", get(audit::audit_log))
+        .route("/v0/admin/db", get(maintenance::db_stats))
+        .route("/v0/admin/db/check", post(maintenance::db_check))
+        .route("/v0/admin/db/metrics", get(db::db_metrics))
+        // the package page for crawlers or the web app, otherwise the start of mocked retrieval
+        // for packages
+        .route("/{package_name}", get(page::package_page))
+        .route("/{package_name}/info/refs", get(git::mocked_refs))
+        .route(
+            "/{package_name}/git-upload-pack",
+            post(git::mocked_upload_pack),
+        )
+        // upstream repositories proxied at `/{host}/{org}/{repo}`, hosts contain a '.' so they
+        // aren't package names
+        .route(
+            "/{package_name}/{org}/{repo}/info/refs",
+            get(proxy::proxy_refs),
+        )
+        .route(
+            "/{package_name}/{org}/{repo}/git-upload-pack",
+            post(proxy::proxy_upload_pack),
+        )
+        .route_layer(axum::middleware::from_fn_with_state(
+            state.clone(),
+            limits::enforce_body,
+        ))
+        .route_layer(axum::middleware::from_fn(db::scope_route))
+        // publishes and artifacts have their own limit above
+        .layer(DefaultBodyLimit::max(max_request_size))
+        .fallback(web::static_file)
+        .with_state(state)
+        .layer(axum::middleware::map_response(meta::api_version_header))
+        .layer(cors)
+}
+
+async fn root() -> axum::response::Response {
+    use axum::response::IntoResponse;
+    web::index().unwrap_or_else(|| "Hello world!".into_response())
+}
@@ -0,0 +1,211 @@
+use axum::body::HttpBody;
+use axum::extract::MatchedPath;
+use axum::extract::Request;
+use axum::extract::State;
+use axum::http::HeaderMap;
+use axum::http::Method;
+use axum::http::StatusCode;
+use axum::http::header;
+use axum::middleware::Next;
+use axum::response::IntoResponse;
+use axum::response::Response;
+
+use onyx_api::prelude::*;
+
+use super::Config;
+use super::OnyxError;
+use super::OnyxState;
+
+const JSON: &str = "application/json";
+const MULTIPART: &str = "multipart/form-data";
+const GIT_UPLOAD_PACK: &str = "application/x-git-upload-pack-request";
+
+/// The content type and largest body `method` on the route `path` accepts. Publishes are
+/// multipart and git clients send upload-pack requests, every other body is json. Publishes
+/// and artifacts may be up to `max_upload_size`, anything else up to `max_request_size`.
+fn accepted(config: &Config, method: &Method, path: &str) -> (&'static str, usize) {
+    match path {
+        "/v0/publish" => (MULTIPART, config.max_upload_size),
+        "/v0/version/{id}/artifacts" if method == Method::POST => (JSON, config.max_upload_size),
+        _ if path.ends_with("/git-upload-pack") => (GIT_UPLOAD_PACK, config.max_request_size),
+        _ => (JSON, config.max_request_size),
+    }
+}
+
+/// The content type of a request without its parameters, e.g. `application/json` for
+/// `application/json; charset=utf-8`.
+fn content_type(headers: &HeaderMap) -> Option<&str> {
+    let value = headers.get(header::CONTENT_TYPE)?.to_str().ok()?;
+    Some(value.split(';').next().unwrap_or_default().trim())
+}
+
+fn too_large(path: &str, max_size: usize) -> Response {
+    OnyxError::new(
+        OnyxErrorCode::PayloadTooLarge,
+        &format!("{path} accepts bodies of at most {max_size} bytes"),
+    )
+    .into_response()
+}
+
+/// Refuse a request whose body is larger than its route accepts or of another content type,
+/// before the body is read. Bodies sent without a length are cut off at the same size as
+/// they're read, see `build_server`.
+pub async fn enforce_body(
+    State(state): State<OnyxState>,
+    path: MatchedPath,
+    request: Request,
+    next: Next,
+) -> Response {
+    let path = path.as_str();
+    let (expected_type, max_size) = accepted(&state.config, request.method(), path);
+    let size = request.body().size_hint();
+    if size.lower() > max_size as u64 {
+        return too_large(path, max_size);
+    }
+    // requests without a body may have any content type, or none
+    if size.upper() != Some(0) {
+        let found = content_type(request.headers());
+        if !found.is_some_and(|found| found.eq_ignore_ascii_case(expected_type)) {
+            return OnyxError::new(
+                OnyxErrorCode::UnsupportedMediaType,
+                &format!(
+                    "{path} expects a {expected_type} body, got {}",
+                    found.unwrap_or("a body without a content type")
+                ),
+            )
+            .into_response();
+        }
+    }
+    let response = next.run(request).await;
+    // json bodies that turn out too large are refused by `ValidJson`, others by axum in plain
+    // text
+    if response.status() == StatusCode::PAYLOAD_TOO_LARGE
+        && content_type(response.headers()) != Some(JSON)
+    {
+        return too_large(path, max_size);
+    }
+    response
+}
+
+#[cfg(test)]
+mod tests {
+    use anyhow::Result;
+    use onyx_api::prelude::*;
+    use reqwest::StatusCode;
+    use tokio::io::AsyncReadExt;
+    use tokio::io::AsyncWriteExt;
+    use tokio::net::TcpStream;
+
+    use crate::testing::OnyxTest;
+
+    async fn error(response: reqwest::Response) -> Result<(StatusCode, ApiError)> {
+        Ok((response.status(), response.json().await?))
+    }
+
+    /// Send `size` bytes to the git-upload-pack route in chunks, which reqwest can't do
+    /// without its stream feature.
+    async fn chunked_upload_pack(url: &str, size: usize) -> Result<(u16, ApiError)> {
+        let address = url.trim_start_matches("http://");
+        let mut stream = TcpStream::connect(address).await?;
+        stream
+            .write_all(
+                format!(
+                    "POST /some_package/git-upload-pack HTTP/1.1\r\nhost: {address}\r\n\
+                     content-type: application/x-git-upload-pack-request\r\n\
+                     transfer-encoding: chunked\r\nconnection: close\r\n\r\n"
+                )
+                .as_bytes(),
+            )
+            .await?;
+        let chunk = vec![b'0'; 64 * 1024];
+        for _ in 0..size / chunk.len() {
+            let mut bytes = format!("{:x}\r\n", chunk.len()).into_bytes();
+            bytes.extend(&chunk);
+            bytes.extend(b"\r\n");
+            // the server stops reading once the body is too large
+            if stream.write_all(&bytes).await.is_err() {
+                break;
+            }
+        }
+        let _ = stream.write_all(b"0\r\n\r\n").await;
+        let mut response = vec![];
+        stream.read_to_end(&mut response).await?;
+        let response = String::from_utf8_lossy(&response);
+        let status = response
+            .split(' ')
+            .nth(1)
+            .ok_or(anyhow::anyhow!("malformed response"))?
+            .parse()?;
+        let (_headers, body) = response
+            .split_once("\r\n\r\n")
+            .ok_or(anyhow::anyhow!("malformed response"))?;
+        Ok((status, serde_json::from_str(body)?))
+    }
+
+    #[tokio::test]
+    async fn should_enforce_body_limits() -> Result<()> {
+        let test = OnyxTest::new().await?;
+        let client = reqwest::Client::new();
+        let login_url = format!("{}/v0/login", test.url);
+
+        let (status, err) = error(
+            client
+                .post(&login_url)
+                .header("content-type", "application/json")
+                .body(vec![b' '; 2 * 1024 * 1024])
+                .send()
+                .await?,
+        )
+        .await?;
+        assert_eq!(status, StatusCode::PAYLOAD_TOO_LARGE);
+        assert_eq!(err.code, OnyxErrorCode::PayloadTooLarge);
+
+        let (status, err) = error(
+            client
+                .post(&login_url)
+                .header("content-type", "text/plain")
+                .body("{}")
+                .send()
+                .await?,
+        )
+        .await?;
+        assert_eq!(status, StatusCode::UNSUPPORTED_MEDIA_TYPE);
+        assert_eq!(err.code, OnyxErrorCode::UnsupportedMediaType);
+
+        // git requests are held to their own content type
+        let (status, err) = error(
+            client
+                .post(format!("{}/some_package/git-upload-pack", test.url))
+                .header("content-type", "application/json")
+                .body("0000")
+                .send()
+                .await?,
+        )
+        .await?;
+        assert_eq!(status, StatusCode::UNSUPPORTED_MEDIA_TYPE);
+        assert_eq!(err.code, OnyxErrorCode::UnsupportedMediaType);
+
+        // bodies without a length are cut off as they're read
+        let (status, err) = chunked_upload_pack(&test.url, 2 * 1024 * 1024).await?;
+        assert_eq!(status, 413);
+        assert_eq!(err.code, OnyxErrorCode::PayloadTooLarge);
+
+        // a json content type with parameters and an empty body are fine
+        let response = client
+            .post(&login_url)
+            .header("content-type", "application/json; charset=utf-8")
+            .body("{}")
+            .send()
+            .await?;
+        assert_ne!(response.status(), StatusCode::UNSUPPORTED_MEDIA_TYPE);
+        let response = client
+            .post(format!(
+                "{}/v0/packages/some_package/transfer/accept",
+                test.url
+            ))
+            .send()
+            .await?;
+        assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+        Ok(())
+    }
+}
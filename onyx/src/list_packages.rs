@@ -6,6 +6,8 @@ use onyx_api::prelude::*;
 use redb::ReadableTable;
 
 use crate::VERSION_TABLE;
+use crate::index;
+use crate::validate::ValidJson;
 
 use super::OnyxError;
 use super::OnyxState;
@@ -15,10 +17,11 @@ pub async fn load_package_versions(
     State(state): State<OnyxState>,
     Path(package_name): Path<String>,
 ) -> Result<ResponseJson<(PackageModel, Vec<PackageVersionModel>)>, OnyxError> {
-    let (package, versions) =
-        PackageModel::versions(state.db, &package_name)?.ok_or(OnyxError::bad_request(
-            &format!("Unable to load versions for package \"{}\"", package_name),
-        ))?;
+    let (package, versions) = PackageModel::versions(&state.db.begin_read()?, &package_name)?
+        .ok_or(OnyxError::not_found(&format!(
+            "Unable to load versions for package \"{}\"",
+            package_name
+        )))?;
     Ok(ResponseJson((package, versions)))
 }
 
@@ -26,9 +29,14 @@ pub async fn load_package_version(
     State(state): State<OnyxState>,
     Path(package_name): Path<String>,
 ) -> Result<ResponseJson<(PackageModel, PackageVersionModel)>, OnyxError> {
-    let (package, version) = PackageModel::latest_version(state.db, &package_name)?.ok_or(
-        OnyxError::bad_request(&format!("Unable to resolve package \"{}\"", package_name)),
-    )?;
+    let (package, version) =
+        state
+            .db
+            .latest_version(&package_name)?
+            .ok_or(OnyxError::not_found(&format!(
+                "Unable to resolve package \"{}\"",
+                package_name
+            )))?;
     Ok(ResponseJson((package, version)))
 }
 
@@ -52,3 +60,112 @@ pub async fn list_packages(
     }
     Ok(ResponseJson(out))
 }
+
+/// Load the index entries of many packages at once, e.g. every registry dependency of a
+/// package being installed. Each package may ask for the newest version matching a semver
+/// requirement, yanked versions are never matched.
+pub async fn batch_packages(
+    State(state): State<OnyxState>,
+    ValidJson(payload): ValidJson<BatchPackagesRequest>,
+) -> Result<ResponseJson<BatchPackagesResponse>, OnyxError> {
+    let mut packages = vec![];
+    for query in payload.packages {
+        // requirements are checked while validating the request
+        let requirement = query
+            .requirement
+            .as_deref()
+            .and_then(|requirement| semver::VersionReq::parse(requirement).ok());
+        let package = index::render_package(&state.db, &query.name)?;
+        let matching = match (&package, requirement) {
+            (Some(package), Some(requirement)) => package
+                .versions
+                .iter()
+                .filter(|v| !v.yanked)
+                .filter_map(|v| Some((semver::Version::parse(&v.name).ok()?, v)))
+                .filter(|(version, _)| requirement.matches(version))
+                .max_by(|(a, _), (b, _)| a.cmp(b))
+                .map(|(_, v)| v.clone()),
+            _ => None,
+        };
+        packages.push(BatchPackage {
+            name: query.name,
+            package,
+            matching,
+        });
+    }
+    Ok(ResponseJson(BatchPackagesResponse { packages }))
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::testing::OnyxTest;
+    use anyhow::Result;
+    use nanoid::nanoid;
+    use onyx_api::prelude::*;
+
+    use crate::validate::MAX_BATCH_PACKAGES;
+
+    #[tokio::test]
+    async fn should_batch_packages() -> Result<()> {
+        let test = OnyxTest::new().await?;
+        let (login, _password) = test.signup(None).await?;
+        let name = nanoid!();
+        for version in ["0.1.0", "0.1.1", "0.2.0"] {
+            let tarball = OnyxTest::create_test_tarball_named(None, Some(&name), Some(version))?;
+            test.publish(
+                Some(PublishData::new(tarball.1.to_string(), login.token.clone())),
+                tarball,
+            )
+            .await?;
+        }
+        let missing = nanoid!();
+        let query = |name: &str, requirement: Option<&str>| BatchPackageQuery {
+            name: name.to_string(),
+            requirement: requirement.map(str::to_string),
+        };
+        let response = test
+            .api
+            .batch_packages(&BatchPackagesRequest {
+                packages: vec![
+                    query(&name, Some("^0.1.0")),
+                    query(&missing, Some("^1.0.0")),
+                    query(&name, None),
+                ],
+            })
+            .await?;
+        assert_eq!(response.packages.len(), 3);
+        let first = &response.packages[0];
+        assert_eq!(first.name, name);
+        assert_eq!(first.package.as_ref().map(|p| p.versions.len()), Some(3));
+        assert_eq!(
+            first.matching.as_ref().map(|v| v.name.as_str()),
+            Some("0.1.1")
+        );
+        assert_eq!(response.packages[1].name, missing);
+        assert!(response.packages[1].package.is_none());
+        assert!(response.packages[1].matching.is_none());
+        assert!(response.packages[2].package.is_some());
+        assert!(response.packages[2].matching.is_none());
+
+        // bad requirements and oversized batches are refused
+        assert!(
+            test.api
+                .batch_packages(&BatchPackagesRequest {
+                    packages: vec![query(&name, Some("not a requirement"))],
+                })
+                .await
+                .is_err()
+        );
+        assert!(
+            test.api
+                .batch_packages(&BatchPackagesRequest {
+                    packages: (0..=MAX_BATCH_PACKAGES)
+                        .map(|_| query(&name, None))
+                        .collect(),
+                })
+                .await
+                .is_err()
+        );
+        Ok(())
+    }
+}
@@ -1,111 +1,143 @@
 use std::path::PathBuf;
-use std::sync::Arc;
 
 use anyhow::Result;
-use axum::Router;
-use axum::extract::DefaultBodyLimit;
-use axum::routing::get;
-use axum::routing::post;
-use redb::Database;
-use tower_http::cors::Any;
-use tower_http::cors::CorsLayer;
 
-use onyx_api::prelude::*;
+const USAGE: &str = "Usage: onyx [--config <path>] [--data-dir <path>] [--print-config]
+            [--check-db] [--repair] [--compact]
+       onyx keygen <path>
+       onyx sign-root <root.json> --key <path>...
+       onyx import <repositories.toml> [--config <path>] [--data-dir <path>]
 
-mod auth;
-mod download;
-mod error;
-mod git;
-mod list_packages;
-mod publish;
-#[cfg(test)]
-mod tests;
-mod user;
+Options:
+  --config <path>    Read settings from this file instead of ./onyx.toml. Also ONYX_CONFIG
+  --data-dir <path>  Keep the database and package storage in this directory. Also
+                     ONYX_DATA_DIR
+  --print-config     Print the settings that would be used, with secrets hidden, and exit
+  --check-db         Verify the database and log rows that refer to missing rows or files
+                     before starting
+  --repair           Like --check-db, and fix what can be fixed without losing data
+  --compact          Reclaim the space of deleted rows before starting
 
-pub use error::OnyxError;
-
-// Max 20 MB upload size
-const MAX_UPLOAD_SIZE: usize = 20 * 1024 * 1024;
-const STORAGE_PATH: &'static str = "./package_data";
-
-#[derive(Clone)]
-struct OnyxState {
-    pub db: Arc<Database>,
-    pub storage: OnyxStorage,
-}
+Commands:
+  keygen      Write a new ed25519 signing key to <path> and print its public key
+  sign-root   Sign a registry root with each --key and print it, save it in the root_path
+              directory as <version>.root.json
+  import      Publish tags of git repositories as versions owned by a user, while the
+              registry isn't running. The file lists the username and each repository's
+              url and tags";
 
 #[tokio::main]
 async fn main() -> Result<()> {
     env_logger::init();
-
-    let db = Arc::new(Database::create("./db.redb")?);
-    create_tables(db.clone())?;
-
-    let app = build_server(OnyxState {
-        db,
-        storage: OnyxStorage::new(PathBuf::from(STORAGE_PATH))?,
-    });
-    let port = std::env::var("PORT").unwrap_or("3000".to_string());
-    let listener = tokio::net::TcpListener::bind(format!("0.0.0.0:{port}")).await?;
-    log::info!("Listening on port {port}");
-    axum::serve(listener, app).await?;
-    Ok(())
-}
-
-fn create_tables(db: Arc<redb::Database>) -> Result<()> {
-    let write = db.begin_write()?;
-
-    write.open_table(AUTH_TOKEN_TABLE)?;
-    write.open_table(USER_TABLE)?;
-    write.open_table(USERNAME_USER_ID_TABLE)?;
-    write.open_table(PACKAGE_TABLE)?;
-    write.open_table(PACKAGE_NAME_TABLE)?;
-    write.open_table(PACKAGE_VERSION_NAME_TABLE)?;
-    write.open_multimap_table(PACKAGE_VERSION_TABLE)?;
-    write.open_table(VERSION_TABLE)?;
-    write.open_table(GIT_REFS_TABLE)?;
-    write.open_table(GIT_PACK_TABLE)?;
-
-    write.commit()?;
-    Ok(())
-}
-
-fn build_server(state: OnyxState) -> axum::Router {
-    let cors = CorsLayer::new()
-        .allow_origin(Any)
-        .allow_methods(Any)
-        .allow_headers(Any);
-    Router::new()
-        .route("/", get(root))
-        .route("/v0/packages", get(list_packages::list_packages))
-        .route(
-            "/v0/publish",
-            post(publish::publish).layer(DefaultBodyLimit::max(MAX_UPLOAD_SIZE)),
-        )
-        .route("/v0/signup", post(auth::signup))
-        .route("/v0/login", post(auth::login))
-        .route("/v0/auth", post(user::current_auth))
-        .route("/v0/propose_token", post(user::propose_token))
-        .route("/v0/version/{id}", get(download::download_package))
-        .route(
-            "/v0/packages/{package_name}/latest",
-            get(list_packages::load_package_version),
-        )
-        .route(
-            "/v0/packages/{package_name}/versions",
-            get(list_packages::load_package_versions),
-        )
-        // mocked retrieval for packages
-        .route("/{package_name}", get(git::empty))
-        .route("/{package_name}/info/refs", get(git::mocked_refs))
-        .route(
-            "/{package_name}/git-upload-pack",
-            post(git::mocked_upload_pack),
-        )
-        .with_state(state)
-        .layer(cors)
-}
-
-async fn root() -> String {
-    format!("Hello world!")
+    let mut config_path = std::env::var("ONYX_CONFIG")
+        .ok()
+        .filter(|v| !v.is_empty())
+        .map(PathBuf::from);
+    let mut data_dir = None;
+    let mut print_config = false;
+    let mut maintenance = onyx::MaintenanceOptions::default();
+    let mut args = std::env::args().skip(1).peekable();
+    match args.peek().map(String::as_str) {
+        Some("keygen") => {
+            let path = args
+                .nth(1)
+                .ok_or(anyhow::anyhow!("keygen requires a path\n\n{USAGE}"))?;
+            println!("{}", onyx::keygen(&PathBuf::from(path))?);
+            return Ok(());
+        }
+        Some("sign-root") => {
+            let root_path = args
+                .nth(1)
+                .ok_or(anyhow::anyhow!("sign-root requires a root\n\n{USAGE}"))?;
+            let mut key_paths = vec![];
+            while let Some(arg) = args.next() {
+                match (arg.as_str(), args.next()) {
+                    ("--key", Some(path)) => key_paths.push(PathBuf::from(path)),
+                    _ => anyhow::bail!("unknown argument: {arg}\n\n{USAGE}"),
+                }
+            }
+            if key_paths.is_empty() {
+                anyhow::bail!("sign-root requires at least one --key\n\n{USAGE}");
+            }
+            let key_paths = key_paths.iter().map(PathBuf::as_path).collect::<Vec<_>>();
+            println!(
+                "{}",
+                onyx::sign_root(&PathBuf::from(root_path), &key_paths)?
+            );
+            return Ok(());
+        }
+        Some("import") => {
+            let path = args
+                .nth(1)
+                .ok_or(anyhow::anyhow!("import requires a file\n\n{USAGE}"))?;
+            let mut data_dir = None;
+            while let Some(arg) = args.next() {
+                match (arg.as_str(), args.next()) {
+                    ("--config", Some(path)) => config_path = Some(PathBuf::from(path)),
+                    ("--data-dir", Some(path)) => data_dir = Some(PathBuf::from(path)),
+                    _ => anyhow::bail!("unknown argument: {arg}\n\n{USAGE}"),
+                }
+            }
+            let request = toml::from_str(&std::fs::read_to_string(&path)?)?;
+            let mut config = onyx::Config::load(config_path.as_deref())?;
+            if data_dir.is_some() {
+                config.data_dir = data_dir;
+            }
+            let response = onyx::import(config, request).await?;
+            for result in response.results {
+                match (result.package_name, result.version_name, result.error) {
+                    (Some(package_name), Some(version_name), _) => println!(
+                        "{:?} {package_name}@{version_name} from {} at {}",
+                        result.status, result.url, result.tag
+                    ),
+                    (_, _, error) => println!(
+                        "{:?} {} at {}: {}",
+                        result.status,
+                        result.url,
+                        result.tag,
+                        error.unwrap_or_default()
+                    ),
+                }
+            }
+            return Ok(());
+        }
+        _ => {}
+    }
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--config" => {
+                let path = args
+                    .next()
+                    .ok_or(anyhow::anyhow!("--config requires a path\n\n{USAGE}"))?;
+                config_path = Some(PathBuf::from(path));
+            }
+            "--data-dir" => {
+                let path = args
+                    .next()
+                    .ok_or(anyhow::anyhow!("--data-dir requires a path\n\n{USAGE}"))?;
+                data_dir = Some(PathBuf::from(path));
+            }
+            "--print-config" => print_config = true,
+            "--check-db" => maintenance.check = true,
+            "--repair" => maintenance.repair = true,
+            "--compact" => maintenance.compact = true,
+            "-h" | "--help" => {
+                println!("{USAGE}");
+                return Ok(());
+            }
+            _ => anyhow::bail!("unknown argument: {arg}\n\n{USAGE}"),
+        }
+    }
+    let mut config = onyx::Config::load(config_path.as_deref())?;
+    if data_dir.is_some() {
+        config.data_dir = data_dir;
+    }
+    if print_config {
+        print!("{}", config.to_toml_redacted()?);
+        return Ok(());
+    }
+    if maintenance.check || maintenance.repair || maintenance.compact {
+        onyx::maintain(&config, &maintenance)?;
+    }
+    onyx::serve(config).await
 }
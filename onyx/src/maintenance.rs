@@ -0,0 +1,443 @@
+use anyhow::Result;
+use axum::extract::State;
+use axum::response::Json as ResponseJson;
+use redb::MultimapTableHandle;
+use redb::ReadableMultimapTable;
+use redb::ReadableTable;
+use redb::ReadableTableMetadata;
+use redb::TableHandle;
+
+use onyx_api::prelude::*;
+
+use super::Config;
+use super::OnyxError;
+use super::OnyxState;
+use super::db::Db;
+use super::session::AdminSession;
+use super::validate::ValidJson;
+
+/// Expand `$table!(TABLE)` for each table of the database and `$multimap_table!(TABLE)` for
+/// each multimap table, so every table is listed once.
+macro_rules! for_each_table {
+    ($table:ident, $multimap_table:ident) => {
+        $table!(AUTH_TOKEN_TABLE);
+        $table!(SESSION_TABLE);
+        $multimap_table!(USER_SESSION_TABLE);
+        $table!(REFRESH_TOKEN_TABLE);
+        $table!(USER_TABLE);
+        $table!(USERNAME_USER_ID_TABLE);
+        $table!(USER_KEY_TABLE);
+        $multimap_table!(USER_KEYS_TABLE);
+        $table!(USER_STORAGE_TABLE);
+        $table!(USER_QUOTA_TABLE);
+        $table!(PACKAGE_STORAGE_TABLE);
+        $table!(PACKAGE_TABLE);
+        $table!(PACKAGE_NAME_TABLE);
+        $table!(PACKAGE_VERSION_NAME_TABLE);
+        $multimap_table!(PACKAGE_VERSION_TABLE);
+        $table!(VERSION_TABLE);
+        $table!(GIT_REFS_TABLE);
+        $table!(GIT_PACK_TABLE);
+        $table!(UPSTREAM_TAG_TABLE);
+        $table!(IDEMPOTENCY_KEY_TABLE);
+        $table!(PENDING_PUBLISH_TABLE);
+        $table!(WEBHOOK_TABLE);
+        $table!(NOTIFICATION_QUEUE_TABLE);
+        $multimap_table!(PACKAGE_WATCHER_TABLE);
+        $multimap_table!(USER_WATCH_TABLE);
+        $table!(USER_NOTIFICATION_TABLE);
+        $table!(NOTIFICATION_READ_TABLE);
+        $table!(DIGEST_TABLE);
+        $table!(PACKAGE_TRANSFER_TABLE);
+        $multimap_table!(USER_TRANSFER_TABLE);
+        $table!(PACKAGE_CLAIM_TABLE);
+        $table!(CHANGELOG_TABLE);
+        $table!(MIRROR_STATE_TABLE);
+        $table!(SNAPSHOT_TABLE);
+        $table!(TRANSPARENCY_LOG_TABLE);
+        $table!(VERSION_LOG_INDEX_TABLE);
+        $table!(LOG_NODE_TABLE);
+        $table!(LOG_ROOT_TABLE);
+        $table!(VERSION_DOCS_TABLE);
+        $table!(VERSION_MANIFEST_TABLE);
+        $table!(VERSION_RELEASE_NOTES_TABLE);
+        $table!(VERSION_ARTIFACT_TABLE);
+        $table!(BUILD_QUEUE_TABLE);
+        $table!(VERSION_BUILD_TABLE);
+        $table!(VERSION_DELTA_TABLE);
+        $table!(VERSION_DOWNLOAD_TABLE);
+        $table!(VERSION_DEPENDENCY_TABLE);
+        $multimap_table!(PACKAGE_DEPENDENT_TABLE);
+        $multimap_table!(METADATA_VERSION_TABLE);
+        $table!(PACKAGE_REPORT_TABLE);
+        $multimap_table!(USER_REPORT_TABLE);
+        $table!(YANKED_VERSION_TABLE);
+        $table!(REMOVED_PACKAGE_TABLE);
+        $table!(BANNED_USER_TABLE);
+        $table!(NAME_REVIEW_TABLE);
+        $table!(AUDIT_LOG_TABLE);
+    };
+}
+
+pub(crate) use for_each_table;
+
+/// What to do to the database before the registry starts, see `run`.
+#[derive(Default)]
+pub struct MaintenanceOptions {
+    /// Verify the checksums of the database and look for rows that refer to missing rows or
+    /// files.
+    pub check: bool,
+    /// Check, and fix what can be fixed.
+    pub repair: bool,
+    /// Reclaim the space of deleted rows.
+    pub compact: bool,
+}
+
+/// Run maintenance that needs the only handle to the database, before the registry starts.
+/// Inconsistencies are logged.
+pub fn run(config: &Config, options: &MaintenanceOptions) -> Result<()> {
+    config.create_data_dir()?;
+    let mut db = Db::open(config)?;
+    if options.check || options.repair {
+        // redb repairs a database with bad checksums as it's opened, this only reports it
+        if db.get_mut().check_integrity()? {
+            log::info!("Database checksums are valid");
+        } else {
+            log::warn!("Database checksums were invalid and have been repaired");
+        }
+        super::create_tables(&db)?;
+        let storage = OnyxStorage::new(config.data_path(&config.storage_path))?;
+        let inconsistencies = check(&db, &storage, options.repair)?;
+        for inconsistency in &inconsistencies {
+            log::warn!(
+                "{} {}: {}{}",
+                inconsistency.table,
+                inconsistency.key,
+                inconsistency.problem,
+                if inconsistency.repaired {
+                    ", repaired"
+                } else {
+                    ""
+                }
+            );
+        }
+        log::info!("Found {} inconsistencies", inconsistencies.len());
+    }
+    if options.compact {
+        if db.get_mut().compact()? {
+            log::info!("Compacted the database");
+        } else {
+            log::info!("The database is already compact");
+        }
+    }
+    Ok(())
+}
+
+/// A row that refers to a missing row, and the change that fixes it if there is one.
+enum Repair {
+    RemovePackageName(String),
+    RemovePackageVersion(String, HashId),
+    RemoveVersionName(String, String),
+    RemoveUsername(String),
+    SetLatestVersion(String, HashId),
+}
+
+/// Find rows that refer to rows or tarballs that don't exist, and fix them with `repair`.
+/// Indexes pointing at missing rows are removed and packages pointing at a missing latest
+/// version get their newest remaining version. Versions without a tarball or a package are only
+/// reported, fixing them would lose published data.
+pub fn check(db: &Db, storage: &OnyxStorage, repair: bool) -> Result<Vec<DbInconsistency>> {
+    let mut found = vec![];
+    let mut inconsistent = |table: &str, key: String, problem: &str, fix: Option<Repair>| {
+        found.push((
+            DbInconsistency {
+                table: table.to_string(),
+                key,
+                problem: problem.to_string(),
+                repaired: false,
+            },
+            fix,
+        ));
+    };
+    {
+        let read = db.begin_read()?;
+        let package_table = read.open_table(PACKAGE_TABLE)?;
+        let package_name_table = read.open_table(PACKAGE_NAME_TABLE)?;
+        let package_version_table = read.open_multimap_table(PACKAGE_VERSION_TABLE)?;
+        let package_version_name_table = read.open_table(PACKAGE_VERSION_NAME_TABLE)?;
+        let version_table = read.open_table(VERSION_TABLE)?;
+        let user_table = read.open_table(USER_TABLE)?;
+        let username_table = read.open_table(USERNAME_USER_ID_TABLE)?;
+
+        for entry in package_name_table.iter()? {
+            let (name, package_id) = entry?;
+            if package_table.get(package_id.value())?.is_none() {
+                inconsistent(
+                    PACKAGE_NAME_TABLE.name(),
+                    name.value().to_string(),
+                    "package does not exist",
+                    Some(Repair::RemovePackageName(name.value().to_string())),
+                );
+            }
+        }
+        for entry in package_table.iter()? {
+            let package = entry?.1.value();
+            if version_table.get(&package.latest_version_id)?.is_some() {
+                continue;
+            }
+            let mut newest: Option<PackageVersionModel> = None;
+            for version_id in package_version_table.get(package.id.as_str())? {
+                if let Some(version) = version_table.get(version_id?.value())? {
+                    let version = version.value();
+                    if newest
+                        .as_ref()
+                        .is_none_or(|newest| version.created_at > newest.created_at)
+                    {
+                        newest = Some(version);
+                    }
+                }
+            }
+            inconsistent(
+                PACKAGE_TABLE.name(),
+                package.name.clone(),
+                "latest version does not exist",
+                newest.map(|version| Repair::SetLatestVersion(package.id.clone(), version.id)),
+            );
+        }
+        for entry in package_version_table.iter()? {
+            let (package_id, version_ids) = entry?;
+            for version_id in version_ids {
+                let version_id = version_id?.value();
+                if version_table.get(&version_id)?.is_none() {
+                    inconsistent(
+                        PACKAGE_VERSION_TABLE.name(),
+                        format!("{} {}", package_id.value(), version_id.to_string()),
+                        "version does not exist",
+                        Some(Repair::RemovePackageVersion(
+                            package_id.value().to_string(),
+                            version_id,
+                        )),
+                    );
+                }
+            }
+        }
+        for entry in package_version_name_table.iter()? {
+            let (key, version_id) = entry?;
+            let (package_id, version_name) = key.value();
+            if version_table.get(version_id.value())?.is_none() {
+                inconsistent(
+                    PACKAGE_VERSION_NAME_TABLE.name(),
+                    format!("{package_id} {version_name}"),
+                    "version does not exist",
+                    Some(Repair::RemoveVersionName(
+                        package_id.to_string(),
+                        version_name.to_string(),
+                    )),
+                );
+            }
+        }
+        for entry in version_table.iter()? {
+            let version = entry?.1.value();
+            let id = version.id.to_string();
+            if package_table.get(version.package_id.as_str())?.is_none() {
+                inconsistent(
+                    VERSION_TABLE.name(),
+                    id.clone(),
+                    "package does not exist",
+                    None,
+                );
+            }
+            if !storage.contains_filename(&id)? {
+                inconsistent(
+                    VERSION_TABLE.name(),
+                    id,
+                    "tarball is missing from storage",
+                    None,
+                );
+            }
+        }
+        for entry in username_table.iter()? {
+            let (username, user_id) = entry?;
+            if user_table.get(user_id.value())?.is_none() {
+                inconsistent(
+                    USERNAME_USER_ID_TABLE.name(),
+                    username.value().to_string(),
+                    "user does not exist",
+                    Some(Repair::RemoveUsername(username.value().to_string())),
+                );
+            }
+        }
+    }
+
+    if repair && found.iter().any(|(_, fix)| fix.is_some()) {
+        let write = db.begin_write()?;
+        {
+            let mut package_table = write.open_table(PACKAGE_TABLE)?;
+            let mut package_name_table = write.open_table(PACKAGE_NAME_TABLE)?;
+            let mut package_version_table = write.open_multimap_table(PACKAGE_VERSION_TABLE)?;
+            let mut package_version_name_table = write.open_table(PACKAGE_VERSION_NAME_TABLE)?;
+            let mut username_table = write.open_table(USERNAME_USER_ID_TABLE)?;
+            for (inconsistency, fix) in &mut found {
+                match fix {
+                    Some(Repair::RemovePackageName(name)) => {
+                        package_name_table.remove(name.as_str())?;
+                    }
+                    Some(Repair::RemovePackageVersion(package_id, version_id)) => {
+                        package_version_table.remove(package_id.as_str(), &*version_id)?;
+                    }
+                    Some(Repair::RemoveVersionName(package_id, version_name)) => {
+                        package_version_name_table
+                            .remove((package_id.as_str(), version_name.as_str()))?;
+                    }
+                    Some(Repair::RemoveUsername(username)) => {
+                        username_table.remove(username.as_str())?;
+                    }
+                    Some(Repair::SetLatestVersion(package_id, version_id)) => {
+                        let package = package_table.get(package_id.as_str())?.map(|v| v.value());
+                        if let Some(mut package) = package {
+                            package.latest_version_id = version_id.clone();
+                            package_table.insert(package_id.as_str(), package)?;
+                        }
+                    }
+                    None => continue,
+                }
+                inconsistency.repaired = true;
+            }
+        }
+        write.commit()?;
+    }
+    Ok(found
+        .into_iter()
+        .map(|(inconsistency, _)| inconsistency)
+        .collect())
+}
+
+fn table_stats(name: &str, table: &impl ReadableTableMetadata) -> Result<TableStats> {
+    let stats = table.stats()?;
+    Ok(TableStats {
+        name: name.to_string(),
+        rows: table.len()?,
+        stored_bytes: stats.stored_bytes(),
+        metadata_bytes: stats.metadata_bytes(),
+        fragmented_bytes: stats.fragmented_bytes(),
+    })
+}
+
+pub async fn db_stats(
+    State(state): State<OnyxState>,
+    _admin: AdminSession,
+) -> Result<ResponseJson<DbStatsResponse>, OnyxError> {
+    let read = state.db.begin_read()?;
+    let mut tables = vec![];
+    macro_rules! table {
+        ($table:ident) => {
+            tables.push(table_stats($table.name(), &read.open_table($table)?)?);
+        };
+    }
+    macro_rules! multimap_table {
+        ($table:ident) => {
+            tables.push(table_stats(
+                $table.name(),
+                &read.open_multimap_table($table)?,
+            )?);
+        };
+    }
+    for_each_table!(table, multimap_table);
+    Ok(ResponseJson(DbStatsResponse { tables }))
+}
+
+pub async fn db_check(
+    State(state): State<OnyxState>,
+    _admin: AdminSession,
+    ValidJson(payload): ValidJson<DbCheckRequest>,
+) -> Result<ResponseJson<DbCheckResponse>, OnyxError> {
+    let inconsistencies =
+        tokio::task::spawn_blocking(move || check(&state.db, &state.storage, payload.repair))
+            .await
+            .map_err(|e| anyhow::anyhow!(e))??;
+    Ok(ResponseJson(DbCheckResponse { inconsistencies }))
+}
+
+#[cfg(test)]
+mod tests {
+    use anyhow::Result;
+    use nanoid::nanoid;
+    use onyx_api::prelude::*;
+
+    use crate::testing::OnyxTest;
+
+    #[tokio::test]
+    async fn should_check_and_repair_database() -> Result<()> {
+        let admin_username = nanoid!();
+        let test = OnyxTest::with_admins(&[&admin_username]).await?;
+        let (admin, _password) = test
+            .signup(Some(LoginRequest {
+                username: admin_username,
+                password: nanoid!(),
+                ..Default::default()
+            }))
+            .await?;
+        let (user, _password) = test.signup(None).await?;
+        let tarball = OnyxTest::create_test_tarball_named(None, None, None)?;
+        let version_id = HashId::from(tarball.1);
+        test.publish(
+            Some(PublishData::new(tarball.1.to_string(), user.token.clone())),
+            tarball,
+        )
+        .await?;
+
+        let stats = test.api.admin_db_stats(&admin.token).await?;
+        let packages = stats.tables.iter().find(|t| t.name == "packages").unwrap();
+        assert_eq!(packages.rows, 1);
+        assert!(test.api.admin_db_stats(&user.token).await.is_err());
+
+        assert!(
+            test.api
+                .admin_db_check(&admin.token, false)
+                .await?
+                .inconsistencies
+                .is_empty()
+        );
+
+        let write = test.db().begin_write()?;
+        write
+            .open_table(PACKAGE_NAME_TABLE)?
+            .insert("ghost", "missing")?;
+        write
+            .open_table(USERNAME_USER_ID_TABLE)?
+            .insert("ghost", "missing")?;
+        write.commit()?;
+        test.state.storage.remove(&version_id.to_string())?;
+
+        let checked = test.api.admin_db_check(&admin.token, false).await?;
+        let mut problems = checked
+            .inconsistencies
+            .iter()
+            .map(|i| (i.table.as_str(), i.problem.as_str(), i.repaired))
+            .collect::<Vec<_>>();
+        problems.sort();
+        assert_eq!(
+            problems,
+            vec![
+                ("package_names", "package does not exist", false),
+                ("username_user_id", "user does not exist", false),
+                ("versions", "tarball is missing from storage", false),
+            ]
+        );
+
+        let repaired = test.api.admin_db_check(&admin.token, true).await?;
+        assert_eq!(
+            repaired
+                .inconsistencies
+                .iter()
+                .filter(|i| i.repaired)
+                .count(),
+            2
+        );
+        // the missing tarball can't be repaired
+        let checked = test.api.admin_db_check(&admin.token, false).await?;
+        assert_eq!(checked.inconsistencies.len(), 1);
+        assert_eq!(checked.inconsistencies[0].key, version_id.to_string());
+        Ok(())
+    }
+}
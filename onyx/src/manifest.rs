@@ -0,0 +1,125 @@
+use std::collections::HashMap;
+use std::path::PathBuf;
+use std::str::FromStr;
+
+use anyhow::Result;
+use axum::extract::Path;
+use axum::extract::State;
+use axum::response::Json as ResponseJson;
+use tokio::io::AsyncReadExt;
+
+use onyx_api::prelude::*;
+
+use super::OnyxError;
+use super::OnyxState;
+use super::db::WriteTxn;
+
+/// Hash every file of a package.
+pub fn from_files(files: &HashMap<PathBuf, Vec<u8>>) -> Result<VersionManifest> {
+    let mut manifest_files = vec![];
+    for (path, bytes) in files {
+        manifest_files.push(ManifestFile {
+            path: path
+                .components()
+                .map(|component| component.as_os_str().to_string_lossy())
+                .collect::<Vec<_>>()
+                .join("/"),
+            size: bytes.len() as u64,
+            blake3: nrpm_tarball::hash_file(path, bytes)?.to_string(),
+        });
+    }
+    manifest_files.sort_by(|a, b| a.path.cmp(&b.path));
+    Ok(VersionManifest {
+        files: manifest_files,
+    })
+}
+
+pub fn store(
+    write: &WriteTxn,
+    version_id: &HashId,
+    manifest: &VersionManifest,
+) -> Result<(), OnyxError> {
+    let mut version_manifest_table = write.open_table(VERSION_MANIFEST_TABLE)?;
+    let json = serde_json::to_string(manifest).map_err(anyhow::Error::from)?;
+    version_manifest_table.insert(version_id, json.as_str())?;
+    Ok(())
+}
+
+/// The manifest of a version, hashed from its tarball if it was published before manifests
+/// were stored.
+pub async fn load(state: &OnyxState, version_id: &HashId) -> Result<VersionManifest, OnyxError> {
+    {
+        let read = state.db.begin_read()?;
+        let version_table = read.open_table(VERSION_TABLE)?;
+        if version_table.get(version_id)?.is_none() {
+            return Err(OnyxError::not_found("Unable to find version"));
+        }
+        let version_manifest_table = read.open_table(VERSION_MANIFEST_TABLE)?;
+        if let Some(json) = version_manifest_table.get(version_id)? {
+            let manifest = serde_json::from_str(json.value()).map_err(anyhow::Error::from)?;
+            return Ok(manifest);
+        }
+    }
+    let mut bytes = vec![];
+    state
+        .storage
+        .reader_async(&version_id.to_string())
+        .await?
+        .read_to_end(&mut bytes)
+        .await?;
+    let (_config, files) = nrpm_tarball::extract_metadata(bytes)?;
+    Ok(from_files(&files)?)
+}
+
+pub async fn version_manifest(
+    State(state): State<OnyxState>,
+    Path(id): Path<String>,
+) -> Result<ResponseJson<VersionManifest>, OnyxError> {
+    let version_id = HashId::from_str(&id)?;
+    Ok(ResponseJson(load(&state, &version_id).await?))
+}
+
+#[cfg(test)]
+mod tests {
+    use anyhow::Result;
+    use nanoid::nanoid;
+    use onyx_api::prelude::*;
+
+    use crate::testing::OnyxTest;
+
+    #[tokio::test]
+    async fn should_store_manifest_on_publish() -> Result<()> {
+        let test = OnyxTest::new().await?;
+        let (login, _password) = test.signup(None).await?;
+        let nargo_toml = format!("[package]\nname = \"{}\"\nversion = \"0.1.0\"\n", nanoid!());
+        let (bytes, hash) = OnyxTest::create_tarball_from_files(&[
+            ("Nargo.toml", &nargo_toml),
+            ("src/lib.nr", "pub fn one() -> Field {\n    1\n}\n"),
+        ])?;
+        let version_id = HashId::from(hash);
+        test.publish(
+            Some(PublishData::new(hash.to_string(), login.token.clone())),
+            (bytes, hash),
+        )
+        .await?;
+
+        let manifest = test.api.version_manifest(&version_id).await?;
+        let paths = manifest
+            .files
+            .iter()
+            .map(|file| file.path.as_str())
+            .collect::<Vec<_>>();
+        assert_eq!(paths, vec!["Nargo.toml", "src/lib.nr"]);
+        assert_eq!(manifest.files[0].size, nargo_toml.len() as u64);
+        assert_eq!(
+            manifest.files[1].blake3,
+            nrpm_tarball::hash_file(
+                std::path::Path::new("src/lib.nr"),
+                b"pub fn one() -> Field {\n    1\n}\n"
+            )?
+            .to_string()
+        );
+        assert_eq!(HashId::from(manifest.version_hash()?), version_id);
+        Ok(())
+    }
+}
@@ -0,0 +1,126 @@
+use axum::extract::State;
+use axum::http::HeaderValue;
+use axum::response::Json as ResponseJson;
+use axum::response::Response;
+
+use onyx_api::prelude::*;
+
+use super::OnyxError;
+use super::OnyxState;
+
+/// Functionality every registry provides.
+const FEATURES: &[&str] = &[
+    "artifacts",
+    "changelog",
+    "deltas",
+    "docs",
+    "index",
+    "keys",
+    "manifests",
+    "moderation",
+    "quotas",
+    "release_notes",
+    "transfers",
+    "transparency_log",
+    "typosquat_screening",
+];
+
+pub async fn meta(State(state): State<OnyxState>) -> Result<ResponseJson<MetaResponse>, OnyxError> {
+    let mut features = FEATURES.iter().map(|f| f.to_string()).collect::<Vec<_>>();
+    if state.config.upstream_url.is_some() {
+        features.push("mirror".to_string());
+    } else {
+        features.push("publish".to_string());
+    }
+    if state.config.cli_version.is_some() {
+        features.push("cli_version".to_string());
+    }
+    if state.snapshots.is_some() {
+        features.push("snapshots".to_string());
+    }
+    if state.config.nargo_path.is_some() {
+        features.push("builds".to_string());
+    }
+    if !state.scanners.is_empty() {
+        features.push("scans".to_string());
+    }
+    features.sort();
+    Ok(ResponseJson(MetaResponse {
+        api_version: API_VERSION,
+        min_api_version: MIN_CLIENT_API_VERSION,
+        features,
+    }))
+}
+
+/// Tell clients which api version they're talking to, on every response.
+pub async fn api_version_header(mut response: Response) -> Response {
+    response
+        .headers_mut()
+        .insert(API_VERSION_HEADER, HeaderValue::from(API_VERSION));
+    response
+}
+
+#[cfg(test)]
+mod tests {
+    use anyhow::Result;
+    use onyx_api::prelude::*;
+
+    use crate::testing::OnyxTest;
+
+    #[tokio::test]
+    async fn should_serve_meta() -> Result<()> {
+        let test = OnyxTest::new().await?;
+        let meta = test.api.check_compatibility().await?;
+        assert_eq!(meta.api_version, API_VERSION);
+        assert!(meta.has_feature("publish"));
+        assert!(!meta.has_feature("mirror"));
+
+        let mirror = OnyxTest::mirror_of(&test.url).await?;
+        let meta = mirror.api.meta().await?;
+        assert!(meta.has_feature("mirror"));
+        assert!(!meta.has_feature("publish"));
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn should_send_api_version_header() -> Result<()> {
+        let test = OnyxTest::new().await?;
+        for path in ["/v0/meta", "/v0/packages", "/v0/packages/missing/latest"] {
+            let response = reqwest::get(format!("{}{path}", test.url)).await?;
+            assert_eq!(
+                response.headers()[API_VERSION_HEADER].to_str()?,
+                API_VERSION.to_string()
+            );
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn should_check_compatibility() {
+        let meta = MetaResponse {
+            api_version: API_VERSION,
+            min_api_version: MIN_CLIENT_API_VERSION,
+            features: vec![],
+        };
+        assert!(meta.check_compatibility("https://api.nrpm.io").is_ok());
+        assert!(
+            MetaResponse::legacy()
+                .check_compatibility("https://api.nrpm.io")
+                .is_ok()
+        );
+
+        let newer = MetaResponse {
+            api_version: API_VERSION + 2,
+            min_api_version: API_VERSION + 1,
+            features: vec![],
+        };
+        assert_eq!(
+            newer.check_compatibility("https://api.nrpm.io"),
+            Err(ApiVersionMismatch::ClientTooOld {
+                url: "https://api.nrpm.io".to_string(),
+                client_api_version: API_VERSION,
+                min_api_version: API_VERSION + 1,
+            })
+        );
+    }
+}
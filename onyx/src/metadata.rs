@@ -0,0 +1,211 @@
+use axum::extract::Path;
+use axum::extract::State;
+use axum::response::Json as ResponseJson;
+use redb::ReadableTable;
+
+use onyx_api::prelude::*;
+
+use super::OnyxError;
+use super::OnyxState;
+use super::audit;
+use super::index;
+use super::session::AuthSession;
+use super::validate::ValidJson;
+
+/// `Some(None)` for an empty value, which removes the change.
+fn non_empty<T>(value: Option<T>, is_empty: impl Fn(&T) -> bool) -> Option<Option<T>> {
+    value.map(|value| if is_empty(&value) { None } else { Some(value) })
+}
+
+/// Change the description, keywords, repository or deprecation notice of a package without
+/// publishing a version. Only the owner may, and each change is kept in the audit log.
+pub async fn update_package(
+    State(state): State<OnyxState>,
+    session: AuthSession,
+    Path(package_name): Path<String>,
+    ValidJson(payload): ValidJson<UpdatePackageRequest>,
+) -> Result<ResponseJson<PackageModel>, OnyxError> {
+    let user = session.user(&state.db)?;
+    let write = state.db.begin_write()?;
+    let mut package = {
+        let package_name_table = write.open_table(PACKAGE_NAME_TABLE)?;
+        let package_table = write.open_table(PACKAGE_TABLE)?;
+        if let Some(package_id) = package_name_table.get(package_name.as_str())?
+            && let Some(package) = package_table.get(package_id.value())?
+        {
+            package.value()
+        } else {
+            return Err(OnyxError::not_found("Package not found"));
+        }
+    };
+    if package.author_id != user.id {
+        return Err(OnyxError::new(
+            OnyxErrorCode::Forbidden,
+            "Only the owner of a package may change its metadata",
+        ));
+    }
+
+    let metadata = &mut package.metadata;
+    let mut changed = vec![];
+    if let Some(description) = non_empty(payload.description, String::is_empty) {
+        metadata.description = description;
+        changed.push("description");
+    }
+    if let Some(keywords) = non_empty(payload.keywords, Vec::is_empty) {
+        metadata.keywords = keywords;
+        changed.push("keywords");
+    }
+    if let Some(repository) = non_empty(payload.repository, String::is_empty) {
+        metadata.repository = repository;
+        changed.push("repository");
+    }
+    let deprecated = non_empty(payload.deprecated, String::is_empty);
+    let changes_index = deprecated
+        .as_ref()
+        .is_some_and(|deprecated| *deprecated != metadata.deprecated);
+    if let Some(deprecated) = deprecated {
+        metadata.deprecated = deprecated;
+        changed.push("deprecated");
+    }
+    {
+        let mut package_table = write.open_table(PACKAGE_TABLE)?;
+        package_table.insert(package.id.as_str(), package.clone())?;
+    }
+    audit::record(
+        &write,
+        AuditAction::MetadataUpdated,
+        None,
+        &package.name,
+        format!("{} changed {}", user.username, changed.join(", ")),
+    )?;
+    write.commit()?;
+    log::info!(
+        "{} changed the {} of {}",
+        user.username,
+        changed.join(", "),
+        package.name
+    );
+
+    if changes_index
+        && let Some(dir) = &state.config.index_path
+        && let Err(e) = index::refresh_package(&state.db, dir, &package.name)
+    {
+        log::warn!("failed to refresh index file of {}: {e:?}", package.name);
+    }
+    Ok(ResponseJson(package))
+}
+
+#[cfg(test)]
+mod tests {
+    use anyhow::Result;
+    use nanoid::nanoid;
+    use onyx_api::prelude::*;
+
+    use crate::testing::OnyxTest;
+
+    #[tokio::test]
+    async fn should_update_package_metadata() -> Result<()> {
+        let admin_username = nanoid!();
+        let test = OnyxTest::with_admins(&[&admin_username]).await?;
+        let (admin, _password) = test
+            .signup(Some(LoginRequest {
+                username: admin_username,
+                password: nanoid!(),
+                ..Default::default()
+            }))
+            .await?;
+        let (owner, _password) = test.signup(None).await?;
+        let (other, _password) = test.signup(None).await?;
+        let tarball = OnyxTest::create_test_tarball_named(None, Some("described"), None)?;
+        test.publish(
+            Some(PublishData::new(tarball.1.to_string(), owner.token.clone())),
+            tarball,
+        )
+        .await?;
+        let (_package, version) = test.api.load_package_latest_version("described").await?;
+
+        let request = UpdatePackageRequest {
+            description: Some("hashes things, now without typos".to_string()),
+            keywords: Some(vec!["hash".to_string()]),
+            deprecated: Some("use described2".to_string()),
+            ..Default::default()
+        };
+        let e = test
+            .api
+            .update_package(&other.token, "described", &request)
+            .await
+            .unwrap_err();
+        assert_eq!(
+            e.downcast_ref::<ApiError>().map(|e| e.code),
+            Some(OnyxErrorCode::Forbidden)
+        );
+        let package = test
+            .api
+            .update_package(&owner.token, "described", &request)
+            .await?;
+        assert_eq!(
+            package.metadata,
+            PackageMetadata {
+                description: Some("hashes things, now without typos".to_string()),
+                keywords: Some(vec!["hash".to_string()]),
+                repository: None,
+                deprecated: Some("use described2".to_string()),
+            }
+        );
+
+        // versions are untouched
+        let (package, latest) = test.api.load_package_latest_version("described").await?;
+        assert_eq!(latest.id, version.id);
+        assert_eq!(latest.metadata, version.metadata);
+        assert_eq!(
+            package.metadata.deprecated.as_deref(),
+            Some("use described2")
+        );
+        let found = test.api.search(Some("typos"), &[]).await?;
+        assert_eq!(found.len(), 1);
+
+        // empty values go back to the Nargo.toml
+        let package = test
+            .api
+            .update_package(
+                &owner.token,
+                "described",
+                &UpdatePackageRequest {
+                    description: Some(String::new()),
+                    deprecated: Some(String::new()),
+                    ..Default::default()
+                },
+            )
+            .await?;
+        assert_eq!(package.metadata.description, None);
+        assert_eq!(package.metadata.deprecated, None);
+        assert_eq!(package.metadata.keywords, Some(vec!["hash".to_string()]));
+
+        let e = test
+            .api
+            .update_package(&owner.token, "described", &UpdatePackageRequest::default())
+            .await
+            .unwrap_err();
+        assert_eq!(
+            e.downcast_ref::<ApiError>().map(|e| e.code),
+            Some(OnyxErrorCode::ValidationFailed)
+        );
+
+        let audit = test.api.admin_audit_log(&admin.token, 0, 100).await?;
+        let updates = audit
+            .entries
+            .iter()
+            .filter(|entry| entry.action == AuditAction::MetadataUpdated)
+            .collect::<Vec<_>>();
+        assert_eq!(updates.len(), 2);
+        assert_eq!(updates[0].package_name, "described");
+        assert_eq!(
+            updates[0].details,
+            format!(
+                "{} changed description, keywords, deprecated",
+                owner.user.username
+            )
+        );
+        Ok(())
+    }
+}
@@ -0,0 +1,229 @@
+use std::io::Write;
+
+use anyhow::Result;
+use tempfile::tempfile;
+
+use onyx_api::prelude::*;
+
+use super::OnyxState;
+use super::publish::NewVersion;
+use super::publish::store_version;
+use super::validate::normalize_version_name;
+
+const PAGE_SIZE: usize = 100;
+
+/// Pull versions published upstream since the last sync. Each tarball is hash checked
+/// against the changelog before it's stored, and progress is saved after every version so
+/// an interrupted sync resumes where it stopped. Returns the number of versions mirrored.
+///
+/// Authors are recorded by their upstream user id; user accounts are not mirrored.
+pub async fn sync(state: &OnyxState) -> Result<usize> {
+    let Some(upstream) = state.config.upstream_url.as_deref() else {
+        return Ok(0);
+    };
+    let api = OnyxApi::new(upstream.to_string())?;
+    let mut since = {
+        let read = state.db.begin_read()?;
+        let mirror_state_table = read.open_table(MIRROR_STATE_TABLE)?;
+        mirror_state_table
+            .get(upstream)?
+            .map(|v| v.value())
+            .unwrap_or(0)
+    };
+    let mut mirrored = 0;
+    loop {
+        let page = api.changelog(since, PAGE_SIZE).await?;
+        if page.entries.is_empty() {
+            break;
+        }
+        for entry in page.entries {
+            let skip = {
+                let read = state.db.begin_read()?;
+                let version_table = read.open_table(VERSION_TABLE)?;
+                let removed_package_table = read.open_table(REMOVED_PACKAGE_TABLE)?;
+                version_table.get(&entry.version_id)?.is_some()
+                    || removed_package_table
+                        .get(entry.package_name.as_str())?
+                        .is_some()
+            };
+            let bytes = if skip {
+                None
+            } else {
+                match api.download_tarball(&entry.version_id).await {
+                    Ok(bytes) => Some(bytes),
+                    // the package was deleted upstream by a moderator
+                    Err(e)
+                        if e.downcast_ref::<ApiError>()
+                            .is_some_and(|e| e.code == OnyxErrorCode::NotFound) =>
+                    {
+                        log::warn!(
+                            "skipping {}@{}, it was removed upstream",
+                            entry.package_name,
+                            entry.version_name
+                        );
+                        None
+                    }
+                    Err(e) => return Err(e),
+                }
+            };
+            let tarball = if let Some(bytes) = bytes {
+                let mut tarball = tempfile()?;
+                tarball.write_all(&bytes)?;
+                // upstream already decided which version names it accepts
+                let (package_name, version_name) =
+                    state.storage.validate_tarball(&mut tarball, true)?;
+                if package_name != entry.package_name
+                    || normalize_version_name(&version_name, true).as_ref()
+                        != Ok(&entry.version_name)
+                {
+                    anyhow::bail!(
+                        "upstream tarball {} contains {package_name}@{version_name}, expected {}@{}",
+                        entry.version_id.to_string(),
+                        entry.package_name,
+                        entry.version_name
+                    );
+                }
+                let hash = nrpm_tarball::hash_tarball(&mut tarball)?;
+                if HashId::from(hash) != entry.version_id {
+                    anyhow::bail!(
+                        "hash mismatch for upstream tarball, computed: {hash}, expected: {}",
+                        entry.version_id.to_string()
+                    );
+                }
+                // release notes are a nicety, upstreams that predate them have none
+                let release_notes = api
+                    .version_release_notes(&entry.version_id)
+                    .await
+                    .inspect_err(|e| log::warn!("failed to mirror release notes: {e:?}"))
+                    .ok()
+                    .flatten();
+                // so is provenance, which upstream only lists with the package's versions
+                let upstream_version = api
+                    .load_package_versions(&entry.package_name)
+                    .await
+                    .inspect_err(|e| log::warn!("failed to mirror provenance: {e:?}"))
+                    .ok()
+                    .and_then(|(_, versions)| {
+                        versions.into_iter().find(|v| v.id == entry.version_id)
+                    });
+                Some((tarball, hash, release_notes, upstream_version))
+            } else {
+                None
+            };
+
+            let write = state.db.begin_write()?;
+            if let Some((mut tarball, hash, release_notes, upstream_version)) = tarball {
+                store_version(
+                    &state.storage,
+                    &write,
+                    NewVersion {
+                        author_id: &entry.author_id,
+                        package_name: entry.package_name.clone(),
+                        version_name: entry.version_name.clone(),
+                        hash,
+                        created_at: entry.published_at,
+                        follow_owner: true,
+                        source_repository: upstream_version
+                            .as_ref()
+                            .and_then(|v| v.source_repository.clone()),
+                        source_commit: upstream_version.and_then(|v| v.source_commit),
+                        release_notes,
+                    },
+                    &mut tarball,
+                )
+                .map_err(|e| anyhow::anyhow!("failed to store mirrored version: {e:?}"))?;
+                mirrored += 1;
+            }
+            {
+                let mut mirror_state_table = write.open_table(MIRROR_STATE_TABLE)?;
+                mirror_state_table.insert(upstream, entry.seq)?;
+            }
+            write.commit()?;
+            since = entry.seq;
+        }
+    }
+    Ok(mirrored)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::testing::OnyxTest;
+    use anyhow::Result;
+    use nanoid::nanoid;
+    use onyx_api::prelude::*;
+
+    use super::*;
+
+    #[tokio::test]
+    async fn should_mirror_upstream() -> Result<()> {
+        let upstream = OnyxTest::new().await?;
+        let mirror = OnyxTest::mirror_of(&upstream.url).await?;
+        let (login, _password) = upstream.signup(None).await?;
+        let name = nanoid!();
+        let mut version_ids = vec![];
+        let tarball = OnyxTest::create_test_tarball_named(None, Some(&name), Some("0.1.0"))?;
+        version_ids.push(HashId::from(tarball.1));
+        upstream
+            .publish(
+                Some(PublishData::new(tarball.1.to_string(), login.token.clone())),
+                tarball,
+            )
+            .await?;
+        // the second version has provenance, which is mirrored with it
+        let tarball = OnyxTest::create_tarball_from_files(&[(
+            "Nargo.toml",
+            &format!(
+                "[package]\nname = \"{name}\"\nversion = \"0.2.0\"\n\
+                 repository = \"https://github.com/noir-lang/noir\"\n"
+            ),
+        )])?;
+        version_ids.push(HashId::from(tarball.1));
+        let mut publish_data = PublishData::new(tarball.1.to_string(), login.token.clone());
+        publish_data.source_repository = Some("https://github.com/noir-lang/noir".to_string());
+        publish_data.source_commit = Some("a".repeat(40));
+        upstream.publish(Some(publish_data), tarball).await?;
+
+        assert_eq!(sync(&mirror.state).await?, 2);
+        // nothing new upstream
+        assert_eq!(sync(&mirror.state).await?, 0);
+
+        let (package, versions) = mirror.api.load_package_versions(&name).await?;
+        assert_eq!(package.author_id, login.user.id);
+        assert_eq!(versions.len(), 2);
+        let provenance = |version_id: &HashId| {
+            let version = versions.iter().find(|v| &v.id == version_id).unwrap();
+            (
+                version.source_repository.clone(),
+                version.source_commit.clone(),
+            )
+        };
+        assert_eq!(provenance(&version_ids[0]), (None, None));
+        assert_eq!(
+            provenance(&version_ids[1]),
+            (
+                Some("https://github.com/noir-lang/noir".to_string()),
+                Some("a".repeat(40))
+            )
+        );
+        for version_id in &version_ids {
+            let original = upstream.api.download_tarball(version_id).await?;
+            let mirrored = mirror.api.download_tarball(version_id).await?;
+            assert_eq!(original, mirrored);
+        }
+        assert_eq!(mirror.api.changelog(0, 100).await?.latest_seq, 2);
+
+        // the mirror is read only
+        let tarball = OnyxTest::create_test_tarball_named(None, Some(&name), Some("0.3.0"))?;
+        let (mirror_login, _password) = mirror.signup(None).await?;
+        assert!(
+            mirror
+                .publish(
+                    Some(PublishData::new(tarball.1.to_string(), mirror_login.token)),
+                    tarball,
+                )
+                .await
+                .is_err()
+        );
+        Ok(())
+    }
+}
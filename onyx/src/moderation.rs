@@ -0,0 +1,630 @@
+use axum::extract::Path;
+use axum::extract::State;
+use axum::response::Json as ResponseJson;
+use nanoid::nanoid;
+use redb::ReadableMultimapTable;
+use redb::ReadableTable;
+
+use onyx_api::prelude::*;
+
+use super::OnyxError;
+use super::OnyxState;
+use super::audit;
+use super::db::WriteTxn;
+use super::index;
+use super::search;
+use super::session;
+use super::session::AdminSession;
+use super::session::AuthSession;
+use super::snapshot;
+use super::transfer;
+use super::validate::ValidJson;
+use super::watch;
+
+/// Number of reports a user may make in `REPORT_WINDOW` seconds.
+pub const REPORT_LIMIT: usize = 10;
+pub const REPORT_WINDOW: u64 = 3600;
+
+fn package_by_name(write: &WriteTxn, name: &str) -> Result<PackageModel, OnyxError> {
+    let package_name_table = write.open_table(PACKAGE_NAME_TABLE)?;
+    let package_table = write.open_table(PACKAGE_TABLE)?;
+    if let Some(package_id) = package_name_table.get(name)?
+        && let Some(package) = package_table.get(package_id.value())?
+    {
+        Ok(package.value())
+    } else {
+        Err(OnyxError::not_found("Package not found"))
+    }
+}
+
+/// Report a package to the admins. A user may have one open report per package and may
+/// only make `REPORT_LIMIT` reports in `REPORT_WINDOW` seconds.
+pub async fn report_package(
+    State(state): State<OnyxState>,
+    session: AuthSession,
+    Path(package_name): Path<String>,
+    ValidJson(payload): ValidJson<ReportPackageRequest>,
+) -> Result<ResponseJson<PackageReportModel>, OnyxError> {
+    let user = session.user(&state.db)?;
+    let now = timestamp();
+    let write = state.db.begin_write()?;
+    let package = package_by_name(&write, &package_name)?;
+    if let Some(version_name) = &payload.version_name {
+        let package_version_name_table = write.open_table(PACKAGE_VERSION_NAME_TABLE)?;
+        if package_version_name_table
+            .get((package.id.as_str(), version_name.as_str()))?
+            .is_none()
+        {
+            return Err(OnyxError::not_found(&format!(
+                "Unable to find version \"{version_name}\" of package \"{package_name}\""
+            )));
+        }
+    }
+    {
+        let package_report_table = write.open_table(PACKAGE_REPORT_TABLE)?;
+        let user_report_table = write.open_multimap_table(USER_REPORT_TABLE)?;
+        let mut recent = 0;
+        for report_id in user_report_table.get(user.id.as_str())? {
+            let Some(report) = package_report_table.get(report_id?.value())? else {
+                continue;
+            };
+            let report = report.value();
+            if report.package_id == package.id && report.status == ReportStatus::Open {
+                return Err(OnyxError::conflict(
+                    "You already have an open report for this package",
+                ));
+            }
+            if report.created_at + REPORT_WINDOW > now {
+                recent += 1;
+            }
+        }
+        if recent >= REPORT_LIMIT {
+            return Err(OnyxError::new(
+                OnyxErrorCode::RateLimited,
+                &format!("Users may make {REPORT_LIMIT} reports per hour, try again later"),
+            ));
+        }
+    }
+
+    let report = PackageReportModel {
+        id: nanoid!(),
+        package_id: package.id,
+        package_name: package.name,
+        version_name: payload.version_name,
+        reason: payload.reason,
+        details: payload.details,
+        user_id: user.id,
+        username: user.username,
+        created_at: now,
+        status: ReportStatus::Open,
+        resolution: None,
+    };
+    {
+        let mut package_report_table = write.open_table(PACKAGE_REPORT_TABLE)?;
+        let mut user_report_table = write.open_multimap_table(USER_REPORT_TABLE)?;
+        package_report_table.insert(report.id.as_str(), report.clone())?;
+        user_report_table.insert(report.user_id.as_str(), report.id.as_str())?;
+    }
+    write.commit()?;
+    log::info!(
+        "{} reported {} for {:?}",
+        report.username,
+        report.package_name,
+        report.reason
+    );
+    Ok(ResponseJson(report))
+}
+
+/// Open reports, oldest first.
+pub async fn list_reports(
+    State(state): State<OnyxState>,
+    _admin: AdminSession,
+) -> Result<ResponseJson<Vec<PackageReportModel>>, OnyxError> {
+    let read = state.db.begin_read()?;
+    let package_report_table = read.open_table(PACKAGE_REPORT_TABLE)?;
+    let mut reports = vec![];
+    for entry in package_report_table.iter()? {
+        let report = entry?.1.value();
+        if report.status == ReportStatus::Open {
+            reports.push(report);
+        }
+    }
+    reports.sort_by_key(|report| report.created_at);
+    Ok(ResponseJson(reports))
+}
+
+/// Dismiss a report, or act on it by yanking a version, deleting the package, or banning its
+/// owner.
+pub async fn resolve_report(
+    State(state): State<OnyxState>,
+    admin: AdminSession,
+    Path(report_id): Path<String>,
+    ValidJson(payload): ValidJson<ResolveReportRequest>,
+) -> Result<ResponseJson<PackageReportModel>, OnyxError> {
+    let now = timestamp();
+    let write = state.db.begin_write()?;
+    let mut report = {
+        let package_report_table = write.open_table(PACKAGE_REPORT_TABLE)?;
+        match package_report_table.get(report_id.as_str())? {
+            Some(report) => report.value(),
+            None => return Err(OnyxError::not_found("Report not found")),
+        }
+    };
+    if report.status != ReportStatus::Open {
+        return Err(OnyxError::conflict("Report is already resolved"));
+    }
+    let record = ModerationRecord {
+        admin_username: admin.user.username.clone(),
+        report_id: report.id.clone(),
+        note: payload.note.clone(),
+        created_at: now,
+    };
+    log::info!(
+        "admin {} resolving report {} of {} with {:?}",
+        admin.user.username,
+        report.id,
+        report.package_name,
+        payload.action
+    );
+
+    // tarballs of a deleted package are removed once the deletion is committed
+    let mut removed_versions = vec![];
+    let mut yanked_version = None;
+    match payload.action {
+        ModerationAction::Dismiss => {}
+        ModerationAction::Yank => {
+            let Some(version_name) = payload.version_name.or(report.version_name.clone()) else {
+                return Err(OnyxError::bad_request(
+                    "The report isn't about a version, choose one to yank",
+                ));
+            };
+            let package = package_by_name(&write, &report.package_name)?;
+            yank(&write, &package, &version_name, record)?;
+            yanked_version = Some(version_name);
+        }
+        ModerationAction::Delete => {
+            let package = package_by_name(&write, &report.package_name)?;
+            removed_versions = delete_package(&write, &package, record)?;
+        }
+        ModerationAction::Ban => {
+            let package = package_by_name(&write, &report.package_name)?;
+            if package.author_id == admin.user.id {
+                return Err(OnyxError::bad_request("Admins can't ban themselves"));
+            }
+            let mut banned_user_table = write.open_table(BANNED_USER_TABLE)?;
+            banned_user_table.insert(package.author_id.as_str(), record)?;
+            drop(banned_user_table);
+            let revoked = session::revoke_user_sessions(&write, &package.author_id, None)?;
+            log::info!("banned {}, revoked {revoked} sessions", package.author_id);
+        }
+    }
+
+    report.status = match payload.action {
+        ModerationAction::Dismiss => ReportStatus::Dismissed,
+        _ => ReportStatus::Actioned,
+    };
+    report.resolution = Some(ReportResolution {
+        action: payload.action,
+        version_name: yanked_version,
+        admin_username: admin.user.username,
+        note: payload.note,
+        resolved_at: now,
+    });
+    {
+        let mut package_report_table = write.open_table(PACKAGE_REPORT_TABLE)?;
+        package_report_table.insert(report.id.as_str(), report.clone())?;
+        // the other open reports of a deleted package have nothing left to review
+        if let ModerationAction::Delete = payload.action {
+            let mut open = vec![];
+            for entry in package_report_table.iter()? {
+                let other = entry?.1.value();
+                if other.package_id == report.package_id && other.status == ReportStatus::Open {
+                    open.push(other);
+                }
+            }
+            for mut other in open {
+                other.status = ReportStatus::Actioned;
+                other.resolution = report.resolution.clone();
+                package_report_table.insert(other.id.as_str(), &other)?;
+            }
+        }
+    }
+    audit::record(
+        &write,
+        AuditAction::ReportResolved,
+        report
+            .resolution
+            .as_ref()
+            .map(|r| r.admin_username.as_str()),
+        &report.package_name,
+        format!(
+            "{:?} report {} with {:?}",
+            report.reason, report.id, payload.action
+        ),
+    )?;
+    let changes_index = !matches!(
+        payload.action,
+        ModerationAction::Dismiss | ModerationAction::Ban
+    );
+    if changes_index {
+        // the next refresh signs a snapshot without the yanked or deleted versions
+        let mut snapshot_table = write.open_table(SNAPSHOT_TABLE)?;
+        snapshot_table.retain(|_, _| false)?;
+    }
+    write.commit()?;
+
+    for version_id in removed_versions {
+        if let Err(e) = state.storage.remove(&version_id.to_string()) {
+            log::warn!("failed to remove tarball {}: {e:?}", version_id.to_string());
+        }
+    }
+    if changes_index {
+        if let Some(dir) = &state.config.index_path
+            && let Err(e) = index::refresh_package(&state.db, dir, &report.package_name)
+        {
+            log::warn!(
+                "failed to refresh index file of {}: {e:?}",
+                report.package_name
+            );
+        }
+        if let Err(e) = snapshot::refresh(&state) {
+            log::warn!("failed to refresh snapshot: {e:?}");
+        }
+    }
+    Ok(ResponseJson(report))
+}
+
+/// Yank a version so it's never resolved as the latest. If it was the latest, the latest
+/// version that isn't yanked takes its place.
+fn yank(
+    write: &WriteTxn,
+    package: &PackageModel,
+    version_name: &str,
+    record: ModerationRecord,
+) -> Result<(), OnyxError> {
+    let version_id = {
+        let package_version_name_table = write.open_table(PACKAGE_VERSION_NAME_TABLE)?;
+        match package_version_name_table.get((package.id.as_str(), version_name))? {
+            Some(version_id) => version_id.value(),
+            None => {
+                return Err(OnyxError::not_found(&format!(
+                    "Unable to find version \"{version_name}\" of package \"{}\"",
+                    package.name
+                )));
+            }
+        }
+    };
+    let mut yanked_version_table = write.open_table(YANKED_VERSION_TABLE)?;
+    if yanked_version_table.get(&version_id)?.is_some() {
+        return Err(OnyxError::conflict("Version is already yanked"));
+    }
+    yanked_version_table.insert(&version_id, record)?;
+    if package.latest_version_id != version_id {
+        return Ok(());
+    }
+
+    let package_version_table = write.open_multimap_table(PACKAGE_VERSION_TABLE)?;
+    let version_table = write.open_table(VERSION_TABLE)?;
+    let mut latest: Option<PackageVersionModel> = None;
+    for id in package_version_table.get(package.id.as_str())? {
+        let id = id?.value();
+        if yanked_version_table.get(&id)?.is_some() {
+            continue;
+        }
+        let Some(version) = version_table.get(&id)? else {
+            continue;
+        };
+        let version = version.value();
+        if latest.as_ref().is_none_or(|latest| {
+            (version.created_at, &version.name) > (latest.created_at, &latest.name)
+        }) {
+            latest = Some(version);
+        }
+    }
+    // with every version yanked the package keeps pointing at the last one published
+    if let Some(latest) = latest {
+        let mut package = package.clone();
+        package.latest_version_id = latest.id;
+        let mut package_table = write.open_table(PACKAGE_TABLE)?;
+        package_table.insert(package.id.as_str(), &package)?;
+    }
+    Ok(())
+}
+
+/// Remove a package and everything stored for its versions, and reserve its name so it
+/// can't be published again. The changelog keeps its entries. Returns the ids of the
+/// removed versions, whose tarballs should be removed from storage.
+fn delete_package(
+    write: &WriteTxn,
+    package: &PackageModel,
+    record: ModerationRecord,
+) -> Result<Vec<HashId>, OnyxError> {
+    let package_id = package.id.as_str();
+    let mut versions = vec![];
+    {
+        let mut package_version_table = write.open_multimap_table(PACKAGE_VERSION_TABLE)?;
+        let mut version_table = write.open_table(VERSION_TABLE)?;
+        for version_id in package_version_table.remove_all(package_id)? {
+            let version_id = version_id?.value();
+            if let Some(version) = version_table.remove(&version_id)? {
+                versions.push(version.value());
+            }
+        }
+    }
+    let version_ids = versions.iter().map(|v| v.id.clone()).collect::<Vec<_>>();
+    for version in &versions {
+        search::remove(write, version)?;
+    }
+
+    // the package stops depending on its dependencies, and nothing depends on it anymore
+    {
+        let package_name_table = write.open_table(PACKAGE_NAME_TABLE)?;
+        let version_dependency_table = write.open_table(VERSION_DEPENDENCY_TABLE)?;
+        let mut package_dependent_table = write.open_multimap_table(PACKAGE_DEPENDENT_TABLE)?;
+        if let Some(dependencies) = version_dependency_table.get(&package.latest_version_id)? {
+            for dependency in dependencies.value().dependencies {
+                if let Some(dependency_id) =
+                    package_name_table.get(dependency.package_name.as_str())?
+                {
+                    package_dependent_table.remove(dependency_id.value(), package_id)?;
+                }
+            }
+        }
+        package_dependent_table.remove_all(package_id)?;
+    }
+
+    {
+        let mut version_dependency_table = write.open_table(VERSION_DEPENDENCY_TABLE)?;
+        let mut version_docs_table = write.open_table(VERSION_DOCS_TABLE)?;
+        let mut version_release_notes_table = write.open_table(VERSION_RELEASE_NOTES_TABLE)?;
+        let mut version_manifest_table = write.open_table(VERSION_MANIFEST_TABLE)?;
+        let mut build_queue_table = write.open_table(BUILD_QUEUE_TABLE)?;
+        let mut version_build_table = write.open_table(VERSION_BUILD_TABLE)?;
+        let mut yanked_version_table = write.open_table(YANKED_VERSION_TABLE)?;
+        for version_id in &version_ids {
+            version_dependency_table.remove(version_id)?;
+            version_docs_table.remove(version_id)?;
+            version_release_notes_table.remove(version_id)?;
+            version_manifest_table.remove(version_id)?;
+            build_queue_table.remove(version_id)?;
+            version_build_table.remove(version_id)?;
+            yanked_version_table.remove(version_id)?;
+        }
+        let mut version_artifact_table = write.open_table(VERSION_ARTIFACT_TABLE)?;
+        version_artifact_table.retain(|(version_id, _), _| !version_ids.contains(&version_id))?;
+        let mut version_delta_table = write.open_table(VERSION_DELTA_TABLE)?;
+        version_delta_table
+            .retain(|(from, to), _| !version_ids.contains(&from) && !version_ids.contains(&to))?;
+    }
+
+    {
+        let mut git_refs_table = write.open_table(GIT_REFS_TABLE)?;
+        let mut git_pack_table = write.open_table(GIT_PACK_TABLE)?;
+        if let Some(refs) = git_refs_table.remove(package_id)? {
+            for line in refs.value().lines() {
+                if let Some(i) = line.find(" refs/heads/")
+                    && let Some(commit_hex) = line.get(i.saturating_sub(40)..i)
+                {
+                    git_pack_table.remove(commit_hex)?;
+                }
+            }
+        }
+    }
+
+    transfer::remove_pending_transfer(write, package_id)?;
+    watch::remove_package(write, package_id)?;
+    {
+        let mut package_claim_table = write.open_table(PACKAGE_CLAIM_TABLE)?;
+        package_claim_table
+            .retain_in((package_id, "")..=(package_id, "\u{10ffff}"), |_, _| false)?;
+        let mut package_version_name_table = write.open_table(PACKAGE_VERSION_NAME_TABLE)?;
+        package_version_name_table
+            .retain_in((package_id, "")..=(package_id, "\u{10ffff}"), |_, _| false)?;
+    }
+
+    {
+        let mut package_storage_table = write.open_table(PACKAGE_STORAGE_TABLE)?;
+        let mut user_storage_table = write.open_table(USER_STORAGE_TABLE)?;
+        if let Some(bytes) = package_storage_table.remove(package_id)? {
+            let bytes = bytes.value();
+            let used = user_storage_table
+                .get(package.author_id.as_str())?
+                .map(|used| used.value())
+                .unwrap_or_default();
+            user_storage_table.insert(package.author_id.as_str(), used.saturating_sub(bytes))?;
+        }
+    }
+
+    let mut package_table = write.open_table(PACKAGE_TABLE)?;
+    let mut package_name_table = write.open_table(PACKAGE_NAME_TABLE)?;
+    let mut removed_package_table = write.open_table(REMOVED_PACKAGE_TABLE)?;
+    package_table.remove(package_id)?;
+    package_name_table.remove(package.name.as_str())?;
+    removed_package_table.insert(package.name.as_str(), record)?;
+    Ok(version_ids)
+}
+
+#[cfg(test)]
+mod tests {
+    use anyhow::Result;
+    use nanoid::nanoid;
+    use onyx_api::prelude::*;
+
+    use crate::AUTH_TOKEN_TABLE;
+    use crate::testing::OnyxTest;
+    use crate::timestamp;
+
+    async fn admin(test: &OnyxTest, username: &str) -> Result<(LoginResponse, String)> {
+        test.signup(Some(LoginRequest {
+            username: username.to_string(),
+            password: nanoid!(),
+            ..Default::default()
+        }))
+        .await
+    }
+
+    fn report(details: &str) -> ReportPackageRequest {
+        ReportPackageRequest {
+            reason: ReportReason::Malware,
+            details: details.to_string(),
+            version_name: None,
+        }
+    }
+
+    fn resolve(action: ModerationAction) -> ResolveReportRequest {
+        ResolveReportRequest {
+            action,
+            version_name: None,
+            note: "Confirmed".to_string(),
+        }
+    }
+
+    #[tokio::test]
+    async fn should_queue_and_yank_reported_version() -> Result<()> {
+        let admin_username = nanoid!();
+        let test = OnyxTest::with_admins(&[&admin_username]).await?;
+        let (owner, _password) = test.signup(None).await?;
+        let (reporter, _password) = test.signup(None).await?;
+        let name = nanoid!();
+        let hashes = test
+            .seed_package(&owner, &name, &["0.1.0", "0.2.0"])
+            .await?;
+        let bad = HashId::from(hashes[1]);
+
+        let mut request = report("Steals keys in build.rs");
+        request.version_name = Some("0.2.0".to_string());
+        let reported = test
+            .api
+            .report_package(&reporter.token, &name, request)
+            .await?;
+        assert_eq!(reported.status, ReportStatus::Open);
+
+        // one open report per package
+        let err = test
+            .api
+            .report_package(&reporter.token, &name, report("Again"))
+            .await
+            .unwrap_err();
+        let err = err.downcast_ref::<ApiError>().unwrap();
+        assert_eq!(err.code, OnyxErrorCode::Conflict);
+
+        // only admins can see the queue
+        let err = test.api.admin_reports(&reporter.token).await.unwrap_err();
+        let err = err.downcast_ref::<ApiError>().unwrap();
+        assert_eq!(err.code, OnyxErrorCode::Forbidden);
+        let (admin, _password) = admin(&test, &admin_username).await?;
+        assert_eq!(
+            test.api.admin_reports(&admin.token).await?,
+            vec![reported.clone()]
+        );
+
+        let resolved = test
+            .api
+            .admin_resolve_report(&admin.token, &reported.id, resolve(ModerationAction::Yank))
+            .await?;
+        assert_eq!(resolved.status, ReportStatus::Actioned);
+        assert!(test.api.admin_reports(&admin.token).await?.is_empty());
+
+        // the yanked version is no longer the latest but can still be downloaded
+        let (_package, latest) = test.api.load_package_latest_version(&name).await?;
+        assert_eq!(latest.name, "0.1.0");
+        let IndexFetch::Fetched { package, .. } = test.api.index_package(&name, None).await? else {
+            anyhow::bail!("expected the index file");
+        };
+        assert!(package.versions.iter().any(|v| v.id == bad && v.yanked));
+        test.api.download_tarball(&bad).await?;
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn should_delete_package_and_ban_owner() -> Result<()> {
+        let admin_username = nanoid!();
+        let test = OnyxTest::with_admins(&[&admin_username]).await?;
+        let (owner, _password) = test.signup(None).await?;
+        let (reporter, _password) = test.signup(None).await?;
+        let (admin, _password) = admin(&test, &admin_username).await?;
+        let name = nanoid!();
+        let version_id = HashId::from(test.seed_package(&owner, &name, &["0.1.0"]).await?[0]);
+
+        let reported = test
+            .api
+            .report_package(
+                &reporter.token,
+                &name,
+                report("Typosquats a popular package"),
+            )
+            .await?;
+        test.api
+            .admin_resolve_report(
+                &admin.token,
+                &reported.id,
+                resolve(ModerationAction::Delete),
+            )
+            .await?;
+        assert!(test.api.load_package_latest_version(&name).await.is_err());
+        assert!(test.api.download_tarball(&version_id).await.is_err());
+        assert_eq!(test.api.storage_usage(&owner.token).await?.used_bytes, 0);
+
+        // the name stays reserved
+        let err = test
+            .seed_package(&owner, &name, &["0.1.1"])
+            .await
+            .unwrap_err();
+        let err = err.downcast_ref::<ApiError>().unwrap();
+        assert_eq!(err.code, OnyxErrorCode::Forbidden);
+
+        let other = nanoid!();
+        test.seed_package(&owner, &other, &["0.1.0"]).await?;
+        let reported = test
+            .api
+            .report_package(&reporter.token, &other, report("Same author"))
+            .await?;
+        test.api
+            .admin_resolve_report(&admin.token, &reported.id, resolve(ModerationAction::Ban))
+            .await?;
+        let err = test.api.storage_usage(&owner.token).await.unwrap_err();
+        let err = err.downcast_ref::<ApiError>().unwrap();
+        assert_eq!(err.code, OnyxErrorCode::InvalidToken);
+
+        // a token issued after the ban can't publish either
+        let token = nanoid!();
+        let write = test.state.db.begin_write()?;
+        write
+            .open_table(AUTH_TOKEN_TABLE)?
+            .insert(token.as_str(), (owner.user.id.as_str(), timestamp() + 60))?;
+        write.commit()?;
+        let mut banned = owner.clone();
+        banned.token = token;
+        let err = test
+            .seed_package(&banned, &nanoid!(), &["0.1.0"])
+            .await
+            .unwrap_err();
+        let err = err.downcast_ref::<ApiError>().unwrap();
+        assert_eq!(err.code, OnyxErrorCode::Forbidden);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn should_rate_limit_reports() -> Result<()> {
+        let test = OnyxTest::new().await?;
+        let (owner, _password) = test.signup(None).await?;
+        let (reporter, _password) = test.signup(None).await?;
+        for _ in 0..super::REPORT_LIMIT {
+            let name = nanoid!();
+            test.seed_package(&owner, &name, &["0.1.0"]).await?;
+            test.api
+                .report_package(&reporter.token, &name, report("Spam"))
+                .await?;
+        }
+        let name = nanoid!();
+        test.seed_package(&owner, &name, &["0.1.0"]).await?;
+        let err = test
+            .api
+            .report_package(&reporter.token, &name, report("Spam"))
+            .await
+            .unwrap_err();
+        let err = err.downcast_ref::<ApiError>().unwrap();
+        assert_eq!(err.code, OnyxErrorCode::RateLimited);
+        Ok(())
+    }
+}
@@ -0,0 +1,1352 @@
+use std::sync::LazyLock;
+
+use axum::response::Html;
+use axum::response::Json as ResponseJson;
+use nargo_parse::PackageDocs;
+use schemars::JsonSchema;
+use schemars::Schema;
+use schemars::SchemaGenerator;
+use schemars::generate::SchemaSettings;
+use serde_json::Map;
+use serde_json::Value;
+use serde_json::json;
+
+use onyx_api::prelude::*;
+
+type SchemaFn = fn(&mut SchemaGenerator) -> Schema;
+
+fn schema<T: JsonSchema>() -> SchemaFn {
+    SchemaGenerator::subschema_for::<T>
+}
+
+enum Auth {
+    None,
+    /// `Authorization: Bearer <token>`
+    Bearer,
+    /// A bearer token belonging to an admin.
+    Admin,
+}
+
+enum RequestBody {
+    None,
+    Json(SchemaFn),
+    /// The publish upload, a tarball and a JSON encoded `PublishData`.
+    Publish,
+}
+
+enum ResponseBody {
+    Json(SchemaFn),
+    NoContent,
+    /// A package tarball, or a redirect to it when downloads are served from a CDN.
+    Tarball,
+    /// A file in the package index, cacheable with `ETag`.
+    IndexFile,
+    /// A delta from the version given by the `from` query parameter, see
+    /// `nrpm_tarball::delta`.
+    Delta,
+}
+
+struct Operation {
+    method: &'static str,
+    /// Path as registered with the router. Wildcards are written `{*name}`.
+    path: &'static str,
+    tag: &'static str,
+    summary: &'static str,
+    auth: Auth,
+    /// `(name, description)` of optional integer query parameters.
+    query: &'static [(&'static str, &'static str)],
+    /// `(name, description)` of optional string query parameters.
+    string_query: &'static [(&'static str, &'static str)],
+    /// `(name, description)` of required string query parameters.
+    required_query: &'static [(&'static str, &'static str)],
+    request: RequestBody,
+    response: ResponseBody,
+}
+
+/// Every endpoint of the HTTP API, in the order they're routed in `main.rs`. The git
+/// endpoints only exist to satisfy `nargo` and are left out.
+fn operations() -> Vec<Operation> {
+    vec![
+        Operation {
+            method: "get",
+            path: "/v0/packages",
+            tag: "packages",
+            summary: "List every package with its latest version",
+            auth: Auth::None,
+            query: &[],
+            string_query: &[],
+            required_query: &[],
+            request: RequestBody::None,
+            response: ResponseBody::Json(schema::<Vec<(PackageModel, PackageVersionModel)>>()),
+        },
+        Operation {
+            method: "post",
+            path: "/v0/packages/batch",
+            tag: "packages",
+            summary: "Load the index entries of many packages at once, with the newest version matching a semver requirement for each",
+            auth: Auth::None,
+            query: &[],
+            string_query: &[],
+            required_query: &[],
+            request: RequestBody::Json(schema::<BatchPackagesRequest>()),
+            response: ResponseBody::Json(schema::<BatchPackagesResponse>()),
+        },
+        Operation {
+            method: "get",
+            path: "/v0/search",
+            tag: "packages",
+            summary: "Search packages by text, each other query parameter filters by a key of their [package.metadata.nrpm] table",
+            auth: Auth::None,
+            query: &[],
+            string_query: &[(
+                "q",
+                "Text in the name, description or keywords of the package",
+            )],
+            required_query: &[],
+            request: RequestBody::None,
+            response: ResponseBody::Json(schema::<Vec<(PackageModel, PackageVersionModel)>>()),
+        },
+        Operation {
+            method: "post",
+            path: "/v0/publish",
+            tag: "publish",
+            summary: "Publish a new version of a package",
+            auth: Auth::None,
+            query: &[],
+            string_query: &[],
+            required_query: &[],
+            request: RequestBody::Publish,
+            response: ResponseBody::Json(schema::<PublishResponse>()),
+        },
+        Operation {
+            method: "get",
+            path: "/v0/publish/{id}",
+            tag: "publish",
+            summary: "Whether an upload of the user is published, pending a scan, flagged or rejected",
+            auth: Auth::Bearer,
+            query: &[],
+            string_query: &[],
+            required_query: &[],
+            request: RequestBody::None,
+            response: ResponseBody::Json(schema::<PublishStatusResponse>()),
+        },
+        Operation {
+            method: "post",
+            path: "/v0/signup",
+            tag: "auth",
+            summary: "Create an account",
+            auth: Auth::None,
+            query: &[],
+            string_query: &[],
+            required_query: &[],
+            request: RequestBody::Json(schema::<LoginRequest>()),
+            response: ResponseBody::Json(schema::<LoginResponse>()),
+        },
+        Operation {
+            method: "post",
+            path: "/v0/login",
+            tag: "auth",
+            summary: "Log in with a username and password",
+            auth: Auth::None,
+            query: &[],
+            string_query: &[],
+            required_query: &[],
+            request: RequestBody::Json(schema::<LoginRequest>()),
+            response: ResponseBody::Json(schema::<LoginResponse>()),
+        },
+        Operation {
+            method: "post",
+            path: "/v0/auth",
+            tag: "auth",
+            summary: "Check an access token or exchange a refresh token for a new one",
+            auth: Auth::None,
+            query: &[],
+            string_query: &[],
+            required_query: &[],
+            request: RequestBody::Json(schema::<AuthRequest>()),
+            response: ResponseBody::Json(schema::<LoginResponse>()),
+        },
+        Operation {
+            method: "post",
+            path: "/v0/propose_token",
+            tag: "auth",
+            summary: "Activate a token generated by another client, e.g. the cli",
+            auth: Auth::None,
+            query: &[],
+            string_query: &[],
+            required_query: &[],
+            request: RequestBody::Json(schema::<ProposeToken>()),
+            response: ResponseBody::NoContent,
+        },
+        Operation {
+            method: "get",
+            path: "/v0/sessions",
+            tag: "auth",
+            summary: "List the active sessions of the authenticated user",
+            auth: Auth::Bearer,
+            query: &[],
+            string_query: &[],
+            required_query: &[],
+            request: RequestBody::None,
+            response: ResponseBody::Json(schema::<Vec<SessionInfo>>()),
+        },
+        Operation {
+            method: "delete",
+            path: "/v0/sessions/{token_prefix}",
+            tag: "auth",
+            summary: "Revoke the session whose token starts with `token_prefix`",
+            auth: Auth::Bearer,
+            query: &[],
+            string_query: &[],
+            required_query: &[],
+            request: RequestBody::None,
+            response: ResponseBody::NoContent,
+        },
+        Operation {
+            method: "patch",
+            path: "/v0/user",
+            tag: "auth",
+            summary: "Change the username or password of the authenticated user, given their current password",
+            auth: Auth::Bearer,
+            query: &[],
+            string_query: &[],
+            required_query: &[],
+            request: RequestBody::Json(schema::<UpdateUserRequest>()),
+            response: ResponseBody::Json(schema::<UserModelSafe>()),
+        },
+        Operation {
+            method: "delete",
+            path: "/v0/user",
+            tag: "auth",
+            summary: "Delete the authenticated user, given their password. Fails while they own packages",
+            auth: Auth::Bearer,
+            query: &[],
+            string_query: &[],
+            required_query: &[],
+            request: RequestBody::Json(schema::<DeleteUserRequest>()),
+            response: ResponseBody::NoContent,
+        },
+        Operation {
+            method: "get",
+            path: "/v0/webhook",
+            tag: "auth",
+            summary: "The webhook the authenticated user's notifications are sent to",
+            auth: Auth::Bearer,
+            query: &[],
+            string_query: &[],
+            required_query: &[],
+            request: RequestBody::None,
+            response: ResponseBody::Json(schema::<WebhookModel>()),
+        },
+        Operation {
+            method: "put",
+            path: "/v0/webhook",
+            tag: "auth",
+            summary: "Send notifications, like publishes by tokens that aren't browser sessions, to a webhook signed with a new secret",
+            auth: Auth::Bearer,
+            query: &[],
+            string_query: &[],
+            required_query: &[],
+            request: RequestBody::Json(schema::<SetWebhookRequest>()),
+            response: ResponseBody::Json(schema::<WebhookModel>()),
+        },
+        Operation {
+            method: "delete",
+            path: "/v0/webhook",
+            tag: "auth",
+            summary: "Stop sending the authenticated user's notifications",
+            auth: Auth::Bearer,
+            query: &[],
+            string_query: &[],
+            required_query: &[],
+            request: RequestBody::None,
+            response: ResponseBody::NoContent,
+        },
+        Operation {
+            method: "get",
+            path: "/v0/notifications",
+            tag: "notifications",
+            summary: "Versions published by the packages the authenticated user watches, newest first",
+            auth: Auth::Bearer,
+            query: &[("limit", "Maximum number of notifications, at most 500")],
+            string_query: &[],
+            required_query: &[],
+            request: RequestBody::None,
+            response: ResponseBody::Json(schema::<NotificationsResponse>()),
+        },
+        Operation {
+            method: "post",
+            path: "/v0/notifications/read",
+            tag: "notifications",
+            summary: "Mark the authenticated user's notifications up to a sequence number read",
+            auth: Auth::Bearer,
+            query: &[],
+            string_query: &[],
+            required_query: &[],
+            request: RequestBody::Json(schema::<MarkNotificationsReadRequest>()),
+            response: ResponseBody::NoContent,
+        },
+        Operation {
+            method: "put",
+            path: "/v0/notifications/digest",
+            tag: "notifications",
+            summary: "Send a daily digest of new notifications to the authenticated user's webhook",
+            auth: Auth::Bearer,
+            query: &[],
+            string_query: &[],
+            required_query: &[],
+            request: RequestBody::None,
+            response: ResponseBody::NoContent,
+        },
+        Operation {
+            method: "delete",
+            path: "/v0/notifications/digest",
+            tag: "notifications",
+            summary: "Stop sending the authenticated user digests",
+            auth: Auth::Bearer,
+            query: &[],
+            string_query: &[],
+            required_query: &[],
+            request: RequestBody::None,
+            response: ResponseBody::NoContent,
+        },
+        Operation {
+            method: "get",
+            path: "/v0/watches",
+            tag: "notifications",
+            summary: "Names of the packages the authenticated user watches",
+            auth: Auth::Bearer,
+            query: &[],
+            string_query: &[],
+            required_query: &[],
+            request: RequestBody::None,
+            response: ResponseBody::Json(schema::<Vec<String>>()),
+        },
+        Operation {
+            method: "put",
+            path: "/v0/packages/{package_name}/watch",
+            tag: "notifications",
+            summary: "Notify the authenticated user of the versions a package publishes",
+            auth: Auth::Bearer,
+            query: &[],
+            string_query: &[],
+            required_query: &[],
+            request: RequestBody::None,
+            response: ResponseBody::NoContent,
+        },
+        Operation {
+            method: "delete",
+            path: "/v0/packages/{package_name}/watch",
+            tag: "notifications",
+            summary: "Stop notifying the authenticated user of a package",
+            auth: Auth::Bearer,
+            query: &[],
+            string_query: &[],
+            required_query: &[],
+            request: RequestBody::None,
+            response: ResponseBody::NoContent,
+        },
+        Operation {
+            method: "post",
+            path: "/v0/keys",
+            tag: "keys",
+            summary: "Add a signing key to the authenticated user, optionally rotating out an active key",
+            auth: Auth::Bearer,
+            query: &[],
+            string_query: &[],
+            required_query: &[],
+            request: RequestBody::Json(schema::<AddKeyRequest>()),
+            response: ResponseBody::Json(schema::<UserKeyModel>()),
+        },
+        Operation {
+            method: "delete",
+            path: "/v0/keys/{public_key}",
+            tag: "keys",
+            summary: "Revoke a signing key of the authenticated user",
+            auth: Auth::Bearer,
+            query: &[],
+            string_query: &[],
+            required_query: &[],
+            request: RequestBody::None,
+            response: ResponseBody::Json(schema::<UserKeyModel>()),
+        },
+        Operation {
+            method: "get",
+            path: "/v0/users/{username}/keys",
+            tag: "keys",
+            summary: "Every signing key of a user, oldest first, including rotated and revoked keys",
+            auth: Auth::None,
+            query: &[],
+            string_query: &[],
+            required_query: &[],
+            request: RequestBody::None,
+            response: ResponseBody::Json(schema::<Vec<UserKeyModel>>()),
+        },
+        Operation {
+            method: "get",
+            path: "/v0/version/{id}",
+            tag: "download",
+            summary: "Download the tarball of a version",
+            auth: Auth::None,
+            query: &[],
+            string_query: &[],
+            required_query: &[],
+            request: RequestBody::None,
+            response: ResponseBody::Tarball,
+        },
+        Operation {
+            method: "get",
+            path: "/v0/packages/{package_name}/{version_name}/download",
+            tag: "download",
+            summary: "Download the tarball of a version by package and version name",
+            auth: Auth::None,
+            query: &[],
+            string_query: &[],
+            required_query: &[],
+            request: RequestBody::None,
+            response: ResponseBody::Tarball,
+        },
+        Operation {
+            method: "get",
+            path: "/v0/version/{id}/docs",
+            tag: "download",
+            summary: "Documentation extracted from the doc comments of a version",
+            auth: Auth::None,
+            query: &[],
+            string_query: &[],
+            required_query: &[],
+            request: RequestBody::None,
+            response: ResponseBody::Json(schema::<PackageDocs>()),
+        },
+        Operation {
+            method: "get",
+            path: "/v0/version/{id}/manifest",
+            tag: "download",
+            summary: "The blake3 hash of every file in a version",
+            auth: Auth::None,
+            query: &[],
+            string_query: &[],
+            required_query: &[],
+            request: RequestBody::None,
+            response: ResponseBody::Json(schema::<VersionManifest>()),
+        },
+        Operation {
+            method: "get",
+            path: "/v0/version/{id}/release_notes",
+            tag: "download",
+            summary: "The release notes a version was published with. Not found if it has none",
+            auth: Auth::None,
+            query: &[],
+            string_query: &[],
+            required_query: &[],
+            request: RequestBody::None,
+            response: ResponseBody::Json(schema::<VersionReleaseNotes>()),
+        },
+        Operation {
+            method: "get",
+            path: "/v0/version/{id}/artifacts",
+            tag: "download",
+            summary: "The compiled artifacts attached to a version, sorted by name",
+            auth: Auth::None,
+            query: &[],
+            string_query: &[],
+            required_query: &[],
+            request: RequestBody::None,
+            response: ResponseBody::Json(schema::<Vec<VersionArtifactModel>>()),
+        },
+        Operation {
+            method: "post",
+            path: "/v0/version/{id}/artifacts",
+            tag: "publish",
+            summary: "Attach a compiled artifact to a version of a package owned by the authenticated user",
+            auth: Auth::Bearer,
+            query: &[],
+            string_query: &[],
+            required_query: &[],
+            request: RequestBody::Json(schema::<UploadArtifactRequest>()),
+            response: ResponseBody::Json(schema::<VersionArtifactModel>()),
+        },
+        Operation {
+            method: "get",
+            path: "/v0/version/{id}/artifacts/{name}",
+            tag: "download",
+            summary: "The json of an artifact attached to a version, as written by nargo compile",
+            auth: Auth::None,
+            query: &[],
+            string_query: &[],
+            required_query: &[],
+            request: RequestBody::None,
+            response: ResponseBody::Json(schema::<serde_json::Value>()),
+        },
+        Operation {
+            method: "get",
+            path: "/v0/version/{id}/build",
+            tag: "download",
+            summary: "The latest rebuild of a version and whether its artifacts match. Not found if it hasn't been rebuilt",
+            auth: Auth::None,
+            query: &[],
+            string_query: &[],
+            required_query: &[],
+            request: RequestBody::None,
+            response: ResponseBody::Json(schema::<VersionBuildModel>()),
+        },
+        Operation {
+            method: "get",
+            path: "/v0/version/{id}/delta",
+            tag: "download",
+            summary: "The files that changed since an earlier version. Not found if the registry has no delta from it",
+            auth: Auth::None,
+            query: &[],
+            string_query: &[],
+            required_query: &[],
+            request: RequestBody::None,
+            response: ResponseBody::Delta,
+        },
+        Operation {
+            method: "patch",
+            path: "/v0/packages/{package_name}",
+            tag: "packages",
+            summary: "Change the description, keywords, repository or deprecation of a package",
+            auth: Auth::Bearer,
+            query: &[],
+            string_query: &[],
+            required_query: &[],
+            request: RequestBody::Json(schema::<UpdatePackageRequest>()),
+            response: ResponseBody::Json(schema::<PackageModel>()),
+        },
+        Operation {
+            method: "get",
+            path: "/v0/packages/{package_name}/latest",
+            tag: "packages",
+            summary: "Load a package and its latest version",
+            auth: Auth::None,
+            query: &[],
+            string_query: &[],
+            required_query: &[],
+            request: RequestBody::None,
+            response: ResponseBody::Json(schema::<(PackageModel, PackageVersionModel)>()),
+        },
+        Operation {
+            method: "get",
+            path: "/v0/packages/{package_name}/versions",
+            tag: "packages",
+            summary: "Load a package and all of its versions",
+            auth: Auth::None,
+            query: &[],
+            string_query: &[],
+            required_query: &[],
+            request: RequestBody::None,
+            response: ResponseBody::Json(schema::<(PackageModel, Vec<PackageVersionModel>)>()),
+        },
+        Operation {
+            method: "get",
+            path: "/v0/packages/{package_name}/graph",
+            tag: "packages",
+            summary: "Dependencies and dependents of a package",
+            auth: Auth::None,
+            query: &[],
+            string_query: &[],
+            required_query: &[],
+            request: RequestBody::None,
+            response: ResponseBody::Json(schema::<PackageGraphResponse>()),
+        },
+        Operation {
+            method: "get",
+            path: "/v0/packages/{package_name}/graph/tree",
+            tag: "packages",
+            summary: "Every registry package a version depends on, directly or indirectly",
+            auth: Auth::None,
+            query: &[],
+            string_query: &[(
+                "version",
+                "Name of the version, the latest version by default",
+            )],
+            required_query: &[],
+            request: RequestBody::None,
+            response: ResponseBody::Json(schema::<DependencyTreeResponse>()),
+        },
+        Operation {
+            method: "get",
+            path: "/v0/packages/{package_name}/diff",
+            tag: "packages",
+            summary: "The files that differ between two versions, with unified diffs of small text files",
+            auth: Auth::None,
+            query: &[],
+            string_query: &[],
+            required_query: &[
+                ("from", "Name of the version to compare from"),
+                ("to", "Name of the version to compare to"),
+            ],
+            request: RequestBody::None,
+            response: ResponseBody::Json(schema::<VersionDiff>()),
+        },
+        Operation {
+            method: "post",
+            path: "/v0/packages/{package_name}/transfer",
+            tag: "transfers",
+            summary: "Offer a package to another user",
+            auth: Auth::Bearer,
+            query: &[],
+            string_query: &[],
+            required_query: &[],
+            request: RequestBody::Json(schema::<TransferPackageRequest>()),
+            response: ResponseBody::Json(schema::<TransferRequestModel>()),
+        },
+        Operation {
+            method: "delete",
+            path: "/v0/packages/{package_name}/transfer",
+            tag: "transfers",
+            summary: "Cancel or decline a pending transfer",
+            auth: Auth::Bearer,
+            query: &[],
+            string_query: &[],
+            required_query: &[],
+            request: RequestBody::None,
+            response: ResponseBody::NoContent,
+        },
+        Operation {
+            method: "post",
+            path: "/v0/packages/{package_name}/transfer/accept",
+            tag: "transfers",
+            summary: "Accept a transfer offered to the authenticated user",
+            auth: Auth::Bearer,
+            query: &[],
+            string_query: &[],
+            required_query: &[],
+            request: RequestBody::None,
+            response: ResponseBody::Json(schema::<PackageModel>()),
+        },
+        Operation {
+            method: "post",
+            path: "/v0/packages/{package_name}/claim",
+            tag: "transfers",
+            summary: "Ask the admins for ownership of an abandoned package",
+            auth: Auth::Bearer,
+            query: &[],
+            string_query: &[],
+            required_query: &[],
+            request: RequestBody::Json(schema::<ClaimPackageRequest>()),
+            response: ResponseBody::Json(schema::<PackageClaimModel>()),
+        },
+        Operation {
+            method: "post",
+            path: "/v0/packages/{package_name}/report",
+            tag: "moderation",
+            summary: "Report a package to the admins, e.g. for malware or typosquatting",
+            auth: Auth::Bearer,
+            query: &[],
+            string_query: &[],
+            required_query: &[],
+            request: RequestBody::Json(schema::<ReportPackageRequest>()),
+            response: ResponseBody::Json(schema::<PackageReportModel>()),
+        },
+        Operation {
+            method: "get",
+            path: "/v0/packages/{package_name}/stats",
+            tag: "packages",
+            summary: "How many times each version was downloaded, including installs reported by clients",
+            auth: Auth::None,
+            query: &[],
+            string_query: &[],
+            required_query: &[],
+            request: RequestBody::None,
+            response: ResponseBody::Json(schema::<PackageStatsResponse>()),
+        },
+        Operation {
+            method: "post",
+            path: "/v0/stats/installs",
+            tag: "packages",
+            summary: "Count versions a client installed without downloading them from the registry",
+            auth: Auth::None,
+            query: &[],
+            string_query: &[],
+            required_query: &[],
+            request: RequestBody::Json(schema::<InstallStatsRequest>()),
+            response: ResponseBody::NoContent,
+        },
+        Operation {
+            method: "get",
+            path: "/v0/changelog",
+            tag: "packages",
+            summary: "Published versions in publish order",
+            auth: Auth::None,
+            query: &[
+                ("since", "Only return entries after this sequence number"),
+                ("limit", "Maximum number of entries, at most 1000"),
+            ],
+            string_query: &[],
+            required_query: &[],
+            request: RequestBody::None,
+            response: ResponseBody::Json(schema::<ChangelogResponse>()),
+        },
+        Operation {
+            method: "get",
+            path: "/v0/meta",
+            tag: "meta",
+            summary: "The api version of the registry, the oldest client api version it works with, and the optional features it provides. Every response carries the api version in the onyx-api-version header",
+            auth: Auth::None,
+            query: &[],
+            string_query: &[],
+            required_query: &[],
+            request: RequestBody::None,
+            response: ResponseBody::Json(schema::<MetaResponse>()),
+        },
+        Operation {
+            method: "get",
+            path: "/v0/cli/version",
+            tag: "meta",
+            summary: "The latest release of the nrpm cli. Not found if the registry doesn't announce one",
+            auth: Auth::None,
+            query: &[],
+            string_query: &[],
+            required_query: &[],
+            request: RequestBody::None,
+            response: ResponseBody::Json(schema::<CliVersionResponse>()),
+        },
+        Operation {
+            method: "get",
+            path: "/v0/root",
+            tag: "meta",
+            summary: "The latest signed RegistryRoot, naming the keys trusted to sign snapshots. Not found if the registry doesn't sign snapshots",
+            auth: Auth::None,
+            query: &[],
+            string_query: &[],
+            required_query: &[],
+            request: RequestBody::None,
+            response: ResponseBody::Json(schema::<SignedDocument>()),
+        },
+        Operation {
+            method: "get",
+            path: "/v0/root/{version}",
+            tag: "meta",
+            summary: "A version of the signed RegistryRoot, to follow key rotations from an older one",
+            auth: Auth::None,
+            query: &[],
+            string_query: &[],
+            required_query: &[],
+            request: RequestBody::None,
+            response: ResponseBody::Json(schema::<SignedDocument>()),
+        },
+        Operation {
+            method: "get",
+            path: "/v0/snapshot",
+            tag: "packages",
+            summary: "The latest signed Snapshot of every published version and its hash. Not found if the registry doesn't sign snapshots",
+            auth: Auth::None,
+            query: &[],
+            string_query: &[],
+            required_query: &[],
+            request: RequestBody::None,
+            response: ResponseBody::Json(schema::<SignedDocument>()),
+        },
+        Operation {
+            method: "get",
+            path: "/v0/log",
+            tag: "transparency",
+            summary: "Size and Merkle root of the transparency log of publishes",
+            auth: Auth::None,
+            query: &[],
+            string_query: &[],
+            required_query: &[],
+            request: RequestBody::None,
+            response: ResponseBody::Json(schema::<LogHead>()),
+        },
+        Operation {
+            method: "get",
+            path: "/v0/log/entries",
+            tag: "transparency",
+            summary: "Transparency log entries in publish order",
+            auth: Auth::None,
+            query: &[
+                ("start", "Index of the first entry to return"),
+                ("limit", "Maximum number of entries, at most 1000"),
+            ],
+            string_query: &[],
+            required_query: &[],
+            request: RequestBody::None,
+            response: ResponseBody::Json(schema::<LogEntriesResponse>()),
+        },
+        Operation {
+            method: "get",
+            path: "/v0/log/proof/{id}",
+            tag: "transparency",
+            summary: "Proof that the publish of a version is in the transparency log",
+            auth: Auth::None,
+            query: &[(
+                "tree_size",
+                "Size of the log to prove against, the current size by default",
+            )],
+            string_query: &[],
+            required_query: &[],
+            request: RequestBody::None,
+            response: ResponseBody::Json(schema::<InclusionProof>()),
+        },
+        Operation {
+            method: "get",
+            path: "/v0/log/consistency",
+            tag: "transparency",
+            summary: "Proof that the transparency log at one size extends it at a smaller size",
+            auth: Auth::None,
+            query: &[
+                ("from", "The smaller size"),
+                ("to", "The larger size, the current size by default"),
+            ],
+            string_query: &[],
+            required_query: &[],
+            request: RequestBody::None,
+            response: ResponseBody::Json(schema::<ConsistencyProof>()),
+        },
+        Operation {
+            method: "get",
+            path: "/v0/index/{*path}",
+            tag: "packages",
+            summary: "A package's file in the sparse index",
+            auth: Auth::None,
+            query: &[],
+            string_query: &[],
+            required_query: &[],
+            request: RequestBody::None,
+            response: ResponseBody::IndexFile,
+        },
+        Operation {
+            method: "get",
+            path: "/v0/transfers",
+            tag: "transfers",
+            summary: "Pending transfers involving the authenticated user",
+            auth: Auth::Bearer,
+            query: &[],
+            string_query: &[],
+            required_query: &[],
+            request: RequestBody::None,
+            response: ResponseBody::Json(schema::<TransfersResponse>()),
+        },
+        Operation {
+            method: "get",
+            path: "/v0/usage",
+            tag: "auth",
+            summary: "Bytes stored for packages the authenticated user owns and their storage quota",
+            auth: Auth::Bearer,
+            query: &[],
+            string_query: &[],
+            required_query: &[],
+            request: RequestBody::None,
+            response: ResponseBody::Json(schema::<StorageUsage>()),
+        },
+        Operation {
+            method: "get",
+            path: "/v0/admin/claims",
+            tag: "admin",
+            summary: "List open package claims",
+            auth: Auth::Admin,
+            query: &[],
+            string_query: &[],
+            required_query: &[],
+            request: RequestBody::None,
+            response: ResponseBody::Json(schema::<Vec<PackageClaimModel>>()),
+        },
+        Operation {
+            method: "post",
+            path: "/v0/admin/index",
+            tag: "admin",
+            summary: "Rebuild the static package index",
+            auth: Auth::Admin,
+            query: &[],
+            string_query: &[],
+            required_query: &[],
+            request: RequestBody::None,
+            response: ResponseBody::Json(schema::<IndexExportResponse>()),
+        },
+        Operation {
+            method: "post",
+            path: "/v0/admin/import",
+            tag: "admin",
+            summary: "Publish tags of git repositories as versions owned by a user",
+            auth: Auth::Admin,
+            query: &[],
+            string_query: &[],
+            required_query: &[],
+            request: RequestBody::Json(schema::<ImportRequest>()),
+            response: ResponseBody::Json(schema::<ImportResponse>()),
+        },
+        Operation {
+            method: "post",
+            path: "/v0/admin/packages/{package_name}/transfer",
+            tag: "admin",
+            summary: "Move a package to another user",
+            auth: Auth::Admin,
+            query: &[],
+            string_query: &[],
+            required_query: &[],
+            request: RequestBody::Json(schema::<AdminTransferRequest>()),
+            response: ResponseBody::Json(schema::<PackageModel>()),
+        },
+        Operation {
+            method: "get",
+            path: "/v0/admin/users/{username}/quota",
+            tag: "admin",
+            summary: "The storage a user uses and their quota",
+            auth: Auth::Admin,
+            query: &[],
+            string_query: &[],
+            required_query: &[],
+            request: RequestBody::None,
+            response: ResponseBody::Json(schema::<StorageUsage>()),
+        },
+        Operation {
+            method: "put",
+            path: "/v0/admin/users/{username}/quota",
+            tag: "admin",
+            summary: "Set the storage quota of a user, null for the registry default",
+            auth: Auth::Admin,
+            query: &[],
+            string_query: &[],
+            required_query: &[],
+            request: RequestBody::Json(schema::<SetQuotaRequest>()),
+            response: ResponseBody::Json(schema::<StorageUsage>()),
+        },
+        Operation {
+            method: "get",
+            path: "/v0/admin/reports",
+            tag: "moderation",
+            summary: "List open package reports, oldest first",
+            auth: Auth::Admin,
+            query: &[],
+            string_query: &[],
+            required_query: &[],
+            request: RequestBody::None,
+            response: ResponseBody::Json(schema::<Vec<PackageReportModel>>()),
+        },
+        Operation {
+            method: "post",
+            path: "/v0/admin/reports/{id}/resolve",
+            tag: "moderation",
+            summary: "Dismiss a report, or yank, delete, or ban in response to it",
+            auth: Auth::Admin,
+            query: &[],
+            string_query: &[],
+            required_query: &[],
+            request: RequestBody::Json(schema::<ResolveReportRequest>()),
+            response: ResponseBody::Json(schema::<PackageReportModel>()),
+        },
+        Operation {
+            method: "get",
+            path: "/v0/admin/names",
+            tag: "moderation",
+            summary: "List names held for review because they resemble popular packages",
+            auth: Auth::Admin,
+            query: &[],
+            string_query: &[],
+            required_query: &[],
+            request: RequestBody::None,
+            response: ResponseBody::Json(schema::<Vec<NameReviewModel>>()),
+        },
+        Operation {
+            method: "delete",
+            path: "/v0/admin/names/{package_name}",
+            tag: "moderation",
+            summary: "Decline a name held for review",
+            auth: Auth::Admin,
+            query: &[],
+            string_query: &[],
+            required_query: &[],
+            request: RequestBody::None,
+            response: ResponseBody::NoContent,
+        },
+        Operation {
+            method: "post",
+            path: "/v0/admin/names/{package_name}/approve",
+            tag: "moderation",
+            summary: "Let the user who requested a held name publish it",
+            auth: Auth::Admin,
+            query: &[],
+            string_query: &[],
+            required_query: &[],
+            request: RequestBody::None,
+            response: ResponseBody::Json(schema::<NameReviewModel>()),
+        },
+        Operation {
+            method: "get",
+            path: "/v0/admin/publishes",
+            tag: "moderation",
+            summary: "List uploads waiting to be scanned or flagged by a scanner",
+            auth: Auth::Admin,
+            query: &[],
+            string_query: &[],
+            required_query: &[],
+            request: RequestBody::None,
+            response: ResponseBody::Json(schema::<Vec<PendingPublishModel>>()),
+        },
+        Operation {
+            method: "delete",
+            path: "/v0/admin/publishes/{id}",
+            tag: "moderation",
+            summary: "Reject a pending or flagged upload",
+            auth: Auth::Admin,
+            query: &[],
+            string_query: &[],
+            required_query: &[],
+            request: RequestBody::None,
+            response: ResponseBody::NoContent,
+        },
+        Operation {
+            method: "post",
+            path: "/v0/admin/publishes/{id}/release",
+            tag: "moderation",
+            summary: "Publish a pending or flagged upload regardless of what the scanners found",
+            auth: Auth::Admin,
+            query: &[],
+            string_query: &[],
+            required_query: &[],
+            request: RequestBody::None,
+            response: ResponseBody::Json(schema::<PublishStatusResponse>()),
+        },
+        Operation {
+            method: "get",
+            path: "/v0/admin/audit",
+            tag: "moderation",
+            summary: "Moderation decisions in the order they were made",
+            auth: Auth::Admin,
+            query: &[
+                ("since", "Only return entries after this sequence number"),
+                ("limit", "Maximum number of entries, at most 1000"),
+            ],
+            string_query: &[],
+            required_query: &[],
+            request: RequestBody::None,
+            response: ResponseBody::Json(schema::<AuditLogResponse>()),
+        },
+        Operation {
+            method: "get",
+            path: "/v0/admin/db",
+            tag: "admin",
+            summary: "Row counts and sizes of each database table",
+            auth: Auth::Admin,
+            query: &[],
+            string_query: &[],
+            required_query: &[],
+            request: RequestBody::None,
+            response: ResponseBody::Json(schema::<DbStatsResponse>()),
+        },
+        Operation {
+            method: "get",
+            path: "/v0/admin/db/metrics",
+            tag: "admin",
+            summary: "Number and duration of database transactions by route, and the tables they used",
+            auth: Auth::Admin,
+            query: &[],
+            string_query: &[],
+            required_query: &[],
+            request: RequestBody::None,
+            response: ResponseBody::Json(schema::<DbMetricsResponse>()),
+        },
+        Operation {
+            method: "post",
+            path: "/v0/admin/db/check",
+            tag: "admin",
+            summary: "Find and optionally repair rows that refer to missing rows or tarballs",
+            auth: Auth::Admin,
+            query: &[],
+            string_query: &[],
+            required_query: &[],
+            request: RequestBody::Json(schema::<DbCheckRequest>()),
+            response: ResponseBody::Json(schema::<DbCheckResponse>()),
+        },
+    ]
+}
+
+fn json_content(schema: Value) -> Value {
+    json!({ "application/json": { "schema": schema } })
+}
+
+impl Operation {
+    fn to_value(&self, generator: &mut SchemaGenerator) -> Value {
+        let mut parameters = vec![];
+        for segment in self.path.split('/') {
+            if let Some(name) = segment.strip_prefix('{').and_then(|s| s.strip_suffix('}')) {
+                parameters.push(json!({
+                    "name": name.trim_start_matches('*'),
+                    "in": "path",
+                    "required": true,
+                    "schema": { "type": "string" },
+                }));
+            }
+        }
+        for (name, description) in self.query {
+            parameters.push(json!({
+                "name": name,
+                "in": "query",
+                "required": false,
+                "description": description,
+                "schema": { "type": "integer", "minimum": 0 },
+            }));
+        }
+        for (name, description) in self.string_query {
+            parameters.push(json!({
+                "name": name,
+                "in": "query",
+                "required": false,
+                "description": description,
+                "schema": { "type": "string" },
+            }));
+        }
+        for (name, description) in self.required_query {
+            parameters.push(json!({
+                "name": name,
+                "in": "query",
+                "required": true,
+                "description": description,
+                "schema": { "type": "string" },
+            }));
+        }
+
+        let mut responses = Map::new();
+        match &self.response {
+            ResponseBody::Json(schema) => {
+                responses.insert(
+                    "200".to_string(),
+                    json!({
+                        "description": "OK",
+                        "content": json_content(schema(generator).to_value()),
+                    }),
+                );
+            }
+            ResponseBody::NoContent => {
+                responses.insert("204".to_string(), json!({ "description": "No Content" }));
+            }
+            ResponseBody::Tarball => {
+                responses.insert(
+                    "200".to_string(),
+                    json!({
+                        "description": "The package tarball",
+                        "content": {
+                            "application/octet-stream": {
+                                "schema": { "type": "string", "format": "binary" },
+                            },
+                        },
+                    }),
+                );
+                responses.insert(
+                    "302".to_string(),
+                    json!({
+                        "description": "Redirect to a signed CDN url for the tarball",
+                        "headers": { "Location": { "schema": { "type": "string" } } },
+                    }),
+                );
+            }
+            ResponseBody::Delta => {
+                parameters.push(json!({
+                    "name": "from",
+                    "in": "query",
+                    "required": true,
+                    "description": "Id of the version the delta applies to",
+                    "schema": { "type": "string" },
+                }));
+                responses.insert(
+                    "200".to_string(),
+                    json!({
+                        "description": "The delta",
+                        "content": {
+                            "application/octet-stream": {
+                                "schema": { "type": "string", "format": "binary" },
+                            },
+                        },
+                    }),
+                );
+            }
+            ResponseBody::IndexFile => {
+                parameters.push(json!({
+                    "name": "If-None-Match",
+                    "in": "header",
+                    "required": false,
+                    "schema": { "type": "string" },
+                }));
+                responses.insert(
+                    "200".to_string(),
+                    json!({
+                        "description": "OK",
+                        "headers": { "ETag": { "schema": { "type": "string" } } },
+                        "content": json_content(generator.subschema_for::<IndexPackage>().to_value()),
+                    }),
+                );
+                responses.insert(
+                    "304".to_string(),
+                    json!({ "description": "The cached copy is current" }),
+                );
+            }
+        }
+        responses.insert(
+            "default".to_string(),
+            json!({
+                "description": "Error",
+                "content": json_content(generator.subschema_for::<ApiError>().to_value()),
+            }),
+        );
+
+        let mut operation = json!({
+            "tags": [self.tag],
+            "summary": self.summary,
+            "parameters": parameters,
+            "responses": responses,
+        });
+        match &self.request {
+            RequestBody::None => {}
+            RequestBody::Json(schema) => {
+                operation["requestBody"] = json!({
+                    "required": true,
+                    "content": json_content(schema(generator).to_value()),
+                });
+            }
+            RequestBody::Publish => {
+                operation["parameters"]
+                    .as_array_mut()
+                    .expect("parameters is an array")
+                    .push(json!({
+                        "name": IDEMPOTENCY_KEY_HEADER,
+                        "in": "header",
+                        "required": false,
+                        "description": "Makes the publish safe to retry, repeated requests with the same key and tarball return the original response",
+                        "schema": { "type": "string", "maxLength": 255 },
+                    }));
+                operation["requestBody"] = json!({
+                    "required": true,
+                    "content": {
+                        "multipart/form-data": {
+                            "schema": {
+                                "type": "object",
+                                "required": ["tarball", "publish_data"],
+                                "properties": {
+                                    "tarball": {
+                                        "description": "The package tarball, as created by `nrpm_tarball::create`",
+                                        "type": "string",
+                                        "format": "binary",
+                                    },
+                                    "publish_data": generator.subschema_for::<PublishData>().to_value(),
+                                },
+                            },
+                            "encoding": {
+                                "publish_data": { "contentType": "application/json" },
+                            },
+                        },
+                    },
+                });
+            }
+        }
+        match self.auth {
+            Auth::None => {}
+            Auth::Bearer => operation["security"] = json!([{ "bearer": [] }]),
+            Auth::Admin => {
+                operation["security"] = json!([{ "bearer": [] }]);
+                operation["description"] = json!("Requires an admin account.");
+            }
+        }
+        operation
+    }
+}
+
+/// Build the OpenAPI document describing the HTTP API.
+pub fn spec() -> Value {
+    let mut generator = SchemaGenerator::new(SchemaSettings::openapi3());
+    let mut paths = Map::new();
+    for operation in operations() {
+        let path = operation.path.replace("{*", "{");
+        let value = operation.to_value(&mut generator);
+        paths
+            .entry(path)
+            .or_insert_with(|| json!({}))
+            .as_object_mut()
+            .expect("path item is an object")
+            .insert(operation.method.to_string(), value);
+    }
+    json!({
+        "openapi": "3.0.3",
+        "info": {
+            "title": "onyx",
+            "description": "The nrpm package registry",
+            "version": env!("CARGO_PKG_VERSION"),
+        },
+        "paths": paths,
+        "components": {
+            "schemas": generator.take_definitions(true),
+            "securitySchemes": {
+                "bearer": { "type": "http", "scheme": "bearer" },
+            },
+        },
+    })
+}
+
+static SPEC: LazyLock<Value> = LazyLock::new(spec);
+
+pub async fn openapi_json() -> ResponseJson<Value> {
+    ResponseJson(SPEC.clone())
+}
+
+/// Swagger UI for `/openapi.json`, loaded from a CDN.
+pub async fn swagger_ui() -> Html<&'static str> {
+    Html(
+        r##"<!DOCTYPE html>
+<html lang="en">
+<head>
+  <meta charset="utf-8" />
+  <title>onyx API</title>
+  <link rel="stylesheet" href="https://unpkg.com/swagger-ui-dist@5/swagger-ui.css" />
+</head>
+<body>
+  <div id="swagger-ui"></div>
+  <script src="https://unpkg.com/swagger-ui-dist@5/swagger-ui-bundle.js" crossorigin></script>
+  <script>
+    window.onload = () => {
+      window.ui = SwaggerUIBundle({ url: "/openapi.json", dom_id: "#swagger-ui" });
+    };
+  </script>
+</body>
+</html>
+"##,
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use anyhow::Result;
+    use serde_json::Value;
+
+    use crate::testing::OnyxTest;
+
+    #[tokio::test]
+    async fn should_serve_openapi_spec() -> Result<()> {
+        let test = OnyxTest::new().await?;
+        let spec = reqwest::get(format!("{}/openapi.json", test.url))
+            .await?
+            .json::<Value>()
+            .await?;
+        let publish = &spec["paths"]["/v0/publish"]["post"];
+        assert_eq!(
+            publish["requestBody"]["content"]["multipart/form-data"]["schema"]["required"],
+            serde_json::json!(["tarball", "publish_data"])
+        );
+        assert_eq!(
+            spec["paths"]["/v0/login"]["post"]["responses"]["200"]["content"]["application/json"]["schema"]
+                ["$ref"],
+            "#/components/schemas/LoginResponse"
+        );
+        assert_eq!(
+            spec["paths"]["/v0/index/{path}"]["get"]["parameters"][0]["name"],
+            "path"
+        );
+        // every referenced schema is defined
+        let schemas = spec["components"]["schemas"]
+            .as_object()
+            .expect("schemas is an object");
+        let text = spec.to_string();
+        for reference in text.split("\"$ref\":\"#/components/schemas/").skip(1) {
+            let name = reference.split('"').next().unwrap_or_default();
+            assert!(schemas.contains_key(name), "missing schema {name}");
+        }
+
+        let ui = reqwest::get(format!("{}/openapi.html", test.url))
+            .await?
+            .text()
+            .await?;
+        assert!(ui.contains("/openapi.json"));
+        Ok(())
+    }
+}
@@ -0,0 +1,228 @@
+use std::collections::BTreeMap;
+use std::path::Path as FilePath;
+use std::path::PathBuf;
+
+use axum::extract::Path;
+use axum::extract::State;
+use axum::http::HeaderMap;
+use axum::http::header::USER_AGENT;
+use axum::response::Html;
+use axum::response::IntoResponse;
+use axum::response::Response;
+use tokio::io::AsyncReadExt;
+
+use nrpm_markdown::LinkBase;
+use nrpm_markdown::LinkTarget;
+use nrpm_markdown::escape_html;
+use nrpm_markdown::render_markdown;
+use onyx_api::prelude::*;
+
+use super::OnyxError;
+use super::OnyxState;
+use super::git;
+use super::web;
+
+/// Substrings of the user agents of search engines and link previews, compared in lowercase.
+const CRAWLER_AGENTS: [&str; 5] = ["bot", "crawler", "spider", "slurp", "facebookexternalhit"];
+
+fn is_crawler(headers: &HeaderMap) -> bool {
+    let Some(user_agent) = headers.get(USER_AGENT).and_then(|v| v.to_str().ok()) else {
+        return false;
+    };
+    let user_agent = user_agent.to_lowercase();
+    CRAWLER_AGENTS
+        .iter()
+        .any(|agent| user_agent.contains(agent))
+}
+
+/// The package page of the web app, rendered for crawlers. The web app renders in the browser
+/// so crawlers see an empty page there, have them served this instead. Everyone else gets the
+/// web app if it's embedded, otherwise the git response for the package url.
+pub async fn package_page(
+    State(state): State<OnyxState>,
+    Path(package_name): Path<String>,
+    headers: HeaderMap,
+) -> Result<Response, OnyxError> {
+    if !is_crawler(&headers) {
+        return match web::index() {
+            Some(page) => Ok(page),
+            None => git::empty().await,
+        };
+    }
+    let Some((package, version)) = state.db.latest_version(&package_name)? else {
+        return git::empty().await;
+    };
+    let mut bytes = vec![];
+    PackageVersionModel::reader_by_id(state.storage, version.id.clone())
+        .await?
+        .read_to_end(&mut bytes)
+        .await?;
+    let (config, files) = nrpm_tarball::extract_metadata(bytes)?;
+    let mut metadata = version
+        .metadata
+        .clone()
+        .unwrap_or_else(|| VersionMetadata::from(&config));
+    package.metadata.apply(&mut metadata);
+    let files = files.into_iter().collect::<BTreeMap<_, _>>();
+    Ok(Html(render(&package, &version, &metadata, &files)).into_response())
+}
+
+/// Whether `path` is the README at the root of the package.
+fn is_readme(path: &FilePath) -> bool {
+    path.parent().is_some_and(|p| p.as_os_str().is_empty())
+        && path
+            .file_name()
+            .and_then(|name| name.to_str())
+            .is_some_and(|name| name.eq_ignore_ascii_case("README.md"))
+}
+
+fn render(
+    package: &PackageModel,
+    version: &PackageVersionModel,
+    metadata: &VersionMetadata,
+    files: &BTreeMap<PathBuf, Vec<u8>>,
+) -> String {
+    let title = escape_html(&format!("{} {}", package.name, version.name));
+    let description = escape_html(metadata.description.as_deref().unwrap_or_default());
+    let mut body = format!("<h1>{title}</h1>\n");
+    if !description.is_empty() {
+        body.push_str(&format!("<p>{description}</p>\n"));
+    }
+    let mut details = vec![];
+    if !metadata.authors.is_empty() {
+        details.push(("Authors", escape_html(&metadata.authors.join(", "))));
+    }
+    if let Some(repository) = metadata.repository.as_deref() {
+        let link = repository.starts_with("https://") || repository.starts_with("http://");
+        let repository = escape_html(repository);
+        details.push((
+            "Repository",
+            if link {
+                format!("<a href=\"{repository}\">{repository}</a>")
+            } else {
+                repository
+            },
+        ));
+    }
+    if let Some(license) = metadata.license.as_ref().or(package.license.as_ref()) {
+        details.push(("License", escape_html(license)));
+    }
+    if !metadata.keywords.is_empty() {
+        details.push(("Keywords", escape_html(&metadata.keywords.join(", "))));
+    }
+    if !metadata.dependencies.is_empty() {
+        let dependencies = metadata
+            .dependencies
+            .iter()
+            .map(|dependency| match dependency.tag.as_deref() {
+                Some(tag) => escape_html(&format!("{} {tag}", dependency.name)),
+                None => escape_html(&dependency.name),
+            })
+            .collect::<Vec<_>>();
+        details.push(("Dependencies", dependencies.join(", ")));
+    }
+    if !details.is_empty() {
+        body.push_str("<dl>\n");
+        for (term, definition) in details {
+            body.push_str(&format!("<dt>{term}</dt><dd>{definition}</dd>\n"));
+        }
+        body.push_str("</dl>\n");
+    }
+    if let Some((_, readme)) = files.iter().find(|(path, _)| is_readme(path)) {
+        // rendered the same as the README in the web app
+        let target = match metadata.repository.as_deref() {
+            Some(repository) => LinkTarget::Repository(repository),
+            None => LinkTarget::FileBrowser {
+                package_name: &package.name,
+                files,
+            },
+        };
+        let link_base = LinkBase {
+            dir: FilePath::new(""),
+            target,
+        };
+        body.push_str(&format!(
+            "<article>\n{}</article>\n",
+            render_markdown(&String::from_utf8_lossy(readme), Some(link_base))
+        ));
+    }
+    format!(
+        "<!DOCTYPE html>
+<html lang=\"en\">
+<head>
+  <meta charset=\"utf-8\" />
+  <title>{title}</title>
+  <meta name=\"description\" content=\"{description}\" />
+  <meta property=\"og:title\" content=\"{title}\" />
+  <meta property=\"og:description\" content=\"{description}\" />
+</head>
+<body>
+{body}</body>
+</html>
+"
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use anyhow::Result;
+    use nanoid::nanoid;
+    use onyx_api::prelude::*;
+
+    use crate::testing::OnyxTest;
+
+    #[tokio::test]
+    async fn should_render_package_page_for_crawlers() -> Result<()> {
+        let test = OnyxTest::new().await?;
+        let (login, _password) = test.signup(None).await?;
+        let package_name = nanoid!();
+        let tarball = OnyxTest::create_tarball_from_files(&[
+            (
+                "Nargo.toml",
+                &format!(
+                    "[package]\nname = \"{package_name}\"\nversion = \"0.1.0\"\n\
+                     description = \"hashes <things>\"\nkeywords = [\"hash\"]\n\
+                     repository = \"https://github.com/noir-lang/hash\"\n"
+                ),
+            ),
+            (
+                "README.md",
+                "# Usage\n\nCall `hash`, see [the guide](docs/guide.md).\n\n<script>alert(1)</script>\n",
+            ),
+        ])?;
+        test.publish(
+            Some(PublishData::new(tarball.1.to_string(), login.token.clone())),
+            tarball,
+        )
+        .await?;
+
+        let client = reqwest::Client::new();
+        let page = |user_agent: &'static str| {
+            client
+                .get(format!("{}/{package_name}", test.url))
+                .header(reqwest::header::USER_AGENT, user_agent)
+                .send()
+        };
+        let response = page("Mozilla/5.0 (compatible; Googlebot/2.1)").await?;
+        assert_eq!(response.status(), reqwest::StatusCode::OK);
+        let html = response.text().await?;
+        assert!(html.contains(&format!("<title>{package_name} 0.1.0</title>")));
+        assert!(html.contains("content=\"hashes &lt;things&gt;\""));
+        assert!(html.contains("<dt>Keywords</dt><dd>hash</dd>"));
+        assert!(html.contains("<h1>Usage</h1>"));
+        assert!(
+            html.contains("href=\"https://github.com/noir-lang/hash/blob/HEAD/docs/guide.md\"")
+        );
+        assert!(!html.contains("<script>"));
+
+        // everyone else gets the web app, if it's embedded
+        let response = page("git/2.43.0").await?;
+        let expected = if cfg!(feature = "web") {
+            reqwest::StatusCode::OK
+        } else {
+            reqwest::StatusCode::NOT_FOUND
+        };
+        assert_eq!(response.status(), expected);
+        Ok(())
+    }
+}
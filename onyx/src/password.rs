@@ -0,0 +1,127 @@
+use argon2::Algorithm;
+use argon2::Argon2;
+use argon2::Params;
+use argon2::PasswordHash;
+use argon2::PasswordHasher;
+use argon2::PasswordVerifier;
+use argon2::Version;
+use argon2::password_hash::SaltString;
+use argon2::password_hash::rand_core::OsRng;
+
+use super::Config;
+use super::OnyxError;
+
+fn argon2(config: &Config) -> Result<Argon2<'static>, OnyxError> {
+    Ok(Argon2::new(
+        Algorithm::Argon2id,
+        Version::V0x13,
+        config.argon2_params()?,
+    ))
+}
+
+/// Outcome of checking a password against a stored hash.
+#[derive(Debug, PartialEq)]
+pub enum PasswordCheck {
+    Invalid,
+    Valid,
+    /// The password is correct but the hash uses bcrypt or outdated argon2 parameters and
+    /// should be replaced.
+    ValidNeedsRehash,
+}
+
+/// Hash `password` with the Argon2id parameters of `config`.
+pub fn hash_password(config: &Config, password: &str) -> Result<String, OnyxError> {
+    let salt = SaltString::generate(&mut OsRng);
+    Ok(argon2(config)?
+        .hash_password(password.as_bytes(), &salt)?
+        .to_string())
+}
+
+/// Check `password` against `hash`. Hashes that don't use the Argon2id parameters of `config`
+/// need a rehash.
+pub fn verify_password(
+    config: &Config,
+    password: &str,
+    hash: &str,
+) -> Result<PasswordCheck, OnyxError> {
+    // accounts created before argon2 have bcrypt hashes
+    if hash.starts_with("$2") {
+        return Ok(if bcrypt::verify(password, hash)? {
+            PasswordCheck::ValidNeedsRehash
+        } else {
+            PasswordCheck::Invalid
+        });
+    }
+    let parsed = PasswordHash::new(hash)?;
+    if argon2(config)?
+        .verify_password(password.as_bytes(), &parsed)
+        .is_err()
+    {
+        return Ok(PasswordCheck::Invalid);
+    }
+    let params = Params::try_from(&parsed)?;
+    let current = config.argon2_params()?;
+    if parsed.algorithm != argon2::ARGON2ID_IDENT
+        || params.m_cost() != current.m_cost()
+        || params.t_cost() != current.t_cost()
+        || params.p_cost() != current.p_cost()
+    {
+        Ok(PasswordCheck::ValidNeedsRehash)
+    } else {
+        Ok(PasswordCheck::Valid)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn should_verify_argon2() -> Result<(), OnyxError> {
+        let config = Config::default();
+        let hash = hash_password(&config, "correct horse battery staple")?;
+        assert!(hash.starts_with("$argon2id$"));
+        assert_eq!(
+            verify_password(&config, "correct horse battery staple", &hash)?,
+            PasswordCheck::Valid
+        );
+        assert_eq!(
+            verify_password(&config, "incorrect horse", &hash)?,
+            PasswordCheck::Invalid
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn should_rehash_bcrypt() -> Result<(), OnyxError> {
+        let config = Config::default();
+        let hash = bcrypt::hash("correct horse battery staple", 4)?;
+        assert_eq!(
+            verify_password(&config, "correct horse battery staple", &hash)?,
+            PasswordCheck::ValidNeedsRehash
+        );
+        assert_eq!(
+            verify_password(&config, "incorrect horse", &hash)?,
+            PasswordCheck::Invalid
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn should_rehash_outdated_params() -> Result<(), OnyxError> {
+        let config = Config::default();
+        let salt = SaltString::generate(&mut OsRng);
+        let hash = Argon2::new(
+            Algorithm::Argon2id,
+            Version::V0x13,
+            Params::new(Params::MIN_M_COST, 1, 1, None).unwrap(),
+        )
+        .hash_password(b"correct horse battery staple", &salt)?
+        .to_string();
+        assert_eq!(
+            verify_password(&config, "correct horse battery staple", &hash)?,
+            PasswordCheck::ValidNeedsRehash
+        );
+        Ok(())
+    }
+}
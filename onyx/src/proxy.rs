@@ -0,0 +1,372 @@
+use std::collections::BTreeSet;
+
+use anyhow::Result;
+use axum::body::Bytes;
+use axum::extract::Path;
+use axum::extract::State;
+use axum::http::HeaderMap;
+use axum::response::Response;
+use nrpm_tarball::pkt_line::UploadPackRequest;
+use nrpm_tarball::pkt_line::parse_upload_pack_request;
+use redb::ReadableTable;
+use tempfile::tempfile;
+use tokio::sync::Semaphore;
+
+use onyx_api::prelude::*;
+
+use super::OnyxError;
+use super::OnyxState;
+use super::audit;
+use super::db::Db;
+use super::git;
+use super::git::GitRef;
+use super::import;
+
+/// The most tags one request may ask for. Each tag that wasn't fetched before is cloned from
+/// upstream, a client only needs one to clone a version.
+const MAX_REQUESTED_TAGS: usize = 8;
+
+/// Tags fetched from upstream at once, across all requests. Each is a clone in a blocking
+/// thread, more requests wait their turn.
+static UPSTREAM_FETCHES: Semaphore = Semaphore::const_new(4);
+
+/// A repository of one of the `proxy_upstreams` hosts.
+struct Upstream {
+    /// `{host}/{org}/{repo}`, the path the registry serves it at.
+    path: String,
+    /// Where the repository is cloned from.
+    url: String,
+}
+
+/// Whether `segment` can be used in an upstream url as it is.
+fn is_plain_segment(segment: &str) -> bool {
+    !segment.is_empty()
+        && !segment.starts_with(['-', '.'])
+        && segment
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || matches!(c, '-' | '_' | '.'))
+}
+
+impl Upstream {
+    /// The repository `org/repo` of `host`, `None` if the host isn't proxied.
+    fn new(state: &OnyxState, host: &str, org: &str, repo: &str) -> Option<Self> {
+        let base_url = state.config.proxy_upstreams.get(host)?;
+        let repo = repo.strip_suffix(".git").unwrap_or(repo);
+        if !is_plain_segment(org) || !is_plain_segment(repo) {
+            return None;
+        }
+        Some(Self {
+            path: format!("{host}/{org}/{repo}"),
+            url: format!("{}/{org}/{repo}", base_url.trim_end_matches('/')),
+        })
+    }
+}
+
+/// The tags named by the ref prefixes of an ls-refs request, e.g. `refs/tags/v0.1.0` from
+/// `git clone --branch v0.1.0`.
+fn requested_tags(ref_prefixes: &[String]) -> BTreeSet<String> {
+    ref_prefixes
+        .iter()
+        .filter_map(|prefix| {
+            prefix
+                .strip_prefix("refs/tags/")
+                .or(prefix.strip_prefix("refs/heads/"))
+        })
+        .filter(|tag| {
+            !tag.is_empty()
+                && !tag.starts_with('-')
+                && !tag.ends_with('/')
+                && !tag.contains("..")
+                && tag.chars().all(|c| c.is_ascii_graphic())
+        })
+        .map(str::to_string)
+        .collect()
+}
+
+/// The refs of the tags of `upstream` fetched so far. There's no `HEAD`, clients ask for a
+/// tag.
+fn upstream_refs(db: &Db, upstream: &Upstream) -> Result<Vec<GitRef>, OnyxError> {
+    let read = db.begin_read()?;
+    let upstream_tag_table = read.open_table(UPSTREAM_TAG_TABLE)?;
+    let mut versions = vec![];
+    for entry in upstream_tag_table.range((upstream.path.as_str(), "")..)? {
+        let (key, tag) = entry?;
+        let (path, tag_name) = key.value();
+        if path != upstream.path {
+            break;
+        }
+        versions.push((tag.value().commit, tag_name.to_string()));
+    }
+    Ok(git::version_refs(&versions, None))
+}
+
+/// Clone `tag` of `upstream` and keep it as a single commit, unless it was fetched before.
+/// Tags are never fetched again, so what the registry serves doesn't change if the tag is
+/// moved upstream. Each fetch is recorded in the audit log. At most `UPSTREAM_FETCHES` clones
+/// run at once, each bounded by `import::checkout`'s timeout.
+async fn fetch_tag(state: &OnyxState, upstream: &Upstream, tag: &str) -> Result<(), OnyxError> {
+    {
+        let read = state.db.begin_read()?;
+        let upstream_tag_table = read.open_table(UPSTREAM_TAG_TABLE)?;
+        if upstream_tag_table
+            .get((upstream.path.as_str(), tag))?
+            .is_some()
+        {
+            return Ok(());
+        }
+    }
+    let _permit = UPSTREAM_FETCHES
+        .acquire()
+        .await
+        .map_err(|e| anyhow::anyhow!(e))?;
+    let (upstream_commit, hash, commit, pack) = {
+        let (url, tag) = (upstream.url.clone(), tag.to_string());
+        let max_size = state.config.max_upload_size as u64;
+        tokio::task::spawn_blocking(move || -> Result<_> {
+            let checkout = import::checkout(&url, &tag)?;
+            let mut tarball = nrpm_tarball::create(checkout.dir.path(), tempfile()?)?;
+            if tarball.metadata()?.len() > max_size {
+                anyhow::bail!("{url} at {tag} is larger than max_upload_size");
+            }
+            let hash = nrpm_tarball::hash_tarball(&mut tarball)?;
+            let (commit, pack) = nrpm_tarball::extract_git_mock(&mut tarball, &tag)?;
+            Ok((checkout.commit, hash, commit, pack))
+        })
+        .await
+        .map_err(|e| anyhow::anyhow!(e))??
+    };
+
+    let write = state.db.begin_write()?;
+    {
+        let mut upstream_tag_table = write.open_table(UPSTREAM_TAG_TABLE)?;
+        // fetched by another request in the meantime
+        if upstream_tag_table
+            .get((upstream.path.as_str(), tag))?
+            .is_some()
+        {
+            return Ok(());
+        }
+        upstream_tag_table.insert(
+            (upstream.path.as_str(), tag),
+            UpstreamTagModel {
+                commit: commit.clone(),
+                upstream_commit: upstream_commit.clone(),
+                hash: hash.to_string(),
+                fetched_at: timestamp(),
+            },
+        )?;
+        write
+            .open_table(GIT_PACK_TABLE)?
+            .insert(commit.as_str(), pack)?;
+    }
+    audit::record(
+        &write,
+        AuditAction::UpstreamFetched,
+        None,
+        &upstream.path,
+        format!(
+            "fetched {tag} at commit {upstream_commit} from {}, content hash {hash}",
+            upstream.url
+        ),
+    )?;
+    write.commit()?;
+    Ok(())
+}
+
+pub async fn proxy_refs(
+    State(state): State<OnyxState>,
+    Path((host, org, repo)): Path<(String, String, String)>,
+    headers: HeaderMap,
+) -> Result<Response, OnyxError> {
+    let Some(upstream) = Upstream::new(&state, &host, &org, &repo) else {
+        return git::empty().await;
+    };
+    git::advertise(&headers, || upstream_refs(&state.db, &upstream))
+}
+
+/// Handles listing and sending the tags of an upstream repository. Tags a client asks for
+/// are fetched from upstream first if they haven't been.
+pub async fn proxy_upload_pack(
+    State(state): State<OnyxState>,
+    Path((host, org, repo)): Path<(String, String, String)>,
+    body: Bytes,
+) -> Result<Response, OnyxError> {
+    let Some(upstream) = Upstream::new(&state, &host, &org, &repo) else {
+        return git::empty().await;
+    };
+    let request = parse_upload_pack_request(&body)
+        .map_err(|e| OnyxError::bad_request(&format!("invalid git request: {e}")))?;
+    if let UploadPackRequest::LsRefs { ref_prefixes, .. } = &request {
+        let tags = requested_tags(ref_prefixes);
+        if tags.len() > MAX_REQUESTED_TAGS {
+            return Err(OnyxError::bad_request(&format!(
+                "at most {MAX_REQUESTED_TAGS} tags may be requested at once"
+            )));
+        }
+        for tag in tags {
+            // a tag that can't be fetched isn't listed, git reports it as missing
+            if let Err(e) = fetch_tag(&state, &upstream, &tag).await {
+                log::warn!("Failed to fetch {} at {tag}: {e:?}", upstream.url);
+            }
+        }
+    }
+    let refs = upstream_refs(&state.db, &upstream)?;
+    git::upload_pack(&state.db, &upstream.path, request, refs)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+    use std::sync::Arc;
+
+    use anyhow::Result;
+    use nrpm_tarball::pkt_line::encode;
+    use onyx_api::prelude::*;
+    use redb::ReadableTable;
+
+    use crate::testing::OnyxTest;
+
+    fn git(dir: &Path, args: &[&str]) -> Result<()> {
+        let output = std::process::Command::new("git")
+            .arg("-C")
+            .arg(dir)
+            .args([
+                "-c",
+                "user.name=upstream",
+                "-c",
+                "user.email=upstream@localhost",
+            ])
+            .args(args)
+            .output()?;
+        anyhow::ensure!(
+            output.status.success(),
+            "{}",
+            String::from_utf8_lossy(&output.stderr)
+        );
+        Ok(())
+    }
+
+    /// Shallow clone `url` at `tag` into `dir`, returning the contents of `src/lib.nr`.
+    async fn clone(url: &str, tag: &str, dir: &Path) -> Result<String> {
+        let output = tokio::process::Command::new("git")
+            .arg("-c")
+            .arg("protocol.version=2")
+            .arg("clone")
+            .arg("--depth")
+            .arg("1")
+            .arg("--branch")
+            .arg(tag)
+            .arg(url)
+            .arg(dir)
+            .output()
+            .await?;
+        anyhow::ensure!(
+            output.status.success(),
+            "{}",
+            String::from_utf8_lossy(&output.stderr)
+        );
+        Ok(std::fs::read_to_string(dir.join("src/lib.nr"))?)
+    }
+
+    #[tokio::test]
+    async fn should_fetch_upstream_tags_once() -> Result<()> {
+        let upstream = tempfile::tempdir()?;
+        let repo = upstream.path().join("noir-lang/proxied");
+        std::fs::create_dir_all(repo.join("src"))?;
+        std::fs::write(
+            repo.join("Nargo.toml"),
+            "[package]\nname = \"proxied\"\ntype = \"lib\"\n",
+        )?;
+        std::fs::write(repo.join("src/lib.nr"), "upstream v0.1.0\n")?;
+        git(&repo, &["init", "-q"])?;
+        git(&repo, &["add", "-A"])?;
+        git(&repo, &["commit", "-qm", "v0.1.0"])?;
+        git(&repo, &["tag", "v0.1.0"])?;
+
+        let base_url = format!("file://{}", upstream.path().display());
+        let test = OnyxTest::with_config(|state| {
+            Arc::make_mut(&mut state.config)
+                .proxy_upstreams
+                .insert("example.com".to_string(), base_url);
+        })
+        .await?;
+        let workdir = tempfile::tempdir()?;
+        let url = format!("{}/example.com/noir-lang/proxied", test.url);
+        assert_eq!(
+            clone(&url, "v0.1.0", &workdir.path().join("first")).await?,
+            "upstream v0.1.0\n"
+        );
+
+        // moving the tag upstream doesn't change what's served
+        std::fs::write(repo.join("src/lib.nr"), "moved\n")?;
+        git(&repo, &["commit", "-qam", "moved"])?;
+        git(&repo, &["tag", "-f", "v0.1.0"])?;
+        assert_eq!(
+            clone(
+                &format!("{url}.git"),
+                "v0.1.0",
+                &workdir.path().join("second")
+            )
+            .await?,
+            "upstream v0.1.0\n"
+        );
+
+        // missing tags and hosts that aren't proxied can't be cloned
+        assert!(
+            clone(&url, "v9.9.9", &workdir.path().join("missing"))
+                .await
+                .is_err()
+        );
+        let unproxied = format!("{}/gitlab.com/noir-lang/proxied", test.url);
+        assert!(
+            clone(&unproxied, "v0.1.0", &workdir.path().join("unproxied"))
+                .await
+                .is_err()
+        );
+
+        let read = test.db().begin_read()?;
+        let audit_log_table = read.open_table(AUDIT_LOG_TABLE)?;
+        let fetches = audit_log_table
+            .iter()?
+            .map(|entry| Ok(entry?.1.value()))
+            .collect::<Result<Vec<_>>>()?
+            .into_iter()
+            .filter(|entry| entry.action == AuditAction::UpstreamFetched)
+            .collect::<Vec<_>>();
+        assert_eq!(fetches.len(), 1);
+        assert_eq!(fetches[0].package_name, "example.com/noir-lang/proxied");
+        assert!(fetches[0].details.contains("v0.1.0"));
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn should_limit_requested_tags() -> Result<()> {
+        let upstream = tempfile::tempdir()?;
+        let base_url = format!("file://{}", upstream.path().display());
+        let test = OnyxTest::with_config(|state| {
+            Arc::make_mut(&mut state.config)
+                .proxy_upstreams
+                .insert("example.com".to_string(), base_url);
+        })
+        .await?;
+        let mut body = vec![encode(b"command=ls-refs\n")?, b"0001".to_vec()];
+        for i in 0..=super::MAX_REQUESTED_TAGS {
+            body.push(encode(
+                format!("ref-prefix refs/tags/v0.{i}.0\n").as_bytes(),
+            )?);
+        }
+        body.push(b"0000".to_vec());
+        let response = reqwest::Client::new()
+            .post(format!(
+                "{}/example.com/noir-lang/proxied/git-upload-pack",
+                test.url
+            ))
+            .header("content-type", "application/x-git-upload-pack-request")
+            .body(body.concat())
+            .send()
+            .await?;
+        assert_eq!(response.status(), reqwest::StatusCode::BAD_REQUEST);
+        assert!(response.text().await?.contains("tags may be requested at once"));
+        Ok(())
+    }
+}
@@ -1,12 +1,23 @@
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::Read;
+use std::io::Seek;
+use std::io::SeekFrom;
 use std::io::Write;
+use std::net::SocketAddr;
+use std::path::PathBuf;
 
 use anyhow::Result;
+use axum::extract::ConnectInfo;
 use axum::extract::Multipart;
 use axum::extract::State;
+use axum::http::HeaderMap;
 use axum::response::Json as ResponseJson;
 use nanoid::nanoid;
+use nargo_parse::NargoConfig;
 use nrpm_tarball::ptk_str;
 use redb::ReadableTable;
+use serde::Deserialize;
 use tempfile::tempfile;
 
 use onyx_api::prelude::*;
@@ -15,17 +26,50 @@ use crate::PACKAGE_NAME_TABLE;
 use crate::PACKAGE_VERSION_NAME_TABLE;
 use crate::VERSION_TABLE;
 
-use super::AUTH_TOKEN_TABLE;
 use super::OnyxError;
 use super::OnyxState;
 use super::PACKAGE_TABLE;
 use super::PACKAGE_VERSION_TABLE;
+use super::build;
+use super::changelog;
+use super::db::WriteTxn;
+use super::delta;
+use super::dependency;
+use super::docs;
+use super::manifest;
+use super::quota;
+use super::release_notes;
+use super::scan;
+use super::search;
+use super::session;
 use super::timestamp;
+use super::transparency;
+use super::typosquat;
+use super::validate::ValidationErrors;
+use super::validate::normalize_version_name;
+use super::validate::validate;
+use super::validate::validate_license;
+use super::validate::validate_nrpm_metadata;
+use super::validate::validate_package_name;
+use super::watch;
+use super::webhook;
+
+// how long a publish idempotency key may be replayed, in seconds
+const IDEMPOTENCY_KEY_TTL: u64 = 24 * 3600;
+const MAX_IDEMPOTENCY_KEY_LEN: usize = 255;
 
 pub async fn publish(
     State(state): State<OnyxState>,
+    ConnectInfo(peer): ConnectInfo<SocketAddr>,
+    headers: HeaderMap,
     mut multipart: Multipart,
 ) -> Result<ResponseJson<PublishResponse>, OnyxError> {
+    if let Some(upstream) = &state.config.upstream_url {
+        return Err(OnyxError::new(
+            OnyxErrorCode::Forbidden,
+            &format!("This registry is a read-only mirror, publish to {upstream} instead"),
+        ));
+    }
     let mut tarball_data = None;
     let mut publish_data: Option<PublishData> = None;
     while let Some(field) = multipart.next_field().await.unwrap() {
@@ -39,10 +83,7 @@ pub async fn publish(
             }
             "publish_data" => {
                 let bytes = field.bytes().await?;
-                publish_data = Some(
-                    bincode::deserialize(&bytes)
-                        .map_err(|_| OnyxError::bad_request("Failed to decode publish data!"))?,
-                );
+                publish_data = Some(decode_publish_data(&bytes)?);
             }
             _ => {}
         }
@@ -56,22 +97,40 @@ pub async fn publish(
             ));
         }
     };
-    // check that we are authenticated
+    validate(&publish_data)?;
+    // check that we are authenticated, and not banned
+    let (user_id, _expires_at) = session::authenticate(&state.db, &publish_data.token)?;
     let read = state.db.begin_read()?;
-    let auth_table = read.open_table(AUTH_TOKEN_TABLE)?;
-    let user_id = if let Some(entry) = auth_table.get(publish_data.token.as_str())? {
-        let (user_id, expires_at) = entry.value();
-        if timestamp() > expires_at {
-            return Err(OnyxError::bad_request(
-                "Publish request contains invalid token!",
-            ));
+
+    // if this is a retry of a publish that already succeeded, respond the same way again
+    let idempotency_key = headers
+        .get(IDEMPOTENCY_KEY_HEADER)
+        .map(|v| v.to_str().map(|v| v.to_string()))
+        .transpose()
+        .map_err(|_| OnyxError::bad_request("Idempotency key contains invalid characters"))?;
+    if let Some(key) = idempotency_key.as_ref() {
+        if key.is_empty() || key.len() > MAX_IDEMPOTENCY_KEY_LEN {
+            return Err(OnyxError::bad_request(&format!(
+                "Idempotency key must be between 1 and {MAX_IDEMPOTENCY_KEY_LEN} characters"
+            )));
         }
-        user_id.to_string()
-    } else {
-        return Err(OnyxError::bad_request(
-            "Publish request contains invalid token!",
-        ));
-    };
+        let idempotency_key_table = read.open_table(IDEMPOTENCY_KEY_TABLE)?;
+        if let Some(entry) = idempotency_key_table.get((user_id.as_str(), key.as_str()))? {
+            let (hash, package_id, created_at) = entry.value();
+            if timestamp() < created_at + IDEMPOTENCY_KEY_TTL {
+                if hash != publish_data.hash {
+                    return Err(OnyxError::conflict(
+                        "Idempotency key was already used for a different publish",
+                    ));
+                }
+                return Ok(ResponseJson(PublishResponse {
+                    package_id: package_id.to_string(),
+                    warnings: vec![],
+                    status: PublishStatus::Published,
+                }));
+            }
+        }
+    }
 
     // now we're authed, and confirmed to be the author of the package
     // let's examine the provided tarball
@@ -79,7 +138,36 @@ pub async fn publish(
     tarball.write_all(&tarball_data)?;
 
     // retrieve name and version from the contents of the tarball
-    let (package_name, package_version) = state.storage.validate_tarball(&mut tarball)?;
+    let (package_name, package_version) = state
+        .storage
+        .validate_tarball(&mut tarball, state.config.legacy_version_names)
+        .map_err(|e| OnyxError::new(OnyxErrorCode::InvalidPackage, &e.to_string()))?;
+    let mut errors = ValidationErrors::default();
+    errors.check("package.name", validate_package_name(&package_name));
+    let package_version =
+        match normalize_version_name(&package_version, state.config.legacy_version_names) {
+            Ok(normalized) => normalized,
+            Err(message) => {
+                errors.check("package.version", Err(message));
+                package_version
+            }
+        };
+    let (config, _files) = read_metadata(&mut tarball)
+        .map_err(|e| OnyxError::new(OnyxErrorCode::InvalidPackage, &e.to_string()))?;
+    if let Some(license) = &config.package.license {
+        errors.check("package.license", validate_license(license));
+    }
+    errors.check(
+        "package.metadata.nrpm",
+        validate_nrpm_metadata(&config.package),
+    );
+    if let Some(source_repository) = &publish_data.source_repository {
+        errors.check(
+            "source_repository",
+            verify_source_repository(&config, source_repository),
+        );
+    }
+    errors.into_result()?;
 
     let actual_hash = nrpm_tarball::hash_tarball(&mut tarball)?;
 
@@ -88,24 +176,249 @@ pub async fn publish(
             "hash mismatch for uploaded package, computed: {actual_hash}, expected: {}",
             publish_data.hash
         );
-        return Err(OnyxError::bad_request(
+        return Err(OnyxError::new(
+            OnyxErrorCode::HashMismatch,
             "Hash mismatch for uploaded tarball!",
         ));
     }
 
+    let warnings = typosquat::screen(&state, &user_id, &package_name)?;
+
     // now write our package to the db
     let write = state.db.begin_write()?;
+    let size = tarball_data.len() as u64;
+    quota::check(&write, &state.config, &user_id, size)?;
+    let ip = webhook::client_ip(&state.config, &headers, peer);
+    if !state.scanners.is_empty() {
+        // fail now instead of after the scan if the version can't be published
+        let package_id = check_publishable(&write, &user_id, &package_name, &package_version)?;
+        let notification = webhook::publish_notification(
+            &write,
+            &user_id,
+            &publish_data.token,
+            &package_name,
+            &package_version,
+            &actual_hash,
+            Some(ip),
+        )?;
+        let status = scan::hold(
+            &state,
+            &write,
+            PendingPublishModel {
+                id: HashId::from(actual_hash),
+                author_id: user_id.clone(),
+                package_name,
+                version_name: package_version,
+                uploaded_at: timestamp(),
+                size,
+                source_repository: publish_data.source_repository.clone(),
+                source_commit: publish_data.source_commit.clone(),
+                release_notes: publish_data.release_notes.clone(),
+                idempotency_key,
+                notification,
+                status: PublishStatus::Pending,
+                findings: vec![],
+                scanned_at: None,
+                rejected_by: None,
+            },
+            &mut tarball,
+        )?;
+        write.commit()?;
+        return Ok(ResponseJson(PublishResponse {
+            package_id: package_id.unwrap_or_default(),
+            warnings,
+            status,
+        }));
+    }
+    let package = store_version(
+        &state.storage,
+        &write,
+        NewVersion {
+            author_id: &user_id,
+            package_name,
+            version_name: package_version.clone(),
+            hash: actual_hash,
+            created_at: timestamp(),
+            follow_owner: false,
+            source_repository: publish_data.source_repository.clone(),
+            source_commit: publish_data.source_commit.clone(),
+            release_notes: publish_data.release_notes.clone(),
+        },
+        &mut tarball,
+    )?;
+    quota::charge(&write, &package.author_id, &package.id, size)?;
+    if state.config.nargo_path.is_some() {
+        build::enqueue(&write, &HashId::from(actual_hash))?;
+    }
+    webhook::enqueue_publish(
+        &write,
+        &user_id,
+        &publish_data.token,
+        &package.name,
+        &package_version,
+        &actual_hash,
+        Some(ip),
+    )?;
+    if let Some(key) = idempotency_key.as_ref() {
+        let mut idempotency_key_table = write.open_table(IDEMPOTENCY_KEY_TABLE)?;
+        idempotency_key_table.insert(
+            (user_id.as_str(), key.as_str()),
+            (publish_data.hash.as_str(), package.id.as_str(), timestamp()),
+        )?;
+    }
+    write.commit()?;
+
+    Ok(ResponseJson(PublishResponse {
+        package_id: package.id,
+        warnings,
+        status: PublishStatus::Published,
+    }))
+}
+
+/// Remove idempotency keys that can no longer be replayed. Returns the number removed.
+pub fn purge_expired_idempotency_keys(state: &OnyxState) -> Result<usize> {
+    let now = timestamp();
+    let write = state.db.begin_write()?;
+    let purged = {
+        let mut idempotency_key_table = write.open_table(IDEMPOTENCY_KEY_TABLE)?;
+        let mut expired_keys = vec![];
+        for entry in idempotency_key_table.iter()? {
+            let (key, value) = entry?;
+            let (user_id, key) = key.value();
+            let (_hash, _package_id, created_at) = value.value();
+            if now >= created_at + IDEMPOTENCY_KEY_TTL {
+                expired_keys.push((user_id.to_string(), key.to_string()));
+            }
+        }
+        for (user_id, key) in &expired_keys {
+            idempotency_key_table.remove((user_id.as_str(), key.as_str()))?;
+        }
+        expired_keys.len()
+    };
+    write.commit()?;
+    Ok(purged)
+}
+
+/// The id of the package `package_name` if it exists, after checking that `author_id` may
+/// publish `version_name` of it, as `store_version` will.
+fn check_publishable(
+    write: &WriteTxn,
+    author_id: &str,
+    package_name: &str,
+    version_name: &str,
+) -> Result<Option<String>, OnyxError> {
+    let package_name_table = write.open_table(PACKAGE_NAME_TABLE)?;
+    let Some(package_id) = package_name_table.get(package_name)? else {
+        let removed_package_table = write.open_table(REMOVED_PACKAGE_TABLE)?;
+        if removed_package_table.get(package_name)?.is_some() {
+            return Err(OnyxError::new(
+                OnyxErrorCode::Forbidden,
+                "This package name was removed by the registry admins and can't be published",
+            ));
+        }
+        return Ok(None);
+    };
+    let package_id = package_id.value().to_string();
+    let package_table = write.open_table(PACKAGE_TABLE)?;
+    if let Some(package) = package_table.get(package_id.as_str())?
+        && package.value().author_id != author_id
+    {
+        return Err(OnyxError::new(
+            OnyxErrorCode::Forbidden,
+            "You are not authorized to publish versions of this package",
+        ));
+    }
+    let package_version_name_table = write.open_table(PACKAGE_VERSION_NAME_TABLE)?;
+    check_version_name(
+        &package_version_name_table,
+        &package_id,
+        package_name,
+        version_name,
+    )?;
+    Ok(Some(package_id))
+}
+
+/// Fail if the package `package_id` has a version named `version_name`, or one that differs
+/// from it only by build metadata, e.g. `1.0.0+a` and `1.0.0+b`, which semver orders the same.
+fn check_version_name(
+    package_version_name_table: &impl ReadableTable<(&'static str, &'static str), HashId>,
+    package_id: &str,
+    package_name: &str,
+    version_name: &str,
+) -> Result<(), OnyxError> {
+    if package_version_name_table
+        .get((package_id, version_name))?
+        .is_some()
+    {
+        return Err(OnyxError::conflict(&format!(
+            "Version already exists for package! version_name: {version_name} package_name: {package_name}"
+        )));
+    }
+    let Ok(version) = semver::Version::parse(version_name) else {
+        return Ok(());
+    };
+    for entry in package_version_name_table.range((package_id, "")..=(package_id, "\u{10ffff}"))? {
+        let (key, _) = entry?;
+        let (_, existing_name) = key.value();
+        if let Ok(existing) = semver::Version::parse(existing_name)
+            && existing.cmp_precedence(&version).is_eq()
+        {
+            return Err(OnyxError::conflict(&format!(
+                "Version {existing_name} of package {package_name} differs from {version_name} only by build metadata"
+            )));
+        }
+    }
+    Ok(())
+}
+
+/// A version that has been validated and hash checked, ready to be stored.
+pub struct NewVersion<'a> {
+    pub author_id: &'a str,
+    pub package_name: String,
+    pub version_name: String,
+    pub hash: blake3::Hash,
+    pub created_at: u64,
+    /// Accept the version even if `author_id` doesn't own the package, making them the owner.
+    /// Mirrors use this to follow ownership changes in the upstream registry.
+    pub follow_owner: bool,
+    pub source_repository: Option<String>,
+    pub source_commit: Option<String>,
+    pub release_notes: Option<String>,
+}
+
+/// Write a new version of a package, creating the package if needed, and append it to the
+/// changelog.
+pub fn store_version(
+    storage: &OnyxStorage,
+    write: &WriteTxn,
+    version: NewVersion,
+    tarball: &mut File,
+) -> Result<PackageModel, OnyxError> {
+    let NewVersion {
+        author_id,
+        package_name,
+        version_name: package_version,
+        hash: actual_hash,
+        created_at,
+        follow_owner,
+        source_repository,
+        source_commit,
+        release_notes,
+    } = version;
+    let user_id = author_id.to_string();
+    let metadata = read_metadata(tarball);
 
-    let package = {
+    // generate a new version id for what is being published
+    let version_id = HashId::from(actual_hash);
+    let mut previous_version_id = None;
+
+    let mut package = {
         let mut package_table = write.open_table(PACKAGE_TABLE)?;
         let mut package_version_table = write.open_multimap_table(PACKAGE_VERSION_TABLE)?;
         let mut version_table = write.open_table(VERSION_TABLE)?;
         let mut package_name_table = write.open_table(PACKAGE_NAME_TABLE)?;
         let mut package_version_name_table = write.open_table(PACKAGE_VERSION_NAME_TABLE)?;
 
-        // generate a new version id for what is being published
-        let version_id = HashId::from(actual_hash);
-
         let package = if let Some(package_id) = package_name_table.get(package_name.as_str())? {
             // the package name is already in use
             // make sure we're the author of the package
@@ -115,21 +428,36 @@ pub async fn publish(
                 unreachable!("package tables are inconsistent")
             };
             if package.author_id != user_id {
-                return Err(OnyxError::bad_request(
-                    "You are not authorized to publish versions of this package",
-                ));
+                if !follow_owner {
+                    return Err(OnyxError::new(
+                        OnyxErrorCode::Forbidden,
+                        "You are not authorized to publish versions of this package",
+                    ));
+                }
+                package.author_id = user_id.clone();
             }
             // we're publishing a new version of an existing package
+            previous_version_id = Some(package.latest_version_id.clone());
             package.latest_version_id = version_id.clone();
             package_table.insert(package_id.value(), package.clone())?;
             package
         } else {
             // this is a completely new package
+            let removed_package_table = write.open_table(REMOVED_PACKAGE_TABLE)?;
+            if removed_package_table.get(package_name.as_str())?.is_some() {
+                return Err(OnyxError::new(
+                    OnyxErrorCode::Forbidden,
+                    "This package name was removed by the registry admins and can't be published",
+                ));
+            }
             let package = PackageModel {
                 id: nanoid!(),
                 name: package_name,
                 author_id: user_id.clone(),
                 latest_version_id: version_id.clone(),
+                ownership_history: vec![],
+                license: None,
+                metadata: PackageMetadata::default(),
             };
             package_table.insert(package.id.as_str(), package.clone())?;
             package_name_table.insert(package.name.as_str(), package.id.as_str())?;
@@ -137,14 +465,12 @@ pub async fn publish(
         };
 
         // make sure the version name is unique
-        if let Some(_) =
-            package_version_name_table.get((package.id.as_str(), package_version.as_str()))?
-        {
-            return Err(OnyxError::bad_request(&format!(
-                "Version already exists for package! version_name: {} package_name: {}",
-                package_version, package.name
-            )));
-        }
+        check_version_name(
+            &package_version_name_table,
+            &package.id,
+            &package.name,
+            &package_version,
+        )?;
 
         let mut git_pack_table = write.open_table(GIT_PACK_TABLE)?;
         let mut git_refs_table = write.open_table(GIT_REFS_TABLE)?;
@@ -155,9 +481,12 @@ pub async fn publish(
 
         // take the tarball and build a git tree with a single commit containing the tarball
         // contents
-        let (commit_hex, pack_bytes) =
-            nrpm_tarball::extract_git_mock(&mut tarball, &package_version).map_err(|e| {
-                OnyxError::bad_request(&format!("Failed to create git pack: {:?}", e))
+        let (commit_hex, pack_bytes) = nrpm_tarball::extract_git_mock(tarball, &package_version)
+            .map_err(|e| {
+                OnyxError::new(
+                    OnyxErrorCode::InvalidPackage,
+                    &format!("Failed to create git pack: {:?}", e),
+                )
             })?;
 
         existing_refs.push_str(&ptk_str(&format!(
@@ -171,18 +500,15 @@ pub async fn publish(
         git_pack_table.insert(commit_hex.as_str(), pack_bytes)?;
 
         if let Some(_) = version_table.get(&version_id)? {
-            return Err(OnyxError::bad_request("Package with hash already exists"));
+            return Err(OnyxError::conflict("Package with hash already exists"));
         } else {
-            if let Err(e) = state
-                .storage
-                .ingest_tarball(&mut tarball, HashId::from(actual_hash).to_string())
-            {
+            if let Err(e) = storage.ingest_tarball(tarball, HashId::from(actual_hash).to_string()) {
                 log::warn!(
                     "package already exists with hash: {} {:?}",
                     actual_hash.to_string(),
                     e
                 );
-                return Err(OnyxError::bad_request(&format!(
+                return Err(OnyxError::conflict(&format!(
                     "File with hash already exists: {}",
                     actual_hash.to_string()
                 )));
@@ -194,29 +520,152 @@ pub async fn publish(
             version_id.clone(),
         )?;
         package_version_table.insert(package.id.as_str(), version_id.clone())?;
-        version_table.insert(
-            version_id.clone(),
-            PackageVersionModel {
-                id: version_id,
-                name: package_version,
-                author_id: user_id,
-                package_id: package.id.clone(),
-                created_at: timestamp(),
-            },
+        let version = PackageVersionModel {
+            id: version_id.clone(),
+            name: package_version,
+            author_id: user_id,
+            package_id: package.id.clone(),
+            created_at,
+            source_repository,
+            source_commit,
+            metadata: metadata
+                .as_ref()
+                .ok()
+                .map(|(config, _files)| VersionMetadata::from(config)),
+            // every caller validates the tarball first, which rejects anything else
+            static_content: true,
+        };
+        version_table.insert(version.id.clone(), version.clone())?;
+        search::index(write, &version)?;
+        let seq = changelog::append(write, &package, &version)?;
+        watch::notify(write, &package, &version, seq)?;
+        transparency::append(
+            write,
+            &package.name,
+            &version.name,
+            &version.id,
+            &version.author_id,
+            version.created_at,
         )?;
+        if let Some(release_notes) = release_notes.as_deref() {
+            release_notes::store(write, &version.id, release_notes)?;
+        }
 
         package
     };
-    write.commit()?;
 
-    Ok(ResponseJson(PublishResponse {
-        package_id: package.id,
-    }))
+    // docs and the dependency graph are a nicety, a package we can't parse is still publishable
+    match metadata {
+        Ok((config, files)) => {
+            // the package shows the license of its latest version
+            if package.license != config.package.license {
+                package.license = config.package.license.clone();
+                let mut package_table = write.open_table(PACKAGE_TABLE)?;
+                package_table.insert(package.id.as_str(), package.clone())?;
+            }
+            docs::store(write, &version_id, &docs::from_files(&files))?;
+            manifest::store(write, &version_id, &manifest::from_files(&files)?)?;
+            if let Some(previous_version_id) = previous_version_id.as_ref() {
+                let tarball_len = tarball.metadata()?.len();
+                delta::store(write, previous_version_id, &version_id, &files, tarball_len)?;
+            }
+            dependency::record(
+                write,
+                &package,
+                &version_id,
+                previous_version_id.as_ref(),
+                &config,
+            )?;
+        }
+        Err(e) => log::warn!(
+            "failed to read metadata for {} {}: {e:?}",
+            package.name,
+            version_id.to_string()
+        ),
+    }
+    Ok(package)
+}
+
+fn read_metadata(tarball: &mut File) -> Result<(NargoConfig, HashMap<PathBuf, Vec<u8>>)> {
+    tarball.seek(SeekFrom::Start(0))?;
+    let mut bytes = vec![];
+    tarball.read_to_end(&mut bytes)?;
+    nrpm_tarball::extract_metadata(bytes)
+}
+
+/// Repository urls are compared without scheme, case, trailing slash and `.git` suffix so
+/// that e.g. `https://github.com/a/b.git` and `https://github.com/A/b/` are the same.
+fn normalize_repository(repository: &str) -> String {
+    let repository = repository.trim().to_lowercase();
+    let repository = repository
+        .strip_prefix("https://")
+        .or_else(|| repository.strip_prefix("http://"))
+        .unwrap_or(&repository);
+    repository
+        .trim_end_matches('/')
+        .trim_end_matches(".git")
+        .to_string()
+}
+
+/// A version built from a repository has to come from the repository its Nargo.toml names,
+/// otherwise anyone could claim provenance from a repository they don't control the package
+/// of.
+fn verify_source_repository(config: &NargoConfig, source_repository: &str) -> Result<(), String> {
+    let Some(repository) = config.package.repository.as_ref() else {
+        return Err(
+            "Nargo.toml must list a repository to publish with a source repository".to_string(),
+        );
+    };
+    if normalize_repository(repository) != normalize_repository(source_repository) {
+        return Err(format!(
+            "source repository does not match the repository in Nargo.toml: {repository}"
+        ));
+    }
+    Ok(())
+}
+
+/// `PublishData` as sent by clients that encoded it with bincode.
+#[derive(Deserialize)]
+struct PublishDataV0 {
+    hash: String,
+    token: String,
+}
+
+/// Decode the `publish_data` field of a publish upload. It's JSON, but bincode from older
+/// clients is still accepted. A JSON encoded field always starts with an object, bincode
+/// starts with the length of the hash.
+// TODO: drop bincode once clients older than PUBLISH_DATA_SCHEMA_VERSION 1 are gone
+fn decode_publish_data(bytes: &[u8]) -> Result<PublishData, OnyxError> {
+    let decode_error = || OnyxError::bad_request("Failed to decode publish data!");
+    let is_json = bytes.iter().find(|b| !b.is_ascii_whitespace()) == Some(&b'{');
+    let publish_data = if is_json {
+        serde_json::from_slice::<PublishData>(bytes).map_err(|_| decode_error())?
+    } else {
+        let legacy = bincode::deserialize::<PublishDataV0>(bytes).map_err(|_| decode_error())?;
+        PublishData {
+            schema_version: 0,
+            hash: legacy.hash,
+            token: legacy.token,
+            source_repository: None,
+            source_commit: None,
+            release_notes: None,
+        }
+    };
+    if publish_data.schema_version > PUBLISH_DATA_SCHEMA_VERSION {
+        return Err(OnyxError::bad_request(&format!(
+            "Unsupported publish data schema version {}, expected at most {PUBLISH_DATA_SCHEMA_VERSION}",
+            publish_data.schema_version
+        )));
+    }
+    Ok(publish_data)
 }
 
 #[cfg(test)]
 mod tests {
-    use crate::tests::*;
+    use std::sync::Arc;
+
+    use crate::AUTH_TOKEN_TABLE;
+    use crate::testing::*;
 
     use super::*;
     use anyhow::Result;
@@ -237,7 +686,7 @@ mod tests {
         let mut publish_data = PublishData::default();
         publish_data.hash = tarball.1.to_string();
         let e = test.publish(Some(publish_data), tarball).await.unwrap_err();
-        assert_eq!(e.to_string(), "Publish request contains invalid token!");
+        assert_eq!(e.to_string(), "Invalid token!");
         Ok(())
     }
 
@@ -271,7 +720,7 @@ mod tests {
             .publish(Some(publish_data), (tarball_bytes, hash))
             .await
             .unwrap_err();
-        assert_eq!(e.to_string(), "Publish request contains invalid token!");
+        assert_eq!(e.to_string(), "Expired token!");
         Ok(())
     }
 
@@ -299,12 +748,202 @@ mod tests {
             .await?;
 
         assert_eq!(response.status().is_success(), false);
-        let e = response.text().await?;
-        assert_eq!(e.to_string(), "Failed to decode publish data!");
+        let e = response.json::<ApiError>().await?;
+        assert_eq!(e.code, OnyxErrorCode::BadRequest);
+        assert_eq!(e.message, "Failed to decode publish data!");
 
         Ok(())
     }
 
+    #[tokio::test]
+    async fn should_publish_with_legacy_publish_data() -> Result<()> {
+        let test = OnyxTest::new().await?;
+        let (login, _password) = test.signup(None).await?;
+        let (tarball_bytes, hash) = OnyxTest::create_test_tarball(None)?;
+
+        let legacy = (hash.to_string(), login.token);
+        let form = multipart::Form::new()
+            .part("tarball", multipart::Part::bytes(tarball_bytes))
+            .part(
+                "publish_data",
+                multipart::Part::bytes(bincode::serialize(&legacy)?),
+            );
+        let response = reqwest::Client::new()
+            .post(format!("{}/v0/publish", test.url))
+            .multipart(form)
+            .send()
+            .await?;
+        assert!(response.status().is_success());
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn fail_publish_unsupported_schema_version() -> Result<()> {
+        let test = OnyxTest::new().await?;
+        let (login, _password) = test.signup(None).await?;
+        let tarball = OnyxTest::create_test_tarball(None)?;
+
+        let mut publish_data = PublishData::new(tarball.1.to_string(), login.token);
+        publish_data.schema_version = PUBLISH_DATA_SCHEMA_VERSION + 1;
+        let e = test
+            .publish(Some(publish_data), tarball)
+            .await
+            .unwrap_err()
+            .downcast::<ApiError>()?;
+        assert_eq!(e.code, OnyxErrorCode::BadRequest);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn should_record_version_source() -> Result<()> {
+        let test = OnyxTest::new().await?;
+        let (login, _password) = test.signup(None).await?;
+        let package_name = nanoid!();
+        let tarball = OnyxTest::create_tarball_from_files(&[(
+            "Nargo.toml",
+            &format!(
+                "[package]\nname = \"{package_name}\"\nversion = \"0.1.0\"\n\
+                 repository = \"https://github.com/noir-lang/noir\"\n"
+            ),
+        )])?;
+        let assert_validation_failed = |e: anyhow::Error| {
+            assert_eq!(
+                e.downcast_ref::<ApiError>().map(|e| e.code),
+                Some(OnyxErrorCode::ValidationFailed)
+            );
+        };
+
+        // a commit without a repository
+        let mut publish_data = PublishData::new(tarball.1.to_string(), login.token.clone());
+        publish_data.source_commit = Some("a".repeat(40));
+        assert_validation_failed(
+            test.publish(Some(publish_data.clone()), tarball.clone())
+                .await
+                .unwrap_err(),
+        );
+
+        // a repository other than the one in Nargo.toml
+        publish_data.source_repository = Some("https://github.com/someone/else".to_string());
+        assert_validation_failed(
+            test.publish(Some(publish_data.clone()), tarball.clone())
+                .await
+                .unwrap_err(),
+        );
+
+        publish_data.source_repository = Some("https://github.com/noir-lang/noir.git".to_string());
+        test.publish(Some(publish_data), tarball).await?;
+        let (_package, version) = test.api.load_package_latest_version(&package_name).await?;
+        assert_eq!(
+            version.source_repository.as_deref(),
+            Some("https://github.com/noir-lang/noir.git")
+        );
+        assert_eq!(version.source_commit, Some("a".repeat(40)));
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn should_record_license() -> Result<()> {
+        let test = OnyxTest::new().await?;
+        let (login, _password) = test.signup(None).await?;
+        let package_name = nanoid!();
+        let tarball = |version: &str, license: &str| {
+            OnyxTest::create_tarball_from_files(&[(
+                "Nargo.toml",
+                &format!(
+                    "[package]\nname = \"{package_name}\"\nversion = \"{version}\"\n\
+                     license = \"{license}\"\n"
+                ),
+            )])
+        };
+
+        let invalid = tarball("0.1.0", "MIT OR")?;
+        let e = test
+            .publish(
+                Some(PublishData::new(invalid.1.to_string(), login.token.clone())),
+                invalid,
+            )
+            .await
+            .unwrap_err();
+        assert_eq!(
+            e.downcast_ref::<ApiError>().map(|e| e.code),
+            Some(OnyxErrorCode::ValidationFailed)
+        );
+
+        let first = tarball("0.1.0", "MIT")?;
+        test.publish(
+            Some(PublishData::new(first.1.to_string(), login.token.clone())),
+            first,
+        )
+        .await?;
+        let (package, _version) = test.api.load_package_latest_version(&package_name).await?;
+        assert_eq!(package.license.as_deref(), Some("MIT"));
+
+        // the package shows the license of its latest version
+        let second = tarball("0.2.0", "MIT OR Apache-2.0")?;
+        test.publish(
+            Some(PublishData::new(second.1.to_string(), login.token.clone())),
+            second,
+        )
+        .await?;
+        let (package, _version) = test.api.load_package_latest_version(&package_name).await?;
+        assert_eq!(package.license.as_deref(), Some("MIT OR Apache-2.0"));
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn should_record_version_metadata() -> Result<()> {
+        let test = OnyxTest::new().await?;
+        let (login, _password) = test.signup(None).await?;
+        let package_name = nanoid!();
+        let tarball = OnyxTest::create_tarball_from_files(&[(
+            "Nargo.toml",
+            &format!(
+                "[package]\nname = \"{package_name}\"\nversion = \"0.1.0\"\n\
+                 description = \"hashes things\"\nauthors = [\"alice\", \"bob\"]\n\
+                 keywords = [\"hash\"]\nlicense = \"MIT\"\n\n[dependencies]\n\
+                 poseidon = {{ git = \"https://github.com/noir-lang/poseidon\", tag = \"v0.1.0\" }}\n"
+            ),
+        )])?;
+        test.publish(
+            Some(PublishData::new(tarball.1.to_string(), login.token.clone())),
+            tarball,
+        )
+        .await?;
+
+        let expected = VersionMetadata {
+            description: Some("hashes things".to_string()),
+            authors: vec!["alice".to_string(), "bob".to_string()],
+            repository: None,
+            keywords: vec!["hash".to_string()],
+            license: Some("MIT".to_string()),
+            dependencies: vec![VersionMetadataDependency {
+                name: "poseidon".to_string(),
+                git: Some("https://github.com/noir-lang/poseidon".to_string()),
+                tag: Some("v0.1.0".to_string()),
+                directory: None,
+                path: None,
+            }],
+            nrpm_metadata: Default::default(),
+        };
+        let (_package, version) = test.api.load_package_latest_version(&package_name).await?;
+        assert_eq!(version.metadata.as_ref(), Some(&expected));
+        let (_package, versions) = test.api.load_package_versions(&package_name).await?;
+        assert_eq!(versions[0].metadata.as_ref(), Some(&expected));
+        Ok(())
+    }
+
+    #[test]
+    fn should_normalize_repository() {
+        assert_eq!(
+            normalize_repository("https://github.com/Noir-Lang/noir.git/"),
+            normalize_repository("http://github.com/noir-lang/noir")
+        );
+        assert_ne!(
+            normalize_repository("https://github.com/noir-lang/noir"),
+            normalize_repository("https://github.com/noir-lang/noir-other")
+        );
+    }
+
     #[tokio::test]
     async fn fail_publish_without_fields() -> Result<()> {
         let test = OnyxTest::new().await?;
@@ -319,7 +958,7 @@ mod tests {
             // without tarball
             let form = multipart::Form::new().part(
                 "publish_data",
-                multipart::Part::bytes(bincode::serialize(&publish_data)?),
+                multipart::Part::bytes(serde_json::to_vec(&publish_data)?),
             );
             let response = client
                 .post(format!("{}/v0/publish", test.url))
@@ -329,7 +968,7 @@ mod tests {
             if response.status().is_success() {
                 assert!(false);
             }
-            assert_eq!(response.text().await?, expected_error);
+            assert_eq!(response.json::<ApiError>().await?.message, expected_error);
         }
 
         {
@@ -348,7 +987,7 @@ mod tests {
             if response.status().is_success() {
                 assert!(false);
             }
-            assert_eq!(response.text().await?, expected_error);
+            assert_eq!(response.json::<ApiError>().await?.message, expected_error);
         }
 
         {
@@ -367,7 +1006,7 @@ mod tests {
             if response.status().is_success() {
                 assert!(false);
             }
-            assert_eq!(response.text().await?, expected_error);
+            assert_eq!(response.json::<ApiError>().await?.message, expected_error);
         }
         Ok(())
     }
@@ -381,12 +1020,9 @@ mod tests {
         let (login, _password) = test.signup(None).await?;
         let tarball = OnyxTest::create_test_tarball(None)?;
 
-        let data = PublishData {
-            hash: tarball.1.to_string(),
-            token: login.token,
-        };
+        let data = PublishData::new(tarball.1.to_string(), login.token);
 
-        let PublishResponse { package_id: _ } =
+        let PublishResponse { package_id: _, .. } =
             test.publish(Some(data.clone()), tarball.clone()).await?;
 
         let e = test.publish(Some(data), tarball).await.unwrap_err();
@@ -405,12 +1041,9 @@ mod tests {
         let tarball =
             OnyxTest::create_test_tarball_named(Some("content1"), Some("test"), Some("0.0.0"))?;
 
-        let data = PublishData {
-            hash: tarball.1.to_string(),
-            token: login1.token,
-        };
+        let data = PublishData::new(tarball.1.to_string(), login1.token);
 
-        let PublishResponse { package_id: _ } =
+        let PublishResponse { package_id: _, .. } =
             test.publish(Some(data.clone()), tarball.clone()).await?;
 
         let tarball =
@@ -425,6 +1058,10 @@ mod tests {
             e.to_string(),
             "You are not authorized to publish versions of this package"
         );
+        assert_eq!(
+            e.downcast_ref::<ApiError>().map(|e| e.code),
+            Some(OnyxErrorCode::Forbidden)
+        );
         Ok(())
     }
 
@@ -435,13 +1072,14 @@ mod tests {
         let tarball = OnyxTest::create_test_tarball(Some("content1"))?;
         let tarball2 = OnyxTest::create_test_tarball(Some("content2"))?;
 
-        let data = PublishData {
-            hash: tarball2.1.to_string(),
-            token: login.token,
-        };
+        let data = PublishData::new(tarball2.1.to_string(), login.token);
 
         let e = test.publish(Some(data), tarball).await.unwrap_err();
         assert_eq!(e.to_string(), "Hash mismatch for uploaded tarball!");
+        assert_eq!(
+            e.downcast_ref::<ApiError>().map(|e| e.code),
+            Some(OnyxErrorCode::HashMismatch)
+        );
         Ok(())
     }
 
@@ -452,19 +1090,96 @@ mod tests {
         let tarball =
             OnyxTest::create_test_tarball_named(Some("content1"), Some("test"), Some("0.0.0"))?;
 
-        let data = PublishData {
-            hash: tarball.1.to_string(),
-            token: login.token.clone(),
-        };
-        let PublishResponse { package_id: _ } = test.publish(Some(data), tarball).await?;
+        let data = PublishData::new(tarball.1.to_string(), login.token.clone());
+        let PublishResponse { package_id: _, .. } = test.publish(Some(data), tarball).await?;
 
         let tarball =
             OnyxTest::create_test_tarball_named(Some("content2"), Some("test"), Some("0.0.0"))?;
-        let data = PublishData {
-            hash: tarball.1.to_string(),
-            token: login.token,
+        let data = PublishData::new(tarball.1.to_string(), login.token);
+
+        let e = test.publish(Some(data), tarball).await.unwrap_err();
+        assert!(
+            e.to_string()
+                .starts_with("Version already exists for package!")
+        );
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn should_require_semver_version_names() -> Result<()> {
+        let test = OnyxTest::new().await?;
+        let (login, _password) = test.signup(None).await?;
+        let publish = async |content: &str, version: &str| -> Result<PublishResponse> {
+            let tarball =
+                OnyxTest::create_test_tarball_named(Some(content), Some("semver"), Some(version))?;
+            let data = PublishData::new(tarball.1.to_string(), login.token.clone());
+            test.publish(Some(data), tarball).await
         };
 
+        // without legacy_version_names the tarball is rejected before it's stored
+        for version in ["next", "vnext"] {
+            let e = publish("content1", version).await.unwrap_err();
+            assert_eq!(
+                e.downcast_ref::<ApiError>().map(|e| e.code),
+                Some(OnyxErrorCode::InvalidPackage)
+            );
+        }
+        assert!(
+            test.api
+                .load_package_latest_version("semver")
+                .await
+                .is_err()
+        );
+
+        // a leading v is dropped
+        publish("content2", "v1.0.0+a").await?;
+        let (_, version) = test.api.load_package_latest_version("semver").await?;
+        assert_eq!(version.name, "1.0.0+a");
+
+        // semver doesn't order versions by build metadata
+        let e = publish("content3", "1.0.0+b").await.unwrap_err();
+        assert_eq!(
+            e.downcast_ref::<ApiError>().map(|e| e.code),
+            Some(OnyxErrorCode::Conflict)
+        );
+        publish("content4", "1.0.1").await?;
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn should_publish_legacy_version_names() -> Result<()> {
+        let test = OnyxTest::with_config(|state| {
+            Arc::make_mut(&mut state.config).legacy_version_names = true
+        })
+        .await?;
+        let (login, _password) = test.signup(None).await?;
+        let tarball = OnyxTest::create_test_tarball_named(None, Some("legacy"), Some("vnext"))?;
+        let data = PublishData::new(tarball.1.to_string(), login.token);
+        test.publish(Some(data), tarball).await?;
+        let (_, version) = test.api.load_package_latest_version("legacy").await?;
+        assert_eq!(version.name, "vnext");
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn should_replay_idempotent_publish() -> Result<()> {
+        let test = OnyxTest::new().await?;
+        let (login, _password) = test.signup(None).await?;
+        let tarball = OnyxTest::create_test_tarball(None)?;
+        let idempotency_key = nanoid!();
+
+        let data = PublishData::new(tarball.1.to_string(), login.token);
+        let r1 = test
+            .api
+            .publish_with_idempotency_key(data.clone(), tarball.0.clone(), &idempotency_key)
+            .await?;
+        let r2 = test
+            .api
+            .publish_with_idempotency_key(data.clone(), tarball.0.clone(), &idempotency_key)
+            .await?;
+        assert_eq!(r1.package_id, r2.package_id);
+
+        // without the key the retry is treated as a new publish
         let e = test.publish(Some(data), tarball).await.unwrap_err();
         assert!(
             e.to_string()
@@ -473,6 +1188,70 @@ mod tests {
         Ok(())
     }
 
+    #[tokio::test]
+    async fn should_purge_expired_idempotency_keys() -> Result<()> {
+        let test = OnyxTest::new().await?;
+        let (login, _password) = test.signup(None).await?;
+        let tarball = OnyxTest::create_test_tarball(None)?;
+        let data = PublishData::new(tarball.1.to_string(), login.token.clone());
+        test.api
+            .publish_with_idempotency_key(data, tarball.0, "fresh")
+            .await?;
+        {
+            let write = test.state.db.begin_write()?;
+            write.open_table(IDEMPOTENCY_KEY_TABLE)?.insert(
+                (login.user.id.as_str(), "expired"),
+                (
+                    tarball.1.to_string().as_str(),
+                    "package",
+                    timestamp() - IDEMPOTENCY_KEY_TTL,
+                ),
+            )?;
+            write.commit()?;
+        }
+
+        assert_eq!(purge_expired_idempotency_keys(&test.state)?, 1);
+        let read = test.state.db.begin_read()?;
+        let idempotency_key_table = read.open_table(IDEMPOTENCY_KEY_TABLE)?;
+        assert!(
+            idempotency_key_table
+                .get((login.user.id.as_str(), "expired"))?
+                .is_none()
+        );
+        assert!(
+            idempotency_key_table
+                .get((login.user.id.as_str(), "fresh"))?
+                .is_some()
+        );
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn fail_publish_reused_idempotency_key() -> Result<()> {
+        let test = OnyxTest::new().await?;
+        let (login, _password) = test.signup(None).await?;
+        let idempotency_key = nanoid!();
+
+        let tarball = OnyxTest::create_test_tarball(Some("content1"))?;
+        let data = PublishData::new(tarball.1.to_string(), login.token.clone());
+        test.api
+            .publish_with_idempotency_key(data, tarball.0, &idempotency_key)
+            .await?;
+
+        let tarball = OnyxTest::create_test_tarball(Some("content2"))?;
+        let data = PublishData::new(tarball.1.to_string(), login.token);
+        let e = test
+            .api
+            .publish_with_idempotency_key(data, tarball.0, &idempotency_key)
+            .await
+            .unwrap_err();
+        assert_eq!(
+            e.to_string(),
+            "Idempotency key was already used for a different publish"
+        );
+        Ok(())
+    }
+
     #[tokio::test]
     async fn publish_package_and_new_version() -> Result<()> {
         let test = OnyxTest::new().await?;
@@ -480,18 +1259,12 @@ mod tests {
         let tarball =
             OnyxTest::create_test_tarball_named(Some("content1"), Some("test"), Some("0.0.0"))?;
 
-        let data = PublishData {
-            hash: tarball.1.to_string(),
-            token: login.token.clone(),
-        };
-        let PublishResponse { package_id } = test.publish(Some(data), tarball).await?;
+        let data = PublishData::new(tarball.1.to_string(), login.token.clone());
+        let PublishResponse { package_id, .. } = test.publish(Some(data), tarball).await?;
 
         let tarball =
             OnyxTest::create_test_tarball_named(Some("content2"), Some("test"), Some("0.0.1"))?;
-        let data = PublishData {
-            hash: tarball.1.to_string(),
-            token: login.token,
-        };
+        let data = PublishData::new(tarball.1.to_string(), login.token);
 
         let r2 = test.publish(Some(data), tarball).await?;
         assert_eq!(r2.package_id, package_id);
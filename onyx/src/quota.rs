@@ -0,0 +1,271 @@
+use axum::extract::Path;
+use axum::extract::State;
+use axum::response::Json as ResponseJson;
+use redb::ReadableTable;
+
+use onyx_api::prelude::*;
+
+use super::Config;
+use super::OnyxError;
+use super::OnyxState;
+use super::db::Db;
+use super::db::WriteTxn;
+use super::session::AdminSession;
+use super::session::AuthSession;
+use super::validate::ValidJson;
+
+/// Error if storing `bytes` more for packages owned by `user_id` would take them over their
+/// quota.
+pub fn check(
+    write: &WriteTxn,
+    config: &Config,
+    user_id: &str,
+    bytes: u64,
+) -> Result<(), OnyxError> {
+    let quota = {
+        let user_quota_table = write.open_table(USER_QUOTA_TABLE)?;
+        user_quota_table
+            .get(user_id)?
+            .map(|quota| quota.value())
+            .or(config.storage_quota)
+    };
+    let Some(quota) = quota else {
+        return Ok(());
+    };
+    let user_storage_table = write.open_table(USER_STORAGE_TABLE)?;
+    let used = user_storage_table
+        .get(user_id)?
+        .map(|used| used.value())
+        .unwrap_or_default();
+    if used + bytes > quota {
+        return Err(OnyxError::new(
+            OnyxErrorCode::QuotaExceeded,
+            &format!(
+                "Storing {bytes} more bytes would exceed the storage quota of the package owner, {used} of {quota} bytes are used"
+            ),
+        ));
+    }
+    Ok(())
+}
+
+/// Count `bytes` stored for `package_id` against its owner `user_id`.
+pub fn charge(
+    write: &WriteTxn,
+    user_id: &str,
+    package_id: &str,
+    bytes: u64,
+) -> Result<(), OnyxError> {
+    let mut user_storage_table = write.open_table(USER_STORAGE_TABLE)?;
+    let mut package_storage_table = write.open_table(PACKAGE_STORAGE_TABLE)?;
+    let used = user_storage_table
+        .get(user_id)?
+        .map(|used| used.value())
+        .unwrap_or_default();
+    user_storage_table.insert(user_id, used + bytes)?;
+    let stored = package_storage_table
+        .get(package_id)?
+        .map(|stored| stored.value())
+        .unwrap_or_default();
+    package_storage_table.insert(package_id, stored + bytes)?;
+    Ok(())
+}
+
+/// Move the bytes stored for `package_id` from its old owner to its new one. The new owner
+/// may end up over their quota, they just can't store more until they're under it again.
+pub fn transfer(
+    write: &WriteTxn,
+    package_id: &str,
+    from_user_id: &str,
+    to_user_id: &str,
+) -> Result<(), OnyxError> {
+    let package_storage_table = write.open_table(PACKAGE_STORAGE_TABLE)?;
+    let mut user_storage_table = write.open_table(USER_STORAGE_TABLE)?;
+    let Some(bytes) = package_storage_table
+        .get(package_id)?
+        .map(|stored| stored.value())
+    else {
+        return Ok(());
+    };
+    let from_used = user_storage_table
+        .get(from_user_id)?
+        .map(|used| used.value())
+        .unwrap_or_default();
+    user_storage_table.insert(from_user_id, from_used.saturating_sub(bytes))?;
+    let to_used = user_storage_table
+        .get(to_user_id)?
+        .map(|used| used.value())
+        .unwrap_or_default();
+    user_storage_table.insert(to_user_id, to_used + bytes)?;
+    Ok(())
+}
+
+fn storage_usage(db: &Db, config: &Config, user: UserModel) -> Result<StorageUsage, OnyxError> {
+    let read = db.begin_read()?;
+    let user_storage_table = read.open_table(USER_STORAGE_TABLE)?;
+    let user_quota_table = read.open_table(USER_QUOTA_TABLE)?;
+    Ok(StorageUsage {
+        used_bytes: user_storage_table
+            .get(user.id.as_str())?
+            .map(|used| used.value())
+            .unwrap_or_default(),
+        quota_bytes: user_quota_table
+            .get(user.id.as_str())?
+            .map(|quota| quota.value())
+            .or(config.storage_quota),
+        username: user.username,
+    })
+}
+
+fn user_by_username(db: &Db, username: &str) -> Result<UserModel, OnyxError> {
+    let read = db.begin_read()?;
+    let username_table = read.open_table(USERNAME_USER_ID_TABLE)?;
+    let user_table = read.open_table(USER_TABLE)?;
+    if let Some(user_id) = username_table.get(username)?
+        && let Some(user) = user_table.get(user_id.value())?
+    {
+        Ok(user.value())
+    } else {
+        Err(OnyxError::not_found("User not found"))
+    }
+}
+
+/// The storage the authenticated user uses and their quota.
+pub async fn usage(
+    State(state): State<OnyxState>,
+    session: AuthSession,
+) -> Result<ResponseJson<StorageUsage>, OnyxError> {
+    let user = session.user(&state.db)?;
+    Ok(ResponseJson(storage_usage(&state.db, &state.config, user)?))
+}
+
+pub async fn admin_quota(
+    State(state): State<OnyxState>,
+    _admin: AdminSession,
+    Path(username): Path<String>,
+) -> Result<ResponseJson<StorageUsage>, OnyxError> {
+    let user = user_by_username(&state.db, &username)?;
+    Ok(ResponseJson(storage_usage(&state.db, &state.config, user)?))
+}
+
+pub async fn admin_set_quota(
+    State(state): State<OnyxState>,
+    admin: AdminSession,
+    Path(username): Path<String>,
+    ValidJson(payload): ValidJson<SetQuotaRequest>,
+) -> Result<ResponseJson<StorageUsage>, OnyxError> {
+    let user = user_by_username(&state.db, &username)?;
+    log::info!(
+        "admin {} setting the storage quota of {} to {:?}",
+        admin.user.username,
+        user.username,
+        payload.quota_bytes
+    );
+    let write = state.db.begin_write()?;
+    {
+        let mut user_quota_table = write.open_table(USER_QUOTA_TABLE)?;
+        match payload.quota_bytes {
+            Some(quota) => user_quota_table.insert(user.id.as_str(), quota)?,
+            None => user_quota_table.remove(user.id.as_str())?,
+        };
+    }
+    write.commit()?;
+    Ok(ResponseJson(storage_usage(&state.db, &state.config, user)?))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use anyhow::Result;
+    use nanoid::nanoid;
+    use onyx_api::prelude::*;
+
+    use crate::testing::OnyxTest;
+
+    #[tokio::test]
+    async fn should_enforce_storage_quota() -> Result<()> {
+        let admin_username = nanoid!();
+        let admins = [admin_username.clone()].into_iter().collect();
+        // every package published here is the size of this tarball, their names are all nanoids
+        let tarball = OnyxTest::create_test_tarball_named(None, Some(&nanoid!()), None)?;
+        let size = tarball.0.len() as u64;
+        let quota = size + 1;
+        let test = OnyxTest::with_config(|state| {
+            let config = Arc::make_mut(&mut state.config);
+            config.admins = admins;
+            config.storage_quota = Some(quota);
+        })
+        .await?;
+        let (admin, _password) = test
+            .signup(Some(LoginRequest {
+                username: admin_username,
+                password: nanoid!(),
+                ..Default::default()
+            }))
+            .await?;
+        let (login, _password) = test.signup(None).await?;
+
+        test.seed_package(&login, &nanoid!(), &["0.0.0"]).await?;
+        let usage = test.api.storage_usage(&login.token).await?;
+        assert_eq!(usage.used_bytes, size);
+        assert_eq!(usage.quota_bytes, Some(quota));
+
+        let err = test
+            .seed_package(&login, &nanoid!(), &["0.0.0"])
+            .await
+            .unwrap_err();
+        let err = err.downcast_ref::<ApiError>().unwrap();
+        assert_eq!(err.code, OnyxErrorCode::QuotaExceeded);
+        assert_eq!(test.api.storage_usage(&login.token).await?.used_bytes, size);
+
+        // only admins can change quotas
+        let err = test
+            .api
+            .admin_set_quota(&login.token, &login.user.username, None)
+            .await
+            .unwrap_err();
+        let err = err.downcast_ref::<ApiError>().unwrap();
+        assert_eq!(err.code, OnyxErrorCode::Forbidden);
+        let usage = test
+            .api
+            .admin_set_quota(&admin.token, &login.user.username, Some(10 * quota))
+            .await?;
+        assert_eq!(usage.quota_bytes, Some(10 * quota));
+        test.seed_package(&login, &nanoid!(), &["0.0.0"]).await?;
+        let usage = test
+            .api
+            .admin_quota(&admin.token, &login.user.username)
+            .await?;
+        assert_eq!(usage.used_bytes, 2 * size);
+
+        let usage = test
+            .api
+            .admin_set_quota(&admin.token, &login.user.username, None)
+            .await?;
+        assert_eq!(usage.quota_bytes, Some(quota));
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn should_move_usage_on_transfer() -> Result<()> {
+        let test = OnyxTest::new().await?;
+        let (owner, _password) = test.signup(None).await?;
+        let (recipient, _password) = test.signup(None).await?;
+        let name = nanoid!();
+        test.seed_package(&owner, &name, &["0.0.0"]).await?;
+        let usage = test.api.storage_usage(&owner.token).await?;
+        assert!(usage.used_bytes > 0);
+        assert_eq!(usage.quota_bytes, None);
+
+        test.api
+            .request_transfer(&owner.token, &name, &recipient.user.username)
+            .await?;
+        test.api.accept_transfer(&recipient.token, &name).await?;
+        assert_eq!(test.api.storage_usage(&owner.token).await?.used_bytes, 0);
+        assert_eq!(
+            test.api.storage_usage(&recipient.token).await?.used_bytes,
+            usage.used_bytes
+        );
+        Ok(())
+    }
+}
@@ -0,0 +1,241 @@
+use std::collections::BTreeMap;
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::sync::atomic::AtomicU64;
+use std::sync::atomic::Ordering;
+
+use redb::TableHandle;
+
+use onyx_api::prelude::*;
+
+use super::git::GitRef;
+
+/// Values with the tick they were last used at, and the keys by that tick so the least
+/// recently used is first.
+struct Entries<V> {
+    values: HashMap<String, (V, u64)>,
+    used: BTreeMap<u64, String>,
+    clock: u64,
+}
+
+/// A map holding at most `capacity` values, dropping the least recently used first.
+pub struct Lru<V> {
+    name: &'static str,
+    capacity: usize,
+    entries: Mutex<Entries<V>>,
+    hits: AtomicU64,
+    misses: AtomicU64,
+}
+
+impl<V: Clone> Lru<V> {
+    fn new(name: &'static str, capacity: usize) -> Self {
+        Self {
+            name,
+            capacity,
+            entries: Mutex::new(Entries {
+                values: HashMap::new(),
+                used: BTreeMap::new(),
+                clock: 0,
+            }),
+            hits: AtomicU64::new(0),
+            misses: AtomicU64::new(0),
+        }
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, Entries<V>> {
+        self.entries.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    fn get(&self, key: &str) -> Option<V> {
+        let mut entries = self.lock();
+        let Entries {
+            values,
+            used,
+            clock,
+        } = &mut *entries;
+        let Some((value, last_used)) = values.get_mut(key) else {
+            self.misses.fetch_add(1, Ordering::Relaxed);
+            return None;
+        };
+        used.remove(last_used);
+        *clock += 1;
+        *last_used = *clock;
+        used.insert(*clock, key.to_string());
+        self.hits.fetch_add(1, Ordering::Relaxed);
+        Some(value.clone())
+    }
+
+    /// Keep `value` unless `generation` has moved on since it was read.
+    fn insert(&self, key: &str, value: V, read_at: u64, generation: &AtomicU64) {
+        if self.capacity == 0 {
+            return;
+        }
+        let mut entries = self.lock();
+        // checked while holding the lock, `ReadCache::invalidate` empties the cache after
+        // moving the generation on
+        if generation.load(Ordering::SeqCst) != read_at {
+            return;
+        }
+        entries.clock += 1;
+        let clock = entries.clock;
+        if let Some((_, last_used)) = entries.values.insert(key.to_string(), (value, clock)) {
+            entries.used.remove(&last_used);
+        }
+        entries.used.insert(clock, key.to_string());
+        while entries.values.len() > self.capacity {
+            let Some((_, key)) = entries.used.pop_first() else {
+                break;
+            };
+            entries.values.remove(&key);
+        }
+    }
+
+    fn clear(&self) {
+        let mut entries = self.lock();
+        entries.values.clear();
+        entries.used.clear();
+    }
+
+    fn metrics(&self) -> CacheMetrics {
+        CacheMetrics {
+            name: self.name.to_string(),
+            entries: self.lock().values.len() as u64,
+            capacity: self.capacity as u64,
+            hits: self.hits.load(Ordering::Relaxed),
+            misses: self.misses.load(Ordering::Relaxed),
+        }
+    }
+}
+
+/// In-memory caches of the reads made for most requests: packages by name, their latest
+/// version and the refs advertised to git. Each holds `read_cache_entries` values and is
+/// emptied whenever a write to the tables they're read from commits, e.g. a publish or yank.
+pub struct ReadCache {
+    /// Moved on by every invalidation, values read before it aren't kept after it.
+    generation: AtomicU64,
+    pub packages: Lru<Option<PackageModel>>,
+    pub latest_versions: Lru<Option<(PackageModel, PackageVersionModel)>>,
+    /// Keyed by package id.
+    pub refs: Lru<Vec<GitRef>>,
+}
+
+impl ReadCache {
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            generation: AtomicU64::new(0),
+            packages: Lru::new("packages", capacity),
+            latest_versions: Lru::new("latest_versions", capacity),
+            refs: Lru::new("refs", capacity),
+        }
+    }
+
+    /// `key` in `lru`, read with `load` if it isn't cached.
+    pub fn get_or_load<V: Clone, E>(
+        &self,
+        lru: &Lru<V>,
+        key: &str,
+        load: impl FnOnce() -> Result<V, E>,
+    ) -> Result<V, E> {
+        if let Some(value) = lru.get(key) {
+            return Ok(value);
+        }
+        let read_at = self.generation.load(Ordering::SeqCst);
+        let value = load()?;
+        lru.insert(key, value.clone(), read_at, &self.generation);
+        Ok(value)
+    }
+
+    /// Empty the caches if a committed write opened any of `tables`, which may have changed
+    /// what they were read from.
+    pub fn invalidate<'a>(&self, mut tables: impl Iterator<Item = &'a String>) {
+        let sources = [
+            PACKAGE_TABLE.name(),
+            PACKAGE_NAME_TABLE.name(),
+            VERSION_TABLE.name(),
+            GIT_REFS_TABLE.name(),
+        ];
+        if !tables.any(|table| sources.contains(&table.as_str())) {
+            return;
+        }
+        self.generation.fetch_add(1, Ordering::SeqCst);
+        self.packages.clear();
+        self.latest_versions.clear();
+        self.refs.clear();
+    }
+
+    pub fn metrics(&self) -> Vec<CacheMetrics> {
+        vec![
+            self.packages.metrics(),
+            self.latest_versions.metrics(),
+            self.refs.metrics(),
+        ]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use anyhow::Result;
+    use nanoid::nanoid;
+    use onyx_api::prelude::*;
+
+    use super::*;
+    use crate::testing::OnyxTest;
+
+    #[test]
+    fn should_drop_least_recently_used() {
+        let cache = ReadCache::new(2);
+        let load = |value: u32| move || Ok::<_, ()>(Some(value));
+        let lru = Lru::<Option<u32>>::new("test", 2);
+        assert_eq!(cache.get_or_load(&lru, "a", load(1)), Ok(Some(1)));
+        assert_eq!(cache.get_or_load(&lru, "b", load(2)), Ok(Some(2)));
+        // a is used more recently than b, so b is dropped for c
+        assert_eq!(cache.get_or_load(&lru, "a", load(10)), Ok(Some(1)));
+        assert_eq!(cache.get_or_load(&lru, "c", load(3)), Ok(Some(3)));
+        assert_eq!(cache.get_or_load(&lru, "b", load(20)), Ok(Some(20)));
+        assert_eq!(cache.get_or_load(&lru, "c", load(30)), Ok(Some(3)));
+        let metrics = lru.metrics();
+        assert_eq!((metrics.hits, metrics.misses, metrics.entries), (2, 4, 2));
+    }
+
+    #[tokio::test]
+    async fn should_invalidate_on_publish() -> Result<()> {
+        let admin_username = nanoid!();
+        let test = OnyxTest::with_admins(&[&admin_username]).await?;
+        let (admin, _password) = test
+            .signup(Some(LoginRequest {
+                username: admin_username,
+                password: nanoid!(),
+                ..Default::default()
+            }))
+            .await?;
+        let name = nanoid!();
+        let publish = async |version: &str| -> Result<()> {
+            let tarball = OnyxTest::create_test_tarball_named(None, Some(&name), Some(version))?;
+            test.publish(
+                Some(PublishData::new(tarball.1.to_string(), admin.token.clone())),
+                tarball,
+            )
+            .await?;
+            Ok(())
+        };
+        publish("0.1.0").await?;
+        for _ in 0..3 {
+            let (_package, version) = test.api.load_package_latest_version(&name).await?;
+            assert_eq!(version.name, "0.1.0");
+        }
+        publish("0.2.0").await?;
+        let (_package, version) = test.api.load_package_latest_version(&name).await?;
+        assert_eq!(version.name, "0.2.0");
+
+        let metrics = test.api.admin_db_metrics(&admin.token).await?;
+        let latest_versions = metrics
+            .caches
+            .iter()
+            .find(|c| c.name == "latest_versions")
+            .unwrap();
+        assert_eq!(latest_versions.hits, 2);
+        assert_eq!(latest_versions.misses, 2);
+        assert_eq!(latest_versions.entries, 1);
+        Ok(())
+    }
+}
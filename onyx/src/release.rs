@@ -0,0 +1,37 @@
+use axum::extract::State;
+use axum::response::Json as ResponseJson;
+
+use onyx_api::prelude::*;
+
+use super::OnyxError;
+use super::OnyxState;
+
+pub async fn cli_version(
+    State(state): State<OnyxState>,
+) -> Result<ResponseJson<CliVersionResponse>, OnyxError> {
+    let version = state
+        .config
+        .cli_version
+        .clone()
+        .ok_or(OnyxError::not_found(
+            "This registry doesn't announce nrpm releases",
+        ))?;
+    Ok(ResponseJson(CliVersionResponse { version }))
+}
+
+#[cfg(test)]
+mod tests {
+    use anyhow::Result;
+
+    use crate::testing::OnyxTest;
+
+    #[tokio::test]
+    async fn should_serve_cli_version() -> Result<()> {
+        let test = OnyxTest::with_cli_version("1.2.3").await?;
+        assert_eq!(test.api.cli_version().await?, "1.2.3");
+
+        let test = OnyxTest::new().await?;
+        assert!(test.api.cli_version().await.is_err());
+        Ok(())
+    }
+}
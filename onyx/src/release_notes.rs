@@ -0,0 +1,82 @@
+use std::str::FromStr;
+
+use axum::extract::Path;
+use axum::extract::State;
+use axum::response::Json as ResponseJson;
+
+use onyx_api::prelude::*;
+
+use super::OnyxError;
+use super::OnyxState;
+use super::db::WriteTxn;
+
+pub fn store(write: &WriteTxn, version_id: &HashId, release_notes: &str) -> Result<(), OnyxError> {
+    let mut version_release_notes_table = write.open_table(VERSION_RELEASE_NOTES_TABLE)?;
+    version_release_notes_table.insert(version_id, release_notes)?;
+    Ok(())
+}
+
+pub async fn version_release_notes(
+    State(state): State<OnyxState>,
+    Path(id): Path<String>,
+) -> Result<ResponseJson<VersionReleaseNotes>, OnyxError> {
+    let version_id = HashId::from_str(&id)?;
+    let read = state.db.begin_read()?;
+    let version_table = read.open_table(VERSION_TABLE)?;
+    if version_table.get(&version_id)?.is_none() {
+        return Err(OnyxError::not_found("Unable to find version"));
+    }
+    let version_release_notes_table = read.open_table(VERSION_RELEASE_NOTES_TABLE)?;
+    match version_release_notes_table.get(&version_id)? {
+        Some(release_notes) => Ok(ResponseJson(VersionReleaseNotes {
+            release_notes: release_notes.value().to_string(),
+        })),
+        None => Err(OnyxError::not_found("Version has no release notes")),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use anyhow::Result;
+    use nanoid::nanoid;
+    use onyx_api::prelude::*;
+
+    use crate::testing::OnyxTest;
+
+    #[tokio::test]
+    async fn should_store_release_notes_on_publish() -> Result<()> {
+        let test = OnyxTest::new().await?;
+        let (login, _password) = test.signup(None).await?;
+        let name = nanoid!();
+        let mut version_ids = vec![];
+        for (version, release_notes) in [("0.1.0", None), ("0.2.0", Some("## 0.2.0\n\n- Fix"))] {
+            let nargo_toml = format!("[package]\nname = \"{name}\"\nversion = \"{version}\"\n");
+            let (bytes, hash) =
+                OnyxTest::create_tarball_from_files(&[("Nargo.toml", &nargo_toml)])?;
+            let mut publish_data = PublishData::new(hash.to_string(), login.token.clone());
+            publish_data.release_notes = release_notes.map(str::to_string);
+            test.publish(Some(publish_data), (bytes, hash)).await?;
+            version_ids.push(HashId::from(hash));
+        }
+        assert_eq!(test.api.version_release_notes(&version_ids[0]).await?, None);
+        assert_eq!(
+            test.api
+                .version_release_notes(&version_ids[1])
+                .await?
+                .as_deref(),
+            Some("## 0.2.0\n\n- Fix")
+        );
+
+        let nargo_toml = format!("[package]\nname = \"{name}\"\nversion = \"0.3.0\"\n");
+        let (bytes, hash) = OnyxTest::create_tarball_from_files(&[("Nargo.toml", &nargo_toml)])?;
+        let mut publish_data = PublishData::new(hash.to_string(), login.token.clone());
+        publish_data.release_notes = Some(String::new());
+        let err = test
+            .publish(Some(publish_data), (bytes, hash))
+            .await
+            .unwrap_err();
+        let err = err.downcast_ref::<ApiError>().unwrap();
+        assert_eq!(err.code, OnyxErrorCode::ValidationFailed);
+        Ok(())
+    }
+}
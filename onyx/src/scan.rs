@@ -0,0 +1,640 @@
+use std::fs::File;
+use std::path::Path;
+use std::path::PathBuf;
+use std::process::Command;
+use std::process::Stdio;
+use std::str::FromStr;
+use std::sync::Arc;
+use std::time::Duration;
+use std::time::Instant;
+
+use anyhow::Result;
+use axum::extract::Path as UrlPath;
+use axum::extract::State;
+use axum::http::StatusCode;
+use axum::response::Json as ResponseJson;
+use redb::ReadableTable;
+use tar::Archive;
+
+use onyx_api::prelude::*;
+
+use super::Config;
+use super::OnyxError;
+use super::OnyxState;
+use super::audit;
+use super::build;
+use super::db::WriteTxn;
+use super::publish::NewVersion;
+use super::publish::store_version;
+use super::quota;
+use super::session::AdminSession;
+use super::session::AuthSession;
+use super::webhook;
+
+const DEFAULT_SCAN_TIMEOUT: u64 = 60;
+// uploads scanned per run of the job, the rest wait for the next run
+const SCANS_PER_RUN: usize = 4;
+// bytes of scanner output kept as a finding
+const MAX_OUTPUT_LEN: usize = 4096;
+
+/// Looks over the extracted contents of an upload before it's published.
+pub trait Scanner: Send + Sync {
+    /// Names the scanner in its findings.
+    fn name(&self) -> String;
+
+    /// What the scanner found in the package extracted to `dir`, `None` if it's clean. An
+    /// error leaves the upload pending, to be scanned again on the next run.
+    fn scan(&self, dir: &Path) -> Result<Option<String>>;
+}
+
+/// Runs a command in the extracted package, with its path as the only argument. The command
+/// runs without the registry's environment and exits 0 for a clean package or 1 with what it
+/// found on stdout or stderr, like `clamscan`. Anything else, or running longer than
+/// `timeout` seconds, is an error.
+pub struct CommandScanner {
+    pub command: PathBuf,
+    pub timeout: u64,
+}
+
+impl Scanner for CommandScanner {
+    fn name(&self) -> String {
+        self.command.to_string_lossy().into_owned()
+    }
+
+    fn scan(&self, dir: &Path) -> Result<Option<String>> {
+        let workdir = tempfile::tempdir()?;
+        let output_path = workdir.path().join("scan.log");
+        let output = File::create(&output_path)?;
+        let mut child = Command::new(&self.command)
+            .arg(dir)
+            .current_dir(dir)
+            .env_clear()
+            .env("PATH", std::env::var_os("PATH").unwrap_or_default())
+            .env("HOME", workdir.path())
+            .stdin(Stdio::null())
+            .stdout(output.try_clone()?)
+            .stderr(output)
+            .spawn()?;
+        let deadline = Instant::now() + Duration::from_secs(self.timeout);
+        let status = loop {
+            if let Some(status) = child.try_wait()? {
+                break status;
+            }
+            if Instant::now() >= deadline {
+                child.kill()?;
+                child.wait()?;
+                anyhow::bail!("timed out after {}s", self.timeout);
+            }
+            std::thread::sleep(Duration::from_millis(100));
+        };
+        let output = std::fs::read(&output_path)?;
+        let tail = String::from_utf8_lossy(&output[output.len().saturating_sub(MAX_OUTPUT_LEN)..])
+            .into_owned();
+        match status.code() {
+            Some(0) => Ok(None),
+            Some(1) => Ok(Some(tail)),
+            _ => anyhow::bail!("exited with {status}: {tail}"),
+        }
+    }
+}
+
+/// The scanners of `config`, one for each of `scan_commands`.
+pub fn scanners(config: &Config) -> Vec<Arc<dyn Scanner>> {
+    config
+        .scan_commands
+        .iter()
+        .map(|command| {
+            Arc::new(CommandScanner {
+                command: command.clone(),
+                timeout: config.scan_timeout.unwrap_or(DEFAULT_SCAN_TIMEOUT),
+            }) as Arc<dyn Scanner>
+        })
+        .collect()
+}
+
+/// Storage filename of the tarball of a pending upload.
+fn pending_filename(version_id: &HashId) -> String {
+    format!("pending-{}", version_id.to_string())
+}
+
+/// Hold an upload until it's scanned. Returns the status of the upload, which stays as it
+/// was if the author already uploaded the same tarball, e.g. when retrying.
+pub fn hold(
+    state: &OnyxState,
+    write: &WriteTxn,
+    pending: PendingPublishModel,
+    tarball: &mut File,
+) -> Result<PublishStatus, OnyxError> {
+    if write.open_table(VERSION_TABLE)?.get(&pending.id)?.is_some() {
+        return Err(OnyxError::conflict("Package with hash already exists"));
+    }
+    let mut pending_publish_table = write.open_table(PENDING_PUBLISH_TABLE)?;
+    if let Some(existing) = pending_publish_table.get(&pending.id)? {
+        let existing = existing.value();
+        if existing.author_id != pending.author_id {
+            return Err(OnyxError::conflict("Package with hash already exists"));
+        }
+        // a rejected upload may be tried again
+        if existing.status != PublishStatus::Rejected {
+            return Ok(existing.status);
+        }
+    }
+    let filename = pending_filename(&pending.id);
+    state.storage.remove(&filename)?;
+    state.storage.ingest_tarball(tarball, filename)?;
+    pending_publish_table.insert(pending.id.clone(), pending)?;
+    Ok(PublishStatus::Pending)
+}
+
+/// Scan pending uploads with the configured scanners. Clean uploads are published, others
+/// are flagged for an admin to review and their authors are notified. Returns the number of
+/// uploads scanned.
+pub async fn run_queue(state: &OnyxState) -> Result<usize> {
+    if state.scanners.is_empty() {
+        return Ok(0);
+    }
+    let queued = {
+        let read = state.db.begin_read()?;
+        let pending_publish_table = read.open_table(PENDING_PUBLISH_TABLE)?;
+        let mut queued = vec![];
+        for entry in pending_publish_table.iter()? {
+            let pending = entry?.1.value();
+            if pending.status == PublishStatus::Pending {
+                queued.push(pending);
+            }
+            if queued.len() >= SCANS_PER_RUN {
+                break;
+            }
+        }
+        queued
+    };
+    let mut scanned = 0;
+    for pending in queued {
+        let findings = match scan(state, &pending) {
+            Ok(findings) => findings,
+            Err(e) => {
+                log::warn!("Failed to scan upload {}: {e:?}", pending.id.to_string());
+                continue;
+            }
+        };
+        scanned += 1;
+        if findings.is_empty() {
+            if let Err(e) = release(state, &pending.id, None) {
+                log::warn!(
+                    "Failed to publish scanned upload {}: {e}",
+                    pending.id.to_string()
+                );
+                if let Err(e) = reject(state, &pending.id, None, Some(e.to_string())) {
+                    log::warn!("Failed to reject upload {}: {e}", pending.id.to_string());
+                }
+            }
+            continue;
+        }
+        match flag(state, &pending.id, findings) {
+            Ok(flagged) => notify(state, &flagged).await,
+            Err(e) => log::warn!("Failed to flag upload {}: {e}", pending.id.to_string()),
+        }
+    }
+    Ok(scanned)
+}
+
+/// Run each scanner over the extracted tarball of `pending`.
+fn scan(state: &OnyxState, pending: &PendingPublishModel) -> Result<Vec<ScanFinding>> {
+    let workdir = tempfile::tempdir()?;
+    // tarballs are validated on upload, unpack_in also refuses paths outside workdir
+    let mut archive = Archive::new(state.storage.reader(&pending_filename(&pending.id))?);
+    for entry in archive.entries()? {
+        entry?.unpack_in(workdir.path())?;
+    }
+    let mut findings = vec![];
+    for scanner in &state.scanners {
+        if let Some(output) = scanner.scan(workdir.path())? {
+            findings.push(ScanFinding {
+                scanner: scanner.name(),
+                output,
+            });
+        }
+    }
+    Ok(findings)
+}
+
+/// The pending upload `version_id`, if it's still waiting to be published.
+fn waiting(write: &WriteTxn, version_id: &HashId) -> Result<PendingPublishModel, OnyxError> {
+    let pending_publish_table = write.open_table(PENDING_PUBLISH_TABLE)?;
+    match pending_publish_table.get(version_id)? {
+        Some(pending) if pending.value().status != PublishStatus::Rejected => Ok(pending.value()),
+        Some(_) => Err(OnyxError::conflict("Upload was rejected")),
+        None => Err(OnyxError::not_found("No pending upload with this id")),
+    }
+}
+
+/// Publish a pending upload, as the publish route would have without scanners.
+fn release(
+    state: &OnyxState,
+    version_id: &HashId,
+    admin_username: Option<&str>,
+) -> Result<PendingPublishModel, OnyxError> {
+    let write = state.db.begin_write()?;
+    let pending = waiting(&write, version_id)?;
+    let filename = pending_filename(version_id);
+    let package = store_version(
+        &state.storage,
+        &write,
+        NewVersion {
+            author_id: &pending.author_id,
+            package_name: pending.package_name.clone(),
+            version_name: pending.version_name.clone(),
+            hash: blake3::Hash::from_bytes(*version_id.as_bytes()),
+            created_at: timestamp(),
+            follow_owner: false,
+            source_repository: pending.source_repository.clone(),
+            source_commit: pending.source_commit.clone(),
+            release_notes: pending.release_notes.clone(),
+        },
+        &mut state.storage.reader(&filename)?,
+    )?;
+    quota::charge(&write, &package.author_id, &package.id, pending.size)?;
+    if state.config.nargo_path.is_some() {
+        build::enqueue(&write, version_id)?;
+    }
+    if let Some(notification) = pending.notification.clone() {
+        webhook::enqueue(&write, &pending.author_id, notification)?;
+    }
+    if let Some(key) = pending.idempotency_key.as_deref() {
+        let mut idempotency_key_table = write.open_table(IDEMPOTENCY_KEY_TABLE)?;
+        idempotency_key_table.insert(
+            (pending.author_id.as_str(), key),
+            (
+                version_id.to_string().as_str(),
+                package.id.as_str(),
+                timestamp(),
+            ),
+        )?;
+    }
+    write
+        .open_table(PENDING_PUBLISH_TABLE)?
+        .remove(version_id)?;
+    if let Some(admin_username) = admin_username {
+        audit::record(
+            &write,
+            AuditAction::PublishReleased,
+            Some(admin_username),
+            &pending.package_name,
+            format!("released version {}", pending.version_name),
+        )?;
+    }
+    write.commit()?;
+    state.storage.remove(&filename)?;
+    Ok(pending)
+}
+
+/// Record that the scanners found something in a pending upload.
+fn flag(
+    state: &OnyxState,
+    version_id: &HashId,
+    findings: Vec<ScanFinding>,
+) -> Result<PendingPublishModel, OnyxError> {
+    let write = state.db.begin_write()?;
+    let mut pending = waiting(&write, version_id)?;
+    pending.status = PublishStatus::Flagged;
+    pending.scanned_at = Some(timestamp());
+    pending.findings = findings;
+    write
+        .open_table(PENDING_PUBLISH_TABLE)?
+        .insert(version_id, pending.clone())?;
+    audit::record(
+        &write,
+        AuditAction::PublishFlagged,
+        None,
+        &pending.package_name,
+        format!(
+            "version {} flagged by {}",
+            pending.version_name,
+            pending
+                .findings
+                .iter()
+                .map(|finding| finding.scanner.as_str())
+                .collect::<Vec<_>>()
+                .join(", ")
+        ),
+    )?;
+    write.commit()?;
+    Ok(pending)
+}
+
+/// Reject a pending upload and discard its tarball. The record is kept so its author can see
+/// what happened to it.
+fn reject(
+    state: &OnyxState,
+    version_id: &HashId,
+    admin_username: Option<&str>,
+    reason: Option<String>,
+) -> Result<PendingPublishModel, OnyxError> {
+    let write = state.db.begin_write()?;
+    let mut pending = waiting(&write, version_id)?;
+    pending.status = PublishStatus::Rejected;
+    pending.rejected_by = admin_username.map(str::to_string);
+    if let Some(reason) = reason {
+        pending.findings.push(ScanFinding {
+            scanner: "registry".to_string(),
+            output: reason,
+        });
+    }
+    write
+        .open_table(PENDING_PUBLISH_TABLE)?
+        .insert(version_id, pending.clone())?;
+    if let Some(admin_username) = admin_username {
+        audit::record(
+            &write,
+            AuditAction::PublishRejected,
+            Some(admin_username),
+            &pending.package_name,
+            format!("rejected version {}", pending.version_name),
+        )?;
+    }
+    write.commit()?;
+    state.storage.remove(&pending_filename(version_id))?;
+    Ok(pending)
+}
+
+/// Tell the author of a flagged upload about it, if they have a webhook. Best effort, the
+/// author also sees it when polling the upload.
+async fn notify(state: &OnyxState, flagged: &PendingPublishModel) {
+    let webhook = || -> Result<Option<WebhookModel>, OnyxError> {
+        let read = state.db.begin_read()?;
+        let webhook_table = read.open_table(WEBHOOK_TABLE)?;
+        Ok(webhook_table
+            .get(flagged.author_id.as_str())?
+            .map(|webhook| webhook.value()))
+    };
+    if let Ok(Some(webhook)) = webhook()
+        && let Err(e) = state
+            .webhooks
+            .send(&webhook, "scan", &status_response(flagged))
+            .await
+    {
+        log::warn!(
+            "Failed to notify user {} of a scan: {e:?}",
+            flagged.author_id
+        );
+    }
+}
+
+fn status_response(pending: &PendingPublishModel) -> PublishStatusResponse {
+    PublishStatusResponse {
+        package_name: pending.package_name.clone(),
+        version_name: pending.version_name.clone(),
+        status: pending.status,
+        findings: pending.findings.clone(),
+    }
+}
+
+/// Where an upload of the authenticated user is on its way to being published.
+pub async fn publish_status(
+    State(state): State<OnyxState>,
+    session: AuthSession,
+    UrlPath(id): UrlPath<String>,
+) -> Result<ResponseJson<PublishStatusResponse>, OnyxError> {
+    let version_id = HashId::from_str(&id)?;
+    let read = state.db.begin_read()?;
+    if let Some(version) = read.open_table(VERSION_TABLE)?.get(&version_id)? {
+        let version = version.value();
+        let package = read
+            .open_table(PACKAGE_TABLE)?
+            .get(version.package_id.as_str())?
+            .ok_or(OnyxError::not_found("Package not found"))?
+            .value();
+        return Ok(ResponseJson(PublishStatusResponse {
+            package_name: package.name,
+            version_name: version.name,
+            status: PublishStatus::Published,
+            findings: vec![],
+        }));
+    }
+    match read.open_table(PENDING_PUBLISH_TABLE)?.get(&version_id)? {
+        Some(pending) if pending.value().author_id == session.user_id => {
+            Ok(ResponseJson(status_response(&pending.value())))
+        }
+        _ => Err(OnyxError::not_found("No upload with this id")),
+    }
+}
+
+/// Uploads waiting to be scanned or flagged by a scanner, oldest first.
+pub async fn pending_publishes(
+    State(state): State<OnyxState>,
+    _admin: AdminSession,
+) -> Result<ResponseJson<Vec<PendingPublishModel>>, OnyxError> {
+    let read = state.db.begin_read()?;
+    let pending_publish_table = read.open_table(PENDING_PUBLISH_TABLE)?;
+    let mut pending = vec![];
+    for entry in pending_publish_table.iter()? {
+        let upload = entry?.1.value();
+        if upload.status != PublishStatus::Rejected {
+            pending.push(upload);
+        }
+    }
+    pending.sort_by_key(|upload| upload.uploaded_at);
+    Ok(ResponseJson(pending))
+}
+
+pub async fn release_publish(
+    State(state): State<OnyxState>,
+    admin: AdminSession,
+    UrlPath(id): UrlPath<String>,
+) -> Result<ResponseJson<PublishStatusResponse>, OnyxError> {
+    let version_id = HashId::from_str(&id)?;
+    let mut released = release(&state, &version_id, Some(&admin.user.username))?;
+    released.status = PublishStatus::Published;
+    Ok(ResponseJson(status_response(&released)))
+}
+
+pub async fn reject_publish(
+    State(state): State<OnyxState>,
+    admin: AdminSession,
+    UrlPath(id): UrlPath<String>,
+) -> Result<StatusCode, OnyxError> {
+    let version_id = HashId::from_str(&id)?;
+    reject(&state, &version_id, Some(&admin.user.username), None)?;
+    Ok(StatusCode::NO_CONTENT)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::os::unix::fs::PermissionsExt;
+    use std::path::Path;
+    use std::sync::Arc;
+
+    use anyhow::Result;
+    use nanoid::nanoid;
+    use onyx_api::prelude::*;
+
+    use super::CommandScanner;
+    use super::Scanner;
+    use crate::testing::OnyxTest;
+
+    // flags packages containing `malware`, exits 2 for packages containing `broken`
+    const SCAN_COMMAND: &str = r#"#!/bin/sh
+if grep -rq broken "$1"; then
+    exit 2
+fi
+if grep -rl malware "$1"; then
+    exit 1
+fi
+"#;
+
+    /// Flags packages whose `aaaaa` file contains `malware`.
+    struct TestScanner;
+
+    impl Scanner for TestScanner {
+        fn name(&self) -> String {
+            "test".to_string()
+        }
+
+        fn scan(&self, dir: &Path) -> Result<Option<String>> {
+            let content = std::fs::read_to_string(dir.join("aaaaa"))?;
+            Ok(content
+                .contains("malware")
+                .then(|| "aaaaa contains malware".to_string()))
+        }
+    }
+
+    #[tokio::test]
+    async fn should_scan_uploads_before_publishing() -> Result<()> {
+        let admin_username = nanoid!();
+        let admins = [admin_username.clone()].into_iter().collect();
+        let test = OnyxTest::with_config(|state| {
+            Arc::make_mut(&mut state.config).admins = admins;
+            state.scanners = vec![Arc::new(TestScanner)];
+        })
+        .await?;
+        assert!(test.api.meta().await?.has_feature("scans"));
+        let (login, _password) = test.signup(None).await?;
+        let (other, _password) = test.signup(None).await?;
+        let (admin, _password) = test
+            .signup(Some(LoginRequest {
+                username: admin_username,
+                password: nanoid!(),
+                ..Default::default()
+            }))
+            .await?;
+
+        let name = nanoid!();
+        let (clean, response) = test
+            .seed_version(&login, &name, "0.1.0", Some("clean"))
+            .await?;
+        let clean = HashId::from(clean);
+        assert_eq!(response.status, PublishStatus::Pending);
+        assert!(response.package_id.is_empty());
+        // retrying the upload doesn't conflict with the pending one
+        let (_, response) = test
+            .seed_version(&login, &name, "0.1.0", Some("clean"))
+            .await?;
+        assert_eq!(response.status, PublishStatus::Pending);
+        assert!(test.api.load_package_versions(&name).await.is_err());
+        let status = test.api.publish_status(&login.token, &clean).await?;
+        assert_eq!(status.status, PublishStatus::Pending);
+        assert_eq!(status.package_name, name);
+        // only the author sees their pending uploads
+        assert!(test.api.publish_status(&other.token, &clean).await.is_err());
+        assert_eq!(
+            test.api.admin_pending_publishes(&admin.token).await?.len(),
+            1
+        );
+
+        assert_eq!(test.scan_uploads().await?, 1);
+        assert_eq!(test.scan_uploads().await?, 0);
+        let status = test.api.publish_status(&login.token, &clean).await?;
+        assert_eq!(status.status, PublishStatus::Published);
+        let (_, versions) = test.api.load_package_versions(&name).await?;
+        assert_eq!(versions.len(), 1);
+        test.api.download_tarball(&clean).await?;
+
+        let flagged_name = nanoid!();
+        let (flagged, _) = test
+            .seed_version(&login, &flagged_name, "0.1.0", Some("malware"))
+            .await?;
+        let (rejected, _) = test
+            .seed_version(&login, &nanoid!(), "0.1.0", Some("more malware"))
+            .await?;
+        let flagged = HashId::from(flagged);
+        let rejected = HashId::from(rejected);
+        assert_eq!(test.scan_uploads().await?, 2);
+        let status = test.api.publish_status(&login.token, &flagged).await?;
+        assert_eq!(status.status, PublishStatus::Flagged);
+        assert_eq!(
+            status.findings,
+            vec![ScanFinding {
+                scanner: "test".to_string(),
+                output: "aaaaa contains malware".to_string(),
+            }]
+        );
+        assert!(test.api.load_package_versions(&flagged_name).await.is_err());
+        let pending = test.api.admin_pending_publishes(&admin.token).await?;
+        assert_eq!(pending.len(), 2);
+        assert!(
+            pending
+                .iter()
+                .all(|upload| upload.status == PublishStatus::Flagged)
+        );
+
+        let released = test
+            .api
+            .admin_release_publish(&admin.token, &flagged)
+            .await?;
+        assert_eq!(released.status, PublishStatus::Published);
+        test.api.load_package_versions(&flagged_name).await?;
+        test.api
+            .admin_reject_publish(&admin.token, &rejected)
+            .await?;
+        let status = test.api.publish_status(&login.token, &rejected).await?;
+        assert_eq!(status.status, PublishStatus::Rejected);
+        assert!(
+            test.api
+                .admin_pending_publishes(&admin.token)
+                .await?
+                .is_empty()
+        );
+        assert!(
+            test.api
+                .admin_release_publish(&admin.token, &rejected)
+                .await
+                .is_err()
+        );
+
+        let log = test.api.admin_audit_log(&admin.token, 0, 100).await?;
+        let actions = log.entries.iter().map(|e| e.action).collect::<Vec<_>>();
+        assert_eq!(
+            actions,
+            vec![
+                AuditAction::PublishFlagged,
+                AuditAction::PublishFlagged,
+                AuditAction::PublishReleased,
+                AuditAction::PublishRejected,
+            ]
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn should_run_scan_commands() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let command = dir.path().join("scan");
+        std::fs::write(&command, SCAN_COMMAND)?;
+        std::fs::set_permissions(&command, std::fs::Permissions::from_mode(0o755))?;
+        let scanner = CommandScanner {
+            command,
+            timeout: 10,
+        };
+        let package = tempfile::tempdir()?;
+        std::fs::write(package.path().join("src.nr"), "fn main() {}")?;
+        assert_eq!(scanner.scan(package.path())?, None);
+
+        std::fs::write(package.path().join("aaaaa"), "malware")?;
+        let findings = scanner.scan(package.path())?.unwrap();
+        assert!(findings.contains("aaaaa"), "{findings}");
+
+        std::fs::write(package.path().join("aaaaa"), "broken")?;
+        assert!(scanner.scan(package.path()).is_err());
+        Ok(())
+    }
+}
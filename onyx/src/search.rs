@@ -0,0 +1,224 @@
+use axum::extract::Query;
+use axum::extract::State;
+use axum::response::Json as ResponseJson;
+use redb::ReadableTable;
+
+use onyx_api::prelude::*;
+
+use super::OnyxError;
+use super::OnyxState;
+use super::db::WriteTxn;
+
+/// Most packages a search returns.
+const MAX_SEARCH_RESULTS: usize = 100;
+
+/// The key of an entry of `[package.metadata.nrpm]` in `METADATA_VERSION_TABLE`. Values are
+/// compared ignoring case.
+fn metadata_entry(key: &str, value: &str) -> String {
+    format!("{key}={}", value.to_lowercase())
+}
+
+/// Index the `[package.metadata.nrpm]` table of a newly published version.
+pub fn index(write: &WriteTxn, version: &PackageVersionModel) -> Result<(), OnyxError> {
+    let Some(metadata) = &version.metadata else {
+        return Ok(());
+    };
+    let mut metadata_version_table = write.open_multimap_table(METADATA_VERSION_TABLE)?;
+    for (key, values) in &metadata.nrpm_metadata {
+        for value in values {
+            metadata_version_table.insert(metadata_entry(key, value).as_str(), &version.id)?;
+        }
+    }
+    Ok(())
+}
+
+/// Remove a deleted version from the index.
+pub fn remove(write: &WriteTxn, version: &PackageVersionModel) -> Result<(), OnyxError> {
+    let Some(metadata) = &version.metadata else {
+        return Ok(());
+    };
+    let mut metadata_version_table = write.open_multimap_table(METADATA_VERSION_TABLE)?;
+    for (key, values) in &metadata.nrpm_metadata {
+        for value in values {
+            metadata_version_table.remove(metadata_entry(key, value).as_str(), &version.id)?;
+        }
+    }
+    Ok(())
+}
+
+/// Whether the name, description or a keyword of the package contains `text`, which is
+/// lowercase.
+fn matches_text(text: &str, package: &PackageModel, version: &PackageVersionModel) -> bool {
+    if package.name.to_lowercase().contains(text) {
+        return true;
+    }
+    let Some(mut metadata) = version.metadata.clone() else {
+        return false;
+    };
+    package.metadata.apply(&mut metadata);
+    metadata
+        .description
+        .as_ref()
+        .is_some_and(|d| d.to_lowercase().contains(text))
+        || metadata
+            .keywords
+            .iter()
+            .any(|k| k.to_lowercase().contains(text))
+}
+
+/// Packages whose latest version matches the query, sorted by name. `q` is matched against
+/// the name, description and keywords. Every other parameter must be a value of that key in
+/// the `[package.metadata.nrpm]` table, e.g. `?q=hash&backend=barretenberg`.
+pub async fn search(
+    State(state): State<OnyxState>,
+    Query(params): Query<Vec<(String, String)>>,
+) -> Result<ResponseJson<Vec<(PackageModel, PackageVersionModel)>>, OnyxError> {
+    let mut text = None;
+    let mut filters = vec![];
+    for (key, value) in params {
+        if key == "q" {
+            text = Some(value.to_lowercase());
+        } else {
+            filters.push(metadata_entry(&key, &value));
+        }
+    }
+
+    let read = state.db.begin_read()?;
+    let package_table = read.open_table(PACKAGE_TABLE)?;
+    let version_table = read.open_table(VERSION_TABLE)?;
+    let mut candidates = vec![];
+    if let Some((first, rest)) = filters.split_first() {
+        let metadata_version_table = read.open_multimap_table(METADATA_VERSION_TABLE)?;
+        let mut version_ids = vec![];
+        for version_id in metadata_version_table.get(first.as_str())? {
+            version_ids.push(version_id?.value());
+        }
+        for filter in rest {
+            let mut matching = vec![];
+            for version_id in metadata_version_table.get(filter.as_str())? {
+                matching.push(version_id?.value());
+            }
+            version_ids.retain(|id| matching.contains(id));
+        }
+        // only the latest version of a package decides if it's found
+        for version_id in version_ids {
+            let Some(version) = version_table.get(&version_id)? else {
+                continue;
+            };
+            let version = version.value();
+            if let Some(package) = package_table.get(version.package_id.as_str())?
+                && package.value().latest_version_id == version.id
+            {
+                candidates.push((package.value(), version));
+            }
+        }
+    } else {
+        for entry in package_table.iter()? {
+            let package = entry?.1.value();
+            if let Some(version) = version_table.get(&package.latest_version_id)? {
+                candidates.push((package, version.value()));
+            }
+        }
+    }
+
+    let mut out = candidates
+        .into_iter()
+        .filter(|(package, version)| {
+            text.as_ref()
+                .is_none_or(|text| matches_text(text, package, version))
+        })
+        .collect::<Vec<_>>();
+    out.sort_by(|a, b| a.0.name.cmp(&b.0.name));
+    out.truncate(MAX_SEARCH_RESULTS);
+    Ok(ResponseJson(out))
+}
+
+#[cfg(test)]
+mod tests {
+    use anyhow::Result;
+    use nanoid::nanoid;
+    use onyx_api::prelude::*;
+
+    use crate::testing::OnyxTest;
+
+    #[tokio::test]
+    async fn should_filter_search_by_metadata() -> Result<()> {
+        let test = OnyxTest::new().await?;
+        let (login, _password) = test.signup(None).await?;
+        let publish = |name: &str, version: &str, description: &str, metadata: &str| {
+            OnyxTest::create_tarball_from_files(&[(
+                "Nargo.toml",
+                &format!(
+                    "[package]\nname = \"{name}\"\nversion = \"{version}\"\ntype = \"lib\"\ndescription = \"{description}\"\n\n[package.metadata.nrpm]\n{metadata}"
+                ),
+            )])
+        };
+        for tarball in [
+            publish(
+                "poseidon",
+                "0.1.0",
+                "a hash",
+                "backend = [\"barretenberg\"]\n",
+            )?,
+            publish("sha", "0.1.0", "a hash", "backend = \"plonky2\"\n")?,
+            publish(
+                "ecdsa",
+                "0.1.0",
+                "signatures",
+                "backend = \"Barretenberg\"\n",
+            )?,
+            // the latest version decides, sha no longer supports plonky2
+            publish("sha", "0.2.0", "a hash", "backend = \"barretenberg\"\n")?,
+        ] {
+            test.publish(
+                Some(PublishData::new(tarball.1.to_string(), login.token.clone())),
+                tarball,
+            )
+            .await?;
+        }
+
+        let names = |results: Vec<(PackageModel, PackageVersionModel)>| {
+            results
+                .into_iter()
+                .map(|(package, _)| package.name)
+                .collect::<Vec<_>>()
+        };
+        let found = test.api.search(Some("hash"), &[]).await?;
+        assert_eq!(names(found), ["poseidon", "sha"]);
+        let found = test
+            .api
+            .search(Some("HASH"), &[("backend", "barretenberg")])
+            .await?;
+        assert_eq!(names(found), ["poseidon", "sha"]);
+        let found = test
+            .api
+            .search(None, &[("backend", "barretenberg")])
+            .await?;
+        assert_eq!(names(found), ["ecdsa", "poseidon", "sha"]);
+        assert!(
+            test.api
+                .search(None, &[("backend", "plonky2")])
+                .await?
+                .is_empty()
+        );
+        assert!(
+            test.api
+                .search(None, &[("backend", "barretenberg"), ("noir", &nanoid!())])
+                .await?
+                .is_empty()
+        );
+
+        // values that can't be searched are rejected on publish
+        let tarball = publish("bad", "0.1.0", "bad", "backend = 1\n")?;
+        let err = test
+            .publish(
+                Some(PublishData::new(tarball.1.to_string(), login.token.clone())),
+                tarball,
+            )
+            .await
+            .unwrap_err();
+        let err = err.downcast_ref::<ApiError>().unwrap();
+        assert_eq!(err.code, OnyxErrorCode::ValidationFailed);
+        Ok(())
+    }
+}
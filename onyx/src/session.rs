@@ -0,0 +1,745 @@
+use anyhow::Result;
+use axum::extract::FromRequestParts;
+use axum::extract::Path;
+use axum::extract::State;
+use axum::http::StatusCode;
+use axum::http::header;
+use axum::http::request::Parts;
+use axum::response::Json as ResponseJson;
+use nanoid::nanoid;
+use redb::ReadableMultimapTable;
+use redb::ReadableTable;
+
+use onyx_api::prelude::*;
+
+use super::Config;
+use super::OnyxError;
+use super::OnyxState;
+use super::db::Db;
+use super::db::WriteTxn;
+
+/// Default number of seconds an auth token is valid for, see `Config::session_ttl`.
+pub const SESSION_TTL: u64 = 3600;
+/// Default number of seconds a refresh token is valid for, see `Config::refresh_ttl`. Each
+/// refresh issues a new refresh token so sessions in regular use don't expire.
+pub const REFRESH_TTL: u64 = 30 * 24 * 3600;
+/// Default number of seconds the refresh token of a "remember me" login is valid for, see
+/// `Config::remember_ttl`.
+pub const REMEMBER_TTL: u64 = 90 * 24 * 3600;
+/// Default number of seconds a publish scoped auth token is valid for, see
+/// `Config::publish_token_ttl`.
+pub const PUBLISH_TOKEN_TTL: u64 = 7 * 24 * 3600;
+/// Number of token characters revealed when listing sessions. Revocation requires at least
+/// this many characters.
+pub const TOKEN_PREFIX_LEN: usize = 8;
+
+/// The user and token of a request authenticated with an `Authorization: Bearer <token>`
+/// header.
+pub struct AuthSession {
+    pub user_id: String,
+    pub token: String,
+    pub scope: TokenScope,
+}
+
+impl FromRequestParts<OnyxState> for AuthSession {
+    type Rejection = OnyxError;
+
+    async fn from_request_parts(
+        parts: &mut Parts,
+        state: &OnyxState,
+    ) -> Result<Self, Self::Rejection> {
+        let token = parts
+            .headers
+            .get(header::AUTHORIZATION)
+            .and_then(|v| v.to_str().ok())
+            .and_then(|v| v.strip_prefix("Bearer "))
+            .ok_or(OnyxError::new(
+                OnyxErrorCode::InvalidToken,
+                "Missing bearer token!",
+            ))?
+            .to_string();
+        let (user_id, _expires_at) = authenticate(&state.db, &token)?;
+        let scope = token_scope(&state.db, &token)?;
+        Ok(Self {
+            user_id,
+            token,
+            scope,
+        })
+    }
+}
+
+impl AuthSession {
+    /// Fail unless the token can manage the account, not just publish.
+    pub fn require_full_scope(&self) -> Result<(), OnyxError> {
+        require_full_scope(self.scope)
+    }
+
+    pub fn user(&self, db: &Db) -> Result<UserModel, OnyxError> {
+        let read = db.begin_read()?;
+        let user_table = read.open_table(USER_TABLE)?;
+        match user_table.get(self.user_id.as_str())? {
+            Some(user) => Ok(user.value()),
+            None => Err(OnyxError::new(
+                OnyxErrorCode::Internal,
+                "auth token belongs to a user without a user document",
+            )),
+        }
+    }
+}
+
+/// A request authenticated by a user whose username is listed in `OnyxState::admins`.
+pub struct AdminSession {
+    pub user: UserModel,
+}
+
+impl FromRequestParts<OnyxState> for AdminSession {
+    type Rejection = OnyxError;
+
+    async fn from_request_parts(
+        parts: &mut Parts,
+        state: &OnyxState,
+    ) -> Result<Self, Self::Rejection> {
+        let session = AuthSession::from_request_parts(parts, state).await?;
+        session.require_full_scope()?;
+        let user = session.user(&state.db)?;
+        if !state.config.admins.contains(&user.username) {
+            return Err(OnyxError::new(
+                OnyxErrorCode::Forbidden,
+                "Admin access required",
+            ));
+        }
+        Ok(Self { user })
+    }
+}
+
+/// Look up the user an auth token belongs to. Returns the user id and the expiration of the
+/// token.
+pub fn authenticate(db: &Db, token: &str) -> Result<(String, u64), OnyxError> {
+    let read = db.begin_read()?;
+    let auth_table = read.open_table(AUTH_TOKEN_TABLE)?;
+    let banned_user_table = read.open_table(BANNED_USER_TABLE)?;
+    if let Some(entry) = auth_table.get(token)? {
+        let (user_id, expires_at) = entry.value();
+        if timestamp() > expires_at {
+            return Err(OnyxError::new(
+                OnyxErrorCode::ExpiredToken,
+                "Expired token!",
+            ));
+        }
+        if banned_user_table.get(user_id)?.is_some() {
+            return Err(OnyxError::new(
+                OnyxErrorCode::Forbidden,
+                "This account is banned",
+            ));
+        }
+        Ok((user_id.to_string(), expires_at))
+    } else {
+        Err(OnyxError::new(
+            OnyxErrorCode::InvalidToken,
+            "Invalid token!",
+        ))
+    }
+}
+
+/// What an auth token can be used for. Tokens issued before sessions were recorded can be used
+/// for anything.
+pub fn token_scope(db: &Db, token: &str) -> Result<TokenScope, OnyxError> {
+    let read = db.begin_read()?;
+    let session_table = read.open_table(SESSION_TABLE)?;
+    Ok(session_table
+        .get(token)?
+        .map(|v| v.value().scope)
+        .unwrap_or_default())
+}
+
+pub fn require_full_scope(scope: TokenScope) -> Result<(), OnyxError> {
+    match scope {
+        TokenScope::Full => Ok(()),
+        TokenScope::Publish => Err(OnyxError::new(
+            OnyxErrorCode::Forbidden,
+            "This token can only publish and manage packages",
+        )),
+    }
+}
+
+/// How a new session was asked for.
+pub struct NewSession<'a> {
+    pub source: SessionSource,
+    /// Refresh token that can extend the session.
+    pub refresh_token: Option<&'a str>,
+    pub scope: TokenScope,
+    pub remember: bool,
+}
+
+/// Seconds an auth token with `scope` is valid for.
+fn access_ttl(config: &Config, scope: TokenScope) -> u64 {
+    match scope {
+        TokenScope::Full => config.session_ttl,
+        TokenScope::Publish => config.publish_token_ttl,
+    }
+}
+
+/// Seconds a refresh token is valid for.
+fn refresh_ttl(config: &Config, remember: bool) -> u64 {
+    match remember {
+        true => config.remember_ttl,
+        false => config.refresh_ttl,
+    }
+}
+
+/// Issue an auth token for a user, optionally along with a refresh token that can extend the
+/// session.
+pub fn create_session(
+    write: &WriteTxn,
+    config: &Config,
+    user_id: &str,
+    token: &str,
+    new: NewSession,
+) -> Result<SessionModel, OnyxError> {
+    let now = timestamp();
+    let session = SessionModel {
+        user_id: user_id.to_string(),
+        created_at: now,
+        expires_at: now + access_ttl(config, new.scope),
+        source: new.source,
+        refresh_token: new.refresh_token.map(|t| t.to_string()),
+        refresh_expires_at: new
+            .refresh_token
+            .map(|_| now + refresh_ttl(config, new.remember)),
+        scope: new.scope,
+        remember: new.remember,
+    };
+    insert_session(write, token, &session)?;
+    Ok(session)
+}
+
+fn insert_session(write: &WriteTxn, token: &str, session: &SessionModel) -> Result<(), OnyxError> {
+    let mut auth_token_table = write.open_table(AUTH_TOKEN_TABLE)?;
+    let mut session_table = write.open_table(SESSION_TABLE)?;
+    let mut user_session_table = write.open_multimap_table(USER_SESSION_TABLE)?;
+    let mut refresh_token_table = write.open_table(REFRESH_TOKEN_TABLE)?;
+    if auth_token_table.get(token)?.is_some() {
+        return Err(OnyxError::conflict("Token already exists"));
+    }
+    auth_token_table.insert(token, (session.user_id.as_str(), session.expires_at))?;
+    session_table.insert(token, session.clone())?;
+    user_session_table.insert(session.user_id.as_str(), token)?;
+    if let (Some(refresh_token), Some(refresh_expires_at)) =
+        (&session.refresh_token, session.refresh_expires_at)
+    {
+        if refresh_token_table.get(refresh_token.as_str())?.is_some() {
+            return Err(OnyxError::conflict("Refresh token already exists"));
+        }
+        refresh_token_table.insert(
+            refresh_token.as_str(),
+            (session.user_id.as_str(), token, refresh_expires_at),
+        )?;
+    }
+    Ok(())
+}
+
+/// Remove an auth token and the refresh token of its session. Returns the removed session.
+fn remove_session(
+    write: &WriteTxn,
+    user_id: &str,
+    token: &str,
+) -> Result<Option<SessionModel>, OnyxError> {
+    let mut auth_token_table = write.open_table(AUTH_TOKEN_TABLE)?;
+    let mut session_table = write.open_table(SESSION_TABLE)?;
+    let mut user_session_table = write.open_multimap_table(USER_SESSION_TABLE)?;
+    let mut refresh_token_table = write.open_table(REFRESH_TOKEN_TABLE)?;
+    auth_token_table.remove(token)?;
+    let session = session_table.remove(token)?.map(|v| v.value());
+    user_session_table.remove(user_id, token)?;
+    if let Some(refresh_token) = session.as_ref().and_then(|s| s.refresh_token.as_ref()) {
+        refresh_token_table.remove(refresh_token.as_str())?;
+    }
+    Ok(session)
+}
+
+/// Exchange a refresh token for a new access token and refresh token. The session keeps its
+/// source, scope, creation time and whether it's remembered. Returns the new access token and the session.
+pub fn refresh_session(
+    db: &Db,
+    config: &Config,
+    refresh_token: &str,
+) -> Result<(String, SessionModel), OnyxError> {
+    let now = timestamp();
+    let write = db.begin_write()?;
+    let (user_id, old_token) = {
+        let refresh_token_table = write.open_table(REFRESH_TOKEN_TABLE)?;
+        let Some(entry) = refresh_token_table.get(refresh_token)? else {
+            return Err(OnyxError::new(
+                OnyxErrorCode::InvalidToken,
+                "Invalid refresh token!",
+            ));
+        };
+        let (user_id, old_token, expires_at) = entry.value();
+        if now > expires_at {
+            return Err(OnyxError::new(
+                OnyxErrorCode::ExpiredToken,
+                "Expired refresh token!",
+            ));
+        }
+        (user_id.to_string(), old_token.to_string())
+    };
+    let old_session = remove_session(&write, &user_id, &old_token)?;
+    let token = nanoid!();
+    let scope = old_session.as_ref().map(|s| s.scope).unwrap_or_default();
+    let remember = old_session.as_ref().is_some_and(|s| s.remember);
+    let session = SessionModel {
+        user_id: user_id.clone(),
+        created_at: old_session.as_ref().map(|s| s.created_at).unwrap_or(now),
+        expires_at: now + access_ttl(config, scope),
+        source: old_session
+            .as_ref()
+            .map(|s| s.source)
+            .unwrap_or(SessionSource::Login),
+        refresh_token: Some(nanoid!()),
+        refresh_expires_at: Some(now + refresh_ttl(config, remember)),
+        scope,
+        remember,
+    };
+    insert_session(&write, &token, &session)?;
+    write.commit()?;
+    Ok((token, session))
+}
+
+/// Build the response for a newly issued access token.
+pub fn login_response(user: UserModel, token: String, session: SessionModel) -> LoginResponse {
+    LoginResponse {
+        user: UserModelSafe::from(user),
+        token,
+        expires_at: session.expires_at,
+        refresh_token: session.refresh_token,
+        refresh_expires_at: session.refresh_expires_at,
+    }
+}
+
+fn user_tokens(write: &WriteTxn, user_id: &str) -> Result<Vec<String>, OnyxError> {
+    let user_session_table = write.open_multimap_table(USER_SESSION_TABLE)?;
+    let mut tokens = vec![];
+    for token in user_session_table.get(user_id)? {
+        tokens.push(token?.value().to_string());
+    }
+    Ok(tokens)
+}
+
+/// Revoke every session of a user other than the one using `except`, including auth tokens
+/// issued before sessions were recorded. Returns the number of sessions revoked.
+pub fn revoke_user_sessions(
+    write: &WriteTxn,
+    user_id: &str,
+    except: Option<&str>,
+) -> Result<usize, OnyxError> {
+    let sessions = user_tokens(write, user_id)?
+        .into_iter()
+        .filter(|token| Some(token.as_str()) != except)
+        .collect::<Vec<_>>();
+    for token in &sessions {
+        remove_session(write, user_id, token)?;
+    }
+    let mut auth_token_table = write.open_table(AUTH_TOKEN_TABLE)?;
+    let mut tokens = vec![];
+    for entry in auth_token_table.iter()? {
+        let (token, value) = entry?;
+        if value.value().0 == user_id && Some(token.value()) != except {
+            tokens.push(token.value().to_string());
+        }
+    }
+    for token in &tokens {
+        auth_token_table.remove(token.as_str())?;
+    }
+    Ok(sessions.len() + tokens.len())
+}
+
+pub async fn list_sessions(
+    State(state): State<OnyxState>,
+    session: AuthSession,
+) -> Result<ResponseJson<Vec<SessionInfo>>, OnyxError> {
+    let read = state.db.begin_read()?;
+    let user_session_table = read.open_multimap_table(USER_SESSION_TABLE)?;
+    let session_table = read.open_table(SESSION_TABLE)?;
+    let now = timestamp();
+    let mut sessions = vec![];
+    for token in user_session_table.get(session.user_id.as_str())? {
+        let token = token?;
+        let token = token.value();
+        let Some(model) = session_table.get(token)? else {
+            continue;
+        };
+        let model = model.value();
+        if !model.is_active(now) {
+            continue;
+        }
+        sessions.push(SessionInfo {
+            token_prefix: token.chars().take(TOKEN_PREFIX_LEN).collect(),
+            created_at: model.created_at,
+            expires_at: model.expires_at,
+            source: model.source,
+            refresh_expires_at: model.refresh_expires_at,
+            scope: model.scope,
+            current: token == session.token,
+        });
+    }
+    // newest first
+    sessions.sort_by_key(|s| std::cmp::Reverse(s.created_at));
+    Ok(ResponseJson(sessions))
+}
+
+pub async fn revoke_session(
+    State(state): State<OnyxState>,
+    session: AuthSession,
+    Path(token_prefix): Path<String>,
+) -> Result<StatusCode, OnyxError> {
+    session.require_full_scope()?;
+    if token_prefix.len() < TOKEN_PREFIX_LEN {
+        return Err(OnyxError::bad_request(&format!(
+            "Token prefix must be at least {TOKEN_PREFIX_LEN} characters"
+        )));
+    }
+    let write = state.db.begin_write()?;
+    let mut matches = user_tokens(&write, &session.user_id)?
+        .into_iter()
+        .filter(|token| token.starts_with(&token_prefix))
+        .collect::<Vec<_>>();
+    let token = match matches.len() {
+        0 => return Err(OnyxError::not_found("No session matches token prefix")),
+        1 => matches.remove(0),
+        _ => {
+            return Err(OnyxError::conflict(
+                "Token prefix matches multiple sessions",
+            ));
+        }
+    };
+    remove_session(&write, &session.user_id, &token)?;
+    write.commit()?;
+    Ok(StatusCode::NO_CONTENT)
+}
+
+/// Remove expired auth tokens, and the metadata of sessions that can no longer be refreshed.
+/// Returns the number of auth tokens removed.
+pub fn purge_expired_sessions(state: &OnyxState) -> Result<usize> {
+    let now = timestamp();
+    let write = state.db.begin_write()?;
+    let purged = {
+        let mut auth_token_table = write.open_table(AUTH_TOKEN_TABLE)?;
+        let mut session_table = write.open_table(SESSION_TABLE)?;
+        let mut user_session_table = write.open_multimap_table(USER_SESSION_TABLE)?;
+        let mut refresh_token_table = write.open_table(REFRESH_TOKEN_TABLE)?;
+
+        let mut expired_tokens = vec![];
+        for entry in auth_token_table.iter()? {
+            let (token, value) = entry?;
+            let (_user_id, expires_at) = value.value();
+            if now > expires_at {
+                expired_tokens.push(token.value().to_string());
+            }
+        }
+        for token in &expired_tokens {
+            auth_token_table.remove(token.as_str())?;
+        }
+
+        let mut inactive_sessions = vec![];
+        for entry in session_table.iter()? {
+            let (token, session) = entry?;
+            let session = session.value();
+            if !session.is_active(now) {
+                inactive_sessions.push((token.value().to_string(), session));
+            }
+        }
+        for (token, session) in &inactive_sessions {
+            session_table.remove(token.as_str())?;
+            user_session_table.remove(session.user_id.as_str(), token.as_str())?;
+        }
+
+        let mut expired_refresh_tokens = vec![];
+        for entry in refresh_token_table.iter()? {
+            let (refresh_token, value) = entry?;
+            let (_user_id, _token, expires_at) = value.value();
+            if now > expires_at {
+                expired_refresh_tokens.push(refresh_token.value().to_string());
+            }
+        }
+        for refresh_token in &expired_refresh_tokens {
+            refresh_token_table.remove(refresh_token.as_str())?;
+        }
+
+        expired_tokens.len()
+    };
+    write.commit()?;
+    Ok(purged)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::testing::OnyxTest;
+    use anyhow::Result;
+    use nanoid::nanoid;
+
+    #[tokio::test]
+    async fn should_list_and_revoke_sessions() -> Result<()> {
+        let test = OnyxTest::new().await?;
+        let (signup, password) = test.signup(None).await?;
+        let login = test
+            .login(Some(LoginRequest {
+                username: signup.user.username.clone(),
+                password,
+                ..Default::default()
+            }))
+            .await?;
+
+        let sessions = test.api.sessions(&login.token).await?;
+        assert_eq!(sessions.len(), 2);
+        let current = sessions.iter().find(|s| s.current).unwrap();
+        assert_eq!(current.source, SessionSource::Login);
+        assert_eq!(current.token_prefix, &login.token[..TOKEN_PREFIX_LEN]);
+        let other = sessions.iter().find(|s| !s.current).unwrap();
+        assert_eq!(other.source, SessionSource::Signup);
+
+        test.api
+            .revoke_session(&login.token, &other.token_prefix)
+            .await?;
+        assert_eq!(test.api.sessions(&login.token).await?.len(), 1);
+        let e = test.api.auth(signup.token).await.unwrap_err();
+        assert_eq!(e.to_string(), "Invalid token!");
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn fail_revoke_other_users_session() -> Result<()> {
+        let test = OnyxTest::new().await?;
+        let (login, _password) = test.signup(None).await?;
+        let (other, _password) = test.signup(None).await?;
+
+        let e = test
+            .api
+            .revoke_session(&login.token, &other.token[..TOKEN_PREFIX_LEN])
+            .await
+            .unwrap_err();
+        assert_eq!(
+            e.downcast_ref::<ApiError>().map(|e| e.code),
+            Some(OnyxErrorCode::NotFound)
+        );
+        // the other user's token should still work
+        test.api.auth(other.token).await?;
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn fail_list_sessions_bad_token() -> Result<()> {
+        let test = OnyxTest::new().await?;
+        let e = test.api.sessions(&nanoid!()).await.unwrap_err();
+        assert_eq!(e.to_string(), "Invalid token!");
+        Ok(())
+    }
+
+    /// Write a session whose access token expired a second ago.
+    fn insert_expired_session(
+        test: &OnyxTest,
+        user_id: &str,
+        refresh_token: Option<&str>,
+    ) -> Result<String> {
+        let token = nanoid!();
+        let now = timestamp();
+        let write = test.state.db.begin_write()?;
+        insert_session(
+            &write,
+            &token,
+            &SessionModel {
+                user_id: user_id.to_string(),
+                created_at: now - SESSION_TTL,
+                expires_at: now - 1,
+                source: SessionSource::Login,
+                refresh_token: refresh_token.map(|t| t.to_string()),
+                refresh_expires_at: refresh_token.map(|_| now + REFRESH_TTL),
+                scope: TokenScope::Full,
+                remember: false,
+            },
+        )
+        .map_err(|_| anyhow::anyhow!("failed to insert session"))?;
+        write.commit()?;
+        Ok(token)
+    }
+
+    #[tokio::test]
+    async fn should_purge_expired_sessions() -> Result<()> {
+        let test = OnyxTest::new().await?;
+        let (login, _password) = test.signup(None).await?;
+
+        let expired_token = insert_expired_session(&test, &login.user.id, None)?;
+
+        assert_eq!(purge_expired_sessions(&test.state)?, 1);
+        let e = test.api.auth(expired_token).await.unwrap_err();
+        assert_eq!(e.to_string(), "Invalid token!");
+        assert_eq!(test.api.sessions(&login.token).await?.len(), 1);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn should_refresh_session() -> Result<()> {
+        let test = OnyxTest::new().await?;
+        let (signup, _password) = test.signup(None).await?;
+        let refresh_token = signup.refresh_token.clone().unwrap();
+
+        let refreshed = test.api.refresh(refresh_token.clone()).await?;
+        assert_eq!(refreshed.user, signup.user);
+        assert_ne!(refreshed.token, signup.token);
+        assert_ne!(refreshed.refresh_token, Some(refresh_token.clone()));
+        test.api.auth(refreshed.token.clone()).await?;
+
+        // the previous tokens are replaced
+        let e = test.api.auth(signup.token).await.unwrap_err();
+        assert_eq!(e.to_string(), "Invalid token!");
+        let e = test.api.refresh(refresh_token).await.unwrap_err();
+        assert_eq!(e.to_string(), "Invalid refresh token!");
+
+        // the session keeps its source
+        let sessions = test.api.sessions(&refreshed.token).await?;
+        assert_eq!(sessions.len(), 1);
+        assert_eq!(sessions[0].source, SessionSource::Signup);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn should_refresh_expired_access_token() -> Result<()> {
+        let test = OnyxTest::new().await?;
+        let (login, _password) = test.signup(None).await?;
+
+        let refresh_token = nanoid!();
+        let expired_token = insert_expired_session(&test, &login.user.id, Some(&refresh_token))?;
+
+        // the session survives purging while it can be refreshed
+        assert_eq!(purge_expired_sessions(&test.state)?, 1);
+        assert_eq!(test.api.sessions(&login.token).await?.len(), 2);
+        let e = test.api.auth(expired_token).await.unwrap_err();
+        assert_eq!(e.to_string(), "Invalid token!");
+
+        let refreshed = test.api.refresh(refresh_token).await?;
+        test.api.auth(refreshed.token).await?;
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn should_revoke_refresh_token() -> Result<()> {
+        let test = OnyxTest::new().await?;
+        let (signup, password) = test.signup(None).await?;
+        let login = test
+            .login(Some(LoginRequest {
+                username: signup.user.username.clone(),
+                password,
+                ..Default::default()
+            }))
+            .await?;
+
+        test.api
+            .revoke_session(&login.token, &signup.token[..TOKEN_PREFIX_LEN])
+            .await?;
+        let e = test
+            .api
+            .refresh(signup.refresh_token.unwrap())
+            .await
+            .unwrap_err();
+        assert_eq!(e.to_string(), "Invalid refresh token!");
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn should_remember_sessions() -> Result<()> {
+        let test = OnyxTest::new().await?;
+        let (signup, password) = test.signup(None).await?;
+        let now = timestamp();
+        let login = test
+            .login(Some(LoginRequest {
+                username: signup.user.username.clone(),
+                password,
+                remember_me: true,
+            }))
+            .await?;
+        assert!(login.refresh_expires_at.unwrap() >= now + REMEMBER_TTL);
+        assert!(signup.refresh_expires_at.unwrap() < now + REMEMBER_TTL);
+
+        // refreshing keeps the session remembered
+        let refreshed = test.api.refresh(login.refresh_token.unwrap()).await?;
+        assert!(refreshed.refresh_expires_at.unwrap() >= now + REMEMBER_TTL);
+        assert!(refreshed.expires_at < now + REMEMBER_TTL);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn should_scope_publish_tokens() -> Result<()> {
+        let test = OnyxTest::new().await?;
+        let (login, _password) = test.signup(None).await?;
+        let now = timestamp();
+        let publish_token = nanoid!();
+        let refresh_token = nanoid!();
+        test.api
+            .propose_token(
+                publish_token.clone(),
+                Some(refresh_token.clone()),
+                TokenScope::Publish,
+                login.token.clone(),
+            )
+            .await?;
+        let auth = test.api.auth(publish_token.clone()).await?;
+        assert!(auth.expires_at >= now + PUBLISH_TOKEN_TTL);
+
+        // publishing and managing packages is allowed
+        let tarball = OnyxTest::create_test_tarball_named(None, Some("scoped"), Some("0.1.0"))?;
+        test.publish(
+            Some(PublishData::new(
+                tarball.1.to_string(),
+                publish_token.clone(),
+            )),
+            tarball,
+        )
+        .await?;
+        test.api.sessions(&publish_token).await?;
+
+        // managing the account isn't
+        let forbidden = |e: anyhow::Error| {
+            assert_eq!(
+                e.downcast_ref::<ApiError>().map(|e| e.code),
+                Some(OnyxErrorCode::Forbidden)
+            )
+        };
+        forbidden(
+            test.api
+                .revoke_session(&publish_token, &login.token[..TOKEN_PREFIX_LEN])
+                .await
+                .unwrap_err(),
+        );
+        forbidden(
+            test.api
+                .propose_token(nanoid!(), None, TokenScope::Full, publish_token.clone())
+                .await
+                .unwrap_err(),
+        );
+        forbidden(
+            test.api
+                .set_webhook(
+                    &publish_token,
+                    &SetWebhookRequest {
+                        url: "https://example.com".to_string(),
+                    },
+                )
+                .await
+                .unwrap_err(),
+        );
+
+        // the session keeps its scope when refreshed
+        let refreshed = test.api.refresh(refresh_token).await?;
+        assert!(refreshed.expires_at >= now + PUBLISH_TOKEN_TTL);
+        let sessions = test.api.sessions(&login.token).await?;
+        let scoped = sessions.iter().find(|s| !s.current).unwrap();
+        assert_eq!(scoped.scope, TokenScope::Publish);
+        assert_eq!(scoped.source, SessionSource::ProposedToken);
+        assert_eq!(
+            sessions.iter().find(|s| s.current).unwrap().scope,
+            TokenScope::Full
+        );
+        Ok(())
+    }
+}
@@ -0,0 +1,413 @@
+use std::collections::BTreeMap;
+use std::path::Path;
+use std::sync::Arc;
+
+use anyhow::Context;
+use anyhow::Result;
+use axum::extract::Path as UrlPath;
+use axum::extract::State;
+use axum::response::Json as ResponseJson;
+use redb::ReadableTable;
+use ring::rand::SystemRandom;
+use ring::signature::ED25519;
+use ring::signature::Ed25519KeyPair;
+use ring::signature::KeyPair;
+use ring::signature::UnparsedPublicKey;
+
+use onyx_api::prelude::*;
+
+use super::Config;
+use super::OnyxError;
+use super::OnyxState;
+use super::index;
+use super::transparency;
+
+const DEFAULT_SNAPSHOT_TTL: u64 = 24 * 60 * 60;
+// roots are read from `{version}.root.json` files in the root directory
+const ROOT_FILE_SUFFIX: &str = ".root.json";
+
+/// Generate an ed25519 signing key and write it to `path` as pkcs8. Returns the hex encoded
+/// public key.
+pub fn keygen(path: &Path) -> Result<String> {
+    if path.exists() {
+        anyhow::bail!("{path:?} already exists");
+    }
+    let pkcs8 = Ed25519KeyPair::generate_pkcs8(&SystemRandom::new())
+        .map_err(|_| anyhow::anyhow!("Failed to generate a key"))?;
+    std::fs::write(path, pkcs8.as_ref())?;
+    Ok(hex::encode(load_key(path)?.public_key()))
+}
+
+fn load_key(path: &Path) -> Result<Ed25519KeyPair> {
+    let pkcs8 = std::fs::read(path).with_context(|| format!("Failed to read key {path:?}"))?;
+    Ed25519KeyPair::from_pkcs8(&pkcs8)
+        .map_err(|_| anyhow::anyhow!("{path:?} is not a pkcs8 ed25519 key"))
+}
+
+fn sign(signed: String, keys: &[&Ed25519KeyPair]) -> SignedDocument {
+    let signatures = keys
+        .iter()
+        .map(|key| DocumentSignature {
+            key: hex::encode(key.public_key()),
+            signature: hex::encode(key.sign(signed.as_bytes())),
+        })
+        .collect();
+    SignedDocument { signed, signatures }
+}
+
+/// Sign the `RegistryRoot` json at `root_path` with the keys at `key_paths`. A rotated root
+/// is signed by a root key of the previous version as well as its own. Returns the
+/// `SignedDocument` json to save as `{version}.root.json` in the root directory.
+pub fn sign_root(root_path: &Path, key_paths: &[&Path]) -> Result<String> {
+    let str = std::fs::read_to_string(root_path)
+        .with_context(|| format!("Failed to read root {root_path:?}"))?;
+    let root = serde_json::from_str::<RegistryRoot>(&str)
+        .with_context(|| format!("Failed to parse root {root_path:?}"))?;
+    let keys = key_paths
+        .iter()
+        .map(|path| load_key(path))
+        .collect::<Result<Vec<_>>>()?;
+    let signed = sign(
+        serde_json::to_string(&root)?,
+        &keys.iter().collect::<Vec<_>>(),
+    );
+    if !is_signed_by(&signed, &root.root_keys) {
+        anyhow::bail!("The root must be signed by one of its own root keys");
+    }
+    Ok(serde_json::to_string_pretty(&signed)?)
+}
+
+/// Whether `signature` is a valid signature of `message` by `key`, both hex encoded.
+pub fn verify_signature(key: &str, message: &[u8], signature: &str) -> bool {
+    match (hex::decode(key), hex::decode(signature)) {
+        (Ok(key), Ok(bytes)) => UnparsedPublicKey::new(&ED25519, key)
+            .verify(message, &bytes)
+            .is_ok(),
+        _ => false,
+    }
+}
+
+/// Whether `document` carries a valid signature by one of `keys`.
+pub fn is_signed_by(document: &SignedDocument, keys: &[String]) -> bool {
+    document.signatures.iter().any(|signature| {
+        keys.contains(&signature.key)
+            && verify_signature(
+                &signature.key,
+                document.signed.as_bytes(),
+                &signature.signature,
+            )
+    })
+}
+
+/// Signs snapshots of the registry with an online key. The key is trusted through the
+/// registry roots, which are signed offline.
+#[derive(Clone)]
+pub struct SnapshotSigner {
+    key: Arc<Ed25519KeyPair>,
+    /// Signed roots by version.
+    roots: Arc<BTreeMap<u64, SignedDocument>>,
+    /// Seconds a snapshot remains valid.
+    ttl: u64,
+}
+
+impl SnapshotSigner {
+    /// Read the snapshot settings of `config`. Snapshots aren't signed if `snapshot_key_path`
+    /// is unset. The roots must form a chain, each signed by the root keys of the one before
+    /// it, and the latest must delegate to the snapshot key.
+    pub fn from_config(config: &Config) -> Result<Option<Self>> {
+        let Some(key_path) = config.snapshot_key_path.as_deref() else {
+            return Ok(None);
+        };
+        let root_path = config.root_path.as_deref().ok_or(anyhow::anyhow!(
+            "root_path is required with snapshot_key_path"
+        ))?;
+        let key = load_key(key_path)?;
+
+        let mut roots = BTreeMap::new();
+        for entry in std::fs::read_dir(root_path)
+            .with_context(|| format!("Failed to read root directory {root_path:?}"))?
+        {
+            let path = entry?.path();
+            let Some(version) = path
+                .file_name()
+                .and_then(|name| name.to_str())
+                .and_then(|name| name.strip_suffix(ROOT_FILE_SUFFIX))
+            else {
+                continue;
+            };
+            let version = version
+                .parse::<u64>()
+                .with_context(|| format!("Root file name is not a version: {path:?}"))?;
+            let signed = serde_json::from_str::<SignedDocument>(&std::fs::read_to_string(&path)?)
+                .with_context(|| format!("Failed to parse signed root {path:?}"))?;
+            roots.insert(version, signed);
+        }
+        let mut previous: Option<RegistryRoot> = None;
+        for (version, signed) in &roots {
+            let root = signed.document::<RegistryRoot>()?;
+            if root.version != *version {
+                anyhow::bail!(
+                    "{version}{ROOT_FILE_SUFFIX} contains root version {}",
+                    root.version
+                );
+            }
+            if !is_signed_by(signed, &root.root_keys) {
+                anyhow::bail!("Root version {version} is not signed by its own root keys");
+            }
+            if let Some(previous) = previous
+                && (previous.version + 1 != root.version
+                    || !is_signed_by(signed, &previous.root_keys))
+            {
+                anyhow::bail!(
+                    "Root version {version} is not signed by the root keys of version {}",
+                    previous.version
+                );
+            }
+            previous = Some(root);
+        }
+        let latest = previous.ok_or(anyhow::anyhow!("No signed roots in {root_path:?}"))?;
+        if !latest
+            .snapshot_keys
+            .contains(&hex::encode(key.public_key()))
+        {
+            anyhow::bail!(
+                "Root version {} doesn't delegate to the snapshot key {key_path:?}",
+                latest.version
+            );
+        }
+        Ok(Some(Self {
+            key: Arc::new(key),
+            roots: Arc::new(roots),
+            ttl: config.snapshot_ttl.unwrap_or(DEFAULT_SNAPSHOT_TTL),
+        }))
+    }
+
+    fn latest_root_version(&self) -> u64 {
+        self.roots.keys().last().copied().unwrap_or_default()
+    }
+}
+
+/// Sign a snapshot of every published version if there were publishes since the last one,
+/// or the last one is half way to expiring. Returns the new snapshot.
+pub fn refresh(state: &OnyxState) -> Result<Option<Snapshot>> {
+    let Some(signer) = &state.snapshots else {
+        return Ok(None);
+    };
+    let now = timestamp();
+    let (id, log, package_names) = {
+        let read = state.db.begin_read()?;
+        let changelog_table = read.open_table(CHANGELOG_TABLE)?;
+        let id = changelog_table
+            .last()?
+            .map(|(seq, _)| seq.value())
+            .unwrap_or(0);
+        let snapshot_table = read.open_table(SNAPSHOT_TABLE)?;
+        if let Some((seq, signed)) = snapshot_table.last()?
+            && seq.value() == id
+        {
+            let snapshot =
+                serde_json::from_str::<SignedDocument>(signed.value())?.document::<Snapshot>()?;
+            if snapshot.root_version == signer.latest_root_version()
+                && snapshot.expires_at.saturating_sub(now) > signer.ttl / 2
+            {
+                return Ok(None);
+            }
+        }
+        let package_name_table = read.open_table(PACKAGE_NAME_TABLE)?;
+        let mut package_names = vec![];
+        for entry in package_name_table.iter()? {
+            package_names.push(entry?.0.value().to_string());
+        }
+        let log_root_table = read.open_table(LOG_ROOT_TABLE)?;
+        let log = transparency::head(&log_root_table)?;
+        (id, log, package_names)
+    };
+
+    let mut packages = BTreeMap::new();
+    for package_name in package_names {
+        let Some(package) = index::render_package(&state.db, &package_name)? else {
+            continue;
+        };
+        packages.insert(
+            package.name,
+            package
+                .versions
+                .into_iter()
+                .map(|v| (v.name, v.id.to_string()))
+                .collect(),
+        );
+    }
+    let snapshot = Snapshot {
+        id,
+        root_version: signer.latest_root_version(),
+        generated_at: now,
+        expires_at: now + signer.ttl,
+        packages,
+        log: Some(log),
+    };
+    let signed = sign(serde_json::to_string(&snapshot)?, &[signer.key.as_ref()]);
+
+    let write = state.db.begin_write()?;
+    {
+        let mut snapshot_table = write.open_table(SNAPSHOT_TABLE)?;
+        // only the latest is served
+        snapshot_table.retain(|seq, _| seq > id)?;
+        snapshot_table.insert(id, serde_json::to_string(&signed)?.as_str())?;
+    }
+    write.commit()?;
+    Ok(Some(snapshot))
+}
+
+pub async fn registry_root(
+    State(state): State<OnyxState>,
+) -> Result<ResponseJson<SignedDocument>, OnyxError> {
+    let signer = state
+        .snapshots
+        .as_ref()
+        .ok_or(OnyxError::not_found("This registry doesn't sign snapshots"))?;
+    let root = signer
+        .roots
+        .values()
+        .last()
+        .cloned()
+        .ok_or(OnyxError::default())?;
+    Ok(ResponseJson(root))
+}
+
+pub async fn registry_root_version(
+    State(state): State<OnyxState>,
+    UrlPath(version): UrlPath<u64>,
+) -> Result<ResponseJson<SignedDocument>, OnyxError> {
+    let signer = state
+        .snapshots
+        .as_ref()
+        .ok_or(OnyxError::not_found("This registry doesn't sign snapshots"))?;
+    let root = signer
+        .roots
+        .get(&version)
+        .cloned()
+        .ok_or(OnyxError::not_found(&format!("No root version {version}")))?;
+    Ok(ResponseJson(root))
+}
+
+pub async fn snapshot(
+    State(state): State<OnyxState>,
+) -> Result<ResponseJson<SignedDocument>, OnyxError> {
+    let read = state.db.begin_read()?;
+    let snapshot_table = read.open_table(SNAPSHOT_TABLE)?;
+    let Some((_, signed)) = snapshot_table.last()? else {
+        return Err(OnyxError::not_found("No snapshot has been signed"));
+    };
+    let signed =
+        serde_json::from_str::<SignedDocument>(signed.value()).map_err(anyhow::Error::from)?;
+    Ok(ResponseJson(signed))
+}
+
+#[cfg(test)]
+mod tests {
+    use anyhow::Result;
+    use onyx_api::prelude::*;
+
+    use super::SnapshotSigner;
+    use super::is_signed_by;
+    use super::keygen;
+    use super::refresh;
+    use super::sign_root;
+    use crate::Config;
+    use crate::testing::OnyxTest;
+
+    #[tokio::test]
+    async fn should_sign_snapshots() -> Result<()> {
+        let test = OnyxTest::with_snapshots().await?;
+        assert_eq!(test.api.snapshot().await?, None);
+
+        let root_doc = test.api.registry_root(None).await?.unwrap();
+        let root = root_doc.document::<RegistryRoot>()?;
+        assert!(is_signed_by(&root_doc, &root.root_keys));
+        assert_eq!(
+            test.api.registry_root(Some(root.version)).await?,
+            Some(root_doc)
+        );
+        assert_eq!(test.api.registry_root(Some(root.version + 1)).await?, None);
+
+        let (login, _) = test.signup(None).await?;
+        let hashes = test
+            .seed_package(&login, "signed", &["0.1.0", "0.2.0"])
+            .await?;
+        let snapshot = refresh(&test.state)?.unwrap();
+        // nothing changed
+        assert_eq!(refresh(&test.state)?, None);
+
+        let signed = test.api.snapshot().await?.unwrap();
+        assert!(is_signed_by(&signed, &root.snapshot_keys));
+        assert!(!is_signed_by(&signed, &root.root_keys));
+        assert_eq!(signed.document::<Snapshot>()?, snapshot);
+        assert_eq!(snapshot.root_version, root.version);
+        assert_eq!(snapshot.packages["signed"]["0.2.0"], hashes[1].to_string());
+
+        let mut tampered = signed.clone();
+        tampered.signed = tampered.signed.replace("0.2.0", "0.2.1");
+        assert!(!is_signed_by(&tampered, &root.snapshot_keys));
+
+        test.seed_package(&login, "signed", &["0.3.0"]).await?;
+        let next = refresh(&test.state)?.unwrap();
+        assert!(next.id > snapshot.id);
+        assert_eq!(next.packages["signed"].len(), 3);
+        Ok(())
+    }
+
+    #[test]
+    fn should_require_root_rotations_to_be_signed() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let key = |name: &str| -> Result<(std::path::PathBuf, String)> {
+            let path = dir.path().join(name);
+            let public_key = keygen(&path)?;
+            Ok((path, public_key))
+        };
+        let (old_root_path, old_root_key) = key("old_root.key")?;
+        let (new_root_path, new_root_key) = key("new_root.key")?;
+        let (snapshot_path, snapshot_key) = key("snapshot.key")?;
+        let root_path = dir.path().join("roots");
+        std::fs::create_dir(&root_path)?;
+        let write_root =
+            |version: u64, root_keys: &str, signers: &[&std::path::Path]| -> Result<()> {
+                let unsigned_path = dir.path().join("root.json");
+                let root = RegistryRoot {
+                    version,
+                    expires_at: timestamp() + 1000,
+                    root_keys: vec![root_keys.to_string()],
+                    snapshot_keys: vec![snapshot_key.clone()],
+                };
+                std::fs::write(&unsigned_path, serde_json::to_vec(&root)?)?;
+                std::fs::write(
+                    root_path.join(format!("{version}.root.json")),
+                    sign_root(&unsigned_path, signers)?,
+                )?;
+                Ok(())
+            };
+        let config = Config {
+            snapshot_key_path: Some(snapshot_path.clone()),
+            root_path: Some(root_path.clone()),
+            ..Config::default()
+        };
+
+        write_root(1, &old_root_key, &[&old_root_path])?;
+        assert!(SnapshotSigner::from_config(&config)?.is_some());
+        // a root must be signed by its own keys
+        assert!(write_root(2, &new_root_key, &[&old_root_path]).is_err());
+        // and by the keys of the root before it
+        write_root(2, &new_root_key, &[&new_root_path])?;
+        assert!(SnapshotSigner::from_config(&config).is_err());
+        write_root(2, &new_root_key, &[&old_root_path, &new_root_path])?;
+        let signer = SnapshotSigner::from_config(&config)?.unwrap();
+        assert_eq!(signer.latest_root_version(), 2);
+
+        let (other_path, _) = key("other.key")?;
+        let config = Config {
+            snapshot_key_path: Some(other_path),
+            ..config
+        };
+        assert!(SnapshotSigner::from_config(&config).is_err());
+        Ok(())
+    }
+}
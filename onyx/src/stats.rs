@@ -0,0 +1,161 @@
+use axum::extract::Path;
+use axum::extract::State;
+use axum::http::StatusCode;
+use axum::response::Json as ResponseJson;
+use redb::ReadableTable;
+
+use onyx_api::prelude::*;
+
+use super::OnyxError;
+use super::OnyxState;
+use super::db::WriteTxn;
+use super::validate::ValidJson;
+
+fn increment(write: &WriteTxn, version_id: &HashId) -> Result<(), OnyxError> {
+    let mut version_download_table = write.open_table(VERSION_DOWNLOAD_TABLE)?;
+    let downloads = version_download_table
+        .get(version_id)?
+        .map(|v| v.value())
+        .unwrap_or_default();
+    version_download_table.insert(version_id, downloads + 1)?;
+    Ok(())
+}
+
+/// Count a download of a version.
+pub fn record_download(state: &OnyxState, version_id: &HashId) -> Result<(), OnyxError> {
+    let write = state.db.begin_write()?;
+    increment(&write, version_id)?;
+    write.commit()?;
+    Ok(())
+}
+
+/// Count versions a client installed without the download endpoint, e.g. cloned with git or
+/// built from a delta. Versions the registry doesn't have are ignored.
+pub async fn report_installs(
+    State(state): State<OnyxState>,
+    ValidJson(payload): ValidJson<InstallStatsRequest>,
+) -> Result<StatusCode, OnyxError> {
+    let write = state.db.begin_write()?;
+    for install in &payload.installs {
+        let version_id = {
+            let package_name_table = write.open_table(PACKAGE_NAME_TABLE)?;
+            let package_version_name_table = write.open_table(PACKAGE_VERSION_NAME_TABLE)?;
+            let Some(package_id) = package_name_table.get(install.package_name.as_str())? else {
+                continue;
+            };
+            let Some(version_id) = package_version_name_table
+                .get((package_id.value(), install.version_name.as_str()))?
+            else {
+                continue;
+            };
+            version_id.value()
+        };
+        increment(&write, &version_id)?;
+    }
+    write.commit()?;
+    Ok(StatusCode::NO_CONTENT)
+}
+
+pub async fn package_stats(
+    State(state): State<OnyxState>,
+    Path(package_name): Path<String>,
+) -> Result<ResponseJson<PackageStatsResponse>, OnyxError> {
+    let (_package, mut versions) = PackageModel::versions(&state.db.begin_read()?, &package_name)?
+        .ok_or(OnyxError::not_found(&format!(
+            "Unable to find package \"{package_name}\""
+        )))?;
+    versions.sort_by(|a, b| (a.created_at, &a.name).cmp(&(b.created_at, &b.name)));
+    let read = state.db.begin_read()?;
+    let version_download_table = read.open_table(VERSION_DOWNLOAD_TABLE)?;
+    let mut stats = vec![];
+    for version in versions {
+        let downloads = version_download_table
+            .get(&version.id)?
+            .map(|v| v.value())
+            .unwrap_or_default();
+        stats.push(VersionStats {
+            version_name: version.name,
+            downloads,
+        });
+    }
+    Ok(ResponseJson(PackageStatsResponse {
+        package_name,
+        downloads: stats.iter().map(|version| version.downloads).sum(),
+        versions: stats,
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use anyhow::Result;
+    use nanoid::nanoid;
+    use onyx_api::prelude::*;
+
+    use crate::testing::OnyxTest;
+
+    #[tokio::test]
+    async fn should_count_downloads_and_reported_installs() -> Result<()> {
+        let test = OnyxTest::new().await?;
+        let (login, _password) = test.signup(None).await?;
+        let name = nanoid!();
+        let mut version_ids = vec![];
+        for version in ["0.1.0", "0.2.0"] {
+            let tarball =
+                OnyxTest::create_test_tarball_named(Some(version), Some(&name), Some(version))?;
+            version_ids.push(HashId::from(tarball.1));
+            test.publish(
+                Some(PublishData::new(tarball.1.to_string(), login.token.clone())),
+                tarball,
+            )
+            .await?;
+        }
+
+        test.api.download_tarball(&version_ids[0]).await?;
+        test.api
+            .report_installs(&InstallStatsRequest {
+                installs: vec![
+                    PackageInstall {
+                        package_name: name.clone(),
+                        version_name: "0.2.0".to_string(),
+                    },
+                    PackageInstall {
+                        package_name: name.clone(),
+                        version_name: "0.2.0".to_string(),
+                    },
+                    // unknown versions are ignored
+                    PackageInstall {
+                        package_name: name.clone(),
+                        version_name: "9.9.9".to_string(),
+                    },
+                ],
+            })
+            .await?;
+
+        let stats = test.api.package_stats(&name).await?;
+        assert_eq!(stats.downloads, 3);
+        assert_eq!(
+            stats.versions,
+            vec![
+                VersionStats {
+                    version_name: "0.1.0".to_string(),
+                    downloads: 1,
+                },
+                VersionStats {
+                    version_name: "0.2.0".to_string(),
+                    downloads: 2,
+                },
+            ]
+        );
+
+        let err = test
+            .api
+            .report_installs(&InstallStatsRequest { installs: vec![] })
+            .await
+            .unwrap_err();
+        assert_eq!(
+            err.downcast_ref::<ApiError>().map(|e| e.code),
+            Some(OnyxErrorCode::ValidationFailed)
+        );
+        Ok(())
+    }
+}
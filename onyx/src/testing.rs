@@ -0,0 +1,498 @@
+//! An in-process registry for testing code that talks to nrpm, enabled with the `testing`
+//! feature. The registry runs on a random local port with a temporary database, can be seeded
+//! through the api, and can be told to fail requests to test how clients handle it.
+
+use std::io::Read;
+use std::io::Seek;
+use std::net::SocketAddr;
+use std::path::Path;
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::sync::Mutex;
+use std::time::Duration;
+
+use anyhow::Result;
+use axum::body::Body;
+use axum::body::Bytes;
+use axum::extract::Request;
+use axum::extract::State;
+use axum::http::HeaderMap;
+use axum::http::StatusCode;
+use axum::middleware::Next;
+use axum::response::IntoResponse;
+use axum::response::Response;
+use nanoid::nanoid;
+use tempfile::TempDir;
+use tempfile::tempfile;
+
+use onyx_api::prelude::*;
+
+use super::Config;
+use super::OnyxState;
+use super::build;
+use super::build_server;
+use super::create_tables;
+use super::db::Db;
+use super::scan;
+use super::snapshot;
+use super::snapshot::SnapshotSigner;
+use super::webhook::WebhookClient;
+
+/// A failure injected into responses, see `OnyxTest::inject`.
+#[derive(Clone, Debug)]
+pub enum Fault {
+    /// Wait before handling the request.
+    Delay(Duration),
+    /// Respond with this status code instead of handling the request.
+    Status(u16),
+    /// Invert every byte of the response body, e.g. to corrupt a downloaded tarball.
+    CorruptBody,
+}
+
+struct Injection {
+    path_prefix: String,
+    fault: Fault,
+    /// Number of requests left to fail, `None` to fail every request.
+    remaining: Option<usize>,
+}
+
+#[derive(Clone, Default)]
+struct Faults(Arc<Mutex<Vec<Injection>>>);
+
+impl Faults {
+    /// The fault to apply to a request for `path`, if any.
+    fn take(&self, path: &str) -> Option<Fault> {
+        let mut injections = self.0.lock().unwrap();
+        let i = injections
+            .iter()
+            .position(|injection| path.starts_with(&injection.path_prefix))?;
+        let fault = injections[i].fault.clone();
+        if let Some(remaining) = injections[i].remaining.as_mut() {
+            *remaining -= 1;
+            if *remaining == 0 {
+                injections.remove(i);
+            }
+        }
+        Some(fault)
+    }
+}
+
+async fn inject_faults(State(faults): State<Faults>, request: Request, next: Next) -> Response {
+    match faults.take(request.uri().path()) {
+        None => next.run(request).await,
+        Some(Fault::Delay(duration)) => {
+            tokio::time::sleep(duration).await;
+            next.run(request).await
+        }
+        Some(Fault::Status(code)) => (
+            StatusCode::from_u16(code).unwrap_or(StatusCode::INTERNAL_SERVER_ERROR),
+            "injected fault",
+        )
+            .into_response(),
+        Some(Fault::CorruptBody) => {
+            let (parts, body) = next.run(request).await.into_parts();
+            let bytes = axum::body::to_bytes(body, usize::MAX)
+                .await
+                .map(|bytes| bytes.iter().map(|byte| !byte).collect::<Vec<_>>())
+                .unwrap_or_default();
+            Response::from_parts(parts, Body::from(bytes))
+        }
+    }
+}
+
+/// Start a server that records the event header, signature header and body of each webhook
+/// request it receives. Returns its url.
+pub async fn webhook_receiver() -> Result<(String, Arc<Mutex<Vec<(String, String, Bytes)>>>)> {
+    let received = Arc::new(Mutex::new(vec![]));
+    let app = axum::Router::new().route(
+        "/hook",
+        axum::routing::post({
+            let received = received.clone();
+            move |headers: HeaderMap, body: Bytes| async move {
+                let header = |name: &str| headers[name].to_str().unwrap().to_string();
+                received.lock().unwrap().push((
+                    header(WEBHOOK_EVENT_HEADER),
+                    header(WEBHOOK_SIGNATURE_HEADER),
+                    body,
+                ));
+            }
+        }),
+    );
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await?;
+    let url = format!("http://{}/hook", listener.local_addr()?);
+    tokio::spawn(async move {
+        axum::serve(listener, app).await.unwrap();
+    });
+    Ok((url, received))
+}
+
+pub struct OnyxTest {
+    pub url: String,
+    pub(crate) state: OnyxState,
+    pub api: OnyxApi,
+    faults: Faults,
+
+    #[allow(dead_code)]
+    tmp_handles: Vec<TempDir>,
+}
+
+impl OnyxTest {
+    pub async fn new() -> Result<Self> {
+        Self::with_config(|_| {}).await
+    }
+
+    /// Start a server that treats the given usernames as admins.
+    pub async fn with_admins(admins: &[&str]) -> Result<Self> {
+        let admins = admins.iter().map(|v| v.to_string()).collect();
+        Self::with_config(|state| Arc::make_mut(&mut state.config).admins = admins).await
+    }
+
+    /// Start a server that mirrors the registry at `upstream`.
+    pub async fn mirror_of(upstream: &str) -> Result<Self> {
+        Self::with_config(|state| {
+            Arc::make_mut(&mut state.config).upstream_url = Some(upstream.to_string())
+        })
+        .await
+    }
+
+    /// Start a server that announces `version` as the latest nrpm release.
+    pub async fn with_cli_version(version: &str) -> Result<Self> {
+        Self::with_config(|state| {
+            Arc::make_mut(&mut state.config).cli_version = Some(version.to_string())
+        })
+        .await
+    }
+
+    /// Start a server that scans uploads with `commands` before publishing them. Uploads are
+    /// only scanned by `scan_uploads`.
+    pub async fn with_scan_commands(commands: &[PathBuf]) -> Result<Self> {
+        let commands = commands.to_vec();
+        Self::with_config(|state| {
+            Arc::make_mut(&mut state.config).scan_commands = commands;
+            state.scanners = scan::scanners(&state.config);
+        })
+        .await
+    }
+
+    /// Start a server that rebuilds published versions with the `nargo` at `nargo_path`.
+    /// Versions are only rebuilt by `verify_builds`.
+    pub async fn with_nargo(nargo_path: &Path) -> Result<Self> {
+        let nargo_path = nargo_path.to_path_buf();
+        Self::with_config(|state| {
+            Arc::make_mut(&mut state.config).nargo_path = Some(nargo_path);
+        })
+        .await
+    }
+
+    /// Start a server that signs snapshots, trusted through a root signed by a new root key.
+    /// Snapshots are only signed by `sign_snapshot`.
+    pub async fn with_snapshots() -> Result<Self> {
+        let key_dir = TempDir::new()?;
+        let root_key_path = key_dir.path().join("root.key");
+        let snapshot_key_path = key_dir.path().join("snapshot.key");
+        let root = RegistryRoot {
+            version: 1,
+            expires_at: timestamp() + 365 * 24 * 60 * 60,
+            root_keys: vec![snapshot::keygen(&root_key_path)?],
+            snapshot_keys: vec![snapshot::keygen(&snapshot_key_path)?],
+        };
+        let unsigned_path = key_dir.path().join("root.json");
+        std::fs::write(&unsigned_path, serde_json::to_vec(&root)?)?;
+        let root_path = key_dir.path().join("roots");
+        std::fs::create_dir(&root_path)?;
+        std::fs::write(
+            root_path.join("1.root.json"),
+            snapshot::sign_root(&unsigned_path, &[&root_key_path])?,
+        )?;
+        let config = Config {
+            snapshot_key_path: Some(snapshot_key_path),
+            root_path: Some(root_path),
+            ..Config::default()
+        };
+        let snapshots = SnapshotSigner::from_config(&config)?;
+        let mut test = Self::with_config(|state| {
+            state.config = Arc::new(config);
+            state.snapshots = snapshots;
+        })
+        .await?;
+        test.tmp_handles.push(key_dir);
+        Ok(test)
+    }
+
+    /// Start a server after adjusting the default state.
+    pub(crate) async fn with_config(configure: impl FnOnce(&mut OnyxState)) -> Result<Self> {
+        let temp_dir = TempDir::new()?;
+
+        let db_path = temp_dir.path().join(format!("{}.db", nanoid!()));
+        let config = Config {
+            // webhook receivers in tests listen on loopback
+            allow_private_webhooks: true,
+            ..Config::default()
+        };
+        let db = Arc::new(Db::new(
+            redb::Database::create(&db_path).unwrap(),
+            Duration::from_millis(config.slow_transaction_ms),
+            config.read_cache_entries,
+        ));
+
+        create_tables(&db)?;
+
+        let mut state = OnyxState {
+            db,
+            storage: OnyxStorage::default(),
+            cdn: None,
+            snapshots: None,
+            scanners: vec![],
+            webhooks: WebhookClient::from_config(&config)?,
+            config: Arc::new(config),
+        };
+        configure(&mut state);
+        let faults = Faults::default();
+        let app = build_server(state.clone()).layer(axum::middleware::from_fn_with_state(
+            faults.clone(),
+            inject_faults,
+        ));
+
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await?;
+        let addr = listener.local_addr()?.to_string();
+        tokio::spawn(async move {
+            axum::serve(
+                listener,
+                app.into_make_service_with_connect_info::<SocketAddr>(),
+            )
+            .await
+            .unwrap();
+        });
+        tokio::time::sleep(Duration::from_millis(500)).await;
+
+        let url = format!("http://{}", addr);
+        Ok(Self {
+            api: OnyxApi::new(url.clone())?,
+            url,
+            state,
+            faults,
+
+            // used to keep handles in memory to prevent directory removal until end of program
+            tmp_handles: vec![temp_dir],
+        })
+    }
+
+    pub fn create_test_tarball(content: Option<&str>) -> Result<(Vec<u8>, blake3::Hash)> {
+        Self::create_test_tarball_named(content, None, None)
+    }
+
+    // Test helper to create a test tarball
+    pub fn create_test_tarball_named(
+        content: Option<&str>,
+        name: Option<&str>,
+        version: Option<&str>,
+    ) -> Result<(Vec<u8>, blake3::Hash)> {
+        let content = content.unwrap_or("testcontents\n");
+        let workdir = tempfile::TempDir::new()?;
+        std::fs::write(workdir.path().join("aaaaa"), content)?;
+        std::fs::write(
+            workdir.path().join("Nargo.toml"),
+            format!(
+                "[package]
+name = \"{}\"
+version = \"{}\"
+",
+                name.unwrap_or(&nanoid!()),
+                version.unwrap_or("0.0.0")
+            ),
+        )?;
+        let tar_file = tempfile()?;
+        let mut tarball = nrpm_tarball::create(workdir.path(), tar_file)?;
+        let mut tarball_clone = tarball.try_clone()?;
+        let hash = nrpm_tarball::hash_tarball(&mut tarball)?;
+
+        tarball_clone.seek(std::io::SeekFrom::Start(0))?;
+        let mut tarball_bytes = vec![];
+        tarball_clone.read_to_end(&mut tarball_bytes)?;
+
+        Ok((tarball_bytes, hash))
+    }
+
+    /// Create a tarball containing exactly `files`, given as `(path, contents)` pairs.
+    pub fn create_tarball_from_files(files: &[(&str, &str)]) -> Result<(Vec<u8>, blake3::Hash)> {
+        let workdir = tempfile::TempDir::new()?;
+        for (path, contents) in files {
+            let path = workdir.path().join(path);
+            if let Some(parent) = path.parent() {
+                std::fs::create_dir_all(parent)?;
+            }
+            std::fs::write(path, contents)?;
+        }
+        let mut tarball = nrpm_tarball::create(workdir.path(), tempfile()?)?;
+        let hash = nrpm_tarball::hash_tarball(&mut tarball)?;
+
+        tarball.seek(std::io::SeekFrom::Start(0))?;
+        let mut tarball_bytes = vec![];
+        tarball.read_to_end(&mut tarball_bytes)?;
+
+        Ok((tarball_bytes, hash))
+    }
+
+    /// Generate a user with random username and password. Returns
+    /// the `UserModel` and the password.
+    pub async fn signup(&self, request: Option<LoginRequest>) -> Result<(LoginResponse, String)> {
+        let request = request.unwrap_or(LoginRequest {
+            username: nanoid!(),
+            password: nanoid!(),
+            remember_me: false,
+        });
+        let password = request.password.clone();
+        let login = self.api.signup(request).await?;
+        Ok((login, password))
+    }
+
+    pub async fn login(&self, request: Option<LoginRequest>) -> Result<LoginResponse> {
+        let request = request.unwrap_or_default();
+        self.api.login(request).await
+    }
+
+    /// The registry database, for seeding or inspecting state the api doesn't expose.
+    pub fn db(&self) -> &Db {
+        &self.state.db
+    }
+
+    /// Sign a snapshot of the registry now instead of waiting for the job, see
+    /// `with_snapshots`.
+    pub fn sign_snapshot(&self) -> Result<()> {
+        snapshot::refresh(&self.state)?;
+        Ok(())
+    }
+
+    /// Rebuild queued versions now instead of waiting for the job. Returns the number of
+    /// versions rebuilt.
+    pub async fn verify_builds(&self) -> Result<usize> {
+        let state = self.state.clone();
+        tokio::task::spawn_blocking(move || build::run_queue(&state)).await?
+    }
+
+    /// Scan pending uploads now instead of waiting for the job. Returns the number of uploads
+    /// scanned.
+    pub async fn scan_uploads(&self) -> Result<usize> {
+        let state = self.state.clone();
+        tokio::task::spawn_blocking(move || {
+            tokio::runtime::Handle::current().block_on(scan::run_queue(&state))
+        })
+        .await?
+    }
+
+    /// Fail requests whose path starts with `path_prefix` with `fault`. Only the first `times`
+    /// matching requests fail if given. Faults are checked in the order they were injected.
+    pub fn inject(&self, path_prefix: &str, fault: Fault, times: Option<usize>) {
+        if times == Some(0) {
+            return;
+        }
+        self.faults.0.lock().unwrap().push(Injection {
+            path_prefix: path_prefix.to_string(),
+            fault,
+            remaining: times,
+        });
+    }
+
+    /// Stop injecting faults.
+    pub fn clear_faults(&self) {
+        self.faults.0.lock().unwrap().clear();
+    }
+
+    /// Publish `versions` of a package named `name` as `login`. Returns the version hashes.
+    pub async fn seed_package(
+        &self,
+        login: &LoginResponse,
+        name: &str,
+        versions: &[&str],
+    ) -> Result<Vec<blake3::Hash>> {
+        let mut hashes = vec![];
+        for version in versions {
+            let (hash, _) = self.seed_version(login, name, version, None).await?;
+            hashes.push(hash);
+        }
+        Ok(hashes)
+    }
+
+    /// Publish `version` of a package named `name` as `login`, with `content` in its test file
+    /// instead of the default. Returns the version hash and the registry's response.
+    pub async fn seed_version(
+        &self,
+        login: &LoginResponse,
+        name: &str,
+        version: &str,
+        content: Option<&str>,
+    ) -> Result<(blake3::Hash, PublishResponse)> {
+        let tarball = Self::create_test_tarball_named(content, Some(name), Some(version))?;
+        let hash = tarball.1;
+        let response = self
+            .publish(
+                Some(PublishData::new(hash.to_string(), login.token.clone())),
+                tarball,
+            )
+            .await?;
+        Ok((hash, response))
+    }
+
+    /// Attach an artifact named `name` holding `content` to `version_id` as `login`.
+    pub async fn seed_artifact(
+        &self,
+        login: &LoginResponse,
+        version_id: &HashId,
+        name: &str,
+        content: &str,
+    ) -> Result<VersionArtifactModel> {
+        self.api
+            .upload_artifact(
+                &login.token,
+                version_id,
+                UploadArtifactRequest {
+                    name: name.to_string(),
+                    content: content.to_string(),
+                },
+            )
+            .await
+    }
+
+    pub async fn publish(
+        &self,
+        request: Option<PublishData>,
+        tarball: (Vec<u8>, blake3::Hash),
+    ) -> Result<PublishResponse> {
+        let data = request.unwrap_or(PublishData::new(tarball.1.to_string(), nanoid!()));
+        self.api.publish(data, tarball.0).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use anyhow::Result;
+    use onyx_api::prelude::*;
+
+    use super::Fault;
+    use super::OnyxTest;
+
+    #[tokio::test]
+    async fn should_inject_faults() -> Result<()> {
+        let test = OnyxTest::new().await?;
+        let (login, _) = test.signup(None).await?;
+        let hashes = test
+            .seed_package(&login, "faulty", &["0.1.0", "0.2.0"])
+            .await?;
+
+        test.inject("/v0/packages/faulty", Fault::Status(503), Some(1));
+        let e = test.api.load_package_versions("faulty").await.unwrap_err();
+        assert!(e.downcast_ref::<ApiError>().is_some());
+        let (_, versions) = test.api.load_package_versions("faulty").await?;
+        assert_eq!(versions.len(), 2);
+
+        let version_id = HashId::from(hashes[0]);
+        test.inject("/v0/version/", Fault::CorruptBody, None);
+        for _ in 0..2 {
+            assert!(test.api.download_tarball(&version_id).await.is_err());
+        }
+        test.clear_faults();
+        test.api.download_tarball(&version_id).await?;
+
+        Ok(())
+    }
+}
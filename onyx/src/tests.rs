@@ -1,123 +0,0 @@
-use std::io::Read;
-use std::io::Seek;
-use std::sync::Arc;
-use std::time::Duration;
-
-use anyhow::Result;
-use nanoid::nanoid;
-use tempfile::TempDir;
-use tempfile::tempfile;
-
-use onyx_api::prelude::*;
-
-use super::OnyxState;
-use super::build_server;
-use super::create_tables;
-
-pub struct OnyxTest {
-    pub url: String,
-    pub state: OnyxState,
-    pub api: OnyxApi,
-
-    #[allow(dead_code)]
-    tmp_handles: Vec<TempDir>,
-}
-
-impl OnyxTest {
-    pub async fn new() -> Result<Self> {
-        let temp_dir = TempDir::new()?;
-
-        let db_path = temp_dir.path().join(format!("{}.db", nanoid!()));
-        let db = Arc::new(redb::Database::create(&db_path).unwrap());
-
-        create_tables(db.clone())?;
-
-        let state = OnyxState {
-            db,
-            storage: OnyxStorage::default(),
-        };
-        let app = build_server(state.clone());
-
-        let listener = tokio::net::TcpListener::bind(format!("127.0.0.1:0")).await?;
-        let addr = listener.local_addr()?.to_string();
-        tokio::spawn(async move {
-            axum::serve(listener, app).await.unwrap();
-        });
-        tokio::time::sleep(Duration::from_millis(500)).await;
-
-        let url = format!("http://{}", addr);
-        Ok(Self {
-            api: OnyxApi::new(url.clone())?,
-            url,
-            state,
-
-            // used to keep handles in memory to prevent directory removal until end of program
-            tmp_handles: vec![temp_dir],
-        })
-    }
-
-    pub fn create_test_tarball(content: Option<&str>) -> Result<(Vec<u8>, blake3::Hash)> {
-        Self::create_test_tarball_named(content, None, None)
-    }
-
-    // Test helper to create a test tarball
-    pub fn create_test_tarball_named(
-        content: Option<&str>,
-        name: Option<&str>,
-        version: Option<&str>,
-    ) -> Result<(Vec<u8>, blake3::Hash)> {
-        let content = content.unwrap_or("testcontents\n");
-        let workdir = tempfile::TempDir::new()?;
-        std::fs::write(workdir.path().join("aaaaa"), content)?;
-        std::fs::write(
-            workdir.path().join("Nargo.toml"),
-            format!(
-                "[package]
-name = \"{}\"
-version = \"{}\"
-",
-                name.unwrap_or(&nanoid!()),
-                version.unwrap_or("0.0.0")
-            ),
-        )?;
-        let tar_file = tempfile()?;
-        let mut tarball = nrpm_tarball::create(workdir.path(), tar_file)?;
-        let mut tarball_clone = tarball.try_clone()?;
-        let hash = nrpm_tarball::hash_tarball(&mut tarball)?;
-
-        tarball_clone.seek(std::io::SeekFrom::Start(0))?;
-        let mut tarball_bytes = vec![];
-        tarball_clone.read_to_end(&mut tarball_bytes)?;
-
-        Ok((tarball_bytes, hash))
-    }
-
-    /// Generate a user with random username and password. Returns
-    /// the `UserModel` and the password.
-    pub async fn signup(&self, request: Option<LoginRequest>) -> Result<(LoginResponse, String)> {
-        let request = request.unwrap_or(LoginRequest {
-            username: nanoid!(),
-            password: nanoid!(),
-        });
-        let password = request.password.clone();
-        let login = self.api.signup(request).await?;
-        Ok((login, password))
-    }
-
-    pub async fn login(&self, request: Option<LoginRequest>) -> Result<LoginResponse> {
-        let request = request.unwrap_or_default();
-        self.api.login(request).await
-    }
-
-    pub async fn publish(
-        &self,
-        request: Option<PublishData>,
-        tarball: (Vec<u8>, blake3::Hash),
-    ) -> Result<PublishResponse> {
-        let data = request.unwrap_or(PublishData {
-            hash: tarball.1.to_string(),
-            token: nanoid!(),
-        });
-        self.api.publish(data, tarball.0).await
-    }
-}
@@ -0,0 +1,437 @@
+use anyhow::Result;
+use axum::extract::Path;
+use axum::extract::State;
+use axum::http::StatusCode;
+use axum::response::Json as ResponseJson;
+use redb::ReadableTable;
+
+use onyx_api::prelude::*;
+
+use super::OnyxError;
+use super::OnyxState;
+use super::db::WriteTxn;
+use super::quota;
+use super::session::AdminSession;
+use super::session::AuthSession;
+use super::validate::ValidJson;
+
+fn package_by_name(write: &WriteTxn, name: &str) -> Result<PackageModel, OnyxError> {
+    let package_name_table = write.open_table(PACKAGE_NAME_TABLE)?;
+    let package_table = write.open_table(PACKAGE_TABLE)?;
+    if let Some(package_id) = package_name_table.get(name)?
+        && let Some(package) = package_table.get(package_id.value())?
+    {
+        Ok(package.value())
+    } else {
+        Err(OnyxError::not_found("Package not found"))
+    }
+}
+
+fn user_by_id(write: &WriteTxn, user_id: &str) -> Result<UserModel, OnyxError> {
+    let user_table = write.open_table(USER_TABLE)?;
+    match user_table.get(user_id)? {
+        Some(user) => Ok(user.value()),
+        None => Err(OnyxError::not_found("User not found")),
+    }
+}
+
+fn user_by_username(write: &WriteTxn, username: &str) -> Result<UserModel, OnyxError> {
+    let user_id = {
+        let username_table = write.open_table(USERNAME_USER_ID_TABLE)?;
+        match username_table.get(username)? {
+            Some(user_id) => user_id.value().to_string(),
+            None => return Err(OnyxError::not_found("User not found")),
+        }
+    };
+    user_by_id(write, &user_id)
+}
+
+fn pending_transfer(
+    write: &WriteTxn,
+    package_id: &str,
+) -> Result<Option<TransferRequestModel>, OnyxError> {
+    let package_transfer_table = write.open_table(PACKAGE_TRANSFER_TABLE)?;
+    Ok(package_transfer_table.get(package_id)?.map(|v| v.value()))
+}
+
+pub fn remove_pending_transfer(write: &WriteTxn, package_id: &str) -> Result<(), OnyxError> {
+    let mut package_transfer_table = write.open_table(PACKAGE_TRANSFER_TABLE)?;
+    let mut user_transfer_table = write.open_multimap_table(USER_TRANSFER_TABLE)?;
+    if let Some(transfer) = package_transfer_table.remove(package_id)? {
+        let transfer = transfer.value();
+        user_transfer_table.remove(transfer.from_user_id.as_str(), package_id)?;
+        user_transfer_table.remove(transfer.to_user_id.as_str(), package_id)?;
+    }
+    Ok(())
+}
+
+/// Make `to` the owner of a package and record the change. Pending transfers and claims on
+/// the package are discarded.
+fn apply_transfer(
+    write: &WriteTxn,
+    mut package: PackageModel,
+    to: &UserModel,
+    admin_username: Option<String>,
+    reason: Option<String>,
+) -> Result<PackageModel, OnyxError> {
+    let from = user_by_id(write, &package.author_id)?;
+    quota::transfer(write, &package.id, &from.id, &to.id)?;
+    package.ownership_history.push(OwnershipTransfer {
+        from_user_id: from.id,
+        from_username: from.username,
+        to_user_id: to.id.clone(),
+        to_username: to.username.clone(),
+        transferred_at: timestamp(),
+        admin_username,
+        reason,
+    });
+    package.author_id = to.id.clone();
+
+    remove_pending_transfer(write, &package.id)?;
+    let mut package_table = write.open_table(PACKAGE_TABLE)?;
+    let mut package_claim_table = write.open_table(PACKAGE_CLAIM_TABLE)?;
+    package_table.insert(package.id.as_str(), package.clone())?;
+    package_claim_table.retain_in(
+        (package.id.as_str(), "")..=(package.id.as_str(), "\u{10ffff}"),
+        |_, _| false,
+    )?;
+    Ok(package)
+}
+
+pub async fn request_transfer(
+    State(state): State<OnyxState>,
+    session: AuthSession,
+    Path(package_name): Path<String>,
+    ValidJson(payload): ValidJson<TransferPackageRequest>,
+) -> Result<ResponseJson<TransferRequestModel>, OnyxError> {
+    let write = state.db.begin_write()?;
+    let package = package_by_name(&write, &package_name)?;
+    if package.author_id != session.user_id {
+        return Err(OnyxError::new(
+            OnyxErrorCode::Forbidden,
+            "Only the owner of a package may transfer it",
+        ));
+    }
+    let from = user_by_id(&write, &session.user_id)?;
+    let to = user_by_username(&write, &payload.to_username)?;
+    if to.id == from.id {
+        return Err(OnyxError::bad_request(
+            "Package is already owned by this user",
+        ));
+    }
+
+    // a new offer replaces any pending one
+    remove_pending_transfer(&write, &package.id)?;
+    let transfer = TransferRequestModel {
+        package_id: package.id.clone(),
+        package_name: package.name.clone(),
+        from_user_id: from.id,
+        from_username: from.username,
+        to_user_id: to.id,
+        to_username: to.username,
+        created_at: timestamp(),
+    };
+    {
+        let mut package_transfer_table = write.open_table(PACKAGE_TRANSFER_TABLE)?;
+        let mut user_transfer_table = write.open_multimap_table(USER_TRANSFER_TABLE)?;
+        package_transfer_table.insert(package.id.as_str(), transfer.clone())?;
+        user_transfer_table.insert(transfer.from_user_id.as_str(), package.id.as_str())?;
+        user_transfer_table.insert(transfer.to_user_id.as_str(), package.id.as_str())?;
+    }
+    write.commit()?;
+    Ok(ResponseJson(transfer))
+}
+
+/// Cancel a transfer as the sender or decline it as the recipient.
+pub async fn cancel_transfer(
+    State(state): State<OnyxState>,
+    session: AuthSession,
+    Path(package_name): Path<String>,
+) -> Result<StatusCode, OnyxError> {
+    let write = state.db.begin_write()?;
+    let package = package_by_name(&write, &package_name)?;
+    let Some(transfer) = pending_transfer(&write, &package.id)? else {
+        return Err(OnyxError::not_found("No pending transfer for package"));
+    };
+    if transfer.from_user_id != session.user_id && transfer.to_user_id != session.user_id {
+        return Err(OnyxError::new(
+            OnyxErrorCode::Forbidden,
+            "Only the sender or recipient may cancel a transfer",
+        ));
+    }
+    remove_pending_transfer(&write, &package.id)?;
+    write.commit()?;
+    Ok(StatusCode::NO_CONTENT)
+}
+
+pub async fn accept_transfer(
+    State(state): State<OnyxState>,
+    session: AuthSession,
+    Path(package_name): Path<String>,
+) -> Result<ResponseJson<PackageModel>, OnyxError> {
+    let write = state.db.begin_write()?;
+    let package = package_by_name(&write, &package_name)?;
+    let Some(transfer) = pending_transfer(&write, &package.id)? else {
+        return Err(OnyxError::not_found("No pending transfer for package"));
+    };
+    if transfer.to_user_id != session.user_id {
+        return Err(OnyxError::new(
+            OnyxErrorCode::Forbidden,
+            "Only the recipient may accept a transfer",
+        ));
+    }
+    // an admin may have reassigned the package since the offer was made
+    if transfer.from_user_id != package.author_id {
+        remove_pending_transfer(&write, &package.id)?;
+        write.commit()?;
+        return Err(OnyxError::conflict(
+            "The package changed owner since the transfer was offered",
+        ));
+    }
+    let to = user_by_id(&write, &session.user_id)?;
+    let package = apply_transfer(&write, package, &to, None, None)?;
+    write.commit()?;
+    Ok(ResponseJson(package))
+}
+
+pub async fn list_transfers(
+    State(state): State<OnyxState>,
+    session: AuthSession,
+) -> Result<ResponseJson<TransfersResponse>, OnyxError> {
+    let read = state.db.begin_read()?;
+    let package_transfer_table = read.open_table(PACKAGE_TRANSFER_TABLE)?;
+    let user_transfer_table = read.open_multimap_table(USER_TRANSFER_TABLE)?;
+    let mut out = TransfersResponse::default();
+    for package_id in user_transfer_table.get(session.user_id.as_str())? {
+        let Some(transfer) = package_transfer_table.get(package_id?.value())? else {
+            continue;
+        };
+        let transfer = transfer.value();
+        if transfer.to_user_id == session.user_id {
+            out.incoming.push(transfer);
+        } else {
+            out.outgoing.push(transfer);
+        }
+    }
+    Ok(ResponseJson(out))
+}
+
+pub async fn claim_package(
+    State(state): State<OnyxState>,
+    session: AuthSession,
+    Path(package_name): Path<String>,
+    ValidJson(payload): ValidJson<ClaimPackageRequest>,
+) -> Result<ResponseJson<PackageClaimModel>, OnyxError> {
+    let write = state.db.begin_write()?;
+    let package = package_by_name(&write, &package_name)?;
+    if package.author_id == session.user_id {
+        return Err(OnyxError::bad_request("You already own this package"));
+    }
+    let user = user_by_id(&write, &session.user_id)?;
+    let claim = PackageClaimModel {
+        package_id: package.id.clone(),
+        package_name: package.name,
+        user_id: user.id,
+        username: user.username,
+        reason: payload.reason,
+        created_at: timestamp(),
+    };
+    {
+        let mut package_claim_table = write.open_table(PACKAGE_CLAIM_TABLE)?;
+        package_claim_table.insert((package.id.as_str(), claim.user_id.as_str()), claim.clone())?;
+    }
+    write.commit()?;
+    Ok(ResponseJson(claim))
+}
+
+pub async fn list_claims(
+    State(state): State<OnyxState>,
+    _admin: AdminSession,
+) -> Result<ResponseJson<Vec<PackageClaimModel>>, OnyxError> {
+    let read = state.db.begin_read()?;
+    let package_claim_table = read.open_table(PACKAGE_CLAIM_TABLE)?;
+    let mut claims = vec![];
+    for entry in package_claim_table.iter()? {
+        claims.push(entry?.1.value());
+    }
+    Ok(ResponseJson(claims))
+}
+
+pub async fn admin_transfer(
+    State(state): State<OnyxState>,
+    admin: AdminSession,
+    Path(package_name): Path<String>,
+    ValidJson(payload): ValidJson<AdminTransferRequest>,
+) -> Result<ResponseJson<PackageModel>, OnyxError> {
+    let write = state.db.begin_write()?;
+    let package = package_by_name(&write, &package_name)?;
+    let to = user_by_username(&write, &payload.to_username)?;
+    if to.id == package.author_id {
+        return Err(OnyxError::bad_request(
+            "Package is already owned by this user",
+        ));
+    }
+    log::info!(
+        "admin {} transferring package {} to {}",
+        admin.user.username,
+        package.name,
+        to.username
+    );
+    let package = apply_transfer(
+        &write,
+        package,
+        &to,
+        Some(admin.user.username),
+        Some(payload.reason),
+    )?;
+    write.commit()?;
+    Ok(ResponseJson(package))
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::testing::OnyxTest;
+    use anyhow::Result;
+    use nanoid::nanoid;
+    use onyx_api::prelude::*;
+
+    #[tokio::test]
+    async fn should_transfer_package() -> Result<()> {
+        let test = OnyxTest::new().await?;
+        let (owner, _password) = test.signup(None).await?;
+        let name = nanoid!();
+        test.seed_package(&owner, &name, &["0.1.0"]).await?;
+        let (recipient, _password) = test.signup(None).await?;
+
+        let transfer = test
+            .api
+            .request_transfer(&owner.token, &name, &recipient.user.username)
+            .await?;
+        assert_eq!(transfer.to_user_id, recipient.user.id);
+        let transfers = test.api.transfers(&recipient.token).await?;
+        assert_eq!(transfers.incoming, vec![transfer.clone()]);
+        let transfers = test.api.transfers(&owner.token).await?;
+        assert_eq!(transfers.outgoing, vec![transfer]);
+
+        let package = test.api.accept_transfer(&recipient.token, &name).await?;
+        assert_eq!(package.author_id, recipient.user.id);
+        assert_eq!(package.ownership_history.len(), 1);
+        assert_eq!(package.ownership_history[0].from_user_id, owner.user.id);
+        assert_eq!(package.ownership_history[0].admin_username, None);
+        assert!(
+            test.api
+                .transfers(&recipient.token)
+                .await?
+                .incoming
+                .is_empty()
+        );
+
+        // only the new owner can publish now
+        let tarball = OnyxTest::create_test_tarball_named(None, Some(&name), Some("0.0.1"))?;
+        let e = test
+            .publish(
+                Some(PublishData::new(tarball.1.to_string(), owner.token.clone())),
+                tarball.clone(),
+            )
+            .await
+            .unwrap_err();
+        assert_eq!(
+            e.downcast_ref::<ApiError>().map(|e| e.code),
+            Some(OnyxErrorCode::Forbidden)
+        );
+        test.publish(
+            Some(PublishData::new(
+                tarball.1.to_string(),
+                recipient.token.clone(),
+            )),
+            tarball,
+        )
+        .await?;
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn fail_transfer_non_owner() -> Result<()> {
+        let test = OnyxTest::new().await?;
+        let (owner, _password) = test.signup(None).await?;
+        let name = nanoid!();
+        test.seed_package(&owner, &name, &["0.1.0"]).await?;
+        let (other, _password) = test.signup(None).await?;
+
+        let e = test
+            .api
+            .request_transfer(&other.token, &name, &other.user.username)
+            .await
+            .unwrap_err();
+        assert_eq!(e.to_string(), "Only the owner of a package may transfer it");
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn should_decline_transfer() -> Result<()> {
+        let test = OnyxTest::new().await?;
+        let (owner, _password) = test.signup(None).await?;
+        let name = nanoid!();
+        test.seed_package(&owner, &name, &["0.1.0"]).await?;
+        let (recipient, _password) = test.signup(None).await?;
+
+        test.api
+            .request_transfer(&owner.token, &name, &recipient.user.username)
+            .await?;
+        test.api.cancel_transfer(&recipient.token, &name).await?;
+        let e = test
+            .api
+            .accept_transfer(&recipient.token, &name)
+            .await
+            .unwrap_err();
+        assert_eq!(e.to_string(), "No pending transfer for package");
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn should_admin_transfer_claimed_package() -> Result<()> {
+        let admin_username = nanoid!();
+        let test = OnyxTest::with_admins(&[&admin_username]).await?;
+        let (admin, _password) = test
+            .signup(Some(LoginRequest {
+                username: admin_username.clone(),
+                password: nanoid!(),
+                ..Default::default()
+            }))
+            .await?;
+        let (owner, _password) = test.signup(None).await?;
+        let name = nanoid!();
+        test.seed_package(&owner, &name, &["0.1.0"]).await?;
+        let (claimant, _password) = test.signup(None).await?;
+
+        // only admins can see claims
+        test.api
+            .claim_package(&claimant.token, &name, "The owner is unreachable")
+            .await?;
+        let e = test.api.admin_claims(&claimant.token).await.unwrap_err();
+        assert_eq!(
+            e.downcast_ref::<ApiError>().map(|e| e.code),
+            Some(OnyxErrorCode::Forbidden)
+        );
+        let claims = test.api.admin_claims(&admin.token).await?;
+        assert_eq!(claims.len(), 1);
+        assert_eq!(claims[0].user_id, claimant.user.id);
+
+        let package = test
+            .api
+            .admin_transfer(
+                &admin.token,
+                &name,
+                &claimant.user.username,
+                "The owner is unreachable",
+            )
+            .await?;
+        assert_eq!(package.author_id, claimant.user.id);
+        let record = package.ownership_history.last().unwrap();
+        assert_eq!(record.admin_username, Some(admin_username));
+        assert_eq!(record.reason.as_deref(), Some("The owner is unreachable"));
+        // the claim is resolved
+        assert!(test.api.admin_claims(&admin.token).await?.is_empty());
+        Ok(())
+    }
+}
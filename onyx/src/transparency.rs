@@ -0,0 +1,476 @@
+use std::str::FromStr;
+
+use anyhow::Result;
+use axum::extract::Path;
+use axum::extract::Query;
+use axum::extract::State;
+use axum::response::Json as ResponseJson;
+use redb::ReadableMultimapTable;
+use redb::ReadableTable;
+use redb::ReadableTableMetadata;
+use redb::StorageError;
+use serde::Deserialize;
+
+use onyx_api::prelude::*;
+
+use super::OnyxError;
+use super::OnyxState;
+use super::db::Db;
+use super::db::WriteTxn;
+
+const DEFAULT_PAGE_SIZE: usize = 100;
+const MAX_PAGE_SIZE: usize = 1000;
+
+/// The active key on the account of `author_id`, the newest if there are several.
+fn publisher_key(write: &WriteTxn, author_id: &str) -> Result<Option<String>, OnyxError> {
+    let user_keys_table = write.open_multimap_table(USER_KEYS_TABLE)?;
+    let user_key_table = write.open_table(USER_KEY_TABLE)?;
+    let mut active = None::<UserKeyModel>;
+    for public_key in user_keys_table.get(author_id)? {
+        let Some(key) = user_key_table.get(public_key?.value())? else {
+            continue;
+        };
+        let key = key.value();
+        if key.is_active()
+            && active
+                .as_ref()
+                .is_none_or(|a| a.created_at <= key.created_at)
+        {
+            active = Some(key);
+        }
+    }
+    Ok(active.map(|key| key.public_key))
+}
+
+/// Append a publish to the transparency log. Returns its index.
+pub fn append(
+    write: &WriteTxn,
+    package_name: &str,
+    version_name: &str,
+    version_id: &HashId,
+    author_id: &str,
+    published_at: u64,
+) -> Result<u64, OnyxError> {
+    let publisher_key = publisher_key(write, author_id)?;
+    let mut transparency_log_table = write.open_table(TRANSPARENCY_LOG_TABLE)?;
+    let index = transparency_log_table
+        .last()?
+        .map(|(index, _)| index.value() + 1)
+        .unwrap_or(0);
+    let entry = LogEntry {
+        index,
+        package_name: package_name.to_string(),
+        version_name: version_name.to_string(),
+        version_id: version_id.clone(),
+        author_id: author_id.to_string(),
+        publisher_key,
+        published_at,
+    };
+    let leaf = entry.leaf_hash();
+    transparency_log_table.insert(index, entry)?;
+    let mut version_log_index_table = write.open_table(VERSION_LOG_INDEX_TABLE)?;
+    version_log_index_table.insert(version_id, index)?;
+    extend_tree(write, index, leaf)?;
+    Ok(index)
+}
+
+/// Add the leaf at `index` to the log's Merkle tree. The complete subtrees it finishes are
+/// stored, so proofs only read the O(log n) subtrees they're made of, and so is the root of
+/// the tree it makes.
+fn extend_tree(write: &WriteTxn, index: u64, leaf: HashId) -> Result<(), OnyxError> {
+    let mut log_node_table = write.open_table(LOG_NODE_TABLE)?;
+    log_node_table.insert((0, index), &leaf)?;
+    let (mut level, mut node_index, mut hash) = (0u8, index, leaf);
+    while node_index & 1 == 1 {
+        let left = node(&log_node_table, level, node_index - 1)?;
+        hash = node_hash(&left, &hash);
+        level += 1;
+        node_index >>= 1;
+        log_node_table.insert((level, node_index), &hash)?;
+    }
+    let root = subtree_hash(&log_node_table, 0, index + 1)?;
+    write.open_table(LOG_ROOT_TABLE)?.insert(index + 1, root)?;
+    Ok(())
+}
+
+/// Populate an empty log with the changelog entries recorded before it existed. Their
+/// publisher key is the one the author has now. A log from before its Merkle tree was stored
+/// gets its tree.
+pub fn backfill(db: &Db) -> Result<()> {
+    let write = db.begin_write()?;
+    {
+        if write.open_table(TRANSPARENCY_LOG_TABLE)?.is_empty()? {
+            let changelog_table = write.open_table(CHANGELOG_TABLE)?;
+            for entry in changelog_table.iter()? {
+                let entry = entry?.1.value();
+                append(
+                    &write,
+                    &entry.package_name,
+                    &entry.version_name,
+                    &entry.version_id,
+                    &entry.author_id,
+                    entry.published_at,
+                )
+                .map_err(|e| anyhow::anyhow!("failed to backfill the transparency log: {e:?}"))?;
+            }
+        } else if write.open_table(LOG_ROOT_TABLE)?.is_empty()? {
+            let mut leaves = vec![];
+            for entry in write.open_table(TRANSPARENCY_LOG_TABLE)?.iter()? {
+                let (index, entry) = entry?;
+                leaves.push((index.value(), entry.value().leaf_hash()));
+            }
+            for (index, leaf) in leaves {
+                extend_tree(&write, index, leaf)
+                    .map_err(|e| anyhow::anyhow!("failed to backfill the log tree: {e:?}"))?;
+            }
+        }
+    }
+    write.commit()?;
+    Ok(())
+}
+
+/// The stored complete subtree at `level` and `index`, see `LOG_NODE_TABLE`.
+fn node(
+    log_node_table: &impl ReadableTable<(u8, u64), HashId>,
+    level: u8,
+    index: u64,
+) -> Result<HashId, OnyxError> {
+    log_node_table
+        .get((level, index))?
+        .map(|hash| hash.value())
+        .ok_or(OnyxError::new(
+            OnyxErrorCode::Internal,
+            &format!("Log tree node {level}/{index} is missing"),
+        ))
+}
+
+/// Hash of the subtree over the `n` leaves from `start`, as `merkle_root` of them. Subtrees of
+/// the log start at a multiple of their left subtree's size, so it's made of at most
+/// O(log n) stored complete subtrees.
+fn subtree_hash(
+    log_node_table: &impl ReadableTable<(u8, u64), HashId>,
+    start: u64,
+    n: u64,
+) -> Result<HashId, OnyxError> {
+    if n == 0 {
+        return Ok(merkle_root(&[]));
+    }
+    if n.is_power_of_two() {
+        let level = n.trailing_zeros();
+        return node(log_node_table, level as u8, start >> level);
+    }
+    let k = merkle_split(n as usize) as u64;
+    Ok(node_hash(
+        &subtree_hash(log_node_table, start, k)?,
+        &subtree_hash(log_node_table, start + k, n - k)?,
+    ))
+}
+
+/// `inclusion_path` of leaf `index` of the subtree over the `n` leaves from `start`.
+fn stored_inclusion_path(
+    log_node_table: &impl ReadableTable<(u8, u64), HashId>,
+    start: u64,
+    n: u64,
+    index: u64,
+) -> Result<Vec<HashId>, OnyxError> {
+    if n <= 1 {
+        return Ok(vec![]);
+    }
+    let k = merkle_split(n as usize) as u64;
+    let (mut path, sibling) = if index < k {
+        (
+            stored_inclusion_path(log_node_table, start, k, index)?,
+            subtree_hash(log_node_table, start + k, n - k)?,
+        )
+    } else {
+        (
+            stored_inclusion_path(log_node_table, start + k, n - k, index - k)?,
+            subtree_hash(log_node_table, start, k)?,
+        )
+    };
+    path.push(sibling);
+    Ok(path)
+}
+
+/// `consistency_path` of the log at `to_size` from `from_size`.
+fn stored_consistency_path(
+    log_node_table: &impl ReadableTable<(u8, u64), HashId>,
+    from_size: u64,
+    to_size: u64,
+) -> Result<Vec<HashId>, OnyxError> {
+    fn subproof(
+        log_node_table: &impl ReadableTable<(u8, u64), HashId>,
+        from_size: u64,
+        start: u64,
+        n: u64,
+        complete: bool,
+    ) -> Result<Vec<HashId>, OnyxError> {
+        if from_size == n {
+            return Ok(if complete {
+                vec![]
+            } else {
+                vec![subtree_hash(log_node_table, start, n)?]
+            });
+        }
+        let k = merkle_split(n as usize) as u64;
+        let (mut path, sibling) = if from_size <= k {
+            (
+                subproof(log_node_table, from_size, start, k, complete)?,
+                subtree_hash(log_node_table, start + k, n - k)?,
+            )
+        } else {
+            (
+                subproof(log_node_table, from_size - k, start + k, n - k, false)?,
+                subtree_hash(log_node_table, start, k)?,
+            )
+        };
+        path.push(sibling);
+        Ok(path)
+    }
+    if from_size == 0 || from_size >= to_size {
+        return Ok(vec![]);
+    }
+    subproof(log_node_table, from_size, 0, to_size, true)
+}
+
+/// The current head of the log, as stored when its last entry was appended.
+pub fn head(log_root_table: &impl ReadableTable<u64, HashId>) -> Result<LogHead, StorageError> {
+    Ok(match log_root_table.last()? {
+        Some((tree_size, root_hash)) => LogHead {
+            tree_size: tree_size.value(),
+            root_hash: root_hash.value(),
+        },
+        None => LogHead {
+            tree_size: 0,
+            root_hash: merkle_root(&[]),
+        },
+    })
+}
+
+pub async fn log_head(State(state): State<OnyxState>) -> Result<ResponseJson<LogHead>, OnyxError> {
+    let read = state.db.begin_read()?;
+    let log_root_table = read.open_table(LOG_ROOT_TABLE)?;
+    Ok(ResponseJson(head(&log_root_table)?))
+}
+
+#[derive(Deserialize)]
+pub struct LogEntriesQuery {
+    #[serde(default)]
+    start: u64,
+    limit: Option<usize>,
+}
+
+pub async fn log_entries(
+    State(state): State<OnyxState>,
+    Query(query): Query<LogEntriesQuery>,
+) -> Result<ResponseJson<LogEntriesResponse>, OnyxError> {
+    let limit = query.limit.unwrap_or(DEFAULT_PAGE_SIZE).min(MAX_PAGE_SIZE);
+    let read = state.db.begin_read()?;
+    let transparency_log_table = read.open_table(TRANSPARENCY_LOG_TABLE)?;
+    let mut entries = vec![];
+    for entry in transparency_log_table.range(query.start..)?.take(limit) {
+        entries.push(entry?.1.value());
+    }
+    Ok(ResponseJson(LogEntriesResponse {
+        entries,
+        tree_size: transparency_log_table.len()?,
+    }))
+}
+
+#[derive(Deserialize)]
+pub struct InclusionProofQuery {
+    tree_size: Option<u64>,
+}
+
+pub async fn inclusion_proof(
+    State(state): State<OnyxState>,
+    Path(id): Path<String>,
+    Query(query): Query<InclusionProofQuery>,
+) -> Result<ResponseJson<InclusionProof>, OnyxError> {
+    let version_id = HashId::from_str(&id)?;
+    let read = state.db.begin_read()?;
+    let version_log_index_table = read.open_table(VERSION_LOG_INDEX_TABLE)?;
+    let Some(index) = version_log_index_table.get(&version_id)?.map(|v| v.value()) else {
+        return Err(OnyxError::not_found(
+            "Version is not in the transparency log",
+        ));
+    };
+    let transparency_log_table = read.open_table(TRANSPARENCY_LOG_TABLE)?;
+    let current_size = transparency_log_table.len()?;
+    let tree_size = query.tree_size.unwrap_or(current_size);
+    if tree_size <= index || tree_size > current_size {
+        return Err(OnyxError::bad_request(&format!(
+            "Tree size must be between {} and {current_size}",
+            index + 1
+        )));
+    }
+    let entry = transparency_log_table
+        .get(index)?
+        .ok_or(OnyxError::not_found("Log entry not found"))?
+        .value();
+    let log_node_table = read.open_table(LOG_NODE_TABLE)?;
+    Ok(ResponseJson(InclusionProof {
+        entry,
+        tree_size,
+        hashes: stored_inclusion_path(&log_node_table, 0, tree_size, index)?,
+    }))
+}
+
+#[derive(Deserialize)]
+pub struct ConsistencyProofQuery {
+    #[serde(default)]
+    from: u64,
+    to: Option<u64>,
+}
+
+pub async fn consistency_proof(
+    State(state): State<OnyxState>,
+    Query(query): Query<ConsistencyProofQuery>,
+) -> Result<ResponseJson<ConsistencyProof>, OnyxError> {
+    let read = state.db.begin_read()?;
+    let transparency_log_table = read.open_table(TRANSPARENCY_LOG_TABLE)?;
+    let current_size = transparency_log_table.len()?;
+    let to_size = query.to.unwrap_or(current_size);
+    if query.from > to_size || to_size > current_size {
+        return Err(OnyxError::bad_request(&format!(
+            "Tree sizes must be in order and at most {current_size}"
+        )));
+    }
+    let log_node_table = read.open_table(LOG_NODE_TABLE)?;
+    Ok(ResponseJson(ConsistencyProof {
+        from_size: query.from,
+        to_size,
+        hashes: stored_consistency_path(&log_node_table, query.from, to_size)?,
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use anyhow::Result;
+    use onyx_api::prelude::*;
+
+    use super::*;
+    use crate::testing::OnyxTest;
+
+    #[tokio::test]
+    async fn should_prove_from_stored_subtrees() -> Result<()> {
+        let test = OnyxTest::new().await?;
+        let leaves = (0..20u8)
+            .map(|i| HashId::from(blake3::hash(&[i])))
+            .collect::<Vec<_>>();
+        let write = test.db().begin_write()?;
+        for (index, leaf) in leaves.iter().enumerate() {
+            extend_tree(&write, index as u64, leaf.clone()).unwrap();
+        }
+        write.commit()?;
+
+        let read = test.db().begin_read()?;
+        let log_node_table = read.open_table(LOG_NODE_TABLE)?;
+        let log_root_table = read.open_table(LOG_ROOT_TABLE)?;
+        assert_eq!(head(&log_root_table)?.root_hash, merkle_root(&leaves));
+        for n in 1..=leaves.len() {
+            let root = log_root_table.get(n as u64)?.unwrap().value();
+            assert_eq!(root, merkle_root(&leaves[..n]), "{n}");
+            for index in 0..n {
+                assert_eq!(
+                    stored_inclusion_path(&log_node_table, 0, n as u64, index as u64).unwrap(),
+                    inclusion_path(&leaves[..n], index),
+                    "{index} of {n}"
+                );
+            }
+            for from in 0..=n {
+                assert_eq!(
+                    stored_consistency_path(&log_node_table, from as u64, n as u64).unwrap(),
+                    consistency_path(&leaves[..n], from),
+                    "{from} to {n}"
+                );
+            }
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn should_verify_proofs_for_every_tree_size() {
+        let leaves = (0..20u8)
+            .map(|i| HashId::from(blake3::hash(&[i])))
+            .collect::<Vec<_>>();
+        let heads = (0..=leaves.len())
+            .map(|n| LogHead {
+                tree_size: n as u64,
+                root_hash: merkle_root(&leaves[..n]),
+            })
+            .collect::<Vec<_>>();
+        for to in 1..=leaves.len() {
+            for from in 0..=to {
+                let proof = ConsistencyProof {
+                    from_size: from as u64,
+                    to_size: to as u64,
+                    hashes: consistency_path(&leaves[..to], from),
+                };
+                assert!(proof.verify(&heads[from], &heads[to]), "{from} to {to}");
+                if from > 0 && from < to {
+                    let forged = LogHead {
+                        tree_size: from as u64,
+                        root_hash: HashId::from(blake3::hash(b"forged")),
+                    };
+                    assert!(!proof.verify(&forged, &heads[to]), "{from} to {to}");
+                }
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn should_prove_inclusion_of_publishes() -> Result<()> {
+        let test = OnyxTest::new().await?;
+        let (login, _password) = test.signup(None).await?;
+        let mut version_ids = vec![];
+        for version in ["0.1.0", "0.2.0", "0.3.0"] {
+            let tarball = OnyxTest::create_test_tarball_named(None, Some("logged"), Some(version))?;
+            version_ids.push(HashId::from(tarball.1));
+            test.publish(
+                Some(PublishData::new(tarball.1.to_string(), login.token.clone())),
+                tarball,
+            )
+            .await?;
+        }
+        let first = test.api.log_head().await?;
+        assert_eq!(first.tree_size, 3);
+
+        let tarball = OnyxTest::create_test_tarball_named(None, Some("logged"), Some("0.4.0"))?;
+        test.publish(
+            Some(PublishData::new(tarball.1.to_string(), login.token.clone())),
+            tarball,
+        )
+        .await?;
+        let head = test.api.log_head().await?;
+        assert_eq!(head.tree_size, 4);
+        let proof = test.api.log_consistency_proof(3, 4).await?;
+        assert!(proof.verify(&first, &head));
+
+        for (i, version_id) in version_ids.iter().enumerate() {
+            let proof = test.api.log_inclusion_proof(version_id, None).await?;
+            assert_eq!(proof.entry.index, i as u64);
+            assert_eq!(proof.entry.package_name, "logged");
+            assert!(proof.verify(&head));
+            assert!(!proof.verify(&first));
+            let proof = test.api.log_inclusion_proof(version_id, Some(3)).await?;
+            assert!(proof.verify(&first));
+        }
+
+        let entries = test.api.log_entries(1, 2).await?;
+        assert_eq!(entries.tree_size, 4);
+        assert_eq!(
+            entries.entries.iter().map(|e| e.index).collect::<Vec<_>>(),
+            vec![1, 2]
+        );
+
+        // logs from before the tree was stored get it when the registry starts
+        let write = test.db().begin_write()?;
+        write.open_table(LOG_NODE_TABLE)?.retain(|_, _| false)?;
+        write.open_table(LOG_ROOT_TABLE)?.retain(|_, _| false)?;
+        write.commit()?;
+        backfill(test.db())?;
+        assert_eq!(test.api.log_head().await?, head);
+        Ok(())
+    }
+}
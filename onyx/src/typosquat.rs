@@ -0,0 +1,419 @@
+use std::str::FromStr;
+
+use axum::extract::Path;
+use axum::extract::State;
+use axum::http::StatusCode;
+use axum::response::Json as ResponseJson;
+use redb::ReadableMultimapTable;
+use redb::ReadableTable;
+use serde::Deserialize;
+use serde::Serialize;
+
+use onyx_api::prelude::*;
+
+use super::OnyxError;
+use super::OnyxState;
+use super::audit;
+use super::db::WriteTxn;
+use super::session::AdminSession;
+
+/// Number of packages, by most dependents, new names are compared against.
+pub const POPULAR_PACKAGES: usize = 1000;
+
+/// What to do when a new package name resembles a popular package.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum TyposquatPolicy {
+    /// Don't compare new names.
+    Off,
+    /// Publish the package and warn the publisher.
+    #[default]
+    Warn,
+    /// Hold the name until an admin approves it.
+    Review,
+    /// Refuse to publish the package.
+    Reject,
+}
+
+impl FromStr for TyposquatPolicy {
+    type Err = serde_json::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        serde_json::from_value(serde_json::Value::String(s.to_string()))
+    }
+}
+
+/// Fold characters that look alike and drop separators, so `p0seidon_` and `poseidon` have
+/// the same skeleton.
+fn skeleton(name: &str) -> String {
+    let folded = name
+        .chars()
+        .filter(|c| *c != '-' && *c != '_')
+        .map(|c| match c.to_ascii_lowercase() {
+            '0' => 'o',
+            '1' | 'i' => 'l',
+            '3' => 'e',
+            '4' => 'a',
+            '5' => 's',
+            '7' => 't',
+            '8' => 'b',
+            c => c,
+        })
+        .collect::<String>();
+    folded
+        .replace("rn", "m")
+        .replace("vv", "w")
+        .replace("cl", "d")
+}
+
+/// Edits to turn `a` into `b`, counting a swap of neighbouring characters as one edit.
+fn edit_distance(a: &str, b: &str) -> usize {
+    let a = a.as_bytes();
+    let b = b.as_bytes();
+    let mut rows = vec![(0..=b.len()).collect::<Vec<_>>()];
+    for i in 1..=a.len() {
+        let mut row = vec![i; b.len() + 1];
+        for j in 1..=b.len() {
+            let cost = usize::from(a[i - 1] != b[j - 1]);
+            row[j] = (rows[i - 1][j] + 1)
+                .min(row[j - 1] + 1)
+                .min(rows[i - 1][j - 1] + cost);
+            if i > 1 && j > 1 && a[i - 1] == b[j - 2] && a[i - 2] == b[j - 1] {
+                row[j] = row[j].min(rows[i - 2][j - 2] + 1);
+            }
+        }
+        rows.push(row);
+    }
+    rows[a.len()][b.len()]
+}
+
+/// Whether `name` could be mistaken for `other`: they differ only in case, separators, or
+/// characters that look alike, or are a typo or two apart. Short names are only compared by
+/// their skeletons since most short names are a typo apart.
+pub fn is_similar(name: &str, other: &str) -> bool {
+    if name == other {
+        return false;
+    }
+    if skeleton(name) == skeleton(other) {
+        return true;
+    }
+    let name = name.to_ascii_lowercase();
+    let other = other.to_ascii_lowercase();
+    let max_distance = match name.len().min(other.len()) {
+        0..5 => return false,
+        5..10 => 1,
+        _ => 2,
+    };
+    name.len().abs_diff(other.len()) <= max_distance && edit_distance(&name, &other) <= max_distance
+}
+
+/// Names of the `POPULAR_PACKAGES` packages with the most dependents, leaving out packages
+/// owned by `user_id`.
+fn popular_packages(write: &WriteTxn, user_id: &str) -> Result<Vec<String>, OnyxError> {
+    let package_table = write.open_table(PACKAGE_TABLE)?;
+    let package_dependent_table = write.open_multimap_table(PACKAGE_DEPENDENT_TABLE)?;
+    let mut packages = vec![];
+    for entry in package_table.iter()? {
+        let package = entry?.1.value();
+        if package.author_id == user_id {
+            continue;
+        }
+        let dependents = package_dependent_table.get(package.id.as_str())?.len();
+        packages.push((dependents, package.name));
+    }
+    packages.sort_by(|a, b| b.0.cmp(&a.0).then_with(|| a.1.cmp(&b.1)));
+    packages.truncate(POPULAR_PACKAGES);
+    Ok(packages.into_iter().map(|(_, name)| name).collect())
+}
+
+/// Compare the name of a package about to be published against popular packages and apply
+/// the registry's `TyposquatPolicy`. Names already in use aren't compared. Returns warnings
+/// for the publisher, or an error if the name is rejected or held for review. Decisions are
+/// recorded in the audit log.
+pub fn screen(
+    state: &OnyxState,
+    user_id: &str,
+    package_name: &str,
+) -> Result<Vec<String>, OnyxError> {
+    let policy = state.config.typosquat_policy;
+    if policy == TyposquatPolicy::Off {
+        return Ok(vec![]);
+    }
+    let write = state.db.begin_write()?;
+    let user = {
+        let package_name_table = write.open_table(PACKAGE_NAME_TABLE)?;
+        if package_name_table.get(package_name)?.is_some() {
+            return Ok(vec![]);
+        }
+        let user_table = write.open_table(USER_TABLE)?;
+        match user_table.get(user_id)? {
+            Some(user) => user.value(),
+            None => return Err(OnyxError::not_found("User not found")),
+        }
+    };
+    let similar_to = popular_packages(&write, &user.id)?
+        .into_iter()
+        .filter(|other| is_similar(package_name, other))
+        .collect::<Vec<_>>();
+    if similar_to.is_empty() {
+        return Ok(vec![]);
+    }
+    let list = similar_to
+        .iter()
+        .map(|name| format!("\"{name}\""))
+        .collect::<Vec<_>>()
+        .join(", ");
+    let details = format!("{} publishing a name resembling {list}", user.username);
+
+    match policy {
+        TyposquatPolicy::Off => Ok(vec![]),
+        TyposquatPolicy::Warn => {
+            audit::record(
+                &write,
+                AuditAction::NameFlagged,
+                None,
+                package_name,
+                details,
+            )?;
+            write.commit()?;
+            Ok(vec![format!(
+                "\"{package_name}\" resembles {list}, make sure users won't mistake one for the other"
+            )])
+        }
+        TyposquatPolicy::Reject => {
+            audit::record(
+                &write,
+                AuditAction::NameRejected,
+                None,
+                package_name,
+                details,
+            )?;
+            write.commit()?;
+            Err(OnyxError::new(
+                OnyxErrorCode::NameTooSimilar,
+                &format!("\"{package_name}\" is too similar to {list}, choose another name"),
+            ))
+        }
+        TyposquatPolicy::Review => {
+            let review = {
+                let name_review_table = write.open_table(NAME_REVIEW_TABLE)?;
+                name_review_table.get(package_name)?.map(|v| v.value())
+            };
+            match review {
+                Some(review) if review.user_id != user.id => Err(OnyxError::new(
+                    OnyxErrorCode::NameTooSimilar,
+                    &format!("\"{package_name}\" is held for review for another user"),
+                )),
+                Some(review) if review.approved_by.is_some() => Ok(vec![]),
+                Some(_) => Err(OnyxError::new(
+                    OnyxErrorCode::NameTooSimilar,
+                    &format!(
+                        "\"{package_name}\" resembles {list} and is waiting for an admin to approve it"
+                    ),
+                )),
+                None => {
+                    let review = NameReviewModel {
+                        package_name: package_name.to_string(),
+                        similar_to,
+                        user_id: user.id.clone(),
+                        username: user.username.clone(),
+                        requested_at: timestamp(),
+                        approved_by: None,
+                        approved_at: None,
+                    };
+                    {
+                        let mut name_review_table = write.open_table(NAME_REVIEW_TABLE)?;
+                        name_review_table.insert(package_name, review)?;
+                    }
+                    audit::record(&write, AuditAction::NameHeld, None, package_name, details)?;
+                    write.commit()?;
+                    Err(OnyxError::new(
+                        OnyxErrorCode::NameTooSimilar,
+                        &format!(
+                            "\"{package_name}\" resembles {list}, it can be published once an admin approves it"
+                        ),
+                    ))
+                }
+            }
+        }
+    }
+}
+
+/// Names held for review that haven't been approved, oldest first.
+pub async fn name_reviews(
+    State(state): State<OnyxState>,
+    _admin: AdminSession,
+) -> Result<ResponseJson<Vec<NameReviewModel>>, OnyxError> {
+    let read = state.db.begin_read()?;
+    let name_review_table = read.open_table(NAME_REVIEW_TABLE)?;
+    let mut reviews = vec![];
+    for entry in name_review_table.iter()? {
+        let review = entry?.1.value();
+        if review.approved_by.is_none() {
+            reviews.push(review);
+        }
+    }
+    reviews.sort_by_key(|review| review.requested_at);
+    Ok(ResponseJson(reviews))
+}
+
+pub async fn approve_name(
+    State(state): State<OnyxState>,
+    admin: AdminSession,
+    Path(package_name): Path<String>,
+) -> Result<ResponseJson<NameReviewModel>, OnyxError> {
+    let write = state.db.begin_write()?;
+    let mut review = {
+        let name_review_table = write.open_table(NAME_REVIEW_TABLE)?;
+        match name_review_table.get(package_name.as_str())? {
+            Some(review) => review.value(),
+            None => return Err(OnyxError::not_found("No review for this name")),
+        }
+    };
+    if review.approved_by.is_some() {
+        return Err(OnyxError::conflict("Name is already approved"));
+    }
+    review.approved_by = Some(admin.user.username.clone());
+    review.approved_at = Some(timestamp());
+    {
+        let mut name_review_table = write.open_table(NAME_REVIEW_TABLE)?;
+        name_review_table.insert(package_name.as_str(), review.clone())?;
+    }
+    audit::record(
+        &write,
+        AuditAction::NameApproved,
+        Some(&admin.user.username),
+        &package_name,
+        format!("approved for {}", review.username),
+    )?;
+    write.commit()?;
+    Ok(ResponseJson(review))
+}
+
+/// Decline a held name. The user is held for review again if they retry.
+pub async fn decline_name(
+    State(state): State<OnyxState>,
+    admin: AdminSession,
+    Path(package_name): Path<String>,
+) -> Result<StatusCode, OnyxError> {
+    let write = state.db.begin_write()?;
+    let review = {
+        let mut name_review_table = write.open_table(NAME_REVIEW_TABLE)?;
+        match name_review_table.remove(package_name.as_str())? {
+            Some(review) => review.value(),
+            None => return Err(OnyxError::not_found("No review for this name")),
+        }
+    };
+    audit::record(
+        &write,
+        AuditAction::NameDeclined,
+        Some(&admin.user.username),
+        &package_name,
+        format!("declined for {}", review.username),
+    )?;
+    write.commit()?;
+    Ok(StatusCode::NO_CONTENT)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use anyhow::Result;
+    use nanoid::nanoid;
+    use onyx_api::prelude::*;
+
+    use super::*;
+    use crate::testing::OnyxTest;
+
+    #[test]
+    fn should_detect_similar_names() {
+        assert!(is_similar("poseidon", "p0seidon"));
+        assert!(is_similar("poseidon", "Poseidon"));
+        assert!(is_similar("poseidon", "pose_idon"));
+        assert!(is_similar("poseidon", "posiedon"));
+        assert!(is_similar("poseidon", "poseidonn"));
+        assert!(is_similar("merkle_tree", "merkle_trie"));
+        assert!(is_similar("modern", "modem"));
+        assert!(!is_similar("poseidon", "poseidon"));
+        assert!(!is_similar("poseidon", "poseidon2_bn254"));
+        assert!(!is_similar("rsa", "rsb"));
+        assert!(!is_similar("sha256", "sha512"));
+    }
+
+    #[tokio::test]
+    async fn should_hold_similar_names_for_review() -> Result<()> {
+        let admin_username = nanoid!();
+        let admins = [admin_username.clone()].into_iter().collect();
+        let test = OnyxTest::with_config(|state| {
+            let config = Arc::make_mut(&mut state.config);
+            config.admins = admins;
+            config.typosquat_policy = TyposquatPolicy::Review;
+        })
+        .await?;
+        let (owner, _password) = test.signup(None).await?;
+        let (squatter, _password) = test.signup(None).await?;
+        let (admin, _password) = test
+            .signup(Some(LoginRequest {
+                username: admin_username,
+                password: nanoid!(),
+                ..Default::default()
+            }))
+            .await?;
+        test.seed_package(&owner, "poseidon", &["0.1.0"]).await?;
+        // owners may publish names resembling their own packages
+        test.seed_package(&owner, "p0seidon", &["0.1.0"]).await?;
+
+        let err = test
+            .seed_package(&squatter, "posiedon", &["0.1.0"])
+            .await
+            .unwrap_err();
+        let err = err.downcast_ref::<ApiError>().unwrap();
+        assert_eq!(err.code, OnyxErrorCode::NameTooSimilar);
+        let reviews = test.api.admin_name_reviews(&admin.token).await?;
+        assert_eq!(reviews.len(), 1);
+        assert_eq!(reviews[0].similar_to, vec!["poseidon"]);
+
+        test.api
+            .admin_approve_name(&admin.token, "posiedon")
+            .await?;
+        test.seed_package(&squatter, "posiedon", &["0.1.0"]).await?;
+        assert!(test.api.admin_name_reviews(&admin.token).await?.is_empty());
+
+        let log = test.api.admin_audit_log(&admin.token, 0, 100).await?;
+        let actions = log.entries.iter().map(|e| e.action).collect::<Vec<_>>();
+        assert_eq!(
+            actions,
+            vec![AuditAction::NameHeld, AuditAction::NameApproved]
+        );
+        assert_eq!(log.latest_seq, 2);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn should_warn_or_reject_similar_names() -> Result<()> {
+        let test = OnyxTest::new().await?;
+        let (owner, _password) = test.signup(None).await?;
+        let (squatter, _password) = test.signup(None).await?;
+        test.seed_package(&owner, "merkle_tree", &["0.1.0"]).await?;
+        let (_, response) = test
+            .seed_version(&squatter, "merkle_trie", "0.1.0", None)
+            .await?;
+        assert_eq!(response.warnings.len(), 1);
+
+        let test = OnyxTest::with_config(|state| {
+            Arc::make_mut(&mut state.config).typosquat_policy = TyposquatPolicy::Reject;
+        })
+        .await?;
+        let (owner, _password) = test.signup(None).await?;
+        let (squatter, _password) = test.signup(None).await?;
+        test.seed_package(&owner, "merkle_tree", &["0.1.0"]).await?;
+        let err = test
+            .seed_package(&squatter, "merkle-tree", &["0.1.0"])
+            .await
+            .unwrap_err();
+        let err = err.downcast_ref::<ApiError>().unwrap();
+        assert_eq!(err.code, OnyxErrorCode::NameTooSimilar);
+        Ok(())
+    }
+}
@@ -1,96 +1,225 @@
 use anyhow::Result;
-use axum::extract::Json;
 use axum::extract::State;
 use axum::response::Json as ResponseJson;
-use nanoid::nanoid;
 use reqwest::StatusCode;
 
 use onyx_api::prelude::*;
 
-use super::AUTH_TOKEN_TABLE;
+use redb::ReadableMultimapTable;
+use redb::ReadableTable;
+
+use super::Config;
 use super::OnyxError;
 use super::OnyxState;
 use super::USER_TABLE;
-
-fn is_safe_nanoid(input: &str) -> bool {
-    input.chars().all(|c| nanoid::alphabet::SAFE.contains(&c))
-}
-
-// TODO: make this a constant or a lazy cell
-fn default_nanoid_len() -> usize {
-    nanoid!().len()
-}
+use super::password::PasswordCheck;
+use super::password::hash_password;
+use super::password::verify_password;
+use super::session::AuthSession;
+use super::session::NewSession;
+use super::session::authenticate;
+use super::session::create_session;
+use super::session::login_response;
+use super::session::refresh_session;
+use super::session::require_full_scope;
+use super::session::revoke_user_sessions;
+use super::session::token_scope;
+use super::transfer::remove_pending_transfer;
+use super::validate::ValidJson;
+use super::validate::ValidationErrors;
+use super::validate::validate_password;
+use super::watch;
 
 pub async fn current_auth(
     State(state): State<OnyxState>,
-    Json(payload): Json<TokenOnly>,
+    ValidJson(payload): ValidJson<AuthRequest>,
 ) -> Result<ResponseJson<LoginResponse>, OnyxError> {
-    let read = state.db.begin_read()?;
-    let auth_table = read.open_table(AUTH_TOKEN_TABLE)?;
-    let user_table = read.open_table(USER_TABLE)?;
-    let (user_id, expires_at) = if let Some(entry) = auth_table.get(payload.token.as_str())? {
-        let (user_id, expires_at) = entry.value();
-        if timestamp() > expires_at {
-            return Err(OnyxError::bad_request("Expired token!"));
+    match payload {
+        AuthRequest::Token { token } => {
+            let (user_id, expires_at) = authenticate(&state.db, &token)?;
+            let read = state.db.begin_read()?;
+            let user_table = read.open_table(USER_TABLE)?;
+            let user = user_table.get(user_id.as_str())?.unwrap().value();
+            Ok(ResponseJson(LoginResponse {
+                user: UserModelSafe::from(user),
+                token,
+                expires_at,
+                refresh_token: None,
+                refresh_expires_at: None,
+            }))
         }
-        (user_id.to_string(), expires_at)
-    } else {
-        return Err(OnyxError::bad_request("Invalid token!"));
-    };
-    let user = user_table.get(user_id.as_str())?.unwrap().value();
-    Ok(ResponseJson(LoginResponse {
-        user: UserModelSafe::from(user),
-        token: payload.token,
-        expires_at,
-    }))
+        AuthRequest::Refresh { refresh_token } => {
+            let (token, session) = refresh_session(&state.db, &state.config, &refresh_token)?;
+            let read = state.db.begin_read()?;
+            let user_table = read.open_table(USER_TABLE)?;
+            let user = user_table.get(session.user_id.as_str())?.unwrap().value();
+            Ok(ResponseJson(login_response(user, token, session)))
+        }
+    }
 }
 
 pub async fn propose_token(
     State(state): State<OnyxState>,
-    Json(payload): Json<ProposeToken>,
+    ValidJson(payload): ValidJson<ProposeToken>,
 ) -> Result<StatusCode, OnyxError> {
-    if !is_safe_nanoid(&payload.proposed_token) {
-        return Err(OnyxError::bad_request("Token contains invalid characters"));
-    }
-    if payload.proposed_token.len() != default_nanoid_len() {
-        return Err(OnyxError::bad_request(&format!(
-            "Token must be {} characters",
-            default_nanoid_len(),
-        )));
+    let (user_id, _expires_at) = authenticate(&state.db, &payload.token)?;
+    // a publish scoped token can't propose a token with more access than it has
+    require_full_scope(token_scope(&state.db, &payload.token)?)?;
+
+    let write = state.db.begin_write()?;
+    create_session(
+        &write,
+        &state.config,
+        &user_id,
+        &payload.proposed_token,
+        NewSession {
+            source: SessionSource::ProposedToken,
+            refresh_token: payload.proposed_refresh_token.as_deref(),
+            scope: payload.scope,
+            remember: false,
+        },
+    )?;
+    write.commit()?;
+
+    Ok(StatusCode::NO_CONTENT)
+}
+
+/// Check the password of `user`, so a leaked token alone can't take over or delete an account.
+fn reauthenticate(config: &Config, user: &UserModel, password: &str) -> Result<(), OnyxError> {
+    match verify_password(config, password, &user.password_hash) {
+        Ok(PasswordCheck::Valid | PasswordCheck::ValidNeedsRehash) => Ok(()),
+        Ok(PasswordCheck::Invalid) => Err(OnyxError::new(
+            OnyxErrorCode::InvalidCredentials,
+            "bad password",
+        )),
+        Err(e) => {
+            log::error!("password verification error for user {}: {e:?}", user.id);
+            Err(OnyxError::new(
+                OnyxErrorCode::InvalidCredentials,
+                "bad password",
+            ))
+        }
     }
-    let read = state.db.begin_read()?;
-    let auth_table = read.open_table(AUTH_TOKEN_TABLE)?;
-    let user_id = if let Some(entry) = auth_table.get(payload.token.as_str())? {
-        let (user_id, expires_at) = entry.value();
-        if timestamp() > expires_at {
-            return Err(OnyxError::bad_request("Expired token!"));
+}
+
+pub async fn update_user(
+    State(state): State<OnyxState>,
+    session: AuthSession,
+    ValidJson(payload): ValidJson<UpdateUserRequest>,
+) -> Result<ResponseJson<UserModelSafe>, OnyxError> {
+    session.require_full_scope()?;
+    let user = session.user(&state.db)?;
+    reauthenticate(&state.config, &user, &payload.password)?;
+    let username = payload.new_username.unwrap_or(user.username.clone());
+    let password_hash = match &payload.new_password {
+        Some(new_password) => {
+            let mut errors = ValidationErrors::default();
+            errors.check("new_password", validate_password(new_password, &username));
+            errors.into_result()?;
+            Some(hash_password(&state.config, new_password)?)
         }
-        user_id.to_string()
-    } else {
-        return Err(OnyxError::bad_request("Invalid token!"));
+        None => None,
     };
 
-    let expires_at = timestamp() + 3600;
     let write = state.db.begin_write()?;
-    {
-        let mut auth_token_table = write.open_table(AUTH_TOKEN_TABLE)?;
-        auth_token_table.insert(
-            payload.proposed_token.as_str(),
-            (user_id.as_str(), expires_at),
-        )?;
+    let user = {
+        let mut user_table = write.open_table(USER_TABLE)?;
+        let mut username_table = write.open_table(USERNAME_USER_ID_TABLE)?;
+        let mut user = user_table
+            .get(user.id.as_str())?
+            .ok_or(OnyxError::not_found("User not found"))?
+            .value();
+        if username != user.username {
+            if username_table.get(username.as_str())?.is_some() {
+                return Err(OnyxError::conflict("username is already in use"));
+            }
+            username_table.remove(user.username.as_str())?;
+            username_table.insert(username.as_str(), user.id.as_str())?;
+            user.username = username;
+        }
+        if let Some(password_hash) = password_hash {
+            user.password_hash = password_hash;
+        }
+        user_table.insert(user.id.as_str(), user.clone())?;
+        user
+    };
+    if payload.new_password.is_some() {
+        // whoever knew the old password may be holding another session
+        revoke_user_sessions(&write, &user.id, Some(&session.token))?;
     }
     write.commit()?;
+    Ok(ResponseJson(UserModelSafe::from(user)))
+}
+
+pub async fn delete_user(
+    State(state): State<OnyxState>,
+    session: AuthSession,
+    ValidJson(payload): ValidJson<DeleteUserRequest>,
+) -> Result<StatusCode, OnyxError> {
+    session.require_full_scope()?;
+    let user = session.user(&state.db)?;
+    reauthenticate(&state.config, &user, &payload.password)?;
+
+    let write = state.db.begin_write()?;
+    {
+        let package_table = write.open_table(PACKAGE_TABLE)?;
+        let mut owned = vec![];
+        for entry in package_table.iter()? {
+            let package = entry?.1.value();
+            if package.author_id == user.id {
+                owned.push(package.name);
+            }
+        }
+        if !owned.is_empty() {
+            return Err(OnyxError::conflict(&format!(
+                "Transfer your packages to another user before deleting your account: {}",
+                owned.join(", ")
+            )));
+        }
+
+        // transfers offered to the user
+        let mut package_ids = vec![];
+        for package_id in write
+            .open_multimap_table(USER_TRANSFER_TABLE)?
+            .get(user.id.as_str())?
+        {
+            package_ids.push(package_id?.value().to_string());
+        }
+        for package_id in package_ids {
+            remove_pending_transfer(&write, &package_id)?;
+        }
+        let mut package_claim_table = write.open_table(PACKAGE_CLAIM_TABLE)?;
+        package_claim_table.retain(|(_package_id, user_id), _| user_id != user.id)?;
 
+        revoke_user_sessions(&write, &user.id, None)?;
+        write
+            .open_table(USER_STORAGE_TABLE)?
+            .remove(user.id.as_str())?;
+        write
+            .open_table(USER_QUOTA_TABLE)?
+            .remove(user.id.as_str())?;
+        write.open_table(WEBHOOK_TABLE)?.remove(user.id.as_str())?;
+        write
+            .open_table(NOTIFICATION_QUEUE_TABLE)?
+            .retain(|_id, notification| notification.user_id != user.id)?;
+        watch::remove_user(&write, &user.id)?;
+        // signing keys are kept so signatures on published versions can still be checked
+        write
+            .open_table(USERNAME_USER_ID_TABLE)?
+            .remove(user.username.as_str())?;
+        write.open_table(USER_TABLE)?.remove(user.id.as_str())?;
+    }
+    write.commit()?;
     Ok(StatusCode::NO_CONTENT)
 }
 
 #[cfg(test)]
 mod tests {
     use crate::AUTH_TOKEN_TABLE;
-    use crate::tests::OnyxTest;
+    use crate::testing::OnyxTest;
     use anyhow::Result;
     use nanoid::nanoid;
-    use onyx_api::timestamp;
+    use onyx_api::prelude::*;
 
     #[tokio::test]
     async fn fail_auth_bad_token() -> Result<()> {
@@ -129,6 +258,132 @@ mod tests {
 
         let e = test.api.auth(expired_token).await.unwrap_err();
         assert_eq!(e.to_string(), "Expired token!");
+        assert_eq!(
+            e.downcast_ref::<ApiError>().map(|e| e.code),
+            Some(OnyxErrorCode::ExpiredToken)
+        );
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn should_update_user() -> Result<()> {
+        let test = OnyxTest::new().await?;
+        let (login, password) = test.signup(None).await?;
+        let other_session = test
+            .login(Some(LoginRequest {
+                username: login.user.username.clone(),
+                password: password.clone(),
+                ..Default::default()
+            }))
+            .await?;
+
+        let e = test
+            .api
+            .update_user(
+                &login.token,
+                UpdateUserRequest {
+                    password: "not the password".to_string(),
+                    new_username: Some(nanoid!()),
+                    new_password: None,
+                },
+            )
+            .await
+            .unwrap_err();
+        assert_eq!(
+            e.downcast_ref::<ApiError>().map(|e| e.code),
+            Some(OnyxErrorCode::InvalidCredentials)
+        );
+
+        let new_username = nanoid!();
+        let new_password = nanoid!();
+        let user = test
+            .api
+            .update_user(
+                &login.token,
+                UpdateUserRequest {
+                    password: password.clone(),
+                    new_username: Some(new_username.clone()),
+                    new_password: Some(new_password.clone()),
+                },
+            )
+            .await?;
+        assert_eq!(user.id, login.user.id);
+        assert_eq!(user.username, new_username);
+
+        // the session that changed the password is kept, others are revoked
+        assert_eq!(test.api.auth(login.token.clone()).await?.user, user);
+        assert!(test.api.auth(other_session.token).await.is_err());
+        assert!(
+            test.login(Some(LoginRequest {
+                username: login.user.username.clone(),
+                password,
+                ..Default::default()
+            }))
+            .await
+            .is_err()
+        );
+        let relogin = test
+            .login(Some(LoginRequest {
+                username: new_username,
+                password: new_password,
+                ..Default::default()
+            }))
+            .await?;
+        assert_eq!(relogin.user, user);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn should_delete_user() -> Result<()> {
+        let test = OnyxTest::new().await?;
+        let (owner, owner_password) = test.signup(None).await?;
+        let (recipient, recipient_password) = test.signup(None).await?;
+        let tarball = OnyxTest::create_test_tarball_named(None, Some("deleted_owner"), None)?;
+        test.publish(
+            Some(PublishData::new(tarball.1.to_string(), owner.token.clone())),
+            tarball,
+        )
+        .await?;
+        test.api
+            .request_transfer(&owner.token, "deleted_owner", &recipient.user.username)
+            .await?;
+
+        // the owner of a package has to transfer it first
+        let e = test
+            .api
+            .delete_user(
+                &owner.token,
+                DeleteUserRequest {
+                    password: owner_password,
+                },
+            )
+            .await
+            .unwrap_err();
+        assert_eq!(
+            e.downcast_ref::<ApiError>().map(|e| e.code),
+            Some(OnyxErrorCode::Conflict)
+        );
+
+        test.api
+            .delete_user(
+                &recipient.token,
+                DeleteUserRequest {
+                    password: recipient_password.clone(),
+                },
+            )
+            .await?;
+        assert!(test.api.auth(recipient.token).await.is_err());
+        assert!(
+            test.login(Some(LoginRequest {
+                username: recipient.user.username.clone(),
+                password: recipient_password,
+                ..Default::default()
+            }))
+            .await
+            .is_err()
+        );
+        // the transfer offered to them is gone
+        assert!(test.api.transfers(&owner.token).await?.outgoing.is_empty());
         Ok(())
     }
 }
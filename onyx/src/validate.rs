@@ -0,0 +1,802 @@
+use axum::Json;
+use axum::extract::FromRequest;
+use axum::extract::Request;
+use axum::extract::rejection::JsonRejection;
+use nanoid::nanoid;
+use serde::Deserialize;
+use serde::de::DeserializeOwned;
+
+use onyx_api::prelude::*;
+
+use super::OnyxError;
+
+pub const MIN_PASSWORD_LEN: usize = 10;
+pub const MAX_PASSWORD_LEN: usize = 256;
+// passwords made of only a few characters, e.g. "aaaaaaaaaaaa" or "abababababab"
+pub const MIN_PASSWORD_DISTINCT_CHARS: usize = 5;
+pub const MIN_USERNAME_LEN: usize = 3;
+pub const MAX_USERNAME_LEN: usize = 32;
+// tokens are issued as nanoids, anything longer than this can't be valid
+pub const MAX_TOKEN_LEN: usize = 64;
+pub const MAX_PACKAGE_NAME_LEN: usize = 64;
+pub const MAX_VERSION_NAME_LEN: usize = 64;
+pub const MAX_ARTIFACT_NAME_LEN: usize = 64;
+pub const MAX_REASON_LEN: usize = 2000;
+// hex length of an ed25519 public key
+pub const PUBLIC_KEY_LEN: usize = 64;
+// hex length of an ed25519 signature
+pub const SIGNATURE_LEN: usize = 128;
+pub const MAX_SOURCE_REPOSITORY_LEN: usize = 512;
+pub const MAX_WEBHOOK_URL_LEN: usize = 512;
+pub const MAX_LICENSE_LEN: usize = 256;
+pub const MAX_RELEASE_NOTES_LEN: usize = 64 * 1024;
+/// Limits of the metadata an owner can change without publishing, see `UpdatePackageRequest`.
+pub const MAX_DESCRIPTION_LEN: usize = 1024;
+pub const MAX_KEYWORDS: usize = 16;
+pub const MAX_KEYWORD_LEN: usize = 64;
+/// Most installs a client may report at once, see `InstallStatsRequest`.
+pub const MAX_REPORTED_INSTALLS: usize = 1000;
+/// Tags imported by a single request, each is cloned while the request waits.
+pub const MAX_IMPORTED_TAGS: usize = 100;
+/// Most packages loaded at once, see `BatchPackagesRequest`.
+pub const MAX_BATCH_PACKAGES: usize = 256;
+/// Limits of the `[package.metadata.nrpm]` table, which is indexed for search.
+pub const MAX_METADATA_KEYS: usize = 16;
+pub const MAX_METADATA_VALUES: usize = 16;
+pub const MAX_METADATA_LEN: usize = 64;
+
+/// A type that can check its own contents before a handler acts on it.
+pub trait Validate {
+    fn validate(&self, errors: &mut ValidationErrors);
+}
+
+/// Field level problems collected while validating a request.
+#[derive(Default)]
+pub struct ValidationErrors(Vec<FieldError>);
+
+impl ValidationErrors {
+    pub fn check(&mut self, field: &str, result: Result<(), String>) {
+        if let Err(message) = result {
+            self.0.push(FieldError {
+                field: field.to_string(),
+                message,
+            });
+        }
+    }
+
+    pub fn into_result(self) -> Result<(), OnyxError> {
+        if self.0.is_empty() {
+            Ok(())
+        } else {
+            Err(self.into())
+        }
+    }
+}
+
+impl From<ValidationErrors> for OnyxError {
+    fn from(value: ValidationErrors) -> Self {
+        let message = value
+            .0
+            .iter()
+            .map(|e| e.message.clone())
+            .collect::<Vec<_>>()
+            .join("; ");
+        OnyxError::new(OnyxErrorCode::ValidationFailed, &message)
+            .with_details(serde_json::json!({ "fields": value.0 }))
+    }
+}
+
+/// Validate a value, returning a `ValidationFailed` error if any field is invalid.
+pub fn validate(value: &impl Validate) -> Result<(), OnyxError> {
+    let mut errors = ValidationErrors::default();
+    value.validate(&mut errors);
+    errors.into_result()
+}
+
+/// A JSON body that has been deserialized and validated.
+pub struct ValidJson<T>(pub T);
+
+impl<T, S> FromRequest<S> for ValidJson<T>
+where
+    T: DeserializeOwned + Validate,
+    S: Send + Sync,
+{
+    type Rejection = OnyxError;
+
+    async fn from_request(req: Request, state: &S) -> Result<Self, Self::Rejection> {
+        let Json(value) =
+            Json::<T>::from_request(req, state)
+                .await
+                .map_err(|e: JsonRejection| {
+                    let code = match e.status().as_u16() {
+                        413 => OnyxErrorCode::PayloadTooLarge,
+                        415 => OnyxErrorCode::UnsupportedMediaType,
+                        422 => OnyxErrorCode::ValidationFailed,
+                        _ => OnyxErrorCode::BadRequest,
+                    };
+                    OnyxError::new(code, &e.body_text())
+                })?;
+        validate(&value)?;
+        Ok(Self(value))
+    }
+}
+
+/// A `LoginRequest` used to create an account. New usernames and passwords are held to
+/// stricter rules than the ones used to log in.
+#[derive(Deserialize)]
+#[serde(transparent)]
+pub struct SignupRequest(pub LoginRequest);
+
+impl Validate for SignupRequest {
+    fn validate(&self, errors: &mut ValidationErrors) {
+        errors.check("username", validate_username(&self.0.username));
+        errors.check(
+            "password",
+            validate_password(&self.0.password, &self.0.username),
+        );
+    }
+}
+
+impl Validate for LoginRequest {
+    fn validate(&self, errors: &mut ValidationErrors) {
+        errors.check(
+            "username",
+            validate_len("username", &self.username, 1, MAX_USERNAME_LEN),
+        );
+        errors.check(
+            "password",
+            validate_len("password", &self.password, 1, MAX_PASSWORD_LEN),
+        );
+    }
+}
+
+impl Validate for UpdateUserRequest {
+    fn validate(&self, errors: &mut ValidationErrors) {
+        errors.check(
+            "password",
+            validate_len("password", &self.password, 1, MAX_PASSWORD_LEN),
+        );
+        if let Some(new_username) = &self.new_username {
+            errors.check("new_username", validate_username(new_username));
+        }
+        // the new password is checked against the new username by the handler
+        if self.new_username.is_none() && self.new_password.is_none() {
+            errors.check(
+                "new_username",
+                Err("new_username or new_password is required".to_string()),
+            );
+        }
+    }
+}
+
+impl Validate for DeleteUserRequest {
+    fn validate(&self, errors: &mut ValidationErrors) {
+        errors.check(
+            "password",
+            validate_len("password", &self.password, 1, MAX_PASSWORD_LEN),
+        );
+    }
+}
+
+impl Validate for BatchPackagesRequest {
+    fn validate(&self, errors: &mut ValidationErrors) {
+        if self.packages.is_empty() || self.packages.len() > MAX_BATCH_PACKAGES {
+            errors.check(
+                "packages",
+                Err(format!(
+                    "must ask for between 1 and {MAX_BATCH_PACKAGES} packages"
+                )),
+            );
+        }
+        for package in &self.packages {
+            errors.check(
+                "packages.name",
+                validate_len("name", &package.name, 1, MAX_PACKAGE_NAME_LEN),
+            );
+            if let Some(requirement) = &package.requirement {
+                errors.check(
+                    "packages.requirement",
+                    semver::VersionReq::parse(requirement)
+                        .map(|_| ())
+                        .map_err(|e| format!("invalid requirement \"{requirement}\": {e}")),
+                );
+            }
+        }
+    }
+}
+
+impl Validate for InstallStatsRequest {
+    fn validate(&self, errors: &mut ValidationErrors) {
+        if self.installs.is_empty() || self.installs.len() > MAX_REPORTED_INSTALLS {
+            errors.check(
+                "installs",
+                Err(format!(
+                    "must report between 1 and {MAX_REPORTED_INSTALLS} installs"
+                )),
+            );
+        }
+        for install in &self.installs {
+            errors.check(
+                "installs.package_name",
+                validate_len(
+                    "package_name",
+                    &install.package_name,
+                    1,
+                    MAX_PACKAGE_NAME_LEN,
+                ),
+            );
+            errors.check(
+                "installs.version_name",
+                validate_len(
+                    "version_name",
+                    &install.version_name,
+                    1,
+                    MAX_VERSION_NAME_LEN,
+                ),
+            );
+        }
+    }
+}
+
+impl Validate for ImportRequest {
+    fn validate(&self, errors: &mut ValidationErrors) {
+        errors.check(
+            "username",
+            validate_len("username", &self.username, 1, MAX_USERNAME_LEN),
+        );
+        let tags = self
+            .repositories
+            .iter()
+            .map(|r| r.tags.len())
+            .sum::<usize>();
+        if tags == 0 || tags > MAX_IMPORTED_TAGS {
+            errors.check(
+                "repositories",
+                Err(format!(
+                    "must import between 1 and {MAX_IMPORTED_TAGS} tags"
+                )),
+            );
+        }
+        for repository in &self.repositories {
+            // other schemes would let the registry read its own files or reach internal hosts
+            let url = if repository.url.starts_with("https://") {
+                validate_len("url", &repository.url, 1, MAX_SOURCE_REPOSITORY_LEN)
+            } else {
+                Err("url must be an https url".to_string())
+            };
+            errors.check("repositories.url", url);
+            for tag in &repository.tags {
+                errors.check(
+                    "repositories.tags",
+                    validate_len("tag", tag, 1, MAX_VERSION_NAME_LEN),
+                );
+            }
+        }
+    }
+}
+
+impl Validate for SetWebhookRequest {
+    fn validate(&self, errors: &mut ValidationErrors) {
+        // only sent over https, `WebhookClient` checks the host is public before sending
+        let url = if self.url.starts_with("https://") {
+            validate_len("url", &self.url, 1, MAX_WEBHOOK_URL_LEN)
+        } else {
+            Err("url must be an https url".to_string())
+        };
+        errors.check("url", url);
+    }
+}
+
+impl Validate for AuthRequest {
+    fn validate(&self, errors: &mut ValidationErrors) {
+        match self {
+            AuthRequest::Token { token } => errors.check("token", validate_token(token)),
+            AuthRequest::Refresh { refresh_token } => {
+                errors.check("refresh_token", validate_token(refresh_token))
+            }
+        }
+    }
+}
+
+impl Validate for ProposeToken {
+    fn validate(&self, errors: &mut ValidationErrors) {
+        errors.check("token", validate_token(&self.token));
+        errors.check("proposed_token", validate_new_token(&self.proposed_token));
+        if let Some(proposed_refresh_token) = &self.proposed_refresh_token {
+            errors.check(
+                "proposed_refresh_token",
+                validate_new_token(proposed_refresh_token),
+            );
+        }
+    }
+}
+
+impl Validate for PublishData {
+    fn validate(&self, errors: &mut ValidationErrors) {
+        errors.check("hash", validate_hash(&self.hash));
+        errors.check("token", validate_token(&self.token));
+        match (&self.source_repository, &self.source_commit) {
+            (Some(repository), Some(commit)) => {
+                errors.check("source_repository", validate_source_repository(repository));
+                errors.check("source_commit", validate_source_commit(commit));
+            }
+            (Some(_), None) => errors.check(
+                "source_commit",
+                Err("source_commit is required with source_repository".to_string()),
+            ),
+            (None, Some(_)) => errors.check(
+                "source_repository",
+                Err("source_repository is required with source_commit".to_string()),
+            ),
+            (None, None) => {}
+        }
+        if let Some(release_notes) = &self.release_notes {
+            errors.check(
+                "release_notes",
+                validate_len("release notes", release_notes, 1, MAX_RELEASE_NOTES_LEN),
+            );
+        }
+    }
+}
+
+impl Validate for TransferPackageRequest {
+    fn validate(&self, errors: &mut ValidationErrors) {
+        errors.check("to_username", validate_username(&self.to_username));
+    }
+}
+
+impl Validate for UpdatePackageRequest {
+    fn validate(&self, errors: &mut ValidationErrors) {
+        if let Some(description) = &self.description {
+            errors.check(
+                "description",
+                validate_len("description", description, 0, MAX_DESCRIPTION_LEN),
+            );
+        }
+        if let Some(keywords) = &self.keywords {
+            let result = if keywords.len() > MAX_KEYWORDS {
+                Err(format!("at most {MAX_KEYWORDS} keywords are allowed"))
+            } else {
+                keywords
+                    .iter()
+                    .try_for_each(|k| validate_len("keyword", k, 1, MAX_KEYWORD_LEN))
+            };
+            errors.check("keywords", result);
+        }
+        if let Some(repository) = &self.repository
+            && !repository.is_empty()
+        {
+            let result = validate_len("repository", repository, 1, MAX_SOURCE_REPOSITORY_LEN)
+                .and_then(|_| match reqwest::Url::parse(repository) {
+                    Ok(url) if url.scheme() == "https" || url.scheme() == "http" => Ok(()),
+                    _ => Err("repository must be an http(s) url".to_string()),
+                });
+            errors.check("repository", result);
+        }
+        if let Some(deprecated) = &self.deprecated {
+            errors.check(
+                "deprecated",
+                validate_len("deprecated", deprecated, 0, MAX_REASON_LEN),
+            );
+        }
+        if self.description.is_none()
+            && self.keywords.is_none()
+            && self.repository.is_none()
+            && self.deprecated.is_none()
+        {
+            errors.check(
+                "description",
+                Err("description, keywords, repository or deprecated is required".to_string()),
+            );
+        }
+    }
+}
+
+impl Validate for AdminTransferRequest {
+    fn validate(&self, errors: &mut ValidationErrors) {
+        errors.check("to_username", validate_username(&self.to_username));
+        errors.check(
+            "reason",
+            validate_len("reason", &self.reason, 1, MAX_REASON_LEN),
+        );
+    }
+}
+
+impl Validate for ReportPackageRequest {
+    fn validate(&self, errors: &mut ValidationErrors) {
+        errors.check(
+            "details",
+            validate_len("details", &self.details, 1, MAX_REASON_LEN),
+        );
+        if let Some(version_name) = &self.version_name {
+            errors.check("version_name", validate_version_name(version_name));
+        }
+    }
+}
+
+impl Validate for ResolveReportRequest {
+    fn validate(&self, errors: &mut ValidationErrors) {
+        errors.check("note", validate_len("note", &self.note, 0, MAX_REASON_LEN));
+        if let Some(version_name) = &self.version_name {
+            errors.check("version_name", validate_version_name(version_name));
+        }
+    }
+}
+
+impl Validate for SetQuotaRequest {
+    // any number of bytes is a valid quota, 0 stops the user from storing anything
+    fn validate(&self, _errors: &mut ValidationErrors) {}
+}
+
+impl Validate for MarkNotificationsReadRequest {
+    fn validate(&self, _errors: &mut ValidationErrors) {}
+}
+
+impl Validate for DbCheckRequest {
+    fn validate(&self, _errors: &mut ValidationErrors) {}
+}
+
+impl Validate for AddKeyRequest {
+    fn validate(&self, errors: &mut ValidationErrors) {
+        errors.check(
+            "public_key",
+            validate_hex("public_key", &self.public_key, PUBLIC_KEY_LEN),
+        );
+        errors.check(
+            "signature",
+            validate_hex("signature", &self.signature, SIGNATURE_LEN),
+        );
+        match (&self.replaces, &self.replaces_signature) {
+            (Some(replaces), Some(replaces_signature)) => {
+                errors.check(
+                    "replaces",
+                    validate_hex("replaces", replaces, PUBLIC_KEY_LEN),
+                );
+                errors.check(
+                    "replaces_signature",
+                    validate_hex("replaces_signature", replaces_signature, SIGNATURE_LEN),
+                );
+            }
+            (Some(_), None) => errors.check(
+                "replaces_signature",
+                Err("replaces_signature is required when replacing a key".to_string()),
+            ),
+            (None, Some(_)) => errors.check(
+                "replaces",
+                Err("replaces is required with replaces_signature".to_string()),
+            ),
+            (None, None) => {}
+        }
+    }
+}
+
+impl Validate for UploadArtifactRequest {
+    fn validate(&self, errors: &mut ValidationErrors) {
+        errors.check("name", validate_artifact_name(&self.name));
+        if self.content.is_empty() {
+            errors.check("content", Err("content must not be empty".to_string()));
+        }
+    }
+}
+
+impl Validate for ClaimPackageRequest {
+    fn validate(&self, errors: &mut ValidationErrors) {
+        errors.check(
+            "reason",
+            validate_len("reason", &self.reason, 1, MAX_REASON_LEN),
+        );
+    }
+}
+
+fn validate_len(field: &str, value: &str, min: usize, max: usize) -> Result<(), String> {
+    if value.len() < min || value.len() > max {
+        Err(format!(
+            "{field} must be between {min} and {max} characters"
+        ))
+    } else {
+        Ok(())
+    }
+}
+
+/// Check that `value` is `len` lowercase hex characters.
+fn validate_hex(field: &str, value: &str, len: usize) -> Result<(), String> {
+    if value.len() == len && value.chars().all(|c| matches!(c, '0'..='9' | 'a'..='f')) {
+        Ok(())
+    } else {
+        Err(format!("{field} must be {len} lowercase hex characters"))
+    }
+}
+
+fn is_safe_nanoid(input: &str) -> bool {
+    input.chars().all(|c| nanoid::alphabet::SAFE.contains(&c))
+}
+
+// TODO: make this a constant or a lazy cell
+fn default_nanoid_len() -> usize {
+    nanoid!().len()
+}
+
+pub fn validate_username(username: &str) -> Result<(), String> {
+    validate_len("username", username, MIN_USERNAME_LEN, MAX_USERNAME_LEN)?;
+    if !username
+        .chars()
+        .all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-')
+    {
+        return Err("username may only contain letters, numbers, '_' and '-'".to_string());
+    }
+    Ok(())
+}
+
+// Passwords long enough to pass the length check that are still among the most common.
+const COMMON_PASSWORDS: &[&str] = &[
+    "1234567890",
+    "12345678910",
+    "123456789012",
+    "0123456789",
+    "1q2w3e4r5t",
+    "qwertyuiop",
+    "1qaz2wsx3edc",
+    "password12",
+    "password123",
+    "password1234",
+    "passw0rd123",
+    "qwerty1234",
+    "qwerty12345",
+    "qwerty123456",
+    "abcdefghij",
+    "abc1234567",
+    "iloveyou12",
+    "letmein123",
+    "welcome123",
+    "administrator",
+    "trustno1234",
+    "football123",
+    "baseball123",
+    "sunshine123",
+    "princess123",
+    "dragon1234",
+    "monkey1234",
+    "superman123",
+    "zaq12wsxcde",
+    "noirpackage",
+];
+
+/// Check a new password. Beyond length limits, passwords can't be common, can't contain the
+/// username, and must use a handful of distinct characters.
+pub fn validate_password(password: &str, username: &str) -> Result<(), String> {
+    if password.len() < MIN_PASSWORD_LEN {
+        return Err(format!(
+            "password must be more than {MIN_PASSWORD_LEN} characters"
+        ));
+    }
+    if password.len() > MAX_PASSWORD_LEN {
+        return Err(format!(
+            "password must be at most {MAX_PASSWORD_LEN} characters"
+        ));
+    }
+    let lowercase = password.to_lowercase();
+    if COMMON_PASSWORDS.contains(&lowercase.as_str()) {
+        return Err("password is too common".to_string());
+    }
+    if username.len() >= MIN_USERNAME_LEN && lowercase.contains(&username.to_lowercase()) {
+        return Err("password must not contain the username".to_string());
+    }
+    let distinct = password
+        .chars()
+        .collect::<std::collections::HashSet<_>>()
+        .len();
+    if distinct < MIN_PASSWORD_DISTINCT_CHARS {
+        return Err(format!(
+            "password must contain at least {MIN_PASSWORD_DISTINCT_CHARS} different characters"
+        ));
+    }
+    Ok(())
+}
+
+/// Check a token presented for authentication. Tokens that are well formed but unknown are
+/// rejected later as invalid.
+pub fn validate_token(token: &str) -> Result<(), String> {
+    if token.len() > MAX_TOKEN_LEN {
+        return Err(format!("token must be at most {MAX_TOKEN_LEN} characters"));
+    }
+    Ok(())
+}
+
+/// Check a token proposed by a client for activation.
+pub fn validate_new_token(token: &str) -> Result<(), String> {
+    if !is_safe_nanoid(token) {
+        return Err("Token contains invalid characters".to_string());
+    }
+    if token.len() != default_nanoid_len() {
+        return Err(format!("Token must be {} characters", default_nanoid_len()));
+    }
+    Ok(())
+}
+
+pub fn validate_hash(hash: &str) -> Result<(), String> {
+    if hash.len() != 64 || !hash.chars().all(|c| c.is_ascii_hexdigit()) {
+        return Err("hash must be a 64 character hex encoded blake3 hash".to_string());
+    }
+    Ok(())
+}
+
+pub fn validate_source_repository(repository: &str) -> Result<(), String> {
+    validate_len(
+        "source repository",
+        repository,
+        1,
+        MAX_SOURCE_REPOSITORY_LEN,
+    )?;
+    match reqwest::Url::parse(repository) {
+        Ok(url) if url.scheme() == "https" || url.scheme() == "http" => Ok(()),
+        _ => Err("source repository must be an http(s) url".to_string()),
+    }
+}
+
+/// Commits are identified by sha1 or, in repositories using it, sha256 hashes.
+pub fn validate_source_commit(commit: &str) -> Result<(), String> {
+    if !(commit.len() == 40 || commit.len() == 64) || !commit.chars().all(|c| c.is_ascii_hexdigit())
+    {
+        return Err("source commit must be a hex encoded commit hash".to_string());
+    }
+    Ok(())
+}
+
+/// Package names are used as path segments in git urls so they're restricted to url safe
+/// characters.
+pub fn validate_package_name(name: &str) -> Result<(), String> {
+    validate_len("package name", name, 1, MAX_PACKAGE_NAME_LEN)?;
+    if !name
+        .chars()
+        .all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-')
+    {
+        return Err("package name may only contain letters, numbers, '_' and '-'".to_string());
+    }
+    Ok(())
+}
+
+pub fn validate_artifact_name(name: &str) -> Result<(), String> {
+    validate_len("artifact name", name, 1, MAX_ARTIFACT_NAME_LEN)?;
+    if !name
+        .chars()
+        .all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-')
+    {
+        return Err("artifact name may only contain letters, numbers, '_' and '-'".to_string());
+    }
+    Ok(())
+}
+
+pub fn validate_version_name(version: &str) -> Result<(), String> {
+    validate_len("version", version, 1, MAX_VERSION_NAME_LEN)?;
+    if !version
+        .chars()
+        .all(|c| c.is_ascii_alphanumeric() || c == '.' || c == '-' || c == '+')
+    {
+        return Err("version may only contain letters, numbers, '.', '-' and '+'".to_string());
+    }
+    Ok(())
+}
+
+/// The name a published version is stored as: a semver version like `1.2.3` or
+/// `1.0.0-rc.1+build`, with a leading `v` removed. Registries with `legacy_version_names` set
+/// accept any other version name `validate_version_name` allows, unchanged.
+pub fn normalize_version_name(version: &str, legacy: bool) -> Result<String, String> {
+    validate_version_name(version)?;
+    let stripped = version.strip_prefix('v').unwrap_or(version);
+    match semver::Version::parse(stripped) {
+        Ok(_) => Ok(stripped.to_string()),
+        Err(_) if legacy => Ok(version.to_string()),
+        Err(e) => Err(format!(
+            "version must be a semver version like 1.2.3, {version:?} is not: {e}"
+        )),
+    }
+}
+
+/// Licenses are SPDX expressions of known license identifiers, e.g. `MIT OR Apache-2.0`.
+pub fn validate_license(license: &str) -> Result<(), String> {
+    validate_len("license", license, 1, MAX_LICENSE_LEN)?;
+    spdx::Expression::parse(license)
+        .map(|_| ())
+        .map_err(|e| format!("license must be a valid SPDX expression: {}", e.reason))
+}
+
+/// The `[package.metadata.nrpm]` table is searched by its keys as query parameters and its
+/// values as strings, so both are short and keys are url safe. `q` is the search text.
+pub fn validate_nrpm_metadata(package: &nargo_parse::Package) -> Result<(), String> {
+    let metadata = package.nrpm_metadata().map_err(|e| e.to_string())?;
+    if metadata.len() > MAX_METADATA_KEYS {
+        return Err(format!(
+            "package.metadata.nrpm may have at most {MAX_METADATA_KEYS} keys"
+        ));
+    }
+    for (key, values) in &metadata {
+        validate_len("metadata key", key, 1, MAX_METADATA_LEN)?;
+        if key == "q"
+            || !key
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-')
+        {
+            return Err(format!(
+                "metadata key \"{key}\" may only contain letters, numbers, '_' and '-', and may not be \"q\""
+            ));
+        }
+        if values.len() > MAX_METADATA_VALUES {
+            return Err(format!(
+                "metadata key \"{key}\" may have at most {MAX_METADATA_VALUES} values"
+            ));
+        }
+        for value in values {
+            validate_len("metadata value", value, 1, MAX_METADATA_LEN)?;
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn should_collect_field_errors() {
+        let mut errors = ValidationErrors::default();
+        SignupRequest(LoginRequest {
+            username: "a".to_string(),
+            password: "short".to_string(),
+            ..Default::default()
+        })
+        .validate(&mut errors);
+        let fields = errors
+            .0
+            .iter()
+            .map(|e| e.field.as_str())
+            .collect::<Vec<_>>();
+        assert_eq!(fields, vec!["username", "password"]);
+    }
+
+    #[test]
+    fn should_validate_names() {
+        assert!(validate_username("alice_1-2").is_ok());
+        assert!(validate_username("al ice").is_err());
+        assert!(validate_username(&"a".repeat(MAX_USERNAME_LEN + 1)).is_err());
+        assert!(validate_package_name("poseidon2").is_ok());
+        assert!(validate_package_name("../etc").is_err());
+        assert!(validate_package_name("").is_err());
+        assert!(validate_version_name("1.0.0-rc.1+build").is_ok());
+        assert!(validate_version_name("1.0.0/../").is_err());
+        assert_eq!(
+            normalize_version_name("1.0.0-rc.1+build", false).unwrap(),
+            "1.0.0-rc.1+build"
+        );
+        assert_eq!(normalize_version_name("v1.2.3", false).unwrap(), "1.2.3");
+        assert!(normalize_version_name("1.2", false).is_err());
+        assert!(normalize_version_name("vnext", false).is_err());
+        assert_eq!(normalize_version_name("vnext", true).unwrap(), "vnext");
+        assert!(normalize_version_name("1.0.0/../", true).is_err());
+    }
+
+    #[test]
+    fn should_validate_password_strength() {
+        assert!(validate_password("correct horse battery staple", "alice").is_ok());
+        assert!(validate_password("Password123", "alice").is_err());
+        assert!(validate_password("alice_is_the_best", "Alice").is_err());
+        assert!(validate_password("abababababab", "alice").is_err());
+        assert!(validate_password("short", "alice").is_err());
+    }
+
+    #[test]
+    fn should_validate_hash() {
+        assert!(validate_hash(&blake3::hash(b"test").to_string()).is_ok());
+        assert!(validate_hash("abcd").is_err());
+        assert!(validate_hash(&"z".repeat(64)).is_err());
+    }
+
+    #[test]
+    fn should_validate_license() {
+        assert!(validate_license("MIT").is_ok());
+        assert!(validate_license("MIT OR Apache-2.0").is_ok());
+        assert!(validate_license("(MIT AND BSD-3-Clause) OR GPL-3.0-or-later").is_ok());
+        assert!(validate_license("").is_err());
+        assert!(validate_license("MIT OR").is_err());
+        assert!(validate_license("Not A License").is_err());
+    }
+}
@@ -0,0 +1,489 @@
+use std::collections::BTreeSet;
+
+use anyhow::Result;
+use axum::extract::Path;
+use axum::extract::Query;
+use axum::extract::State;
+use axum::http::StatusCode;
+use axum::response::Json as ResponseJson;
+use redb::ReadableMultimapTable;
+use redb::ReadableTable;
+use serde::Deserialize;
+
+use onyx_api::prelude::*;
+
+use super::OnyxError;
+use super::OnyxState;
+use super::db::WriteTxn;
+use super::session::AuthSession;
+use super::validate::ValidJson;
+
+/// Notifications kept per user, older ones are removed as new ones arrive.
+const MAX_NOTIFICATIONS: usize = 500;
+const DEFAULT_PAGE_SIZE: usize = 50;
+/// Seconds between the digests of a user.
+const DIGEST_INTERVAL: u64 = 24 * 60 * 60;
+
+fn package_id(write: &WriteTxn, package_name: &str) -> Result<String, OnyxError> {
+    match write.open_table(PACKAGE_NAME_TABLE)?.get(package_name)? {
+        Some(package_id) => Ok(package_id.value().to_string()),
+        None => Err(OnyxError::not_found("Package not found")),
+    }
+}
+
+/// Notify the watchers of `package` that `version` was published, other than its author.
+pub fn notify(
+    write: &WriteTxn,
+    package: &PackageModel,
+    version: &PackageVersionModel,
+    seq: u64,
+) -> Result<(), OnyxError> {
+    let package_watcher_table = write.open_multimap_table(PACKAGE_WATCHER_TABLE)?;
+    let mut user_notification_table = write.open_table(USER_NOTIFICATION_TABLE)?;
+    for user_id in package_watcher_table.get(package.id.as_str())? {
+        let user_id = user_id?;
+        let user_id = user_id.value();
+        if user_id == version.author_id {
+            continue;
+        }
+        user_notification_table.insert(
+            (user_id, seq),
+            ChangelogEntry {
+                seq,
+                package_name: package.name.clone(),
+                version_name: version.name.clone(),
+                version_id: version.id.clone(),
+                author_id: version.author_id.clone(),
+                published_at: version.created_at,
+            },
+        )?;
+        let mut oldest = vec![];
+        for entry in user_notification_table
+            .range((user_id, 0)..=(user_id, u64::MAX))?
+            .rev()
+            .skip(MAX_NOTIFICATIONS)
+        {
+            oldest.push(entry?.0.value().1);
+        }
+        for seq in oldest {
+            user_notification_table.remove((user_id, seq))?;
+        }
+    }
+    Ok(())
+}
+
+/// Stop every user watching a deleted package.
+pub fn remove_package(write: &WriteTxn, package_id: &str) -> Result<(), OnyxError> {
+    let mut package_watcher_table = write.open_multimap_table(PACKAGE_WATCHER_TABLE)?;
+    let mut user_watch_table = write.open_multimap_table(USER_WATCH_TABLE)?;
+    for user_id in package_watcher_table.remove_all(package_id)? {
+        user_watch_table.remove(user_id?.value(), package_id)?;
+    }
+    Ok(())
+}
+
+/// Remove the watches and notifications of a deleted user.
+pub fn remove_user(write: &WriteTxn, user_id: &str) -> Result<(), OnyxError> {
+    let mut package_watcher_table = write.open_multimap_table(PACKAGE_WATCHER_TABLE)?;
+    let mut user_watch_table = write.open_multimap_table(USER_WATCH_TABLE)?;
+    for package_id in user_watch_table.remove_all(user_id)? {
+        package_watcher_table.remove(package_id?.value(), user_id)?;
+    }
+    write
+        .open_table(USER_NOTIFICATION_TABLE)?
+        .retain(|(notified_id, _), _| notified_id != user_id)?;
+    write.open_table(NOTIFICATION_READ_TABLE)?.remove(user_id)?;
+    write.open_table(DIGEST_TABLE)?.remove(user_id)?;
+    Ok(())
+}
+
+/// Notify the authenticated user of the versions a package publishes.
+pub async fn watch_package(
+    State(state): State<OnyxState>,
+    session: AuthSession,
+    Path(package_name): Path<String>,
+) -> Result<StatusCode, OnyxError> {
+    let write = state.db.begin_write()?;
+    let package_id = package_id(&write, &package_name)?;
+    write
+        .open_multimap_table(PACKAGE_WATCHER_TABLE)?
+        .insert(package_id.as_str(), session.user_id.as_str())?;
+    write
+        .open_multimap_table(USER_WATCH_TABLE)?
+        .insert(session.user_id.as_str(), package_id.as_str())?;
+    write.commit()?;
+    Ok(StatusCode::NO_CONTENT)
+}
+
+/// Stop notifying the authenticated user of a package. Notifications already made are kept.
+pub async fn unwatch_package(
+    State(state): State<OnyxState>,
+    session: AuthSession,
+    Path(package_name): Path<String>,
+) -> Result<StatusCode, OnyxError> {
+    let write = state.db.begin_write()?;
+    let package_id = package_id(&write, &package_name)?;
+    if !write
+        .open_multimap_table(USER_WATCH_TABLE)?
+        .remove(session.user_id.as_str(), package_id.as_str())?
+    {
+        return Err(OnyxError::not_found("Package is not watched"));
+    }
+    write
+        .open_multimap_table(PACKAGE_WATCHER_TABLE)?
+        .remove(package_id.as_str(), session.user_id.as_str())?;
+    write.commit()?;
+    Ok(StatusCode::NO_CONTENT)
+}
+
+/// Names of the packages the authenticated user watches, sorted.
+pub async fn list_watches(
+    State(state): State<OnyxState>,
+    session: AuthSession,
+) -> Result<ResponseJson<Vec<String>>, OnyxError> {
+    let read = state.db.begin_read()?;
+    let user_watch_table = read.open_multimap_table(USER_WATCH_TABLE)?;
+    let package_table = read.open_table(PACKAGE_TABLE)?;
+    let mut names = BTreeSet::new();
+    for package_id in user_watch_table.get(session.user_id.as_str())? {
+        if let Some(package) = package_table.get(package_id?.value())? {
+            names.insert(package.value().name);
+        }
+    }
+    Ok(ResponseJson(names.into_iter().collect()))
+}
+
+#[derive(Deserialize)]
+pub struct NotificationsQuery {
+    limit: Option<usize>,
+}
+
+/// The newest notifications of the authenticated user.
+pub async fn notifications(
+    State(state): State<OnyxState>,
+    session: AuthSession,
+    Query(query): Query<NotificationsQuery>,
+) -> Result<ResponseJson<NotificationsResponse>, OnyxError> {
+    let limit = query
+        .limit
+        .unwrap_or(DEFAULT_PAGE_SIZE)
+        .min(MAX_NOTIFICATIONS);
+    let user_id = session.user_id.as_str();
+    let read = state.db.begin_read()?;
+    let user_notification_table = read.open_table(USER_NOTIFICATION_TABLE)?;
+    let read_seq = read
+        .open_table(NOTIFICATION_READ_TABLE)?
+        .get(user_id)?
+        .map(|seq| seq.value())
+        .unwrap_or(0);
+    let mut notifications = vec![];
+    let mut unread = 0;
+    for entry in user_notification_table
+        .range((user_id, 0)..=(user_id, u64::MAX))?
+        .rev()
+    {
+        let (key, notification) = entry?;
+        if key.value().1 <= read_seq && notifications.len() >= limit {
+            break;
+        }
+        if key.value().1 > read_seq {
+            unread += 1;
+        }
+        if notifications.len() < limit {
+            notifications.push(notification.value());
+        }
+    }
+    let digest = read.open_table(DIGEST_TABLE)?.get(user_id)?.is_some();
+    Ok(ResponseJson(NotificationsResponse {
+        notifications,
+        read_seq,
+        unread,
+        digest,
+    }))
+}
+
+/// Mark the authenticated user's notifications up to a sequence number read.
+pub async fn mark_notifications_read(
+    State(state): State<OnyxState>,
+    session: AuthSession,
+    ValidJson(payload): ValidJson<MarkNotificationsReadRequest>,
+) -> Result<StatusCode, OnyxError> {
+    let write = state.db.begin_write()?;
+    {
+        let mut notification_read_table = write.open_table(NOTIFICATION_READ_TABLE)?;
+        let read_seq = notification_read_table
+            .get(session.user_id.as_str())?
+            .map(|seq| seq.value())
+            .unwrap_or(0);
+        notification_read_table.insert(session.user_id.as_str(), read_seq.max(payload.seq))?;
+    }
+    write.commit()?;
+    Ok(StatusCode::NO_CONTENT)
+}
+
+/// Send the authenticated user a daily digest of new notifications through their webhook.
+/// Notifications made before it's enabled aren't sent.
+pub async fn enable_digest(
+    State(state): State<OnyxState>,
+    session: AuthSession,
+) -> Result<StatusCode, OnyxError> {
+    let user_id = session.user_id.as_str();
+    let write = state.db.begin_write()?;
+    if write.open_table(WEBHOOK_TABLE)?.get(user_id)?.is_none() {
+        return Err(OnyxError::bad_request(
+            "Digests are sent to your webhook, set one first",
+        ));
+    }
+    let newest = write
+        .open_table(USER_NOTIFICATION_TABLE)?
+        .range((user_id, 0)..=(user_id, u64::MAX))?
+        .next_back()
+        .transpose()?
+        .map(|(key, _)| key.value().1)
+        .unwrap_or(0);
+    {
+        let mut digest_table = write.open_table(DIGEST_TABLE)?;
+        if digest_table.get(user_id)?.is_none() {
+            digest_table.insert(user_id, (newest, 0))?;
+        }
+    }
+    write.commit()?;
+    Ok(StatusCode::NO_CONTENT)
+}
+
+/// Stop sending the authenticated user digests.
+pub async fn disable_digest(
+    State(state): State<OnyxState>,
+    session: AuthSession,
+) -> Result<StatusCode, OnyxError> {
+    let write = state.db.begin_write()?;
+    if write
+        .open_table(DIGEST_TABLE)?
+        .remove(session.user_id.as_str())?
+        .is_none()
+    {
+        return Err(OnyxError::not_found("Digests aren't enabled"));
+    }
+    write.commit()?;
+    Ok(StatusCode::NO_CONTENT)
+}
+
+/// Send a digest to each user who has new notifications and wasn't sent one in the last
+/// `DIGEST_INTERVAL` seconds. A digest that fails is tried again on the next run. Returns the
+/// number sent.
+pub async fn send_digests(state: &OnyxState) -> Result<usize> {
+    let now = timestamp();
+    let due = {
+        let read = state.db.begin_read()?;
+        let digest_table = read.open_table(DIGEST_TABLE)?;
+        let user_notification_table = read.open_table(USER_NOTIFICATION_TABLE)?;
+        let webhook_table = read.open_table(WEBHOOK_TABLE)?;
+        let user_table = read.open_table(USER_TABLE)?;
+        let mut due = vec![];
+        for entry in digest_table.iter()? {
+            let (user_id, digest) = entry?;
+            let user_id = user_id.value();
+            let (sent_seq, sent_at) = digest.value();
+            if sent_at + DIGEST_INTERVAL > now {
+                continue;
+            }
+            let Some(webhook) = webhook_table.get(user_id)? else {
+                continue;
+            };
+            let Some(user) = user_table.get(user_id)? else {
+                continue;
+            };
+            let mut notifications = vec![];
+            for entry in
+                user_notification_table.range((user_id, sent_seq + 1)..=(user_id, u64::MAX))?
+            {
+                notifications.push(entry?.1.value());
+            }
+            if notifications.is_empty() {
+                continue;
+            }
+            due.push((
+                user_id.to_string(),
+                webhook.value(),
+                NotificationDigest {
+                    username: user.value().username,
+                    notifications,
+                },
+            ));
+        }
+        due
+    };
+    let mut sent = 0;
+    for (user_id, webhook, digest) in due {
+        if let Err(e) = state.webhooks.send(&webhook, "digest", &digest).await {
+            log::warn!("Failed to send digest to user {user_id}: {e:?}");
+            continue;
+        }
+        let newest = digest.notifications.last().map(|n| n.seq).unwrap_or(0);
+        let write = state.db.begin_write()?;
+        {
+            let mut digest_table = write.open_table(DIGEST_TABLE)?;
+            // the user disabled digests while this one was sent
+            if digest_table.get(user_id.as_str())?.is_some() {
+                digest_table.insert(user_id.as_str(), (newest, now))?;
+            }
+        }
+        write.commit()?;
+        sent += 1;
+    }
+    Ok(sent)
+}
+
+#[cfg(test)]
+mod tests {
+    use anyhow::Result;
+    use onyx_api::prelude::*;
+
+    use super::send_digests;
+    use crate::testing::OnyxTest;
+    use crate::testing::webhook_receiver;
+
+    #[tokio::test]
+    async fn should_notify_watchers_of_publishes() -> Result<()> {
+        let test = OnyxTest::new().await?;
+        let (author, _password) = test.signup(None).await?;
+        let (watcher, _password) = test.signup(None).await?;
+        let publish = |version: &'static str| {
+            let token = author.token.clone();
+            let test = &test;
+            async move {
+                let tarball =
+                    OnyxTest::create_test_tarball_named(None, Some("watched"), Some(version))?;
+                test.publish(
+                    Some(PublishData::new(tarball.1.to_string(), token)),
+                    tarball,
+                )
+                .await
+            }
+        };
+        publish("0.1.0").await?;
+
+        assert!(
+            test.api
+                .watch_package(&watcher.token, "missing")
+                .await
+                .is_err()
+        );
+        test.api.watch_package(&watcher.token, "watched").await?;
+        // the author isn't notified of their own publishes
+        test.api.watch_package(&author.token, "watched").await?;
+        assert_eq!(test.api.watches(&watcher.token).await?, vec!["watched"]);
+        publish("0.2.0").await?;
+        publish("0.3.0").await?;
+
+        let notifications = test.api.notifications(&watcher.token).await?;
+        assert_eq!(
+            notifications
+                .notifications
+                .iter()
+                .map(|n| n.version_name.as_str())
+                .collect::<Vec<_>>(),
+            vec!["0.3.0", "0.2.0"]
+        );
+        assert_eq!(notifications.unread, 2);
+        assert!(!notifications.digest);
+        assert!(
+            test.api
+                .notifications(&author.token)
+                .await?
+                .notifications
+                .is_empty()
+        );
+
+        let seq = notifications.notifications[1].seq;
+        test.api
+            .mark_notifications_read(&watcher.token, seq)
+            .await?;
+        let notifications = test.api.notifications(&watcher.token).await?;
+        assert_eq!(notifications.read_seq, seq);
+        assert_eq!(notifications.unread, 1);
+
+        test.api.unwatch_package(&watcher.token, "watched").await?;
+        assert!(test.api.watches(&watcher.token).await?.is_empty());
+        publish("0.4.0").await?;
+        assert_eq!(test.api.notifications(&watcher.token).await?.unread, 1);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn should_send_daily_digest_to_webhook() -> Result<()> {
+        let test = OnyxTest::new().await?;
+        let (author, _password) = test.signup(None).await?;
+        let (watcher, _password) = test.signup(None).await?;
+        let tarball = OnyxTest::create_test_tarball_named(None, Some("digested"), Some("0.1.0"))?;
+        test.publish(
+            Some(PublishData::new(
+                tarball.1.to_string(),
+                author.token.clone(),
+            )),
+            tarball,
+        )
+        .await?;
+        test.api.watch_package(&watcher.token, "digested").await?;
+
+        // digests go to the webhook
+        assert!(test.api.enable_digest(&watcher.token).await.is_err());
+        let webhook = test
+            .api
+            .set_webhook(
+                &watcher.token,
+                &SetWebhookRequest {
+                    url: "https://example.com/hook".to_string(),
+                },
+            )
+            .await?;
+        let (url, received) = webhook_receiver().await?;
+        {
+            let write = test.db().begin_write()?;
+            write.open_table(WEBHOOK_TABLE)?.insert(
+                watcher.user.id.as_str(),
+                WebhookModel {
+                    url,
+                    ..webhook.clone()
+                },
+            )?;
+            write.commit()?;
+        }
+        test.api.enable_digest(&watcher.token).await?;
+        assert!(test.api.notifications(&watcher.token).await?.digest);
+        assert_eq!(send_digests(&test.state).await?, 0);
+
+        let tarball = OnyxTest::create_test_tarball_named(None, Some("digested"), Some("0.2.0"))?;
+        test.publish(
+            Some(PublishData::new(
+                tarball.1.to_string(),
+                author.token.clone(),
+            )),
+            tarball,
+        )
+        .await?;
+        assert_eq!(send_digests(&test.state).await?, 1);
+        let (event, _signature, body) = received.lock().unwrap()[0].clone();
+        assert_eq!(event, "digest");
+        let digest = serde_json::from_slice::<NotificationDigest>(&body)?;
+        assert_eq!(digest.username, watcher.user.username);
+        assert_eq!(digest.notifications.len(), 1);
+        assert_eq!(digest.notifications[0].version_name, "0.2.0");
+
+        // at most one digest a day
+        let tarball = OnyxTest::create_test_tarball_named(None, Some("digested"), Some("0.3.0"))?;
+        test.publish(
+            Some(PublishData::new(
+                tarball.1.to_string(),
+                author.token.clone(),
+            )),
+            tarball,
+        )
+        .await?;
+        assert_eq!(send_digests(&test.state).await?, 0);
+
+        test.api.disable_digest(&watcher.token).await?;
+        assert!(!test.api.notifications(&watcher.token).await?.digest);
+        Ok(())
+    }
+}
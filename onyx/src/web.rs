@@ -0,0 +1,123 @@
+use axum::http::Method;
+use axum::http::StatusCode;
+use axum::http::Uri;
+use axum::http::header::CACHE_CONTROL;
+use axum::http::header::CONTENT_TYPE;
+use axum::response::IntoResponse;
+use axum::response::Response;
+
+/// The web app, built with `dx build --release -p web` before onyx is built with the `web`
+/// feature.
+#[cfg(feature = "web")]
+#[derive(rust_embed::Embed)]
+#[folder = "../target/dx/web/release/web/public"]
+struct Assets;
+
+/// The file of the web app at `path`. Assets have a hash in their name, so they're cached
+/// forever.
+#[cfg(feature = "web")]
+fn asset(path: &str) -> Option<Response> {
+    let file = Assets::get(path)?;
+    let cache_control = if path.starts_with("assets/") {
+        "public, max-age=31536000, immutable"
+    } else {
+        "no-cache"
+    };
+    Some(
+        (
+            [
+                (CONTENT_TYPE, file.metadata.mimetype()),
+                (CACHE_CONTROL, cache_control),
+            ],
+            file.data,
+        )
+            .into_response(),
+    )
+}
+
+#[cfg(not(feature = "web"))]
+fn asset(_path: &str) -> Option<Response> {
+    None
+}
+
+/// The page of the web app, if it's embedded.
+pub fn index() -> Option<Response> {
+    asset("index.html")
+}
+
+/// The service worker of the web app, which caches tarballs and package metadata in the
+/// browser. It's served from the root so it can see every request of the app, and isn't
+/// cached so changes to it reach browsers.
+pub async fn service_worker() -> Response {
+    (
+        [
+            (CONTENT_TYPE, "text/javascript"),
+            (CACHE_CONTROL, "no-cache"),
+        ],
+        include_str!("../../web/sw.js"),
+    )
+        .into_response()
+}
+
+/// A file of the web app, or its page for any other path so the app routes it in the browser.
+/// Api paths, other methods, and registries built without the web app get a 404.
+pub async fn static_file(method: Method, uri: Uri) -> Response {
+    let path = uri.path().trim_start_matches('/');
+    if method != Method::GET || path.starts_with("v0/") {
+        return StatusCode::NOT_FOUND.into_response();
+    }
+    asset(path)
+        .or_else(index)
+        .unwrap_or_else(|| StatusCode::NOT_FOUND.into_response())
+}
+
+#[cfg(test)]
+mod tests {
+    use anyhow::Result;
+    use reqwest::StatusCode;
+
+    use crate::testing::OnyxTest;
+
+    #[tokio::test]
+    async fn should_route_other_paths_to_web_app() -> Result<()> {
+        let test = OnyxTest::new().await?;
+        let client = reqwest::Client::new();
+
+        let response = client.get(format!("{}/settings/", test.url)).send().await?;
+        if cfg!(feature = "web") {
+            assert_eq!(response.status(), StatusCode::OK);
+            assert!(response.text().await?.contains("<html"));
+        } else {
+            assert_eq!(response.status(), StatusCode::NOT_FOUND);
+        }
+
+        // api paths and other methods are never the web app
+        let response = client
+            .get(format!("{}/v0/missing", test.url))
+            .send()
+            .await?;
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+        let response = client
+            .post(format!("{}/some/page", test.url))
+            .send()
+            .await?;
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn should_serve_service_worker() -> Result<()> {
+        let test = OnyxTest::new().await?;
+        let response = reqwest::get(format!("{}/sw.js", test.url)).await?;
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(response.headers()["content-type"], "text/javascript");
+        assert_eq!(response.headers()["cache-control"], "no-cache");
+        assert!(
+            response
+                .text()
+                .await?
+                .contains("addEventListener(\"fetch\"")
+        );
+        Ok(())
+    }
+}
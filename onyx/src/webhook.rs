@@ -0,0 +1,534 @@
+use std::net::IpAddr;
+use std::net::SocketAddr;
+use std::sync::Arc;
+use std::time::Duration;
+
+use anyhow::Result;
+use axum::extract::State;
+use axum::http::HeaderMap;
+use axum::http::StatusCode;
+use axum::response::Json as ResponseJson;
+use nanoid::nanoid;
+use redb::ReadableTable;
+use ring::hmac;
+use serde::Serialize;
+
+use onyx_api::prelude::*;
+
+use super::Config;
+use super::OnyxError;
+use super::OnyxState;
+use super::db::WriteTxn;
+use super::session::AuthSession;
+use super::session::TOKEN_PREFIX_LEN;
+use super::validate::ValidJson;
+use super::validate::ValidationErrors;
+
+/// Notifications sent per run of the delivery job.
+const DELIVERIES_PER_RUN: usize = 50;
+/// Failed deliveries after which a notification is dropped.
+const MAX_DELIVERY_ATTEMPTS: u32 = 5;
+/// Seconds a webhook has to respond.
+const DELIVERY_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// The address a request came from: the first `X-Forwarded-For` entry if the registry is
+/// configured to trust it, otherwise the peer.
+pub fn client_ip(config: &Config, headers: &HeaderMap, peer: SocketAddr) -> String {
+    if config.trust_forwarded_for
+        && let Some(ip) = headers
+            .get("x-forwarded-for")
+            .and_then(|v| v.to_str().ok())
+            .and_then(|v| v.split(',').next())
+            .map(str::trim)
+            .filter(|ip| !ip.is_empty())
+    {
+        return ip.to_string();
+    }
+    peer.ip().to_string()
+}
+
+/// Queue a notification that `token` published a version, unless it's the token of a browser
+/// session, which the user is watching. Nothing is queued if the user has no webhook.
+pub fn enqueue_publish(
+    write: &WriteTxn,
+    user_id: &str,
+    token: &str,
+    package_name: &str,
+    version_name: &str,
+    hash: &blake3::Hash,
+    ip: Option<String>,
+) -> Result<(), OnyxError> {
+    let notification =
+        publish_notification(write, user_id, token, package_name, version_name, hash, ip)?;
+    if let Some(notification) = notification {
+        enqueue(write, user_id, notification)?;
+    }
+    Ok(())
+}
+
+/// The notification `enqueue_publish` would queue, for a version published later, see
+/// `scan`.
+pub fn publish_notification(
+    write: &WriteTxn,
+    user_id: &str,
+    token: &str,
+    package_name: &str,
+    version_name: &str,
+    hash: &blake3::Hash,
+    ip: Option<String>,
+) -> Result<Option<PublishNotification>, OnyxError> {
+    if write.open_table(WEBHOOK_TABLE)?.get(user_id)?.is_none() {
+        return Ok(None);
+    }
+    let source = write
+        .open_table(SESSION_TABLE)?
+        .get(token)?
+        .map(|session| session.value().source);
+    if matches!(source, Some(SessionSource::Login | SessionSource::Signup)) {
+        return Ok(None);
+    }
+    Ok(Some(PublishNotification {
+        package_name: package_name.to_string(),
+        version_name: version_name.to_string(),
+        hash: hash.to_string(),
+        token_prefix: token.chars().take(TOKEN_PREFIX_LEN).collect(),
+        source,
+        ip,
+        published_at: timestamp(),
+    }))
+}
+
+/// Queue `notification` to be delivered to the webhook of `user_id`.
+pub fn enqueue(
+    write: &WriteTxn,
+    user_id: &str,
+    notification: PublishNotification,
+) -> Result<(), OnyxError> {
+    let notification = NotificationModel {
+        user_id: user_id.to_string(),
+        notification,
+        attempts: 0,
+    };
+    let mut notification_queue_table = write.open_table(NOTIFICATION_QUEUE_TABLE)?;
+    notification_queue_table.insert(nanoid!().as_str(), notification)?;
+    Ok(())
+}
+
+/// Hex encoded HMAC-SHA256 of `body` keyed with `secret`, see `WEBHOOK_SIGNATURE_HEADER`.
+pub fn signature(secret: &str, body: &[u8]) -> String {
+    let key = hmac::Key::new(hmac::HMAC_SHA256, secret.as_bytes());
+    hex::encode(hmac::sign(&key, body).as_ref())
+}
+
+/// Sends webhook requests. Redirects aren't followed, and unless the registry allows private
+/// webhooks, a webhook's host must only resolve to public addresses. Hosts are resolved by
+/// `PublicResolver` when connecting, so the request goes to the addresses that were checked
+/// even if the host resolves elsewhere by then.
+#[derive(Clone)]
+pub struct WebhookClient {
+    client: reqwest::Client,
+    allow_private: bool,
+}
+
+impl WebhookClient {
+    pub fn from_config(config: &Config) -> Result<Self> {
+        let mut builder = reqwest::Client::builder()
+            .timeout(DELIVERY_TIMEOUT)
+            .redirect(reqwest::redirect::Policy::none());
+        if !config.allow_private_webhooks {
+            builder = builder.dns_resolver(Arc::new(PublicResolver));
+        }
+        Ok(Self {
+            client: builder.build()?,
+            allow_private: config.allow_private_webhooks,
+        })
+    }
+
+    /// Check that the host of `url` is a public address, or only resolves to public
+    /// addresses.
+    pub async fn check(&self, url: &str) -> Result<()> {
+        if self.allow_private {
+            return Ok(());
+        }
+        let url = reqwest::Url::parse(url)?;
+        let Some(host) = url.host_str() else {
+            anyhow::bail!("url has no host");
+        };
+        // ipv6 hosts are bracketed in urls
+        match host.trim_matches(['[', ']']).parse::<IpAddr>() {
+            Ok(ip) => check_public(ip),
+            Err(_) => public_addrs(host).await.map(|_| ()),
+        }
+    }
+
+    /// POST `body` as json to `webhook`, see `WEBHOOK_EVENT_HEADER` for `event`.
+    pub async fn send(
+        &self,
+        webhook: &WebhookModel,
+        event: &str,
+        body: &impl Serialize,
+    ) -> Result<()> {
+        // checked again since it was set, the host may resolve elsewhere now
+        self.check(&webhook.url).await?;
+        let body = serde_json::to_vec(body)?;
+        let response = self
+            .client
+            .post(&webhook.url)
+            .header(reqwest::header::CONTENT_TYPE, "application/json")
+            .header(WEBHOOK_SIGNATURE_HEADER, signature(&webhook.secret, &body))
+            .header(WEBHOOK_EVENT_HEADER, event)
+            .body(body)
+            .send()
+            .await?;
+        if !response.status().is_success() {
+            anyhow::bail!("webhook responded {}", response.status());
+        }
+        Ok(())
+    }
+}
+
+/// Resolves hosts to their addresses only if all of them are public, see `check_public`.
+struct PublicResolver;
+
+impl reqwest::dns::Resolve for PublicResolver {
+    fn resolve(&self, name: reqwest::dns::Name) -> reqwest::dns::Resolving {
+        Box::pin(async move {
+            let addrs = public_addrs(name.as_str()).await?;
+            Ok(Box::new(addrs.into_iter()) as reqwest::dns::Addrs)
+        })
+    }
+}
+
+/// The addresses of `host`, if all of them are public.
+async fn public_addrs(host: &str) -> Result<Vec<SocketAddr>> {
+    let addrs = tokio::net::lookup_host((host, 0))
+        .await?
+        .collect::<Vec<_>>();
+    if addrs.is_empty() {
+        anyhow::bail!("{host} doesn't resolve to any address");
+    }
+    for addr in &addrs {
+        check_public(addr.ip())?;
+    }
+    Ok(addrs)
+}
+
+/// Errors if `ip` is a loopback, private, link-local, unspecified or multicast address, which
+/// would let a webhook reach the registry's own network.
+fn check_public(ip: IpAddr) -> Result<()> {
+    let ip = match ip {
+        IpAddr::V6(v6) => v6.to_ipv4_mapped().map(IpAddr::V4).unwrap_or(ip),
+        IpAddr::V4(_) => ip,
+    };
+    let private = ip.is_loopback()
+        || ip.is_unspecified()
+        || ip.is_multicast()
+        || match ip {
+            IpAddr::V4(v4) => v4.is_private() || v4.is_link_local() || v4.is_broadcast(),
+            IpAddr::V6(v6) => v6.is_unique_local() || v6.is_unicast_link_local(),
+        };
+    if private {
+        anyhow::bail!("{ip} is not a public address");
+    }
+    Ok(())
+}
+
+/// Send queued notifications to the webhooks of their users. A notification that fails is
+/// retried on later runs, up to `MAX_DELIVERY_ATTEMPTS` times. Returns the number delivered.
+pub async fn deliver(state: &OnyxState) -> Result<usize> {
+    let queued = {
+        let read = state.db.begin_read()?;
+        let notification_queue_table = read.open_table(NOTIFICATION_QUEUE_TABLE)?;
+        let webhook_table = read.open_table(WEBHOOK_TABLE)?;
+        let mut queued = vec![];
+        for entry in notification_queue_table.iter()?.take(DELIVERIES_PER_RUN) {
+            let (id, notification) = entry?;
+            let notification = notification.value();
+            let webhook = webhook_table
+                .get(notification.user_id.as_str())?
+                .map(|webhook| webhook.value());
+            queued.push((id.value().to_string(), notification, webhook));
+        }
+        queued
+    };
+    let mut delivered = 0;
+    for (id, mut notification, webhook) in queued {
+        // the user removed their webhook since this was queued
+        let result = match &webhook {
+            Some(webhook) => {
+                state
+                    .webhooks
+                    .send(webhook, "publish", &notification.notification)
+                    .await
+            }
+            None => Ok(()),
+        };
+        let write = state.db.begin_write()?;
+        {
+            let mut notification_queue_table = write.open_table(NOTIFICATION_QUEUE_TABLE)?;
+            match result {
+                Ok(()) => {
+                    notification_queue_table.remove(id.as_str())?;
+                    if webhook.is_some() {
+                        delivered += 1;
+                    }
+                }
+                Err(e) => {
+                    notification.attempts += 1;
+                    log::warn!(
+                        "Failed to notify user {} (attempt {}): {e:?}",
+                        notification.user_id,
+                        notification.attempts
+                    );
+                    if notification.attempts >= MAX_DELIVERY_ATTEMPTS {
+                        notification_queue_table.remove(id.as_str())?;
+                    } else {
+                        notification_queue_table.insert(id.as_str(), notification)?;
+                    }
+                }
+            }
+        }
+        write.commit()?;
+    }
+    Ok(delivered)
+}
+
+/// The webhook of the authenticated user.
+pub async fn webhook(
+    State(state): State<OnyxState>,
+    session: AuthSession,
+) -> Result<ResponseJson<WebhookModel>, OnyxError> {
+    let read = state.db.begin_read()?;
+    let webhook_table = read.open_table(WEBHOOK_TABLE)?;
+    match webhook_table.get(session.user_id.as_str())? {
+        Some(webhook) => Ok(ResponseJson(webhook.value())),
+        None => Err(OnyxError::not_found("No webhook is set")),
+    }
+}
+
+/// Send the authenticated user's notifications to a webhook, with a new signing secret.
+pub async fn set_webhook(
+    State(state): State<OnyxState>,
+    session: AuthSession,
+    ValidJson(payload): ValidJson<SetWebhookRequest>,
+) -> Result<ResponseJson<WebhookModel>, OnyxError> {
+    session.require_full_scope()?;
+    if let Err(e) = state.webhooks.check(&payload.url).await {
+        let mut errors = ValidationErrors::default();
+        errors.check("url", Err(e.to_string()));
+        return Err(errors.into());
+    }
+    let webhook = WebhookModel {
+        url: payload.url,
+        secret: hex::encode(rand::random::<[u8; 32]>()),
+        created_at: timestamp(),
+    };
+    let write = state.db.begin_write()?;
+    write
+        .open_table(WEBHOOK_TABLE)?
+        .insert(session.user_id.as_str(), webhook.clone())?;
+    write.commit()?;
+    Ok(ResponseJson(webhook))
+}
+
+/// Stop sending the authenticated user's notifications anywhere.
+pub async fn delete_webhook(
+    State(state): State<OnyxState>,
+    session: AuthSession,
+) -> Result<StatusCode, OnyxError> {
+    session.require_full_scope()?;
+    let write = state.db.begin_write()?;
+    if write
+        .open_table(WEBHOOK_TABLE)?
+        .remove(session.user_id.as_str())?
+        .is_none()
+    {
+        return Err(OnyxError::not_found("No webhook is set"));
+    }
+    write.commit()?;
+    Ok(StatusCode::NO_CONTENT)
+}
+
+#[cfg(test)]
+mod tests {
+    use anyhow::Result;
+    use nanoid::nanoid;
+    use onyx_api::prelude::*;
+
+    use std::sync::Arc;
+
+    use super::WebhookClient;
+    use super::deliver;
+    use super::signature;
+    use crate::testing::OnyxTest;
+    use crate::testing::webhook_receiver;
+
+    #[tokio::test]
+    async fn should_notify_publishes_by_tokens() -> Result<()> {
+        let test = OnyxTest::new().await?;
+        let (login, _password) = test.signup(None).await?;
+
+        // the registry only sends to https urls
+        let err = test
+            .api
+            .set_webhook(
+                &login.token,
+                &SetWebhookRequest {
+                    url: "http://localhost/hook".to_string(),
+                },
+            )
+            .await
+            .unwrap_err();
+        let err = err.downcast_ref::<ApiError>().unwrap();
+        assert_eq!(err.code, OnyxErrorCode::ValidationFailed);
+        let webhook = test
+            .api
+            .set_webhook(
+                &login.token,
+                &SetWebhookRequest {
+                    url: "https://example.com/hook".to_string(),
+                },
+            )
+            .await?;
+        assert_eq!(test.api.webhook(&login.token).await?, webhook);
+
+        // point the webhook at a local server instead
+        let (url, received) = webhook_receiver().await?;
+        {
+            let write = test.db().begin_write()?;
+            write.open_table(WEBHOOK_TABLE)?.insert(
+                login.user.id.as_str(),
+                WebhookModel {
+                    url,
+                    ..webhook.clone()
+                },
+            )?;
+            write.commit()?;
+        }
+
+        // publishing from the browser session isn't notified
+        let tarball = OnyxTest::create_test_tarball_named(None, Some("notified"), Some("0.1.0"))?;
+        test.publish(
+            Some(PublishData::new(tarball.1.to_string(), login.token.clone())),
+            tarball,
+        )
+        .await?;
+        assert_eq!(deliver(&test.state).await?, 0);
+
+        let ci_token = nanoid!();
+        test.api
+            .propose_token(
+                ci_token.clone(),
+                None,
+                TokenScope::Full,
+                login.token.clone(),
+            )
+            .await?;
+        let tarball = OnyxTest::create_test_tarball_named(None, Some("notified"), Some("0.2.0"))?;
+        let hash = tarball.1.to_string();
+        test.publish(
+            Some(PublishData::new(hash.clone(), ci_token.clone())),
+            tarball,
+        )
+        .await?;
+        assert_eq!(deliver(&test.state).await?, 1);
+        let received = received.lock().unwrap().clone();
+        assert_eq!(received.len(), 1);
+        let (event, sig, body) = &received[0];
+        assert_eq!(event, "publish");
+        assert_eq!(*sig, signature(&webhook.secret, body));
+        let notification = serde_json::from_slice::<PublishNotification>(body)?;
+        assert_eq!(notification.package_name, "notified");
+        assert_eq!(notification.version_name, "0.2.0");
+        assert_eq!(notification.hash, hash);
+        assert!(ci_token.starts_with(&notification.token_prefix));
+        assert_eq!(notification.source, Some(SessionSource::ProposedToken));
+        assert_eq!(notification.ip.as_deref(), Some("127.0.0.1"));
+
+        // delivered notifications aren't sent again
+        assert_eq!(deliver(&test.state).await?, 0);
+        test.api.delete_webhook(&login.token).await?;
+        assert!(test.api.webhook(&login.token).await.is_err());
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn should_refuse_private_webhooks() -> Result<()> {
+        let test = OnyxTest::with_config(|state| {
+            Arc::make_mut(&mut state.config).allow_private_webhooks = false;
+            state.webhooks = WebhookClient::from_config(&state.config).unwrap();
+        })
+        .await?;
+        let (login, _password) = test.signup(None).await?;
+        for url in [
+            "https://127.0.0.1/hook",
+            "https://localhost/hook",
+            "https://[::1]/hook",
+            "https://169.254.169.254/latest/meta-data",
+            "https://10.0.0.1/hook",
+            "https://[fd00::1]/hook",
+            "https://[::ffff:127.0.0.1]/hook",
+        ] {
+            let err = test
+                .api
+                .set_webhook(
+                    &login.token,
+                    &SetWebhookRequest {
+                        url: url.to_string(),
+                    },
+                )
+                .await
+                .unwrap_err();
+            let err = err.downcast_ref::<ApiError>().unwrap();
+            assert_eq!(err.code, OnyxErrorCode::ValidationFailed, "{url}");
+        }
+
+        // a webhook that resolves to a private address since it was set isn't sent to
+        let (url, received) = webhook_receiver().await?;
+        let webhook = WebhookModel {
+            url,
+            secret: nanoid!(),
+            created_at: 0,
+        };
+        assert!(
+            test.state
+                .webhooks
+                .send(&webhook, "publish", &())
+                .await
+                .is_err()
+        );
+        assert!(received.lock().unwrap().is_empty());
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn should_not_follow_webhook_redirects() -> Result<()> {
+        let test = OnyxTest::new().await?;
+        let (target, received) = webhook_receiver().await?;
+        let app = axum::Router::new().route(
+            "/hook",
+            axum::routing::post(
+                move || async move { axum::response::Redirect::temporary(&target) },
+            ),
+        );
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await?;
+        let url = format!("http://{}/hook", listener.local_addr()?);
+        tokio::spawn(async move {
+            axum::serve(listener, app).await.unwrap();
+        });
+        let webhook = WebhookModel {
+            url,
+            secret: nanoid!(),
+            created_at: 0,
+        };
+        let err = test
+            .state
+            .webhooks
+            .send(&webhook, "publish", &())
+            .await
+            .unwrap_err();
+        assert!(err.to_string().contains("307"), "{err}");
+        assert!(received.lock().unwrap().is_empty());
+        Ok(())
+    }
+}
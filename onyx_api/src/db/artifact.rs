@@ -0,0 +1,41 @@
+use serde::Deserialize;
+use serde::Serialize;
+
+/// A compiled artifact attached to a version by the owner of the package, e.g. the program
+/// json `nargo compile` writes to `target/`. The contents are kept in storage by hash.
+#[derive(Clone, Serialize, Deserialize, Debug, PartialEq)]
+#[cfg_attr(feature = "openapi", derive(schemars::JsonSchema))]
+pub struct VersionArtifactModel {
+    pub name: String,
+    /// Hex encoded blake3 hash of the artifact contents.
+    pub hash: String,
+    pub size: u64,
+    /// The `noir_version` the artifact says it was compiled with.
+    pub noir_version: Option<String>,
+    pub uploaded_at: u64,
+}
+
+#[cfg(feature = "server")]
+impl redb::Value for VersionArtifactModel {
+    type SelfType<'a> = VersionArtifactModel;
+    type AsBytes<'a> = Vec<u8>;
+
+    fn fixed_width() -> Option<usize> {
+        None // Variable width due to strings
+    }
+
+    fn from_bytes<'a>(data: &'a [u8]) -> Self::SelfType<'a>
+    where
+        Self: 'a,
+    {
+        bincode::deserialize(data).expect("Failed to deserialize VersionArtifactModel")
+    }
+
+    fn as_bytes<'a, 'b: 'a>(value: &'a Self::SelfType<'b>) -> Self::AsBytes<'a> {
+        bincode::serialize(value).expect("Failed to serialize VersionArtifactModel")
+    }
+
+    fn type_name() -> redb::TypeName {
+        redb::TypeName::new("VersionArtifactModel")
+    }
+}
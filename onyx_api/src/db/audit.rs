@@ -0,0 +1,72 @@
+use serde::Deserialize;
+use serde::Serialize;
+
+#[derive(Clone, Copy, Serialize, Deserialize, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "openapi", derive(schemars::JsonSchema))]
+#[serde(rename_all = "snake_case")]
+pub enum AuditAction {
+    /// A new package name resembles a popular package and was published with a warning.
+    NameFlagged,
+    /// A new package name resembles a popular package and waits for an admin to approve it.
+    NameHeld,
+    /// A new package name resembles a popular package and was rejected.
+    NameRejected,
+    /// An admin approved a held package name.
+    NameApproved,
+    /// An admin declined a held package name.
+    NameDeclined,
+    /// An admin resolved a package report.
+    ReportResolved,
+    /// A version was imported from a repository on behalf of a user.
+    VersionImported,
+    /// A tag of an upstream repository was fetched to be served by the git proxy.
+    UpstreamFetched,
+    /// The owner of a package changed its metadata.
+    MetadataUpdated,
+    /// A scanner found something in an upload, which waits for an admin to review it.
+    PublishFlagged,
+    /// An admin released a flagged upload.
+    PublishReleased,
+    /// An admin rejected a flagged upload.
+    PublishRejected,
+}
+
+/// A decision made by the registry or an admin, kept for later review. Sequence numbers
+/// start at 1 and increase by one with each entry.
+#[derive(Clone, Serialize, Deserialize, Debug, PartialEq)]
+#[cfg_attr(feature = "openapi", derive(schemars::JsonSchema))]
+pub struct AuditEntry {
+    pub seq: u64,
+    pub created_at: u64,
+    pub action: AuditAction,
+    /// The admin who made the decision, `None` if the registry made it.
+    pub admin_username: Option<String>,
+    /// The package name the decision is about.
+    pub package_name: String,
+    pub details: String,
+}
+
+#[cfg(feature = "server")]
+impl redb::Value for AuditEntry {
+    type SelfType<'a> = AuditEntry;
+    type AsBytes<'a> = Vec<u8>;
+
+    fn fixed_width() -> Option<usize> {
+        None // Variable width due to strings
+    }
+
+    fn from_bytes<'a>(data: &'a [u8]) -> Self::SelfType<'a>
+    where
+        Self: 'a,
+    {
+        bincode::deserialize(data).expect("Failed to deserialize AuditEntry")
+    }
+
+    fn as_bytes<'a, 'b: 'a>(value: &'a Self::SelfType<'b>) -> Self::AsBytes<'a> {
+        bincode::serialize(value).expect("Failed to serialize AuditEntry")
+    }
+
+    fn type_name() -> redb::TypeName {
+        redb::TypeName::new("AuditEntry")
+    }
+}
@@ -0,0 +1,64 @@
+use serde::Deserialize;
+use serde::Serialize;
+
+/// The outcome of rebuilding a version from its tarball.
+#[derive(Clone, Copy, Serialize, Deserialize, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "openapi", derive(schemars::JsonSchema))]
+#[serde(rename_all = "snake_case")]
+pub enum BuildStatus {
+    /// The package compiled and every attached artifact matches the rebuilt one.
+    Verified,
+    /// The package compiled but at least one attached artifact differs from the rebuilt one.
+    Mismatch,
+    /// The package compiled and has no attached artifacts to compare against.
+    Built,
+    /// `nargo compile` failed or ran out of time or memory.
+    Failed,
+}
+
+/// How an attached artifact compares to the rebuilt one.
+#[derive(Clone, Serialize, Deserialize, Debug, PartialEq)]
+#[cfg_attr(feature = "openapi", derive(schemars::JsonSchema))]
+pub struct ArtifactCheck {
+    pub name: String,
+    /// Whether the abi and bytecode of the attached artifact equal the rebuilt ones.
+    pub matches: bool,
+}
+
+/// The latest rebuild of a version by the registry's verification worker.
+#[derive(Clone, Serialize, Deserialize, Debug, PartialEq)]
+#[cfg_attr(feature = "openapi", derive(schemars::JsonSchema))]
+pub struct VersionBuildModel {
+    pub status: BuildStatus,
+    /// The output of `nargo --version` on the worker.
+    pub nargo_version: Option<String>,
+    pub built_at: u64,
+    pub artifacts: Vec<ArtifactCheck>,
+    /// The tail of the compiler output when the build failed.
+    pub log: Option<String>,
+}
+
+#[cfg(feature = "server")]
+impl redb::Value for VersionBuildModel {
+    type SelfType<'a> = VersionBuildModel;
+    type AsBytes<'a> = Vec<u8>;
+
+    fn fixed_width() -> Option<usize> {
+        None // Variable width due to strings
+    }
+
+    fn from_bytes<'a>(data: &'a [u8]) -> Self::SelfType<'a>
+    where
+        Self: 'a,
+    {
+        bincode::deserialize(data).expect("Failed to deserialize VersionBuildModel")
+    }
+
+    fn as_bytes<'a, 'b: 'a>(value: &'a Self::SelfType<'b>) -> Self::AsBytes<'a> {
+        bincode::serialize(value).expect("Failed to serialize VersionBuildModel")
+    }
+
+    fn type_name() -> redb::TypeName {
+        redb::TypeName::new("VersionBuildModel")
+    }
+}
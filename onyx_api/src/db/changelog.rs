@@ -0,0 +1,42 @@
+use serde::Deserialize;
+use serde::Serialize;
+
+use super::HashId;
+
+/// A published version in the order it was published. Sequence numbers start at 1 and
+/// increase by one with each publish, so a consumer can resume from the last number it saw.
+#[derive(Clone, Serialize, Deserialize, Debug, PartialEq)]
+#[cfg_attr(feature = "openapi", derive(schemars::JsonSchema))]
+pub struct ChangelogEntry {
+    pub seq: u64,
+    pub package_name: String,
+    pub version_name: String,
+    pub version_id: HashId,
+    pub author_id: String,
+    pub published_at: u64,
+}
+
+#[cfg(feature = "server")]
+impl redb::Value for ChangelogEntry {
+    type SelfType<'a> = ChangelogEntry;
+    type AsBytes<'a> = Vec<u8>;
+
+    fn fixed_width() -> Option<usize> {
+        None // Variable width due to strings
+    }
+
+    fn from_bytes<'a>(data: &'a [u8]) -> Self::SelfType<'a>
+    where
+        Self: 'a,
+    {
+        bincode::deserialize(data).expect("Failed to deserialize ChangelogEntry")
+    }
+
+    fn as_bytes<'a, 'b: 'a>(value: &'a Self::SelfType<'b>) -> Self::AsBytes<'a> {
+        bincode::serialize(value).expect("Failed to serialize ChangelogEntry")
+    }
+
+    fn type_name() -> redb::TypeName {
+        redb::TypeName::new("ChangelogEntry")
+    }
+}
@@ -0,0 +1,42 @@
+use serde::Deserialize;
+use serde::Serialize;
+
+/// A dependency of a published version on another package in this registry.
+#[derive(Clone, Serialize, Deserialize, Debug, PartialEq)]
+#[cfg_attr(feature = "openapi", derive(schemars::JsonSchema))]
+pub struct PackageDependency {
+    pub package_name: String,
+    /// The tag requested in Nargo.toml.
+    pub version_name: String,
+}
+
+/// The registry dependencies of a version, read from its Nargo.toml when it was published.
+#[derive(Clone, Serialize, Deserialize, Debug, Default, PartialEq)]
+pub struct VersionDependencies {
+    pub dependencies: Vec<PackageDependency>,
+}
+
+#[cfg(feature = "server")]
+impl redb::Value for VersionDependencies {
+    type SelfType<'a> = VersionDependencies;
+    type AsBytes<'a> = Vec<u8>;
+
+    fn fixed_width() -> Option<usize> {
+        None // Variable width due to strings
+    }
+
+    fn from_bytes<'a>(data: &'a [u8]) -> Self::SelfType<'a>
+    where
+        Self: 'a,
+    {
+        bincode::deserialize(data).expect("Failed to deserialize VersionDependencies")
+    }
+
+    fn as_bytes<'a, 'b: 'a>(value: &'a Self::SelfType<'b>) -> Self::AsBytes<'a> {
+        bincode::serialize(value).expect("Failed to serialize VersionDependencies")
+    }
+
+    fn type_name() -> redb::TypeName {
+        redb::TypeName::new("VersionDependencies")
+    }
+}
@@ -3,7 +3,8 @@ use std::str::FromStr;
 use serde::Deserialize;
 use serde::Serialize;
 
-#[derive(Clone, Debug, Serialize, Deserialize)]
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq, Eq)]
+#[cfg_attr(feature = "openapi", derive(schemars::JsonSchema))]
 pub struct HashId {
     bytes: [u8; 32],
 }
@@ -16,6 +17,13 @@ impl From<blake3::Hash> for HashId {
     }
 }
 
+impl HashId {
+    /// The raw hash, e.g. to hash ids together.
+    pub fn as_bytes(&self) -> &[u8; 32] {
+        &self.bytes
+    }
+}
+
 impl FromStr for HashId {
     type Err = anyhow::Error;
 
@@ -0,0 +1,58 @@
+use serde::Deserialize;
+use serde::Serialize;
+
+/// An ed25519 public key a user signs with. Keys are never deleted, so signatures made with
+/// them can still be checked. A rotated key is still trusted for what it signed before it was
+/// replaced; a revoked key isn't trusted at all.
+#[derive(Clone, Serialize, Deserialize, Debug, PartialEq)]
+#[cfg_attr(feature = "openapi", derive(schemars::JsonSchema))]
+pub struct UserKeyModel {
+    /// Hex encoded ed25519 public key.
+    pub public_key: String,
+    pub user_id: String,
+    pub username: String,
+    pub created_at: u64,
+    /// The key this one replaced when it was rotated in.
+    pub replaces: Option<String>,
+    /// The key that replaced this one when it was rotated out.
+    pub replaced_by: Option<String>,
+    pub replaced_at: Option<u64>,
+    pub revoked_at: Option<u64>,
+}
+
+impl UserKeyModel {
+    /// Whether the key may sign new things: it hasn't been rotated out or revoked.
+    pub fn is_active(&self) -> bool {
+        self.replaced_by.is_none() && self.revoked_at.is_none()
+    }
+}
+
+/// The message a key signs to be added to the account of `username`, see `AddKeyRequest`.
+pub fn key_message(username: &str, public_key: &str) -> String {
+    format!("nrpm key {username} {public_key}")
+}
+
+#[cfg(feature = "server")]
+impl redb::Value for UserKeyModel {
+    type SelfType<'a> = UserKeyModel;
+    type AsBytes<'a> = Vec<u8>;
+
+    fn fixed_width() -> Option<usize> {
+        None // Variable width due to strings
+    }
+
+    fn from_bytes<'a>(data: &'a [u8]) -> Self::SelfType<'a>
+    where
+        Self: 'a,
+    {
+        bincode::deserialize(data).expect("Failed to deserialize UserKeyModel")
+    }
+
+    fn as_bytes<'a, 'b: 'a>(value: &'a Self::SelfType<'b>) -> Self::AsBytes<'a> {
+        bincode::serialize(value).expect("Failed to serialize UserKeyModel")
+    }
+
+    fn type_name() -> redb::TypeName {
+        redb::TypeName::new("UserKeyModel")
+    }
+}
@@ -1,12 +1,38 @@
+mod artifact;
+mod audit;
+mod build;
+mod changelog;
+mod dependency;
 mod hash_id;
+mod key;
+mod moderation;
 mod package;
+mod scan;
+mod session;
+mod transfer;
+mod transparency;
+mod upstream;
 mod user;
 mod version;
+mod webhook;
 
+pub use artifact::*;
+pub use audit::*;
+pub use build::*;
+pub use changelog::*;
+pub use dependency::*;
 pub use hash_id::*;
+pub use key::*;
+pub use moderation::*;
 pub use package::*;
+pub use scan::*;
+pub use session::*;
+pub use transfer::*;
+pub use transparency::*;
+pub use upstream::*;
 pub use user::*;
 pub use version::*;
+pub use webhook::*;
 
 use super::*;
 
@@ -21,11 +47,35 @@ pub mod tables {
     // auth token keyed to expiration timestamp
     pub const AUTH_TOKEN_TABLE: TableDefinition<NanoId, (NanoId, u64)> =
         TableDefinition::new("auth_tokens");
+    // auth token keyed to session metadata
+    pub const SESSION_TABLE: TableDefinition<NanoId, SessionModel> =
+        TableDefinition::new("sessions");
+    // refresh token keyed to (user_id, access token, expires_at)
+    pub const REFRESH_TOKEN_TABLE: TableDefinition<NanoId, (NanoId, NanoId, u64)> =
+        TableDefinition::new("refresh_tokens");
+    // user_id keyed to many auth tokens
+    pub const USER_SESSION_TABLE: MultimapTableDefinition<NanoId, NanoId> =
+        MultimapTableDefinition::new("user_sessions");
     // user_id keyed to user document
     pub const USER_TABLE: TableDefinition<NanoId, UserModel> = TableDefinition::new("users");
     // username keyed to user_id
     pub const USERNAME_USER_ID_TABLE: TableDefinition<&str, NanoId> =
         TableDefinition::new("username_user_id");
+    // hex encoded public key keyed to the user key document
+    pub const USER_KEY_TABLE: TableDefinition<&str, UserKeyModel> =
+        TableDefinition::new("user_keys");
+    // user_id keyed to many public keys, in any state
+    pub const USER_KEYS_TABLE: MultimapTableDefinition<NanoId, &str> =
+        MultimapTableDefinition::new("user_key_ids");
+    // user_id keyed to the bytes of tarballs and artifacts stored for packages they own
+    pub const USER_STORAGE_TABLE: TableDefinition<NanoId, u64> =
+        TableDefinition::new("user_storage");
+    // user_id keyed to the storage quota an admin set for them, in bytes
+    pub const USER_QUOTA_TABLE: TableDefinition<NanoId, u64> = TableDefinition::new("user_quotas");
+    // package_id keyed to the bytes of its tarballs and artifacts, moved to the new owner on
+    // transfer
+    pub const PACKAGE_STORAGE_TABLE: TableDefinition<NanoId, u64> =
+        TableDefinition::new("package_storage");
 
     pub const PACKAGE_TABLE: TableDefinition<NanoId, PackageModel> =
         TableDefinition::new("packages");
@@ -48,7 +98,169 @@ pub mod tables {
     pub const GIT_REFS_TABLE: TableDefinition<NanoId, &str> = TableDefinition::new("git_refs");
     // commit_id_hex keyed to pack bytes
     pub const GIT_PACK_TABLE: TableDefinition<&str, Vec<u8>> = TableDefinition::new("git_packs");
+    // (upstream repository as `{host}/{org}/{repo}`, tag) keyed to the tag as the proxy
+    // serves it
+    pub const UPSTREAM_TAG_TABLE: TableDefinition<(&str, &str), UpstreamTagModel> =
+        TableDefinition::new("upstream_tags");
+
+    // package_id keyed to the pending transfer of the package
+    pub const PACKAGE_TRANSFER_TABLE: TableDefinition<NanoId, TransferRequestModel> =
+        TableDefinition::new("package_transfers");
+    // user_id of the sender and of the recipient keyed to many package_ids with pending transfers
+    pub const USER_TRANSFER_TABLE: MultimapTableDefinition<NanoId, NanoId> =
+        MultimapTableDefinition::new("user_transfers");
+    // (package_id, user_id) keyed to a claim on the package by the user
+    pub const PACKAGE_CLAIM_TABLE: TableDefinition<(NanoId, NanoId), PackageClaimModel> =
+        TableDefinition::new("package_claims");
+
+    // sequence number keyed to a published version, see `ChangelogEntry`
+    pub const CHANGELOG_TABLE: TableDefinition<u64, ChangelogEntry> =
+        TableDefinition::new("changelog");
+    // upstream registry url keyed to the last changelog sequence number mirrored from it
+    pub const MIRROR_STATE_TABLE: TableDefinition<&str, u64> = TableDefinition::new("mirror_state");
+    // index keyed to a publish in the transparency log, see `LogEntry`
+    pub const TRANSPARENCY_LOG_TABLE: TableDefinition<u64, LogEntry> =
+        TableDefinition::new("transparency_log");
+    // version_id keyed to the index of its entry in the transparency log
+    pub const VERSION_LOG_INDEX_TABLE: TableDefinition<HashId, u64> =
+        TableDefinition::new("version_log_index");
+    // (level, index) keyed to the hash of a complete subtree of the transparency log, the one
+    // over leaves index * 2^level up to (index + 1) * 2^level
+    pub const LOG_NODE_TABLE: TableDefinition<(u8, u64), HashId> =
+        TableDefinition::new("log_nodes");
+    // tree size keyed to the root hash of the transparency log at that size
+    pub const LOG_ROOT_TABLE: TableDefinition<u64, HashId> = TableDefinition::new("log_roots");
+    // changelog sequence number keyed to the signed snapshot of the registry at it, see
+    // `Snapshot`
+    pub const SNAPSHOT_TABLE: TableDefinition<u64, &str> = TableDefinition::new("snapshot");
+
+    // version_id keyed to the packages it depends on
+    pub const VERSION_DEPENDENCY_TABLE: TableDefinition<HashId, VersionDependencies> =
+        TableDefinition::new("version_dependencies");
+    // package_id keyed to the ids of packages whose latest version depends on it
+    pub const PACKAGE_DEPENDENT_TABLE: MultimapTableDefinition<NanoId, NanoId> =
+        MultimapTableDefinition::new("package_dependents");
+
+    // `key=value` of a `[package.metadata.nrpm]` entry keyed to the versions declaring it,
+    // used to filter searches
+    pub const METADATA_VERSION_TABLE: MultimapTableDefinition<&str, HashId> =
+        MultimapTableDefinition::new("metadata_versions");
+
+    // version_id keyed to the api docs extracted from its sources, as `PackageDocs` json
+    pub const VERSION_DOCS_TABLE: TableDefinition<HashId, &str> =
+        TableDefinition::new("version_docs");
+
+    // version_id keyed to the markdown release notes it was published with
+    pub const VERSION_RELEASE_NOTES_TABLE: TableDefinition<HashId, &str> =
+        TableDefinition::new("version_release_notes");
+    // (version_id, artifact name) keyed to a compiled artifact attached to the version
+    pub const VERSION_ARTIFACT_TABLE: TableDefinition<(HashId, &str), VersionArtifactModel> =
+        TableDefinition::new("version_artifacts");
+    // version_id keyed to the time it was queued to be rebuilt by the verification worker
+    pub const BUILD_QUEUE_TABLE: TableDefinition<HashId, u64> = TableDefinition::new("build_queue");
+    // version_id keyed to its latest rebuild by the verification worker
+    pub const VERSION_BUILD_TABLE: TableDefinition<HashId, VersionBuildModel> =
+        TableDefinition::new("version_builds");
+
+    // report_id keyed to a report of a package, open or resolved
+    pub const PACKAGE_REPORT_TABLE: TableDefinition<NanoId, PackageReportModel> =
+        TableDefinition::new("package_reports");
+    // user_id of the reporter keyed to many report_ids, used to rate limit reports
+    pub const USER_REPORT_TABLE: MultimapTableDefinition<NanoId, NanoId> =
+        MultimapTableDefinition::new("user_reports");
+    // version_id keyed to why it was yanked by an admin
+    pub const YANKED_VERSION_TABLE: TableDefinition<HashId, ModerationRecord> =
+        TableDefinition::new("yanked_versions");
+    // name of a deleted package keyed to why it was deleted, the name can't be published again
+    pub const REMOVED_PACKAGE_TABLE: TableDefinition<&str, ModerationRecord> =
+        TableDefinition::new("removed_packages");
+    // user_id keyed to why the user was banned
+    pub const BANNED_USER_TABLE: TableDefinition<NanoId, ModerationRecord> =
+        TableDefinition::new("banned_users");
+
+    // new package name keyed to its review, see `NameReviewModel`
+    pub const NAME_REVIEW_TABLE: TableDefinition<&str, NameReviewModel> =
+        TableDefinition::new("name_reviews");
+    // sequence number keyed to a decision made by the registry or an admin
+    pub const AUDIT_LOG_TABLE: TableDefinition<u64, AuditEntry> = TableDefinition::new("audit_log");
+
+    // version_id keyed to the hashes of its files, as `VersionManifest` json
+    pub const VERSION_MANIFEST_TABLE: TableDefinition<HashId, &str> =
+        TableDefinition::new("version_manifests");
+    // (from_version_id, to_version_id) keyed to the delta between them, see
+    // `nrpm_tarball::delta`
+    pub const VERSION_DELTA_TABLE: TableDefinition<(HashId, HashId), &[u8]> =
+        TableDefinition::new("version_deltas");
+
+    // version_id keyed to the number of times it was downloaded, including installs reported
+    // by clients that fetched it another way
+    pub const VERSION_DOWNLOAD_TABLE: TableDefinition<HashId, u64> =
+        TableDefinition::new("version_downloads");
+
+    // (user_id, idempotency_key) keyed to (tarball_hash, package_id, created_at)
+    // used to replay the response of a publish that is retried by the client
+    pub const IDEMPOTENCY_KEY_TABLE: TableDefinition<(NanoId, &str), (&str, NanoId, u64)> =
+        TableDefinition::new("idempotency_keys");
+    // version_id keyed to an upload waiting to be scanned or reviewed before it's published
+    pub const PENDING_PUBLISH_TABLE: TableDefinition<HashId, PendingPublishModel> =
+        TableDefinition::new("pending_publishes");
+
+    // user_id keyed to the webhook notifications are sent to
+    pub const WEBHOOK_TABLE: TableDefinition<NanoId, WebhookModel> =
+        TableDefinition::new("webhooks");
+    // random id keyed to a notification waiting to be delivered
+    pub const NOTIFICATION_QUEUE_TABLE: TableDefinition<NanoId, NotificationModel> =
+        TableDefinition::new("notification_queue");
+
+    // package_id keyed to many user_ids watching it
+    pub const PACKAGE_WATCHER_TABLE: MultimapTableDefinition<NanoId, NanoId> =
+        MultimapTableDefinition::new("package_watchers");
+    // user_id keyed to many package_ids the user watches
+    pub const USER_WATCH_TABLE: MultimapTableDefinition<NanoId, NanoId> =
+        MultimapTableDefinition::new("user_watches");
+    // (user_id, changelog sequence number) keyed to a version published by a package the user
+    // watches
+    pub const USER_NOTIFICATION_TABLE: TableDefinition<(NanoId, u64), ChangelogEntry> =
+        TableDefinition::new("user_notifications");
+    // user_id keyed to the sequence number of the newest notification the user has read
+    pub const NOTIFICATION_READ_TABLE: TableDefinition<NanoId, u64> =
+        TableDefinition::new("notifications_read");
+    // user_id keyed to (sequence number of the newest notification in the last digest, when it
+    // was sent), for users who get a daily digest
+    pub const DIGEST_TABLE: TableDefinition<NanoId, (u64, u64)> = TableDefinition::new("digests");
 }
 
 #[cfg(feature = "server")]
 pub use tables::*;
+
+/// Opens tables for reading. Implemented by redb's read transaction, and by the registry's
+/// wrapper around it that records which tables each transaction uses.
+#[cfg(feature = "server")]
+pub trait ReadTables {
+    fn open_table<K: redb::Key + 'static, V: redb::Value + 'static>(
+        &self,
+        definition: redb::TableDefinition<K, V>,
+    ) -> Result<redb::ReadOnlyTable<K, V>, redb::TableError>;
+
+    fn open_multimap_table<K: redb::Key + 'static, V: redb::Key + 'static>(
+        &self,
+        definition: redb::MultimapTableDefinition<K, V>,
+    ) -> Result<redb::ReadOnlyMultimapTable<K, V>, redb::TableError>;
+}
+
+#[cfg(feature = "server")]
+impl ReadTables for redb::ReadTransaction {
+    fn open_table<K: redb::Key + 'static, V: redb::Value + 'static>(
+        &self,
+        definition: redb::TableDefinition<K, V>,
+    ) -> Result<redb::ReadOnlyTable<K, V>, redb::TableError> {
+        redb::ReadTransaction::open_table(self, definition)
+    }
+
+    fn open_multimap_table<K: redb::Key + 'static, V: redb::Key + 'static>(
+        &self,
+        definition: redb::MultimapTableDefinition<K, V>,
+    ) -> Result<redb::ReadOnlyMultimapTable<K, V>, redb::TableError> {
+        redb::ReadTransaction::open_multimap_table(self, definition)
+    }
+}
@@ -0,0 +1,172 @@
+use serde::Deserialize;
+use serde::Serialize;
+
+/// Why a package was reported.
+#[derive(Clone, Copy, Serialize, Deserialize, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "openapi", derive(schemars::JsonSchema))]
+#[serde(rename_all = "snake_case")]
+pub enum ReportReason {
+    Malware,
+    /// The name imitates another package to catch typos.
+    Typosquatting,
+    Spam,
+    Other,
+}
+
+#[derive(Clone, Copy, Serialize, Deserialize, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "openapi", derive(schemars::JsonSchema))]
+#[serde(rename_all = "snake_case")]
+pub enum ReportStatus {
+    /// Waiting in the moderation queue.
+    Open,
+    /// An admin decided no action was needed.
+    Dismissed,
+    /// An admin yanked, deleted, or banned in response.
+    Actioned,
+}
+
+/// What an admin did about a report.
+#[derive(Clone, Copy, Serialize, Deserialize, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "openapi", derive(schemars::JsonSchema))]
+#[serde(rename_all = "snake_case")]
+pub enum ModerationAction {
+    /// Close the report without acting on it.
+    Dismiss,
+    /// Yank a version so it's no longer resolved for new installs. It stays downloadable
+    /// for lockfiles that already pin it.
+    Yank,
+    /// Delete the package and every version of it. The name can't be published again.
+    Delete,
+    /// Ban the owner of the package, revoking every session they have.
+    Ban,
+}
+
+#[derive(Clone, Serialize, Deserialize, Debug, PartialEq)]
+#[cfg_attr(feature = "openapi", derive(schemars::JsonSchema))]
+pub struct ReportResolution {
+    pub action: ModerationAction,
+    /// The version that was yanked.
+    pub version_name: Option<String>,
+    pub admin_username: String,
+    pub note: String,
+    pub resolved_at: u64,
+}
+
+/// A report of a package by a user, e.g. because it contains malware.
+#[derive(Clone, Serialize, Deserialize, Debug, PartialEq)]
+#[cfg_attr(feature = "openapi", derive(schemars::JsonSchema))]
+pub struct PackageReportModel {
+    pub id: String,
+    pub package_id: String,
+    pub package_name: String,
+    /// The version the report is about, if it's about one.
+    pub version_name: Option<String>,
+    pub reason: ReportReason,
+    pub details: String,
+    pub user_id: String,
+    pub username: String,
+    pub created_at: u64,
+    pub status: ReportStatus,
+    pub resolution: Option<ReportResolution>,
+}
+
+/// Who took a moderation action and why, kept for yanked versions, deleted package names,
+/// and banned users.
+#[derive(Clone, Serialize, Deserialize, Debug, PartialEq)]
+#[cfg_attr(feature = "openapi", derive(schemars::JsonSchema))]
+pub struct ModerationRecord {
+    pub admin_username: String,
+    pub report_id: String,
+    pub note: String,
+    pub created_at: u64,
+}
+
+/// A new package name held for review because it resembles popular packages.
+#[derive(Clone, Serialize, Deserialize, Debug, PartialEq)]
+#[cfg_attr(feature = "openapi", derive(schemars::JsonSchema))]
+pub struct NameReviewModel {
+    pub package_name: String,
+    /// The popular packages the name resembles.
+    pub similar_to: Vec<String>,
+    /// The user who tried to publish the name. Only they may publish it once approved.
+    pub user_id: String,
+    pub username: String,
+    pub requested_at: u64,
+    pub approved_by: Option<String>,
+    pub approved_at: Option<u64>,
+}
+
+#[cfg(feature = "server")]
+impl redb::Value for PackageReportModel {
+    type SelfType<'a> = PackageReportModel;
+    type AsBytes<'a> = Vec<u8>;
+
+    fn fixed_width() -> Option<usize> {
+        None // Variable width due to strings
+    }
+
+    fn from_bytes<'a>(data: &'a [u8]) -> Self::SelfType<'a>
+    where
+        Self: 'a,
+    {
+        bincode::deserialize(data).expect("Failed to deserialize PackageReportModel")
+    }
+
+    fn as_bytes<'a, 'b: 'a>(value: &'a Self::SelfType<'b>) -> Self::AsBytes<'a> {
+        bincode::serialize(value).expect("Failed to serialize PackageReportModel")
+    }
+
+    fn type_name() -> redb::TypeName {
+        redb::TypeName::new("PackageReportModel")
+    }
+}
+
+#[cfg(feature = "server")]
+impl redb::Value for ModerationRecord {
+    type SelfType<'a> = ModerationRecord;
+    type AsBytes<'a> = Vec<u8>;
+
+    fn fixed_width() -> Option<usize> {
+        None // Variable width due to strings
+    }
+
+    fn from_bytes<'a>(data: &'a [u8]) -> Self::SelfType<'a>
+    where
+        Self: 'a,
+    {
+        bincode::deserialize(data).expect("Failed to deserialize ModerationRecord")
+    }
+
+    fn as_bytes<'a, 'b: 'a>(value: &'a Self::SelfType<'b>) -> Self::AsBytes<'a> {
+        bincode::serialize(value).expect("Failed to serialize ModerationRecord")
+    }
+
+    fn type_name() -> redb::TypeName {
+        redb::TypeName::new("ModerationRecord")
+    }
+}
+
+#[cfg(feature = "server")]
+impl redb::Value for NameReviewModel {
+    type SelfType<'a> = NameReviewModel;
+    type AsBytes<'a> = Vec<u8>;
+
+    fn fixed_width() -> Option<usize> {
+        None // Variable width due to strings
+    }
+
+    fn from_bytes<'a>(data: &'a [u8]) -> Self::SelfType<'a>
+    where
+        Self: 'a,
+    {
+        bincode::deserialize(data).expect("Failed to deserialize NameReviewModel")
+    }
+
+    fn as_bytes<'a, 'b: 'a>(value: &'a Self::SelfType<'b>) -> Self::AsBytes<'a> {
+        bincode::serialize(value).expect("Failed to serialize NameReviewModel")
+    }
+
+    fn type_name() -> redb::TypeName {
+        redb::TypeName::new("NameReviewModel")
+    }
+}
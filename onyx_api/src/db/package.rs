@@ -1,27 +1,140 @@
-#[cfg(feature = "server")]
-use std::sync::Arc;
-
 #[cfg(feature = "server")]
 use anyhow::Result;
-#[cfg(feature = "server")]
-use redb::Database;
 use serde::Deserialize;
 use serde::Serialize;
 
 use super::*;
 
 #[derive(Clone, Serialize, Deserialize, Debug)]
+#[cfg_attr(feature = "openapi", derive(schemars::JsonSchema))]
 pub struct PackageModel {
     pub id: String,
     pub name: String,
+    /// The current owner of the package, the only user allowed to publish versions.
     pub author_id: String,
     pub latest_version_id: HashId,
+    /// Every change of owner, oldest first. The original owner is the `from_user_id` of the
+    /// first entry, or `author_id` if the package was never transferred.
+    #[serde(default)]
+    pub ownership_history: Vec<OwnershipTransfer>,
+    /// SPDX license expression from the Nargo.toml of the latest version.
+    #[serde(default)]
+    pub license: Option<String>,
+    /// Metadata the owner changed since publishing the latest version.
+    #[serde(default)]
+    pub metadata: PackageMetadata,
+}
+
+/// Metadata the owner of a package can change without publishing a version. Each field that
+/// is set is shown instead of the one in the Nargo.toml of the latest version, the contents of
+/// versions never change.
+#[derive(Clone, Serialize, Deserialize, Debug, Default, PartialEq)]
+#[cfg_attr(feature = "openapi", derive(schemars::JsonSchema))]
+pub struct PackageMetadata {
+    pub description: Option<String>,
+    pub keywords: Option<Vec<String>>,
+    pub repository: Option<String>,
+    /// Why the package shouldn't be used anymore, e.g. the package that replaces it.
+    pub deprecated: Option<String>,
+}
+
+impl PackageMetadata {
+    /// Replace the fields of `metadata` the owner changed.
+    pub fn apply(&self, metadata: &mut VersionMetadata) {
+        if let Some(description) = &self.description {
+            metadata.description = Some(description.clone());
+        }
+        if let Some(keywords) = &self.keywords {
+            metadata.keywords = keywords.clone();
+        }
+        if let Some(repository) = &self.repository {
+            metadata.repository = Some(repository.clone());
+        }
+    }
+}
+
+/// Layout of `PackageModel` before owners could change metadata.
+#[cfg(feature = "server")]
+#[derive(Deserialize)]
+struct PackageModelV2 {
+    id: String,
+    name: String,
+    author_id: String,
+    latest_version_id: HashId,
+    ownership_history: Vec<OwnershipTransfer>,
+    license: Option<String>,
+}
+
+#[cfg(feature = "server")]
+impl From<PackageModelV2> for PackageModel {
+    fn from(value: PackageModelV2) -> Self {
+        Self {
+            id: value.id,
+            name: value.name,
+            author_id: value.author_id,
+            latest_version_id: value.latest_version_id,
+            ownership_history: value.ownership_history,
+            license: value.license,
+            metadata: PackageMetadata::default(),
+        }
+    }
+}
+
+/// Layout of `PackageModel` before licenses were recorded.
+#[cfg(feature = "server")]
+#[derive(Deserialize)]
+struct PackageModelV1 {
+    id: String,
+    name: String,
+    author_id: String,
+    latest_version_id: HashId,
+    ownership_history: Vec<OwnershipTransfer>,
+}
+
+#[cfg(feature = "server")]
+impl From<PackageModelV1> for PackageModel {
+    fn from(value: PackageModelV1) -> Self {
+        Self {
+            id: value.id,
+            name: value.name,
+            author_id: value.author_id,
+            latest_version_id: value.latest_version_id,
+            ownership_history: value.ownership_history,
+            license: None,
+            metadata: PackageMetadata::default(),
+        }
+    }
+}
+
+/// Layout of `PackageModel` before ownership history was recorded. bincode can't fill in
+/// missing fields so rows written then are decoded with this.
+#[cfg(feature = "server")]
+#[derive(Deserialize)]
+struct PackageModelV0 {
+    id: String,
+    name: String,
+    author_id: String,
+    latest_version_id: HashId,
+}
+
+#[cfg(feature = "server")]
+impl From<PackageModelV0> for PackageModel {
+    fn from(value: PackageModelV0) -> Self {
+        Self {
+            id: value.id,
+            name: value.name,
+            author_id: value.author_id,
+            latest_version_id: value.latest_version_id,
+            ownership_history: vec![],
+            license: None,
+            metadata: PackageMetadata::default(),
+        }
+    }
 }
 
 #[cfg(feature = "server")]
 impl PackageModel {
-    pub fn package_by_name(db: Arc<Database>, name: &str) -> Result<Option<Self>> {
-        let read = db.begin_read()?;
+    pub fn package_by_name(read: &impl ReadTables, name: &str) -> Result<Option<Self>> {
         let package_table = read.open_table(PACKAGE_TABLE)?;
         let package_name_table = read.open_table(PACKAGE_NAME_TABLE)?;
         if let Some(package_id) = package_name_table.get(name)?
@@ -34,11 +147,10 @@ impl PackageModel {
     }
 
     pub fn version(
-        db: Arc<Database>,
+        read: &impl ReadTables,
         name: &str,
         version_name: &str,
     ) -> Result<Option<PackageVersionModel>> {
-        let read = db.begin_read()?;
         let package_name_table = read.open_table(PACKAGE_NAME_TABLE)?;
         let package_version_name_table = read.open_table(PACKAGE_VERSION_NAME_TABLE)?;
         let version_table = read.open_table(VERSION_TABLE)?;
@@ -54,10 +166,9 @@ impl PackageModel {
     }
 
     pub fn versions(
-        db: Arc<Database>,
+        read: &impl ReadTables,
         name: &str,
     ) -> Result<Option<(PackageModel, Vec<PackageVersionModel>)>> {
-        let read = db.begin_read()?;
         let package_name_table = read.open_table(PACKAGE_NAME_TABLE)?;
         let package_table = read.open_table(PACKAGE_TABLE)?;
         let package_version_table = read.open_multimap_table(PACKAGE_VERSION_TABLE)?;
@@ -89,10 +200,9 @@ impl PackageModel {
     }
 
     pub fn latest_version(
-        db: Arc<Database>,
+        read: &impl ReadTables,
         name: &str,
     ) -> Result<Option<(PackageModel, PackageVersionModel)>> {
-        let read = db.begin_read()?;
         let package_table = read.open_table(PACKAGE_TABLE)?;
         let package_name_table = read.open_table(PACKAGE_NAME_TABLE)?;
         let version_table = read.open_table(VERSION_TABLE)?;
@@ -120,7 +230,12 @@ impl redb::Value for PackageModel {
     where
         Self: 'a,
     {
-        bincode::deserialize(data).expect("Failed to deserialize PackageModel")
+        // older layouts are prefixes of newer ones, so try the newest first
+        bincode::deserialize(data)
+            .or_else(|_| bincode::deserialize::<PackageModelV2>(data).map(PackageModel::from))
+            .or_else(|_| bincode::deserialize::<PackageModelV1>(data).map(PackageModel::from))
+            .or_else(|_| bincode::deserialize::<PackageModelV0>(data).map(PackageModel::from))
+            .expect("Failed to deserialize PackageModel")
     }
 
     fn as_bytes<'a, 'b: 'a>(value: &'a Self::SelfType<'b>) -> Self::AsBytes<'a> {
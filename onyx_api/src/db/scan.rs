@@ -0,0 +1,83 @@
+use serde::Deserialize;
+use serde::Serialize;
+
+use super::HashId;
+use crate::http::PublishNotification;
+
+/// Where an upload is on its way to being published.
+#[derive(Clone, Copy, Serialize, Deserialize, Debug, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "openapi", derive(schemars::JsonSchema))]
+#[serde(rename_all = "snake_case")]
+pub enum PublishStatus {
+    /// The version is available.
+    #[default]
+    Published,
+    /// The upload waits to be scanned.
+    Pending,
+    /// A scanner found something, the upload waits for an admin to release or reject it.
+    Flagged,
+    /// An admin rejected the upload, or it could no longer be published once scanned.
+    Rejected,
+}
+
+/// What a scanner reported about an upload.
+#[derive(Clone, Serialize, Deserialize, Debug, PartialEq)]
+#[cfg_attr(feature = "openapi", derive(schemars::JsonSchema))]
+pub struct ScanFinding {
+    pub scanner: String,
+    /// The tail of the scanner's output.
+    pub output: String,
+}
+
+/// An upload held until the registry's scanners have looked at it. The tarball is stored as
+/// `pending-{id}` until the upload is published or rejected.
+#[derive(Clone, Serialize, Deserialize, Debug, PartialEq)]
+#[cfg_attr(feature = "openapi", derive(schemars::JsonSchema))]
+pub struct PendingPublishModel {
+    /// The id the version will have, the hash of the tarball.
+    pub id: HashId,
+    pub author_id: String,
+    pub package_name: String,
+    pub version_name: String,
+    pub uploaded_at: u64,
+    /// Bytes of the tarball, charged to the author once it's published.
+    pub size: u64,
+    pub source_repository: Option<String>,
+    pub source_commit: Option<String>,
+    pub release_notes: Option<String>,
+    /// Idempotency key of the upload, recorded once it's published.
+    pub idempotency_key: Option<String>,
+    /// Webhook notification queued once it's published, made at upload time because it
+    /// describes the token that uploaded it.
+    pub notification: Option<PublishNotification>,
+    pub status: PublishStatus,
+    pub findings: Vec<ScanFinding>,
+    pub scanned_at: Option<u64>,
+    /// The admin who rejected the upload, if one did.
+    pub rejected_by: Option<String>,
+}
+
+#[cfg(feature = "server")]
+impl redb::Value for PendingPublishModel {
+    type SelfType<'a> = PendingPublishModel;
+    type AsBytes<'a> = Vec<u8>;
+
+    fn fixed_width() -> Option<usize> {
+        None // Variable width due to strings
+    }
+
+    fn from_bytes<'a>(data: &'a [u8]) -> Self::SelfType<'a>
+    where
+        Self: 'a,
+    {
+        bincode::deserialize(data).expect("Failed to deserialize PendingPublishModel")
+    }
+
+    fn as_bytes<'a, 'b: 'a>(value: &'a Self::SelfType<'b>) -> Self::AsBytes<'a> {
+        bincode::serialize(value).expect("Failed to serialize PendingPublishModel")
+    }
+
+    fn type_name() -> redb::TypeName {
+        redb::TypeName::new("PendingPublishModel")
+    }
+}
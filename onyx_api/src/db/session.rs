@@ -0,0 +1,105 @@
+use serde::Deserialize;
+use serde::Serialize;
+
+/// How an auth token was issued.
+#[derive(Clone, Copy, Serialize, Deserialize, Debug, PartialEq)]
+#[serde(rename_all = "snake_case")]
+#[cfg_attr(feature = "openapi", derive(schemars::JsonSchema))]
+pub enum SessionSource {
+    Login,
+    Signup,
+    /// A token proposed by an application (e.g. the CLI) and activated by the user.
+    ProposedToken,
+}
+
+/// What an auth token can be used for.
+#[derive(Clone, Copy, Serialize, Deserialize, Debug, Default, PartialEq)]
+#[serde(rename_all = "snake_case")]
+#[cfg_attr(feature = "openapi", derive(schemars::JsonSchema))]
+pub enum TokenScope {
+    /// Everything the user can do.
+    #[default]
+    Full,
+    /// Publishing and managing packages, but not the account itself: no changing the
+    /// password, managing sessions or webhooks, proposing tokens, or admin routes.
+    Publish,
+}
+
+/// Metadata about an auth token. The token itself is the table key. Refreshing a session
+/// moves it to the new access token, keeping its source and creation time.
+#[derive(Clone, Serialize, Deserialize, Debug, PartialEq)]
+pub struct SessionModel {
+    pub user_id: String,
+    pub created_at: u64,
+    pub expires_at: u64,
+    pub source: SessionSource,
+    /// Refresh token that extends this session, if one was issued.
+    pub refresh_token: Option<String>,
+    pub refresh_expires_at: Option<u64>,
+    pub scope: TokenScope,
+    /// Whether the user asked to be remembered when logging in. Refresh tokens of remembered
+    /// sessions last longer.
+    pub remember: bool,
+}
+
+/// `SessionModel` before token scopes and remembered sessions.
+#[cfg(feature = "server")]
+#[derive(Deserialize)]
+struct SessionModelV0 {
+    user_id: String,
+    created_at: u64,
+    expires_at: u64,
+    source: SessionSource,
+    refresh_token: Option<String>,
+    refresh_expires_at: Option<u64>,
+}
+
+#[cfg(feature = "server")]
+impl From<SessionModelV0> for SessionModel {
+    fn from(value: SessionModelV0) -> Self {
+        Self {
+            user_id: value.user_id,
+            created_at: value.created_at,
+            expires_at: value.expires_at,
+            source: value.source,
+            refresh_token: value.refresh_token,
+            refresh_expires_at: value.refresh_expires_at,
+            scope: TokenScope::Full,
+            remember: false,
+        }
+    }
+}
+
+impl SessionModel {
+    /// A session is active while its access token or refresh token is unexpired.
+    pub fn is_active(&self, now: u64) -> bool {
+        now <= self.expires_at || self.refresh_expires_at.is_some_and(|e| now <= e)
+    }
+}
+
+#[cfg(feature = "server")]
+impl redb::Value for SessionModel {
+    type SelfType<'a> = SessionModel;
+    type AsBytes<'a> = Vec<u8>;
+
+    fn fixed_width() -> Option<usize> {
+        None // Variable width due to strings
+    }
+
+    fn from_bytes<'a>(data: &'a [u8]) -> Self::SelfType<'a>
+    where
+        Self: 'a,
+    {
+        bincode::deserialize(data)
+            .or_else(|_| bincode::deserialize::<SessionModelV0>(data).map(SessionModel::from))
+            .expect("Failed to deserialize SessionModel")
+    }
+
+    fn as_bytes<'a, 'b: 'a>(value: &'a Self::SelfType<'b>) -> Self::AsBytes<'a> {
+        bincode::serialize(value).expect("Failed to serialize SessionModel")
+    }
+
+    fn type_name() -> redb::TypeName {
+        redb::TypeName::new("SessionModel")
+    }
+}
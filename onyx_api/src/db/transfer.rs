@@ -0,0 +1,94 @@
+use serde::Deserialize;
+use serde::Serialize;
+
+/// A change of a package's owner, kept in `PackageModel::ownership_history`.
+#[derive(Clone, Serialize, Deserialize, Debug, PartialEq)]
+#[cfg_attr(feature = "openapi", derive(schemars::JsonSchema))]
+pub struct OwnershipTransfer {
+    pub from_user_id: String,
+    pub from_username: String,
+    pub to_user_id: String,
+    pub to_username: String,
+    pub transferred_at: u64,
+    /// Set when an admin reassigned the package instead of the owner, e.g. because it was
+    /// abandoned.
+    pub admin_username: Option<String>,
+    pub reason: Option<String>,
+}
+
+/// A pending offer from the owner of a package to hand it to another user. The transfer
+/// completes when the recipient accepts.
+#[derive(Clone, Serialize, Deserialize, Debug, PartialEq)]
+#[cfg_attr(feature = "openapi", derive(schemars::JsonSchema))]
+pub struct TransferRequestModel {
+    pub package_id: String,
+    pub package_name: String,
+    pub from_user_id: String,
+    pub from_username: String,
+    pub to_user_id: String,
+    pub to_username: String,
+    pub created_at: u64,
+}
+
+/// A request by a user to take over a package, usually one that has been abandoned. Claims
+/// are resolved by an admin.
+#[derive(Clone, Serialize, Deserialize, Debug, PartialEq)]
+#[cfg_attr(feature = "openapi", derive(schemars::JsonSchema))]
+pub struct PackageClaimModel {
+    pub package_id: String,
+    pub package_name: String,
+    pub user_id: String,
+    pub username: String,
+    pub reason: String,
+    pub created_at: u64,
+}
+
+#[cfg(feature = "server")]
+impl redb::Value for TransferRequestModel {
+    type SelfType<'a> = TransferRequestModel;
+    type AsBytes<'a> = Vec<u8>;
+
+    fn fixed_width() -> Option<usize> {
+        None // Variable width due to strings
+    }
+
+    fn from_bytes<'a>(data: &'a [u8]) -> Self::SelfType<'a>
+    where
+        Self: 'a,
+    {
+        bincode::deserialize(data).expect("Failed to deserialize TransferRequestModel")
+    }
+
+    fn as_bytes<'a, 'b: 'a>(value: &'a Self::SelfType<'b>) -> Self::AsBytes<'a> {
+        bincode::serialize(value).expect("Failed to serialize TransferRequestModel")
+    }
+
+    fn type_name() -> redb::TypeName {
+        redb::TypeName::new("TransferRequestModel")
+    }
+}
+
+#[cfg(feature = "server")]
+impl redb::Value for PackageClaimModel {
+    type SelfType<'a> = PackageClaimModel;
+    type AsBytes<'a> = Vec<u8>;
+
+    fn fixed_width() -> Option<usize> {
+        None // Variable width due to strings
+    }
+
+    fn from_bytes<'a>(data: &'a [u8]) -> Self::SelfType<'a>
+    where
+        Self: 'a,
+    {
+        bincode::deserialize(data).expect("Failed to deserialize PackageClaimModel")
+    }
+
+    fn as_bytes<'a, 'b: 'a>(value: &'a Self::SelfType<'b>) -> Self::AsBytes<'a> {
+        bincode::serialize(value).expect("Failed to serialize PackageClaimModel")
+    }
+
+    fn type_name() -> redb::TypeName {
+        redb::TypeName::new("PackageClaimModel")
+    }
+}
@@ -0,0 +1,245 @@
+use serde::Deserialize;
+use serde::Serialize;
+
+use super::HashId;
+
+/// A publish recorded in the transparency log. Entries are never changed or removed, not even
+/// when a package is deleted, so anyone holding a `LogHead` can check what was published.
+#[derive(Clone, Serialize, Deserialize, Debug, PartialEq)]
+#[cfg_attr(feature = "openapi", derive(schemars::JsonSchema))]
+pub struct LogEntry {
+    /// Position in the log, starting at 0.
+    pub index: u64,
+    pub package_name: String,
+    pub version_name: String,
+    /// Content hash of the version tarball.
+    pub version_id: HashId,
+    pub author_id: String,
+    /// Hex encoded ed25519 key the author had on their account when publishing, if any.
+    pub publisher_key: Option<String>,
+    pub published_at: u64,
+}
+
+impl LogEntry {
+    /// The leaf of the entry in the log's Merkle tree.
+    pub fn leaf_hash(&self) -> HashId {
+        let mut hasher = blake3::Hasher::new();
+        hasher.update(&[0]);
+        hasher.update(&serde_json::to_vec(self).expect("Failed to serialize LogEntry"));
+        hasher.finalize().into()
+    }
+}
+
+/// The size and Merkle root of the transparency log. A head commits to every entry before
+/// it, and later heads only ever extend it, see `ConsistencyProof`.
+#[derive(Clone, Serialize, Deserialize, Debug, PartialEq)]
+#[cfg_attr(feature = "openapi", derive(schemars::JsonSchema))]
+pub struct LogHead {
+    pub tree_size: u64,
+    pub root_hash: HashId,
+}
+
+/// Proof that `entry` is in the log at `tree_size`.
+#[derive(Clone, Serialize, Deserialize, Debug)]
+#[cfg_attr(feature = "openapi", derive(schemars::JsonSchema))]
+pub struct InclusionProof {
+    pub entry: LogEntry,
+    pub tree_size: u64,
+    /// Sibling hashes from the leaf up to the root.
+    pub hashes: Vec<HashId>,
+}
+
+impl InclusionProof {
+    /// Whether the proof places `entry` in the log with `head`.
+    pub fn verify(&self, head: &LogHead) -> bool {
+        if self.tree_size != head.tree_size || self.entry.index >= self.tree_size {
+            return false;
+        }
+        let mut index = self.entry.index;
+        let mut last = self.tree_size - 1;
+        let mut hash = self.entry.leaf_hash();
+        for sibling in &self.hashes {
+            if last == 0 {
+                return false;
+            }
+            if index & 1 == 1 || index == last {
+                hash = node_hash(sibling, &hash);
+                while index & 1 == 0 && index != 0 {
+                    index >>= 1;
+                    last >>= 1;
+                }
+            } else {
+                hash = node_hash(&hash, sibling);
+            }
+            index >>= 1;
+            last >>= 1;
+        }
+        last == 0 && hash == head.root_hash
+    }
+}
+
+/// Proof that the log at `to_size` extends the log at `from_size`, so nothing in it was
+/// changed or removed.
+#[derive(Clone, Serialize, Deserialize, Debug)]
+#[cfg_attr(feature = "openapi", derive(schemars::JsonSchema))]
+pub struct ConsistencyProof {
+    pub from_size: u64,
+    pub to_size: u64,
+    pub hashes: Vec<HashId>,
+}
+
+impl ConsistencyProof {
+    /// Whether the proof shows `to` extends `from`.
+    pub fn verify(&self, from: &LogHead, to: &LogHead) -> bool {
+        if self.from_size != from.tree_size
+            || self.to_size != to.tree_size
+            || from.tree_size > to.tree_size
+        {
+            return false;
+        }
+        if from.tree_size == to.tree_size {
+            return self.hashes.is_empty() && from.root_hash == to.root_hash;
+        }
+        if from.tree_size == 0 {
+            return self.hashes.is_empty();
+        }
+        let mut hashes = self.hashes.iter().collect::<Vec<_>>();
+        if from.tree_size.is_power_of_two() {
+            hashes.insert(0, &from.root_hash);
+        }
+        let Some((first, rest)) = hashes.split_first() else {
+            return false;
+        };
+        let mut index = from.tree_size - 1;
+        let mut last = to.tree_size - 1;
+        while index & 1 == 1 {
+            index >>= 1;
+            last >>= 1;
+        }
+        let mut from_hash = (*first).clone();
+        let mut to_hash = (*first).clone();
+        for sibling in rest {
+            if last == 0 {
+                return false;
+            }
+            if index & 1 == 1 || index == last {
+                from_hash = node_hash(sibling, &from_hash);
+                to_hash = node_hash(sibling, &to_hash);
+                while index & 1 == 0 && index != 0 {
+                    index >>= 1;
+                    last >>= 1;
+                }
+            } else {
+                to_hash = node_hash(&to_hash, sibling);
+            }
+            index >>= 1;
+            last >>= 1;
+        }
+        last == 0 && from_hash == from.root_hash && to_hash == to.root_hash
+    }
+}
+
+/// Hash of an interior node of the log's Merkle tree.
+pub fn node_hash(left: &HashId, right: &HashId) -> HashId {
+    let mut hasher = blake3::Hasher::new();
+    hasher.update(&[1]);
+    hasher.update(left.as_bytes());
+    hasher.update(right.as_bytes());
+    hasher.finalize().into()
+}
+
+/// Leaves in the left subtree of a tree of `n` leaves, the largest power of two smaller than
+/// `n`, which must be at least 2.
+pub fn merkle_split(n: usize) -> usize {
+    1 << (usize::BITS - (n - 1).leading_zeros() - 1)
+}
+
+/// Merkle root of the log with the leaf hashes `leaves`, as in RFC 6962 with blake3.
+pub fn merkle_root(leaves: &[HashId]) -> HashId {
+    match leaves.len() {
+        0 => blake3::hash(&[]).into(),
+        1 => leaves[0].clone(),
+        n => {
+            let k = merkle_split(n);
+            node_hash(&merkle_root(&leaves[..k]), &merkle_root(&leaves[k..]))
+        }
+    }
+}
+
+/// Sibling hashes proving leaf `index` is in the log with the leaf hashes `leaves`.
+pub fn inclusion_path(leaves: &[HashId], index: usize) -> Vec<HashId> {
+    if leaves.len() <= 1 {
+        return vec![];
+    }
+    let k = merkle_split(leaves.len());
+    let (mut path, sibling) = if index < k {
+        (
+            inclusion_path(&leaves[..k], index),
+            merkle_root(&leaves[k..]),
+        )
+    } else {
+        (
+            inclusion_path(&leaves[k..], index - k),
+            merkle_root(&leaves[..k]),
+        )
+    };
+    path.push(sibling);
+    path
+}
+
+/// Hashes proving the log with the leaf hashes `leaves` extends its first `from_size` leaves.
+pub fn consistency_path(leaves: &[HashId], from_size: usize) -> Vec<HashId> {
+    fn subproof(from_size: usize, leaves: &[HashId], complete: bool) -> Vec<HashId> {
+        let n = leaves.len();
+        if from_size == n {
+            return if complete {
+                vec![]
+            } else {
+                vec![merkle_root(leaves)]
+            };
+        }
+        let k = merkle_split(n);
+        let (mut path, sibling) = if from_size <= k {
+            (
+                subproof(from_size, &leaves[..k], complete),
+                merkle_root(&leaves[k..]),
+            )
+        } else {
+            (
+                subproof(from_size - k, &leaves[k..], false),
+                merkle_root(&leaves[..k]),
+            )
+        };
+        path.push(sibling);
+        path
+    }
+    if from_size == 0 || from_size >= leaves.len() {
+        return vec![];
+    }
+    subproof(from_size, leaves, true)
+}
+
+#[cfg(feature = "server")]
+impl redb::Value for LogEntry {
+    type SelfType<'a> = LogEntry;
+    type AsBytes<'a> = Vec<u8>;
+
+    fn fixed_width() -> Option<usize> {
+        None // Variable width due to strings
+    }
+
+    fn from_bytes<'a>(data: &'a [u8]) -> Self::SelfType<'a>
+    where
+        Self: 'a,
+    {
+        bincode::deserialize(data).expect("Failed to deserialize LogEntry")
+    }
+
+    fn as_bytes<'a, 'b: 'a>(value: &'a Self::SelfType<'b>) -> Self::AsBytes<'a> {
+        bincode::serialize(value).expect("Failed to serialize LogEntry")
+    }
+
+    fn type_name() -> redb::TypeName {
+        redb::TypeName::new("LogEntry")
+    }
+}
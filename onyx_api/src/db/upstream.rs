@@ -0,0 +1,40 @@
+use serde::Deserialize;
+use serde::Serialize;
+
+/// A tag of an upstream git repository the registry fetched and serves as a single commit,
+/// like a published version.
+#[derive(Clone, Serialize, Deserialize, Debug, PartialEq)]
+pub struct UpstreamTagModel {
+    /// The commit the registry serves, its pack is in `GIT_PACK_TABLE`.
+    pub commit: String,
+    /// The commit the tag pointed at upstream when it was fetched.
+    pub upstream_commit: String,
+    /// blake3 content hash of the files at the tag.
+    pub hash: String,
+    pub fetched_at: u64,
+}
+
+#[cfg(feature = "server")]
+impl redb::Value for UpstreamTagModel {
+    type SelfType<'a> = UpstreamTagModel;
+    type AsBytes<'a> = Vec<u8>;
+
+    fn fixed_width() -> Option<usize> {
+        None // Variable width due to strings
+    }
+
+    fn from_bytes<'a>(data: &'a [u8]) -> Self::SelfType<'a>
+    where
+        Self: 'a,
+    {
+        bincode::deserialize(data).expect("Failed to deserialize UpstreamTagModel")
+    }
+
+    fn as_bytes<'a, 'b: 'a>(value: &'a Self::SelfType<'b>) -> Self::AsBytes<'a> {
+        bincode::serialize(value).expect("Failed to serialize UpstreamTagModel")
+    }
+
+    fn type_name() -> redb::TypeName {
+        redb::TypeName::new("UpstreamTagModel")
+    }
+}
@@ -38,6 +38,7 @@ impl redb::Value for UserModel {
 }
 
 #[derive(Clone, Serialize, Deserialize, Debug, PartialEq)]
+#[cfg_attr(feature = "openapi", derive(schemars::JsonSchema))]
 pub struct UserModelSafe {
     pub id: String,
     pub username: String,
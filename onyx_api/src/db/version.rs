@@ -1,4 +1,7 @@
+use std::collections::BTreeMap;
+
 use anyhow::Result;
+use nargo_parse::NargoConfig;
 use serde::Deserialize;
 use serde::Serialize;
 #[cfg(feature = "server")]
@@ -7,12 +10,227 @@ use tokio::io::AsyncRead;
 use super::*;
 
 #[derive(Clone, Serialize, Deserialize, Debug)]
+#[cfg_attr(feature = "openapi", derive(schemars::JsonSchema))]
 pub struct PackageVersionModel {
     pub id: HashId,
     pub name: String,
     pub author_id: String,
     pub package_id: String,
     pub created_at: u64,
+    /// Url of the git repository the version was built from, if it was published from one.
+    #[serde(default)]
+    pub source_repository: Option<String>,
+    /// Hex encoded hash of the commit the version was built from.
+    #[serde(default)]
+    pub source_commit: Option<String>,
+    /// Read from the Nargo.toml of the version when it was published, so it can be shown without
+    /// downloading the tarball. Missing if the Nargo.toml couldn't be parsed, or for versions
+    /// published before it was recorded.
+    #[serde(default)]
+    pub metadata: Option<VersionMetadata>,
+    /// Whether the registry checked the tarball holds only static content when it was
+    /// published, see `nrpm_tarball::check_static_file`. False for versions published before
+    /// the check.
+    #[serde(default)]
+    pub static_content: bool,
+}
+
+/// The `[package]` section and dependencies of a published Nargo.toml.
+#[derive(Clone, Serialize, Deserialize, Debug, Default, PartialEq)]
+#[cfg_attr(feature = "openapi", derive(schemars::JsonSchema))]
+pub struct VersionMetadata {
+    pub description: Option<String>,
+    pub authors: Vec<String>,
+    pub repository: Option<String>,
+    pub keywords: Vec<String>,
+    pub license: Option<String>,
+    pub dependencies: Vec<VersionMetadataDependency>,
+    /// The `[package.metadata.nrpm]` table searches are filtered by, e.g. `backend`. Empty if
+    /// it isn't valid.
+    #[serde(default)]
+    pub nrpm_metadata: BTreeMap<String, Vec<String>>,
+}
+
+/// A dependency as it's written in Nargo.toml.
+#[derive(Clone, Serialize, Deserialize, Debug, PartialEq)]
+#[cfg_attr(feature = "openapi", derive(schemars::JsonSchema))]
+pub struct VersionMetadataDependency {
+    pub name: String,
+    pub git: Option<String>,
+    pub tag: Option<String>,
+    pub directory: Option<String>,
+    pub path: Option<String>,
+}
+
+impl From<&NargoConfig> for VersionMetadata {
+    fn from(config: &NargoConfig) -> Self {
+        let package = &config.package;
+        Self {
+            description: package.description.clone(),
+            authors: package.authors.clone().unwrap_or_default(),
+            repository: package.repository.clone(),
+            keywords: package.keywords.clone().unwrap_or_default(),
+            license: package.license.clone(),
+            // dependencies that can't be parsed aren't listed
+            dependencies: config
+                .dependencies()
+                .map(|dependencies| {
+                    dependencies
+                        .values()
+                        .map(|dependency| VersionMetadataDependency {
+                            name: dependency.name.clone(),
+                            git: dependency.git.clone(),
+                            tag: dependency.tag.clone(),
+                            directory: dependency.directory.clone(),
+                            path: dependency.path.clone(),
+                        })
+                        .collect()
+                })
+                .unwrap_or_default(),
+            nrpm_metadata: package.nrpm_metadata().unwrap_or_default(),
+        }
+    }
+}
+
+/// Layout of `VersionMetadata` before the `[package.metadata.nrpm]` table was recorded.
+#[cfg(feature = "server")]
+#[derive(Deserialize)]
+struct VersionMetadataV0 {
+    description: Option<String>,
+    authors: Vec<String>,
+    repository: Option<String>,
+    keywords: Vec<String>,
+    license: Option<String>,
+    dependencies: Vec<VersionMetadataDependency>,
+}
+
+/// Layout of `PackageVersionModel` before static content was attested.
+#[cfg(feature = "server")]
+#[derive(Deserialize)]
+struct PackageVersionModelV3 {
+    id: HashId,
+    name: String,
+    author_id: String,
+    package_id: String,
+    created_at: u64,
+    source_repository: Option<String>,
+    source_commit: Option<String>,
+    metadata: Option<VersionMetadata>,
+}
+
+#[cfg(feature = "server")]
+impl From<PackageVersionModelV3> for PackageVersionModel {
+    fn from(value: PackageVersionModelV3) -> Self {
+        Self {
+            id: value.id,
+            name: value.name,
+            author_id: value.author_id,
+            package_id: value.package_id,
+            created_at: value.created_at,
+            source_repository: value.source_repository,
+            source_commit: value.source_commit,
+            metadata: value.metadata,
+            static_content: false,
+        }
+    }
+}
+
+/// Layout of `PackageVersionModel` before the `[package.metadata.nrpm]` table was recorded.
+#[cfg(feature = "server")]
+#[derive(Deserialize)]
+struct PackageVersionModelV2 {
+    id: HashId,
+    name: String,
+    author_id: String,
+    package_id: String,
+    created_at: u64,
+    source_repository: Option<String>,
+    source_commit: Option<String>,
+    metadata: Option<VersionMetadataV0>,
+}
+
+#[cfg(feature = "server")]
+impl From<PackageVersionModelV2> for PackageVersionModel {
+    fn from(value: PackageVersionModelV2) -> Self {
+        Self {
+            id: value.id,
+            name: value.name,
+            author_id: value.author_id,
+            package_id: value.package_id,
+            created_at: value.created_at,
+            source_repository: value.source_repository,
+            source_commit: value.source_commit,
+            metadata: value.metadata.map(|metadata| VersionMetadata {
+                description: metadata.description,
+                authors: metadata.authors,
+                repository: metadata.repository,
+                keywords: metadata.keywords,
+                license: metadata.license,
+                dependencies: metadata.dependencies,
+                nrpm_metadata: BTreeMap::default(),
+            }),
+            static_content: false,
+        }
+    }
+}
+
+/// Layout of `PackageVersionModel` before the metadata of versions was recorded.
+#[cfg(feature = "server")]
+#[derive(Deserialize)]
+struct PackageVersionModelV1 {
+    id: HashId,
+    name: String,
+    author_id: String,
+    package_id: String,
+    created_at: u64,
+    source_repository: Option<String>,
+    source_commit: Option<String>,
+}
+
+#[cfg(feature = "server")]
+impl From<PackageVersionModelV1> for PackageVersionModel {
+    fn from(value: PackageVersionModelV1) -> Self {
+        Self {
+            id: value.id,
+            name: value.name,
+            author_id: value.author_id,
+            package_id: value.package_id,
+            created_at: value.created_at,
+            source_repository: value.source_repository,
+            source_commit: value.source_commit,
+            metadata: None,
+            static_content: false,
+        }
+    }
+}
+
+/// Layout of `PackageVersionModel` before the source of versions was recorded. bincode can't
+/// fill in missing fields so rows written then are decoded with this.
+#[cfg(feature = "server")]
+#[derive(Deserialize)]
+struct PackageVersionModelV0 {
+    id: HashId,
+    name: String,
+    author_id: String,
+    package_id: String,
+    created_at: u64,
+}
+
+#[cfg(feature = "server")]
+impl From<PackageVersionModelV0> for PackageVersionModel {
+    fn from(value: PackageVersionModelV0) -> Self {
+        Self {
+            id: value.id,
+            name: value.name,
+            author_id: value.author_id,
+            package_id: value.package_id,
+            created_at: value.created_at,
+            source_repository: None,
+            source_commit: None,
+            metadata: None,
+            static_content: false,
+        }
+    }
 }
 
 #[cfg(feature = "server")]
@@ -35,7 +253,20 @@ impl redb::Value for PackageVersionModel {
     where
         Self: 'a,
     {
-        bincode::deserialize(data).expect("Failed to deserialize PackageVersionModel")
+        bincode::deserialize(data)
+            .or_else(|_| {
+                bincode::deserialize::<PackageVersionModelV3>(data).map(PackageVersionModel::from)
+            })
+            .or_else(|_| {
+                bincode::deserialize::<PackageVersionModelV2>(data).map(PackageVersionModel::from)
+            })
+            .or_else(|_| {
+                bincode::deserialize::<PackageVersionModelV1>(data).map(PackageVersionModel::from)
+            })
+            .or_else(|_| {
+                bincode::deserialize::<PackageVersionModelV0>(data).map(PackageVersionModel::from)
+            })
+            .expect("Failed to deserialize PackageVersionModel")
     }
 
     fn as_bytes<'a, 'b: 'a>(value: &'a Self::SelfType<'b>) -> Self::AsBytes<'a> {
@@ -0,0 +1,84 @@
+use serde::Deserialize;
+use serde::Serialize;
+
+use crate::http::PublishNotification;
+
+/// Where a user's notifications are sent. The registry POSTs each notification as json, signed
+/// with `secret`, see `WEBHOOK_SIGNATURE_HEADER`.
+#[derive(Clone, Serialize, Deserialize, Debug, PartialEq)]
+#[cfg_attr(feature = "openapi", derive(schemars::JsonSchema))]
+pub struct WebhookModel {
+    pub url: String,
+    /// Key of the HMAC-SHA256 signature of each request body. A new one is generated each time
+    /// the webhook is set.
+    pub secret: String,
+    pub created_at: u64,
+}
+
+/// Header holding the hex encoded HMAC-SHA256 of a webhook request body, keyed with the
+/// webhook's secret.
+pub const WEBHOOK_SIGNATURE_HEADER: &str = "x-nrpm-signature";
+
+/// Header naming what a webhook request is about: `publish` for a `PublishNotification`,
+/// `digest` for a `NotificationDigest`, or `scan` for the `PublishStatusResponse` of an
+/// upload a scanner flagged.
+pub const WEBHOOK_EVENT_HEADER: &str = "x-nrpm-event";
+
+/// A notification waiting to be delivered to the webhook of `user_id`.
+#[derive(Clone, Serialize, Deserialize, Debug, PartialEq)]
+pub struct NotificationModel {
+    pub user_id: String,
+    pub notification: PublishNotification,
+    /// Failed deliveries so far.
+    pub attempts: u32,
+}
+
+#[cfg(feature = "server")]
+impl redb::Value for WebhookModel {
+    type SelfType<'a> = WebhookModel;
+    type AsBytes<'a> = Vec<u8>;
+
+    fn fixed_width() -> Option<usize> {
+        None // Variable width due to strings
+    }
+
+    fn from_bytes<'a>(data: &'a [u8]) -> Self::SelfType<'a>
+    where
+        Self: 'a,
+    {
+        bincode::deserialize(data).expect("Failed to deserialize WebhookModel")
+    }
+
+    fn as_bytes<'a, 'b: 'a>(value: &'a Self::SelfType<'b>) -> Self::AsBytes<'a> {
+        bincode::serialize(value).expect("Failed to serialize WebhookModel")
+    }
+
+    fn type_name() -> redb::TypeName {
+        redb::TypeName::new("WebhookModel")
+    }
+}
+
+#[cfg(feature = "server")]
+impl redb::Value for NotificationModel {
+    type SelfType<'a> = NotificationModel;
+    type AsBytes<'a> = Vec<u8>;
+
+    fn fixed_width() -> Option<usize> {
+        None // Variable width due to strings
+    }
+
+    fn from_bytes<'a>(data: &'a [u8]) -> Self::SelfType<'a>
+    where
+        Self: 'a,
+    {
+        bincode::deserialize(data).expect("Failed to deserialize NotificationModel")
+    }
+
+    fn as_bytes<'a, 'b: 'a>(value: &'a Self::SelfType<'b>) -> Self::AsBytes<'a> {
+        bincode::serialize(value).expect("Failed to serialize NotificationModel")
+    }
+
+    fn type_name() -> redb::TypeName {
+        redb::TypeName::new("NotificationModel")
+    }
+}
@@ -1,6 +1,8 @@
 use anyhow::Result;
+use nargo_parse::PackageDocs;
 use serde_json::json;
 
+use super::error::*;
 use super::types::*;
 use crate::REGISTRY_URL;
 use crate::db::*;
@@ -27,101 +29,1440 @@ impl OnyxApi {
         format!("{}/v0/version/{}", self.url, id.to_string())
     }
 
+    /// Url to download a version by name. The tarball is saved as `{package}_{version}.tar`.
+    pub fn package_download_url(&self, package_name: &str, version_name: &str) -> String {
+        format!(
+            "{}/v0/packages/{package_name}/{version_name}/download",
+            self.url
+        )
+    }
+
+    /// Download the tarball of a version. Registries may redirect to a CDN, so the content
+    /// hash is checked against the version id before the bytes are returned.
     pub async fn download_tarball(&self, version_id: &HashId) -> Result<Vec<u8>> {
         let download_url = self.version_download_url(version_id);
         let response = reqwest::Client::new().get(download_url).send().await?;
         if response.status().is_success() {
             let data = response.bytes().await?;
+            let hash = nrpm_tarball::hash_tarball(&mut std::io::Cursor::new(&data))?;
+            if HashId::from(hash) != *version_id {
+                anyhow::bail!(
+                    "hash mismatch for downloaded tarball, computed: {hash}, expected: {}",
+                    version_id.to_string()
+                );
+            }
             Ok(data.into())
         } else {
-            anyhow::bail!(
-                "failed to download version id \"{}\": {}",
-                version_id.to_string(),
-                response.text().await?
-            );
+            Err(
+                anyhow::Error::from(ApiError::from_response(response).await).context(format!(
+                    "failed to download version id \"{}\"",
+                    version_id.to_string()
+                )),
+            )
+        }
+    }
+
+    /// Download the tarball of a version like `download_tarball`, without buffering it. The
+    /// content is hashed as it's read, see `VerifiedDownload`.
+    #[cfg(feature = "stream")]
+    pub async fn download_tarball_stream(
+        &self,
+        version_id: &HashId,
+    ) -> Result<super::VerifiedDownload> {
+        let download_url = self.version_download_url(version_id);
+        let response = reqwest::Client::new().get(download_url).send().await?;
+        if response.status().is_success() {
+            Ok(super::VerifiedDownload::new(response, version_id.clone()))
+        } else {
+            Err(
+                anyhow::Error::from(ApiError::from_response(response).await).context(format!(
+                    "failed to download version id \"{}\"",
+                    version_id.to_string()
+                )),
+            )
+        }
+    }
+
+    pub async fn load_package_versions(
+        &self,
+        package_name: &str,
+    ) -> Result<(PackageModel, Vec<PackageVersionModel>)> {
+        let response = reqwest::Client::new()
+            .get(format!("{}/v0/packages/{package_name}/versions", self.url))
+            .send()
+            .await?;
+        if response.status().is_success() {
+            let data = response.json().await?;
+            Ok(data)
+        } else {
+            Err(
+                anyhow::Error::from(ApiError::from_response(response).await).context(format!(
+                    "failed to load versions of package \"{package_name}\""
+                )),
+            )
+        }
+    }
+
+    /// Load the api docs extracted from the sources of a version.
+    pub async fn version_docs(&self, version_id: &HashId) -> Result<PackageDocs> {
+        let response = reqwest::Client::new()
+            .get(format!(
+                "{}/v0/version/{}/docs",
+                self.url,
+                version_id.to_string()
+            ))
+            .send()
+            .await?;
+        if response.status().is_success() {
+            let data = response.json().await?;
+            Ok(data)
+        } else {
+            Err(ApiError::from_response(response).await.into())
+        }
+    }
+
+    /// Load the release notes a version was published with. `None` if it has none.
+    pub async fn version_release_notes(&self, version_id: &HashId) -> Result<Option<String>> {
+        let response = reqwest::Client::new()
+            .get(format!(
+                "{}/v0/version/{}/release_notes",
+                self.url,
+                version_id.to_string()
+            ))
+            .send()
+            .await?;
+        if response.status() == reqwest::StatusCode::NOT_FOUND {
+            return Ok(None);
+        }
+        if response.status().is_success() {
+            let data: VersionReleaseNotes = response.json().await?;
+            Ok(Some(data.release_notes))
+        } else {
+            Err(ApiError::from_response(response).await.into())
+        }
+    }
+
+    /// Attach a compiled artifact to a version of a package owned by the user authenticated by
+    /// `token`.
+    pub async fn upload_artifact(
+        &self,
+        token: &str,
+        version_id: &HashId,
+        request: UploadArtifactRequest,
+    ) -> Result<VersionArtifactModel> {
+        let response = reqwest::Client::new()
+            .post(format!(
+                "{}/v0/version/{}/artifacts",
+                self.url,
+                version_id.to_string()
+            ))
+            .bearer_auth(token)
+            .json(&request)
+            .send()
+            .await?;
+        if response.status().is_success() {
+            let data = response.json().await?;
+            Ok(data)
+        } else {
+            Err(ApiError::from_response(response).await.into())
+        }
+    }
+
+    /// The compiled artifacts attached to a version, sorted by name.
+    pub async fn version_artifacts(
+        &self,
+        version_id: &HashId,
+    ) -> Result<Vec<VersionArtifactModel>> {
+        let response = reqwest::Client::new()
+            .get(format!(
+                "{}/v0/version/{}/artifacts",
+                self.url,
+                version_id.to_string()
+            ))
+            .send()
+            .await?;
+        if response.status().is_success() {
+            let data = response.json().await?;
+            Ok(data)
+        } else {
+            Err(ApiError::from_response(response).await.into())
+        }
+    }
+
+    /// Url of the contents of an artifact attached to a version.
+    pub fn artifact_download_url(&self, version_id: &HashId, name: &str) -> String {
+        format!(
+            "{}/v0/version/{}/artifacts/{name}",
+            self.url,
+            version_id.to_string()
+        )
+    }
+
+    /// Download an artifact attached to a version. The contents are checked against the hash
+    /// in `artifact`.
+    pub async fn download_artifact(
+        &self,
+        version_id: &HashId,
+        artifact: &VersionArtifactModel,
+    ) -> Result<String> {
+        let response = reqwest::Client::new()
+            .get(self.artifact_download_url(version_id, &artifact.name))
+            .send()
+            .await?;
+        if response.status().is_success() {
+            let data = response.text().await?;
+            let hash = blake3::hash(data.as_bytes()).to_hex().to_string();
+            if hash != artifact.hash {
+                anyhow::bail!(
+                    "hash mismatch for downloaded artifact \"{}\", computed: {hash}, expected: {}",
+                    artifact.name,
+                    artifact.hash
+                );
+            }
+            Ok(data)
+        } else {
+            Err(ApiError::from_response(response).await.into())
+        }
+    }
+
+    /// The latest rebuild of a version by the registry's verification worker. `None` if the
+    /// version hasn't been rebuilt.
+    pub async fn version_build(&self, version_id: &HashId) -> Result<Option<VersionBuildModel>> {
+        let response = reqwest::Client::new()
+            .get(format!(
+                "{}/v0/version/{}/build",
+                self.url,
+                version_id.to_string()
+            ))
+            .send()
+            .await?;
+        if response.status() == reqwest::StatusCode::NOT_FOUND {
+            return Ok(None);
+        }
+        if response.status().is_success() {
+            let data = response.json().await?;
+            Ok(Some(data))
+        } else {
+            Err(ApiError::from_response(response).await.into())
+        }
+    }
+
+    /// Load the file hashes of a version. The manifest is checked against the version id.
+    pub async fn version_manifest(&self, version_id: &HashId) -> Result<VersionManifest> {
+        let response = reqwest::Client::new()
+            .get(format!(
+                "{}/v0/version/{}/manifest",
+                self.url,
+                version_id.to_string()
+            ))
+            .send()
+            .await?;
+        if response.status().is_success() {
+            let data: VersionManifest = response.json().await?;
+            if HashId::from(data.version_hash()?) != *version_id {
+                anyhow::bail!(
+                    "manifest of version {} does not match its hash",
+                    version_id.to_string()
+                );
+            }
+            Ok(data)
+        } else {
+            Err(ApiError::from_response(response).await.into())
+        }
+    }
+
+    /// Download the delta from the version `from_id` to the version `version_id`, see
+    /// `nrpm_tarball::delta`. `None` if the registry has no delta between them, download the
+    /// whole version instead.
+    pub async fn version_delta(
+        &self,
+        version_id: &HashId,
+        from_id: &HashId,
+    ) -> Result<Option<Vec<u8>>> {
+        let response = reqwest::Client::new()
+            .get(format!(
+                "{}/v0/version/{}/delta?from={}",
+                self.url,
+                version_id.to_string(),
+                from_id.to_string()
+            ))
+            .send()
+            .await?;
+        if response.status() == reqwest::StatusCode::NOT_FOUND {
+            Ok(None)
+        } else if response.status().is_success() {
+            Ok(Some(response.bytes().await?.into()))
+        } else {
+            Err(ApiError::from_response(response).await.into())
+        }
+    }
+
+    /// The files that differ between two versions of a package.
+    pub async fn package_diff(
+        &self,
+        package_name: &str,
+        from_version: &str,
+        to_version: &str,
+    ) -> Result<VersionDiff> {
+        let response = reqwest::Client::new()
+            .get(format!("{}/v0/packages/{package_name}/diff", self.url))
+            .query(&[("from", from_version), ("to", to_version)])
+            .send()
+            .await?;
+        if response.status().is_success() {
+            let data = response.json().await?;
+            Ok(data)
+        } else {
+            Err(
+                anyhow::Error::from(ApiError::from_response(response).await).context(format!(
+                    "failed to compare versions {from_version} and {to_version} of package \"{package_name}\""
+                )),
+            )
+        }
+    }
+
+    /// How many times each version of a package was downloaded.
+    pub async fn package_stats(&self, package_name: &str) -> Result<PackageStatsResponse> {
+        let response = reqwest::Client::new()
+            .get(format!("{}/v0/packages/{package_name}/stats", self.url))
+            .send()
+            .await?;
+        if response.status().is_success() {
+            let data = response.json().await?;
+            Ok(data)
+        } else {
+            Err(ApiError::from_response(response).await.into())
+        }
+    }
+
+    /// Report versions installed without downloading them from the registry, so they're
+    /// counted as downloads.
+    pub async fn report_installs(&self, request: &InstallStatsRequest) -> Result<()> {
+        let response = reqwest::Client::new()
+            .post(format!("{}/v0/stats/installs", self.url))
+            .json(request)
+            .send()
+            .await?;
+        if response.status().is_success() {
+            Ok(())
+        } else {
+            Err(ApiError::from_response(response).await.into())
+        }
+    }
+
+    /// Load the dependencies and dependents of a package.
+    pub async fn package_graph(&self, package_name: &str) -> Result<PackageGraphResponse> {
+        let response = reqwest::Client::new()
+            .get(format!("{}/v0/packages/{package_name}/graph", self.url))
+            .send()
+            .await?;
+        if response.status().is_success() {
+            let data = response.json().await?;
+            Ok(data)
+        } else {
+            Err(ApiError::from_response(response).await.into())
+        }
+    }
+
+    /// Load the dependency tree of a version of a package, the latest version if `version_name`
+    /// is `None`.
+    pub async fn dependency_tree(
+        &self,
+        package_name: &str,
+        version_name: Option<&str>,
+    ) -> Result<DependencyTreeResponse> {
+        let mut request = reqwest::Client::new().get(format!(
+            "{}/v0/packages/{package_name}/graph/tree",
+            self.url
+        ));
+        if let Some(version_name) = version_name {
+            request = request.query(&[("version", version_name)]);
+        }
+        let response = request.send().await?;
+        if response.status().is_success() {
+            let data = response.json().await?;
+            Ok(data)
+        } else {
+            Err(ApiError::from_response(response).await.into())
+        }
+    }
+
+    /// Load the index file for a package. Pass the etag of a cached copy to skip the
+    /// download if it's unchanged.
+    pub async fn index_package(
+        &self,
+        package_name: &str,
+        etag: Option<&str>,
+    ) -> Result<IndexFetch> {
+        let mut request = reqwest::Client::new().get(format!(
+            "{}/v0/index/{}",
+            self.url,
+            index_path(package_name)
+        ));
+        if let Some(etag) = etag {
+            request = request.header(reqwest::header::IF_NONE_MATCH, etag);
+        }
+        let response = request.send().await?;
+        if response.status() == reqwest::StatusCode::NOT_MODIFIED {
+            Ok(IndexFetch::NotModified)
+        } else if response.status().is_success() {
+            let etag = response
+                .headers()
+                .get(reqwest::header::ETAG)
+                .and_then(|v| v.to_str().ok())
+                .map(|v| v.to_string());
+            Ok(IndexFetch::Fetched {
+                package: response.json().await?,
+                etag,
+            })
+        } else {
+            Err(
+                anyhow::Error::from(ApiError::from_response(response).await).context(format!(
+                    "failed to load index for package \"{package_name}\""
+                )),
+            )
+        }
+    }
+
+    /// Load the index entries of many packages in one request, see `BatchPackagesRequest`.
+    pub async fn batch_packages(
+        &self,
+        request: &BatchPackagesRequest,
+    ) -> Result<BatchPackagesResponse> {
+        let response = reqwest::Client::new()
+            .post(format!("{}/v0/packages/batch", self.url))
+            .json(request)
+            .send()
+            .await?;
+        if response.status().is_success() {
+            Ok(response.json().await?)
+        } else {
+            Err(anyhow::Error::from(ApiError::from_response(response).await)
+                .context("failed to load packages"))
+        }
+    }
+
+    pub async fn load_package_latest_version(
+        &self,
+        package_name: &str,
+    ) -> Result<(PackageModel, PackageVersionModel)> {
+        let response = reqwest::Client::new()
+            .get(format!("{}/v0/packages/{package_name}/latest", self.url))
+            .send()
+            .await?;
+        if response.status().is_success() {
+            let data = response.json().await?;
+            Ok(data)
+        } else {
+            Err(
+                anyhow::Error::from(ApiError::from_response(response).await).context(format!(
+                    "failed to determine latest version of package \"{package_name}\""
+                )),
+            )
+        }
+    }
+
+    /// Packages matching the text `q` whose latest version has each `(key, value)` of
+    /// `filters` in its `[package.metadata.nrpm]` table.
+    pub async fn search(
+        &self,
+        q: Option<&str>,
+        filters: &[(&str, &str)],
+    ) -> Result<Vec<(PackageModel, PackageVersionModel)>> {
+        let mut query = filters.to_vec();
+        if let Some(q) = q {
+            query.push(("q", q));
+        }
+        let response = reqwest::Client::new()
+            .get(format!("{}/v0/search", self.url))
+            .query(&query)
+            .send()
+            .await?;
+        if response.status().is_success() {
+            let data = response.json().await?;
+            Ok(data)
+        } else {
+            Err(ApiError::from_response(response).await.into())
+        }
+    }
+
+    pub async fn load_packages(&self) -> Result<Vec<(PackageModel, PackageVersionModel)>> {
+        let response = reqwest::Client::new()
+            .get(format!("{}/v0/packages", self.url))
+            .send()
+            .await?;
+        if response.status().is_success() {
+            let data = response.json().await?;
+            Ok(data)
+        } else {
+            Err(ApiError::from_response(response).await.into())
+        }
+    }
+
+    pub async fn auth(&self, token: String) -> Result<LoginResponse> {
+        let response = reqwest::Client::new()
+            .post(format!("{}/v0/auth", self.url))
+            .json(&TokenOnly { token })
+            .send()
+            .await?;
+        if response.status().is_success() {
+            let data: LoginResponse = response.json().await?;
+            Ok(data)
+        } else {
+            Err(ApiError::from_response(response).await.into())
+        }
+    }
+
+    /// Change the username or password of the user authenticated by `token`.
+    pub async fn update_user(
+        &self,
+        token: &str,
+        request: UpdateUserRequest,
+    ) -> Result<UserModelSafe> {
+        let response = reqwest::Client::new()
+            .patch(format!("{}/v0/user", self.url))
+            .bearer_auth(token)
+            .json(&request)
+            .send()
+            .await?;
+        if response.status().is_success() {
+            let data = response.json().await?;
+            Ok(data)
+        } else {
+            Err(ApiError::from_response(response).await.into())
+        }
+    }
+
+    /// Delete the user authenticated by `token`, revoking all of their sessions.
+    pub async fn delete_user(&self, token: &str, request: DeleteUserRequest) -> Result<()> {
+        let response = reqwest::Client::new()
+            .delete(format!("{}/v0/user", self.url))
+            .bearer_auth(token)
+            .json(&request)
+            .send()
+            .await?;
+        if response.status().is_success() {
+            Ok(())
+        } else {
+            Err(ApiError::from_response(response).await.into())
+        }
+    }
+
+    /// Exchange a refresh token for a new access token and refresh token. The old refresh
+    /// token can't be used again.
+    pub async fn refresh(&self, refresh_token: String) -> Result<LoginResponse> {
+        let response = reqwest::Client::new()
+            .post(format!("{}/v0/auth", self.url))
+            .json(&AuthRequest::Refresh { refresh_token })
+            .send()
+            .await?;
+        if response.status().is_success() {
+            let data: LoginResponse = response.json().await?;
+            Ok(data)
+        } else {
+            Err(ApiError::from_response(response).await.into())
+        }
+    }
+
+    pub async fn propose_token(
+        &self,
+        proposed_token: String,
+        proposed_refresh_token: Option<String>,
+        scope: TokenScope,
+        token: String,
+    ) -> Result<()> {
+        let response = reqwest::Client::new()
+            .post(format!("{}/v0/propose_token", self.url))
+            .json(&ProposeToken {
+                token,
+                proposed_token,
+                proposed_refresh_token,
+                scope,
+            })
+            .send()
+            .await?;
+        if response.status().is_success() {
+            Ok(())
+        } else {
+            Err(ApiError::from_response(response).await.into())
+        }
+    }
+
+    /// Load changelog entries with sequence numbers greater than `since`.
+    pub async fn changelog(&self, since: u64, limit: usize) -> Result<ChangelogResponse> {
+        let response = reqwest::Client::new()
+            .get(format!(
+                "{}/v0/changelog?since={since}&limit={limit}",
+                self.url
+            ))
+            .send()
+            .await?;
+        if response.status().is_success() {
+            let data = response.json().await?;
+            Ok(data)
+        } else {
+            Err(ApiError::from_response(response).await.into())
+        }
+    }
+
+    /// The signed root of the registry, the latest one unless `version` is given. `None` if
+    /// the registry doesn't sign snapshots or has no such version.
+    pub async fn registry_root(&self, version: Option<u64>) -> Result<Option<SignedDocument>> {
+        let url = match version {
+            Some(version) => format!("{}/v0/root/{version}", self.url),
+            None => format!("{}/v0/root", self.url),
+        };
+        let response = reqwest::Client::new().get(url).send().await?;
+        if response.status() == reqwest::StatusCode::NOT_FOUND {
+            return Ok(None);
+        }
+        if response.status().is_success() {
+            let data: SignedDocument = response.json().await?;
+            Ok(Some(data))
+        } else {
+            Err(ApiError::from_response(response).await.into())
+        }
+    }
+
+    /// The latest signed `Snapshot` of the registry. `None` if the registry doesn't sign
+    /// snapshots or hasn't signed one yet.
+    pub async fn snapshot(&self) -> Result<Option<SignedDocument>> {
+        let response = reqwest::Client::new()
+            .get(format!("{}/v0/snapshot", self.url))
+            .send()
+            .await?;
+        if response.status() == reqwest::StatusCode::NOT_FOUND {
+            return Ok(None);
+        }
+        if response.status().is_success() {
+            let data: SignedDocument = response.json().await?;
+            Ok(Some(data))
+        } else {
+            Err(ApiError::from_response(response).await.into())
+        }
+    }
+
+    /// The current head of the transparency log.
+    pub async fn log_head(&self) -> Result<LogHead> {
+        let response = reqwest::Client::new()
+            .get(format!("{}/v0/log", self.url))
+            .send()
+            .await?;
+        if response.status().is_success() {
+            let data = response.json().await?;
+            Ok(data)
+        } else {
+            Err(ApiError::from_response(response).await.into())
+        }
+    }
+
+    /// Up to `limit` transparency log entries, starting at index `start`.
+    pub async fn log_entries(&self, start: u64, limit: usize) -> Result<LogEntriesResponse> {
+        let response = reqwest::Client::new()
+            .get(format!(
+                "{}/v0/log/entries?start={start}&limit={limit}",
+                self.url
+            ))
+            .send()
+            .await?;
+        if response.status().is_success() {
+            let data = response.json().await?;
+            Ok(data)
+        } else {
+            Err(ApiError::from_response(response).await.into())
+        }
+    }
+
+    /// Proof that the publish of `version_id` is in the transparency log at `tree_size`, the
+    /// current size unless given.
+    pub async fn log_inclusion_proof(
+        &self,
+        version_id: &HashId,
+        tree_size: Option<u64>,
+    ) -> Result<InclusionProof> {
+        let mut url = format!("{}/v0/log/proof/{}", self.url, version_id.to_string());
+        if let Some(tree_size) = tree_size {
+            url = format!("{url}?tree_size={tree_size}");
+        }
+        let response = reqwest::Client::new().get(url).send().await?;
+        if response.status().is_success() {
+            let data = response.json().await?;
+            Ok(data)
+        } else {
+            Err(ApiError::from_response(response).await.into())
+        }
+    }
+
+    /// Proof that the transparency log at `to_size` extends the log at `from_size`.
+    pub async fn log_consistency_proof(
+        &self,
+        from_size: u64,
+        to_size: u64,
+    ) -> Result<ConsistencyProof> {
+        let response = reqwest::Client::new()
+            .get(format!(
+                "{}/v0/log/consistency?from={from_size}&to={to_size}",
+                self.url
+            ))
+            .send()
+            .await?;
+        if response.status().is_success() {
+            let data = response.json().await?;
+            Ok(data)
+        } else {
+            Err(ApiError::from_response(response).await.into())
+        }
+    }
+
+    /// The api version and features of the registry. Registries that predate `/v0/meta` are
+    /// described by `MetaResponse::legacy`.
+    pub async fn meta(&self) -> Result<MetaResponse> {
+        let response = reqwest::Client::new()
+            .get(format!("{}/v0/meta", self.url))
+            .send()
+            .await?;
+        if response.status().is_success() {
+            let data: MetaResponse = response.json().await?;
+            Ok(data)
+        } else if response.status() == reqwest::StatusCode::NOT_FOUND {
+            Ok(MetaResponse::legacy())
+        } else {
+            Err(ApiError::from_response(response).await.into())
+        }
+    }
+
+    /// Fail with an `ApiVersionMismatch` if this client can't talk to the registry.
+    pub async fn check_compatibility(&self) -> Result<MetaResponse> {
+        let meta = self.meta().await?;
+        meta.check_compatibility(&self.url)?;
+        Ok(meta)
+    }
+
+    /// A client for the registry at `url`, checked for compatibility with it.
+    pub async fn connect(url: String) -> Result<Self> {
+        let api = Self { url };
+        api.check_compatibility().await?;
+        Ok(api)
+    }
+
+    /// The latest release of the nrpm cli, if the registry announces one.
+    pub async fn cli_version(&self) -> Result<String> {
+        let response = reqwest::Client::new()
+            .get(format!("{}/v0/cli/version", self.url))
+            .send()
+            .await?;
+        if response.status().is_success() {
+            let data: CliVersionResponse = response.json().await?;
+            Ok(data.version)
+        } else {
+            Err(ApiError::from_response(response).await.into())
+        }
+    }
+
+    /// List the active sessions of the user authenticated by `token`.
+    pub async fn sessions(&self, token: &str) -> Result<Vec<SessionInfo>> {
+        let response = reqwest::Client::new()
+            .get(format!("{}/v0/sessions", self.url))
+            .bearer_auth(token)
+            .send()
+            .await?;
+        if response.status().is_success() {
+            let data = response.json().await?;
+            Ok(data)
+        } else {
+            Err(ApiError::from_response(response).await.into())
+        }
+    }
+
+    /// Revoke the session whose token starts with `token_prefix`.
+    pub async fn revoke_session(&self, token: &str, token_prefix: &str) -> Result<()> {
+        let response = reqwest::Client::new()
+            .delete(format!("{}/v0/sessions/{token_prefix}", self.url))
+            .bearer_auth(token)
+            .send()
+            .await?;
+        if response.status().is_success() {
+            Ok(())
+        } else {
+            Err(ApiError::from_response(response).await.into())
+        }
+    }
+
+    /// The webhook notifications for the user authenticated by `token` are sent to.
+    pub async fn webhook(&self, token: &str) -> Result<WebhookModel> {
+        let response = reqwest::Client::new()
+            .get(format!("{}/v0/webhook", self.url))
+            .bearer_auth(token)
+            .send()
+            .await?;
+        if response.status().is_success() {
+            let data = response.json().await?;
+            Ok(data)
+        } else {
+            Err(ApiError::from_response(response).await.into())
+        }
+    }
+
+    /// Send notifications for the user authenticated by `token` to a webhook, see
+    /// `SetWebhookRequest`.
+    pub async fn set_webhook(
+        &self,
+        token: &str,
+        request: &SetWebhookRequest,
+    ) -> Result<WebhookModel> {
+        let response = reqwest::Client::new()
+            .put(format!("{}/v0/webhook", self.url))
+            .bearer_auth(token)
+            .json(request)
+            .send()
+            .await?;
+        if response.status().is_success() {
+            let data = response.json().await?;
+            Ok(data)
+        } else {
+            Err(ApiError::from_response(response).await.into())
+        }
+    }
+
+    /// Stop sending notifications for the user authenticated by `token`.
+    pub async fn delete_webhook(&self, token: &str) -> Result<()> {
+        let response = reqwest::Client::new()
+            .delete(format!("{}/v0/webhook", self.url))
+            .bearer_auth(token)
+            .send()
+            .await?;
+        if response.status().is_success() {
+            Ok(())
+        } else {
+            Err(ApiError::from_response(response).await.into())
+        }
+    }
+
+    /// Notify the authenticated user of the versions a package publishes.
+    pub async fn watch_package(&self, token: &str, package_name: &str) -> Result<()> {
+        let response = reqwest::Client::new()
+            .put(format!("{}/v0/packages/{package_name}/watch", self.url))
+            .bearer_auth(token)
+            .send()
+            .await?;
+        if response.status().is_success() {
+            Ok(())
+        } else {
+            Err(ApiError::from_response(response).await.into())
+        }
+    }
+
+    /// Stop notifying the authenticated user of a package.
+    pub async fn unwatch_package(&self, token: &str, package_name: &str) -> Result<()> {
+        let response = reqwest::Client::new()
+            .delete(format!("{}/v0/packages/{package_name}/watch", self.url))
+            .bearer_auth(token)
+            .send()
+            .await?;
+        if response.status().is_success() {
+            Ok(())
+        } else {
+            Err(ApiError::from_response(response).await.into())
+        }
+    }
+
+    /// Names of the packages the authenticated user watches.
+    pub async fn watches(&self, token: &str) -> Result<Vec<String>> {
+        let response = reqwest::Client::new()
+            .get(format!("{}/v0/watches", self.url))
+            .bearer_auth(token)
+            .send()
+            .await?;
+        if response.status().is_success() {
+            let data = response.json().await?;
+            Ok(data)
+        } else {
+            Err(ApiError::from_response(response).await.into())
+        }
+    }
+
+    /// The newest notifications of the authenticated user.
+    pub async fn notifications(&self, token: &str) -> Result<NotificationsResponse> {
+        let response = reqwest::Client::new()
+            .get(format!("{}/v0/notifications", self.url))
+            .bearer_auth(token)
+            .send()
+            .await?;
+        if response.status().is_success() {
+            let data = response.json().await?;
+            Ok(data)
+        } else {
+            Err(ApiError::from_response(response).await.into())
+        }
+    }
+
+    /// Mark the authenticated user's notifications up to `seq` read.
+    pub async fn mark_notifications_read(&self, token: &str, seq: u64) -> Result<()> {
+        let response = reqwest::Client::new()
+            .post(format!("{}/v0/notifications/read", self.url))
+            .bearer_auth(token)
+            .json(&MarkNotificationsReadRequest { seq })
+            .send()
+            .await?;
+        if response.status().is_success() {
+            Ok(())
+        } else {
+            Err(ApiError::from_response(response).await.into())
+        }
+    }
+
+    /// Send a daily digest of new notifications to the authenticated user's webhook.
+    pub async fn enable_digest(&self, token: &str) -> Result<()> {
+        let response = reqwest::Client::new()
+            .put(format!("{}/v0/notifications/digest", self.url))
+            .bearer_auth(token)
+            .send()
+            .await?;
+        if response.status().is_success() {
+            Ok(())
+        } else {
+            Err(ApiError::from_response(response).await.into())
+        }
+    }
+
+    /// Stop sending the authenticated user digests.
+    pub async fn disable_digest(&self, token: &str) -> Result<()> {
+        let response = reqwest::Client::new()
+            .delete(format!("{}/v0/notifications/digest", self.url))
+            .bearer_auth(token)
+            .send()
+            .await?;
+        if response.status().is_success() {
+            Ok(())
+        } else {
+            Err(ApiError::from_response(response).await.into())
+        }
+    }
+
+    /// Add a signing key to the user authenticated by `token`, see `AddKeyRequest`.
+    pub async fn add_key(&self, token: &str, request: AddKeyRequest) -> Result<UserKeyModel> {
+        let response = reqwest::Client::new()
+            .post(format!("{}/v0/keys", self.url))
+            .bearer_auth(token)
+            .json(&request)
+            .send()
+            .await?;
+        if response.status().is_success() {
+            let data = response.json().await?;
+            Ok(data)
+        } else {
+            Err(ApiError::from_response(response).await.into())
+        }
+    }
+
+    /// Revoke a signing key of the user authenticated by `token`. The key stays listed so
+    /// signatures made with it are known to be untrusted.
+    pub async fn revoke_key(&self, token: &str, public_key: &str) -> Result<UserKeyModel> {
+        let response = reqwest::Client::new()
+            .delete(format!("{}/v0/keys/{public_key}", self.url))
+            .bearer_auth(token)
+            .send()
+            .await?;
+        if response.status().is_success() {
+            let data = response.json().await?;
+            Ok(data)
+        } else {
+            Err(ApiError::from_response(response).await.into())
+        }
+    }
+
+    /// Every signing key `username` has added, oldest first, including rotated and revoked
+    /// keys.
+    pub async fn user_keys(&self, username: &str) -> Result<Vec<UserKeyModel>> {
+        let response = reqwest::Client::new()
+            .get(format!("{}/v0/users/{username}/keys", self.url))
+            .send()
+            .await?;
+        if response.status().is_success() {
+            let data = response.json().await?;
+            Ok(data)
+        } else {
+            Err(ApiError::from_response(response).await.into())
+        }
+    }
+
+    /// Offer a package owned by the authenticated user to another user.
+    pub async fn request_transfer(
+        &self,
+        token: &str,
+        package_name: &str,
+        to_username: &str,
+    ) -> Result<TransferRequestModel> {
+        let response = reqwest::Client::new()
+            .post(format!("{}/v0/packages/{package_name}/transfer", self.url))
+            .bearer_auth(token)
+            .json(&TransferPackageRequest {
+                to_username: to_username.to_string(),
+            })
+            .send()
+            .await?;
+        if response.status().is_success() {
+            let data = response.json().await?;
+            Ok(data)
+        } else {
+            Err(ApiError::from_response(response).await.into())
         }
     }
 
-    pub async fn load_package_versions(
+    /// Change the metadata of a package as its owner, see `UpdatePackageRequest`.
+    pub async fn update_package(
         &self,
+        token: &str,
         package_name: &str,
-    ) -> Result<(PackageModel, Vec<PackageVersionModel>)> {
+        request: &UpdatePackageRequest,
+    ) -> Result<PackageModel> {
         let response = reqwest::Client::new()
-            .get(format!("{}/v0/packages/{package_name}/versions", self.url))
+            .patch(format!("{}/v0/packages/{package_name}", self.url))
+            .bearer_auth(token)
+            .json(request)
             .send()
             .await?;
         if response.status().is_success() {
             let data = response.json().await?;
             Ok(data)
         } else {
-            anyhow::bail!(
-                "failed to load versions of package \"{}\": {}",
-                package_name,
-                response.text().await?
-            );
+            Err(ApiError::from_response(response).await.into())
         }
     }
 
-    pub async fn load_package_latest_version(
+    /// Cancel a pending transfer as the sender, or decline it as the recipient.
+    pub async fn cancel_transfer(&self, token: &str, package_name: &str) -> Result<()> {
+        let response = reqwest::Client::new()
+            .delete(format!("{}/v0/packages/{package_name}/transfer", self.url))
+            .bearer_auth(token)
+            .send()
+            .await?;
+        if response.status().is_success() {
+            Ok(())
+        } else {
+            Err(ApiError::from_response(response).await.into())
+        }
+    }
+
+    /// Accept a package offered to the authenticated user.
+    pub async fn accept_transfer(&self, token: &str, package_name: &str) -> Result<PackageModel> {
+        let response = reqwest::Client::new()
+            .post(format!(
+                "{}/v0/packages/{package_name}/transfer/accept",
+                self.url
+            ))
+            .bearer_auth(token)
+            .send()
+            .await?;
+        if response.status().is_success() {
+            let data = response.json().await?;
+            Ok(data)
+        } else {
+            Err(ApiError::from_response(response).await.into())
+        }
+    }
+
+    /// List pending transfers sent or received by the authenticated user.
+    pub async fn transfers(&self, token: &str) -> Result<TransfersResponse> {
+        let response = reqwest::Client::new()
+            .get(format!("{}/v0/transfers", self.url))
+            .bearer_auth(token)
+            .send()
+            .await?;
+        if response.status().is_success() {
+            let data = response.json().await?;
+            Ok(data)
+        } else {
+            Err(ApiError::from_response(response).await.into())
+        }
+    }
+
+    /// The storage the authenticated user uses and their quota.
+    pub async fn storage_usage(&self, token: &str) -> Result<StorageUsage> {
+        let response = reqwest::Client::new()
+            .get(format!("{}/v0/usage", self.url))
+            .bearer_auth(token)
+            .send()
+            .await?;
+        if response.status().is_success() {
+            let data = response.json().await?;
+            Ok(data)
+        } else {
+            Err(ApiError::from_response(response).await.into())
+        }
+    }
+
+    /// Ask the registry admins to hand over a package, e.g. because it was abandoned.
+    pub async fn claim_package(
         &self,
+        token: &str,
         package_name: &str,
-    ) -> Result<(PackageModel, PackageVersionModel)> {
+        reason: &str,
+    ) -> Result<PackageClaimModel> {
         let response = reqwest::Client::new()
-            .get(format!("{}/v0/packages/{package_name}/latest", self.url))
+            .post(format!("{}/v0/packages/{package_name}/claim", self.url))
+            .bearer_auth(token)
+            .json(&ClaimPackageRequest {
+                reason: reason.to_string(),
+            })
             .send()
             .await?;
         if response.status().is_success() {
             let data = response.json().await?;
             Ok(data)
         } else {
-            anyhow::bail!(
-                "failed to determine latest version of package \"{}\": {}",
-                package_name,
-                response.text().await?
-            );
+            Err(ApiError::from_response(response).await.into())
         }
     }
 
-    pub async fn load_packages(&self) -> Result<Vec<(PackageModel, PackageVersionModel)>> {
+    /// List open package claims. Requires an admin token.
+    pub async fn admin_claims(&self, token: &str) -> Result<Vec<PackageClaimModel>> {
         let response = reqwest::Client::new()
-            .get(format!("{}/v0/packages", self.url))
+            .get(format!("{}/v0/admin/claims", self.url))
+            .bearer_auth(token)
             .send()
             .await?;
         if response.status().is_success() {
             let data = response.json().await?;
             Ok(data)
         } else {
-            anyhow::bail!("{}", response.text().await?);
+            Err(ApiError::from_response(response).await.into())
         }
     }
 
-    pub async fn auth(&self, token: String) -> Result<LoginResponse> {
+    /// Rebuild the registry's static index from scratch. Requires an admin token.
+    pub async fn admin_export_index(&self, token: &str) -> Result<IndexExportResponse> {
         let response = reqwest::Client::new()
-            .post(format!("{}/v0/auth", self.url))
-            .json(&TokenOnly { token })
+            .post(format!("{}/v0/admin/index", self.url))
+            .bearer_auth(token)
             .send()
             .await?;
         if response.status().is_success() {
-            let data: LoginResponse = response.json().await?;
+            let data = response.json().await?;
             Ok(data)
         } else {
-            anyhow::bail!("{}", response.text().await?);
+            Err(ApiError::from_response(response).await.into())
         }
     }
 
-    pub async fn propose_token(&self, proposed_token: String, token: String) -> Result<()> {
+    /// Clone repositories and publish their tags as versions owned by a user. Requires an admin
+    /// token.
+    pub async fn admin_import(
+        &self,
+        token: &str,
+        request: &ImportRequest,
+    ) -> Result<ImportResponse> {
         let response = reqwest::Client::new()
-            .post(format!("{}/v0/propose_token", self.url))
-            .json(&ProposeToken {
-                token,
-                proposed_token,
+            .post(format!("{}/v0/admin/import", self.url))
+            .bearer_auth(token)
+            .json(request)
+            .send()
+            .await?;
+        if response.status().is_success() {
+            let data = response.json().await?;
+            Ok(data)
+        } else {
+            Err(ApiError::from_response(response).await.into())
+        }
+    }
+
+    /// Row counts and sizes of each table in the registry's database. Requires an admin token.
+    pub async fn admin_db_stats(&self, token: &str) -> Result<DbStatsResponse> {
+        let response = reqwest::Client::new()
+            .get(format!("{}/v0/admin/db", self.url))
+            .bearer_auth(token)
+            .send()
+            .await?;
+        if response.status().is_success() {
+            let data = response.json().await?;
+            Ok(data)
+        } else {
+            Err(ApiError::from_response(response).await.into())
+        }
+    }
+
+    /// How many database transactions each route made and how long they took. Requires an
+    /// admin token.
+    pub async fn admin_db_metrics(&self, token: &str) -> Result<DbMetricsResponse> {
+        let response = reqwest::Client::new()
+            .get(format!("{}/v0/admin/db/metrics", self.url))
+            .bearer_auth(token)
+            .send()
+            .await?;
+        if response.status().is_success() {
+            let data = response.json().await?;
+            Ok(data)
+        } else {
+            Err(ApiError::from_response(response).await.into())
+        }
+    }
+
+    /// Check that rows of the registry's database refer to rows and files that exist, and
+    /// optionally repair them. Requires an admin token.
+    pub async fn admin_db_check(&self, token: &str, repair: bool) -> Result<DbCheckResponse> {
+        let response = reqwest::Client::new()
+            .post(format!("{}/v0/admin/db/check", self.url))
+            .bearer_auth(token)
+            .json(&DbCheckRequest { repair })
+            .send()
+            .await?;
+        if response.status().is_success() {
+            let data = response.json().await?;
+            Ok(data)
+        } else {
+            Err(ApiError::from_response(response).await.into())
+        }
+    }
+
+    /// Reassign a package without the involvement of its owner. Requires an admin token.
+    pub async fn admin_transfer(
+        &self,
+        token: &str,
+        package_name: &str,
+        to_username: &str,
+        reason: &str,
+    ) -> Result<PackageModel> {
+        let response = reqwest::Client::new()
+            .post(format!(
+                "{}/v0/admin/packages/{package_name}/transfer",
+                self.url
+            ))
+            .bearer_auth(token)
+            .json(&AdminTransferRequest {
+                to_username: to_username.to_string(),
+                reason: reason.to_string(),
             })
             .send()
             .await?;
+        if response.status().is_success() {
+            let data = response.json().await?;
+            Ok(data)
+        } else {
+            Err(ApiError::from_response(response).await.into())
+        }
+    }
+
+    /// The storage a user uses and their quota. Requires an admin token.
+    pub async fn admin_quota(&self, token: &str, username: &str) -> Result<StorageUsage> {
+        let response = reqwest::Client::new()
+            .get(format!("{}/v0/admin/users/{username}/quota", self.url))
+            .bearer_auth(token)
+            .send()
+            .await?;
+        if response.status().is_success() {
+            let data = response.json().await?;
+            Ok(data)
+        } else {
+            Err(ApiError::from_response(response).await.into())
+        }
+    }
+
+    /// Set the storage quota of a user, or go back to the registry default with `None`.
+    /// Requires an admin token.
+    pub async fn admin_set_quota(
+        &self,
+        token: &str,
+        username: &str,
+        quota_bytes: Option<u64>,
+    ) -> Result<StorageUsage> {
+        let response = reqwest::Client::new()
+            .put(format!("{}/v0/admin/users/{username}/quota", self.url))
+            .bearer_auth(token)
+            .json(&SetQuotaRequest { quota_bytes })
+            .send()
+            .await?;
+        if response.status().is_success() {
+            let data = response.json().await?;
+            Ok(data)
+        } else {
+            Err(ApiError::from_response(response).await.into())
+        }
+    }
+
+    /// Report a package to the registry admins, e.g. because it contains malware.
+    pub async fn report_package(
+        &self,
+        token: &str,
+        package_name: &str,
+        request: ReportPackageRequest,
+    ) -> Result<PackageReportModel> {
+        let response = reqwest::Client::new()
+            .post(format!("{}/v0/packages/{package_name}/report", self.url))
+            .bearer_auth(token)
+            .json(&request)
+            .send()
+            .await?;
+        if response.status().is_success() {
+            let data = response.json().await?;
+            Ok(data)
+        } else {
+            Err(ApiError::from_response(response).await.into())
+        }
+    }
+
+    /// Open reports waiting for review, oldest first. Admin only.
+    pub async fn admin_reports(&self, token: &str) -> Result<Vec<PackageReportModel>> {
+        let response = reqwest::Client::new()
+            .get(format!("{}/v0/admin/reports", self.url))
+            .bearer_auth(token)
+            .send()
+            .await?;
+        if response.status().is_success() {
+            let data = response.json().await?;
+            Ok(data)
+        } else {
+            Err(ApiError::from_response(response).await.into())
+        }
+    }
+
+    /// Dismiss a report or act on it. Admin only.
+    pub async fn admin_resolve_report(
+        &self,
+        token: &str,
+        report_id: &str,
+        request: ResolveReportRequest,
+    ) -> Result<PackageReportModel> {
+        let response = reqwest::Client::new()
+            .post(format!("{}/v0/admin/reports/{report_id}/resolve", self.url))
+            .bearer_auth(token)
+            .json(&request)
+            .send()
+            .await?;
+        if response.status().is_success() {
+            let data = response.json().await?;
+            Ok(data)
+        } else {
+            Err(ApiError::from_response(response).await.into())
+        }
+    }
+
+    /// New package names held for review, oldest first. Admin only.
+    pub async fn admin_name_reviews(&self, token: &str) -> Result<Vec<NameReviewModel>> {
+        let response = reqwest::Client::new()
+            .get(format!("{}/v0/admin/names", self.url))
+            .bearer_auth(token)
+            .send()
+            .await?;
+        if response.status().is_success() {
+            let data = response.json().await?;
+            Ok(data)
+        } else {
+            Err(ApiError::from_response(response).await.into())
+        }
+    }
+
+    /// Let the user who asked for a held package name publish it. Admin only.
+    pub async fn admin_approve_name(
+        &self,
+        token: &str,
+        package_name: &str,
+    ) -> Result<NameReviewModel> {
+        let response = reqwest::Client::new()
+            .post(format!(
+                "{}/v0/admin/names/{package_name}/approve",
+                self.url
+            ))
+            .bearer_auth(token)
+            .send()
+            .await?;
+        if response.status().is_success() {
+            let data = response.json().await?;
+            Ok(data)
+        } else {
+            Err(ApiError::from_response(response).await.into())
+        }
+    }
+
+    /// Decline a held package name. Admin only.
+    pub async fn admin_decline_name(&self, token: &str, package_name: &str) -> Result<()> {
+        let response = reqwest::Client::new()
+            .delete(format!("{}/v0/admin/names/{package_name}", self.url))
+            .bearer_auth(token)
+            .send()
+            .await?;
         if response.status().is_success() {
             Ok(())
         } else {
-            anyhow::bail!("{}", response.text().await?);
+            Err(ApiError::from_response(response).await.into())
+        }
+    }
+
+    /// Uploads waiting to be scanned or flagged by a scanner, oldest first. Admin only.
+    pub async fn admin_pending_publishes(&self, token: &str) -> Result<Vec<PendingPublishModel>> {
+        let response = reqwest::Client::new()
+            .get(format!("{}/v0/admin/publishes", self.url))
+            .bearer_auth(token)
+            .send()
+            .await?;
+        if response.status().is_success() {
+            let data = response.json().await?;
+            Ok(data)
+        } else {
+            Err(ApiError::from_response(response).await.into())
+        }
+    }
+
+    /// Publish a pending or flagged upload regardless of what the scanners found. Admin only.
+    pub async fn admin_release_publish(
+        &self,
+        token: &str,
+        version_id: &HashId,
+    ) -> Result<PublishStatusResponse> {
+        let response = reqwest::Client::new()
+            .post(format!(
+                "{}/v0/admin/publishes/{}/release",
+                self.url,
+                version_id.to_string()
+            ))
+            .bearer_auth(token)
+            .send()
+            .await?;
+        if response.status().is_success() {
+            let data = response.json().await?;
+            Ok(data)
+        } else {
+            Err(ApiError::from_response(response).await.into())
+        }
+    }
+
+    /// Reject a pending or flagged upload, discarding its tarball. Admin only.
+    pub async fn admin_reject_publish(&self, token: &str, version_id: &HashId) -> Result<()> {
+        let response = reqwest::Client::new()
+            .delete(format!(
+                "{}/v0/admin/publishes/{}",
+                self.url,
+                version_id.to_string()
+            ))
+            .bearer_auth(token)
+            .send()
+            .await?;
+        if response.status().is_success() {
+            Ok(())
+        } else {
+            Err(ApiError::from_response(response).await.into())
+        }
+    }
+
+    /// Entries of the audit log after `since`, oldest first. Admin only.
+    pub async fn admin_audit_log(
+        &self,
+        token: &str,
+        since: u64,
+        limit: usize,
+    ) -> Result<AuditLogResponse> {
+        let response = reqwest::Client::new()
+            .get(format!(
+                "{}/v0/admin/audit?since={since}&limit={limit}",
+                self.url
+            ))
+            .bearer_auth(token)
+            .send()
+            .await?;
+        if response.status().is_success() {
+            let data = response.json().await?;
+            Ok(data)
+        } else {
+            Err(ApiError::from_response(response).await.into())
         }
     }
 
@@ -141,7 +1482,7 @@ impl OnyxApi {
 
             Ok(data)
         } else {
-            anyhow::bail!("{}", response.text().await?);
+            Err(ApiError::from_response(response).await.into())
         }
     }
 
@@ -155,12 +1496,59 @@ impl OnyxApi {
             let data: LoginResponse = response.json().await?;
             Ok(data)
         } else {
-            anyhow::bail!("{}", response.text().await?);
+            Err(ApiError::from_response(response).await.into())
+        }
+    }
+
+    /// Where an upload of the user is on its way to being published, see
+    /// `PublishResponse::status`.
+    pub async fn publish_status(
+        &self,
+        token: &str,
+        version_id: &HashId,
+    ) -> Result<PublishStatusResponse> {
+        let response = reqwest::Client::new()
+            .get(format!(
+                "{}/v0/publish/{}",
+                self.url,
+                version_id.to_string()
+            ))
+            .bearer_auth(token)
+            .send()
+            .await?;
+        if response.status().is_success() {
+            let data = response.json().await?;
+            Ok(data)
+        } else {
+            Err(ApiError::from_response(response).await.into())
         }
     }
 
     #[cfg(feature = "publish")]
     pub async fn publish(&self, request: PublishData, tarball: Vec<u8>) -> Result<PublishResponse> {
+        self.publish_inner(request, tarball, None).await
+    }
+
+    /// Publish with an idempotency key. Retrying a publish with the same key is safe, the
+    /// registry will respond with the result of the first successful attempt.
+    #[cfg(feature = "publish")]
+    pub async fn publish_with_idempotency_key(
+        &self,
+        request: PublishData,
+        tarball: Vec<u8>,
+        idempotency_key: &str,
+    ) -> Result<PublishResponse> {
+        self.publish_inner(request, tarball, Some(idempotency_key))
+            .await
+    }
+
+    #[cfg(feature = "publish")]
+    async fn publish_inner(
+        &self,
+        request: PublishData,
+        tarball: Vec<u8>,
+        idempotency_key: Option<&str>,
+    ) -> Result<PublishResponse> {
         use reqwest::multipart;
 
         let form = multipart::Form::new()
@@ -172,22 +1560,21 @@ impl OnyxApi {
             )
             .part(
                 "publish_data",
-                // idk about this use of bincode
-                // feels like a serialize to json
-                //
-                // ehhh no publish from web
-                multipart::Part::bytes(bincode::serialize(&request)?),
+                multipart::Part::bytes(serde_json::to_vec(&request)?)
+                    .mime_str("application/json")?,
             );
-        let response = reqwest::Client::new()
+        let mut builder = reqwest::Client::new()
             .post(format!("{}/v0/publish", self.url))
-            .multipart(form)
-            .send()
-            .await?;
+            .multipart(form);
+        if let Some(idempotency_key) = idempotency_key {
+            builder = builder.header(IDEMPOTENCY_KEY_HEADER, idempotency_key);
+        }
+        let response = builder.send().await?;
         if response.status().is_success() {
             let data: PublishResponse = response.json().await?;
             Ok(data)
         } else {
-            anyhow::bail!("{}", response.text().await?);
+            Err(ApiError::from_response(response).await.into())
         }
     }
 }
@@ -0,0 +1,240 @@
+use std::fmt::Display;
+
+use serde::Deserialize;
+use serde::Serialize;
+
+/// Machine readable error codes returned by onyx servers.
+#[derive(Clone, Copy, Debug, Default, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+#[cfg_attr(feature = "openapi", derive(schemars::JsonSchema))]
+pub enum OnyxErrorCode {
+    /// The request is malformed.
+    BadRequest,
+    /// The request is well formed but one or more fields are invalid. See
+    /// `ApiError::field_errors`.
+    ValidationFailed,
+    /// Login failed because of an unknown username or bad password.
+    InvalidCredentials,
+    /// The supplied auth token does not exist.
+    InvalidToken,
+    /// The supplied auth token exists but has expired.
+    ExpiredToken,
+    /// The user is authenticated but may not perform the action.
+    Forbidden,
+    /// The requested resource does not exist.
+    NotFound,
+    /// The request conflicts with existing state, e.g. a duplicate version.
+    Conflict,
+    /// The uploaded tarball does not match the hash supplied by the client.
+    HashMismatch,
+    /// The uploaded tarball is not a valid package.
+    InvalidPackage,
+    /// The upload would take the owner of the package over their storage quota.
+    QuotaExceeded,
+    /// Too many requests were made recently, try again later.
+    RateLimited,
+    /// The request body is larger than the route accepts.
+    PayloadTooLarge,
+    /// The request body isn't of the content type the route accepts, e.g. json.
+    UnsupportedMediaType,
+    /// A new package name resembles a popular package. It's rejected, or held until an admin
+    /// approves it, depending on the registry's policy.
+    NameTooSimilar,
+    /// An unexpected error occurred in the server.
+    #[default]
+    Internal,
+    /// A code this version of onyx_api does not know about.
+    #[serde(other)]
+    Unknown,
+}
+
+impl OnyxErrorCode {
+    /// The HTTP status code responses with this error code are sent with.
+    pub fn status_code(&self) -> u16 {
+        match self {
+            Self::BadRequest | Self::HashMismatch | Self::InvalidPackage => 400,
+            Self::InvalidCredentials | Self::InvalidToken | Self::ExpiredToken => 401,
+            Self::Forbidden | Self::QuotaExceeded | Self::NameTooSimilar => 403,
+            Self::NotFound => 404,
+            Self::Conflict => 409,
+            Self::PayloadTooLarge => 413,
+            Self::UnsupportedMediaType => 415,
+            Self::ValidationFailed => 422,
+            Self::RateLimited => 429,
+            Self::Internal | Self::Unknown => 500,
+        }
+    }
+
+    /// Best guess at an error code for responses that don't include one.
+    pub fn from_status_code(status_code: u16) -> Self {
+        match status_code {
+            400 => Self::BadRequest,
+            401 => Self::InvalidToken,
+            403 => Self::Forbidden,
+            404 => Self::NotFound,
+            409 => Self::Conflict,
+            413 => Self::PayloadTooLarge,
+            415 => Self::UnsupportedMediaType,
+            422 => Self::ValidationFailed,
+            429 => Self::RateLimited,
+            500..=599 => Self::Internal,
+            _ => Self::Unknown,
+        }
+    }
+}
+
+/// The body of an unsuccessful response from an onyx server.
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq)]
+#[cfg_attr(feature = "openapi", derive(schemars::JsonSchema))]
+pub struct ApiError {
+    pub code: OnyxErrorCode,
+    pub message: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub details: Option<serde_json::Value>,
+}
+
+/// A problem with a single field of a request.
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq)]
+#[cfg_attr(feature = "openapi", derive(schemars::JsonSchema))]
+pub struct FieldError {
+    pub field: String,
+    pub message: String,
+}
+
+impl ApiError {
+    /// Field level problems included with a `ValidationFailed` error.
+    pub fn field_errors(&self) -> Vec<FieldError> {
+        self.details
+            .as_ref()
+            .and_then(|details| details.get("fields"))
+            .and_then(|fields| serde_json::from_value(fields.clone()).ok())
+            .unwrap_or_default()
+    }
+
+    /// Decode the body of an unsuccessful response. Registries that predate structured errors
+    /// respond with plain text, in which case the code is inferred from the status.
+    pub async fn from_response(response: reqwest::Response) -> Self {
+        let code = OnyxErrorCode::from_status_code(response.status().as_u16());
+        match response.text().await {
+            Ok(text) => serde_json::from_str(&text).unwrap_or(Self {
+                code,
+                message: text,
+                details: None,
+            }),
+            Err(e) => Self {
+                code,
+                message: format!("failed to read error response: {e}"),
+                details: None,
+            },
+        }
+    }
+}
+
+impl Display for ApiError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.message)
+    }
+}
+
+impl std::error::Error for ApiError {}
+
+/// The client and registry api versions are too far apart to work together, see
+/// `MetaResponse::check_compatibility`.
+#[derive(Clone, Debug, PartialEq)]
+pub enum ApiVersionMismatch {
+    /// The registry no longer supports clients this old.
+    ClientTooOld {
+        url: String,
+        client_api_version: u32,
+        min_api_version: u32,
+    },
+    /// The client no longer supports registries this old.
+    ServerTooOld {
+        url: String,
+        server_api_version: u32,
+        min_api_version: u32,
+    },
+}
+
+impl Display for ApiVersionMismatch {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::ClientTooOld {
+                url,
+                client_api_version,
+                min_api_version,
+            } => write!(
+                f,
+                "The registry at {url} requires api version {min_api_version} or newer, this client uses version {client_api_version}"
+            ),
+            Self::ServerTooOld {
+                url,
+                server_api_version,
+                min_api_version,
+            } => write!(
+                f,
+                "The registry at {url} uses api version {server_api_version}, this client requires version {min_api_version} or newer"
+            ),
+        }
+    }
+}
+
+impl std::error::Error for ApiVersionMismatch {}
+
+#[cfg(test)]
+mod tests {
+    use anyhow::Result;
+    use tokio::io::AsyncReadExt;
+    use tokio::io::AsyncWriteExt;
+    use tokio::net::TcpListener;
+
+    use super::*;
+
+    /// Answer one request with `status` and `body`.
+    async fn respond(status: &str, body: &'static str) -> Result<reqwest::Response> {
+        let listener = TcpListener::bind("127.0.0.1:0").await?;
+        let url = format!("http://{}", listener.local_addr()?);
+        let status = status.to_string();
+        tokio::spawn(async move {
+            let (mut socket, _) = listener.accept().await?;
+            let mut request = [0; 1024];
+            let _ = socket.read(&mut request).await?;
+            let head = format!(
+                "HTTP/1.1 {status}\r\ncontent-length: {}\r\nconnection: close\r\n\r\n",
+                body.len()
+            );
+            socket.write_all(head.as_bytes()).await?;
+            socket.write_all(body.as_bytes()).await?;
+            socket.shutdown().await
+        });
+        Ok(reqwest::get(url).await?)
+    }
+
+    #[tokio::test]
+    async fn should_decode_structured_error() -> Result<()> {
+        let response = respond(
+            "409 Conflict",
+            r#"{"code":"hash_mismatch","message":"Hash mismatch!"}"#,
+        )
+        .await?;
+        let err = ApiError::from_response(response).await;
+        assert_eq!(err.code, OnyxErrorCode::HashMismatch);
+        assert_eq!(err.message, "Hash mismatch!");
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn should_infer_code_of_plain_text_error() -> Result<()> {
+        let response = respond("409 Conflict", "Version already exists!").await?;
+        let err = ApiError::from_response(response).await;
+        assert_eq!(err.code, OnyxErrorCode::Conflict);
+        assert_eq!(err.message, "Version already exists!");
+        assert_eq!(err.details, None);
+
+        let response = respond("418 I'm a teapot", "short and stout").await?;
+        let err = ApiError::from_response(response).await;
+        assert_eq!(err.code, OnyxErrorCode::Unknown);
+        assert_eq!(err.message, "short and stout");
+        Ok(())
+    }
+}
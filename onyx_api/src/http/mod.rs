@@ -1,5 +1,11 @@
 mod api;
+mod error;
+#[cfg(feature = "stream")]
+mod stream;
 mod types;
 
 pub use api::OnyxApi;
+pub use error::*;
+#[cfg(feature = "stream")]
+pub use stream::VerifiedDownload;
 pub use types::*;
@@ -0,0 +1,248 @@
+use std::io;
+use std::pin::Pin;
+use std::task::Context;
+use std::task::Poll;
+use std::task::ready;
+
+use bytes::Bytes;
+use tokio::io::AsyncRead;
+use tokio::io::ReadBuf;
+use tokio::sync::mpsc;
+
+use crate::db::HashId;
+
+/// Chunks buffered between the network, the reader, and the hasher. Memory use is bounded by
+/// this regardless of the size of the download.
+const CHUNK_BUFFER: usize = 16;
+
+enum Chunk {
+    Data(Bytes),
+    /// The download ended, with the content hash if it matched the version.
+    Done(io::Result<blake3::Hash>),
+}
+
+/// The tarball of a version, hashed as it's read. Reading to the end fails with
+/// `io::ErrorKind::InvalidData` instead of returning EOF if the content doesn't match the
+/// version, or `io::ErrorKind::UnexpectedEof` if the download ends early, so nothing read
+/// should be trusted until EOF is reached.
+pub struct VerifiedDownload {
+    chunks: mpsc::Receiver<Chunk>,
+    current: Bytes,
+    content_length: Option<u64>,
+    hash: Option<blake3::Hash>,
+}
+
+impl VerifiedDownload {
+    /// Start streaming `response`, the tarball of `version_id`. Must be called within a tokio
+    /// runtime.
+    pub(crate) fn new(response: reqwest::Response, version_id: HashId) -> Self {
+        let content_length = response.content_length();
+        let (chunk_tx, chunks) = mpsc::channel(CHUNK_BUFFER);
+        tokio::spawn(forward(response, version_id, chunk_tx));
+        Self {
+            chunks,
+            current: Bytes::new(),
+            content_length,
+            hash: None,
+        }
+    }
+
+    /// Size of the tarball in bytes, if the registry sent it.
+    pub fn content_length(&self) -> Option<u64> {
+        self.content_length
+    }
+
+    /// The verified content hash, once the download has been read to the end.
+    pub fn hash(&self) -> Option<blake3::Hash> {
+        self.hash
+    }
+}
+
+impl AsyncRead for VerifiedDownload {
+    fn poll_read(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        let this = &mut *self;
+        while this.current.is_empty() {
+            if this.hash.is_some() {
+                return Poll::Ready(Ok(()));
+            }
+            match ready!(this.chunks.poll_recv(cx)) {
+                Some(Chunk::Data(chunk)) => this.current = chunk,
+                Some(Chunk::Done(Ok(hash))) => this.hash = Some(hash),
+                Some(Chunk::Done(Err(e))) => return Poll::Ready(Err(e)),
+                None => {
+                    return Poll::Ready(Err(io::Error::new(
+                        io::ErrorKind::UnexpectedEof,
+                        "download ended before it was verified",
+                    )));
+                }
+            }
+        }
+        let len = buf.remaining().min(this.current.len());
+        buf.put_slice(&this.current.split_to(len));
+        Poll::Ready(Ok(()))
+    }
+}
+
+/// Blocking reader of the chunks sent to the hasher.
+struct ChunkReader {
+    chunks: mpsc::Receiver<Bytes>,
+    current: Bytes,
+}
+
+impl io::Read for ChunkReader {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        while self.current.is_empty() {
+            match self.chunks.blocking_recv() {
+                Some(chunk) => self.current = chunk,
+                None => return Ok(0),
+            }
+        }
+        let len = buf.len().min(self.current.len());
+        buf[..len].copy_from_slice(&self.current.split_to(len));
+        Ok(len)
+    }
+}
+
+/// Send each chunk of `response` to both the reader and a hasher, then tell the reader if the
+/// content matched `version_id`.
+async fn forward(
+    mut response: reqwest::Response,
+    version_id: HashId,
+    chunk_tx: mpsc::Sender<Chunk>,
+) {
+    let (hash_tx, hash_rx) = mpsc::channel(CHUNK_BUFFER);
+    let hasher = tokio::task::spawn_blocking(move || {
+        nrpm_tarball::hash_tarball_stream(ChunkReader {
+            chunks: hash_rx,
+            current: Bytes::new(),
+        })
+    });
+    let mut hash_tx = Some(hash_tx);
+    let downloaded = loop {
+        match response.chunk().await {
+            Ok(Some(chunk)) => {
+                // the hasher stops reading at the end of the archive
+                if let Some(tx) = &hash_tx
+                    && tx.send(chunk.clone()).await.is_err()
+                {
+                    hash_tx = None;
+                }
+                if chunk_tx.send(Chunk::Data(chunk)).await.is_err() {
+                    // the reader was dropped
+                    return;
+                }
+            }
+            Ok(None) => break Ok(()),
+            Err(e) => {
+                break Err(io::Error::new(
+                    io::ErrorKind::UnexpectedEof,
+                    format!("download failed: {e}"),
+                ));
+            }
+        }
+    };
+    drop(hash_tx);
+    let done = match downloaded {
+        Ok(()) => match hasher.await {
+            Ok(Ok(hash)) if HashId::from(hash) == version_id => Ok(hash),
+            Ok(Ok(hash)) => Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!(
+                    "hash mismatch for downloaded tarball, computed: {hash}, expected: {}",
+                    version_id.to_string()
+                ),
+            )),
+            Ok(Err(e)) => Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("downloaded tarball is invalid: {e:?}"),
+            )),
+            Err(e) => Err(io::Error::other(format!(
+                "failed to hash downloaded tarball: {e}"
+            ))),
+        },
+        Err(e) => Err(e),
+    };
+    let _ = chunk_tx.send(Chunk::Done(done)).await;
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Read;
+    use std::io::Seek;
+
+    use anyhow::Result;
+    use tokio::io::AsyncReadExt;
+    use tokio::io::AsyncWriteExt;
+    use tokio::net::TcpListener;
+
+    use super::*;
+
+    fn tarball(content: &str) -> Result<(Vec<u8>, HashId)> {
+        let workdir = tempfile::tempdir()?;
+        std::fs::write(workdir.path().join("lib.nr"), content)?;
+        let mut tarball = nrpm_tarball::create(workdir.path(), tempfile::tempfile()?)?;
+        let hash = nrpm_tarball::hash_tarball(&mut tarball)?;
+        let mut bytes = vec![];
+        tarball.seek(std::io::SeekFrom::Start(0))?;
+        tarball.read_to_end(&mut bytes)?;
+        Ok((bytes, HashId::from(hash)))
+    }
+
+    /// Answer one request with `body`, closing the connection after `sent` bytes of it.
+    async fn download(body: Vec<u8>, sent: usize, version_id: HashId) -> Result<VerifiedDownload> {
+        let listener = TcpListener::bind("127.0.0.1:0").await?;
+        let url = format!("http://{}", listener.local_addr()?);
+        tokio::spawn(async move {
+            let (mut socket, _) = listener.accept().await?;
+            let mut request = [0; 1024];
+            let _ = socket.read(&mut request).await?;
+            let head = format!(
+                "HTTP/1.1 200 OK\r\ncontent-length: {}\r\nconnection: close\r\n\r\n",
+                body.len()
+            );
+            socket.write_all(head.as_bytes()).await?;
+            socket.write_all(&body[..sent]).await?;
+            socket.shutdown().await
+        });
+        let response = reqwest::get(url).await?;
+        Ok(VerifiedDownload::new(response, version_id))
+    }
+
+    #[tokio::test]
+    async fn should_hash_verified_download() -> Result<()> {
+        let (bytes, version_id) = tarball("pub fn one() -> Field { 1 }")?;
+        let mut download = download(bytes.clone(), bytes.len(), version_id.clone()).await?;
+        assert_eq!(download.content_length(), Some(bytes.len() as u64));
+        assert!(download.hash().is_none());
+        let mut read = vec![];
+        download.read_to_end(&mut read).await?;
+        assert_eq!(read, bytes);
+        assert_eq!(download.hash().map(HashId::from), Some(version_id));
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn should_reject_tampered_download() -> Result<()> {
+        let (_, version_id) = tarball("pub fn one() -> Field { 1 }")?;
+        let (tampered, _) = tarball("pub fn one() -> Field { 2 }")?;
+        let mut download = download(tampered.clone(), tampered.len(), version_id).await?;
+        let error = download.read_to_end(&mut vec![]).await.unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+        assert!(download.hash().is_none());
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn should_reject_truncated_download() -> Result<()> {
+        let (bytes, version_id) = tarball("pub fn one() -> Field { 1 }")?;
+        let mut download = download(bytes.clone(), bytes.len() / 2, version_id).await?;
+        let error = download.read_to_end(&mut vec![]).await.unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::UnexpectedEof);
+        assert!(download.hash().is_none());
+        Ok(())
+    }
+}
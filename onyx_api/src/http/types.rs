@@ -1,35 +1,149 @@
+use std::collections::BTreeMap;
+use std::path::PathBuf;
+
 use nanoid::nanoid;
 use serde::Deserialize;
 use serde::Serialize;
 
+use crate::db::AuditEntry;
+use crate::db::ChangelogEntry;
+use crate::db::HashId;
+use crate::db::LogEntry;
+use crate::db::LogHead;
+use crate::db::ModerationAction;
+use crate::db::PackageDependency;
+use crate::db::PublishStatus;
+use crate::db::ReportReason;
+use crate::db::ScanFinding;
+use crate::db::SessionSource;
+use crate::db::TokenScope;
+use crate::db::TransferRequestModel;
 use crate::db::UserModelSafe;
 
+/// Header used to mark a publish request as retryable. A publish repeated with the same key
+/// (and the same tarball hash) returns the original response instead of an error.
+pub const IDEMPOTENCY_KEY_HEADER: &str = "Idempotency-Key";
+
+/// Header sent with every registry response, the `API_VERSION` of the registry.
+pub const API_VERSION_HEADER: &str = "onyx-api-version";
+
+/// Version of the http api. Incremented when a change would break older clients or registries.
+pub const API_VERSION: u32 = 1;
+
+/// The oldest registry api version this client works with. Registries that predate
+/// `/v0/meta` are version 0.
+pub const MIN_SERVER_API_VERSION: u32 = 0;
+
+/// The oldest client api version this registry works with. Clients that predate api versions
+/// are version 0.
+pub const MIN_CLIENT_API_VERSION: u32 = 0;
+
 #[derive(Clone, Serialize, Deserialize, Debug, Default)]
 pub struct TokenOnly {
     pub token: String,
 }
 
+/// Body of a request to `/v0/auth`. Either checks an access token or exchanges a refresh
+/// token for a new access token.
+#[derive(Clone, Serialize, Deserialize, Debug)]
+#[serde(untagged)]
+#[cfg_attr(feature = "openapi", derive(schemars::JsonSchema))]
+pub enum AuthRequest {
+    Refresh { refresh_token: String },
+    Token { token: String },
+}
+
 #[derive(Clone, Serialize, Deserialize, Debug, Default)]
+#[cfg_attr(feature = "openapi", derive(schemars::JsonSchema))]
 pub struct ProposeToken {
     pub token: String,
     pub proposed_token: String,
+    /// A refresh token to activate along with `proposed_token`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub proposed_refresh_token: Option<String>,
+    /// What the proposed token can be used for. Publish scoped tokens last longer.
+    #[serde(default)]
+    pub scope: TokenScope,
 }
 
-#[derive(Clone, Serialize, Deserialize, Debug, Default)]
+/// Version of the `PublishData` schema written by this crate.
+pub const PUBLISH_DATA_SCHEMA_VERSION: u32 = 3;
+
+/// The `publish_data` field of a publish upload, sent as JSON.
+#[derive(Clone, Serialize, Deserialize, Debug)]
+#[cfg_attr(feature = "openapi", derive(schemars::JsonSchema))]
 pub struct PublishData {
+    /// Servers reject versions newer than they understand. Bump
+    /// `PUBLISH_DATA_SCHEMA_VERSION` when fields are added or change meaning.
+    pub schema_version: u32,
+    /// blake3 hash of the tarball as hex.
     pub hash: String,
     pub token: String,
+    /// Url of the git repository the tarball was built from. Since schema version 2.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub source_repository: Option<String>,
+    /// Hex encoded hash of the commit the tarball was built from. Since schema version 2.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub source_commit: Option<String>,
+    /// Markdown notes describing what changed in this version, shown with the version. Since
+    /// schema version 3.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub release_notes: Option<String>,
+}
+
+impl PublishData {
+    pub fn new(hash: String, token: String) -> Self {
+        Self {
+            schema_version: PUBLISH_DATA_SCHEMA_VERSION,
+            hash,
+            token,
+            source_repository: None,
+            source_commit: None,
+            release_notes: None,
+        }
+    }
+}
+
+impl Default for PublishData {
+    fn default() -> Self {
+        Self::new(String::new(), String::new())
+    }
 }
 
 #[derive(Clone, Serialize, Deserialize, Debug, Default)]
+#[cfg_attr(feature = "openapi", derive(schemars::JsonSchema))]
 pub struct PublishResponse {
     pub package_id: String,
+    /// Things the publisher should know about, e.g. that the name resembles another package.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub warnings: Vec<String>,
+    /// `pending` if the registry scans uploads before publishing them, poll
+    /// `/v0/publish/{version_id}` until it isn't. `package_id` is empty while a new package is
+    /// pending.
+    #[serde(default)]
+    pub status: PublishStatus,
 }
 
+/// Where an upload of the authenticated user is on its way to being published.
 #[derive(Clone, Serialize, Deserialize, Debug)]
+#[cfg_attr(feature = "openapi", derive(schemars::JsonSchema))]
+pub struct PublishStatusResponse {
+    pub package_name: String,
+    pub version_name: String,
+    pub status: PublishStatus,
+    /// What the scanners found, if the upload was flagged.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub findings: Vec<ScanFinding>,
+}
+
+#[derive(Clone, Serialize, Deserialize, Debug)]
+#[cfg_attr(feature = "openapi", derive(schemars::JsonSchema))]
 pub struct LoginRequest {
     pub username: String,
     pub password: String,
+    /// Issue a refresh token that lasts longer, so the user stays logged in on this device.
+    #[serde(default)]
+    pub remember_me: bool,
 }
 
 impl Default for LoginRequest {
@@ -37,13 +151,779 @@ impl Default for LoginRequest {
         Self {
             username: nanoid!(),
             password: nanoid!(),
+            remember_me: false,
         }
     }
 }
 
+/// Change the username or password of the authenticated user. Both are optional, the current
+/// password is always required.
+#[derive(Clone, Serialize, Deserialize, Debug, Default)]
+#[cfg_attr(feature = "openapi", derive(schemars::JsonSchema))]
+pub struct UpdateUserRequest {
+    pub password: String,
+    pub new_username: Option<String>,
+    /// Other sessions of the user are revoked when the password changes.
+    pub new_password: Option<String>,
+}
+
+/// Change the metadata of a package, see `PackageMetadata`. Fields that aren't set stay as
+/// they are, an empty value goes back to the Nargo.toml of the latest version, or for
+/// `deprecated` undeprecates the package.
+#[derive(Clone, Serialize, Deserialize, Debug, Default)]
+#[cfg_attr(feature = "openapi", derive(schemars::JsonSchema))]
+pub struct UpdatePackageRequest {
+    pub description: Option<String>,
+    pub keywords: Option<Vec<String>>,
+    pub repository: Option<String>,
+    pub deprecated: Option<String>,
+}
+
+/// Delete the authenticated user. Users that own packages must transfer them first.
+#[derive(Clone, Serialize, Deserialize, Debug, Default)]
+#[cfg_attr(feature = "openapi", derive(schemars::JsonSchema))]
+pub struct DeleteUserRequest {
+    pub password: String,
+}
+
 #[derive(Clone, Serialize, Deserialize, PartialEq, Debug)]
+#[cfg_attr(feature = "openapi", derive(schemars::JsonSchema))]
 pub struct LoginResponse {
     pub user: UserModelSafe,
     pub token: String,
     pub expires_at: u64,
+    /// Only included when a refresh token is issued, i.e. on login, signup, and refresh.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub refresh_token: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub refresh_expires_at: Option<u64>,
+}
+
+/// An active session as listed to the user that owns it. Only a prefix of the token is
+/// revealed.
+#[derive(Clone, Serialize, Deserialize, PartialEq, Debug)]
+#[cfg_attr(feature = "openapi", derive(schemars::JsonSchema))]
+pub struct SessionInfo {
+    pub token_prefix: String,
+    pub created_at: u64,
+    pub expires_at: u64,
+    pub source: SessionSource,
+    /// When the session's refresh token expires, if it has one. The session can be extended
+    /// until this time.
+    pub refresh_expires_at: Option<u64>,
+    pub scope: TokenScope,
+    /// True for the session used to make the listing request.
+    pub current: bool,
+}
+
+/// Send notifications about the authenticated user's account to `url`, replacing any webhook
+/// set before.
+#[derive(Clone, Serialize, Deserialize, Debug)]
+#[cfg_attr(feature = "openapi", derive(schemars::JsonSchema))]
+pub struct SetWebhookRequest {
+    pub url: String,
+}
+
+/// Body of the webhook request sent when a version is published with a token that isn't a
+/// browser session, e.g. a token made for CI. A publish the owner doesn't recognize means the
+/// token leaked: revoke the session starting with `token_prefix`.
+#[derive(Clone, Serialize, Deserialize, Debug, PartialEq)]
+#[cfg_attr(feature = "openapi", derive(schemars::JsonSchema))]
+pub struct PublishNotification {
+    pub package_name: String,
+    pub version_name: String,
+    /// Hex encoded blake3 hash of the published tarball.
+    pub hash: String,
+    /// The first characters of the token that published, as listed in the user's sessions.
+    pub token_prefix: String,
+    /// How the token was issued, if the registry recorded it.
+    pub source: Option<SessionSource>,
+    /// Address the publish request came from.
+    pub ip: Option<String>,
+    pub published_at: u64,
+}
+
+/// Versions published by the packages the authenticated user watches.
+#[derive(Clone, Serialize, Deserialize, Debug)]
+#[cfg_attr(feature = "openapi", derive(schemars::JsonSchema))]
+pub struct NotificationsResponse {
+    /// Newest first.
+    pub notifications: Vec<ChangelogEntry>,
+    /// The sequence number of the newest notification the user has read.
+    pub read_seq: u64,
+    pub unread: u64,
+    /// Whether a daily digest of new notifications is sent to the user's webhook.
+    pub digest: bool,
+}
+
+/// Mark the notifications up to `seq` read.
+#[derive(Clone, Serialize, Deserialize, Debug)]
+#[cfg_attr(feature = "openapi", derive(schemars::JsonSchema))]
+pub struct MarkNotificationsReadRequest {
+    pub seq: u64,
+}
+
+/// Body of the daily webhook request listing the versions published by watched packages since
+/// the last digest, oldest first.
+#[derive(Clone, Serialize, Deserialize, Debug, PartialEq)]
+#[cfg_attr(feature = "openapi", derive(schemars::JsonSchema))]
+pub struct NotificationDigest {
+    pub username: String,
+    pub notifications: Vec<ChangelogEntry>,
+}
+
+#[derive(Clone, Serialize, Deserialize, Debug)]
+#[cfg_attr(feature = "openapi", derive(schemars::JsonSchema))]
+pub struct TransferPackageRequest {
+    pub to_username: String,
+}
+
+#[derive(Clone, Serialize, Deserialize, Debug)]
+#[cfg_attr(feature = "openapi", derive(schemars::JsonSchema))]
+pub struct AdminTransferRequest {
+    pub to_username: String,
+    pub reason: String,
+}
+
+#[derive(Clone, Serialize, Deserialize, Debug)]
+#[cfg_attr(feature = "openapi", derive(schemars::JsonSchema))]
+pub struct ClaimPackageRequest {
+    pub reason: String,
+}
+
+/// Add a signing key to the authenticated user, optionally rotating out one of their active
+/// keys.
+#[derive(Clone, Serialize, Deserialize, Debug)]
+#[cfg_attr(feature = "openapi", derive(schemars::JsonSchema))]
+pub struct AddKeyRequest {
+    /// Hex encoded ed25519 public key.
+    pub public_key: String,
+    /// Hex encoded signature of `key_message` by the new key, proving the user holds it.
+    pub signature: String,
+    /// The active key the new key replaces.
+    pub replaces: Option<String>,
+    /// Hex encoded signature of the same `key_message` by the replaced key, required with
+    /// `replaces` so the lineage can be checked.
+    pub replaces_signature: Option<String>,
+}
+
+/// Pending transfers involving the authenticated user.
+#[derive(Clone, Serialize, Deserialize, Debug, Default)]
+#[cfg_attr(feature = "openapi", derive(schemars::JsonSchema))]
+pub struct TransfersResponse {
+    /// Packages offered to the user.
+    pub incoming: Vec<TransferRequestModel>,
+    /// Packages the user has offered to others.
+    pub outgoing: Vec<TransferRequestModel>,
+}
+
+/// Bytes a user stores in the registry and how many they may store.
+#[derive(Clone, Serialize, Deserialize, Debug, PartialEq)]
+#[cfg_attr(feature = "openapi", derive(schemars::JsonSchema))]
+pub struct StorageUsage {
+    pub username: String,
+    /// Bytes of the tarballs and artifacts of packages the user owns.
+    pub used_bytes: u64,
+    /// `None` if the user may store any amount.
+    pub quota_bytes: Option<u64>,
+}
+
+#[derive(Clone, Serialize, Deserialize, Debug)]
+#[cfg_attr(feature = "openapi", derive(schemars::JsonSchema))]
+pub struct SetQuotaRequest {
+    /// `None` to go back to the registry default.
+    pub quota_bytes: Option<u64>,
+}
+
+#[derive(Clone, Serialize, Deserialize, Debug)]
+#[cfg_attr(feature = "openapi", derive(schemars::JsonSchema))]
+pub struct ReportPackageRequest {
+    pub reason: ReportReason,
+    /// What the reporter found, shown to admins reviewing the report.
+    pub details: String,
+    /// The version the report is about, if it's about one.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub version_name: Option<String>,
+}
+
+#[derive(Clone, Serialize, Deserialize, Debug)]
+#[cfg_attr(feature = "openapi", derive(schemars::JsonSchema))]
+pub struct ResolveReportRequest {
+    pub action: ModerationAction,
+    /// The version to yank, defaults to the version the report is about.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub version_name: Option<String>,
+    /// Why the admin resolved the report this way.
+    #[serde(default)]
+    pub note: String,
+}
+
+/// A file of a version, see `VersionManifest`.
+#[derive(Clone, Serialize, Deserialize, Debug, PartialEq)]
+#[cfg_attr(feature = "openapi", derive(schemars::JsonSchema))]
+pub struct ManifestFile {
+    /// Relative to the package root, with `/` separators.
+    pub path: String,
+    pub size: u64,
+    /// `nrpm_tarball::hash_file` of the file.
+    pub blake3: String,
+}
+
+/// The release notes a version was published with, see `PublishData::release_notes`.
+#[derive(Clone, Serialize, Deserialize, Debug, PartialEq, Default)]
+#[cfg_attr(feature = "openapi", derive(schemars::JsonSchema))]
+pub struct VersionReleaseNotes {
+    /// Markdown.
+    pub release_notes: String,
+}
+
+/// Attach a compiled artifact to a version, see `VersionArtifactModel`.
+#[derive(Clone, Serialize, Deserialize, Debug)]
+#[cfg_attr(feature = "openapi", derive(schemars::JsonSchema))]
+pub struct UploadArtifactRequest {
+    /// Letters, numbers, '-' and '_', usually the name of the compiled package.
+    pub name: String,
+    /// The artifact json as written by `nargo compile`. It must include an `abi`.
+    pub content: String,
+}
+
+/// Every file of a version, sorted by path. Combining the file hashes with
+/// `nrpm_tarball::combine_file_hashes` gives the version id, so a manifest can be checked
+/// without downloading the tarball.
+#[derive(Clone, Serialize, Deserialize, Debug, PartialEq, Default)]
+#[cfg_attr(feature = "openapi", derive(schemars::JsonSchema))]
+pub struct VersionManifest {
+    pub files: Vec<ManifestFile>,
+}
+
+impl VersionManifest {
+    /// The hash of each file keyed by its path.
+    pub fn file_hashes(&self) -> anyhow::Result<BTreeMap<PathBuf, blake3::Hash>> {
+        let mut ordered_files = BTreeMap::new();
+        for file in &self.files {
+            ordered_files.insert(
+                PathBuf::from(&file.path),
+                blake3::Hash::from_hex(&file.blake3)?,
+            );
+        }
+        Ok(ordered_files)
+    }
+
+    /// The hash of the version these files make up.
+    pub fn version_hash(&self) -> anyhow::Result<blake3::Hash> {
+        Ok(nrpm_tarball::combine_file_hashes(self.file_hashes()?))
+    }
+}
+
+/// How a file changed between two versions, see `FileDiff`.
+#[derive(Clone, Copy, Serialize, Deserialize, Debug, PartialEq)]
+#[cfg_attr(feature = "openapi", derive(schemars::JsonSchema))]
+#[serde(rename_all = "snake_case")]
+pub enum FileChange {
+    Added,
+    Removed,
+    Modified,
+}
+
+/// A file that differs between two versions, see `VersionDiff`.
+#[derive(Clone, Serialize, Deserialize, Debug, PartialEq)]
+#[cfg_attr(feature = "openapi", derive(schemars::JsonSchema))]
+pub struct FileDiff {
+    /// Relative to the package root, with `/` separators.
+    pub path: String,
+    pub change: FileChange,
+    /// `nrpm_tarball::hash_file` of the file in the version compared from, `None` if it was
+    /// added.
+    pub from_blake3: Option<String>,
+    /// `nrpm_tarball::hash_file` of the file in the version compared to, `None` if it was
+    /// removed.
+    pub to_blake3: Option<String>,
+    pub from_size: Option<u64>,
+    pub to_size: Option<u64>,
+    /// A unified diff of the file, if it's utf-8 text no larger than 64 KiB in both versions.
+    pub unified_diff: Option<String>,
+}
+
+/// The files that differ between two versions of a package, sorted by path.
+#[derive(Clone, Serialize, Deserialize, Debug, PartialEq)]
+#[cfg_attr(feature = "openapi", derive(schemars::JsonSchema))]
+pub struct VersionDiff {
+    pub package_name: String,
+    /// Name of the version compared from.
+    pub from: String,
+    /// Name of the version compared to.
+    pub to: String,
+    pub files: Vec<FileDiff>,
+}
+
+/// A version installed by a client, see `InstallStatsRequest`.
+#[derive(Clone, Serialize, Deserialize, Debug, PartialEq)]
+#[cfg_attr(feature = "openapi", derive(schemars::JsonSchema))]
+pub struct PackageInstall {
+    pub package_name: String,
+    pub version_name: String,
+}
+
+/// Versions a client installed without downloading them from the registry, e.g. cloned with
+/// git, to be counted as downloads. Nothing about the client is sent.
+#[derive(Clone, Serialize, Deserialize, Debug, PartialEq)]
+#[cfg_attr(feature = "openapi", derive(schemars::JsonSchema))]
+pub struct InstallStatsRequest {
+    pub installs: Vec<PackageInstall>,
+}
+
+/// How many times a version was downloaded, see `PackageStatsResponse`.
+#[derive(Clone, Serialize, Deserialize, Debug, PartialEq)]
+#[cfg_attr(feature = "openapi", derive(schemars::JsonSchema))]
+pub struct VersionStats {
+    pub version_name: String,
+    pub downloads: u64,
+}
+
+/// Download counts of a package, including installs reported by clients.
+#[derive(Clone, Serialize, Deserialize, Debug, PartialEq)]
+#[cfg_attr(feature = "openapi", derive(schemars::JsonSchema))]
+pub struct PackageStatsResponse {
+    pub package_name: String,
+    /// The sum of the downloads of every version.
+    pub downloads: u64,
+    /// In publish order.
+    pub versions: Vec<VersionStats>,
+}
+
+/// The latest release of the nrpm cli, so older clients can tell their users to update.
+#[derive(Clone, Serialize, Deserialize, Debug, PartialEq)]
+#[cfg_attr(feature = "openapi", derive(schemars::JsonSchema))]
+pub struct CliVersionResponse {
+    pub version: String,
+}
+
+/// What a registry supports, from `/v0/meta`.
+#[derive(Clone, Serialize, Deserialize, Debug, PartialEq)]
+#[cfg_attr(feature = "openapi", derive(schemars::JsonSchema))]
+pub struct MetaResponse {
+    /// The `API_VERSION` of the registry.
+    pub api_version: u32,
+    /// The oldest client api version the registry works with.
+    pub min_api_version: u32,
+    /// Optional functionality the registry provides, e.g. `publish` or `deltas`.
+    pub features: Vec<String>,
+}
+
+impl MetaResponse {
+    /// How registries that predate `/v0/meta` are described.
+    pub fn legacy() -> Self {
+        Self {
+            api_version: 0,
+            min_api_version: 0,
+            features: vec![],
+        }
+    }
+
+    pub fn has_feature(&self, feature: &str) -> bool {
+        self.features.iter().any(|f| f == feature)
+    }
+
+    /// Check that a client built from this crate can talk to the registry at `url`.
+    // every registry is supported until MIN_SERVER_API_VERSION is raised above 0
+    #[allow(clippy::absurd_extreme_comparisons)]
+    pub fn check_compatibility(&self, url: &str) -> Result<(), super::ApiVersionMismatch> {
+        if API_VERSION < self.min_api_version {
+            Err(super::ApiVersionMismatch::ClientTooOld {
+                url: url.to_string(),
+                client_api_version: API_VERSION,
+                min_api_version: self.min_api_version,
+            })
+        } else if self.api_version < MIN_SERVER_API_VERSION {
+            Err(super::ApiVersionMismatch::ServerTooOld {
+                url: url.to_string(),
+                server_api_version: self.api_version,
+                min_api_version: MIN_SERVER_API_VERSION,
+            })
+        } else {
+            Ok(())
+        }
+    }
+}
+
+/// A page of the publish changelog.
+#[derive(Clone, Serialize, Deserialize, Debug)]
+#[cfg_attr(feature = "openapi", derive(schemars::JsonSchema))]
+pub struct ChangelogResponse {
+    /// Entries after the requested sequence number, oldest first.
+    pub entries: Vec<ChangelogEntry>,
+    /// The sequence number of the newest entry in the registry.
+    pub latest_seq: u64,
+}
+
+/// A page of the transparency log.
+#[derive(Clone, Serialize, Deserialize, Debug)]
+#[cfg_attr(feature = "openapi", derive(schemars::JsonSchema))]
+pub struct LogEntriesResponse {
+    /// Entries from the requested index, oldest first.
+    pub entries: Vec<LogEntry>,
+    pub tree_size: u64,
+}
+
+/// A page of the audit log.
+#[derive(Clone, Serialize, Deserialize, Debug)]
+#[cfg_attr(feature = "openapi", derive(schemars::JsonSchema))]
+pub struct AuditLogResponse {
+    /// Entries after the requested sequence number, oldest first.
+    pub entries: Vec<AuditEntry>,
+    /// The sequence number of the newest entry.
+    pub latest_seq: u64,
+}
+
+/// Path of a package's file relative to the root of a static index. Names are bucketed by
+/// length and leading characters the same way as cargo's sparse index: `1/a`, `2/ab`,
+/// `3/a/abc` and `ab/cd/abcd…`. Buckets are lowercase, file names keep the package's case.
+pub fn index_path(package_name: &str) -> String {
+    let lower = package_name.to_ascii_lowercase();
+    match lower.len() {
+        1 => format!("1/{package_name}"),
+        2 => format!("2/{package_name}"),
+        3 => format!("3/{}/{package_name}", &lower[..1]),
+        _ => format!("{}/{}/{package_name}", &lower[..2], &lower[2..4]),
+    }
+}
+
+/// Contents of a package's file in the static index.
+#[derive(Clone, Serialize, Deserialize, Debug, PartialEq)]
+#[cfg_attr(feature = "openapi", derive(schemars::JsonSchema))]
+pub struct IndexPackage {
+    pub name: String,
+    /// Why the owner deprecated the package, see `PackageMetadata`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub deprecated: Option<String>,
+    /// Oldest first.
+    pub versions: Vec<IndexVersion>,
+}
+
+impl IndexPackage {
+    /// Position of the version a git tag asks for: the version named `tag`, or the version
+    /// `tag` is an alias of with a `v` prefix, e.g. `1.2.3` for `v1.2.3`.
+    pub fn position(&self, tag: &str) -> Option<usize> {
+        self.versions
+            .iter()
+            .position(|v| v.name == tag)
+            .or_else(|| {
+                let version_name = tag.strip_prefix('v')?;
+                self.versions.iter().position(|v| v.name == version_name)
+            })
+    }
+}
+
+/// A package asked about in a `BatchPackagesRequest`.
+#[derive(Clone, Serialize, Deserialize, Debug, PartialEq)]
+#[cfg_attr(feature = "openapi", derive(schemars::JsonSchema))]
+pub struct BatchPackageQuery {
+    pub name: String,
+    /// A semver requirement, e.g. `^1.2.0`. The newest version matching it that isn't yanked
+    /// is returned as `BatchPackage::matching`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub requirement: Option<String>,
+}
+
+/// The packages to load in one request, see `POST /v0/packages/batch`.
+#[derive(Clone, Serialize, Deserialize, Debug, PartialEq)]
+#[cfg_attr(feature = "openapi", derive(schemars::JsonSchema))]
+pub struct BatchPackagesRequest {
+    pub packages: Vec<BatchPackageQuery>,
+}
+
+/// What the registry has for a `BatchPackageQuery`.
+#[derive(Clone, Serialize, Deserialize, Debug, PartialEq)]
+#[cfg_attr(feature = "openapi", derive(schemars::JsonSchema))]
+pub struct BatchPackage {
+    /// The name as it was asked for.
+    pub name: String,
+    /// The package as it's in the static index, `None` if the registry doesn't have it.
+    pub package: Option<IndexPackage>,
+    /// The newest version matching the requirement that isn't yanked, if one was given.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub matching: Option<IndexVersion>,
+}
+
+/// The packages of a `BatchPackagesRequest`, in the order they were asked for.
+#[derive(Clone, Serialize, Deserialize, Debug, PartialEq)]
+#[cfg_attr(feature = "openapi", derive(schemars::JsonSchema))]
+pub struct BatchPackagesResponse {
+    pub packages: Vec<BatchPackage>,
+}
+
+#[derive(Clone, Serialize, Deserialize, Debug, PartialEq)]
+#[cfg_attr(feature = "openapi", derive(schemars::JsonSchema))]
+pub struct IndexVersion {
+    pub name: String,
+    /// blake3 hash of the tarball, also used to download it.
+    pub id: HashId,
+    pub published_at: u64,
+    /// Yanked by an admin, e.g. for containing malware. A yanked version is never picked as
+    /// the latest version but stays downloadable for lockfiles that pin it.
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub yanked: bool,
+    /// The registry checked the version holds only static content: no executable files, no
+    /// scripts, no symlinks and only allowlisted extensions.
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub static_content: bool,
+}
+
+/// `config.json` at the root of a static index.
+#[derive(Clone, Serialize, Deserialize, Debug, Default)]
+#[cfg_attr(feature = "openapi", derive(schemars::JsonSchema))]
+pub struct IndexConfig {
+    /// The last changelog entry reflected in the index.
+    pub latest_seq: u64,
+    pub generated_at: u64,
+}
+
+/// Result of a conditional request for a package's index file.
+#[derive(Clone, Debug)]
+pub enum IndexFetch {
+    /// The cached copy with the given etag is still current.
+    NotModified,
+    Fetched {
+        package: IndexPackage,
+        etag: Option<String>,
+    },
+}
+
+#[derive(Clone, Serialize, Deserialize, Debug)]
+#[cfg_attr(feature = "openapi", derive(schemars::JsonSchema))]
+pub struct IndexExportResponse {
+    pub latest_seq: u64,
+    /// Number of package files written.
+    pub packages_written: usize,
+}
+
+/// Tags of a git repository to publish as versions.
+#[derive(Clone, Serialize, Deserialize, Debug)]
+#[cfg_attr(feature = "openapi", derive(schemars::JsonSchema))]
+pub struct ImportRepository {
+    pub url: String,
+    pub tags: Vec<String>,
+}
+
+/// Publish tagged versions of existing packages on behalf of a user, e.g. to seed a new registry.
+#[derive(Clone, Serialize, Deserialize, Debug)]
+#[cfg_attr(feature = "openapi", derive(schemars::JsonSchema))]
+pub struct ImportRequest {
+    /// The user the packages are published as, who must exist.
+    pub username: String,
+    pub repositories: Vec<ImportRepository>,
+}
+
+#[derive(Clone, Copy, Serialize, Deserialize, Debug, PartialEq)]
+#[cfg_attr(feature = "openapi", derive(schemars::JsonSchema))]
+#[serde(rename_all = "snake_case")]
+pub enum ImportStatus {
+    Published,
+    /// The version was already published with the same contents.
+    Skipped,
+    Failed,
+}
+
+/// What happened to a tag of an imported repository.
+#[derive(Clone, Serialize, Deserialize, Debug)]
+#[cfg_attr(feature = "openapi", derive(schemars::JsonSchema))]
+pub struct ImportResult {
+    pub url: String,
+    pub tag: String,
+    pub status: ImportStatus,
+    pub package_name: Option<String>,
+    pub version_name: Option<String>,
+    /// Why the tag failed to import.
+    pub error: Option<String>,
+}
+
+#[derive(Clone, Serialize, Deserialize, Debug)]
+#[cfg_attr(feature = "openapi", derive(schemars::JsonSchema))]
+pub struct ImportResponse {
+    pub results: Vec<ImportResult>,
+}
+
+/// Rows and bytes of a table in the registry's database.
+#[derive(Clone, Serialize, Deserialize, Debug)]
+#[cfg_attr(feature = "openapi", derive(schemars::JsonSchema))]
+pub struct TableStats {
+    pub name: String,
+    /// Number of rows, or of values in a multimap table.
+    pub rows: u64,
+    pub stored_bytes: u64,
+    pub metadata_bytes: u64,
+    /// Bytes allocated to the table but unused, reclaimed by compacting the database.
+    pub fragmented_bytes: u64,
+}
+
+#[derive(Clone, Serialize, Deserialize, Debug)]
+#[cfg_attr(feature = "openapi", derive(schemars::JsonSchema))]
+pub struct DbStatsResponse {
+    pub tables: Vec<TableStats>,
+}
+
+#[derive(Clone, Copy, Serialize, Deserialize, Debug, PartialEq, Eq, PartialOrd, Ord)]
+#[cfg_attr(feature = "openapi", derive(schemars::JsonSchema))]
+#[serde(rename_all = "snake_case")]
+pub enum TransactionKind {
+    Read,
+    Write,
+}
+
+/// The transactions of one kind a route of the registry made since it started.
+#[derive(Clone, Serialize, Deserialize, Debug)]
+#[cfg_attr(feature = "openapi", derive(schemars::JsonSchema))]
+pub struct TransactionMetrics {
+    /// The method and route of the requests, e.g. `GET /v0/packages`, `job <name>` for
+    /// background jobs, or `other`.
+    pub route: String,
+    pub kind: TransactionKind,
+    pub count: u64,
+    /// Transactions that took longer than `slow_transaction_ms`.
+    pub slow: u64,
+    pub total_micros: u64,
+    pub max_micros: u64,
+    /// Every table the transactions opened.
+    pub tables: Vec<String>,
+}
+
+#[derive(Clone, Serialize, Deserialize, Debug)]
+#[cfg_attr(feature = "openapi", derive(schemars::JsonSchema))]
+pub struct DbMetricsResponse {
+    /// Transactions that take longer than this are logged.
+    pub slow_transaction_ms: u64,
+    /// Those that took longest in total first.
+    pub transactions: Vec<TransactionMetrics>,
+    #[serde(default)]
+    pub caches: Vec<CacheMetrics>,
+}
+
+/// How well one of the registry's in-memory caches of database reads is doing since it
+/// started.
+#[derive(Clone, Serialize, Deserialize, Debug)]
+#[cfg_attr(feature = "openapi", derive(schemars::JsonSchema))]
+pub struct CacheMetrics {
+    pub name: String,
+    pub entries: u64,
+    pub capacity: u64,
+    pub hits: u64,
+    pub misses: u64,
+}
+
+#[derive(Clone, Serialize, Deserialize, Debug, Default)]
+#[cfg_attr(feature = "openapi", derive(schemars::JsonSchema))]
+pub struct DbCheckRequest {
+    /// Fix the inconsistencies that can be fixed without losing published data.
+    #[serde(default)]
+    pub repair: bool,
+}
+
+/// A row that refers to something that doesn't exist.
+#[derive(Clone, Serialize, Deserialize, Debug, PartialEq)]
+#[cfg_attr(feature = "openapi", derive(schemars::JsonSchema))]
+pub struct DbInconsistency {
+    pub table: String,
+    pub key: String,
+    pub problem: String,
+    pub repaired: bool,
+}
+
+#[derive(Clone, Serialize, Deserialize, Debug)]
+#[cfg_attr(feature = "openapi", derive(schemars::JsonSchema))]
+pub struct DbCheckResponse {
+    pub inconsistencies: Vec<DbInconsistency>,
+}
+
+/// A json document and ed25519 signatures over its exact bytes. The document is kept as a
+/// string so signatures don't depend on how it's serialized.
+#[derive(Clone, Serialize, Deserialize, Debug, PartialEq)]
+#[cfg_attr(feature = "openapi", derive(schemars::JsonSchema))]
+pub struct SignedDocument {
+    pub signed: String,
+    pub signatures: Vec<DocumentSignature>,
+}
+
+impl SignedDocument {
+    /// Parse the signed document. Check the signatures first.
+    pub fn document<T: serde::de::DeserializeOwned>(&self) -> anyhow::Result<T> {
+        Ok(serde_json::from_str(&self.signed)?)
+    }
+}
+
+#[derive(Clone, Serialize, Deserialize, Debug, PartialEq)]
+#[cfg_attr(feature = "openapi", derive(schemars::JsonSchema))]
+pub struct DocumentSignature {
+    /// Hex encoded public key that made the signature.
+    pub key: String,
+    /// Hex encoded signature.
+    pub signature: String,
+}
+
+/// The keys a registry is trusted with, signed by its root keys. The root keys are kept
+/// offline. Version `n + 1` of the root must also be signed by a root key of version `n`, so
+/// clients that trust one version can follow rotations.
+#[derive(Clone, Serialize, Deserialize, Debug, PartialEq)]
+#[cfg_attr(feature = "openapi", derive(schemars::JsonSchema))]
+pub struct RegistryRoot {
+    pub version: u64,
+    pub expires_at: u64,
+    /// Hex encoded ed25519 public keys that sign the root.
+    pub root_keys: Vec<String>,
+    /// Hex encoded ed25519 public keys that sign snapshots.
+    pub snapshot_keys: Vec<String>,
+}
+
+/// Every published version of every package at a point in the changelog, signed by a
+/// snapshot key of the registry root.
+#[derive(Clone, Serialize, Deserialize, Debug, PartialEq)]
+#[cfg_attr(feature = "openapi", derive(schemars::JsonSchema))]
+pub struct Snapshot {
+    /// The last changelog entry included. Snapshots never go back, clients reject one older
+    /// than what they've already seen.
+    pub id: u64,
+    /// Version of the root that delegates to the signing key.
+    pub root_version: u64,
+    pub generated_at: u64,
+    pub expires_at: u64,
+    /// Package name keyed to version name keyed to the blake3 hash of the version tarball.
+    pub packages: BTreeMap<String, BTreeMap<String, String>>,
+    /// Head of the transparency log at the same point in the changelog.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub log: Option<LogHead>,
+}
+
+/// Packages related to a package through the dependencies of their latest versions.
+#[derive(Clone, Serialize, Deserialize, Debug, Default)]
+#[cfg_attr(feature = "openapi", derive(schemars::JsonSchema))]
+pub struct PackageGraphResponse {
+    pub dependencies: Vec<PackageDependency>,
+    /// Names of up to 100 dependent packages, see `dependent_count` for the total.
+    pub dependents: Vec<String>,
+    pub dependent_count: usize,
+}
+
+/// A package version reached from the root of a dependency tree.
+#[derive(Clone, Serialize, Deserialize, Debug, PartialEq)]
+#[cfg_attr(feature = "openapi", derive(schemars::JsonSchema))]
+pub struct DependencyTreeNode {
+    pub package_name: String,
+    pub version_name: String,
+    /// The fewest dependency edges from the root, 0 for the root itself.
+    pub depth: usize,
+    pub yanked: bool,
+    /// Why the owner deprecated the package, if they did.
+    pub deprecated: Option<String>,
+    /// Indices in `DependencyTreeResponse::nodes` of the direct dependencies, sorted.
+    pub dependencies: Vec<usize>,
+}
+
+/// Every registry package a version depends on, directly or through other packages. Each
+/// package version appears once, so the tree is a graph where packages are shared.
+#[derive(Clone, Serialize, Deserialize, Debug, Default)]
+#[cfg_attr(feature = "openapi", derive(schemars::JsonSchema))]
+pub struct DependencyTreeResponse {
+    /// The root version first, then by depth.
+    pub nodes: Vec<DependencyTreeNode>,
+    /// Whether nodes were left out because the tree is too large.
+    pub truncated: bool,
 }
@@ -10,9 +10,16 @@ use storage::*;
 pub use http::OnyxApi;
 
 #[cfg(debug_assertions)]
-pub const REGISTRY_URL: &'static str = "http://127.0.0.1:3000";
+const DEFAULT_REGISTRY_URL: &str = "http://127.0.0.1:3000";
 #[cfg(not(debug_assertions))]
-pub const REGISTRY_URL: &'static str = "https://api.nrpm.io";
+const DEFAULT_REGISTRY_URL: &str = "https://api.nrpm.io";
+
+/// The registry api used by default, `ONYX_API_URL` at build time if set, e.g. to build the web
+/// app for a self-hosted registry.
+pub const REGISTRY_URL: &str = match option_env!("ONYX_API_URL") {
+    Some(url) => url,
+    None => DEFAULT_REGISTRY_URL,
+};
 
 pub fn timestamp() -> u64 {
     use std::time::{SystemTime, UNIX_EPOCH};
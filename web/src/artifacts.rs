@@ -0,0 +1,50 @@
+use dioxus::prelude::*;
+use onyx_api::prelude::*;
+
+/// Download links for the compiled artifacts attached to a version, if any.
+#[component]
+pub fn Artifacts(version_id: HashId) -> Element {
+    let mut artifacts: Signal<Vec<VersionArtifactModel>> = use_signal(Vec::new);
+
+    use_effect(use_reactive!(|version_id| {
+        artifacts.set(vec![]);
+        spawn(async move {
+            // the section is optional, leave it out if the artifacts can't be loaded
+            let loaded = OnyxApi::default()
+                .version_artifacts(&version_id)
+                .await
+                .unwrap_or_default();
+            artifacts.set(loaded);
+        });
+    }));
+
+    if artifacts.read().is_empty() {
+        return rsx! {};
+    }
+    let api = OnyxApi::default();
+    rsx! {
+        div {
+            h4 {
+                style: "margin: 0px",
+                "Compiled artifacts"
+            }
+        }
+        div {
+            style: "margin-left: 8px;",
+            for artifact in artifacts.read().iter() {
+                div {
+                    a {
+                        href: api.artifact_download_url(&version_id, &artifact.name),
+                        "{artifact.name}.json"
+                    }
+                    if let Some(noir_version) = &artifact.noir_version {
+                        " (noir {noir_version})"
+                    }
+                }
+            }
+        }
+        div {
+            style: "width: 100%; margin: 4px 0px; border-bottom: 1px solid black;"
+        },
+    }
+}
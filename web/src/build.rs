@@ -0,0 +1,67 @@
+use dioxus::prelude::*;
+use onyx_api::prelude::*;
+
+/// The result of the registry rebuilding a version, if it has been rebuilt.
+#[component]
+pub fn BuildBadge(version_id: HashId) -> Element {
+    let mut build: Signal<Option<VersionBuildModel>> = use_signal(|| None);
+
+    use_effect(use_reactive!(|version_id| {
+        build.set(None);
+        spawn(async move {
+            // the badge is optional, leave it out if the build can't be loaded
+            let loaded = OnyxApi::default()
+                .version_build(&version_id)
+                .await
+                .unwrap_or_default();
+            build.set(loaded);
+        });
+    }));
+
+    let Some(build) = build.read().clone() else {
+        return rsx! {};
+    };
+    let (label, color, title) = match build.status {
+        BuildStatus::Verified => (
+            "✓ verified build",
+            "green",
+            "The registry rebuilt this version and its artifacts match",
+        ),
+        BuildStatus::Mismatch => (
+            "✗ build mismatch",
+            "darkred",
+            "The registry rebuilt this version and an artifact doesn't match",
+        ),
+        BuildStatus::Built => (
+            "builds",
+            "black",
+            "The registry rebuilt this version, it has no artifacts to compare",
+        ),
+        BuildStatus::Failed => (
+            "✗ build failed",
+            "darkred",
+            "The registry failed to rebuild this version",
+        ),
+    };
+    rsx! {
+        div {
+            span {
+                style: "border: 1px solid {color}; color: {color}; padding: 0px 4px;",
+                title: "{title}",
+                "{label}"
+            }
+            if let Some(nargo_version) = &build.nargo_version {
+                " {nargo_version}"
+            }
+        }
+        for artifact in build.artifacts.iter().filter(|artifact| !artifact.matches) {
+            div {
+                style: "margin-left: 8px;",
+                "{artifact.name}.json doesn't match the rebuilt artifact"
+            }
+        }
+        div {
+            style: "width: 100%; margin: 4px 0px; border-bottom: 1px solid black;"
+        },
+    }
+}
@@ -0,0 +1,178 @@
+use dioxus::prelude::*;
+use onyx_api::prelude::*;
+
+use super::components::Header;
+use super::propose_token::get_query_param;
+use crate::Route;
+
+/// Color of a line of a unified diff.
+fn line_style(line: &str) -> &'static str {
+    if line.starts_with("+++") || line.starts_with("---") {
+        "color: dimgray;"
+    } else if line.starts_with('+') {
+        "background-color: #e6ffec;"
+    } else if line.starts_with('-') {
+        "background-color: #ffebe9;"
+    } else if line.starts_with("@@") {
+        "color: #0550ae;"
+    } else {
+        ""
+    }
+}
+
+fn change_label(change: FileChange) -> &'static str {
+    match change {
+        FileChange::Added => "added",
+        FileChange::Removed => "removed",
+        FileChange::Modified => "modified",
+    }
+}
+
+fn hash_label(hash: &Option<String>) -> &str {
+    hash.as_deref().unwrap_or("(none)")
+}
+
+/// What changed between two versions of a package, chosen with `?from=` and `?to=`. Defaults
+/// to the two latest versions.
+#[component]
+pub fn CompareView(package_name: String) -> Element {
+    let mut versions: Signal<Vec<PackageVersionModel>> = use_signal(Vec::new);
+    let mut from = use_signal(|| get_query_param("from"));
+    let mut to = use_signal(|| get_query_param("to"));
+    let mut diff: Signal<Option<VersionDiff>> = use_signal(|| None);
+    let mut status = use_signal(|| String::new());
+
+    let name = package_name.clone();
+    use_effect(move || {
+        let package_name = name.clone();
+        spawn(async move {
+            match OnyxApi::default()
+                .load_package_versions(&package_name)
+                .await
+            {
+                Ok((_package, mut loaded)) => {
+                    loaded.sort_by_key(|version| std::cmp::Reverse(version.created_at));
+                    let (default_to, default_from) = (loaded.first(), loaded.get(1));
+                    if to.peek().is_empty() {
+                        to.set(default_to.map(|v| v.name.clone()).unwrap_or_default());
+                    }
+                    if from.peek().is_empty() {
+                        from.set(default_from.map(|v| v.name.clone()).unwrap_or_default());
+                    }
+                    versions.set(loaded);
+                }
+                Err(e) => status.set(format!("Error: {e:#}")),
+            }
+        });
+    });
+
+    let name = package_name.clone();
+    use_effect(move || {
+        let package_name = name.clone();
+        let from = from.read().clone();
+        let to = to.read().clone();
+        if from.is_empty() || to.is_empty() {
+            return;
+        }
+        spawn(async move {
+            diff.set(None);
+            match OnyxApi::default()
+                .package_diff(&package_name, &from, &to)
+                .await
+            {
+                Ok(d) => {
+                    status.set(String::new());
+                    diff.set(Some(d));
+                }
+                Err(e) => status.set(format!("Error: {e:#}")),
+            }
+        });
+    });
+
+    rsx! {
+        Header { show_auth: true },
+        div {
+            style: "padding: 40px; font-family: Arial, sans-serif;",
+            h3 {
+                style: "margin: 0px; margin-bottom: 8px;",
+                Link {
+                    to: Route::PackageView { package_name: package_name.clone() },
+                    "{package_name}"
+                }
+                " - compare versions"
+            }
+            div {
+                style: "display: flex; flex-direction: row; align-items: center; margin-bottom: 16px;",
+                select {
+                    style: "padding: 4px;",
+                    onchange: move |e| from.set(e.value()),
+                    for version in versions.read().iter() {
+                        option {
+                            key: "{version.id.to_string()}",
+                            value: "{version.name}",
+                            selected: *from.read() == version.name,
+                            "{version.name}"
+                        }
+                    }
+                }
+                span {
+                    style: "margin: 0px 8px;",
+                    "→"
+                }
+                select {
+                    style: "padding: 4px;",
+                    onchange: move |e| to.set(e.value()),
+                    for version in versions.read().iter() {
+                        option {
+                            key: "{version.id.to_string()}",
+                            value: "{version.name}",
+                            selected: *to.read() == version.name,
+                            "{version.name}"
+                        }
+                    }
+                }
+            }
+            if !status.read().is_empty() {
+                div {
+                    style: "padding: 10px; border-radius: 4px; text-align: center; font-weight: bold; background-color: #f8d7da; color: #721c24; border: 1px solid #f5c6cb;",
+                    "{status}"
+                }
+            }
+            if let Some(diff) = diff.read().as_ref() {
+                if diff.files.is_empty() {
+                    div { "No changes" }
+                }
+                for file in diff.files.iter() {
+                    div {
+                        key: "{file.path}",
+                        style: "margin-bottom: 16px;",
+                        div {
+                            style: "font-weight: bold; margin-bottom: 4px;",
+                            "{change_label(file.change)} "
+                            span {
+                                style: "font-family: monospace;",
+                                "{file.path}"
+                            }
+                        }
+                        if let Some(unified_diff) = file.unified_diff.as_ref() {
+                            pre {
+                                style: "margin: 0px; padding: 8px; overflow-x: auto; border: 1px solid #ddd; border-radius: 4px;",
+                                for line in unified_diff.lines() {
+                                    div {
+                                        style: line_style(line),
+                                        "{line}"
+                                    }
+                                }
+                            }
+                        } else {
+                            div {
+                                style: "color: dimgray; font-family: monospace;",
+                                "blake3 {hash_label(&file.from_blake3)} → {hash_label(&file.to_blake3)}"
+                            }
+                        }
+                    }
+                }
+            }
+        }
+    }
+}
@@ -14,6 +14,7 @@ pub fn Auth(props: AuthProps) -> Element {
 
     let mut username = use_signal(|| String::new());
     let mut password = use_signal(|| String::new());
+    let mut remember_me = use_signal(|| false);
     let status_message = use_signal(|| String::new());
 
     let is_loading = auth_store.read().is_loading().clone();
@@ -23,6 +24,7 @@ pub fn Auth(props: AuthProps) -> Element {
         move |_| {
             let username_val = username.read().clone();
             let password_val = password.read().clone();
+            let remember_val = *remember_me.read();
             let mut status = status_message.clone();
             spawn(async move {
                 status.set("Logging in...".to_string());
@@ -32,11 +34,15 @@ pub fn Auth(props: AuthProps) -> Element {
                     .login(LoginRequest {
                         username: username_val,
                         password: password_val,
+                        remember_me: remember_val,
                     })
                     .await
                 {
                     Ok(login) => {
-                        auth_store.with_mut(|v| v.set_login(login));
+                        auth_store.with_mut(|v| {
+                            v.remember.set(remember_val);
+                            v.set_login(login)
+                        });
                         props.on_auth.call(());
                     }
                     Err(e) => status.set(format!("Login failed: {e}")),
@@ -49,6 +55,7 @@ pub fn Auth(props: AuthProps) -> Element {
         move |_| {
             let username_val = username.read().clone();
             let password_val = password.read().clone();
+            let remember_val = *remember_me.read();
             let mut status = status_message.clone();
 
             spawn(async move {
@@ -59,11 +66,15 @@ pub fn Auth(props: AuthProps) -> Element {
                     .signup(LoginRequest {
                         username: username_val,
                         password: password_val,
+                        remember_me: remember_val,
                     })
                     .await
                 {
                     Ok(login) => {
-                        auth_store.with_mut(|v| v.set_login(login));
+                        auth_store.with_mut(|v| {
+                            v.remember.set(remember_val);
+                            v.set_login(login)
+                        });
                         props.on_auth.call(());
                     }
                     Err(e) => status.set(format!("Signup failed: {e}")),
@@ -138,7 +149,7 @@ pub fn Auth(props: AuthProps) -> Element {
                 }
 
                 div {
-                    style: "margin-bottom: 30px;",
+                    style: "margin-bottom: 20px;",
                     label {
                         style: "display: block; margin-bottom: 5px; font-weight: bold; color: #555;",
                         "Password:"
@@ -152,6 +163,20 @@ pub fn Auth(props: AuthProps) -> Element {
                     }
                 }
 
+                div {
+                    style: "margin-bottom: 30px;",
+                    label {
+                        style: "color: #555; cursor: pointer;",
+                        input {
+                            r#type: "checkbox",
+                            checked: *remember_me.read(),
+                            onchange: move |e| remember_me.set(e.checked()),
+                            style: "margin-right: 8px;",
+                        }
+                        "Remember me"
+                    }
+                }
+
                 div {
                     style: "display: flex; gap: 10px; margin-bottom: 20px;",
 
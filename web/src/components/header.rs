@@ -1,6 +1,7 @@
 use dioxus::prelude::*;
 
 use crate::Route;
+use crate::notifications::Notifications;
 
 #[component]
 pub fn Header(show_auth: bool) -> Element {
@@ -26,6 +27,22 @@ pub fn Header(show_auth: bool) -> Element {
                             style: "margin-bottom: 8px;",
                             "Welcome back, {login.user.username}"
                         }
+                        Notifications {}
+                        Link {
+                            style: "margin-bottom: 8px;",
+                            to: Route::SessionsView,
+                            "Sessions"
+                        }
+                        Link {
+                            style: "margin-bottom: 8px;",
+                            to: Route::TransfersView,
+                            "Transfers"
+                        }
+                        Link {
+                            style: "margin-bottom: 8px;",
+                            to: Route::SettingsView,
+                            "Settings"
+                        }
                         button {
                             style: "flex: 1; padding: 12px; background-color: #007bff; color: white; border: none; border-radius: 4px; font-size: 16px; cursor: pointer; transition: background-color 0.2s;",
                             onclick: {
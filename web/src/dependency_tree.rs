@@ -0,0 +1,155 @@
+use dioxus::prelude::*;
+use onyx_api::prelude::*;
+
+use crate::Route;
+
+const NODE_WIDTH: usize = 180;
+const NODE_HEIGHT: usize = 28;
+const COLUMN_GAP: usize = 60;
+const ROW_GAP: usize = 12;
+
+/// Fill and text color of a node, red for yanked versions and orange for deprecated packages.
+fn node_colors(node: &DependencyTreeNode) -> (&'static str, &'static str) {
+    if node.yanked {
+        ("#f8d7da", "#721c24")
+    } else if node.deprecated.is_some() {
+        ("#fff3cd", "#856404")
+    } else {
+        ("#f5f5f5", "black")
+    }
+}
+
+/// Where each node is drawn, a column per depth and a row per node in it.
+fn layout(tree: &DependencyTreeResponse) -> (Vec<(usize, usize)>, usize, usize) {
+    let mut rows = vec![];
+    let mut positions = vec![];
+    for node in &tree.nodes {
+        if rows.len() <= node.depth {
+            rows.resize(node.depth + 1, 0);
+        }
+        positions.push((
+            node.depth * (NODE_WIDTH + COLUMN_GAP),
+            rows[node.depth] * (NODE_HEIGHT + ROW_GAP),
+        ));
+        rows[node.depth] += 1;
+    }
+    let width = rows.len() * (NODE_WIDTH + COLUMN_GAP) - COLUMN_GAP;
+    let height = rows.iter().max().copied().unwrap_or(0) * (NODE_HEIGHT + ROW_GAP);
+    (positions, width, height.saturating_sub(ROW_GAP))
+}
+
+/// Every registry package a version depends on, drawn as layers by depth. Nodes link to
+/// their packages.
+#[component]
+pub fn DependencyTree(package_name: String, version_name: String) -> Element {
+    let navigator = use_navigator();
+    let mut tree: Signal<Option<DependencyTreeResponse>> = use_signal(|| None);
+
+    use_effect(use_reactive!(|package_name, version_name| {
+        tree.set(None);
+        spawn(async move {
+            // the tree is optional, leave it out if it can't be loaded
+            let loaded = OnyxApi::default()
+                .dependency_tree(&package_name, Some(&version_name))
+                .await
+                .ok();
+            tree.set(loaded);
+        });
+    }));
+
+    let Some(tree) = tree.read().clone() else {
+        return rsx! {};
+    };
+    if tree.nodes.len() < 2 {
+        return rsx! {};
+    }
+    let (positions, width, height) = layout(&tree);
+    let yanked = tree.nodes.iter().filter(|node| node.yanked).count();
+    let deprecated = tree
+        .nodes
+        .iter()
+        .filter(|node| node.deprecated.is_some())
+        .count();
+    let mut edges = vec![];
+    for (i, node) in tree.nodes.iter().enumerate() {
+        for dependency in &node.dependencies {
+            edges.push((positions[i], positions[*dependency]));
+        }
+    }
+    rsx! {
+        div {
+            h4 {
+                style: "margin: 0px",
+                "Dependency tree"
+            }
+            div {
+                style: "margin-left: 8px; color: dimgray;",
+                "{tree.nodes.len() - 1} packages"
+                if tree.truncated {
+                    " or more"
+                }
+                if yanked > 0 {
+                    ", {yanked} yanked"
+                }
+                if deprecated > 0 {
+                    ", {deprecated} deprecated"
+                }
+            }
+        }
+        div {
+            style: "overflow-x: auto; padding: 8px;",
+            svg {
+                width: "{width}",
+                height: "{height}",
+                view_box: "0 0 {width} {height}",
+                for ((from_x, from_y), (to_x, to_y)) in edges {
+                    line {
+                        x1: "{from_x + NODE_WIDTH}",
+                        y1: "{from_y + NODE_HEIGHT / 2}",
+                        x2: "{to_x}",
+                        y2: "{to_y + NODE_HEIGHT / 2}",
+                        stroke: "gray",
+                    }
+                }
+                for (node, (x, y)) in tree.nodes.iter().cloned().zip(positions) {
+                    g {
+                        key: "{node.package_name}@{node.version_name}",
+                        style: "cursor: pointer;",
+                        onclick: {
+                            let package_name = node.package_name.clone();
+                            move |_| {
+                                navigator.push(Route::PackageView {
+                                    package_name: package_name.clone(),
+                                });
+                            }
+                        },
+                        rect {
+                            x: "{x}",
+                            y: "{y}",
+                            width: "{NODE_WIDTH}",
+                            height: "{NODE_HEIGHT}",
+                            rx: "4",
+                            fill: node_colors(&node).0,
+                            stroke: "gray",
+                        }
+                        text {
+                            x: "{x + 6}",
+                            y: "{y + NODE_HEIGHT / 2 + 4}",
+                            font_size: "12",
+                            fill: node_colors(&node).1,
+                            "{node.package_name} {node.version_name}"
+                            if node.yanked {
+                                " (yanked)"
+                            } else if node.deprecated.is_some() {
+                                " (deprecated)"
+                            }
+                        }
+                    }
+                }
+            }
+        }
+        div {
+            style: "width: 100%; margin: 4px 0px; border-bottom: 1px solid black;"
+        },
+    }
+}
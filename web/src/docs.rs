@@ -0,0 +1,104 @@
+use dioxus::prelude::*;
+use nargo_parse::*;
+use onyx_api::prelude::*;
+
+use nrpm_markdown::render_markdown;
+
+fn kind_label(kind: DocItemKind) -> &'static str {
+    match kind {
+        DocItemKind::Function => "fn",
+        DocItemKind::Struct => "struct",
+        DocItemKind::Trait => "trait",
+        DocItemKind::Global => "global",
+        DocItemKind::Type => "type",
+    }
+}
+
+/// The public api of a version, built from the doc comments extracted when it was published.
+#[component]
+pub fn ApiReference(version_id: HashId) -> Element {
+    let mut docs: Signal<Option<PackageDocs>> = use_signal(|| None);
+    let mut status = use_signal(|| String::new());
+
+    use_effect(use_reactive!(|version_id| {
+        spawn(async move {
+            match OnyxApi::default().version_docs(&version_id).await {
+                Ok(d) => docs.set(Some(d)),
+                Err(e) => status.set(format!("Failed to load docs: {e:#}")),
+            }
+        });
+    }));
+
+    let docs = docs.read();
+    let Some(docs) = docs.as_ref() else {
+        return rsx! {
+            div {
+                style: "padding: 8px; color: dimgray;",
+                if status.read().is_empty() {
+                    "Loading..."
+                } else {
+                    "{status}"
+                }
+            }
+        };
+    };
+    if docs.modules.is_empty() {
+        return rsx! {
+            div {
+                style: "padding: 8px; color: dimgray;",
+                "This package has no documented public items."
+            }
+        };
+    }
+
+    rsx! {
+        div {
+            style: "padding: 8px;",
+            for module in docs.modules.iter() {
+                div {
+                    key: "{module.path}",
+                    style: "margin-bottom: 16px;",
+                    h3 {
+                        style: "margin: 0px; margin-bottom: 4px; font-family: monospace;",
+                        if module.path.is_empty() {
+                            "crate"
+                        } else {
+                            "{module.path}"
+                        }
+                    }
+                    if !module.doc.is_empty() {
+                        div {
+                            dangerous_inner_html: render_markdown(&module.doc, None)
+                        }
+                    }
+                    for item in module.items.iter() {
+                        div {
+                            key: "{item.parent.clone().unwrap_or_default()}::{item.name}",
+                            style: "margin: 8px 0px 8px 8px; padding-left: 8px; border-left: 2px solid #ccc;",
+                            div {
+                                style: "font-family: monospace; font-weight: bold;",
+                                span {
+                                    style: "color: purple;",
+                                    "{kind_label(item.kind)} "
+                                }
+                                if let Some(parent) = &item.parent {
+                                    "{parent}::"
+                                }
+                                "{item.name}"
+                            }
+                            pre {
+                                style: "overflow: scroll; margin: 4px 0px; background: white; padding: 4px;",
+                                "{item.signature}"
+                            }
+                            if !item.doc.is_empty() {
+                                div {
+                                    dangerous_inner_html: render_markdown(&item.doc, None)
+                                }
+                            }
+                        }
+                    }
+                }
+            }
+        }
+    }
+}
@@ -18,7 +18,10 @@ pub fn HomeView() -> Element {
                 Ok(p) => {
                     let mut a = p
                         .into_iter()
-                        .map(|(p, v)| (p, v.clone(), api.version_download_url(&v.id)))
+                        .map(|(p, v)| {
+                            let download_url = api.package_download_url(&p.name, &v.name);
+                            (p, v, download_url)
+                        })
                         .collect::<Vec<_>>();
                     a.sort_by(|v0, v1| v1.1.created_at.cmp(&v0.1.created_at));
                     packages.set(a);
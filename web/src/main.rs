@@ -1,16 +1,33 @@
 use dioxus::prelude::*;
 
+mod artifacts;
 mod auth;
+mod build;
+mod compare;
 mod components;
+mod dependency_tree;
+mod docs;
 mod home;
+mod moderation;
+mod notifications;
 mod package;
 mod propose_token;
+mod release_notes;
+mod service_worker;
+mod sessions;
+mod settings;
 mod stores;
+mod transfers;
 
 use auth::AuthView;
+use compare::CompareView;
 use home::HomeView;
+use moderation::ModerationView;
 use package::PackageView;
 use propose_token::ProposeTokenView;
+use sessions::SessionsView;
+use settings::SettingsView;
+use transfers::TransfersView;
 
 use stores::*;
 
@@ -22,8 +39,18 @@ enum Route {
     AuthView,
     #[route("/_/propose_token")]
     ProposeTokenView,
+    #[route("/_/sessions")]
+    SessionsView,
+    #[route("/_/settings")]
+    SettingsView,
+    #[route("/_/transfers")]
+    TransfersView,
+    #[route("/_/moderation")]
+    ModerationView,
     #[route("/:package_name")]
     PackageView { package_name: String },
+    #[route("/:package_name/compare")]
+    CompareView { package_name: String },
 }
 
 fn app() -> Element {
@@ -37,5 +64,6 @@ fn app() -> Element {
 
 fn main() {
     gloo_utils::document().set_title("Noir Package Manager");
+    service_worker::register();
     launch(app);
 }
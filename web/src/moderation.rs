@@ -0,0 +1,281 @@
+use dioxus::prelude::*;
+use onyx_api::prelude::*;
+
+use super::components::Auth;
+use super::components::Header;
+use crate::Route;
+
+/// Open package reports and flagged uploads for admins to dismiss or act on.
+#[component]
+pub fn ModerationView() -> Element {
+    let auth_store = &crate::AUTH_STORE;
+
+    let mut reports: Signal<Vec<PackageReportModel>> = use_signal(Vec::new);
+    let mut uploads: Signal<Vec<PendingPublishModel>> = use_signal(Vec::new);
+    let mut note = use_signal(|| String::new());
+    let mut status_message = use_signal(|| String::new());
+
+    let load_reports = move || {
+        spawn(async move {
+            let token = auth_store.read().token.read().clone();
+            let Some(token) = token else {
+                return;
+            };
+            match auth_store.read().api.admin_reports(&token).await {
+                Ok(r) => reports.set(r),
+                Err(e) => status_message.set(format!("Failed to load reports: {e:#}")),
+            }
+            match auth_store.read().api.admin_pending_publishes(&token).await {
+                Ok(u) => uploads.set(
+                    u.into_iter()
+                        .filter(|upload| upload.status == PublishStatus::Flagged)
+                        .collect(),
+                ),
+                Err(e) => status_message.set(format!("Failed to load uploads: {e:#}")),
+            }
+        });
+    };
+
+    use_effect(move || {
+        if auth_store.read().login.read().is_some() {
+            load_reports();
+        }
+    });
+
+    let resolve = move |report_id: String, action: ModerationAction| {
+        spawn(async move {
+            let token = auth_store.read().token.read().clone();
+            let Some(token) = token else {
+                return;
+            };
+            let request = ResolveReportRequest {
+                action,
+                version_name: None,
+                note: note.read().clone(),
+            };
+            match auth_store
+                .read()
+                .api
+                .admin_resolve_report(&token, &report_id, request)
+                .await
+            {
+                Ok(_) => {
+                    note.set(String::new());
+                    status_message.set(String::new());
+                    load_reports();
+                }
+                Err(e) => status_message.set(format!("Failed to resolve report: {e:#}")),
+            }
+        });
+    };
+
+    let review_upload = move |version_id: HashId, release: bool| {
+        spawn(async move {
+            let token = auth_store.read().token.read().clone();
+            let Some(token) = token else {
+                return;
+            };
+            let api = auth_store.read().api.clone();
+            let result = if release {
+                api.admin_release_publish(&token, &version_id)
+                    .await
+                    .map(|_| ())
+            } else {
+                api.admin_reject_publish(&token, &version_id).await
+            };
+            match result {
+                Ok(()) => {
+                    status_message.set(String::new());
+                    load_reports();
+                }
+                Err(e) => status_message.set(format!("Failed to review upload: {e:#}")),
+            }
+        });
+    };
+
+    rsx! {
+        Header { show_auth: true },
+        if auth_store.read().login.read().is_some() {
+            div {
+                style: "padding: 20px;",
+                h2 { "Reported packages" }
+                input {
+                    style: "width: 100%; padding: 4px; margin-bottom: 8px; box-sizing: border-box;",
+                    placeholder: "note for the action taken",
+                    value: "{note}",
+                    oninput: move |e| note.set(e.value()),
+                }
+                if reports.read().is_empty() {
+                    p { style: "color: #666;", "No reports are waiting for review." }
+                }
+                for report in reports.read().iter().cloned() {
+                    div {
+                        key: "{report.id}",
+                        style: "padding: 8px; border-bottom: 1px solid #ddd;",
+                        div {
+                            Link {
+                                to: Route::PackageView { package_name: report.package_name.clone() },
+                                "{report.package_name}"
+                            }
+                            if let Some(version_name) = &report.version_name {
+                                " version {version_name}"
+                            }
+                            " reported for {report.reason:?} by {report.username}"
+                        }
+                        p {
+                            style: "color: dimgray; white-space: pre-wrap;",
+                            "{report.details}"
+                        }
+                        div {
+                            for (action, label, color) in [
+                                (ModerationAction::Dismiss, "Dismiss", "#6b7280"),
+                                (ModerationAction::Yank, "Yank version", "#f59e0b"),
+                                (ModerationAction::Delete, "Delete package", "#f87171"),
+                                (ModerationAction::Ban, "Ban owner", "#b91c1c"),
+                            ] {
+                                if action != ModerationAction::Yank || report.version_name.is_some() {
+                                    button {
+                                        style: "margin-right: 8px; padding: 8px; background-color: {color}; color: white; border: none; border-radius: 4px; cursor: pointer;",
+                                        onclick: {
+                                            let report_id = report.id.clone();
+                                            move |_| resolve(report_id.clone(), action)
+                                        },
+                                        "{label}"
+                                    }
+                                }
+                            }
+                        }
+                    }
+                }
+                h2 { "Flagged uploads" }
+                if uploads.read().is_empty() {
+                    p { style: "color: #666;", "No uploads are waiting for review." }
+                }
+                for upload in uploads.read().iter().cloned() {
+                    div {
+                        key: "{upload.id.to_string()}",
+                        style: "padding: 8px; border-bottom: 1px solid #ddd;",
+                        div {
+                            "{upload.package_name} version {upload.version_name}"
+                        }
+                        for finding in upload.findings.iter() {
+                            p {
+                                style: "color: dimgray; white-space: pre-wrap;",
+                                "{finding.scanner}: {finding.output}"
+                            }
+                        }
+                        div {
+                            for (release, label, color) in [
+                                (true, "Publish", "#6b7280"),
+                                (false, "Reject", "#f87171"),
+                            ] {
+                                button {
+                                    style: "margin-right: 8px; padding: 8px; background-color: {color}; color: white; border: none; border-radius: 4px; cursor: pointer;",
+                                    onclick: {
+                                        let version_id = upload.id.clone();
+                                        move |_| review_upload(version_id.clone(), release)
+                                    },
+                                    "{label}"
+                                }
+                            }
+                        }
+                    }
+                }
+                if !status_message.read().is_empty() {
+                    div {
+                        style: "margin-top: 10px; padding: 10px; border-radius: 4px; text-align: center; font-weight: bold; background-color: #f8d7da; color: #721c24; border: 1px solid #f5c6cb;",
+                        "{status_message}"
+                    }
+                }
+            }
+        } else {
+            Auth {
+                on_auth: move |_| {}
+            }
+        }
+    }
+}
+
+/// Report a package to the registry admins. Shown on the package page to logged in users
+/// who don't own it.
+#[component]
+pub fn ReportPackage(package_name: String, version_name: String) -> Element {
+    let auth_store = &crate::AUTH_STORE;
+
+    let mut reason = use_signal(|| ReportReason::Malware);
+    let mut details = use_signal(|| String::new());
+    let mut status_message = use_signal(|| String::new());
+
+    let handle_report = move |_| {
+        let package_name = package_name.clone();
+        let version_name = version_name.clone();
+        spawn(async move {
+            let token = auth_store.read().token.read().clone();
+            let Some(token) = token else {
+                return;
+            };
+            let request = ReportPackageRequest {
+                reason: *reason.read(),
+                details: details.read().clone(),
+                version_name: Some(version_name),
+            };
+            match auth_store
+                .read()
+                .api
+                .report_package(&token, &package_name, request)
+                .await
+            {
+                Ok(_) => {
+                    details.set(String::new());
+                    status_message.set("Reported, thank you. An admin will review it.".into());
+                }
+                Err(e) => status_message.set(format!("Failed to report: {e:#}")),
+            }
+        });
+    };
+
+    rsx! {
+        div {
+            h4 {
+                style: "margin: 0px",
+                "Report this package"
+            }
+        }
+        div {
+            style: "display: flex; flex-direction: row; margin: 4px 0px;",
+            select {
+                style: "padding: 4px;",
+                onchange: move |e| {
+                    reason.set(match e.value().as_str() {
+                        "typosquatting" => ReportReason::Typosquatting,
+                        "spam" => ReportReason::Spam,
+                        "other" => ReportReason::Other,
+                        _ => ReportReason::Malware,
+                    })
+                },
+                option { value: "malware", "Malware" }
+                option { value: "typosquatting", "Typosquatting" }
+                option { value: "spam", "Spam" }
+                option { value: "other", "Other" }
+            }
+            input {
+                style: "flex: 1; margin-left: 4px; padding: 4px;",
+                placeholder: "what did you find?",
+                value: "{details}",
+                oninput: move |e| details.set(e.value()),
+            }
+            button {
+                style: "margin-left: 4px; padding: 4px 8px; cursor: pointer;",
+                disabled: details.read().is_empty(),
+                onclick: handle_report,
+                "Report"
+            }
+        }
+        if !status_message.read().is_empty() {
+            div {
+                style: "color: dimgray;",
+                "{status_message}"
+            }
+        }
+    }
+}
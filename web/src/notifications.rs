@@ -0,0 +1,172 @@
+use dioxus::prelude::*;
+use onyx_api::prelude::*;
+
+use crate::Route;
+
+/// Watch or stop watching a package, to be notified of the versions it publishes.
+#[component]
+pub fn WatchPackage(package_name: String) -> Element {
+    let auth_store = &crate::AUTH_STORE;
+
+    let mut watching = use_signal(|| false);
+    let mut status_message = use_signal(|| String::new());
+
+    let name = package_name.clone();
+    use_effect(move || {
+        let name = name.clone();
+        spawn(async move {
+            let token = auth_store.read().token.read().clone();
+            let Some(token) = token else {
+                return;
+            };
+            match auth_store.read().api.watches(&token).await {
+                Ok(watches) => watching.set(watches.contains(&name)),
+                Err(e) => status_message.set(format!("Failed to load watches: {e:#}")),
+            }
+        });
+    });
+
+    let toggle = move |_| {
+        let package_name = package_name.clone();
+        spawn(async move {
+            let token = auth_store.read().token.read().clone();
+            let Some(token) = token else {
+                return;
+            };
+            let api = auth_store.with(|v| v.api.clone());
+            let result = if *watching.read() {
+                api.unwatch_package(&token, &package_name).await
+            } else {
+                api.watch_package(&token, &package_name).await
+            };
+            match result {
+                Ok(()) => {
+                    let watched = !*watching.read();
+                    watching.set(watched);
+                    status_message.set(String::new());
+                }
+                Err(e) => status_message.set(format!("Failed to change watch: {e:#}")),
+            }
+        });
+    };
+
+    rsx! {
+        button {
+            style: "padding: 4px 8px; cursor: pointer;",
+            onclick: toggle,
+            if *watching.read() { "Unwatch" } else { "Watch for new versions" }
+        }
+        if !status_message.read().is_empty() {
+            div {
+                style: "color: dimgray;",
+                "{status_message}"
+            }
+        }
+    }
+}
+
+/// The versions published by watched packages, opened from the header.
+#[component]
+pub fn Notifications() -> Element {
+    let auth_store = &crate::AUTH_STORE;
+
+    let mut notifications: Signal<Option<NotificationsResponse>> = use_signal(|| None);
+    let mut open = use_signal(|| false);
+    let mut status_message = use_signal(|| String::new());
+
+    let load_notifications = move || {
+        spawn(async move {
+            let token = auth_store.read().token.read().clone();
+            let Some(token) = token else {
+                return;
+            };
+            match auth_store.read().api.notifications(&token).await {
+                Ok(n) => notifications.set(Some(n)),
+                Err(e) => status_message.set(format!("Failed to load notifications: {e:#}")),
+            }
+        });
+    };
+
+    use_effect(move || {
+        if auth_store.read().login.read().is_some() {
+            load_notifications();
+        }
+    });
+
+    let mark_read = move |_| {
+        let Some(seq) = notifications
+            .read()
+            .as_ref()
+            .and_then(|n| n.notifications.first())
+            .map(|n| n.seq)
+        else {
+            return;
+        };
+        spawn(async move {
+            let token = auth_store.read().token.read().clone();
+            let Some(token) = token else {
+                return;
+            };
+            match auth_store
+                .read()
+                .api
+                .mark_notifications_read(&token, seq)
+                .await
+            {
+                Ok(()) => load_notifications(),
+                Err(e) => status_message.set(format!("Failed to mark notifications read: {e:#}")),
+            }
+        });
+    };
+
+    let unread = notifications.read().as_ref().map(|n| n.unread).unwrap_or(0);
+    rsx! {
+        div {
+            style: "position: relative; margin-bottom: 8px;",
+            a {
+                style: "cursor: pointer;",
+                onclick: move |_| {
+                    let opened = !*open.read();
+                    open.set(opened);
+                },
+                if unread > 0 { "Notifications ({unread})" } else { "Notifications" }
+            }
+            if *open.read() {
+                div {
+                    style: "position: absolute; right: 0px; z-index: 1; width: 320px; max-height: 400px; overflow-y: auto; padding: 8px; background-color: white; border: 1px solid black; border-radius: 4px;",
+                    if let Some(response) = notifications.read().as_ref() {
+                        if response.notifications.is_empty() {
+                            div {
+                                style: "color: dimgray;",
+                                "Watch a package to be notified of its new versions."
+                            }
+                        }
+                        for notification in response.notifications.iter().cloned() {
+                            div {
+                                key: "{notification.seq}",
+                                style: if notification.seq > response.read_seq { "padding: 4px 0px; font-weight: bold;" } else { "padding: 4px 0px;" },
+                                Link {
+                                    to: Route::PackageView { package_name: notification.package_name.clone() },
+                                    "{notification.package_name}@{notification.version_name}"
+                                }
+                            }
+                        }
+                        if response.unread > 0 {
+                            button {
+                                style: "margin-top: 4px; padding: 4px 8px; cursor: pointer;",
+                                onclick: mark_read,
+                                "Mark all read"
+                            }
+                        }
+                    }
+                    if !status_message.read().is_empty() {
+                        div {
+                            style: "color: dimgray;",
+                            "{status_message}"
+                        }
+                    }
+                }
+            }
+        }
+    }
+}
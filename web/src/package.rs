@@ -1,12 +1,59 @@
 use std::collections::BTreeMap;
+use std::path::Path;
 use std::path::PathBuf;
 
 use dioxus::prelude::*;
 use onyx_api::prelude::*;
 
 use nargo_parse::*;
+use nrpm_markdown::HIGHLIGHT_CSS;
+use nrpm_markdown::LinkBase;
+use nrpm_markdown::LinkTarget;
+use nrpm_markdown::render_markdown;
 
+use super::artifacts::Artifacts;
+use super::build::BuildBadge;
 use super::components::Header;
+use super::dependency_tree::DependencyTree;
+use super::docs::ApiReference;
+use super::moderation::ReportPackage;
+use super::notifications::WatchPackage;
+use super::propose_token::get_query_param;
+use super::release_notes::ReleaseNotes;
+use super::service_worker;
+use super::transfers::TransferPackage;
+use crate::Route;
+
+/// Link to a commit, assuming the repository uses the GitHub url layout.
+fn commit_url(repository: &str, commit: &str) -> String {
+    let repository = repository.trim_end_matches('/').trim_end_matches(".git");
+    format!("{repository}/commit/{commit}")
+}
+
+/// A snippet with a button copying it to the clipboard.
+#[component]
+fn CopySnippet(text: String) -> Element {
+    let mut copied = use_signal(|| false);
+    let copy_text = text.clone();
+    rsx! {
+        div {
+            style: "display: flex; flex-direction: row; align-items: flex-start; width: 100%;",
+            pre {
+                style: "flex-grow: 1; margin: 0px; padding: 8px; font-family: monospace; border: 1px solid gray; border-radius: 2px; white-space: pre-wrap; word-break: break-all;",
+                "{text}"
+            }
+            button {
+                style: "margin-left: 4px;",
+                onclick: move |_| {
+                    let text = serde_json::to_string(&copy_text).unwrap_or_default();
+                    document::eval(&format!("navigator.clipboard.writeText({text})"));
+                    copied.set(true);
+                },
+                if *copied.read() { "Copied" } else { "Copy" }
+            }
+        }
+    }
+}
 
 #[component]
 pub fn PackageView(package_name: String) -> Element {
@@ -16,7 +63,18 @@ pub fn PackageView(package_name: String) -> Element {
     let mut package_config: Signal<Option<(NargoConfig, BTreeMap<PathBuf, Vec<u8>>)>> =
         use_signal(|| None);
     let mut package_hash_verified = use_signal(|| false);
-    let mut active_file = use_signal(|| PathBuf::from("README.md"));
+    // rewritten README links open files with `?file=`
+    let mut active_file = use_signal(|| {
+        let file = get_query_param("file");
+        if file.is_empty() {
+            PathBuf::from("README.md")
+        } else {
+            PathBuf::from(file)
+        }
+    });
+    let mut show_docs = use_signal(|| false);
+    let mut graph: Signal<Option<PackageGraphResponse>> = use_signal(|| None);
+    let mut downloads: Signal<Option<u64>> = use_signal(|| None);
 
     // On mount fetch the package metadata, load the package tarball, decompress and analyze
     use_effect(move || {
@@ -32,17 +90,26 @@ pub fn PackageView(package_name: String) -> Element {
                     p
                 }
                 Err(e) => {
-                    status.set(format!("Error: {}", e));
+                    status.set(format!("Error: {:#}", e));
                     is_loading.set(false);
                     return;
                 }
             };
 
-            // download the package tarball and extract to get the metadata
-            let bytes = match api.download_tarball(&version.id).await {
+            // dependency sections are optional, don't fail the page without them
+            if let Ok(g) = api.package_graph(&package_name).await {
+                graph.set(Some(g));
+            }
+            if let Ok(stats) = api.package_stats(&package_name).await {
+                downloads.set(Some(stats.downloads));
+            }
+
+            // download the package tarball and extract to get the metadata, the service worker
+            // keeps it so revisits don't download it again
+            let bytes = match service_worker::download_tarball(&api, &version.id).await {
                 Ok(bytes) => bytes,
                 Err(e) => {
-                    status.set(format!("Error: failed to download tarball bytes! {}", e));
+                    status.set(format!("Error: failed to download tarball bytes! {:#}", e));
                     is_loading.set(false);
                     return;
                 }
@@ -105,6 +172,7 @@ pub fn PackageView(package_name: String) -> Element {
     let (package, version) = package_inner.as_ref().unwrap();
     let (package_config, package_contents) = package_config_inner.as_ref().unwrap();
     let active_file_path = active_file.read().clone();
+    let download_url = OnyxApi::default().package_download_url(&package.name, &version.name);
     let file_content = package_contents
         .get(&active_file_path)
         .map(|v| {
@@ -112,16 +180,49 @@ pub fn PackageView(package_name: String) -> Element {
         })
         .unwrap_or("No README.md found for this package!\n\nIf you're the author you should consider adding one 😊".into());
 
+    let is_owner = crate::AUTH_STORE
+        .read()
+        .login
+        .read()
+        .as_ref()
+        .is_some_and(|login| login.user.id == package.author_id);
+    let is_logged_in = crate::AUTH_STORE.read().login.read().is_some();
+    // the package urls nrpm installs from are on the host serving the web app
+    let registry_url = web_sys::window()
+        .and_then(|window| window.location().origin().ok())
+        .unwrap_or_default();
+    let dependency_section = nargo_parse::Dependency::registry(
+        package.name.clone(),
+        &registry_url,
+        version.name.clone(),
+    )
+    .to_dependencies_section()
+    .ok();
+
     let file_content_rendered = if let Some(ext) = active_file_path.extension()
         && ext == "md"
     {
-        Some(ammonia::clean(&markdown::to_html(&file_content)))
+        let target = match package_config.package.repository.as_deref() {
+            Some(repository) => LinkTarget::Repository(repository),
+            None => LinkTarget::FileBrowser {
+                package_name: &package.name,
+                files: package_contents,
+            },
+        };
+        Some(render_markdown(
+            &file_content,
+            Some(LinkBase {
+                dir: active_file_path.parent().unwrap_or(Path::new("")),
+                target,
+            }),
+        ))
     } else {
         None
     };
 
     rsx! {
         Header { show_auth: true },
+        style { "{HIGHLIGHT_CSS}" }
         div {
             style: "padding: 40px; font-family: Arial, sans-serif;",
 
@@ -143,13 +244,33 @@ pub fn PackageView(package_name: String) -> Element {
                         style: "margin: 0px; margin-bottom: 8px;",
                         "{package.name}@{version.name}"
                     }
+                    if let Some(deprecated) = &package.metadata.deprecated {
+                        div {
+                            style: "margin-bottom: 8px; color: darkred;",
+                            "⚠️ Deprecated: {deprecated}"
+                        }
+                    }
+                    div {
+                        style: "padding-left: 8px; cursor: pointer;",
+                        onclick: move |_| {
+                            show_docs.set(true);
+                        },
+                        if *show_docs.read() {
+                            span {
+                                style: "color: purple; font-weight: bold;",
+                                "> "
+                            },
+                        },
+                        "API reference"
+                    }
                     for (path, data) in package_contents.iter().map(|(k, v)| (k.clone(), v)) {
                         div {
                             style: "padding-left: 8px; cursor: pointer;",
                             onclick: move |_| {
                                 active_file.set(path.clone());
+                                show_docs.set(false);
                             },
-                            if active_file_path == path {
+                            if !*show_docs.read() && active_file_path == path {
                                 span {
                                     style: "color: purple; font-weight: bold;",
                                     "> "
@@ -168,6 +289,12 @@ pub fn PackageView(package_name: String) -> Element {
                     div {
                         "published {time_ago(version.created_at)}"
                     }
+                    if let Some(downloads) = *downloads.read() {
+                        div {
+                            "{downloads} download"
+                            if downloads != 1 { "s" }
+                        }
+                    }
                     div {
                         "blake3: {version.id.to_string().chars().take(13).collect::<String>()}..."
                     },
@@ -180,22 +307,104 @@ pub fn PackageView(package_name: String) -> Element {
                             "❌ hash mismatch!"
                         }
                     }
+                    a {
+                        href: "{download_url}",
+                        "Download"
+                    }
+                    Link {
+                        to: Route::CompareView { package_name: package.name.clone() },
+                        "Compare versions"
+                    }
                     div {
                         style: "width: 100%; margin: 4px 0px; border-bottom: 1px solid black;"
                     },
+                    if let (Some(repository), Some(commit)) = (&version.source_repository, &version.source_commit) {
+                        div {
+                            h4 {
+                                style: "margin: 0px",
+                                "Provenance"
+                            }
+                        }
+                        div {
+                            style: "margin-left: 8px; color: dimgray;",
+                            "Built from "
+                            a {
+                                href: "{repository}",
+                                "{repository}"
+                            }
+                            " at commit "
+                            a {
+                                style: "font-family: monospace;",
+                                href: commit_url(repository, commit),
+                                "{commit.chars().take(12).collect::<String>()}"
+                            }
+                        }
+                        div {
+                            style: "width: 100%; margin: 4px 0px; border-bottom: 1px solid black;"
+                        },
+                    }
                     div {
                         h4 {
                             style: "margin: 0px",
                             "Install"
                         }
                     },
-                    div {
-                        style: "padding: 8px; font-family: monospace; border: 1px solid gray; border-radius: 2px;",
-                        "nrpm install {package.name}"
+                    CopySnippet { text: format!("nrpm install {}", package.name) }
+                    if let Some(dependency) = dependency_section.as_ref() {
+                        div {
+                            style: "margin: 4px 0px; color: dimgray;",
+                            "or add to Nargo.toml"
+                        }
+                        CopySnippet { text: dependency.clone() }
                     }
                     div {
                         style: "width: 100%; margin: 4px 0px; border-bottom: 1px solid black;"
                     },
+                    if !package.ownership_history.is_empty() {
+                        div {
+                            h4 {
+                                style: "margin: 0px",
+                                "Ownership history"
+                            }
+                        }
+                        for transfer in package.ownership_history.iter().rev() {
+                            div {
+                                key: "{transfer.transferred_at}",
+                                style: "margin-left: 8px; color: dimgray;",
+                                "{transfer.from_username} → {transfer.to_username}, {time_ago(transfer.transferred_at)}"
+                                if let Some(admin_username) = &transfer.admin_username {
+                                    " (by admin {admin_username})"
+                                }
+                            }
+                        }
+                        div {
+                            style: "width: 100%; margin: 4px 0px; border-bottom: 1px solid black;"
+                        },
+                    }
+                    if is_owner {
+                        TransferPackage { package_name: package.name.clone() }
+                        div {
+                            style: "width: 100%; margin: 4px 0px; border-bottom: 1px solid black;"
+                        },
+                    }
+                    if is_logged_in {
+                        div {
+                            style: "margin: 4px 0px;",
+                            WatchPackage { package_name: package.name.clone() }
+                        }
+                        div {
+                            style: "width: 100%; margin: 4px 0px; border-bottom: 1px solid black;"
+                        },
+                    }
+                    if is_logged_in && !is_owner {
+                        ReportPackage {
+                            package_name: package.name.clone(),
+                            version_name: version.name.clone(),
+                        }
+                        div {
+                            style: "width: 100%; margin: 4px 0px; border-bottom: 1px solid black;"
+                        },
+                    }
                     if let Some(authors) = &package_config.package.authors && !authors.is_empty(){
                         div {
                             h4 {
@@ -226,7 +435,7 @@ pub fn PackageView(package_name: String) -> Element {
                             style: "width: 100%; margin: 4px 0px; border-bottom: 1px solid black;"
                         },
                     }
-                    if let Some(description) = package_config.package.description.as_ref() {
+                    if let Some(description) = package.metadata.description.as_ref().or(package_config.package.description.as_ref()) {
                         div {
                             h4 {
                                 style: "margin: 0px",
@@ -241,7 +450,7 @@ pub fn PackageView(package_name: String) -> Element {
                             style: "width: 100%; margin: 4px 0px; border-bottom: 1px solid black;"
                         },
                     }
-                    if let Some(repository) = package_config.package.repository.as_ref() {
+                    if let Some(repository) = package.metadata.repository.as_ref().or(package_config.package.repository.as_ref()) {
                         div {
                             h4 {
                                 style: "margin: 0px",
@@ -257,7 +466,22 @@ pub fn PackageView(package_name: String) -> Element {
                             style: "width: 100%; margin: 4px 0px; border-bottom: 1px solid black;"
                         },
                     }
-                    if let Some(keywords) = package_config.package.keywords.as_ref() {
+                    if let Some(license) = package_config.package.license.as_ref().or(package.license.as_ref()) {
+                        div {
+                            h4 {
+                                style: "margin: 0px",
+                                "License"
+                            }
+                        }
+                        div {
+                            style: "margin-left: 8px; color: dimgray;",
+                            "{license}"
+                        }
+                        div {
+                            style: "width: 100%; margin: 4px 0px; border-bottom: 1px solid black;"
+                        },
+                    }
+                    if let Some(keywords) = package.metadata.keywords.as_ref().or(package_config.package.keywords.as_ref()) {
                         div {
                             h4 {
                                 style: "margin: 0px; margin-bottom: 4px;",
@@ -277,6 +501,71 @@ pub fn PackageView(package_name: String) -> Element {
                             style: "width: 100%; margin: 4px 0px; border-bottom: 1px solid black;"
                         },
                     }
+                    ReleaseNotes { version_id: version.id.clone() }
+                    Artifacts { version_id: version.id.clone() }
+                    BuildBadge { version_id: version.id.clone() }
+                    DependencyTree {
+                        package_name: package.name.clone(),
+                        version_name: version.name.clone(),
+                    }
+                    if let Some(graph) = graph.read().as_ref() {
+                        if !graph.dependencies.is_empty() {
+                            div {
+                                h4 {
+                                    style: "margin: 0px",
+                                    "Dependencies"
+                                }
+                            }
+                            for dependency in graph.dependencies.iter().cloned() {
+                                div {
+                                    key: "{dependency.package_name}",
+                                    style: "margin-left: 8px;",
+                                    Link {
+                                        to: Route::PackageView { package_name: dependency.package_name.clone() },
+                                        "{dependency.package_name}"
+                                    }
+                                    span {
+                                        style: "color: dimgray;",
+                                        " {dependency.version_name}"
+                                    }
+                                }
+                            }
+                            div {
+                                style: "width: 100%; margin: 4px 0px; border-bottom: 1px solid black;"
+                            },
+                        }
+                        div {
+                            h4 {
+                                style: "margin: 0px",
+                                "Dependents ({graph.dependent_count})"
+                            }
+                        }
+                        if graph.dependents.is_empty() {
+                            div {
+                                style: "margin-left: 8px; color: dimgray;",
+                                "No packages depend on this one."
+                            }
+                        }
+                        for dependent in graph.dependents.iter().cloned() {
+                            div {
+                                key: "{dependent}",
+                                style: "margin-left: 8px;",
+                                Link {
+                                    to: Route::PackageView { package_name: dependent.clone() },
+                                    "{dependent}"
+                                }
+                            }
+                        }
+                        if graph.dependent_count > graph.dependents.len() {
+                            div {
+                                style: "margin-left: 8px; color: dimgray;",
+                                "and {graph.dependent_count - graph.dependents.len()} more"
+                            }
+                        }
+                        div {
+                            style: "width: 100%; margin: 4px 0px; border-bottom: 1px solid black;"
+                        },
+                    }
                 }
             }
 
@@ -297,7 +586,9 @@ pub fn PackageView(package_name: String) -> Element {
             }
             div {
                 style: "background: #f5f5f5; padding: 4px; border-radius: 2px; border: 1px solid gray;",
-                if let Some(content) = file_content_rendered {
+                if *show_docs.read() {
+                    ApiReference { version_id: version.id.clone() }
+                } else if let Some(content) = file_content_rendered {
                     div {
                         dangerous_inner_html: content
                     }
@@ -1,11 +1,13 @@
 use dioxus::prelude::*;
 use web_sys::UrlSearchParams;
 
+use onyx_api::prelude::*;
+
 use super::components::Auth;
 use crate::Route;
 use crate::components::Header;
 
-fn get_query_param(key: &str) -> String {
+pub fn get_query_param(key: &str) -> String {
     let window = web_sys::window().unwrap();
     let search = window.location().search().unwrap_or_default();
     let params = UrlSearchParams::new_with_str(&search).unwrap();
@@ -25,6 +27,14 @@ pub fn ProposeTokenView() -> Element {
     let handle_propose_token = move |_| {
         spawn(async move {
             let proposed_token = get_query_param("token");
+            // older clients don't propose a refresh token
+            let proposed_refresh_token =
+                Some(get_query_param("refresh_token")).filter(|t| !t.is_empty());
+            // older clients ask for a full scoped token
+            let scope = match get_query_param("scope").as_str() {
+                "publish" => TokenScope::Publish,
+                _ => TokenScope::Full,
+            };
             let self_token = {
                 let auth_store = auth_store.read();
                 auth_store.token.read().clone()
@@ -37,7 +47,12 @@ pub fn ProposeTokenView() -> Element {
             match auth_store
                 .read()
                 .api
-                .propose_token(proposed_token, self_token.unwrap())
+                .propose_token(
+                    proposed_token,
+                    proposed_refresh_token,
+                    scope,
+                    self_token.unwrap(),
+                )
                 .await
             {
                 Ok(()) => {
@@ -0,0 +1,43 @@
+use dioxus::prelude::*;
+use onyx_api::prelude::*;
+
+use nrpm_markdown::render_markdown;
+
+/// The release notes published with a version, if any.
+#[component]
+pub fn ReleaseNotes(version_id: HashId) -> Element {
+    let mut release_notes: Signal<Option<String>> = use_signal(|| None);
+
+    use_effect(use_reactive!(|version_id| {
+        release_notes.set(None);
+        spawn(async move {
+            // the section is optional, leave it out if the notes can't be loaded
+            let notes = OnyxApi::default()
+                .version_release_notes(&version_id)
+                .await
+                .ok()
+                .flatten();
+            release_notes.set(notes);
+        });
+    }));
+
+    let release_notes = release_notes.read();
+    let Some(release_notes) = release_notes.as_ref() else {
+        return rsx! {};
+    };
+    rsx! {
+        div {
+            h4 {
+                style: "margin: 0px",
+                "Release notes"
+            }
+        }
+        div {
+            style: "margin-left: 8px;",
+            dangerous_inner_html: render_markdown(release_notes, None)
+        }
+        div {
+            style: "width: 100%; margin: 4px 0px; border-bottom: 1px solid black;"
+        },
+    }
+}
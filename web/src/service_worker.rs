@@ -0,0 +1,48 @@
+use anyhow::Result;
+use onyx_api::prelude::*;
+use wasm_bindgen::JsValue;
+use wasm_bindgen_futures::JsFuture;
+
+/// The cache the service worker keeps tarballs in, `TARBALL_CACHE` in `web/sw.js`.
+const TARBALL_CACHE: &str = "nrpm-tarballs-v1";
+
+/// Register the service worker onyx serves at `/sw.js`. Browsers without service workers,
+/// and pages not served by onyx, work without it.
+pub fn register() {
+    let Some(window) = web_sys::window() else {
+        return;
+    };
+    let navigator = window.navigator();
+    if !js_sys::Reflect::has(&navigator, &JsValue::from_str("serviceWorker")).unwrap_or(false) {
+        return;
+    }
+    let registration = navigator.service_worker().register("/sw.js");
+    // without the worker nothing is cached, the app works the same
+    wasm_bindgen_futures::spawn_local(async move {
+        let _ = JsFuture::from(registration).await;
+    });
+}
+
+/// Drop the tarball at `url` from the service worker's cache.
+async fn evict(url: &str) {
+    let Some(caches) = web_sys::window().and_then(|window| window.caches().ok()) else {
+        return;
+    };
+    let Ok(cache) = JsFuture::from(caches.open(TARBALL_CACHE)).await else {
+        return;
+    };
+    let _ = JsFuture::from(web_sys::Cache::from(cache).delete_with_str(url)).await;
+}
+
+/// Download and verify the tarball of a version, from the service worker's cache if it's
+/// there. A cached copy that doesn't match its hash is dropped and downloaded again.
+pub async fn download_tarball(api: &OnyxApi, version_id: &HashId) -> Result<Vec<u8>> {
+    match api.download_tarball(version_id).await {
+        Ok(bytes) => Ok(bytes),
+        // the retry fails the same way if it wasn't the cached copy
+        Err(_) => {
+            evict(&api.version_download_url(version_id)).await;
+            api.download_tarball(version_id).await
+        }
+    }
+}
@@ -0,0 +1,166 @@
+use dioxus::prelude::*;
+use onyx_api::prelude::*;
+
+use super::components::Auth;
+use super::components::Header;
+
+/// Format a unix timestamp relative to now, e.g. "12 minutes ago" or "in 3 days".
+fn relative_time(timestamp: u64) -> String {
+    let now = (js_sys::Date::now() / 1000.0) as i64;
+    let diff = timestamp as i64 - now;
+    let (count, unit) = match diff.abs() {
+        s if s < 60 => (0, ""),
+        s if s < 3600 => (s / 60, "minute"),
+        s if s < 24 * 3600 => (s / 3600, "hour"),
+        s => (s / (24 * 3600), "day"),
+    };
+    let amount = match count {
+        0 => "less than a minute".to_string(),
+        1 => format!("1 {unit}"),
+        _ => format!("{count} {unit}s"),
+    };
+    if diff < 0 {
+        format!("{amount} ago")
+    } else {
+        format!("in {amount}")
+    }
+}
+
+/// Format a number of bytes with a binary unit, e.g. "1.5 MiB".
+fn format_bytes(bytes: u64) -> String {
+    let units = ["KiB", "MiB", "GiB", "TiB"];
+    if bytes < 1024 {
+        return format!("{bytes} B");
+    }
+    let mut size = bytes as f64 / 1024.0;
+    let mut unit = 0;
+    while size >= 1024.0 && unit < units.len() - 1 {
+        size /= 1024.0;
+        unit += 1;
+    }
+    format!("{size:.1} {}", units[unit])
+}
+
+fn source_label(source: SessionSource) -> &'static str {
+    match source {
+        SessionSource::Login => "Login",
+        SessionSource::Signup => "Signup",
+        SessionSource::ProposedToken => "Application token",
+    }
+}
+
+#[component]
+pub fn SessionsView() -> Element {
+    let auth_store = &crate::AUTH_STORE;
+
+    let mut sessions: Signal<Vec<SessionInfo>> = use_signal(Vec::new);
+    let mut usage: Signal<Option<StorageUsage>> = use_signal(|| None);
+    let mut status_message = use_signal(|| String::new());
+
+    let load_sessions = move || {
+        spawn(async move {
+            let token = auth_store.read().token.read().clone();
+            let Some(token) = token else {
+                return;
+            };
+            match auth_store.read().api.sessions(&token).await {
+                Ok(s) => sessions.set(s),
+                Err(e) => status_message.set(format!("Failed to load sessions: {e:#}")),
+            }
+            // usage is informational, leave it out if it can't be loaded
+            usage.set(auth_store.read().api.storage_usage(&token).await.ok());
+        });
+    };
+
+    use_effect(move || {
+        if auth_store.read().login.read().is_some() {
+            load_sessions();
+        }
+    });
+
+    let revoke = move |token_prefix: String| {
+        spawn(async move {
+            let token = auth_store.read().token.read().clone();
+            let Some(token) = token else {
+                return;
+            };
+            match auth_store
+                .read()
+                .api
+                .revoke_session(&token, &token_prefix)
+                .await
+            {
+                Ok(()) => load_sessions(),
+                Err(e) => status_message.set(format!("Failed to revoke session: {e:#}")),
+            }
+        });
+    };
+
+    rsx! {
+        Header { show_auth: true },
+        if auth_store.read().login.read().is_some() {
+            div {
+                style: "padding: 20px;",
+                if let Some(usage) = usage.read().as_ref() {
+                    p {
+                        "Storage used: {format_bytes(usage.used_bytes)}"
+                        if let Some(quota_bytes) = usage.quota_bytes {
+                            " of {format_bytes(quota_bytes)}"
+                        }
+                    }
+                }
+                h2 { "Sessions" }
+                p {
+                    style: "color: #666;",
+                    "Tokens that can act on behalf of your account. Revoke any you don't recognize."
+                }
+                for session in sessions.read().iter().cloned() {
+                    div {
+                        key: "{session.token_prefix}",
+                        style: "display: flex; flex-direction: row; justify-content: space-between; align-items: center; padding: 8px; border-bottom: 1px solid #ddd;",
+                        div {
+                            div {
+                                style: "font-family: monospace; font-weight: bold;",
+                                "{session.token_prefix}…"
+                                if session.current {
+                                    span {
+                                        style: "margin-left: 8px; font-family: sans-serif; font-weight: normal; color: #28a745;",
+                                        "(this browser)"
+                                    }
+                                }
+                            }
+                            div {
+                                style: "color: #666; font-size: 14px;",
+                                "{source_label(session.source)}"
+                                if session.scope == TokenScope::Publish {
+                                    " (publish only)"
+                                }
+                                " · created {relative_time(session.created_at)} · expires {relative_time(session.expires_at)}"
+                                if let Some(refresh_expires_at) = session.refresh_expires_at {
+                                    " · renews until {relative_time(refresh_expires_at)}"
+                                }
+                            }
+                        }
+                        if !session.current {
+                            button {
+                                style: "padding: 8px; background-color: #f87171; color: white; border: none; border-radius: 4px; cursor: pointer;",
+                                onclick: move |_| revoke(session.token_prefix.clone()),
+                                "Revoke"
+                            }
+                        }
+                    }
+                }
+                if !status_message.read().is_empty() {
+                    div {
+                        style: "margin-top: 10px; padding: 10px; border-radius: 4px; text-align: center; font-weight: bold; background-color: #f8d7da; color: #721c24; border: 1px solid #f5c6cb;",
+                        "{status_message}"
+                    }
+                }
+            }
+        } else {
+            Auth {
+                on_auth: move |_| {}
+            }
+        }
+    }
+}
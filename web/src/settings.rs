@@ -0,0 +1,157 @@
+use dioxus::prelude::*;
+use onyx_api::prelude::*;
+
+use super::components::Auth;
+use super::components::Header;
+use crate::Route;
+
+const INPUT_STYLE: &str = "width: 100%; padding: 10px; margin-bottom: 8px; border: 1px solid #ddd; border-radius: 4px; font-size: 16px;";
+
+/// `value`, or `None` if it's empty.
+fn non_empty(value: String) -> Option<String> {
+    if value.is_empty() { None } else { Some(value) }
+}
+
+#[component]
+pub fn SettingsView() -> Element {
+    let navigator = use_navigator();
+    let auth_store = &crate::AUTH_STORE;
+
+    let mut password = use_signal(|| String::new());
+    let mut new_username = use_signal(|| String::new());
+    let mut new_password = use_signal(|| String::new());
+    let mut delete_password = use_signal(|| String::new());
+    let mut status_message = use_signal(|| String::new());
+    let mut is_error = use_signal(|| false);
+
+    let save = move |_| {
+        spawn(async move {
+            let token = auth_store.read().token.read().clone();
+            let Some(token) = token else {
+                return;
+            };
+            let request = UpdateUserRequest {
+                password: password.read().clone(),
+                new_username: non_empty(new_username.read().clone()),
+                new_password: non_empty(new_password.read().clone()),
+            };
+            let api = auth_store.read().api.clone();
+            match api.update_user(&token, request).await {
+                Ok(user) => {
+                    let mut login = auth_store.read().login;
+                    login.with_mut(|login| {
+                        if let Some(login) = login {
+                            login.user = user;
+                        }
+                    });
+                    password.set(String::new());
+                    new_username.set(String::new());
+                    new_password.set(String::new());
+                    is_error.set(false);
+                    status_message.set("Account updated".to_string());
+                }
+                Err(e) => {
+                    is_error.set(true);
+                    status_message.set(format!("Failed to update account: {e:#}"));
+                }
+            }
+        });
+    };
+
+    let delete_account = move |_| {
+        spawn(async move {
+            let token = auth_store.read().token.read().clone();
+            let Some(token) = token else {
+                return;
+            };
+            let request = DeleteUserRequest {
+                password: delete_password.read().clone(),
+            };
+            let api = auth_store.read().api.clone();
+            match api.delete_user(&token, request).await {
+                Ok(()) => {
+                    auth_store.write().clear_login();
+                    navigator.push(Route::HomeView);
+                }
+                Err(e) => {
+                    is_error.set(true);
+                    status_message.set(format!("Failed to delete account: {e:#}"));
+                }
+            }
+        });
+    };
+
+    rsx! {
+        Header { show_auth: true },
+        if auth_store.read().login.read().is_some() {
+            div {
+                style: "padding: 20px;",
+                h2 { "Account" }
+                p {
+                    style: "color: #666;",
+                    "Leave a field empty to keep it. Changing your password signs out your other sessions."
+                }
+                input {
+                    r#type: "text",
+                    value: "{new_username}",
+                    oninput: move |e| new_username.set(e.value()),
+                    style: INPUT_STYLE,
+                    placeholder: "New username"
+                }
+                input {
+                    r#type: "password",
+                    value: "{new_password}",
+                    oninput: move |e| new_password.set(e.value()),
+                    style: INPUT_STYLE,
+                    placeholder: "New password"
+                }
+                input {
+                    r#type: "password",
+                    value: "{password}",
+                    oninput: move |e| password.set(e.value()),
+                    style: INPUT_STYLE,
+                    placeholder: "Current password"
+                }
+                button {
+                    style: "padding: 8px; background-color: #007bff; color: white; border: none; border-radius: 4px; cursor: pointer;",
+                    onclick: save,
+                    "Save"
+                }
+                h2 { "Delete account" }
+                p {
+                    style: "color: #666;",
+                    "Transfer the packages you own to another user first. Deleting your account can't be undone."
+                }
+                input {
+                    r#type: "password",
+                    value: "{delete_password}",
+                    oninput: move |e| delete_password.set(e.value()),
+                    style: INPUT_STYLE,
+                    placeholder: "Current password"
+                }
+                button {
+                    style: "padding: 8px; background-color: #f87171; color: white; border: none; border-radius: 4px; cursor: pointer;",
+                    onclick: delete_account,
+                    "Delete account"
+                }
+                if !status_message.read().is_empty() {
+                    if *is_error.read() {
+                        div {
+                            style: "margin-top: 10px; padding: 10px; border-radius: 4px; text-align: center; font-weight: bold; background-color: #f8d7da; color: #721c24; border: 1px solid #f5c6cb;",
+                            "{status_message}"
+                        }
+                    } else {
+                        div {
+                            style: "margin-top: 10px; padding: 10px; border-radius: 4px; text-align: center; font-weight: bold; background-color: #d4edda; color: #155724; border: 1px solid #c3e6cb;",
+                            "{status_message}"
+                        }
+                    }
+                }
+            }
+        } else {
+            Auth {
+                on_auth: move |_| {}
+            }
+        }
+    }
+}
@@ -1,26 +1,46 @@
 use dioxus::prelude::*;
 use gloo_storage::LocalStorage;
+use gloo_storage::SessionStorage;
 use gloo_storage::Storage;
 
 use onyx_api::prelude::*;
 
 pub static AUTH_STORE: GlobalSignal<AuthStore> = Signal::global(AuthStore::new);
 
-const AUTH_TOKEN_LOCALSTORAGE: &'static str = "auth_token";
+const AUTH_TOKEN_STORAGE: &str = "auth_token";
+const REFRESH_TOKEN_STORAGE: &str = "refresh_token";
+// refresh the access token this many seconds before it expires
+const REFRESH_MARGIN: u64 = 60;
+
+fn now() -> u64 {
+    (js_sys::Date::now() / 1000.0) as u64
+}
+
+/// Read a token from local storage, or from session storage if the user wasn't remembered.
+fn load(key: &str) -> Option<String> {
+    LocalStorage::get(key)
+        .ok()
+        .or_else(|| SessionStorage::get(key).ok())
+}
 
 #[derive(Clone, Debug)]
 pub struct AuthStore {
     pub login: Signal<Option<LoginResponse>>,
     pub token: Signal<Option<String>>,
+    /// Whether the user asked to be remembered. Tokens are kept in local storage if so, and
+    /// otherwise in session storage, which the browser clears when it closes.
+    pub remember: Signal<bool>,
     pub api: OnyxApi,
 }
 
 impl AuthStore {
     pub fn new() -> Self {
-        let token = LocalStorage::get(AUTH_TOKEN_LOCALSTORAGE).ok();
+        let token = load(AUTH_TOKEN_STORAGE);
+        let remember = LocalStorage::get::<String>(AUTH_TOKEN_STORAGE).is_ok();
         let mut out = Self {
             login: Signal::new(None),
             token: Signal::new(token.clone()),
+            remember: Signal::new(remember),
             api: OnyxApi::default(),
         };
         if token.is_some() {
@@ -36,13 +56,57 @@ impl AuthStore {
     }
 
     pub fn set_login(&mut self, login: LoginResponse) {
-        LocalStorage::set(AUTH_TOKEN_LOCALSTORAGE, login.token.clone()).unwrap();
+        self.save(AUTH_TOKEN_STORAGE, &login.token);
+        // responses to token checks don't include a refresh token, keep the saved one
+        if let Some(refresh_token) = &login.refresh_token {
+            self.save(REFRESH_TOKEN_STORAGE, refresh_token);
+        }
         self.token.with_mut(|v| *v = Some(login.token.clone()));
+        self.schedule_refresh(login.token.clone(), login.expires_at);
         self.login.with_mut(|v| *v = Some(login));
     }
 
+    fn save(&self, key: &str, value: &str) {
+        if *self.remember.peek() {
+            LocalStorage::set(key, value).unwrap();
+        } else {
+            SessionStorage::set(key, value).unwrap();
+        }
+    }
+
+    /// Refresh the session shortly before `token` expires. Does nothing if the user logs out
+    /// or logs in again in the meantime.
+    fn schedule_refresh(&self, token: String, expires_at: u64) {
+        let mut self_clone = self.clone();
+        spawn(async move {
+            let delay = expires_at
+                .saturating_sub(REFRESH_MARGIN)
+                .saturating_sub(now());
+            gloo_timers::future::TimeoutFuture::new((delay * 1000).min(u32::MAX as u64) as u32)
+                .await;
+            if self_clone.token.with(|v| v.as_ref() != Some(&token)) {
+                return;
+            }
+            if let Err(e) = self_clone.refresh().await {
+                println!("refresh error: {:?}", e);
+            }
+        });
+    }
+
+    /// Exchange the saved refresh token for a new session.
+    async fn refresh(&mut self) -> anyhow::Result<()> {
+        let refresh_token =
+            load(REFRESH_TOKEN_STORAGE).ok_or(anyhow::anyhow!("No saved refresh token"))?;
+        let login = self.api.refresh(refresh_token).await?;
+        self.set_login(login);
+        Ok(())
+    }
+
     pub fn clear_login(&mut self) {
-        LocalStorage::delete(AUTH_TOKEN_LOCALSTORAGE);
+        for key in [AUTH_TOKEN_STORAGE, REFRESH_TOKEN_STORAGE] {
+            LocalStorage::delete(key);
+            SessionStorage::delete(key);
+        }
         self.token.with_mut(|v| *v = None);
         self.login.with_mut(|v| *v = None);
     }
@@ -65,7 +129,10 @@ impl AuthStore {
                 Ok(login) => self_clone.set_login(login),
                 Err(e) => {
                     println!("auth error: {:?}", e);
-                    self_clone.clear_login();
+                    // the access token may have expired while the page was closed
+                    if self_clone.refresh().await.is_err() {
+                        self_clone.clear_login();
+                    }
                 }
             };
         });
@@ -0,0 +1,203 @@
+use dioxus::prelude::*;
+use onyx_api::prelude::*;
+
+use super::components::Auth;
+use super::components::Header;
+use crate::Route;
+
+#[component]
+pub fn TransfersView() -> Element {
+    let auth_store = &crate::AUTH_STORE;
+
+    let mut transfers: Signal<TransfersResponse> = use_signal(TransfersResponse::default);
+    let mut status_message = use_signal(|| String::new());
+
+    let load_transfers = move || {
+        spawn(async move {
+            let token = auth_store.read().token.read().clone();
+            let Some(token) = token else {
+                return;
+            };
+            match auth_store.read().api.transfers(&token).await {
+                Ok(t) => transfers.set(t),
+                Err(e) => status_message.set(format!("Failed to load transfers: {e:#}")),
+            }
+        });
+    };
+
+    use_effect(move || {
+        if auth_store.read().login.read().is_some() {
+            load_transfers();
+        }
+    });
+
+    let accept = move |package_name: String| {
+        spawn(async move {
+            let token = auth_store.read().token.read().clone();
+            let Some(token) = token else {
+                return;
+            };
+            match auth_store
+                .read()
+                .api
+                .accept_transfer(&token, &package_name)
+                .await
+            {
+                Ok(_) => load_transfers(),
+                Err(e) => status_message.set(format!("Failed to accept transfer: {e:#}")),
+            }
+        });
+    };
+
+    let cancel = move |package_name: String| {
+        spawn(async move {
+            let token = auth_store.read().token.read().clone();
+            let Some(token) = token else {
+                return;
+            };
+            match auth_store
+                .read()
+                .api
+                .cancel_transfer(&token, &package_name)
+                .await
+            {
+                Ok(()) => load_transfers(),
+                Err(e) => status_message.set(format!("Failed to cancel transfer: {e:#}")),
+            }
+        });
+    };
+
+    rsx! {
+        Header { show_auth: true },
+        if auth_store.read().login.read().is_some() {
+            div {
+                style: "padding: 20px;",
+                h2 { "Incoming transfers" }
+                if transfers.read().incoming.is_empty() {
+                    p { style: "color: #666;", "No packages have been offered to you." }
+                }
+                for transfer in transfers.read().incoming.iter().cloned() {
+                    div {
+                        key: "{transfer.package_id}",
+                        style: "display: flex; flex-direction: row; justify-content: space-between; align-items: center; padding: 8px; border-bottom: 1px solid #ddd;",
+                        div {
+                            Link {
+                                to: Route::PackageView { package_name: transfer.package_name.clone() },
+                                "{transfer.package_name}"
+                            }
+                            " from {transfer.from_username}"
+                        }
+                        div {
+                            button {
+                                style: "padding: 8px; background-color: #28a745; color: white; border: none; border-radius: 4px; cursor: pointer;",
+                                onclick: {
+                                    let package_name = transfer.package_name.clone();
+                                    move |_| accept(package_name.clone())
+                                },
+                                "Accept"
+                            }
+                            button {
+                                style: "margin-left: 8px; padding: 8px; background-color: #f87171; color: white; border: none; border-radius: 4px; cursor: pointer;",
+                                onclick: move |_| cancel(transfer.package_name.clone()),
+                                "Decline"
+                            }
+                        }
+                    }
+                }
+                h2 { "Outgoing transfers" }
+                if transfers.read().outgoing.is_empty() {
+                    p { style: "color: #666;", "You haven't offered any packages." }
+                }
+                for transfer in transfers.read().outgoing.iter().cloned() {
+                    div {
+                        key: "{transfer.package_id}",
+                        style: "display: flex; flex-direction: row; justify-content: space-between; align-items: center; padding: 8px; border-bottom: 1px solid #ddd;",
+                        div {
+                            Link {
+                                to: Route::PackageView { package_name: transfer.package_name.clone() },
+                                "{transfer.package_name}"
+                            }
+                            " to {transfer.to_username}"
+                        }
+                        button {
+                            style: "padding: 8px; background-color: #f87171; color: white; border: none; border-radius: 4px; cursor: pointer;",
+                            onclick: move |_| cancel(transfer.package_name.clone()),
+                            "Cancel"
+                        }
+                    }
+                }
+                if !status_message.read().is_empty() {
+                    div {
+                        style: "margin-top: 10px; padding: 10px; border-radius: 4px; text-align: center; font-weight: bold; background-color: #f8d7da; color: #721c24; border: 1px solid #f5c6cb;",
+                        "{status_message}"
+                    }
+                }
+            }
+        } else {
+            Auth {
+                on_auth: move |_| {}
+            }
+        }
+    }
+}
+
+/// Offer a package to another user. Shown on the package page to its owner.
+#[component]
+pub fn TransferPackage(package_name: String) -> Element {
+    let auth_store = &crate::AUTH_STORE;
+
+    let mut to_username = use_signal(|| String::new());
+    let mut status_message = use_signal(|| String::new());
+
+    let handle_transfer = move |_| {
+        let package_name = package_name.clone();
+        spawn(async move {
+            let token = auth_store.read().token.read().clone();
+            let Some(token) = token else {
+                return;
+            };
+            let recipient = to_username.read().clone();
+            match auth_store
+                .read()
+                .api
+                .request_transfer(&token, &package_name, &recipient)
+                .await
+            {
+                Ok(_) => status_message.set(format!(
+                    "Transfer offered, it completes when {recipient} accepts."
+                )),
+                Err(e) => status_message.set(format!("Failed to offer transfer: {e:#}")),
+            }
+        });
+    };
+
+    rsx! {
+        div {
+            h4 {
+                style: "margin: 0px",
+                "Transfer ownership"
+            }
+        }
+        div {
+            style: "display: flex; flex-direction: row; margin: 4px 0px;",
+            input {
+                style: "flex: 1; padding: 4px;",
+                placeholder: "username",
+                value: "{to_username}",
+                oninput: move |e| to_username.set(e.value()),
+            }
+            button {
+                style: "margin-left: 4px; padding: 4px 8px; cursor: pointer;",
+                disabled: to_username.read().is_empty(),
+                onclick: handle_transfer,
+                "Offer"
+            }
+        }
+        if !status_message.read().is_empty() {
+            div {
+                style: "color: dimgray;",
+                "{status_message}"
+            }
+        }
+    }
+}
@@ -0,0 +1,87 @@
+// Service worker of the web app, served by onyx at /sw.js.
+//
+// Everything under /v0/version/{id} except builds and artifacts is derived from the tarball
+// with the content hash {id}, so it never changes and is served from the cache once fetched.
+// The web app hashes tarballs it downloads, cached or not, and drops a cached copy that
+// doesn't match, see `web/src/service_worker.rs`. Package metadata changes as versions are
+// published, so it's fetched every time and the cached copy is only used offline.
+
+// bump to drop everything cached by older workers
+const VERSION = "v1";
+// must match `TARBALL_CACHE` in web/src/service_worker.rs
+const TARBALL_CACHE = `nrpm-tarballs-${VERSION}`;
+const METADATA_CACHE = `nrpm-metadata-${VERSION}`;
+
+// GET /v0/version/{id}, /v0/version/{id}/manifest, /docs and /release_notes
+const IMMUTABLE = /^\/v0\/version\/[0-9a-f]+(\/(manifest|docs|release_notes))?$/;
+const PACKAGE = /^\/v0\/packages\/[^/]+\/(latest|versions|graph|stats)$/;
+
+self.addEventListener("install", () => self.skipWaiting());
+
+self.addEventListener("activate", (event) => {
+  const current = [TARBALL_CACHE, METADATA_CACHE];
+  event.waitUntil(
+    caches
+      .keys()
+      .then((names) =>
+        Promise.all(
+          names
+            .filter((name) => name.startsWith("nrpm-") && !current.includes(name))
+            .map((name) => caches.delete(name)),
+        ),
+      )
+      .then(() => self.clients.claim()),
+  );
+});
+
+self.addEventListener("fetch", (event) => {
+  const request = event.request;
+  if (request.method !== "GET") {
+    return;
+  }
+  // the api may be on another origin than the app, see ONYX_API_URL
+  const path = new URL(request.url).pathname;
+  const immutable = path.match(IMMUTABLE);
+  if (immutable) {
+    // tarballs are the `/v0/version/{id}` path itself
+    const cache = immutable[1] ? METADATA_CACHE : TARBALL_CACHE;
+    event.respondWith(cacheFirst(cache, request));
+  } else if (PACKAGE.test(path)) {
+    event.respondWith(networkFirst(METADATA_CACHE, request));
+  }
+});
+
+// Responses worth keeping, not errors or opaque responses whose status can't be read.
+function cacheable(response) {
+  return response.ok && response.type !== "opaque";
+}
+
+async function cacheFirst(name, request) {
+  const cache = await caches.open(name);
+  const cached = await cache.match(request);
+  if (cached) {
+    return cached;
+  }
+  const response = await fetch(request);
+  if (cacheable(response)) {
+    await cache.put(request, response.clone());
+  }
+  return response;
+}
+
+async function networkFirst(name, request) {
+  const cache = await caches.open(name);
+  try {
+    const response = await fetch(request);
+    if (cacheable(response)) {
+      await cache.put(request, response.clone());
+    }
+    return response;
+  } catch (error) {
+    const cached = await cache.match(request);
+    if (cached) {
+      return cached;
+    }
+    throw error;
+  }
+}